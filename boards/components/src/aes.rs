@@ -7,6 +7,10 @@
 //! Usage
 //! -----
 //! ```rust
+//! // Only on chips without an AES peripheral.
+//! let aes = components::aes::Aes128SoftwareComponent::new()
+//!     .finalize(components::aes128_software_component_static!());
+//!
 //! let aes_driver_device = components::aes::AesVirtualComponent::new(aes_mux).finalize(
 //!     components::aes_virtual_component_static!(nrf52840::aes::AesECB<'static>),
 //! );
@@ -28,6 +32,7 @@ use core::mem::MaybeUninit;
 use kernel::capabilities;
 use kernel::component::Component;
 use kernel::create_capability;
+use kernel::deferred_call::DeferredCallClient;
 use kernel::hil;
 use kernel::hil::symmetric_encryption::{
     AES128Ctr, AES128, AES128CBC, AES128CCM, AES128ECB, AES128GCM,
@@ -61,6 +66,34 @@ macro_rules! aes_driver_component_static {
     };};
 }
 
+#[macro_export]
+macro_rules! aes128_software_component_static {
+    ($(,)?) => {{
+        kernel::static_buf!(capsules_extra::aes128::Aes128Software<'static>)
+    };};
+}
+
+pub type Aes128SoftwareComponentType = capsules_extra::aes128::Aes128Software<'static>;
+
+pub struct Aes128SoftwareComponent {}
+
+impl Aes128SoftwareComponent {
+    pub fn new() -> Aes128SoftwareComponent {
+        Aes128SoftwareComponent {}
+    }
+}
+
+impl Component for Aes128SoftwareComponent {
+    type StaticInput = &'static mut MaybeUninit<capsules_extra::aes128::Aes128Software<'static>>;
+    type Output = &'static capsules_extra::aes128::Aes128Software<'static>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let aes = s.write(capsules_extra::aes128::Aes128Software::new());
+        aes.register();
+        aes
+    }
+}
+
 pub struct AesVirtualComponent<A: 'static + AES128<'static> + AES128Ctr + AES128CBC + AES128ECB> {
     aes_mux: &'static capsules_core::virtualizers::virtual_aes_ccm::MuxAES128CCM<'static, A>,
 }
@@ -0,0 +1,157 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Components for loading applications while the kernel is running.
+//!
+//! This provides two components, `DynamicProcessLoadingMuxComponent`, which
+//! shares the process loader between the capsules loading applications at
+//! runtime and lets the kernel unload processes with it, and
+//! `AppLoaderComponent`, for the driver installing, upgrading and removing
+//! applications from userspace.
+//!
+//! The driver writes new applications to free app flash through a
+//! `NonvolatileStorage` covering the app flash, and is notified of the
+//! outcome of loading them through the mux.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let loader_mux =
+//!     components::app_loader::DynamicProcessLoadingMuxComponent::new(board_kernel, loader)
+//!         .finalize(components::dynamic_process_loading_mux_component_static!());
+//! let app_loader = components::app_loader::AppLoaderComponent::new(
+//!     board_kernel,
+//!     capsules_extra::app_loader::DRIVER_NUM,
+//!     nv_to_page,
+//!     loader_mux,
+//! )
+//! .finalize(components::app_loader_component_static!());
+//! ```
+
+use capsules_core::virtualizers::virtual_process_loading::{
+    MuxDynamicProcessLoading, VirtualDynamicProcessLoading,
+};
+use capsules_extra::app_loader::AppLoader;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::nonvolatile_storage::NonvolatileStorage;
+use kernel::process::DynamicProcessLoading;
+
+/// Size of the largest chunk of a TBF written at once.
+pub const BUF_LEN: usize = 512;
+
+#[macro_export]
+macro_rules! dynamic_process_loading_mux_component_static {
+    () => {{
+        kernel::static_buf!(
+            capsules_core::virtualizers::virtual_process_loading::MuxDynamicProcessLoading<'static>
+        )
+    };};
+}
+
+#[macro_export]
+macro_rules! app_loader_component_static {
+    () => {{
+        let virtual_loader = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_process_loading::VirtualDynamicProcessLoading<
+                'static,
+            >
+        );
+        let buffer = kernel::static_buf!([u8; $crate::app_loader::BUF_LEN]);
+        let app_loader = kernel::static_buf!(
+            capsules_extra::app_loader::AppLoader<'static, $crate::app_loader::Capability>
+        );
+        (virtual_loader, buffer, app_loader)
+    };};
+}
+
+pub struct DynamicProcessLoadingMuxComponent<L: DynamicProcessLoading<'static> + 'static> {
+    board_kernel: &'static kernel::Kernel,
+    loader: &'static L,
+}
+
+impl<L: DynamicProcessLoading<'static> + 'static> DynamicProcessLoadingMuxComponent<L> {
+    pub fn new(board_kernel: &'static kernel::Kernel, loader: &'static L) -> Self {
+        Self {
+            board_kernel,
+            loader,
+        }
+    }
+}
+
+impl<L: DynamicProcessLoading<'static> + 'static> Component
+    for DynamicProcessLoadingMuxComponent<L>
+{
+    type StaticInput = &'static mut MaybeUninit<MuxDynamicProcessLoading<'static>>;
+    type Output = &'static MuxDynamicProcessLoading<'static>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let process_management_cap = create_capability!(capabilities::ProcessManagementCapability);
+
+        let mux = s.write(MuxDynamicProcessLoading::new(self.loader));
+        self.loader.set_load_client(mux);
+        self.board_kernel
+            .set_dynamic_process_loader(self.loader, &process_management_cap);
+
+        mux
+    }
+}
+
+pub struct Capability;
+unsafe impl capabilities::ProcessManagementCapability for Capability {}
+
+pub struct AppLoaderComponent {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    storage: &'static dyn NonvolatileStorage<'static>,
+    loader_mux: &'static MuxDynamicProcessLoading<'static>,
+}
+
+impl AppLoaderComponent {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        storage: &'static dyn NonvolatileStorage<'static>,
+        loader_mux: &'static MuxDynamicProcessLoading<'static>,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            storage,
+            loader_mux,
+        }
+    }
+}
+
+impl Component for AppLoaderComponent {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualDynamicProcessLoading<'static>>,
+        &'static mut MaybeUninit<[u8; BUF_LEN]>,
+        &'static mut MaybeUninit<AppLoader<'static, Capability>>,
+    );
+    type Output = &'static AppLoader<'static, Capability>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let loader =
+            s.0.write(VirtualDynamicProcessLoading::new(self.loader_mux));
+        loader.setup();
+
+        let app_loader = s.2.write(AppLoader::new(
+            self.board_kernel,
+            self.storage,
+            loader,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            s.1.write([0; BUF_LEN]),
+            Capability,
+        ));
+        self.storage.set_client(app_loader);
+        loader.set_load_client(app_loader);
+
+        app_loader
+    }
+}
@@ -0,0 +1,119 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Components for signature-based credential checkers.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let sha = components::sha::ShaSoftware256Component::new()
+//!     .finalize(components::sha_software_256_component_static!());
+//! let verifier = components::ecdsa::EcdsaP256SoftwareComponent::new(&PUBLIC_KEY)
+//!     .finalize(components::ecdsa_p256_software_component_static!());
+//! let checker = components::appid::checker_signature::AppCheckerSignatureComponent::new(
+//!     sha,
+//!     verifier,
+//!     tock_tbf::types::TbfFooterV2CredentialsType::EcdsaNistP256,
+//! )
+//! .finalize(components::app_checker_signature_component_static!(
+//!     capsules_extra::ecdsa_p256::EcdsaP256SignatureVerifier<'static>,
+//!     capsules_extra::sha256::Sha256Software<'static>,
+//!     32,
+//!     64,
+//! ));
+//! ```
+
+use capsules_system::process_checker::signature::AppCheckerSignature;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::digest;
+use kernel::hil::public_key_crypto::signature;
+use tock_tbf::types::TbfFooterV2CredentialsType;
+
+#[macro_export]
+macro_rules! app_checker_signature_component_static {
+    ($S:ty, $H:ty, $HL:expr, $SL:expr $(,)?) => {{
+        let hash_buffer = kernel::static_buf!([u8; $HL]);
+        let signature_buffer = kernel::static_buf!([u8; $SL]);
+        let checker = kernel::static_buf!(
+            capsules_system::process_checker::signature::AppCheckerSignature<
+                'static,
+                $S,
+                $H,
+                $HL,
+                $SL,
+            >
+        );
+
+        (checker, hash_buffer, signature_buffer)
+    };};
+}
+
+pub type AppCheckerSignatureComponentType<S, H, const HL: usize, const SL: usize> =
+    AppCheckerSignature<'static, S, H, HL, SL>;
+
+pub struct AppCheckerSignatureComponent<
+    S: signature::SignatureVerify<'static, HL, SL> + 'static,
+    H: digest::DigestDataHash<'static, HL> + digest::Digest<'static, HL> + 'static,
+    const HL: usize,
+    const SL: usize,
+> {
+    hasher: &'static H,
+    verifier: &'static S,
+    credential_type: TbfFooterV2CredentialsType,
+}
+
+impl<
+        S: signature::SignatureVerify<'static, HL, SL>,
+        H: digest::DigestDataHash<'static, HL> + digest::Digest<'static, HL>,
+        const HL: usize,
+        const SL: usize,
+    > AppCheckerSignatureComponent<S, H, HL, SL>
+{
+    pub fn new(
+        hasher: &'static H,
+        verifier: &'static S,
+        credential_type: TbfFooterV2CredentialsType,
+    ) -> Self {
+        Self {
+            hasher,
+            verifier,
+            credential_type,
+        }
+    }
+}
+
+impl<
+        S: signature::SignatureVerify<'static, HL, SL>,
+        H: digest::DigestDataHash<'static, HL> + digest::Digest<'static, HL>,
+        const HL: usize,
+        const SL: usize,
+    > Component for AppCheckerSignatureComponent<S, H, HL, SL>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<AppCheckerSignature<'static, S, H, HL, SL>>,
+        &'static mut MaybeUninit<[u8; HL]>,
+        &'static mut MaybeUninit<[u8; SL]>,
+    );
+
+    type Output = &'static AppCheckerSignature<'static, S, H, HL, SL>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let hash_buffer = s.1.write([0; HL]);
+        let signature_buffer = s.2.write([0; SL]);
+
+        let checker = s.0.write(AppCheckerSignature::new(
+            self.hasher,
+            self.verifier,
+            hash_buffer,
+            signature_buffer,
+            self.credential_type,
+        ));
+
+        digest::Digest::set_client(self.hasher, checker);
+        self.verifier.set_verify_client(checker);
+
+        checker
+    }
+}
@@ -0,0 +1,284 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Component to initialize the userland CoAP driver.
+//!
+//! This provides one Component, CoapComponent. This component initializes a
+//! userspace CoAP driver bound to the CoAP port of the UDP stack. The driver
+//! retransmits confirmable messages on a virtual alarm, and protects the
+//! exchanges of processes with OSCORE using a virtual AES-CCM, an HMAC-SHA256
+//! for key derivation and the KV store for the security context records.
+//!
+//! Usage
+//! -----
+//! ```rust
+//!    let coap_driver = components::coap::CoapComponent::new(
+//!        board_kernel,
+//!        capsules_extra::net::coap::DRIVER_NUM,
+//!        udp_send_mux,
+//!        udp_recv_mux,
+//!        udp_port_table,
+//!        mux_alarm,
+//!        aes_mux,
+//!        hmac,
+//!        kv,
+//!     )
+//!     .finalize(components::coap_component_static!(
+//!         nrf52840::rtc::Rtc,
+//!         nrf52840::aes::AesECB<'static>,
+//!         HmacSha256Software<'static, Sha256Software<'static>>,
+//!     ));
+//! ```
+
+use capsules_core::virtualizers::virtual_aes_ccm::{MuxAES128CCM, VirtualAES128CCM};
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::net::coap::driver::{CoapDriver, COAP_PORT};
+use capsules_extra::net::coap::oscore;
+use capsules_extra::net::ipv6::ipv6_send::IP6Sender;
+use capsules_extra::net::network_capabilities::{
+    AddrRange, NetworkCapability, PortRange, UdpVisibilityCapability,
+};
+use capsules_extra::net::udp::udp_port_table::UdpPortManager;
+use capsules_extra::net::udp::udp_recv::{MuxUdpReceiver, UDPReceiver};
+use capsules_extra::net::udp::udp_send::{MuxUdpSender, UDPSendStruct, UDPSender};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::capabilities::NetworkCapabilityCreationCapability;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::digest;
+use kernel::hil::kv;
+use kernel::hil::symmetric_encryption::{self, AES128Ctr, AES128, AES128CBC, AES128CCM, AES128ECB};
+use kernel::hil::time::{self, Alarm};
+
+const MAX_PAYLOAD_LEN: usize = super::udp_mux::MAX_PAYLOAD_LEN;
+
+/// Size of the buffer OSCORE messages are encrypted in: the additional
+/// authenticated data followed by the message and its tag.
+pub const OSCORE_BUF_LEN: usize = oscore::MAX_AAD_LEN + MAX_PAYLOAD_LEN;
+
+/// Size of the buffer of the virtual AES-CCM of the driver.
+pub const CRYPT_SIZE: usize = 4 * symmetric_encryption::AES128_BLOCK_SIZE + OSCORE_BUF_LEN;
+
+/// Size of the buffer the security context records are read into.
+pub const KV_VALUE_LEN: usize =
+    capsules_extra::kv_store_permissions::HEADER_LENGTH + oscore::MAX_RECORD_LEN;
+
+pub type CoapComponentType<A, H> = CoapDriver<'static, VirtualMuxAlarm<'static, A>, H>;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! coap_component_static {
+    ($A:ty, $B:ty, $H:ty $(,)?) => {{
+        $crate::coap_ip6_sender_component_static!(
+            $A,
+            $B,
+            $H,
+            capsules_extra::net::ipv6::ipv6_send::IP6SendStruct<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        )
+    };};
+}
+
+/// Static space for a CoAP driver on top of any IPv6 sender `$S`, such as
+/// `IP6EthernetStruct`.
+#[macro_export]
+macro_rules! coap_ip6_sender_component_static {
+    ($A:ty, $B:ty, $H:ty, $S:ty $(,)?) => {{
+        use components::coap::{CRYPT_SIZE, KV_VALUE_LEN, OSCORE_BUF_LEN};
+        use components::udp_mux::MAX_PAYLOAD_LEN;
+
+        let udp_send =
+            kernel::static_buf!(capsules_extra::net::udp::udp_send::UDPSendStruct<'static, $S>);
+        let udp_vis_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::UdpVisibilityCapability);
+        let net_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::NetworkCapability);
+        let udp_recv =
+            kernel::static_buf!(capsules_extra::net::udp::udp_recv::UDPReceiver<'static>);
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let buffer = kernel::static_buf!([u8; MAX_PAYLOAD_LEN]);
+        let coap_driver = kernel::static_buf!(
+            capsules_extra::net::coap::CoapDriver<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $H,
+            >
+        );
+        let crypt_buf = kernel::static_buf!([u8; CRYPT_SIZE]);
+        let crypt = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_aes_ccm::VirtualAES128CCM<'static, $B>
+        );
+        let oscore_buf = kernel::static_buf!([u8; OSCORE_BUF_LEN]);
+        let hmac_data = kernel::static_buf!([u8; capsules_extra::net::coap::oscore::MAX_INFO_LEN]);
+        let hmac_digest = kernel::static_buf!([u8; 32]);
+        let kv_key = kernel::static_buf!([u8; capsules_extra::net::coap::oscore::KV_KEY.len()]);
+        let kv_value = kernel::static_buf!([u8; KV_VALUE_LEN]);
+
+        (
+            udp_send,
+            udp_vis_cap,
+            net_cap,
+            udp_recv,
+            alarm,
+            buffer,
+            coap_driver,
+            crypt_buf,
+            crypt,
+            oscore_buf,
+            hmac_data,
+            hmac_digest,
+            kv_key,
+            kv_value,
+        )
+    };};
+}
+
+pub struct CoapComponent<
+    A: Alarm<'static> + 'static,
+    S: IP6Sender<'static> + 'static,
+    B: AES128<'static> + AES128Ctr + AES128CBC + AES128ECB + 'static,
+    H: digest::Digest<'static, 32> + digest::HmacSha256 + 'static,
+> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    udp_send_mux: &'static MuxUdpSender<'static, S>,
+    udp_recv_mux: &'static MuxUdpReceiver<'static>,
+    port_table: &'static UdpPortManager,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    aes_mux: &'static MuxAES128CCM<'static, B>,
+    hmac: &'static H,
+    kv: &'static dyn kv::KVPermissions<'static>,
+}
+
+impl<
+        A: Alarm<'static>,
+        S: IP6Sender<'static>,
+        B: AES128<'static> + AES128Ctr + AES128CBC + AES128ECB,
+        H: digest::Digest<'static, 32> + digest::HmacSha256,
+    > CoapComponent<A, S, B, H>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        udp_send_mux: &'static MuxUdpSender<'static, S>,
+        udp_recv_mux: &'static MuxUdpReceiver<'static>,
+        port_table: &'static UdpPortManager,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        aes_mux: &'static MuxAES128CCM<'static, B>,
+        hmac: &'static H,
+        kv: &'static dyn kv::KVPermissions<'static>,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            udp_send_mux,
+            udp_recv_mux,
+            port_table,
+            alarm_mux,
+            aes_mux,
+            hmac,
+            kv,
+        }
+    }
+}
+
+impl<
+        A: Alarm<'static>,
+        S: IP6Sender<'static>,
+        B: AES128<'static> + AES128Ctr + AES128CBC + AES128ECB,
+        H: digest::Digest<'static, 32> + digest::HmacSha256,
+    > Component for CoapComponent<A, S, B, H>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<UDPSendStruct<'static, S>>,
+        &'static mut MaybeUninit<UdpVisibilityCapability>,
+        &'static mut MaybeUninit<NetworkCapability>,
+        &'static mut MaybeUninit<UDPReceiver<'static>>,
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<[u8; MAX_PAYLOAD_LEN]>,
+        &'static mut MaybeUninit<CoapDriver<'static, VirtualMuxAlarm<'static, A>, H>>,
+        &'static mut MaybeUninit<[u8; CRYPT_SIZE]>,
+        &'static mut MaybeUninit<VirtualAES128CCM<'static, B>>,
+        &'static mut MaybeUninit<[u8; OSCORE_BUF_LEN]>,
+        &'static mut MaybeUninit<[u8; oscore::MAX_INFO_LEN]>,
+        &'static mut MaybeUninit<[u8; 32]>,
+        &'static mut MaybeUninit<[u8; oscore::KV_KEY.len()]>,
+        &'static mut MaybeUninit<[u8; KV_VALUE_LEN]>,
+    );
+    type Output = &'static CoapComponentType<A, H>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+        let create_cap = create_capability!(NetworkCapabilityCreationCapability);
+
+        let udp_vis = s.1.write(UdpVisibilityCapability::new(&create_cap));
+        let udp_send = s.0.write(UDPSendStruct::new(self.udp_send_mux, udp_vis));
+        let net_cap = s.2.write(NetworkCapability::new(
+            AddrRange::Any,
+            PortRange::Any,
+            PortRange::Any,
+            &create_cap,
+        ));
+
+        let coap_alarm = s.4.write(VirtualMuxAlarm::new(self.alarm_mux));
+        coap_alarm.setup();
+
+        let buffer = s.5.write([0; MAX_PAYLOAD_LEN]);
+
+        // AES-128CCM setup
+        let crypt_buf = s.7.write([0; CRYPT_SIZE]);
+        let aes_ccm = s.8.write(VirtualAES128CCM::new(self.aes_mux, crypt_buf));
+        aes_ccm.setup();
+
+        let coap_driver = s.6.write(CoapDriver::new(
+            udp_send,
+            coap_alarm,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            buffer,
+            net_cap,
+            aes_ccm,
+            self.hmac,
+            self.kv,
+            s.9.write([0; OSCORE_BUF_LEN]),
+            s.10.write([0; oscore::MAX_INFO_LEN]),
+            s.11.write([0; 32]),
+            s.12.write([0; oscore::KV_KEY.len()]),
+            s.13.write([0; KV_VALUE_LEN]),
+        ));
+        AES128CCM::set_client(aes_ccm, coap_driver);
+        digest::Digest::set_client(self.hmac, coap_driver);
+        self.kv.set_client(coap_driver);
+        time::Alarm::set_alarm_client(coap_alarm, coap_driver);
+        udp_send.set_client(coap_driver);
+
+        let udp_rcvr = s.3.write(UDPReceiver::new());
+        udp_rcvr.set_client(coap_driver);
+
+        // The driver cannot work without the CoAP port, so fail loudly if
+        // another capsule holds it already.
+        self.port_table
+            .create_socket()
+            .map(|socket| {
+                self.port_table
+                    .bind(socket, COAP_PORT, net_cap)
+                    .map_or_else(
+                        |_| panic!("CoAP port is already bound"),
+                        |(tx_bind, rx_bind)| {
+                            udp_rcvr.set_binding(rx_bind);
+                            udp_send.set_binding(tx_bind);
+                        },
+                    )
+            })
+            .unwrap();
+
+        self.udp_recv_mux.add_client(udp_rcvr);
+
+        coap_driver
+    }
+}
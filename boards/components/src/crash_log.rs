@@ -0,0 +1,109 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for crash reports kept in flash.
+//!
+//! This provides one Component, CrashLogComponent, which creates a circular
+//! log on a storage volume and a crash log saving reports to it. The crash
+//! log is registered as the kernel's crash recorder, so panic reports are
+//! saved, and reads the reports saved before the reboot. Reports are at most
+//! `REPORT_LEN` bytes, which must fit in one flash page together with the
+//! log headers.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! kernel::storage_volume!(CRASH_LOG_VOLUME, 8);
+//!
+//! let crash_log = components::crash_log::CrashLogComponent::new(
+//!     &CRASH_LOG_VOLUME,
+//!     &base_peripherals.nvmc,
+//!     process_printer,
+//! )
+//! .finalize(components::crash_log_component_static!(nrf52840::nvmc::Nvmc, 4000));
+//! process_console.set_crash_log(crash_log);
+//! ```
+
+use capsules_extra::crash_log::CrashLog;
+use capsules_extra::log::Log;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::hil::flash::{Flash, HasClient};
+use kernel::hil::log::{LogRead, LogWrite};
+use kernel::process::ProcessPrinter;
+
+pub type CrashLogComponentType<F> = CrashLog<'static, Log<'static, F>>;
+
+#[macro_export]
+macro_rules! crash_log_component_static {
+    ($F:ty, $REPORT_LEN:expr $(,)?) => {{
+        let log = kernel::static_buf!(capsules_extra::log::Log<'static, $F>);
+        let pagebuffer = kernel::static_buf!(<$F as kernel::hil::flash::Flash>::Page);
+        let crash_log = kernel::static_buf!(
+            capsules_extra::crash_log::CrashLog<'static, capsules_extra::log::Log<'static, $F>>
+        );
+        let report = kernel::static_buf!([u8; $REPORT_LEN]);
+
+        (log, pagebuffer, crash_log, report)
+    };};
+}
+
+pub struct CrashLogComponent<
+    F: 'static + Flash + HasClient<'static, Log<'static, F>>,
+    const REPORT_LEN: usize,
+> {
+    volume: &'static [u8],
+    flash: &'static F,
+    process_printer: &'static dyn ProcessPrinter,
+}
+
+impl<F: 'static + Flash + HasClient<'static, Log<'static, F>>, const REPORT_LEN: usize>
+    CrashLogComponent<F, REPORT_LEN>
+{
+    pub fn new(
+        volume: &'static [u8],
+        flash: &'static F,
+        process_printer: &'static dyn ProcessPrinter,
+    ) -> Self {
+        Self {
+            volume,
+            flash,
+            process_printer,
+        }
+    }
+}
+
+impl<F: 'static + Flash + HasClient<'static, Log<'static, F>>, const REPORT_LEN: usize> Component
+    for CrashLogComponent<F, REPORT_LEN>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<Log<'static, F>>,
+        &'static mut MaybeUninit<F::Page>,
+        &'static mut MaybeUninit<CrashLog<'static, Log<'static, F>>>,
+        &'static mut MaybeUninit<[u8; REPORT_LEN]>,
+    );
+    type Output = &'static CrashLogComponentType<F>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let pagebuffer = s.1.write(F::Page::default());
+        let log =
+            s.0.write(Log::new(self.volume, self.flash, pagebuffer, true));
+        HasClient::set_client(self.flash, log);
+        log.register();
+
+        let report = s.3.write([0; REPORT_LEN]);
+        let crash_log = s.2.write(CrashLog::new(log, self.process_printer, report));
+        log.set_read_client(crash_log);
+        log.set_append_client(crash_log);
+
+        unsafe {
+            kernel::debug::set_crash_recorder(crash_log);
+        }
+
+        let _ = crash_log.start();
+
+        crash_log
+    }
+}
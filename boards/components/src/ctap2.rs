@@ -0,0 +1,280 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for a FIDO2 authenticator over USB.
+//!
+//! This provides one Component, Ctap2Component, which sets up a CTAP HID
+//! device on a USB controller and the kernel CTAP2 authenticator on top of
+//! it. The authenticator hashes with a SHA-256 digest, creates and uses
+//! credential keys with an ECDSA P-256 signer, stores credentials in the KV
+//! store with kernel permissions and waits for user presence on a button.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let (ctap, authenticator) = components::ctap2::Ctap2Component::new(
+//!     &nrf52840_peripherals.usbd,
+//!     0x1915,
+//!     0x503a,
+//!     STRINGS,
+//!     mux_alarm,
+//!     sha,
+//!     signer,
+//!     kv,
+//!     &nrf52840_peripherals.gpio_port[BUTTON_PIN],
+//!     kernel::hil::gpio::ActivationMode::ActiveLow,
+//!     kernel::hil::gpio::FloatingState::PullUp,
+//!     AAGUID,
+//! )
+//! .finalize(components::ctap2_component_static!(
+//!     nrf52840::usbd::Usbd,
+//!     nrf52840::rtc::Rtc,
+//!     capsules_extra::sha256::Sha256Software<'static>,
+//!     capsules_extra::ecdsa_p256::EcdsaP256SignatureSigner<
+//!         'static,
+//!         capsules_core::virtualizers::virtual_rng::VirtualRngMasterDevice<'static>,
+//!     >,
+//! ));
+//!
+//! ctap.enable();
+//! ctap.attach();
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::ctap2::authenticator::{
+    Authenticator, AAGUID_LEN, HASH_LEN, PRIVATE_KEY_LEN, PUBLIC_KEY_LEN, SIGNATURE_LEN,
+};
+use capsules_extra::ctap2::credentials;
+use capsules_extra::ctap2::ctaphid::PACKET_LEN;
+use capsules_extra::usb::ctap::CtapHid;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::hil;
+use kernel::hil::digest;
+use kernel::hil::gpio;
+use kernel::hil::kv;
+use kernel::hil::public_key_crypto::{keys, signature};
+use kernel::hil::time::Alarm;
+use kernel::storage_permissions::StoragePermissions;
+
+/// Size of the request and response buffers, which bounds the length of
+/// messages.
+pub const MESSAGE_LEN: usize = 1024;
+
+/// Size of the buffer the credential table is read into.
+pub const KV_VALUE_LEN: usize =
+    capsules_extra::kv_store_permissions::HEADER_LENGTH + credentials::RECORD_LEN;
+
+pub type Ctap2ComponentType<U, A, H, S> =
+    Authenticator<'static, CtapHid<'static, U>, VirtualMuxAlarm<'static, A>, H, S>;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! ctap2_component_static {
+    ($U:ty, $A:ty, $H:ty, $S:ty $(,)?) => {{
+        let hid = kernel::static_buf!(capsules_extra::usb::ctap::CtapHid<'static, $U>);
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let authenticator = kernel::static_buf!(
+            capsules_extra::ctap2::authenticator::Authenticator<
+                'static,
+                capsules_extra::usb::ctap::CtapHid<'static, $U>,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $H,
+                $S,
+            >
+        );
+        let rx_packet = kernel::static_buf!([u8; capsules_extra::ctap2::ctaphid::PACKET_LEN]);
+        let tx_packet = kernel::static_buf!([u8; capsules_extra::ctap2::ctaphid::PACKET_LEN]);
+        let hash = kernel::static_buf!([u8; capsules_extra::ctap2::authenticator::HASH_LEN]);
+        let signature =
+            kernel::static_buf!([u8; capsules_extra::ctap2::authenticator::SIGNATURE_LEN]);
+        let public_key =
+            kernel::static_buf!([u8; capsules_extra::ctap2::authenticator::PUBLIC_KEY_LEN]);
+        let private_key =
+            kernel::static_buf!([u8; capsules_extra::ctap2::authenticator::PRIVATE_KEY_LEN]);
+        let request = kernel::static_buf!([u8; $crate::ctap2::MESSAGE_LEN]);
+        let response = kernel::static_buf!([u8; $crate::ctap2::MESSAGE_LEN]);
+        let kv_key = kernel::static_buf!([u8; capsules_extra::ctap2::credentials::KV_KEY.len()]);
+        let kv_value = kernel::static_buf!([u8; $crate::ctap2::KV_VALUE_LEN]);
+
+        (
+            hid,
+            alarm,
+            authenticator,
+            (
+                rx_packet,
+                tx_packet,
+                hash,
+                signature,
+                public_key,
+                private_key,
+            ),
+            (request, response, kv_key, kv_value),
+        )
+    };};
+}
+
+pub struct Ctap2Component<
+    U: 'static + hil::usb::UsbController<'static>,
+    A: 'static + Alarm<'static>,
+    H: 'static + digest::Digest<'static, HASH_LEN> + digest::Sha256,
+    S: 'static
+        + signature::SignatureSign<'static, HASH_LEN, SIGNATURE_LEN>
+        + keys::SetKeyBySlice<'static, PRIVATE_KEY_LEN>
+        + keys::KeyPairGenerate<'static, PUBLIC_KEY_LEN, PRIVATE_KEY_LEN>,
+    P: 'static + gpio::InterruptPin<'static>,
+> {
+    usb: &'static U,
+    vendor_id: u16,
+    product_id: u16,
+    strings: &'static [&'static str; 3],
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    sha: &'static H,
+    signer: &'static S,
+    kv: &'static dyn kv::KVPermissions<'static>,
+    button: &'static P,
+    button_mode: gpio::ActivationMode,
+    button_floating: gpio::FloatingState,
+    aaguid: [u8; AAGUID_LEN],
+}
+
+impl<
+        U: 'static + hil::usb::UsbController<'static>,
+        A: 'static + Alarm<'static>,
+        H: 'static + digest::Digest<'static, HASH_LEN> + digest::Sha256,
+        S: 'static
+            + signature::SignatureSign<'static, HASH_LEN, SIGNATURE_LEN>
+            + keys::SetKeyBySlice<'static, PRIVATE_KEY_LEN>
+            + keys::KeyPairGenerate<'static, PUBLIC_KEY_LEN, PRIVATE_KEY_LEN>,
+        P: 'static + gpio::InterruptPin<'static>,
+    > Ctap2Component<U, A, H, S, P>
+{
+    pub fn new(
+        usb: &'static U,
+        vendor_id: u16,
+        product_id: u16,
+        strings: &'static [&'static str; 3],
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        sha: &'static H,
+        signer: &'static S,
+        kv: &'static dyn kv::KVPermissions<'static>,
+        button: &'static P,
+        button_mode: gpio::ActivationMode,
+        button_floating: gpio::FloatingState,
+        aaguid: [u8; AAGUID_LEN],
+    ) -> Self {
+        Self {
+            usb,
+            vendor_id,
+            product_id,
+            strings,
+            alarm_mux,
+            sha,
+            signer,
+            kv,
+            button,
+            button_mode,
+            button_floating,
+            aaguid,
+        }
+    }
+}
+
+impl<
+        U: 'static + hil::usb::UsbController<'static>,
+        A: 'static + Alarm<'static>,
+        H: 'static + digest::Digest<'static, HASH_LEN> + digest::Sha256,
+        S: 'static
+            + signature::SignatureSign<'static, HASH_LEN, SIGNATURE_LEN>
+            + keys::SetKeyBySlice<'static, PRIVATE_KEY_LEN>
+            + keys::KeyPairGenerate<'static, PUBLIC_KEY_LEN, PRIVATE_KEY_LEN>,
+        P: 'static + gpio::InterruptPin<'static>,
+    > Component for Ctap2Component<U, A, H, S, P>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<CtapHid<'static, U>>,
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<Ctap2ComponentType<U, A, H, S>>,
+        (
+            &'static mut MaybeUninit<[u8; PACKET_LEN]>,
+            &'static mut MaybeUninit<[u8; PACKET_LEN]>,
+            &'static mut MaybeUninit<[u8; HASH_LEN]>,
+            &'static mut MaybeUninit<[u8; SIGNATURE_LEN]>,
+            &'static mut MaybeUninit<[u8; PUBLIC_KEY_LEN]>,
+            &'static mut MaybeUninit<[u8; PRIVATE_KEY_LEN]>,
+        ),
+        (
+            &'static mut MaybeUninit<[u8; MESSAGE_LEN]>,
+            &'static mut MaybeUninit<[u8; MESSAGE_LEN]>,
+            &'static mut MaybeUninit<[u8; credentials::KV_KEY.len()]>,
+            &'static mut MaybeUninit<[u8; KV_VALUE_LEN]>,
+        ),
+    );
+    type Output = (
+        &'static CtapHid<'static, U>,
+        &'static Ctap2ComponentType<U, A, H, S>,
+    );
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let storage_cap = create_capability!(capabilities::KerneluserStorageCapability);
+
+        let ctap = s.0.write(CtapHid::new(
+            self.usb,
+            self.vendor_id,
+            self.product_id,
+            self.strings,
+        ));
+        self.usb.set_client(ctap);
+
+        let alarm = s.1.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        self.button.make_input();
+        self.button.set_floating_state(self.button_floating);
+
+        let (rx_packet, tx_packet, hash, signature, public_key, private_key) = s.3;
+        let (request, response, kv_key, kv_value) = s.4;
+        let authenticator = s.2.write(Authenticator::new(
+            ctap,
+            alarm,
+            self.sha,
+            self.signer,
+            self.kv,
+            StoragePermissions::new_kernel(&storage_cap),
+            self.button,
+            self.button_mode,
+            self.aaguid,
+            (
+                rx_packet.write([0; PACKET_LEN]),
+                tx_packet.write([0; PACKET_LEN]),
+                hash.write([0; HASH_LEN]),
+                signature.write([0; SIGNATURE_LEN]),
+                public_key.write([0; PUBLIC_KEY_LEN]),
+                private_key.write([0; PRIVATE_KEY_LEN]),
+            ),
+            request.write([0; MESSAGE_LEN]),
+            response.write([0; MESSAGE_LEN]),
+            kv_key.write([0; credentials::KV_KEY.len()]),
+            kv_value.write([0; KV_VALUE_LEN]),
+        ));
+        ctap.set_client(authenticator);
+        alarm.set_alarm_client(authenticator);
+        self.button.set_client(authenticator);
+        digest::Digest::set_client(self.sha, authenticator);
+        self.kv.set_client(authenticator);
+        self.signer.set_sign_client(authenticator);
+        self.signer.set_key_client(authenticator);
+        self.signer.set_generate_client(authenticator);
+        authenticator.register();
+
+        let _ = authenticator.start();
+
+        (ctap, authenticator)
+    }
+}
@@ -0,0 +1,91 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Components for ECDSA signature verifiers and signers.
+//!
+//! The signer takes a random number generator, which it uses to generate key
+//! pairs and signature nonces.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let verifier = components::ecdsa::EcdsaP256SoftwareComponent::new(&PUBLIC_KEY)
+//!     .finalize(components::ecdsa_p256_software_component_static!());
+//!
+//! let signer = components::ecdsa::EcdsaP256SoftwareSignerComponent::new(rng)
+//!     .finalize(components::ecdsa_p256_software_signer_component_static!(
+//!         capsules_core::virtualizers::virtual_rng::VirtualRngMasterDevice<'static>
+//!     ));
+//! ```
+
+use capsules_extra::ecdsa_p256::{
+    EcdsaP256SignatureSigner, EcdsaP256SignatureVerifier, PUBLIC_KEY_LEN,
+};
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::hil::rng;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! ecdsa_p256_software_component_static {
+    ($(,)?) => {{
+        kernel::static_buf!(capsules_extra::ecdsa_p256::EcdsaP256SignatureVerifier<'static>)
+    };};
+}
+
+pub type EcdsaP256SoftwareComponentType = EcdsaP256SignatureVerifier<'static>;
+
+pub struct EcdsaP256SoftwareComponent {
+    public_key: &'static [u8; PUBLIC_KEY_LEN],
+}
+
+impl EcdsaP256SoftwareComponent {
+    pub fn new(public_key: &'static [u8; PUBLIC_KEY_LEN]) -> EcdsaP256SoftwareComponent {
+        EcdsaP256SoftwareComponent { public_key }
+    }
+}
+
+impl Component for EcdsaP256SoftwareComponent {
+    type StaticInput = &'static mut MaybeUninit<EcdsaP256SignatureVerifier<'static>>;
+    type Output = &'static EcdsaP256SignatureVerifier<'static>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let verifier = s.write(EcdsaP256SignatureVerifier::new(self.public_key));
+        verifier.register();
+        verifier
+    }
+}
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! ecdsa_p256_software_signer_component_static {
+    ($R:ty $(,)?) => {{
+        kernel::static_buf!(capsules_extra::ecdsa_p256::EcdsaP256SignatureSigner<'static, $R>)
+    };};
+}
+
+pub type EcdsaP256SoftwareSignerComponentType<R> = EcdsaP256SignatureSigner<'static, R>;
+
+pub struct EcdsaP256SoftwareSignerComponent<R: 'static + rng::Rng<'static>> {
+    rng: &'static R,
+}
+
+impl<R: 'static + rng::Rng<'static>> EcdsaP256SoftwareSignerComponent<R> {
+    pub fn new(rng: &'static R) -> EcdsaP256SoftwareSignerComponent<R> {
+        EcdsaP256SoftwareSignerComponent { rng }
+    }
+}
+
+impl<R: 'static + rng::Rng<'static>> Component for EcdsaP256SoftwareSignerComponent<R> {
+    type StaticInput = &'static mut MaybeUninit<EcdsaP256SignatureSigner<'static, R>>;
+    type Output = &'static EcdsaP256SignatureSigner<'static, R>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let signer = s.write(EcdsaP256SignatureSigner::new(self.rng));
+        self.rng.set_client(signer);
+        signer.register();
+        signer
+    }
+}
@@ -0,0 +1,101 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for an 802.15.4 radio simulated over an Ethernet adapter.
+//!
+//! This provides one Component, EthernetRadioComponent, which turns an
+//! Ethernet adapter into a `hil::radio::Radio` for the 802.15.4 stack. The
+//! radio is started and listens on its link right away.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let radio = components::ethernet_radio::EthernetRadioComponent::new(
+//!     virtio_net,
+//!     mux_alarm,
+//!     mac_addr,
+//! )
+//! .finalize(components::ethernet_radio_component_static!(
+//!     qemu_rv32_virt_chip::chip::QemuRv32VirtClint
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::ieee802154::ethernet_radio::{EthernetRadio, FRAME_BUF_LEN};
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::hil::ethernet::{EthernetAdapter, MAC_ADDR_LEN};
+use kernel::hil::radio::RadioConfig;
+use kernel::hil::time::Alarm;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! ethernet_radio_component_static {
+    ($A:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let radio = kernel::static_buf!(
+            capsules_extra::ieee802154::ethernet_radio::EthernetRadio<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+        let frame =
+            kernel::static_buf!([u8; capsules_extra::ieee802154::ethernet_radio::FRAME_BUF_LEN]);
+
+        (alarm, radio, frame)
+    };};
+}
+
+pub type EthernetRadioComponentType<A> = EthernetRadio<'static, VirtualMuxAlarm<'static, A>>;
+
+pub struct EthernetRadioComponent<A: Alarm<'static> + 'static> {
+    adapter: &'static dyn EthernetAdapter<'static>,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    mac_addr: [u8; MAC_ADDR_LEN],
+}
+
+impl<A: Alarm<'static>> EthernetRadioComponent<A> {
+    pub fn new(
+        adapter: &'static dyn EthernetAdapter<'static>,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        mac_addr: [u8; MAC_ADDR_LEN],
+    ) -> Self {
+        Self {
+            adapter,
+            alarm_mux,
+            mac_addr,
+        }
+    }
+}
+
+impl<A: Alarm<'static> + 'static> Component for EthernetRadioComponent<A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<EthernetRadio<'static, VirtualMuxAlarm<'static, A>>>,
+        &'static mut MaybeUninit<[u8; FRAME_BUF_LEN]>,
+    );
+    type Output = &'static EthernetRadio<'static, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let alarm = s.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let frame = s.2.write([0; FRAME_BUF_LEN]);
+        let radio = s.1.write(EthernetRadio::new(
+            self.adapter,
+            alarm,
+            self.mac_addr,
+            frame,
+        ));
+        alarm.set_alarm_client(radio);
+        self.adapter.set_client(radio);
+        radio.register();
+        let _ = radio.start();
+
+        radio
+    }
+}
@@ -153,3 +153,78 @@ impl<
         hmac_sha256_sw
     }
 }
+
+#[macro_export]
+macro_rules! hmac_sha512_software_component_static {
+    ($S:ty, $L:expr $(,)?) => {{
+        let hmac_sha512 =
+            kernel::static_buf!(capsules_extra::hmac_sha512::HmacSha512Software<'static, $S, $L>);
+
+        let data_buffer = kernel::static_buf!([u8; 128]);
+        let verify_buffer = kernel::static_buf!([u8; $L]);
+
+        (hmac_sha512, data_buffer, verify_buffer)
+    };};
+}
+
+pub type HmacSha512SoftwareComponentType<S, const L: usize> =
+    capsules_extra::hmac_sha512::HmacSha512Software<'static, S, L>;
+
+/// Component for HMAC-SHA384 (`L = 48`) or HMAC-SHA512 (`L = 64`) on top of a
+/// hasher with the same digest length.
+pub struct HmacSha512SoftwareComponent<
+    S: digest::Sha384
+        + digest::Sha512
+        + digest::DigestDataHash<'static, L>
+        + digest::Digest<'static, L>
+        + 'static,
+    const L: usize,
+> {
+    sha: &'static S,
+}
+
+impl<
+        S: digest::Sha384
+            + digest::Sha512
+            + digest::DigestDataHash<'static, L>
+            + digest::Digest<'static, L>,
+        const L: usize,
+    > HmacSha512SoftwareComponent<S, L>
+{
+    pub fn new(sha: &'static S) -> HmacSha512SoftwareComponent<S, L> {
+        HmacSha512SoftwareComponent { sha }
+    }
+}
+
+impl<
+        S: digest::Sha384
+            + digest::Sha512
+            + digest::DigestDataHash<'static, L>
+            + digest::Digest<'static, L>
+            + 'static,
+        const L: usize,
+    > Component for HmacSha512SoftwareComponent<S, L>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<capsules_extra::hmac_sha512::HmacSha512Software<'static, S, L>>,
+        &'static mut MaybeUninit<[u8; 128]>,
+        &'static mut MaybeUninit<[u8; L]>,
+    );
+    type Output = &'static capsules_extra::hmac_sha512::HmacSha512Software<'static, S, L>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let data_buffer = s.1.write([0; 128]);
+        let verify_buffer = s.2.write([0; L]);
+
+        let hmac_sha512_sw =
+            s.0.write(capsules_extra::hmac_sha512::HmacSha512Software::new(
+                self.sha,
+                data_buffer,
+                verify_buffer,
+            ));
+
+        kernel::hil::digest::Digest::set_client(self.sha, hmac_sha512_sw);
+
+        hmac_sha512_sw
+    }
+}
@@ -0,0 +1,272 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Component to initialize the icmpv6/6lowpan interface.
+//!
+//! This provides one Component, ICMP6Component. This component creates a
+//! separate IPv6/6LoWPAN sender and receiver on top of the MAC layer and
+//! exposes an ICMP6RecvStruct, which answers echo requests sent to this node
+//! and passes echo replies and error messages to its clients.
+//!
+//! As for TCP, the IPv6 layer currently supports a single client per sender
+//! and receiver, so ICMPv6 uses its own instances alongside those of the UDP
+//! stack. To report ICMPv6 errors to UDP apps, set the `MuxUdpReceiver` of
+//! the UDP stack as the error client.
+//!
+//! Usage
+//! -----
+//! ```rust
+//!    let icmp6 = ICMP6Component::new(
+//!        mux_mac,
+//!        ctx_table,
+//!        None,
+//!        DST_MAC_ADDR,
+//!        src_mac_from_serial_num,
+//!        local_ip_ifaces,
+//!        mux_alarm,
+//!    )
+//!    .finalize(components::icmp6_component_static!(
+//!        nrf52840::rtc::Rtc,
+//!        nrf52840::ieee802154_radio::Radio
+//!    ));
+//!    icmp6.set_error_client(udp_recv_mux);
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::ieee802154::device::MacDevice;
+use capsules_extra::net::icmpv6::icmpv6_recv::ICMP6RecvStruct;
+use capsules_extra::net::icmpv6::icmpv6_send::{ICMP6SendStruct, ICMP6Sender};
+use capsules_extra::net::icmpv6::{ICMP6Header, ICMP6Type};
+use capsules_extra::net::ieee802154::MacAddress;
+use capsules_extra::net::ipv6::ip_utils::IPAddr;
+use capsules_extra::net::ipv6::ipv6_recv::IP6Receiver;
+use capsules_extra::net::ipv6::ipv6_recv::IP6RecvStruct;
+use capsules_extra::net::ipv6::ipv6_send::IP6SendStruct;
+use capsules_extra::net::ipv6::ipv6_send::IP6Sender;
+use capsules_extra::net::ipv6::ipv6_send::NeighborResolver;
+use capsules_extra::net::ipv6::{IP6Packet, IPPayload, TransportHeader};
+use capsules_extra::net::network_capabilities::{
+    AddrRange, IpVisibilityCapability, NetworkCapability, PortRange,
+};
+use capsules_extra::net::sixlowpan::{sixlowpan_compression, sixlowpan_state};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::radio;
+use kernel::hil::time::Alarm;
+
+/// The maximum ICMPv6 payload, for both echo requests sent by apps and echo
+/// replies sent by the kernel.
+pub const ICMP6_MAX_PAYLOAD_LEN: usize = 200;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! icmp6_component_static {
+    ($A:ty, $M:ty $(,)?) => {{
+        use capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm;
+        use capsules_extra::net::sixlowpan::{sixlowpan_compression, sixlowpan_state};
+        use components::icmpv6::ICMP6_MAX_PAYLOAD_LEN;
+
+        let ip_alarm = kernel::static_buf!(VirtualMuxAlarm<'static, $A>);
+        let mac_user =
+            kernel::static_buf!(capsules_extra::ieee802154::virtual_mac::MacUser<'static, $M>);
+        let sixlowpan = kernel::static_buf!(
+            sixlowpan_state::Sixlowpan<
+                'static,
+                VirtualMuxAlarm<'static, $A>,
+                &'static sixlowpan_compression::ContextTable,
+            >
+        );
+        let rx_state = kernel::static_buf!(sixlowpan_state::RxState<'static>);
+        let ip6_send = kernel::static_buf!(
+            capsules_extra::net::ipv6::ipv6_send::IP6SendStruct<
+                'static,
+                VirtualMuxAlarm<'static, $A>,
+            >
+        );
+        let ip6_packet = kernel::static_buf!(capsules_extra::net::ipv6::IP6Packet<'static>);
+        let ip6_receive =
+            kernel::static_buf!(capsules_extra::net::ipv6::ipv6_recv::IP6RecvStruct<'static>);
+        let icmp_send = kernel::static_buf!(
+            capsules_extra::net::icmpv6::icmpv6_send::ICMP6SendStruct<
+                'static,
+                capsules_extra::net::ipv6::ipv6_send::IP6SendStruct<
+                    'static,
+                    VirtualMuxAlarm<'static, $A>,
+                >,
+            >
+        );
+        let icmp_recv =
+            kernel::static_buf!(capsules_extra::net::icmpv6::icmpv6_recv::ICMP6RecvStruct<'static>);
+
+        let radio_buf = kernel::static_buf!([u8; kernel::hil::radio::MAX_BUF_SIZE]);
+        let sixlowpan_rx = kernel::static_buf!([u8; 1280]);
+        let ip_payload = kernel::static_buf!([u8; ICMP6_MAX_PAYLOAD_LEN]);
+        let reply_buf = kernel::static_buf!([u8; ICMP6_MAX_PAYLOAD_LEN]);
+
+        let ip_vis_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::IpVisibilityCapability);
+        let reply_net_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::NetworkCapability);
+
+        (
+            ip_alarm,
+            mac_user,
+            sixlowpan,
+            rx_state,
+            ip6_send,
+            ip6_packet,
+            ip6_receive,
+            icmp_send,
+            icmp_recv,
+            radio_buf,
+            sixlowpan_rx,
+            ip_payload,
+            reply_buf,
+            ip_vis_cap,
+            reply_net_cap,
+        )
+    };};
+}
+
+pub struct ICMP6Component<A: Alarm<'static> + 'static, M: MacDevice<'static> + 'static> {
+    mux_mac: &'static capsules_extra::ieee802154::virtual_mac::MuxMac<'static, M>,
+    ctx_store: &'static sixlowpan_compression::ContextTable,
+    resolver: Option<&'static dyn NeighborResolver>,
+    dst_mac_addr: MacAddress,
+    src_mac_addr: MacAddress,
+    interface_list: &'static [IPAddr],
+    alarm_mux: &'static MuxAlarm<'static, A>,
+}
+
+impl<A: Alarm<'static> + 'static, M: MacDevice<'static>> ICMP6Component<A, M> {
+    pub fn new(
+        mux_mac: &'static capsules_extra::ieee802154::virtual_mac::MuxMac<'static, M>,
+        ctx_store: &'static sixlowpan_compression::ContextTable,
+        resolver: Option<&'static dyn NeighborResolver>,
+        dst_mac_addr: MacAddress,
+        src_mac_addr: MacAddress,
+        interface_list: &'static [IPAddr],
+        alarm_mux: &'static MuxAlarm<'static, A>,
+    ) -> Self {
+        Self {
+            mux_mac,
+            ctx_store,
+            resolver,
+            dst_mac_addr,
+            src_mac_addr,
+            interface_list,
+            alarm_mux,
+        }
+    }
+}
+
+impl<A: Alarm<'static> + 'static, M: MacDevice<'static>> Component for ICMP6Component<A, M> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<capsules_extra::ieee802154::virtual_mac::MacUser<'static, M>>,
+        &'static mut MaybeUninit<
+            sixlowpan_state::Sixlowpan<
+                'static,
+                VirtualMuxAlarm<'static, A>,
+                &'static sixlowpan_compression::ContextTable,
+            >,
+        >,
+        &'static mut MaybeUninit<sixlowpan_state::RxState<'static>>,
+        &'static mut MaybeUninit<IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
+        &'static mut MaybeUninit<IP6Packet<'static>>,
+        &'static mut MaybeUninit<IP6RecvStruct<'static>>,
+        &'static mut MaybeUninit<
+            ICMP6SendStruct<'static, IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
+        >,
+        &'static mut MaybeUninit<ICMP6RecvStruct<'static>>,
+        &'static mut MaybeUninit<[u8; radio::MAX_BUF_SIZE]>,
+        &'static mut MaybeUninit<[u8; 1280]>,
+        &'static mut MaybeUninit<[u8; ICMP6_MAX_PAYLOAD_LEN]>,
+        &'static mut MaybeUninit<[u8; ICMP6_MAX_PAYLOAD_LEN]>,
+        &'static mut MaybeUninit<IpVisibilityCapability>,
+        &'static mut MaybeUninit<NetworkCapability>,
+    );
+    type Output = &'static ICMP6RecvStruct<'static>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let ipsender_virtual_alarm = s.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        ipsender_virtual_alarm.setup();
+
+        let icmp_mac =
+            s.1.write(capsules_extra::ieee802154::virtual_mac::MacUser::new(
+                self.mux_mac,
+            ));
+        self.mux_mac.add_user(icmp_mac);
+        let create_cap = create_capability!(capabilities::NetworkCapabilityCreationCapability);
+        let ip_vis = s.13.write(IpVisibilityCapability::new(&create_cap));
+        let reply_net_cap = s.14.write(NetworkCapability::new(
+            AddrRange::Any,
+            PortRange::Any,
+            PortRange::Any,
+            &create_cap,
+        ));
+
+        let sixlowpan = s.2.write(sixlowpan_state::Sixlowpan::new(
+            self.ctx_store,
+            ipsender_virtual_alarm, // OK to reuse bc only used to get time, not set alarms
+        ));
+
+        let sixlowpan_rx_buffer = s.10.write([0; 1280]);
+        let sixlowpan_state = sixlowpan as &dyn sixlowpan_state::SixlowpanState;
+        let sixlowpan_tx = sixlowpan_state::TxState::new(sixlowpan_state);
+        let default_rx_state =
+            s.3.write(sixlowpan_state::RxState::new(sixlowpan_rx_buffer));
+        sixlowpan_state.add_rx_state(default_rx_state);
+        icmp_mac.set_receive_client(sixlowpan);
+
+        let ip_payload_buffer = s.11.write([0; ICMP6_MAX_PAYLOAD_LEN]);
+        let ip_pyld: IPPayload = IPPayload {
+            header: TransportHeader::ICMP(ICMP6Header::new(ICMP6Type::Type129)),
+            payload: ip_payload_buffer,
+        };
+        let ip6_dg = s.5.write(IP6Packet::new(ip_pyld));
+
+        let radio_buf = s.9.write([0; radio::MAX_BUF_SIZE]);
+
+        let ip_send = s.4.write(IP6SendStruct::new(
+            ip6_dg,
+            ipsender_virtual_alarm,
+            radio_buf,
+            sixlowpan_tx,
+            icmp_mac,
+            self.dst_mac_addr,
+            self.src_mac_addr,
+            ip_vis,
+        ));
+        ipsender_virtual_alarm.set_alarm_client(ip_send);
+        ip_send.set_addr(self.interface_list[0]);
+        if let Some(resolver) = self.resolver {
+            ip_send.set_resolver(resolver);
+        }
+        icmp_mac.set_transmit_client(ip_send);
+
+        let ip_receive = s.6.write(IP6RecvStruct::new());
+        sixlowpan_state.set_rx_client(ip_receive);
+
+        let icmp_send = s.7.write(ICMP6SendStruct::new(ip_send));
+        ip_send.set_client(icmp_send);
+
+        let reply_buf = s.12.write([0; ICMP6_MAX_PAYLOAD_LEN]);
+        let icmp_recv = s.8.write(ICMP6RecvStruct::new(
+            icmp_send,
+            self.interface_list,
+            reply_buf,
+            reply_net_cap,
+        ));
+        icmp_send.set_client(icmp_recv);
+        ip_receive.set_client(icmp_recv);
+        // Only this stack reports discarded packets, since the other stacks
+        // receive the same packets.
+        ip_receive.set_error_client(icmp_recv);
+
+        icmp_recv
+    }
+}
@@ -8,6 +8,32 @@
 //! userspace syscall interface to a full 802.15.4 stack with a always-on MAC
 //! implementation, as well as multiplexed access to that MAC implementation.
 //!
+//! `Ieee802154MacComponent` builds the same stack on top of another MAC
+//! layer, such as the one of `CsmaMacComponent`, which adds CSMA-CA,
+//! acknowledgement waiting and retransmission for radios that lack them:
+//!
+//! ```rust
+//! let csma_mac = components::ieee802154::CsmaMacComponent::new(radio, mux_alarm, rng)
+//!     .finalize(components::csma_mac_component_static!(
+//!         capsules_extra::rf233::RF233<'static, RF233Spi>,
+//!         sam4l::ast::Ast<'static>,
+//!     ));
+//!
+//! let (radio_driver, mux_mac) = components::ieee802154::Ieee802154MacComponent::new(
+//!     board_kernel,
+//!     capsules_extra::ieee802154::DRIVER_NUM,
+//!     csma_mac,
+//!     aes_mux,
+//!     PAN_ID,
+//!     SRC_MAC,
+//!     long_addr,
+//! )
+//! .finalize(components::ieee802154_mac_component_static!(
+//!     components::ieee802154::CsmaMacComponentType<RF233Device, sam4l::ast::Ast<'static>>,
+//!     sam4l::aes::Aes<'static>
+//! ));
+//! ```
+//!
 //! Usage
 //! -----
 //! ```rust
@@ -34,6 +60,8 @@
 //! ```
 
 use capsules_core::virtualizers::virtual_aes_ccm::MuxAES128CCM;
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::ieee802154::csma::CsmaMac;
 use capsules_extra::ieee802154::device::MacDevice;
 use capsules_extra::ieee802154::mac::{AwakeMac, Mac};
 use core::mem::MaybeUninit;
@@ -41,7 +69,9 @@ use kernel::capabilities;
 use kernel::component::Component;
 use kernel::create_capability;
 use kernel::hil::radio::{self, MAX_BUF_SIZE};
+use kernel::hil::rng::Rng;
 use kernel::hil::symmetric_encryption::{self, AES128Ctr, AES128, AES128CBC, AES128CCM, AES128ECB};
+use kernel::hil::time;
 
 // This buffer is used as an intermediate buffer for AES CCM encryption. An
 // upper bound on the required size is `3 * BLOCK_SIZE + radio::MAX_BUF_SIZE`.
@@ -85,14 +115,111 @@ impl<A: 'static + AES128<'static> + AES128Ctr + AES128CBC + AES128ECB> Component
 #[macro_export]
 macro_rules! ieee802154_component_static {
     ($R:ty, $A:ty $(,)?) => {{
+        let awake_mac = kernel::static_buf!(capsules_extra::ieee802154::mac::AwakeMac<'static, $R>);
+        let radio_rx_buf = kernel::static_buf!([u8; kernel::hil::radio::MAX_BUF_SIZE]);
+
+        (
+            awake_mac,
+            radio_rx_buf,
+            $crate::ieee802154_mac_component_static!(
+                capsules_extra::ieee802154::mac::AwakeMac<'static, $R>,
+                $A
+            ),
+        )
+    };};
+}
+
+pub type Ieee802154ComponentType<R, A> = Ieee802154MacComponentType<AwakeMac<'static, R>, A>;
+
+pub type Ieee802154ComponentMacDeviceType<R, A> =
+    Ieee802154MacComponentMacDeviceType<AwakeMac<'static, R>, A>;
+
+pub struct Ieee802154Component<
+    R: 'static + kernel::hil::radio::Radio<'static>,
+    A: 'static + AES128<'static> + AES128Ctr + AES128CBC + AES128ECB,
+> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    radio: &'static R,
+    aes_mux: &'static MuxAES128CCM<'static, A>,
+    pan_id: capsules_extra::net::ieee802154::PanID,
+    short_addr: u16,
+    long_addr: [u8; 8],
+}
+
+impl<
+        R: 'static + kernel::hil::radio::Radio<'static>,
+        A: 'static + AES128<'static> + AES128Ctr + AES128CBC + AES128ECB,
+    > Ieee802154Component<R, A>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        radio: &'static R,
+        aes_mux: &'static MuxAES128CCM<'static, A>,
+        pan_id: capsules_extra::net::ieee802154::PanID,
+        short_addr: u16,
+        long_addr: [u8; 8],
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            radio,
+            aes_mux,
+            pan_id,
+            short_addr,
+            long_addr,
+        }
+    }
+}
+
+impl<
+        R: 'static + kernel::hil::radio::Radio<'static>,
+        A: 'static + AES128<'static> + AES128Ctr + AES128CBC + AES128ECB,
+    > Component for Ieee802154Component<R, A>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<AwakeMac<'static, R>>,
+        &'static mut MaybeUninit<[u8; radio::MAX_BUF_SIZE]>,
+        <Ieee802154MacComponent<AwakeMac<'static, R>, A> as Component>::StaticInput,
+    );
+    type Output = <Ieee802154MacComponent<AwakeMac<'static, R>, A> as Component>::Output;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        // Keeps the radio on permanently; pass-through layer.
+        let radio_rx_buf = static_buffer.1.write([0; radio::MAX_BUF_SIZE]);
+        let awake_mac = static_buffer.0.write(AwakeMac::new(self.radio));
+        self.radio.set_transmit_client(awake_mac);
+        self.radio.set_receive_client(awake_mac);
+        self.radio.set_receive_buffer(radio_rx_buf);
+
+        Ieee802154MacComponent::new(
+            self.board_kernel,
+            self.driver_num,
+            awake_mac,
+            self.aes_mux,
+            self.pan_id,
+            self.short_addr,
+            self.long_addr,
+        )
+        .finalize(static_buffer.2)
+    }
+}
+
+// IEEE 802.15.4 STACK ON ANY MAC LAYER
+
+/// Static space for the 802.15.4 stack on top of the MAC layer `$M`, such as
+/// the `CsmaMac` of a `CsmaMacComponent`.
+#[macro_export]
+macro_rules! ieee802154_mac_component_static {
+    ($M:ty, $A:ty $(,)?) => {{
         let virtual_aes = kernel::static_buf!(
             capsules_core::virtualizers::virtual_aes_ccm::VirtualAES128CCM<'static, $A>
         );
-        let awake_mac = kernel::static_buf!(capsules_extra::ieee802154::mac::AwakeMac<'static, $R>);
         let framer = kernel::static_buf!(
             capsules_extra::ieee802154::framer::Framer<
                 'static,
-                capsules_extra::ieee802154::mac::AwakeMac<'static, $R>,
+                $M,
                 capsules_core::virtualizers::virtual_aes_ccm::VirtualAES128CCM<'static, $A>,
             >
         );
@@ -102,7 +229,7 @@ macro_rules! ieee802154_component_static {
                 'static,
                 capsules_extra::ieee802154::framer::Framer<
                     'static,
-                    capsules_extra::ieee802154::mac::AwakeMac<'static, $R>,
+                    $M,
                     capsules_core::virtualizers::virtual_aes_ccm::VirtualAES128CCM<'static, $A>,
                 >,
             >
@@ -112,7 +239,7 @@ macro_rules! ieee802154_component_static {
                 'static,
                 capsules_extra::ieee802154::framer::Framer<
                     'static,
-                    capsules_extra::ieee802154::mac::AwakeMac<'static, $R>,
+                    $M,
                     capsules_core::virtualizers::virtual_aes_ccm::VirtualAES128CCM<'static, $A>,
                 >,
             >
@@ -124,7 +251,7 @@ macro_rules! ieee802154_component_static {
                     'static,
                     capsules_extra::ieee802154::framer::Framer<
                         'static,
-                        capsules_extra::ieee802154::mac::AwakeMac<'static, $R>,
+                        $M,
                         capsules_core::virtualizers::virtual_aes_ccm::VirtualAES128CCM<'static, $A>,
                     >,
                 >,
@@ -132,50 +259,46 @@ macro_rules! ieee802154_component_static {
         );
 
         let radio_buf = kernel::static_buf!([u8; kernel::hil::radio::MAX_BUF_SIZE]);
-        let radio_rx_buf = kernel::static_buf!([u8; kernel::hil::radio::MAX_BUF_SIZE]);
         let crypt_buf = kernel::static_buf!([u8; components::ieee802154::CRYPT_SIZE]);
         let radio_rx_crypt_buf = kernel::static_buf!([u8; kernel::hil::radio::MAX_BUF_SIZE]);
 
         (
             virtual_aes,
-            awake_mac,
             framer,
             mux_mac,
             mac_user,
             radio_driver,
             radio_buf,
-            radio_rx_buf,
             crypt_buf,
             radio_rx_crypt_buf,
         )
     };};
 }
 
-pub type Ieee802154ComponentType<R, A> = capsules_extra::ieee802154::RadioDriver<
+pub type Ieee802154MacComponentType<M, A> = capsules_extra::ieee802154::RadioDriver<
     'static,
     capsules_extra::ieee802154::virtual_mac::MacUser<
         'static,
-        capsules_extra::ieee802154::framer::Framer<
-            'static,
-            capsules_extra::ieee802154::mac::AwakeMac<'static, R>,
-            capsules_core::virtualizers::virtual_aes_ccm::VirtualAES128CCM<'static, A>,
-        >,
+        Ieee802154MacComponentMacDeviceType<M, A>,
     >,
 >;
 
-pub type Ieee802154ComponentMacDeviceType<R, A> = capsules_extra::ieee802154::framer::Framer<
+pub type Ieee802154MacComponentMacDeviceType<M, A> = capsules_extra::ieee802154::framer::Framer<
     'static,
-    capsules_extra::ieee802154::mac::AwakeMac<'static, R>,
+    M,
     capsules_core::virtualizers::virtual_aes_ccm::VirtualAES128CCM<'static, A>,
 >;
 
-pub struct Ieee802154Component<
-    R: 'static + kernel::hil::radio::Radio<'static>,
+/// The 802.15.4 stack and its userspace driver on top of a MAC layer. The
+/// MAC layer must be the transmit and receive client of its radio, and hold
+/// a receive buffer.
+pub struct Ieee802154MacComponent<
+    M: 'static + Mac<'static>,
     A: 'static + AES128<'static> + AES128Ctr + AES128CBC + AES128ECB,
 > {
     board_kernel: &'static kernel::Kernel,
     driver_num: usize,
-    radio: &'static R,
+    mac: &'static M,
     aes_mux: &'static MuxAES128CCM<'static, A>,
     pan_id: capsules_extra::net::ieee802154::PanID,
     short_addr: u16,
@@ -183,14 +306,14 @@ pub struct Ieee802154Component<
 }
 
 impl<
-        R: 'static + kernel::hil::radio::Radio<'static>,
+        M: 'static + Mac<'static>,
         A: 'static + AES128<'static> + AES128Ctr + AES128CBC + AES128ECB,
-    > Ieee802154Component<R, A>
+    > Ieee802154MacComponent<M, A>
 {
     pub fn new(
         board_kernel: &'static kernel::Kernel,
         driver_num: usize,
-        radio: &'static R,
+        mac: &'static M,
         aes_mux: &'static MuxAES128CCM<'static, A>,
         pan_id: capsules_extra::net::ieee802154::PanID,
         short_addr: u16,
@@ -199,7 +322,7 @@ impl<
         Self {
             board_kernel,
             driver_num,
-            radio,
+            mac,
             aes_mux,
             pan_id,
             short_addr,
@@ -209,86 +332,44 @@ impl<
 }
 
 impl<
-        R: 'static + kernel::hil::radio::Radio<'static>,
+        M: 'static + Mac<'static>,
         A: 'static + AES128<'static> + AES128Ctr + AES128CBC + AES128ECB,
-    > Component for Ieee802154Component<R, A>
+    > Component for Ieee802154MacComponent<M, A>
 {
     type StaticInput = (
         &'static mut MaybeUninit<
             capsules_core::virtualizers::virtual_aes_ccm::VirtualAES128CCM<'static, A>,
         >,
-        &'static mut MaybeUninit<capsules_extra::ieee802154::mac::AwakeMac<'static, R>>,
-        &'static mut MaybeUninit<
-            capsules_extra::ieee802154::framer::Framer<
-                'static,
-                AwakeMac<'static, R>,
-                capsules_core::virtualizers::virtual_aes_ccm::VirtualAES128CCM<'static, A>,
-            >,
-        >,
+        &'static mut MaybeUninit<Ieee802154MacComponentMacDeviceType<M, A>>,
         &'static mut MaybeUninit<
             capsules_extra::ieee802154::virtual_mac::MuxMac<
                 'static,
-                capsules_extra::ieee802154::framer::Framer<
-                    'static,
-                    AwakeMac<'static, R>,
-                    capsules_core::virtualizers::virtual_aes_ccm::VirtualAES128CCM<'static, A>,
-                >,
+                Ieee802154MacComponentMacDeviceType<M, A>,
             >,
         >,
         &'static mut MaybeUninit<
             capsules_extra::ieee802154::virtual_mac::MacUser<
                 'static,
-                capsules_extra::ieee802154::framer::Framer<
-                    'static,
-                    AwakeMac<'static, R>,
-                    capsules_core::virtualizers::virtual_aes_ccm::VirtualAES128CCM<'static, A>,
-                >,
-            >,
-        >,
-        &'static mut MaybeUninit<
-            capsules_extra::ieee802154::RadioDriver<
-                'static,
-                capsules_extra::ieee802154::virtual_mac::MacUser<
-                    'static,
-                    capsules_extra::ieee802154::framer::Framer<
-                        'static,
-                        AwakeMac<'static, R>,
-                        capsules_core::virtualizers::virtual_aes_ccm::VirtualAES128CCM<'static, A>,
-                    >,
-                >,
+                Ieee802154MacComponentMacDeviceType<M, A>,
             >,
         >,
-        &'static mut MaybeUninit<[u8; radio::MAX_BUF_SIZE]>,
+        &'static mut MaybeUninit<Ieee802154MacComponentType<M, A>>,
         &'static mut MaybeUninit<[u8; radio::MAX_BUF_SIZE]>,
         &'static mut MaybeUninit<[u8; CRYPT_SIZE]>,
         &'static mut MaybeUninit<[u8; radio::MAX_BUF_SIZE]>,
     );
     type Output = (
-        &'static capsules_extra::ieee802154::RadioDriver<
-            'static,
-            capsules_extra::ieee802154::virtual_mac::MacUser<
-                'static,
-                capsules_extra::ieee802154::framer::Framer<
-                    'static,
-                    AwakeMac<'static, R>,
-                    capsules_core::virtualizers::virtual_aes_ccm::VirtualAES128CCM<'static, A>,
-                >,
-            >,
-        >,
+        &'static Ieee802154MacComponentType<M, A>,
         &'static capsules_extra::ieee802154::virtual_mac::MuxMac<
             'static,
-            capsules_extra::ieee802154::framer::Framer<
-                'static,
-                AwakeMac<'static, R>,
-                capsules_core::virtualizers::virtual_aes_ccm::VirtualAES128CCM<'static, A>,
-            >,
+            Ieee802154MacComponentMacDeviceType<M, A>,
         >,
     );
 
     fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
         let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
 
-        let crypt_buf = static_buffer.8.write([0; CRYPT_SIZE]);
+        let crypt_buf = static_buffer.6.write([0; CRYPT_SIZE]);
         let aes_ccm = static_buffer.0.write(
             capsules_core::virtualizers::virtual_aes_ccm::VirtualAES128CCM::new(
                 self.aes_mux,
@@ -297,29 +378,22 @@ impl<
         );
         aes_ccm.setup();
 
-        // Keeps the radio on permanently; pass-through layer.
-        let radio_rx_buf = static_buffer.7.write([0; radio::MAX_BUF_SIZE]);
-        let awake_mac = static_buffer.1.write(AwakeMac::new(self.radio));
-        self.radio.set_transmit_client(awake_mac);
-        self.radio.set_receive_client(awake_mac);
-        self.radio.set_receive_buffer(radio_rx_buf);
-
-        let radio_rx_crypt_buf = static_buffer.9.write([0; MAX_BUF_SIZE]);
+        let radio_rx_crypt_buf = static_buffer.7.write([0; MAX_BUF_SIZE]);
 
         let mac_device = static_buffer
-            .2
+            .1
             .write(capsules_extra::ieee802154::framer::Framer::new(
-                awake_mac,
+                self.mac,
                 aes_ccm,
                 kernel::utilities::leasable_buffer::SubSliceMut::new(radio_rx_crypt_buf),
             ));
         AES128CCM::set_client(aes_ccm, mac_device);
-        awake_mac.set_transmit_client(mac_device);
-        awake_mac.set_receive_client(mac_device);
-        awake_mac.set_config_client(mac_device);
+        self.mac.set_transmit_client(mac_device);
+        self.mac.set_receive_client(mac_device);
+        self.mac.set_config_client(mac_device);
 
         let mux_mac = static_buffer
-            .3
+            .2
             .write(capsules_extra::ieee802154::virtual_mac::MuxMac::new(
                 mac_device,
             ));
@@ -328,15 +402,15 @@ impl<
 
         let userspace_mac =
             static_buffer
-                .4
+                .3
                 .write(capsules_extra::ieee802154::virtual_mac::MacUser::new(
                     mux_mac,
                 ));
         mux_mac.add_user(userspace_mac);
 
-        let radio_buffer = static_buffer.6.write([0; radio::MAX_BUF_SIZE]);
+        let radio_buffer = static_buffer.5.write([0; radio::MAX_BUF_SIZE]);
         let radio_driver = static_buffer
-            .5
+            .4
             .write(capsules_extra::ieee802154::RadioDriver::new(
                 userspace_mac,
                 self.board_kernel.create_grant(self.driver_num, &grant_cap),
@@ -356,6 +430,85 @@ impl<
     }
 }
 
+// IEEE 802.15.4 CSMA-CA MAC LAYER
+
+#[macro_export]
+macro_rules! csma_mac_component_static {
+    ($R:ty, $T:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $T>
+        );
+        let csma_mac = kernel::static_buf!(
+            capsules_extra::ieee802154::csma::CsmaMac<
+                'static,
+                $R,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $T>,
+            >
+        );
+        let radio_rx_buf = kernel::static_buf!([u8; kernel::hil::radio::MAX_BUF_SIZE]);
+
+        (alarm, csma_mac, radio_rx_buf)
+    };};
+}
+
+pub type CsmaMacComponentType<R, T> = CsmaMac<'static, R, VirtualMuxAlarm<'static, T>>;
+
+/// MAC layer with CSMA-CA, acknowledgement waiting and retransmission, for
+/// radios that do not implement them in hardware. The resulting MAC layer is
+/// the backend of an `Ieee802154MacComponent`.
+pub struct CsmaMacComponent<
+    R: 'static + kernel::hil::radio::Radio<'static>,
+    T: 'static + time::Alarm<'static>,
+> {
+    radio: &'static R,
+    alarm_mux: &'static MuxAlarm<'static, T>,
+    rng: &'static dyn Rng<'static>,
+}
+
+impl<R: 'static + kernel::hil::radio::Radio<'static>, T: 'static + time::Alarm<'static>>
+    CsmaMacComponent<R, T>
+{
+    pub fn new(
+        radio: &'static R,
+        alarm_mux: &'static MuxAlarm<'static, T>,
+        rng: &'static dyn Rng<'static>,
+    ) -> Self {
+        Self {
+            radio,
+            alarm_mux,
+            rng,
+        }
+    }
+}
+
+impl<R: 'static + kernel::hil::radio::Radio<'static>, T: 'static + time::Alarm<'static>> Component
+    for CsmaMacComponent<R, T>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, T>>,
+        &'static mut MaybeUninit<CsmaMac<'static, R, VirtualMuxAlarm<'static, T>>>,
+        &'static mut MaybeUninit<[u8; radio::MAX_BUF_SIZE]>,
+    );
+    type Output = &'static CsmaMacComponentType<R, T>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let radio_rx_buf = static_buffer.2.write([0; radio::MAX_BUF_SIZE]);
+        let csma_mac = static_buffer
+            .1
+            .write(CsmaMac::new(self.radio, alarm, self.rng));
+        time::Alarm::set_alarm_client(alarm, csma_mac);
+        self.rng.set_client(csma_mac);
+        self.radio.set_transmit_client(csma_mac);
+        self.radio.set_receive_client(csma_mac);
+        self.radio.set_receive_buffer(radio_rx_buf);
+
+        csma_mac
+    }
+}
+
 // IEEE 802.15.4 RAW DRIVER
 
 // Setup static space for the objects.
@@ -12,6 +12,7 @@ pub mod alarm;
 pub mod analog_comparator;
 pub mod apds9960;
 pub mod app_flash_driver;
+pub mod app_loader;
 pub mod appid;
 pub mod atecc508a;
 pub mod ble;
@@ -23,13 +24,18 @@ pub mod button;
 pub mod can;
 pub mod ccs811;
 pub mod cdc;
+pub mod coap;
 pub mod console;
+pub mod crash_log;
 pub mod crc;
 pub mod ctap;
+pub mod ctap2;
 pub mod dac;
 pub mod date_time;
 pub mod debug_queue;
 pub mod debug_writer;
+pub mod ecdsa;
+pub mod ethernet_radio;
 pub mod eui64;
 pub mod flash;
 pub mod fm25cl;
@@ -42,6 +48,7 @@ pub mod hs3003;
 pub mod hts221;
 pub mod humidity;
 pub mod i2c;
+pub mod icmpv6;
 pub mod ieee802154;
 pub mod isl29035;
 pub mod keyboard_hid;
@@ -59,11 +66,14 @@ pub mod lsm303dlhc;
 pub mod lsm6dsox;
 pub mod ltc294x;
 pub mod mlx90614;
+pub mod msc;
 pub mod mx25r6435f;
+pub mod ndp;
 pub mod ninedof;
 pub mod nonvolatile_storage;
 pub mod nrf51822;
 pub mod panic_button;
+pub mod ping_driver;
 pub mod pressure;
 pub mod process_console;
 pub mod process_printer;
@@ -80,11 +90,15 @@ pub mod sht3x;
 pub mod sht4x;
 pub mod si7021;
 pub mod siphash;
+pub mod sixlowpan_context;
 pub mod sound_pressure;
 pub mod spi;
 pub mod ssd1306;
 pub mod st77xx;
 pub mod storage_permissions;
+pub mod syscall_trace;
+pub mod tcp_driver;
+pub mod tcp_mux;
 pub mod temperature;
 pub mod temperature_rp2040;
 pub mod temperature_stm;
@@ -93,6 +107,9 @@ pub mod text_screen;
 pub mod thread_network;
 pub mod tickv;
 pub mod touch;
+pub mod uart_app_loader;
 pub mod udp_driver;
 pub mod udp_mux;
+pub mod udp_mux_ethernet;
 pub mod usb;
+pub mod usb_composite;
@@ -0,0 +1,121 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for USB mass storage support.
+//!
+//! This provides a component for using the mass storage driver. This exposes
+//! a region of a nonvolatile storage to the host as a USB drive.
+//!
+//! The driver becomes the client of the storage, so the storage cannot be
+//! shared with another user, such as the nonvolatile storage driver.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! static STRINGS: &'static [&str; 3] = &[
+//!     "XYZ Corp.",     // Manufacturer
+//!     "Log Drive",     // Product
+//!     "0123456789AB",  // Serial number
+//! ];
+//! let msc = components::msc::MassStorageComponent::new(
+//!     &nrf52::usbd::USBD,
+//!     capsules_extra::usb::cdc::MAX_CTRL_PACKET_SIZE_NRF52840,
+//!     0x1915,
+//!     0x503a,
+//!     STRINGS,
+//!     nv_to_page,
+//!     0x60000,  // Start address of the drive in the storage
+//!     0x100000, // Length of the drive
+//!     false,    // Writable by the host
+//! )
+//! .finalize(components::msc_component_static!(nrf52::usbd::Usbd));
+//!
+//! msc.enable();
+//! msc.attach();
+//! ```
+
+use core::mem::MaybeUninit;
+
+use capsules_extra::usb::msc::{MassStorage, BLOCK_SIZE};
+use kernel::component::Component;
+use kernel::hil;
+use kernel::hil::nonvolatile_storage::NonvolatileStorage;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! msc_component_static {
+    ($U:ty $(,)?) => {{
+        let msc = kernel::static_buf!(capsules_extra::usb::msc::MassStorage<'static, $U>);
+        let block_buffer = kernel::static_buf!([u8; capsules_extra::usb::msc::BLOCK_SIZE]);
+
+        (msc, block_buffer)
+    };};
+}
+
+pub struct MassStorageComponent<U: 'static + hil::usb::UsbController<'static>> {
+    usb: &'static U,
+    max_ctrl_packet_size: u8,
+    vendor_id: u16,
+    product_id: u16,
+    strings: &'static [&'static str; 3],
+    storage: &'static dyn NonvolatileStorage<'static>,
+    start: usize,
+    length: usize,
+    read_only: bool,
+}
+
+impl<U: 'static + hil::usb::UsbController<'static>> MassStorageComponent<U> {
+    pub fn new(
+        usb: &'static U,
+        max_ctrl_packet_size: u8,
+        vendor_id: u16,
+        product_id: u16,
+        strings: &'static [&'static str; 3],
+        storage: &'static dyn NonvolatileStorage<'static>,
+        start: usize,
+        length: usize,
+        read_only: bool,
+    ) -> Self {
+        Self {
+            usb,
+            max_ctrl_packet_size,
+            vendor_id,
+            product_id,
+            strings,
+            storage,
+            start,
+            length,
+            read_only,
+        }
+    }
+}
+
+impl<U: 'static + hil::usb::UsbController<'static>> Component for MassStorageComponent<U> {
+    type StaticInput = (
+        &'static mut MaybeUninit<MassStorage<'static, U>>,
+        &'static mut MaybeUninit<[u8; BLOCK_SIZE]>,
+    );
+    type Output = &'static MassStorage<'static, U>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let block_buffer = s.1.write([0; BLOCK_SIZE]);
+
+        let msc = s.0.write(MassStorage::new(
+            self.usb,
+            self.max_ctrl_packet_size,
+            self.vendor_id,
+            self.product_id,
+            self.strings,
+            self.storage,
+            self.start,
+            self.length,
+            block_buffer,
+            self.read_only,
+        ));
+        self.usb.set_client(msc);
+        self.storage.set_client(msc);
+
+        msc
+    }
+}
@@ -0,0 +1,100 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Component for IPv6 Neighbor Discovery over 6LoWPAN.
+//!
+//! This provides one Component, NdpComponent, which creates the `Ndp`
+//! instance of a board. `Ndp` autoconfigures an address from the prefix
+//! advertised by a router and the EUI-64 of the node, and configures the
+//! shared 6LoWPAN context table.
+//!
+//! The UDP, TCP and ICMPv6 stacks use `Ndp` as their resolver, and the
+//! ICMPv6 stack passes it the Neighbor Discovery messages, so it is created
+//! before them and started once the ICMPv6 stack exists.
+//!
+//! Usage
+//! -----
+//! ```rust
+//!    let ndp = components::ndp::NdpComponent::new(mux_alarm, ctx_table, eui64, local_ip_ifaces)
+//!        .finalize(components::ndp_component_static!(nrf52840::rtc::Rtc));
+//!
+//!    // Create the UDP, TCP and ICMPv6 stacks with `Some(ndp)` as resolver.
+//!
+//!    icmp6.set_nd_client(ndp);
+//!    ndp.set_icmp(icmp6);
+//!    ndp.start();
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::eui64::Eui64;
+use capsules_extra::net::icmpv6::ndp::Ndp;
+use capsules_extra::net::ieee802154::MacAddress;
+use capsules_extra::net::ipv6::ip_utils::IPAddr;
+use capsules_extra::net::sixlowpan::sixlowpan_compression::ContextTable;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::time::Alarm;
+
+#[macro_export]
+macro_rules! ndp_component_static {
+    ($A:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let ndp = kernel::static_buf!(
+            capsules_extra::net::icmpv6::ndp::Ndp<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+
+        (alarm, ndp)
+    };};
+}
+
+pub struct NdpComponent<A: Alarm<'static> + 'static> {
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    ctx_store: &'static ContextTable,
+    eui64: &'static Eui64,
+    interface_list: &'static [IPAddr],
+}
+
+impl<A: Alarm<'static> + 'static> NdpComponent<A> {
+    pub fn new(
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        ctx_store: &'static ContextTable,
+        eui64: &'static Eui64,
+        interface_list: &'static [IPAddr],
+    ) -> Self {
+        Self {
+            alarm_mux,
+            ctx_store,
+            eui64,
+            interface_list,
+        }
+    }
+}
+
+impl<A: Alarm<'static> + 'static> Component for NdpComponent<A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<Ndp<'static, VirtualMuxAlarm<'static, A>>>,
+    );
+    type Output = &'static Ndp<'static, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let ndp_alarm = s.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        ndp_alarm.setup();
+
+        let ndp = s.1.write(Ndp::new(
+            ndp_alarm,
+            self.ctx_store,
+            MacAddress::Long(self.eui64.long_addr()),
+            self.interface_list,
+        ));
+        ndp_alarm.set_alarm_client(ndp);
+
+        ndp
+    }
+}
@@ -0,0 +1,100 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Component to initialize the userland ping driver.
+//!
+//! This provides one Component, PingDriverComponent. This component
+//! initializes a userspace driver that sends ICMPv6 echo requests through an
+//! ICMP6RecvStruct and reports the matching echo replies.
+//!
+//! Usage
+//! -----
+//! ```rust
+//!    let ping_driver = PingDriverComponent::new(
+//!        board_kernel,
+//!        capsules_extra::net::icmpv6::driver::DRIVER_NUM,
+//!        icmp6,
+//!     )
+//!     .finalize(components::ping_driver_component_static!());
+//! ```
+
+use crate::icmpv6::ICMP6_MAX_PAYLOAD_LEN;
+use capsules_extra::net::icmpv6::driver::PingDriver;
+use capsules_extra::net::icmpv6::icmpv6_recv::{ICMP6Receiver, ICMP6RecvStruct};
+use capsules_extra::net::icmpv6::icmpv6_send::ICMP6Sender;
+use capsules_extra::net::network_capabilities::{AddrRange, NetworkCapability, PortRange};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::capabilities::NetworkCapabilityCreationCapability;
+use kernel::component::Component;
+use kernel::create_capability;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! ping_driver_component_static {
+    () => {{
+        use components::icmpv6::ICMP6_MAX_PAYLOAD_LEN;
+
+        let net_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::NetworkCapability);
+        let buffer = kernel::static_buf!([u8; ICMP6_MAX_PAYLOAD_LEN]);
+        let ping_driver =
+            kernel::static_buf!(capsules_extra::net::icmpv6::driver::PingDriver<'static>);
+
+        (net_cap, buffer, ping_driver)
+    };};
+}
+
+pub struct PingDriverComponent {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    icmp6: &'static ICMP6RecvStruct<'static>,
+}
+
+impl PingDriverComponent {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        icmp6: &'static ICMP6RecvStruct<'static>,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            icmp6,
+        }
+    }
+}
+
+impl Component for PingDriverComponent {
+    type StaticInput = (
+        &'static mut MaybeUninit<NetworkCapability>,
+        &'static mut MaybeUninit<[u8; ICMP6_MAX_PAYLOAD_LEN]>,
+        &'static mut MaybeUninit<PingDriver<'static>>,
+    );
+    type Output = &'static PingDriver<'static>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+        let create_cap = create_capability!(NetworkCapabilityCreationCapability);
+
+        let net_cap = s.0.write(NetworkCapability::new(
+            AddrRange::Any,
+            PortRange::Any,
+            PortRange::Any,
+            &create_cap,
+        ));
+        let buffer = s.1.write([0; ICMP6_MAX_PAYLOAD_LEN]);
+
+        let ping_driver = s.2.write(PingDriver::new(
+            self.icmp6,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            buffer,
+            net_cap,
+        ));
+        ICMP6Sender::set_client(self.icmp6, ping_driver);
+        ICMP6Receiver::set_client(self.icmp6, ping_driver);
+
+        ping_driver
+    }
+}
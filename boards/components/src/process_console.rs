@@ -71,6 +71,7 @@ pub struct ProcessConsoleComponent<const COMMAND_HISTORY_LEN: usize, A: 'static
     alarm_mux: &'static MuxAlarm<'static, A>,
     process_printer: &'static dyn ProcessPrinter,
     reset_function: Option<fn() -> !>,
+    kernel_addresses: process_console::KernelAddresses,
 }
 
 impl<const COMMAND_HISTORY_LEN: usize, A: 'static + Alarm<'static>>
@@ -82,6 +83,43 @@ impl<const COMMAND_HISTORY_LEN: usize, A: 'static + Alarm<'static>>
         alarm_mux: &'static MuxAlarm<'static, A>,
         process_printer: &'static dyn ProcessPrinter,
         reset_function: Option<fn() -> !>,
+    ) -> ProcessConsoleComponent<COMMAND_HISTORY_LEN, A> {
+        // Get addresses of where the kernel is placed to enable additional
+        // debugging in process console.
+        // SAFETY: These statics are defined by the linker script, and we are merely creating
+        // pointers to them.
+        let kernel_addresses = unsafe {
+            process_console::KernelAddresses {
+                stack_start: core::ptr::addr_of!(_sstack),
+                stack_end: core::ptr::addr_of!(_estack),
+                text_start: core::ptr::addr_of!(_stext),
+                text_end: core::ptr::addr_of!(_etext),
+                read_only_data_start: core::ptr::addr_of!(_srodata),
+                relocations_start: core::ptr::addr_of!(_srelocate),
+                relocations_end: core::ptr::addr_of!(_erelocate),
+                bss_start: core::ptr::addr_of!(_szero),
+                bss_end: core::ptr::addr_of!(_ezero),
+            }
+        };
+        Self::new_with_kernel_addresses(
+            board_kernel,
+            uart_mux,
+            alarm_mux,
+            process_printer,
+            reset_function,
+            kernel_addresses,
+        )
+    }
+
+    /// Creates the component for a kernel which is not placed in memory by
+    /// the Tock linker script, with the addresses of its memory regions.
+    pub fn new_with_kernel_addresses(
+        board_kernel: &'static kernel::Kernel,
+        uart_mux: &'static MuxUart,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        process_printer: &'static dyn ProcessPrinter,
+        reset_function: Option<fn() -> !>,
+        kernel_addresses: process_console::KernelAddresses,
     ) -> ProcessConsoleComponent<COMMAND_HISTORY_LEN, A> {
         ProcessConsoleComponent {
             board_kernel,
@@ -89,6 +127,7 @@ impl<const COMMAND_HISTORY_LEN: usize, A: 'static + Alarm<'static>>
             alarm_mux,
             process_printer,
             reset_function,
+            kernel_addresses,
         }
     }
 }
@@ -137,24 +176,6 @@ impl<const COMMAND_HISTORY_LEN: usize, A: 'static + Alarm<'static>> Component
         let console_uart = static_buffer.1.write(UartDevice::new(self.uart_mux, true));
         console_uart.setup();
 
-        // Get addresses of where the kernel is placed to enable additional
-        // debugging in process console.
-        // SAFETY: These statics are defined by the linker script, and we are merely creating
-        // pointers to them.
-        let kernel_addresses = unsafe {
-            process_console::KernelAddresses {
-                stack_start: core::ptr::addr_of!(_sstack),
-                stack_end: core::ptr::addr_of!(_estack),
-                text_start: core::ptr::addr_of!(_stext),
-                text_end: core::ptr::addr_of!(_etext),
-                read_only_data_start: core::ptr::addr_of!(_srodata),
-                relocations_start: core::ptr::addr_of!(_srelocate),
-                relocations_end: core::ptr::addr_of!(_erelocate),
-                bss_start: core::ptr::addr_of!(_szero),
-                bss_end: core::ptr::addr_of!(_ezero),
-            }
-        };
-
         let console_alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
         console_alarm.setup();
 
@@ -184,7 +205,7 @@ impl<const COMMAND_HISTORY_LEN: usize, A: 'static + Alarm<'static>> Component
             command_buffer,
             command_history_buffer,
             self.board_kernel,
-            kernel_addresses,
+            self.kernel_addresses,
             self.reset_function,
             Capability,
         ));
@@ -0,0 +1,61 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the driver letting processes declare their timing
+//! requirements to a deadline scheduler.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let scheduler = components::sched::edf::EDFComponent::new(
+//!     mux_alarm,
+//!     &*addr_of!(PROCESSES),
+//!     Some(process_printer),
+//! )
+//! .finalize(components::edf_component_static!(sam4l::ast::Ast, NUM_PROCS));
+//! let deadline_scheduler =
+//!     components::sched::deadline_scheduler::DeadlineSchedulerComponent::new(scheduler).finalize(
+//!         components::deadline_scheduler_component_static!(
+//!             kernel::scheduler::edf::EDFSched<
+//!                 'static,
+//!                 VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+//!             >
+//!         ),
+//!     );
+//! ```
+
+use capsules_extra::deadline_scheduler::DeadlineSchedulerDriver;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::scheduler::edf::DeadlineScheduler;
+
+#[macro_export]
+macro_rules! deadline_scheduler_component_static {
+    ($S:ty $(,)?) => {{
+        kernel::static_buf!(
+            capsules_extra::deadline_scheduler::DeadlineSchedulerDriver<'static, $S>
+        )
+    };};
+}
+
+pub type DeadlineSchedulerComponentType<S> = DeadlineSchedulerDriver<'static, S>;
+
+pub struct DeadlineSchedulerComponent<S: DeadlineScheduler + 'static> {
+    scheduler: &'static S,
+}
+
+impl<S: DeadlineScheduler + 'static> DeadlineSchedulerComponent<S> {
+    pub fn new(scheduler: &'static S) -> Self {
+        Self { scheduler }
+    }
+}
+
+impl<S: DeadlineScheduler + 'static> Component for DeadlineSchedulerComponent<S> {
+    type StaticInput = &'static mut MaybeUninit<DeadlineSchedulerDriver<'static, S>>;
+    type Output = &'static DeadlineSchedulerDriver<'static, S>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        s.write(DeadlineSchedulerDriver::new(self.scheduler))
+    }
+}
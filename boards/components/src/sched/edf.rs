@@ -0,0 +1,99 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for an earliest deadline first scheduler.
+//!
+//! This provides one Component, EDFComponent. Budget overruns and missed
+//! deadlines are reported using the optional process printer.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let scheduler = components::sched::edf::EDFComponent::new(
+//!     mux_alarm,
+//!     &*addr_of!(PROCESSES),
+//!     Some(process_printer),
+//! )
+//! .finalize(components::edf_component_static!(
+//!     sam4l::ast::Ast,
+//!     NUM_PROCS
+//! ));
+//! ```
+
+use core::mem::MaybeUninit;
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use kernel::component::Component;
+use kernel::hil::time;
+use kernel::process::{Process, ProcessPrinter};
+use kernel::scheduler::edf::{EDFProcessNode, EDFSched};
+
+#[macro_export]
+macro_rules! edf_component_static {
+    ($A:ty, $N:expr $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let edf_sched = kernel::static_buf!(
+            kernel::scheduler::edf::EDFSched<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+        let edf_node = kernel::static_buf!(
+            [core::mem::MaybeUninit<kernel::scheduler::edf::EDFProcessNode<'static>>; $N]
+        );
+
+        (alarm, edf_sched, edf_node)
+    };};
+}
+
+pub struct EDFComponent<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> {
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    processes: &'static [Option<&'static dyn Process>],
+    process_printer: Option<&'static dyn ProcessPrinter>,
+}
+
+impl<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> EDFComponent<A, NUM_PROCS> {
+    pub fn new(
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        processes: &'static [Option<&'static dyn Process>],
+        process_printer: Option<&'static dyn ProcessPrinter>,
+    ) -> EDFComponent<A, NUM_PROCS> {
+        EDFComponent {
+            alarm_mux,
+            processes,
+            process_printer,
+        }
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> Component
+    for EDFComponent<A, NUM_PROCS>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<EDFSched<'static, VirtualMuxAlarm<'static, A>>>,
+        &'static mut MaybeUninit<[MaybeUninit<EDFProcessNode<'static>>; NUM_PROCS]>,
+    );
+    type Output = &'static mut EDFSched<'static, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let scheduler_alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        scheduler_alarm.setup();
+
+        let scheduler = static_buffer
+            .1
+            .write(EDFSched::new(scheduler_alarm, self.process_printer));
+
+        const UNINIT: MaybeUninit<EDFProcessNode<'static>> = MaybeUninit::uninit();
+        let nodes = static_buffer.2.write([UNINIT; NUM_PROCS]);
+
+        for (i, node) in nodes.iter_mut().enumerate() {
+            let init_node = node.write(EDFProcessNode::new(&self.processes[i]));
+            scheduler.processes.push_head(init_node);
+        }
+        scheduler
+    }
+}
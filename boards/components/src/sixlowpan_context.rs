@@ -0,0 +1,61 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Component for the 6LoWPAN context table.
+//!
+//! The context table holds the address prefixes 6LoWPAN compresses
+//! addresses against. It is shared by the UDP, TCP and ICMPv6 stacks of a
+//! board, and contexts advertised by routers are added to it by Neighbor
+//! Discovery.
+//!
+//! Usage
+//! -----
+//! ```rust
+//!    let ctx_table = components::sixlowpan_context::ContextTableComponent::new(
+//!        DEFAULT_CTX_PREFIX_LEN,
+//!        DEFAULT_CTX_PREFIX,
+//!    )
+//!    .finalize(components::context_table_component_static!());
+//! ```
+
+use capsules_extra::net::sixlowpan::sixlowpan_compression::{Context, ContextTable};
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+
+#[macro_export]
+macro_rules! context_table_component_static {
+    () => {{
+        kernel::static_buf!(capsules_extra::net::sixlowpan::sixlowpan_compression::ContextTable)
+    };};
+}
+
+pub struct ContextTableComponent {
+    ctx_pfix_len: u8,
+    ctx_pfix: [u8; 16],
+}
+
+impl ContextTableComponent {
+    /// Creates a context table whose context 0 is `ctx_pfix`. Context 0 is
+    /// only used to decompress addresses until a router advertises it.
+    pub fn new(ctx_pfix_len: u8, ctx_pfix: [u8; 16]) -> Self {
+        Self {
+            ctx_pfix_len,
+            ctx_pfix,
+        }
+    }
+}
+
+impl Component for ContextTableComponent {
+    type StaticInput = &'static mut MaybeUninit<ContextTable>;
+    type Output = &'static ContextTable;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        s.write(ContextTable::new(Context {
+            prefix: self.ctx_pfix,
+            prefix_len: self.ctx_pfix_len,
+            id: 0,
+            compress: false,
+        }))
+    }
+}
@@ -0,0 +1,77 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for runtime system call tracing.
+//!
+//! This provides one Component, SyscallTraceComponent, which creates a ring
+//! buffer of system call records and registers it as the kernel's system
+//! call tracer. Records are timestamped with `time`. The tracer also counts
+//! the system calls made to up to `D` drivers. Handing the trace to the
+//! process console makes it available through the `trace` and `syscalls`
+//! commands.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let syscall_trace = components::syscall_trace::SyscallTraceComponent::new(
+//!     board_kernel,
+//!     &base_peripherals.rtc,
+//! )
+//! .finalize(components::syscall_trace_component_static!(nrf52840::rtc::Rtc<'static>, 64, 16));
+//! process_console.set_syscall_trace(syscall_trace);
+//! ```
+
+use capsules_core::syscall_trace::SyscallTrace;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::time::Time;
+use kernel::syscall_trace::{DriverSyscallStats, SyscallRecord};
+
+#[macro_export]
+macro_rules! syscall_trace_component_static {
+    ($T:ty, $N:expr, $D:expr $(,)?) => {{
+        let trace = kernel::static_buf!(capsules_core::syscall_trace::SyscallTrace<'static, $T>);
+        let records = kernel::static_buf!([kernel::syscall_trace::SyscallRecord; $N]);
+        let driver_stats = kernel::static_buf!([kernel::syscall_trace::DriverSyscallStats; $D]);
+
+        (trace, records, driver_stats)
+    };};
+}
+
+pub struct SyscallTraceComponent<T: 'static + Time, const N: usize, const D: usize> {
+    board_kernel: &'static kernel::Kernel,
+    time: &'static T,
+}
+
+impl<T: 'static + Time, const N: usize, const D: usize> SyscallTraceComponent<T, N, D> {
+    pub fn new(board_kernel: &'static kernel::Kernel, time: &'static T) -> Self {
+        Self { board_kernel, time }
+    }
+}
+
+impl<T: 'static + Time, const N: usize, const D: usize> Component
+    for SyscallTraceComponent<T, N, D>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<SyscallTrace<'static, T>>,
+        &'static mut MaybeUninit<[SyscallRecord; N]>,
+        &'static mut MaybeUninit<[DriverSyscallStats; D]>,
+    );
+    type Output = &'static SyscallTrace<'static, T>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let records = s.1.write([SyscallRecord::default(); N]);
+        let driver_stats = s.2.write([DriverSyscallStats::default(); D]);
+        let trace =
+            s.0.write(SyscallTrace::new(self.time, records, driver_stats));
+
+        let process_mgmt_cap = create_capability!(capabilities::ProcessManagementCapability);
+        self.board_kernel
+            .set_syscall_tracer(trace, &process_mgmt_cap);
+
+        trace
+    }
+}
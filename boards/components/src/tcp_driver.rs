@@ -0,0 +1,106 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Component to initialize the userland TCP driver.
+//!
+//! This provides one Component, TCPDriverComponent. This component
+//! initializes a userspace TCP driver on top of a MuxTcp, along with the pool
+//! of sockets shared by all processes. Each process can hold one socket at a
+//! time, so `NUM_SOCKETS` bounds the number of concurrent connections.
+//!
+//! Usage
+//! -----
+//! ```rust
+//!    let tcp_driver = TCPDriverComponent::new(
+//!        board_kernel,
+//!        capsules_extra::net::tcp::DRIVER_NUM,
+//!        tcp_mux,
+//!     )
+//!     .finalize(components::tcp_driver_component_static!(
+//!         nrf52840::rtc::Rtc,
+//!         NUM_SOCKETS
+//!     ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm;
+use capsules_extra::net::network_capabilities::{AddrRange, NetworkCapability, PortRange};
+use capsules_extra::net::tcp::tcp_mux::{MuxTcp, TCPSocket, TCPStack};
+use capsules_extra::net::tcp::TCPDriver;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::capabilities::NetworkCapabilityCreationCapability;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::time::Alarm;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! tcp_driver_component_static {
+    ($A:ty, $N:expr $(,)?) => {{
+        let sockets =
+            kernel::static_buf!([capsules_extra::net::tcp::tcp_mux::TCPSocket<'static>; $N]);
+        let net_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::NetworkCapability);
+        let tcp_driver = kernel::static_buf!(capsules_extra::net::tcp::TCPDriver<'static>);
+
+        (sockets, net_cap, tcp_driver)
+    };};
+}
+
+pub struct TCPDriverComponent<A: Alarm<'static> + 'static, const N: usize> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    tcp_mux: &'static MuxTcp<'static, VirtualMuxAlarm<'static, A>>,
+}
+
+impl<A: Alarm<'static>, const N: usize> TCPDriverComponent<A, N> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        tcp_mux: &'static MuxTcp<'static, VirtualMuxAlarm<'static, A>>,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            tcp_mux,
+        }
+    }
+}
+
+impl<A: Alarm<'static>, const N: usize> Component for TCPDriverComponent<A, N> {
+    type StaticInput = (
+        &'static mut MaybeUninit<[TCPSocket<'static>; N]>,
+        &'static mut MaybeUninit<NetworkCapability>,
+        &'static mut MaybeUninit<TCPDriver<'static>>,
+    );
+    type Output = &'static TCPDriver<'static>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+        let create_cap = create_capability!(NetworkCapabilityCreationCapability);
+
+        let net_cap = s.1.write(NetworkCapability::new(
+            AddrRange::Any,
+            PortRange::Any,
+            PortRange::Any,
+            &create_cap,
+        ));
+
+        let sockets = s.0.write(core::array::from_fn(TCPSocket::new));
+
+        let tcp_driver = s.2.write(TCPDriver::new(
+            self.tcp_mux,
+            sockets,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            net_cap,
+        ));
+
+        for socket in sockets.iter() {
+            socket.set_client(tcp_driver);
+            self.tcp_mux.add_socket(socket);
+        }
+
+        tcp_driver
+    }
+}
@@ -6,6 +6,9 @@
 //!
 //! This provides one Component, ThreadNetworkComponent. This component initializes
 //! a Thread Network controller for maintaining and managing a Thread network.
+//! The controller uses the random number generator for the MLE challenges and
+//! an HMAC-SHA256 engine to derive the MLE and MAC keys from the Thread network
+//! key.
 //!
 //! Usage
 //! -----
@@ -19,10 +22,13 @@
 //!             aes_mux,
 //!             device_id,
 //!             mux_alarm,
+//!             rng,
+//!             hmac,
 //!         )
 //!         .finalize(components::thread_network_component_static!(
 //!         nrf52840::rtc::Rtc,
-//!         nrf52840::aes::AesECB<'static>
+//!         nrf52840::aes::AesECB<'static>,
+//!         capsules_extra::hmac_sha256::HmacSha256Software<'static, Sha256Software<'static>>,
 //!         ));
 //! ```
 
@@ -35,6 +41,7 @@ use capsules_extra::net::network_capabilities::{
 use kernel::hil::symmetric_encryption::{self, AES128Ctr, AES128, AES128CBC, AES128CCM, AES128ECB};
 
 use capsules_core::virtualizers::virtual_alarm::MuxAlarm;
+use capsules_extra::net::thread::driver::KEY_DERIVATION_DATA_LEN;
 use capsules_extra::net::thread::thread_utils::THREAD_PORT_NUMBER;
 use capsules_extra::net::udp::udp_port_table::UdpPortManager;
 use capsules_extra::net::udp::udp_recv::MuxUdpReceiver;
@@ -45,7 +52,9 @@ use kernel::capabilities;
 use kernel::capabilities::NetworkCapabilityCreationCapability;
 use kernel::component::Component;
 use kernel::create_capability;
+use kernel::hil::digest;
 use kernel::hil::radio;
+use kernel::hil::rng::Rng;
 use kernel::hil::time::Alarm;
 
 const MAX_PAYLOAD_LEN: usize = super::udp_mux::MAX_PAYLOAD_LEN;
@@ -54,7 +63,8 @@ pub const CRYPT_SIZE: usize = 3 * symmetric_encryption::AES128_BLOCK_SIZE + radi
 // Setup static space for the objects.
 #[macro_export]
 macro_rules! thread_network_component_static {
-    ($A:ty, $B:ty $(,)?) => {{
+    ($A:ty, $B:ty, $H:ty $(,)?) => {{
+        use capsules_extra::net::thread::driver::KEY_DERIVATION_DATA_LEN;
         use components::udp_mux::MAX_PAYLOAD_LEN;
 
         let udp_send = kernel::static_buf!(
@@ -74,6 +84,7 @@ macro_rules! thread_network_component_static {
             capsules_extra::net::thread::driver::ThreadNetworkDriver<
                 'static,
                 VirtualMuxAlarm<'static, $A>,
+                $H,
             >
         );
         let send_buffer = kernel::static_buf!([u8; MAX_PAYLOAD_LEN]);
@@ -85,6 +96,8 @@ macro_rules! thread_network_component_static {
             capsules_core::virtualizers::virtual_aes_ccm::VirtualAES128CCM<'static, $B>,
         );
         let alarm = kernel::static_buf!(VirtualMuxAlarm<'static, $A>);
+        let hmac_data = kernel::static_buf!([u8; KEY_DERIVATION_DATA_LEN]);
+        let hmac_digest = kernel::static_buf!([u8; 32]);
 
         (
             udp_send,
@@ -97,12 +110,15 @@ macro_rules! thread_network_component_static {
             crypt_buf,
             crypt,
             alarm,
+            hmac_data,
+            hmac_digest,
         )
     };};
 }
 pub struct ThreadNetworkComponent<
     A: Alarm<'static> + 'static,
     B: AES128<'static> + AES128Ctr + AES128CBC + AES128ECB + 'static,
+    H: digest::Digest<'static, 32> + digest::HmacSha256 + 'static,
 > {
     board_kernel: &'static kernel::Kernel,
     driver_num: usize,
@@ -113,12 +129,15 @@ pub struct ThreadNetworkComponent<
     aes_mux: &'static MuxAES128CCM<'static, B>,
     serial_num: [u8; 8],
     alarm_mux: &'static MuxAlarm<'static, A>,
+    rng: &'static dyn Rng<'static>,
+    hmac: &'static H,
 }
 
 impl<
         A: Alarm<'static> + 'static,
         B: AES128<'static> + AES128Ctr + AES128CBC + AES128ECB + 'static,
-    > ThreadNetworkComponent<A, B>
+        H: digest::Digest<'static, 32> + digest::HmacSha256 + 'static,
+    > ThreadNetworkComponent<A, B, H>
 {
     pub fn new(
         board_kernel: &'static kernel::Kernel,
@@ -132,6 +151,8 @@ impl<
         aes_mux: &'static MuxAES128CCM<'static, B>,
         serial_num: [u8; 8],
         alarm_mux: &'static MuxAlarm<'static, A>,
+        rng: &'static dyn Rng<'static>,
+        hmac: &'static H,
     ) -> Self {
         Self {
             board_kernel,
@@ -142,6 +163,8 @@ impl<
             aes_mux,
             serial_num,
             alarm_mux,
+            rng,
+            hmac,
         }
     }
 }
@@ -149,7 +172,8 @@ impl<
 impl<
         A: Alarm<'static> + 'static,
         B: AES128<'static> + AES128Ctr + AES128CBC + AES128ECB + 'static,
-    > Component for ThreadNetworkComponent<A, B>
+        H: digest::Digest<'static, 32> + digest::HmacSha256 + 'static,
+    > Component for ThreadNetworkComponent<A, B, H>
 {
     type StaticInput = (
         &'static mut MaybeUninit<
@@ -169,6 +193,7 @@ impl<
             capsules_extra::net::thread::driver::ThreadNetworkDriver<
                 'static,
                 VirtualMuxAlarm<'static, A>,
+                H,
             >,
         >,
         &'static mut MaybeUninit<[u8; MAX_PAYLOAD_LEN]>,
@@ -179,10 +204,13 @@ impl<
             capsules_core::virtualizers::virtual_aes_ccm::VirtualAES128CCM<'static, B>,
         >,
         &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<[u8; KEY_DERIVATION_DATA_LEN]>,
+        &'static mut MaybeUninit<[u8; 32]>,
     );
     type Output = &'static capsules_extra::net::thread::driver::ThreadNetworkDriver<
         'static,
         VirtualMuxAlarm<'static, A>,
+        H,
     >;
 
     fn finalize(self, s: Self::StaticInput) -> Self::Output {
@@ -221,24 +249,32 @@ impl<
 
         let send_buffer = s.4.write([0; MAX_PAYLOAD_LEN]);
         let recv_buffer = s.5.write([0; MAX_PAYLOAD_LEN]);
+        let hmac_data = s.10.write([0; KEY_DERIVATION_DATA_LEN]);
+        let hmac_digest = s.11.write([0; 32]);
 
         let thread_network_driver = s.3.write(
             capsules_extra::net::thread::driver::ThreadNetworkDriver::new(
                 udp_send,
                 aes_ccm,
                 thread_virtual_alarm,
+                self.rng,
+                self.hmac,
                 self.board_kernel.create_grant(self.driver_num, &grant_cap),
                 self.serial_num,
                 MAX_PAYLOAD_LEN,
                 self.port_table,
                 kernel::utilities::leasable_buffer::SubSliceMut::new(send_buffer),
                 kernel::utilities::leasable_buffer::SubSliceMut::new(recv_buffer),
+                hmac_data,
+                hmac_digest,
                 &DRIVER_CAP,
                 net_cap,
             ),
         );
 
         thread_virtual_alarm.set_alarm_client(thread_network_driver);
+        self.rng.set_client(thread_network_driver);
+        digest::Digest::set_client(self.hmac, thread_network_driver);
 
         udp_send.set_client(thread_network_driver);
         AES128CCM::set_client(aes_ccm, thread_network_driver);
@@ -0,0 +1,144 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for loading applications over a UART at runtime.
+//!
+//! The loader writes received applications to free app flash through a
+//! `NonvolatileStorage` covering it (for example `NonvolatileToPages` over
+//! the chip's flash), and loads them with the kernel's process loader, shared
+//! with other users through `MuxDynamicProcessLoading` (see
+//! `components::app_loader::DynamicProcessLoadingMuxComponent`).
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let uart_app_loader = components::uart_app_loader::UartAppLoaderComponent::new(
+//!     loader_uart_mux,
+//!     nv_to_page,
+//!     loader_mux,
+//!     mux_alarm,
+//! )
+//! .finalize(components::uart_app_loader_component_static!(
+//!     nrf52840::rtc::Rtc<'static>
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_core::virtualizers::virtual_process_loading::{
+    MuxDynamicProcessLoading, VirtualDynamicProcessLoading,
+};
+use capsules_core::virtualizers::virtual_uart::{MuxUart, UartDevice};
+use capsules_extra::uart_app_loader::UartAppLoader;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil;
+use kernel::hil::nonvolatile_storage::NonvolatileStorage;
+use kernel::hil::time::Alarm;
+use kernel::process::DynamicProcessLoading;
+
+/// Size of the chunks in which applications are sent by the host.
+pub const BUF_LEN: usize = 512;
+
+#[macro_export]
+macro_rules! uart_app_loader_component_static {
+    ($A:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let uart = kernel::static_buf!(capsules_core::virtualizers::virtual_uart::UartDevice);
+        let virtual_loader = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_process_loading::VirtualDynamicProcessLoading<
+                'static,
+            >
+        );
+        let loader = kernel::static_buf!(
+            capsules_extra::uart_app_loader::UartAppLoader<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+        let buffer = kernel::static_buf!([u8; $crate::uart_app_loader::BUF_LEN]);
+        let write_buffer = kernel::static_buf!([u8; $crate::uart_app_loader::BUF_LEN]);
+        let tx_buffer = kernel::static_buf!([u8; 1]);
+
+        (
+            alarm,
+            uart,
+            virtual_loader,
+            loader,
+            buffer,
+            write_buffer,
+            tx_buffer,
+        )
+    };};
+}
+
+pub type UartAppLoaderComponentType<A> = UartAppLoader<'static, VirtualMuxAlarm<'static, A>>;
+
+pub struct UartAppLoaderComponent<A: Alarm<'static> + 'static> {
+    uart_mux: &'static MuxUart<'static>,
+    storage: &'static dyn NonvolatileStorage<'static>,
+    loader_mux: &'static MuxDynamicProcessLoading<'static>,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+}
+
+impl<A: Alarm<'static> + 'static> UartAppLoaderComponent<A> {
+    pub fn new(
+        uart_mux: &'static MuxUart<'static>,
+        storage: &'static dyn NonvolatileStorage<'static>,
+        loader_mux: &'static MuxDynamicProcessLoading<'static>,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+    ) -> Self {
+        Self {
+            uart_mux,
+            storage,
+            loader_mux,
+            alarm_mux,
+        }
+    }
+}
+
+impl<A: Alarm<'static> + 'static> Component for UartAppLoaderComponent<A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<UartDevice<'static>>,
+        &'static mut MaybeUninit<VirtualDynamicProcessLoading<'static>>,
+        &'static mut MaybeUninit<UartAppLoader<'static, VirtualMuxAlarm<'static, A>>>,
+        &'static mut MaybeUninit<[u8; BUF_LEN]>,
+        &'static mut MaybeUninit<[u8; BUF_LEN]>,
+        &'static mut MaybeUninit<[u8; 1]>,
+    );
+    type Output = &'static UartAppLoader<'static, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let alarm = s.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let uart = s.1.write(UartDevice::new(self.uart_mux, true));
+        uart.setup();
+
+        let loader =
+            s.2.write(VirtualDynamicProcessLoading::new(self.loader_mux));
+        loader.setup();
+
+        let uart_app_loader = s.3.write(UartAppLoader::new(
+            uart,
+            self.storage,
+            loader,
+            alarm,
+            s.4.write([0; BUF_LEN]),
+            s.5.write([0; BUF_LEN]),
+            s.6.write([0; 1]),
+        ));
+        hil::uart::Transmit::set_transmit_client(uart, uart_app_loader);
+        hil::uart::Receive::set_receive_client(uart, uart_app_loader);
+        self.storage.set_client(uart_app_loader);
+        loader.set_load_client(uart_app_loader);
+        alarm.set_alarm_client(uart_app_loader);
+
+        let _ = uart_app_loader.start();
+
+        uart_app_loader
+    }
+}
@@ -13,8 +13,8 @@
 //! ```rust
 //!    let (udp_mux, udp_recv) = UDPMuxComponent::new(
 //!        mux_mac,
-//!        DEFAULT_CTX_PREFIX_LEN,
-//!        DEFAULT_CTX_PREFIX,
+//!        ctx_table,
+//!        None,
 //!        DST_MAC_ADDR,
 //!        src_mac_from_serial_num,
 //!        local_ip_ifaces,
@@ -35,6 +35,7 @@ use capsules_extra::net::ipv6::ipv6_recv::IP6Receiver;
 use capsules_extra::net::ipv6::ipv6_recv::IP6RecvStruct;
 use capsules_extra::net::ipv6::ipv6_send::IP6SendStruct;
 use capsules_extra::net::ipv6::ipv6_send::IP6Sender;
+use capsules_extra::net::ipv6::ipv6_send::NeighborResolver;
 use capsules_extra::net::ipv6::{IP6Packet, IPPayload, TransportHeader};
 use capsules_extra::net::network_capabilities::{IpVisibilityCapability, UdpVisibilityCapability};
 use capsules_extra::net::sixlowpan::{sixlowpan_compression, sixlowpan_state};
@@ -80,7 +81,7 @@ macro_rules! udp_mux_component_static {
             sixlowpan_state::Sixlowpan<
                 'static,
                 VirtualMuxAlarm<'static, $A>,
-                sixlowpan_compression::Context,
+                &'static sixlowpan_compression::ContextTable,
             >
         );
         let rx_state = kernel::static_buf!(sixlowpan_state::RxState<'static>);
@@ -127,6 +128,7 @@ macro_rules! udp_mux_component_static {
         let radio_buf = kernel::static_buf!([u8; kernel::hil::radio::MAX_BUF_SIZE]);
         let sixlowpan_rx = kernel::static_buf!([u8; 1280]);
         let udp_dgram = kernel::static_buf!([u8; MAX_PAYLOAD_LEN]);
+        let reassembly_buf = kernel::static_buf!([u8; 1280]);
 
         let udp_vis_cap =
             kernel::static_buf!(capsules_extra::net::network_capabilities::UdpVisibilityCapability);
@@ -150,14 +152,15 @@ macro_rules! udp_mux_component_static {
             udp_dgram,
             udp_vis_cap,
             ip_vis_cap,
+            reassembly_buf,
         )
     };};
 }
 
 pub struct UDPMuxComponent<A: Alarm<'static> + 'static, M: MacDevice<'static> + 'static> {
     mux_mac: &'static capsules_extra::ieee802154::virtual_mac::MuxMac<'static, M>,
-    ctx_pfix_len: u8,
-    ctx_pfix: [u8; 16],
+    ctx_store: &'static sixlowpan_compression::ContextTable,
+    resolver: Option<&'static dyn NeighborResolver>,
     dst_mac_addr: MacAddress,
     src_mac_addr: MacAddress,
     interface_list: &'static [IPAddr],
@@ -167,8 +170,8 @@ pub struct UDPMuxComponent<A: Alarm<'static> + 'static, M: MacDevice<'static> +
 impl<A: Alarm<'static> + 'static, M: MacDevice<'static>> UDPMuxComponent<A, M> {
     pub fn new(
         mux_mac: &'static capsules_extra::ieee802154::virtual_mac::MuxMac<'static, M>,
-        ctx_pfix_len: u8,
-        ctx_pfix: [u8; 16],
+        ctx_store: &'static sixlowpan_compression::ContextTable,
+        resolver: Option<&'static dyn NeighborResolver>,
         dst_mac_addr: MacAddress,
         src_mac_addr: MacAddress,
         interface_list: &'static [IPAddr],
@@ -176,8 +179,8 @@ impl<A: Alarm<'static> + 'static, M: MacDevice<'static>> UDPMuxComponent<A, M> {
     ) -> Self {
         Self {
             mux_mac,
-            ctx_pfix_len,
-            ctx_pfix,
+            ctx_store,
+            resolver,
             dst_mac_addr,
             src_mac_addr,
             interface_list,
@@ -194,7 +197,7 @@ impl<A: Alarm<'static> + 'static, M: MacDevice<'static>> Component for UDPMuxCom
             sixlowpan_state::Sixlowpan<
                 'static,
                 VirtualMuxAlarm<'static, A>,
-                sixlowpan_compression::Context,
+                &'static sixlowpan_compression::ContextTable,
             >,
         >,
         &'static mut MaybeUninit<sixlowpan_state::RxState<'static>>,
@@ -223,6 +226,7 @@ impl<A: Alarm<'static> + 'static, M: MacDevice<'static>> Component for UDPMuxCom
         &'static mut MaybeUninit<[u8; MAX_PAYLOAD_LEN]>,
         &'static mut MaybeUninit<UdpVisibilityCapability>,
         &'static mut MaybeUninit<IpVisibilityCapability>,
+        &'static mut MaybeUninit<[u8; 1280]>,
     );
     type Output = (
         &'static MuxUdpSender<'static, IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
@@ -244,12 +248,7 @@ impl<A: Alarm<'static> + 'static, M: MacDevice<'static>> Component for UDPMuxCom
         let ip_vis = s.15.write(IpVisibilityCapability::new(&create_cap));
 
         let sixlowpan = s.2.write(sixlowpan_state::Sixlowpan::new(
-            sixlowpan_compression::Context {
-                prefix: self.ctx_pfix,
-                prefix_len: self.ctx_pfix_len,
-                id: 0,
-                compress: false,
-            },
+            self.ctx_store,
             ipsender_virtual_alarm, // OK to reuse bc only used to get time, not set alarms
         ));
 
@@ -276,8 +275,9 @@ impl<A: Alarm<'static> + 'static, M: MacDevice<'static>> Component for UDPMuxCom
         // must send to the same mac address...this works fine under the
         // assumption of all packets being routed via a single gateway router,
         // but doesn't work if multiple senders want to send to different
-        // addresses on a local network. This will be fixed once we have an
-        // ipv6_nd cache mapping IP addresses to dst macs
+        // addresses on a local network. Boards running Neighbor Discovery
+        // pass it as the resolver, which picks the next hop of each packet
+        // from its neighbor cache instead.
         let ip_send =
             s.4.write(capsules_extra::net::ipv6::ipv6_send::IP6SendStruct::new(
                 ip6_dg,
@@ -296,11 +296,18 @@ impl<A: Alarm<'static> + 'static, M: MacDevice<'static>> Component for UDPMuxCom
         // Notably, the src addr is the same regardless of if messages are sent
         // from userland or capsules.
         ip_send.set_addr(self.interface_list[0]);
+        if let Some(resolver) = self.resolver {
+            ip_send.set_resolver(resolver);
+        }
         udp_mac.set_transmit_client(ip_send);
 
         let ip_receive =
             s.9.write(capsules_extra::net::ipv6::ipv6_recv::IP6RecvStruct::new());
         sixlowpan_state.set_rx_client(ip_receive);
+        // UDP is the transport most likely to send packets larger than the
+        // MTU, so this stack reassembles IPv6 fragments.
+        let reassembly_buf = s.16.write([0; 1280]);
+        ip_receive.set_reassembly_buffer(reassembly_buf);
         let udp_recv_mux = s.6.write(MuxUdpReceiver::new());
         ip_receive.set_client(udp_recv_mux);
 
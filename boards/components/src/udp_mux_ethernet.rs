@@ -0,0 +1,204 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Component to initialize the UDP/IPv6 stack over an Ethernet adapter.
+//!
+//! This provides one Component, UDPMuxEthernetComponent. Like
+//! `UDPMuxComponent`, it exposes a MuxUdpSender, a MuxUdpReceiver and a
+//! UdpPortManager, which can be passed to the `UDPDriverComponent`, but sends
+//! IPv6 packets over any `hil::ethernet::EthernetAdapter` instead of 6LoWPAN.
+//! The IPv6 interface is returned as well, so that a default router can be
+//! configured.
+//!
+//! Usage
+//! -----
+//! ```rust
+//!    let (udp_send_mux, udp_recv_mux, udp_port_table, ip6_eth) =
+//!        UDPMuxEthernetComponent::new(
+//!            ethernet_adapter,
+//!            mac_addr,
+//!            local_ip_ifaces,
+//!            mux_alarm,
+//!        )
+//!        .finalize(components::udp_mux_ethernet_component_static!(
+//!            qemu_rv32_virt_chip::chip::QemuRv32VirtClint
+//!        ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::net::ipv6::ip_utils::IPAddr;
+use capsules_extra::net::ipv6::ipv6_ethernet::{IP6EthernetStruct, NDP_FRAME_LEN};
+use capsules_extra::net::ipv6::ipv6_recv::IP6Receiver;
+use capsules_extra::net::ipv6::ipv6_send::IP6Sender;
+use capsules_extra::net::ipv6::{IP6Packet, IPPayload, TransportHeader, UDP_HDR_LEN};
+use capsules_extra::net::network_capabilities::{IpVisibilityCapability, UdpVisibilityCapability};
+use capsules_extra::net::udp::udp_port_table::{
+    SocketBindingEntry, UdpPortManager, MAX_NUM_BOUND_PORTS,
+};
+use capsules_extra::net::udp::udp_recv::MuxUdpReceiver;
+use capsules_extra::net::udp::udp_send::MuxUdpSender;
+use capsules_extra::net::udp::UDPHeader;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::ethernet::{EthernetAdapter, ETHERNET_HDR_LEN, MAC_ADDR_LEN};
+use kernel::hil::time::Alarm;
+
+use crate::udp_mux::MAX_PAYLOAD_LEN;
+
+/// Size of the frame buffer holding outgoing packets: an Ethernet header and
+/// a UDP packet with the largest payload the stack accepts.
+pub const DATA_FRAME_LEN: usize = ETHERNET_HDR_LEN + 40 + UDP_HDR_LEN + MAX_PAYLOAD_LEN;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! udp_mux_ethernet_component_static {
+    ($A:ty $(,)?) => {{
+        use capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm;
+        use capsules_extra::net::ipv6::ipv6_ethernet::{IP6EthernetStruct, NDP_FRAME_LEN};
+        use capsules_extra::net::udp::udp_send::MuxUdpSender;
+        use components::udp_mux::MAX_PAYLOAD_LEN;
+        use components::udp_mux_ethernet::DATA_FRAME_LEN;
+
+        let alarm = kernel::static_buf!(VirtualMuxAlarm<'static, $A>);
+        let ip6_eth = kernel::static_buf!(IP6EthernetStruct<'static, VirtualMuxAlarm<'static, $A>>);
+        let mux_udp_send = kernel::static_buf!(
+            MuxUdpSender<'static, IP6EthernetStruct<'static, VirtualMuxAlarm<'static, $A>>>
+        );
+        let mux_udp_recv =
+            kernel::static_buf!(capsules_extra::net::udp::udp_recv::MuxUdpReceiver<'static>);
+        let udp_port_manager =
+            kernel::static_buf!(capsules_extra::net::udp::udp_port_table::UdpPortManager);
+
+        let ip6_packet = kernel::static_buf!(capsules_extra::net::ipv6::IP6Packet<'static>);
+
+        // See `udp_mux_component_static` for the use of this table.
+        let used_ports = kernel::static_buf!(
+            [Option<capsules_extra::net::udp::udp_port_table::SocketBindingEntry>;
+                capsules_extra::net::udp::udp_port_table::MAX_NUM_BOUND_PORTS]
+        );
+
+        let udp_dgram = kernel::static_buf!([u8; MAX_PAYLOAD_LEN]);
+        let data_frame = kernel::static_buf!([u8; DATA_FRAME_LEN]);
+        let ctrl_frame = kernel::static_buf!([u8; NDP_FRAME_LEN]);
+
+        let udp_vis_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::UdpVisibilityCapability);
+        let ip_vis_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::IpVisibilityCapability);
+
+        (
+            alarm,
+            ip6_eth,
+            mux_udp_send,
+            mux_udp_recv,
+            udp_port_manager,
+            ip6_packet,
+            used_ports,
+            udp_dgram,
+            data_frame,
+            ctrl_frame,
+            udp_vis_cap,
+            ip_vis_cap,
+        )
+    };};
+}
+
+pub struct UDPMuxEthernetComponent<A: Alarm<'static> + 'static> {
+    adapter: &'static dyn EthernetAdapter<'static>,
+    mac_addr: [u8; MAC_ADDR_LEN],
+    interface_list: &'static [IPAddr],
+    alarm_mux: &'static MuxAlarm<'static, A>,
+}
+
+impl<A: Alarm<'static> + 'static> UDPMuxEthernetComponent<A> {
+    pub fn new(
+        adapter: &'static dyn EthernetAdapter<'static>,
+        mac_addr: [u8; MAC_ADDR_LEN],
+        interface_list: &'static [IPAddr],
+        alarm_mux: &'static MuxAlarm<'static, A>,
+    ) -> Self {
+        Self {
+            adapter,
+            mac_addr,
+            interface_list,
+            alarm_mux,
+        }
+    }
+}
+
+impl<A: Alarm<'static> + 'static> Component for UDPMuxEthernetComponent<A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<IP6EthernetStruct<'static, VirtualMuxAlarm<'static, A>>>,
+        &'static mut MaybeUninit<
+            MuxUdpSender<'static, IP6EthernetStruct<'static, VirtualMuxAlarm<'static, A>>>,
+        >,
+        &'static mut MaybeUninit<MuxUdpReceiver<'static>>,
+        &'static mut MaybeUninit<UdpPortManager>,
+        &'static mut MaybeUninit<IP6Packet<'static>>,
+        &'static mut MaybeUninit<[Option<SocketBindingEntry>; MAX_NUM_BOUND_PORTS]>,
+        &'static mut MaybeUninit<[u8; MAX_PAYLOAD_LEN]>,
+        &'static mut MaybeUninit<[u8; DATA_FRAME_LEN]>,
+        &'static mut MaybeUninit<[u8; NDP_FRAME_LEN]>,
+        &'static mut MaybeUninit<UdpVisibilityCapability>,
+        &'static mut MaybeUninit<IpVisibilityCapability>,
+    );
+    type Output = (
+        &'static MuxUdpSender<'static, IP6EthernetStruct<'static, VirtualMuxAlarm<'static, A>>>,
+        &'static MuxUdpReceiver<'static>,
+        &'static UdpPortManager,
+        &'static IP6EthernetStruct<'static, VirtualMuxAlarm<'static, A>>,
+    );
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let ip6_eth_alarm = s.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        ip6_eth_alarm.setup();
+
+        let create_cap = create_capability!(capabilities::NetworkCapabilityCreationCapability);
+        let udp_vis = s.10.write(UdpVisibilityCapability::new(&create_cap));
+        let ip_vis = s.11.write(IpVisibilityCapability::new(&create_cap));
+
+        let udp_dgram_buffer = s.7.write([0; MAX_PAYLOAD_LEN]);
+        let ip_pyld: IPPayload = IPPayload {
+            header: TransportHeader::UDP(UDPHeader::new()),
+            payload: udp_dgram_buffer,
+        };
+        let ip6_dg = s.5.write(IP6Packet::new(ip_pyld));
+
+        // The first address of the interface list is used as the source
+        // address, as with `UDPMuxComponent`.
+        let ip6_eth = s.1.write(IP6EthernetStruct::new(
+            self.adapter,
+            ip6_eth_alarm,
+            self.mac_addr,
+            self.interface_list,
+            ip6_dg,
+            s.8.write([0; DATA_FRAME_LEN]),
+            s.9.write([0; NDP_FRAME_LEN]),
+            ip_vis,
+        ));
+        ip6_eth_alarm.set_alarm_client(ip6_eth);
+        self.adapter.set_client(ip6_eth);
+
+        let udp_recv_mux = s.3.write(MuxUdpReceiver::new());
+        IP6Receiver::set_client(ip6_eth, udp_recv_mux);
+
+        let udp_send_mux = s.2.write(MuxUdpSender::new(ip6_eth));
+        IP6Sender::set_client(ip6_eth, udp_send_mux);
+
+        let kernel_ports = s.6.write([None; MAX_NUM_BOUND_PORTS]);
+        let create_table_cap = create_capability!(capabilities::CreatePortTableCapability);
+        let udp_port_table = s.4.write(UdpPortManager::new(
+            &create_table_cap,
+            kernel_ports,
+            udp_vis,
+        ));
+
+        self.adapter.enable_receive();
+
+        (udp_send_mux, udp_recv_mux, udp_port_table, ip6_eth)
+    }
+}
@@ -0,0 +1,150 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Components for composite USB devices.
+//!
+//! This provides two components. `UsbCompositeComponent` shares a USB
+//! controller between several USB classes, and
+//! `UsbCompositeFunctionComponent` provides the virtual controller for one of
+//! the classes. The class components take the function as their controller.
+//! Functions get their interfaces in the order they are created.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let usb_composite = components::usb_composite::UsbCompositeComponent::new(
+//!     &nrf52840_peripherals.usbd,
+//!     capsules_extra::usb::cdc::MAX_CTRL_PACKET_SIZE_NRF52840,
+//!     0x1915,
+//!     0x503a,
+//!     STRINGS,
+//! )
+//! .finalize(components::usb_composite_component_static!(nrf52840::usbd::Usbd));
+//!
+//! let cdc_function =
+//!     components::usb_composite::UsbCompositeFunctionComponent::new(usb_composite)
+//!         .finalize(components::usb_composite_function_component_static!(
+//!             nrf52840::usbd::Usbd
+//!         ));
+//! let cdc = components::cdc::CdcAcmComponent::new(
+//!     cdc_function,
+//!     capsules_extra::usb::cdc::MAX_CTRL_PACKET_SIZE_NRF52840,
+//!     0x1915,
+//!     0x503a,
+//!     STRINGS,
+//!     mux_alarm,
+//!     None,
+//! )
+//! .finalize(components::cdc_acm_component_static!(
+//!     capsules_extra::usb::composite::UsbCompositeFunction<'static, nrf52840::usbd::Usbd>,
+//!     nrf52840::rtc::Rtc
+//! ));
+//!
+//! let ctap_function =
+//!     components::usb_composite::UsbCompositeFunctionComponent::new(usb_composite)
+//!         .finalize(components::usb_composite_function_component_static!(
+//!             nrf52840::usbd::Usbd
+//!         ));
+//! let (ctap, ctap_driver) = components::ctap::CtapComponent::new(
+//!     board_kernel,
+//!     CTAP_DRIVER_NUM,
+//!     ctap_function,
+//!     0x1915,
+//!     0x503a,
+//!     STRINGS,
+//! )
+//! .finalize(components::ctap_component_static!(
+//!     capsules_extra::usb::composite::UsbCompositeFunction<'static, nrf52840::usbd::Usbd>
+//! ));
+//!
+//! usb_composite.enable();
+//! usb_composite.attach();
+//! ```
+
+use core::mem::MaybeUninit;
+
+use capsules_extra::usb::composite::{UsbComposite, UsbCompositeFunction};
+use kernel::component::Component;
+use kernel::hil;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! usb_composite_component_static {
+    ($U:ty $(,)?) => {{
+        kernel::static_buf!(capsules_extra::usb::composite::UsbComposite<'static, $U>)
+    };};
+}
+
+#[macro_export]
+macro_rules! usb_composite_function_component_static {
+    ($U:ty $(,)?) => {{
+        kernel::static_buf!(capsules_extra::usb::composite::UsbCompositeFunction<'static, $U>)
+    };};
+}
+
+pub struct UsbCompositeComponent<U: 'static + hil::usb::UsbController<'static>> {
+    usb: &'static U,
+    max_ctrl_packet_size: u8,
+    vendor_id: u16,
+    product_id: u16,
+    strings: &'static [&'static str; 3],
+}
+
+impl<U: 'static + hil::usb::UsbController<'static>> UsbCompositeComponent<U> {
+    pub fn new(
+        usb: &'static U,
+        max_ctrl_packet_size: u8,
+        vendor_id: u16,
+        product_id: u16,
+        strings: &'static [&'static str; 3],
+    ) -> Self {
+        Self {
+            usb,
+            max_ctrl_packet_size,
+            vendor_id,
+            product_id,
+            strings,
+        }
+    }
+}
+
+impl<U: 'static + hil::usb::UsbController<'static>> Component for UsbCompositeComponent<U> {
+    type StaticInput = &'static mut MaybeUninit<UsbComposite<'static, U>>;
+    type Output = &'static UsbComposite<'static, U>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let usb_composite = s.write(UsbComposite::new(
+            self.usb,
+            self.max_ctrl_packet_size,
+            self.vendor_id,
+            self.product_id,
+            self.strings,
+        ));
+        self.usb.set_client(usb_composite);
+
+        usb_composite
+    }
+}
+
+pub struct UsbCompositeFunctionComponent<U: 'static + hil::usb::UsbController<'static>> {
+    usb_composite: &'static UsbComposite<'static, U>,
+}
+
+impl<U: 'static + hil::usb::UsbController<'static>> UsbCompositeFunctionComponent<U> {
+    pub fn new(usb_composite: &'static UsbComposite<'static, U>) -> Self {
+        Self { usb_composite }
+    }
+}
+
+impl<U: 'static + hil::usb::UsbController<'static>> Component for UsbCompositeFunctionComponent<U> {
+    type StaticInput = &'static mut MaybeUninit<UsbCompositeFunction<'static, U>>;
+    type Output = &'static UsbCompositeFunction<'static, U>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let function = s.write(UsbCompositeFunction::new(self.usb_composite));
+        function.setup();
+
+        function
+    }
+}
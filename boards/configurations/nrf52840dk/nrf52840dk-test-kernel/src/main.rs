@@ -96,6 +96,18 @@ impl TestLauncher {
             3 => unsafe { test::aes_test::run_aes128_ctr(&self.peripherals.ecb, self) },
             4 => unsafe { test::aes_test::run_aes128_cbc(&self.peripherals.ecb, self) },
             5 => unsafe { test::aes_test::run_aes128_ecb(&self.peripherals.ecb, self) },
+            6 => unsafe { test::ecdsa_p256_test::run_ecdsa_p256_verify(self) },
+            7 => unsafe { test::ecdsa_p256_test::run_ecdsa_p256_verify_invalid(self) },
+            8 => unsafe { test::aes128_test::run_aes128_fips197(self) },
+            9 => unsafe { test::sha512_test::run_sha384_one_block(self) },
+            10 => unsafe { test::sha512_test::run_sha384_two_block(self) },
+            11 => unsafe { test::sha512_test::run_sha512_one_block(self) },
+            12 => unsafe { test::sha512_test::run_sha512_two_block(self) },
+            13 => unsafe { test::hmac_sha512_test::run_hmacsha384_case1(self) },
+            14 => unsafe { test::hmac_sha512_test::run_hmacsha384_case2(self) },
+            15 => unsafe { test::hmac_sha512_test::run_hmacsha512_case1(self) },
+            16 => unsafe { test::hmac_sha512_test::run_hmacsha512_case2(self) },
+            17 => unsafe { test::aes128_test::run_aes128_sp800_38a(self) },
             _ => kernel::debug!("All tests finished."),
         }
     }
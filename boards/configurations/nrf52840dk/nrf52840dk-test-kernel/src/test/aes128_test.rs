@@ -0,0 +1,64 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! This tests the software AES-128 implementation with the examples of
+//! FIPS-197 (ECB) and NIST SP 800-38A (CBC and CTR).
+//!
+//! The expected output is
+//! Aes128Fips197Test: all examples passed
+//! Aes128Sp80038aTest: all examples passed
+
+use capsules_core::test::capsule_test::{CapsuleTest, CapsuleTestClient};
+use capsules_extra::aes128::Aes128Software;
+use capsules_extra::test::aes128::{TestAes128Fips197, TestAes128Sp80038a, SP800_38A_LEN};
+use kernel::hil::symmetric_encryption::{AES128, AES128_BLOCK_SIZE};
+use kernel::static_init;
+
+pub unsafe fn run_aes128_fips197(client: &'static dyn CapsuleTestClient) {
+    let aes = static_init!(Aes128Software<'static>, Aes128Software::new());
+    kernel::deferred_call::DeferredCallClient::register(aes);
+
+    let t = static_init_test_aes128_fips197(aes, client);
+    aes.set_client(t);
+
+    t.run();
+}
+
+pub unsafe fn run_aes128_sp800_38a(client: &'static dyn CapsuleTestClient) {
+    let aes = static_init!(Aes128Software<'static>, Aes128Software::new());
+    kernel::deferred_call::DeferredCallClient::register(aes);
+
+    let t = static_init_test_aes128_sp800_38a(aes, client);
+    aes.set_client(t);
+
+    t.run();
+}
+
+unsafe fn static_init_test_aes128_fips197(
+    aes: &'static Aes128Software<'static>,
+    client: &'static dyn CapsuleTestClient,
+) -> &'static TestAes128Fips197<'static, Aes128Software<'static>> {
+    let data = static_init!([u8; AES128_BLOCK_SIZE], [0; AES128_BLOCK_SIZE]);
+
+    let test = static_init!(
+        TestAes128Fips197<'static, Aes128Software<'static>>,
+        TestAes128Fips197::new(aes, data)
+    );
+    test.set_client(client);
+    test
+}
+
+unsafe fn static_init_test_aes128_sp800_38a(
+    aes: &'static Aes128Software<'static>,
+    client: &'static dyn CapsuleTestClient,
+) -> &'static TestAes128Sp80038a<'static, Aes128Software<'static>> {
+    let data = static_init!([u8; SP800_38A_LEN], [0; SP800_38A_LEN]);
+
+    let test = static_init!(
+        TestAes128Sp80038a<'static, Aes128Software<'static>>,
+        TestAes128Sp80038a::new(aes, data)
+    );
+    test.set_client(client);
+    test
+}
@@ -0,0 +1,75 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! This tests the software ECDSA P-256 signature verifier.
+//!
+//! It verifies the P-256/SHA-256 signature of the message "sample" from
+//! RFC 6979 appendix A.2.5, and checks that the same signature is rejected
+//! for a different hash.
+//!
+//! The expected output is
+//! EcdsaP256Test: Verification result: Ok(true)
+//! EcdsaP256Test: Verification result: Ok(false)
+
+use capsules_core::test::capsule_test::{CapsuleTest, CapsuleTestClient};
+use capsules_extra::ecdsa_p256::EcdsaP256SignatureVerifier;
+use capsules_extra::test::ecdsa_p256::TestEcdsaP256Verify;
+use kernel::static_init;
+
+pub unsafe fn run_ecdsa_p256_verify(client: &'static dyn CapsuleTestClient) {
+    let t = static_init_test_ecdsa_p256(HASH, true, client);
+    t.run();
+}
+
+pub unsafe fn run_ecdsa_p256_verify_invalid(client: &'static dyn CapsuleTestClient) {
+    let mut hash = HASH;
+    hash[31] ^= 0x01;
+    let t = static_init_test_ecdsa_p256(hash, false, client);
+    t.run();
+}
+
+// The public key `Ux || Uy` of RFC 6979 appendix A.2.5.
+const PUBLIC_KEY: [u8; 64] = [
+    0x60, 0xfe, 0xd4, 0xba, 0x25, 0x5a, 0x9d, 0x31, 0xc9, 0x61, 0xeb, 0x74, 0xc6, 0x35, 0x6d, 0x68,
+    0xc0, 0x49, 0xb8, 0x92, 0x3b, 0x61, 0xfa, 0x6c, 0xe6, 0x69, 0x62, 0x2e, 0x60, 0xf2, 0x9f, 0xb6,
+    0x79, 0x03, 0xfe, 0x10, 0x08, 0xb8, 0xbc, 0x99, 0xa4, 0x1a, 0xe9, 0xe9, 0x56, 0x28, 0xbc, 0x64,
+    0xf2, 0xf1, 0xb2, 0x0c, 0x2d, 0x7e, 0x9f, 0x51, 0x77, 0xa3, 0xc2, 0x94, 0xd4, 0x46, 0x22, 0x99,
+];
+
+// SHA-256("sample")
+const HASH: [u8; 32] = [
+    0xaf, 0x2b, 0xdb, 0xe1, 0xaa, 0x9b, 0x6e, 0xc1, 0xe2, 0xad, 0xe1, 0xd6, 0x94, 0xf4, 0x1f, 0xc7,
+    0x1a, 0x83, 0x1d, 0x02, 0x68, 0xe9, 0x89, 0x15, 0x62, 0x11, 0x3d, 0x8a, 0x62, 0xad, 0xd1, 0xbf,
+];
+
+// The signature `r || s` of "sample" with SHA-256.
+const SIGNATURE: [u8; 64] = [
+    0xef, 0xd4, 0x8b, 0x2a, 0xac, 0xb6, 0xa8, 0xfd, 0x11, 0x40, 0xdd, 0x9c, 0xd4, 0x5e, 0x81, 0xd6,
+    0x9d, 0x2c, 0x87, 0x7b, 0x56, 0xaa, 0xf9, 0x91, 0xc3, 0x4d, 0x0e, 0xa8, 0x4e, 0xaf, 0x37, 0x16,
+    0xf7, 0xcb, 0x1c, 0x94, 0x2d, 0x65, 0x7c, 0x41, 0xd4, 0x36, 0xc7, 0xa1, 0xb6, 0xe2, 0x9f, 0x65,
+    0xf3, 0xe9, 0x00, 0xdb, 0xb9, 0xaf, 0xf4, 0x06, 0x4d, 0xc4, 0xab, 0x2f, 0x84, 0x3a, 0xcd, 0xa8,
+];
+
+unsafe fn static_init_test_ecdsa_p256(
+    hash: [u8; 32],
+    correct: bool,
+    client: &'static dyn CapsuleTestClient,
+) -> &'static TestEcdsaP256Verify {
+    let verifier = static_init!(
+        EcdsaP256SignatureVerifier<'static>,
+        EcdsaP256SignatureVerifier::new(&PUBLIC_KEY)
+    );
+    kernel::deferred_call::DeferredCallClient::register(verifier);
+
+    let hash = static_init!([u8; 32], hash);
+    let signature = static_init!([u8; 64], SIGNATURE);
+
+    let test = static_init!(
+        TestEcdsaP256Verify,
+        TestEcdsaP256Verify::new(verifier, hash, signature, correct)
+    );
+    test.set_client(client);
+
+    test
+}
@@ -0,0 +1,151 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! This tests the software HMAC-SHA384 and HMAC-SHA512 implementation with
+//! test cases 1 and 2 of RFC 4231.
+
+use capsules_core::test::capsule_test::{CapsuleTest, CapsuleTestClient};
+use capsules_extra::hmac_sha512::HmacSha512Software;
+use capsules_extra::sha512::{Sha512Software, SHA_384_OUTPUT_LEN_BYTES, SHA_512_OUTPUT_LEN_BYTES};
+use capsules_extra::test::hmac_sha512::TestHmacSha512;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::static_init;
+
+pub unsafe fn run_hmacsha384_case1(client: &'static dyn CapsuleTestClient) {
+    let data = static_init!([u8; 8], CASE1_DATA);
+    let t = static_init_test_hmacsha384(&CASE1_KEY, data, &CASE1_HMAC_SHA384, client);
+    t.run();
+}
+
+pub unsafe fn run_hmacsha384_case2(client: &'static dyn CapsuleTestClient) {
+    let data = static_init!([u8; 28], CASE2_DATA);
+    let t = static_init_test_hmacsha384(&CASE2_KEY, data, &CASE2_HMAC_SHA384, client);
+    t.run();
+}
+
+pub unsafe fn run_hmacsha512_case1(client: &'static dyn CapsuleTestClient) {
+    let data = static_init!([u8; 8], CASE1_DATA);
+    let t = static_init_test_hmacsha512(&CASE1_KEY, data, &CASE1_HMAC_SHA512, client);
+    t.run();
+}
+
+pub unsafe fn run_hmacsha512_case2(client: &'static dyn CapsuleTestClient) {
+    let data = static_init!([u8; 28], CASE2_DATA);
+    let t = static_init_test_hmacsha512(&CASE2_KEY, data, &CASE2_HMAC_SHA512, client);
+    t.run();
+}
+
+// Test case 1 from RFC 4231 section 4.2.
+const CASE1_KEY: [u8; 20] = [0x0b; 20];
+const CASE1_DATA: [u8; 8] = *b"Hi There";
+const CASE1_HMAC_SHA384: [u8; SHA_384_OUTPUT_LEN_BYTES] = [
+    0xaf, 0xd0, 0x39, 0x44, 0xd8, 0x48, 0x95, 0x62, 0x6b, 0x08, 0x25, 0xf4, 0xab, 0x46, 0x90, 0x7f,
+    0x15, 0xf9, 0xda, 0xdb, 0xe4, 0x10, 0x1e, 0xc6, 0x82, 0xaa, 0x03, 0x4c, 0x7c, 0xeb, 0xc5, 0x9c,
+    0xfa, 0xea, 0x9e, 0xa9, 0x07, 0x6e, 0xde, 0x7f, 0x4a, 0xf1, 0x52, 0xe8, 0xb2, 0xfa, 0x9c, 0xb6,
+];
+const CASE1_HMAC_SHA512: [u8; SHA_512_OUTPUT_LEN_BYTES] = [
+    0x87, 0xaa, 0x7c, 0xde, 0xa5, 0xef, 0x61, 0x9d, 0x4f, 0xf0, 0xb4, 0x24, 0x1a, 0x1d, 0x6c, 0xb0,
+    0x23, 0x79, 0xf4, 0xe2, 0xce, 0x4e, 0xc2, 0x78, 0x7a, 0xd0, 0xb3, 0x05, 0x45, 0xe1, 0x7c, 0xde,
+    0xda, 0xa8, 0x33, 0xb7, 0xd6, 0xb8, 0xa7, 0x02, 0x03, 0x8b, 0x27, 0x4e, 0xae, 0xa3, 0xf4, 0xe4,
+    0xbe, 0x9d, 0x91, 0x4e, 0xeb, 0x61, 0xf1, 0x70, 0x2e, 0x69, 0x6c, 0x20, 0x3a, 0x12, 0x68, 0x54,
+];
+
+// Test case 2 from RFC 4231 section 4.3.
+const CASE2_KEY: [u8; 4] = *b"Jefe";
+const CASE2_DATA: [u8; 28] = *b"what do ya want for nothing?";
+const CASE2_HMAC_SHA384: [u8; SHA_384_OUTPUT_LEN_BYTES] = [
+    0xaf, 0x45, 0xd2, 0xe3, 0x76, 0x48, 0x40, 0x31, 0x61, 0x7f, 0x78, 0xd2, 0xb5, 0x8a, 0x6b, 0x1b,
+    0x9c, 0x7e, 0xf4, 0x64, 0xf5, 0xa0, 0x1b, 0x47, 0xe4, 0x2e, 0xc3, 0x73, 0x63, 0x22, 0x44, 0x5e,
+    0x8e, 0x22, 0x40, 0xca, 0x5e, 0x69, 0xe2, 0xc7, 0x8b, 0x32, 0x39, 0xec, 0xfa, 0xb2, 0x16, 0x49,
+];
+const CASE2_HMAC_SHA512: [u8; SHA_512_OUTPUT_LEN_BYTES] = [
+    0x16, 0x4b, 0x7a, 0x7b, 0xfc, 0xf8, 0x19, 0xe2, 0xe3, 0x95, 0xfb, 0xe7, 0x3b, 0x56, 0xe0, 0xa3,
+    0x87, 0xbd, 0x64, 0x22, 0x2e, 0x83, 0x1f, 0xd6, 0x10, 0x27, 0x0c, 0xd7, 0xea, 0x25, 0x05, 0x54,
+    0x97, 0x58, 0xbf, 0x75, 0xc0, 0x5a, 0x99, 0x4a, 0x6d, 0x03, 0x4f, 0x65, 0xf8, 0xf0, 0xe6, 0xfd,
+    0xca, 0xea, 0xb1, 0xa3, 0x4d, 0x4a, 0x6b, 0x4b, 0x63, 0x6e, 0x07, 0x0a, 0x38, 0xbc, 0xe7, 0x37,
+];
+
+unsafe fn static_init_test_hmacsha384(
+    key: &'static [u8],
+    data: &'static mut [u8],
+    correct: &'static [u8; SHA_384_OUTPUT_LEN_BYTES],
+    client: &'static dyn CapsuleTestClient,
+) -> &'static TestHmacSha512<SHA_384_OUTPUT_LEN_BYTES> {
+    let sha384 = static_init!(
+        Sha512Software<'static, SHA_384_OUTPUT_LEN_BYTES>,
+        Sha512Software::new()
+    );
+    sha384.register();
+
+    let hmacsha384_data_buf = static_init!([u8; 128], [0; 128]);
+    let hmacsha384_verify_buf = static_init!(
+        [u8; SHA_384_OUTPUT_LEN_BYTES],
+        [0; SHA_384_OUTPUT_LEN_BYTES]
+    );
+
+    let hmacsha384 = static_init!(
+        HmacSha512Software<
+            'static,
+            Sha512Software<'static, SHA_384_OUTPUT_LEN_BYTES>,
+            SHA_384_OUTPUT_LEN_BYTES,
+        >,
+        HmacSha512Software::new(sha384, hmacsha384_data_buf, hmacsha384_verify_buf)
+    );
+    kernel::hil::digest::Digest::set_client(sha384, hmacsha384);
+
+    let digest = static_init!(
+        [u8; SHA_384_OUTPUT_LEN_BYTES],
+        [0; SHA_384_OUTPUT_LEN_BYTES]
+    );
+
+    let test = static_init!(
+        TestHmacSha512<SHA_384_OUTPUT_LEN_BYTES>,
+        TestHmacSha512::new(hmacsha384, key, data, digest, correct)
+    );
+    test.set_client(client);
+
+    test
+}
+
+unsafe fn static_init_test_hmacsha512(
+    key: &'static [u8],
+    data: &'static mut [u8],
+    correct: &'static [u8; SHA_512_OUTPUT_LEN_BYTES],
+    client: &'static dyn CapsuleTestClient,
+) -> &'static TestHmacSha512<SHA_512_OUTPUT_LEN_BYTES> {
+    let sha512 = static_init!(
+        Sha512Software<'static, SHA_512_OUTPUT_LEN_BYTES>,
+        Sha512Software::new()
+    );
+    sha512.register();
+
+    let hmacsha512_data_buf = static_init!([u8; 128], [0; 128]);
+    let hmacsha512_verify_buf = static_init!(
+        [u8; SHA_512_OUTPUT_LEN_BYTES],
+        [0; SHA_512_OUTPUT_LEN_BYTES]
+    );
+
+    let hmacsha512 = static_init!(
+        HmacSha512Software<
+            'static,
+            Sha512Software<'static, SHA_512_OUTPUT_LEN_BYTES>,
+            SHA_512_OUTPUT_LEN_BYTES,
+        >,
+        HmacSha512Software::new(sha512, hmacsha512_data_buf, hmacsha512_verify_buf)
+    );
+    kernel::hil::digest::Digest::set_client(sha512, hmacsha512);
+
+    let digest = static_init!(
+        [u8; SHA_512_OUTPUT_LEN_BYTES],
+        [0; SHA_512_OUTPUT_LEN_BYTES]
+    );
+
+    let test = static_init!(
+        TestHmacSha512<SHA_512_OUTPUT_LEN_BYTES>,
+        TestHmacSha512::new(hmacsha512, key, data, digest, correct)
+    );
+    test.set_client(client);
+
+    test
+}
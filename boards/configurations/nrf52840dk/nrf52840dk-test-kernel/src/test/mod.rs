@@ -2,7 +2,11 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 // Copyright Tock Contributors 2023.
 
+pub(crate) mod aes128_test;
 pub(crate) mod aes_test;
+pub(crate) mod ecdsa_p256_test;
 pub(crate) mod hmac_sha256_test;
+pub(crate) mod hmac_sha512_test;
 pub(crate) mod sha256_test;
+pub(crate) mod sha512_test;
 pub(crate) mod siphash24_test;
@@ -0,0 +1,115 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! This tests the software SHA-384 and SHA-512 implementation.
+//!
+//! This test uses a deferred call (for callbacks). It hashes the one-block
+//! and the two-block messages of the NIST SHA-384 and SHA-512 examples and
+//! uses Digest::verify to check that the hashes are correct.
+//!
+//! The expected output is
+//! Sha512Test: Verification result: Ok(true)
+//!
+//! for each of the four hashes.
+
+use capsules_core::test::capsule_test::{CapsuleTest, CapsuleTestClient};
+use capsules_extra::sha512::{Sha512Software, SHA_384_OUTPUT_LEN_BYTES, SHA_512_OUTPUT_LEN_BYTES};
+use capsules_extra::test::sha512::TestSha512;
+use kernel::static_init;
+
+pub unsafe fn run_sha384_one_block(client: &'static dyn CapsuleTestClient) {
+    let t = static_init_test_sha384(static_init!([u8; 3], ONE_BLOCK), SHA384_ONE_BLOCK, client);
+    t.run();
+}
+
+pub unsafe fn run_sha384_two_block(client: &'static dyn CapsuleTestClient) {
+    let t = static_init_test_sha384(static_init!([u8; 112], TWO_BLOCK), SHA384_TWO_BLOCK, client);
+    t.run();
+}
+
+pub unsafe fn run_sha512_one_block(client: &'static dyn CapsuleTestClient) {
+    let t = static_init_test_sha512(static_init!([u8; 3], ONE_BLOCK), SHA512_ONE_BLOCK, client);
+    t.run();
+}
+
+pub unsafe fn run_sha512_two_block(client: &'static dyn CapsuleTestClient) {
+    let t = static_init_test_sha512(static_init!([u8; 112], TWO_BLOCK), SHA512_TWO_BLOCK, client);
+    t.run();
+}
+
+const ONE_BLOCK: [u8; 3] = *b"abc";
+const TWO_BLOCK: [u8; 112] = *b"abcdefghbcdefghicdefghijdefghijkefghijklfghijklmghijklmn\
+hijklmnoijklmnopjklmnopqklmnopqrlmnopqrsmnopqrstnopqrstu";
+
+const SHA384_ONE_BLOCK: [u8; SHA_384_OUTPUT_LEN_BYTES] = [
+    0xcb, 0x00, 0x75, 0x3f, 0x45, 0xa3, 0x5e, 0x8b, 0xb5, 0xa0, 0x3d, 0x69, 0x9a, 0xc6, 0x50, 0x07,
+    0x27, 0x2c, 0x32, 0xab, 0x0e, 0xde, 0xd1, 0x63, 0x1a, 0x8b, 0x60, 0x5a, 0x43, 0xff, 0x5b, 0xed,
+    0x80, 0x86, 0x07, 0x2b, 0xa1, 0xe7, 0xcc, 0x23, 0x58, 0xba, 0xec, 0xa1, 0x34, 0xc8, 0x25, 0xa7,
+];
+
+const SHA384_TWO_BLOCK: [u8; SHA_384_OUTPUT_LEN_BYTES] = [
+    0x09, 0x33, 0x0c, 0x33, 0xf7, 0x11, 0x47, 0xe8, 0x3d, 0x19, 0x2f, 0xc7, 0x82, 0xcd, 0x1b, 0x47,
+    0x53, 0x11, 0x1b, 0x17, 0x3b, 0x3b, 0x05, 0xd2, 0x2f, 0xa0, 0x80, 0x86, 0xe3, 0xb0, 0xf7, 0x12,
+    0xfc, 0xc7, 0xc7, 0x1a, 0x55, 0x7e, 0x2d, 0xb9, 0x66, 0xc3, 0xe9, 0xfa, 0x91, 0x74, 0x60, 0x39,
+];
+
+const SHA512_ONE_BLOCK: [u8; SHA_512_OUTPUT_LEN_BYTES] = [
+    0xdd, 0xaf, 0x35, 0xa1, 0x93, 0x61, 0x7a, 0xba, 0xcc, 0x41, 0x73, 0x49, 0xae, 0x20, 0x41, 0x31,
+    0x12, 0xe6, 0xfa, 0x4e, 0x89, 0xa9, 0x7e, 0xa2, 0x0a, 0x9e, 0xee, 0xe6, 0x4b, 0x55, 0xd3, 0x9a,
+    0x21, 0x92, 0x99, 0x2a, 0x27, 0x4f, 0xc1, 0xa8, 0x36, 0xba, 0x3c, 0x23, 0xa3, 0xfe, 0xeb, 0xbd,
+    0x45, 0x4d, 0x44, 0x23, 0x64, 0x3c, 0xe8, 0x0e, 0x2a, 0x9a, 0xc9, 0x4f, 0xa5, 0x4c, 0xa4, 0x9f,
+];
+
+const SHA512_TWO_BLOCK: [u8; SHA_512_OUTPUT_LEN_BYTES] = [
+    0x8e, 0x95, 0x9b, 0x75, 0xda, 0xe3, 0x13, 0xda, 0x8c, 0xf4, 0xf7, 0x28, 0x14, 0xfc, 0x14, 0x3f,
+    0x8f, 0x77, 0x79, 0xc6, 0xeb, 0x9f, 0x7f, 0xa1, 0x72, 0x99, 0xae, 0xad, 0xb6, 0x88, 0x90, 0x18,
+    0x50, 0x1d, 0x28, 0x9e, 0x49, 0x00, 0xf7, 0xe4, 0x33, 0x1b, 0x99, 0xde, 0xc4, 0xb5, 0x43, 0x3a,
+    0xc7, 0xd3, 0x29, 0xee, 0xb6, 0xdd, 0x26, 0x54, 0x5e, 0x96, 0xe5, 0x5b, 0x87, 0x4b, 0xe9, 0x09,
+];
+
+unsafe fn static_init_test_sha384(
+    data: &'static mut [u8],
+    hash: [u8; SHA_384_OUTPUT_LEN_BYTES],
+    client: &'static dyn CapsuleTestClient,
+) -> &'static TestSha512<SHA_384_OUTPUT_LEN_BYTES> {
+    let sha = static_init!(
+        Sha512Software<'static, SHA_384_OUTPUT_LEN_BYTES>,
+        Sha512Software::new()
+    );
+    kernel::deferred_call::DeferredCallClient::register(sha);
+
+    let hash = static_init!([u8; SHA_384_OUTPUT_LEN_BYTES], hash);
+
+    // We expect data to hash to hash, so final argument is true
+    let test = static_init!(
+        TestSha512<SHA_384_OUTPUT_LEN_BYTES>,
+        TestSha512::new(sha, data, hash, true)
+    );
+    test.set_client(client);
+
+    test
+}
+
+unsafe fn static_init_test_sha512(
+    data: &'static mut [u8],
+    hash: [u8; SHA_512_OUTPUT_LEN_BYTES],
+    client: &'static dyn CapsuleTestClient,
+) -> &'static TestSha512<SHA_512_OUTPUT_LEN_BYTES> {
+    let sha = static_init!(
+        Sha512Software<'static, SHA_512_OUTPUT_LEN_BYTES>,
+        Sha512Software::new()
+    );
+    kernel::deferred_call::DeferredCallClient::register(sha);
+
+    let hash = static_init!([u8; SHA_512_OUTPUT_LEN_BYTES], hash);
+
+    // We expect data to hash to hash, so final argument is true
+    let test = static_init!(
+        TestSha512<SHA_512_OUTPUT_LEN_BYTES>,
+        TestSha512::new(sha, data, hash, true)
+    );
+    test.set_client(client);
+
+    test
+}
@@ -663,10 +663,16 @@ unsafe fn start() -> (
         ]
     );
 
-    let (udp_send_mux, udp_recv_mux, udp_port_table) = components::udp_mux::UDPMuxComponent::new(
-        mux_mac,
+    let ctx_table = components::sixlowpan_context::ContextTableComponent::new(
         DEFAULT_CTX_PREFIX_LEN,
         DEFAULT_CTX_PREFIX,
+    )
+    .finalize(components::context_table_component_static!());
+
+    let (udp_send_mux, udp_recv_mux, udp_port_table) = components::udp_mux::UDPMuxComponent::new(
+        mux_mac,
+        ctx_table,
+        None,
         DST_MAC_ADDR,
         src_mac_from_serial_num, //comment out for dual rx test only
         //MacAddress::Short(49138), //comment in for dual rx test only
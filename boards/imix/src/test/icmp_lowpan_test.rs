@@ -37,6 +37,8 @@ use kernel::debug;
 use kernel::hil::radio;
 use kernel::hil::time::{self, Alarm, ConvertTicks};
 use kernel::static_init;
+use kernel::utilities::cells::MapCell;
+use kernel::utilities::leasable_buffer::SubSliceMut;
 
 pub const SRC_ADDR: IPAddr = IPAddr([
     0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
@@ -56,6 +58,7 @@ pub const TEST_DELAY_MS: u32 = 10000;
 pub const TEST_LOOP: bool = false;
 
 static mut ICMP_PAYLOAD: [u8; 10] = [0; 10];
+static mut ICMP_BUF: [u8; 10] = [0; 10];
 
 pub static mut RF233_BUF: [u8; radio::MAX_BUF_SIZE] = [0_u8; radio::MAX_BUF_SIZE];
 
@@ -65,6 +68,7 @@ pub struct LowpanICMPTest<'a, A: time::Alarm<'a>> {
     alarm: &'a A,
     test_counter: Cell<usize>,
     icmp_sender: &'a dyn ICMP6Sender<'a>,
+    icmp_buf: MapCell<SubSliceMut<'static, u8>>,
     net_cap: &'static NetworkCapability,
 }
 
@@ -167,6 +171,7 @@ pub unsafe fn run(
             //radio_mac,
             alarm,
             icmp_send_struct,
+            &mut *addr_of_mut!(ICMP_BUF),
             net_cap
         )
     );
@@ -181,7 +186,8 @@ pub unsafe fn run(
 impl<'a, A: time::Alarm<'a>> capsules_extra::net::icmpv6::icmpv6_send::ICMP6SendClient
     for LowpanICMPTest<'a, A>
 {
-    fn send_done(&self, result: Result<(), ErrorCode>) {
+    fn send_done(&self, result: Result<(), ErrorCode>, buf: SubSliceMut<'static, u8>) {
+        self.icmp_buf.replace(buf);
         match result {
             Ok(()) => {
                 debug!("ICMP Echo Request Packet Sent!");
@@ -199,12 +205,14 @@ impl<'a, A: time::Alarm<'a>> LowpanICMPTest<'a, A> {
     pub fn new(
         alarm: &'a A,
         icmp_sender: &'a dyn ICMP6Sender<'a>,
+        icmp_buf: &'static mut [u8],
         net_cap: &'static NetworkCapability,
     ) -> LowpanICMPTest<'a, A> {
         LowpanICMPTest {
             alarm,
             test_counter: Cell::new(0),
             icmp_sender,
+            icmp_buf: MapCell::new(SubSliceMut::new(icmp_buf)),
             net_cap,
         }
     }
@@ -253,14 +261,11 @@ impl<'a, A: time::Alarm<'a>> LowpanICMPTest<'a, A> {
 
     fn send_next(&self) {
         let icmp_hdr = ICMP6Header::new(ICMP6Type::Type128); // Echo Request
-        let _ = unsafe {
-            self.icmp_sender.send(
-                DST_ADDR,
-                icmp_hdr,
-                &mut *addr_of_mut!(ICMP_PAYLOAD),
-                self.net_cap,
-            )
-        };
+        if let Some(buf) = self.icmp_buf.take() {
+            if let Err((_, buf)) = self.icmp_sender.send(DST_ADDR, icmp_hdr, buf, self.net_cap) {
+                self.icmp_buf.replace(buf);
+            }
+        }
     }
 }
 
@@ -73,6 +73,7 @@
 use core::ptr::addr_of;
 
 use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::net::icmpv6::icmpv6_recv::ICMP6Receiver;
 use capsules_extra::net::ieee802154::MacAddress;
 use capsules_extra::net::ipv6::ip_utils::IPAddr;
 use kernel::component::Component;
@@ -148,6 +149,9 @@ static mut CHIP: Option<&'static nrf52840::chip::NRF52<Nrf52840DefaultPeripheral
 static mut PROCESS_PRINTER: Option<&'static capsules_system::process_printer::ProcessPrinterText> =
     None;
 
+/// Maximum length of a crash report, which must fit in one flash page.
+const CRASH_REPORT_LEN: usize = 4000;
+
 /// Dummy buffer that causes the linker to reserve enough space for the stack.
 #[no_mangle]
 #[link_section = ".stack_buffer"]
@@ -176,6 +180,9 @@ type TicKVKVStore = components::kv::TicKVKVStoreComponentType<
     capsules_extra::tickv::TicKVKeyType,
 >;
 type KVStorePermissions = components::kv::KVStorePermissionsComponentType<TicKVKVStore>;
+/// Multiplexer of the KV store, which kernel capsules share with the KV driver.
+pub type KVPermissionsMux =
+    capsules_extra::virtual_kv::MuxKVPermissions<'static, KVStorePermissions>;
 type VirtualKVPermissions = components::kv::VirtualKVPermissionsComponentType<KVStorePermissions>;
 type KVDriver = components::kv::KVDriverComponentType<VirtualKVPermissions>;
 
@@ -194,6 +201,14 @@ pub type Ieee802154Driver = components::ieee802154::Ieee802154ComponentType<
     nrf52840::aes::AesECB<'static>,
 >;
 
+// CoAP
+type HmacSha256Software = components::hmac::HmacSha256SoftwareComponentType<
+    capsules_extra::sha256::Sha256Software<'static>,
+>;
+/// Userspace CoAP driver.
+pub type CoapDriver =
+    components::coap::CoapComponentType<nrf52840::rtc::Rtc<'static>, HmacSha256Software>;
+
 // EUI64
 /// Userspace EUI64 driver.
 pub type Eui64Driver = components::eui64::Eui64ComponentType;
@@ -241,6 +256,8 @@ pub struct Platform {
         >,
     >,
     kv_driver: &'static KVDriver,
+    /// The KV store multiplexer, for kernel users of the KV store.
+    pub mux_kv: &'static KVPermissionsMux,
     scheduler: &'static RoundRobinSched<'static>,
     systick: cortexm4::systick::SysTick,
 }
@@ -302,15 +319,23 @@ impl KernelResources<Chip> for Platform {
     }
 }
 
-/// Create the capsules needed for the in-kernel UDP and 15.4 stack.
+/// Number of TCP connections userspace can hold open at the same time.
+const NUM_TCP_SOCKETS: usize = 2;
+
+/// Create the capsules needed for the in-kernel UDP, CoAP, TCP, ICMPv6 and
+/// 15.4 stack.
 pub unsafe fn ieee802154_udp(
     board_kernel: &'static kernel::Kernel,
     nrf52840_peripherals: &'static Nrf52840DefaultPeripherals<'static>,
     mux_alarm: &'static MuxAlarm<nrf52840::rtc::Rtc>,
+    mux_kv: &'static KVPermissionsMux,
 ) -> (
     &'static Eui64Driver,
     &'static Ieee802154Driver,
     &'static capsules_extra::net::udp::UDPDriver<'static>,
+    &'static CoapDriver,
+    &'static capsules_extra::net::tcp::TCPDriver<'static>,
+    &'static capsules_extra::net::icmpv6::driver::PingDriver<'static>,
 ) {
     //--------------------------------------------------------------------------
     // AES
@@ -364,10 +389,22 @@ pub unsafe fn ieee802154_udp(
         ]
     );
 
-    let (udp_send_mux, udp_recv_mux, udp_port_table) = components::udp_mux::UDPMuxComponent::new(
-        mux_mac,
+    // All stacks share the 6LoWPAN contexts, and use Neighbor Discovery to
+    // autoconfigure a global address and to find their next hops.
+    let ctx_table = components::sixlowpan_context::ContextTableComponent::new(
         DEFAULT_CTX_PREFIX_LEN,
         DEFAULT_CTX_PREFIX,
+    )
+    .finalize(components::context_table_component_static!());
+
+    let ndp =
+        components::ndp::NdpComponent::new(mux_alarm, ctx_table, eui64_driver, local_ip_ifaces)
+            .finalize(components::ndp_component_static!(nrf52840::rtc::Rtc));
+
+    let (udp_send_mux, udp_recv_mux, udp_port_table) = components::udp_mux::UDPMuxComponent::new(
+        mux_mac,
+        ctx_table,
+        Some(ndp),
         DST_MAC_ADDR,
         MacAddress::Long(device_id),
         local_ip_ifaces,
@@ -389,7 +426,105 @@ pub unsafe fn ieee802154_udp(
     )
     .finalize(components::udp_driver_component_static!(nrf52840::rtc::Rtc));
 
-    (eui64_driver, ieee802154_driver, udp_driver)
+    //--------------------------------------------------------------------------
+    // CoAP
+    //--------------------------------------------------------------------------
+
+    // OSCORE derives its keys with HMAC-SHA256, and keeps the security
+    // contexts of processes in the KV store.
+    let sha256_sw = components::sha::ShaSoftware256Component::new()
+        .finalize(components::sha_software_256_component_static!());
+
+    let hmac_sha256_sw = components::hmac::HmacSha256SoftwareComponent::new(sha256_sw).finalize(
+        components::hmac_sha256_software_component_static!(capsules_extra::sha256::Sha256Software),
+    );
+
+    let coap_kv = components::kv::VirtualKVPermissionsComponent::new(mux_kv).finalize(
+        components::virtual_kv_permissions_component_static!(KVStorePermissions),
+    );
+
+    let coap_driver = components::coap::CoapComponent::new(
+        board_kernel,
+        capsules_extra::net::coap::DRIVER_NUM,
+        udp_send_mux,
+        udp_recv_mux,
+        udp_port_table,
+        mux_alarm,
+        aes_mux,
+        hmac_sha256_sw,
+        coap_kv,
+    )
+    .finalize(components::coap_component_static!(
+        nrf52840::rtc::Rtc,
+        nrf52840::aes::AesECB<'static>,
+        HmacSha256Software,
+    ));
+
+    //--------------------------------------------------------------------------
+    // TCP
+    //--------------------------------------------------------------------------
+
+    let tcp_mux = components::tcp_mux::TCPMuxComponent::new(
+        mux_mac,
+        ctx_table,
+        Some(ndp),
+        DST_MAC_ADDR,
+        MacAddress::Long(device_id),
+        local_ip_ifaces,
+        mux_alarm,
+    )
+    .finalize(components::tcp_mux_component_static!(
+        nrf52840::rtc::Rtc,
+        Ieee802154MacDevice
+    ));
+
+    let tcp_driver = components::tcp_driver::TCPDriverComponent::new(
+        board_kernel,
+        capsules_extra::net::tcp::DRIVER_NUM,
+        tcp_mux,
+    )
+    .finalize(components::tcp_driver_component_static!(
+        nrf52840::rtc::Rtc,
+        NUM_TCP_SOCKETS
+    ));
+
+    //--------------------------------------------------------------------------
+    // ICMPv6
+    //--------------------------------------------------------------------------
+
+    let icmp6 = components::icmpv6::ICMP6Component::new(
+        mux_mac,
+        ctx_table,
+        Some(ndp),
+        DST_MAC_ADDR,
+        MacAddress::Long(device_id),
+        local_ip_ifaces,
+        mux_alarm,
+    )
+    .finalize(components::icmp6_component_static!(
+        nrf52840::rtc::Rtc,
+        Ieee802154MacDevice
+    ));
+    icmp6.set_error_client(udp_recv_mux);
+    icmp6.set_nd_client(ndp);
+    ndp.set_icmp(icmp6);
+    ndp.start();
+
+    let ping_driver = components::ping_driver::PingDriverComponent::new(
+        board_kernel,
+        capsules_extra::net::icmpv6::driver::DRIVER_NUM,
+        icmp6,
+    )
+    .finalize(components::ping_driver_component_static!());
+
+    (
+        eui64_driver,
+        ieee802154_driver,
+        udp_driver,
+        coap_driver,
+        tcp_driver,
+        ping_driver,
+    )
 }
 
 /// This is in a separate, inline(never) function so that its stack frame is
@@ -610,6 +745,26 @@ pub unsafe fn start() -> (
         nrf52840::rtc::Rtc<'static>
     ));
 
+    // Record system calls at runtime, controlled with the `trace` command.
+    let syscall_trace =
+        components::syscall_trace::SyscallTraceComponent::new(board_kernel, rtc).finalize(
+            components::syscall_trace_component_static!(nrf52840::rtc::Rtc<'static>, 64, 16),
+        );
+    pconsole.set_syscall_trace(syscall_trace);
+
+    // Save panic reports to flash, shown with the `crashlog` command.
+    kernel::storage_volume!(CRASH_LOG_VOLUME, 16);
+    let crash_log = components::crash_log::CrashLogComponent::new(
+        &CRASH_LOG_VOLUME,
+        &base_peripherals.nvmc,
+        process_printer,
+    )
+    .finalize(components::crash_log_component_static!(
+        nrf52840::nvmc::Nvmc,
+        CRASH_REPORT_LEN
+    ));
+    pconsole.set_crash_log(crash_log);
+
     // Setup the serial console for userspace.
     let console = components::console::ConsoleComponent::new(
         board_kernel,
@@ -887,6 +1042,7 @@ pub unsafe fn start() -> (
 
     let scheduler = components::sched::round_robin::RoundRobinComponent::new(&*addr_of!(PROCESSES))
         .finalize(components::round_robin_component_static!(NUM_PROCS));
+    pconsole.set_scheduler(scheduler);
 
     let platform = Platform {
         button,
@@ -908,6 +1064,7 @@ pub unsafe fn start() -> (
         i2c_master_slave,
         spi_controller,
         kv_driver,
+        mux_kv,
         scheduler,
         systick: cortexm4::systick::SysTick::new_with_calibration(64000000),
     };
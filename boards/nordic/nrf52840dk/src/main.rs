@@ -27,6 +27,9 @@ struct Platform {
     eui64_driver: &'static nrf52840dk_lib::Eui64Driver,
     ieee802154_driver: &'static nrf52840dk_lib::Ieee802154Driver,
     udp_driver: &'static capsules_extra::net::udp::UDPDriver<'static>,
+    coap_driver: &'static nrf52840dk_lib::CoapDriver,
+    tcp_driver: &'static capsules_extra::net::tcp::TCPDriver<'static>,
+    ping_driver: &'static capsules_extra::net::icmpv6::driver::PingDriver<'static>,
 }
 
 impl SyscallDriverLookup for Platform {
@@ -37,6 +40,9 @@ impl SyscallDriverLookup for Platform {
         match driver_num {
             capsules_extra::eui64::DRIVER_NUM => f(Some(self.eui64_driver)),
             capsules_extra::net::udp::DRIVER_NUM => f(Some(self.udp_driver)),
+            capsules_extra::net::coap::DRIVER_NUM => f(Some(self.coap_driver)),
+            capsules_extra::net::tcp::DRIVER_NUM => f(Some(self.tcp_driver)),
+            capsules_extra::net::icmpv6::driver::DRIVER_NUM => f(Some(self.ping_driver)),
             capsules_extra::ieee802154::DRIVER_NUM => f(Some(self.ieee802154_driver)),
             _ => self.base.with_driver(driver_num, f),
         }
@@ -85,17 +91,25 @@ pub unsafe fn main() {
         nrf52840dk_lib::start();
 
     //--------------------------------------------------------------------------
-    // IEEE 802.15.4 and UDP
+    // IEEE 802.15.4, UDP, CoAP, TCP and ICMPv6
     //--------------------------------------------------------------------------
 
-    let (eui64_driver, ieee802154_driver, udp_driver) =
-        nrf52840dk_lib::ieee802154_udp(board_kernel, default_peripherals, mux_alarm);
+    let (eui64_driver, ieee802154_driver, udp_driver, coap_driver, tcp_driver, ping_driver) =
+        nrf52840dk_lib::ieee802154_udp(
+            board_kernel,
+            default_peripherals,
+            mux_alarm,
+            base_platform.mux_kv,
+        );
 
     let platform = Platform {
         base: base_platform,
         eui64_driver,
         ieee802154_driver,
         udp_driver,
+        coap_driver,
+        tcp_driver,
+        ping_driver,
     };
 
     // These symbols are defined in the linker script.
@@ -0,0 +1,113 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Example applications loaded by the board.
+//!
+//! Each application is a function running on a thread of its own and making
+//! system calls through its [`App`]. They show how to write to the console,
+//! wait for an alarm with an upcall and keep a value in the KV store, which
+//! persists in the flash file across runs of the kernel.
+
+use core::cell::Cell;
+
+use posix_chip::app::{App, Buffer, Image};
+
+// Memory of each process, for its buffers and the grants of the kernel.
+const MINIMUM_RAM_SIZE: u32 = 8192;
+
+const CONSOLE_BUFFER_LEN: usize = 128;
+
+thread_local! {
+    /// Console buffer of the process running on this thread.
+    static CONSOLE_BUFFER: Cell<Option<Buffer>> = const { Cell::new(None) };
+}
+
+/// Returns the images of the applications, to load as the application flash.
+pub fn image() -> &'static [u8] {
+    Image::new()
+        .add("hello", hello, MINIMUM_RAM_SIZE)
+        .add("ticker", ticker, MINIMUM_RAM_SIZE)
+        .add("boot_count", boot_count, MINIMUM_RAM_SIZE)
+        .into_flash()
+}
+
+/// Writes `text` to the console and waits for the write to complete.
+fn print(app: &App, text: &str) {
+    let buffer = CONSOLE_BUFFER.with(|cell| {
+        // Allocations are never freed, so the buffer is only allocated once.
+        let buffer = cell.get().unwrap_or_else(|| app.alloc(CONSOLE_BUFFER_LEN));
+        cell.set(Some(buffer));
+        buffer
+    });
+    for chunk in text.as_bytes().chunks(buffer.len()) {
+        buffer.write(chunk);
+        app.allow_ro(capsules_core::console::DRIVER_NUM, 1, Some(&buffer));
+        app.command(capsules_core::console::DRIVER_NUM, 1, chunk.len(), 0);
+        app.yield_wait_for(capsules_core::console::DRIVER_NUM, 1);
+        app.allow_ro(capsules_core::console::DRIVER_NUM, 1, None);
+    }
+}
+
+fn hello(app: &App) {
+    let (start, len) = app.memory();
+    print(
+        app,
+        &format!(
+            "Hello from process {:?}, with {} bytes of memory at {:#x}\r\n",
+            std::thread::current().name().unwrap_or_default(),
+            len,
+            start
+        ),
+    );
+}
+
+/// Prints a tick every second, five times, from an alarm upcall.
+fn ticker(app: &App) {
+    fn tick(app: &App, _now: usize, _expiration: usize, _: usize, count: usize) {
+        print(app, &format!("tick {}\r\n", count));
+    }
+
+    for count in 1..=5 {
+        app.subscribe(capsules_core::alarm::DRIVER_NUM, 0, Some(tick), count);
+        // Sets an alarm relative to now, in microseconds at 1 MHz.
+        app.command(capsules_core::alarm::DRIVER_NUM, 5, 1_000_000, 0);
+        app.yield_wait();
+    }
+}
+
+/// Counts the boots of the kernel in the KV store.
+fn boot_count(app: &App) {
+    const KEY: &[u8] = b"boot_count";
+    const GET: usize = 1;
+    const SET: usize = 2;
+
+    let key = app.alloc(KEY.len());
+    key.write(KEY);
+    let value = app.alloc(4);
+    app.allow_ro(capsules_extra::kv_driver::DRIVER_NUM, 0, Some(&key));
+
+    app.allow_rw(capsules_extra::kv_driver::DRIVER_NUM, 0, Some(&value));
+    app.command(capsules_extra::kv_driver::DRIVER_NUM, GET, 0, 0);
+    let (status, len, _) = app.yield_wait_for(capsules_extra::kv_driver::DRIVER_NUM, 0);
+    app.allow_rw(capsules_extra::kv_driver::DRIVER_NUM, 0, None);
+    let boots = match value.read(len).try_into() {
+        Ok(bytes) if status == 0 => u32::from_le_bytes(bytes) + 1,
+        _ => 1,
+    };
+
+    value.write(&boots.to_le_bytes());
+    app.allow_ro(capsules_extra::kv_driver::DRIVER_NUM, 1, Some(&value));
+    app.command(capsules_extra::kv_driver::DRIVER_NUM, SET, 0, 0);
+    let (status, _, _) = app.yield_wait_for(capsules_extra::kv_driver::DRIVER_NUM, 0);
+    app.allow_ro(capsules_extra::kv_driver::DRIVER_NUM, 1, None);
+
+    if status == 0 {
+        print(app, &format!("Boot number {}\r\n", boots));
+    } else {
+        print(
+            app,
+            &format!("Storing the boot count failed: {}\r\n", status),
+        );
+    }
+}
@@ -0,0 +1,648 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Board file running the kernel as a process of a POSIX host.
+//!
+//! The kernel and its capsules run on the main thread and the applications
+//! in [`apps`] on threads of their own (see `posix_chip`). The UART is the
+//! standard input and output, the alarm the monotonic clock of the host and
+//! the flash a file, which holds the KV store.
+//!
+//! Options:
+//!
+//! - `--flash <path>`: file used as the flash, `posix-flash.bin` by default.
+//! - `--radio-hub <address>`: connects the simulated 802.15.4 radio to the
+//!   hub started with `tools/ieee802154_hub.py` at `address`, and runs UDP
+//!   over 6LoWPAN on it.
+//! - `--radio-node <byte>`: last byte of the MAC and short addresses of the
+//!   radio, in hex, `01` by default. Each node connected to the hub must use
+//!   a different one.
+
+use core::ptr::{addr_of, addr_of_mut};
+use std::path::PathBuf;
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::net::icmpv6::icmpv6_recv::ICMP6Receiver;
+use capsules_extra::net::ieee802154::MacAddress;
+use capsules_extra::net::ipv6::ip_utils::IPAddr;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::platform::scheduler_timer::VirtualSchedulerTimer;
+use kernel::platform::KernelResources;
+use kernel::platform::SyscallDriverLookup;
+use kernel::process::ProcessLoadingAsync;
+use kernel::scheduler::round_robin::RoundRobinSched;
+use kernel::{create_capability, debug, static_init};
+use posix_chip::chip::{Posix, PosixDefaultPeripherals};
+
+mod apps;
+
+pub const NUM_PROCS: usize = 4;
+
+// Actual memory for holding the active process structures. Need an empty list
+// at least.
+static mut PROCESSES: [Option<&'static dyn kernel::process::Process>; NUM_PROCS] =
+    [None; NUM_PROCS];
+
+// Memory of the processes. Their stacks are those of their host threads.
+static mut APP_MEMORY: [u8; 0x10000] = [0; 0x10000];
+
+// How should the kernel respond when a process faults.
+const FAULT_RESPONSE: capsules_system::process_policies::StopWithDebugFaultPolicy =
+    capsules_system::process_policies::StopWithDebugFaultPolicy {};
+
+// Number of pages of the flash file, which all belong to the KV store.
+const FLASH_PAGES: usize = 64;
+
+// The radio is addressed like the simulated radios of the qemu_rv32_virt
+// board, so that both can join the same PAN.
+const RADIO_MAC_PREFIX: [u8; 5] = [0x02, 0x15, 0x04, 0x00, 0x00];
+
+// Constants related to the configuration of the 15.4 network stack
+const PAN_ID: u16 = 0xABCD;
+const DST_MAC_ADDR: MacAddress = MacAddress::Short(0xFFFF);
+const DEFAULT_CTX_PREFIX_LEN: u8 = 8; //Length of context for 6LoWPAN compression
+const DEFAULT_CTX_PREFIX: [u8; 16] = [0x0_u8; 16]; //Context for 6LoWPAN Compression
+
+type Alarm = posix_chip::alarm::Alarm<'static>;
+type ChipHw = Posix<'static, PosixDefaultPeripherals<'static>>;
+
+type AlarmDriver = components::alarm::AlarmDriverComponentType<Alarm>;
+
+// TicKV
+type Flash = posix_chip::flash::Flash;
+const TICKV_PAGE_SIZE: usize = posix_chip::flash::PAGE_SIZE;
+type Siphasher24 = components::siphash::Siphasher24ComponentType;
+type TicKVDedicatedFlash =
+    components::tickv::TicKVDedicatedFlashComponentType<Flash, Siphasher24, TICKV_PAGE_SIZE>;
+type TicKVKVStore = components::kv::TicKVKVStoreComponentType<
+    TicKVDedicatedFlash,
+    capsules_extra::tickv::TicKVKeyType,
+>;
+type KVStorePermissions = components::kv::KVStorePermissionsComponentType<TicKVKVStore>;
+type VirtualKVPermissions = components::kv::VirtualKVPermissionsComponentType<KVStorePermissions>;
+type KVDriver = components::kv::KVDriverComponentType<VirtualKVPermissions>;
+
+// IEEE 802.15.4
+type EthernetRadio = components::ethernet_radio::EthernetRadioComponentType<Alarm>;
+type Aes128Software = components::aes::Aes128SoftwareComponentType;
+type Ieee802154MacDevice =
+    components::ieee802154::Ieee802154ComponentMacDeviceType<EthernetRadio, Aes128Software>;
+type Ieee802154Driver =
+    components::ieee802154::Ieee802154ComponentType<EthernetRadio, Aes128Software>;
+
+/// A structure representing this platform that holds references to all
+/// capsules for this platform.
+struct PosixPlatform {
+    pconsole: &'static capsules_core::process_console::ProcessConsole<
+        'static,
+        { capsules_core::process_console::DEFAULT_COMMAND_HISTORY_LEN },
+        VirtualMuxAlarm<'static, Alarm>,
+        components::process_console::Capability,
+    >,
+    console: &'static capsules_core::console::Console<'static>,
+    lldb: &'static capsules_core::low_level_debug::LowLevelDebug<
+        'static,
+        capsules_core::virtualizers::virtual_uart::UartDevice<'static>,
+    >,
+    alarm: &'static AlarmDriver,
+    kv_driver: &'static KVDriver,
+    ipc: kernel::ipc::IPC<{ NUM_PROCS as u8 }>,
+    scheduler: &'static RoundRobinSched<'static>,
+    scheduler_timer: &'static VirtualSchedulerTimer<VirtualMuxAlarm<'static, Alarm>>,
+    udp_driver: Option<&'static capsules_extra::net::udp::UDPDriver<'static>>,
+    eui64: Option<&'static components::eui64::Eui64ComponentType>,
+    ieee802154_driver: Option<&'static Ieee802154Driver>,
+    ping_driver: Option<&'static capsules_extra::net::icmpv6::driver::PingDriver<'static>>,
+}
+
+/// Mapping of integer syscalls to objects that implement syscalls.
+impl SyscallDriverLookup for PosixPlatform {
+    fn with_driver<F, R>(&self, driver_num: usize, f: F) -> R
+    where
+        F: FnOnce(Option<&dyn kernel::syscall::SyscallDriver>) -> R,
+    {
+        match driver_num {
+            capsules_core::console::DRIVER_NUM => f(Some(self.console)),
+            capsules_core::alarm::DRIVER_NUM => f(Some(self.alarm)),
+            capsules_core::low_level_debug::DRIVER_NUM => f(Some(self.lldb)),
+            capsules_extra::kv_driver::DRIVER_NUM => f(Some(self.kv_driver)),
+            capsules_extra::net::udp::DRIVER_NUM => {
+                if let Some(udp_driver) = self.udp_driver {
+                    f(Some(udp_driver))
+                } else {
+                    f(None)
+                }
+            }
+            capsules_extra::eui64::DRIVER_NUM => {
+                if let Some(eui64) = self.eui64 {
+                    f(Some(eui64))
+                } else {
+                    f(None)
+                }
+            }
+            capsules_extra::ieee802154::DRIVER_NUM => {
+                if let Some(ieee802154_driver) = self.ieee802154_driver {
+                    f(Some(ieee802154_driver))
+                } else {
+                    f(None)
+                }
+            }
+            capsules_extra::net::icmpv6::driver::DRIVER_NUM => {
+                if let Some(ping_driver) = self.ping_driver {
+                    f(Some(ping_driver))
+                } else {
+                    f(None)
+                }
+            }
+            kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
+            _ => f(None),
+        }
+    }
+}
+
+impl KernelResources<ChipHw> for PosixPlatform {
+    type SyscallDriverLookup = Self;
+    type SyscallFilter = ();
+    type ProcessFault = ();
+    type Scheduler = RoundRobinSched<'static>;
+    type SchedulerTimer = VirtualSchedulerTimer<VirtualMuxAlarm<'static, Alarm>>;
+    type WatchDog = ();
+    type ContextSwitchCallback = ();
+
+    fn syscall_driver_lookup(&self) -> &Self::SyscallDriverLookup {
+        self
+    }
+    fn syscall_filter(&self) -> &Self::SyscallFilter {
+        &()
+    }
+    fn process_fault(&self) -> &Self::ProcessFault {
+        &()
+    }
+    fn scheduler(&self) -> &Self::Scheduler {
+        self.scheduler
+    }
+    fn scheduler_timer(&self) -> &Self::SchedulerTimer {
+        self.scheduler_timer
+    }
+    fn watchdog(&self) -> &Self::WatchDog {
+        &()
+    }
+    fn context_switch_callback(&self) -> &Self::ContextSwitchCallback {
+        &()
+    }
+}
+
+/// Options given on the command line.
+struct Options {
+    flash: PathBuf,
+    radio_hub: Option<String>,
+    radio_node: u8,
+}
+
+fn parse_options() -> Options {
+    let mut options = Options {
+        flash: PathBuf::from("posix-flash.bin"),
+        radio_hub: None,
+        radio_node: 0x01,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args
+            .next()
+            .unwrap_or_else(|| panic!("missing value for {}", arg));
+        match arg.as_str() {
+            "--flash" => options.flash = PathBuf::from(value),
+            "--radio-hub" => options.radio_hub = Some(value),
+            "--radio-node" => {
+                options.radio_node = u8::from_str_radix(&value, 16)
+                    .unwrap_or_else(|_| panic!("invalid radio node {}", value));
+            }
+            _ => panic!("unknown option {}", arg),
+        }
+    }
+    options
+}
+
+/// Returns the addresses of the memory regions of the kernel, for the `kernel`
+/// command of the process console.
+///
+/// The host linker has no symbols for the regions of the Tock linker script,
+/// so they are approximated from the symbols of the standard ELF layout: code
+/// up to `_etext`, read-only data up to `__data_start`, initialized data and
+/// then the BSS. The stack is the one of the main thread, as listed in
+/// `/proc/self/maps`.
+fn kernel_addresses() -> capsules_core::process_console::KernelAddresses {
+    extern "C" {
+        static __executable_start: u8;
+        static _etext: u8;
+        static __data_start: u8;
+        static _edata: u8;
+        static __bss_start: u8;
+        static _end: u8;
+    }
+
+    let (stack_start, stack_end) = std::fs::read_to_string("/proc/self/maps")
+        .ok()
+        .and_then(|maps| {
+            let line = maps.lines().find(|line| line.ends_with("[stack]"))?;
+            let (start, end) = line.split_whitespace().next()?.split_once('-')?;
+            Some((
+                usize::from_str_radix(start, 16).ok()?,
+                usize::from_str_radix(end, 16).ok()?,
+            ))
+        })
+        .unwrap_or_default();
+
+    // SAFETY: These statics are defined by the linker, and we are merely
+    // creating pointers to them.
+    unsafe {
+        capsules_core::process_console::KernelAddresses {
+            stack_start: stack_start as *const u8,
+            stack_end: stack_end as *const u8,
+            text_start: addr_of!(__executable_start),
+            text_end: addr_of!(__data_start),
+            read_only_data_start: addr_of!(_etext),
+            relocations_start: addr_of!(__data_start),
+            relocations_end: addr_of!(_edata),
+            bss_start: addr_of!(__bss_start),
+            bss_end: addr_of!(_end),
+        }
+    }
+}
+
+/// Runs the 802.15.4 stack on a radio simulated over the Ethernet adapter
+/// `ethernet`, with UDP and ICMPv6 over 6LoWPAN on top of it.
+///
+/// The addresses of the node derive from the MAC address of the radio: the
+/// extended address is its EUI-64 and the short address its last two bytes.
+unsafe fn ieee802154_udp(
+    board_kernel: &'static kernel::Kernel,
+    ethernet: &'static posix_chip::ethernet::Ethernet<'static>,
+    mac_addr: [u8; 6],
+    mux_alarm: &'static MuxAlarm<'static, Alarm>,
+) -> (
+    &'static components::eui64::Eui64ComponentType,
+    &'static Ieee802154Driver,
+    &'static capsules_extra::net::udp::UDPDriver<'static>,
+    &'static capsules_extra::net::icmpv6::driver::PingDriver<'static>,
+) {
+    let long_addr = [
+        mac_addr[0],
+        mac_addr[1],
+        mac_addr[2],
+        0xff,
+        0xfe,
+        mac_addr[3],
+        mac_addr[4],
+        mac_addr[5],
+    ];
+    let short_addr = u16::from_be_bytes([mac_addr[4], mac_addr[5]]);
+
+    let radio =
+        components::ethernet_radio::EthernetRadioComponent::new(ethernet, mux_alarm, mac_addr)
+            .finalize(components::ethernet_radio_component_static!(Alarm));
+
+    let aes = components::aes::Aes128SoftwareComponent::new()
+        .finalize(components::aes128_software_component_static!());
+    let aes_mux = components::ieee802154::MuxAes128ccmComponent::new(aes)
+        .finalize(components::mux_aes128ccm_component_static!(Aes128Software));
+
+    let eui64 = components::eui64::Eui64Component::new(u64::from_le_bytes(long_addr))
+        .finalize(components::eui64_component_static!());
+
+    let (ieee802154_driver, mux_mac) = components::ieee802154::Ieee802154Component::new(
+        board_kernel,
+        capsules_extra::ieee802154::DRIVER_NUM,
+        radio,
+        aes_mux,
+        PAN_ID,
+        short_addr,
+        long_addr,
+    )
+    .finalize(components::ieee802154_component_static!(
+        EthernetRadio,
+        Aes128Software
+    ));
+
+    let local_ip_ifaces = static_init!(
+        [IPAddr; 2],
+        [
+            IPAddr::generate_from_mac(MacAddress::Long(long_addr)),
+            IPAddr::generate_from_mac(MacAddress::Short(short_addr)),
+        ]
+    );
+
+    let ctx_table = components::sixlowpan_context::ContextTableComponent::new(
+        DEFAULT_CTX_PREFIX_LEN,
+        DEFAULT_CTX_PREFIX,
+    )
+    .finalize(components::context_table_component_static!());
+
+    let ndp = components::ndp::NdpComponent::new(mux_alarm, ctx_table, eui64, local_ip_ifaces)
+        .finalize(components::ndp_component_static!(Alarm));
+
+    let (udp_send_mux, udp_recv_mux, udp_port_table) = components::udp_mux::UDPMuxComponent::new(
+        mux_mac,
+        ctx_table,
+        Some(ndp),
+        DST_MAC_ADDR,
+        MacAddress::Long(long_addr),
+        local_ip_ifaces,
+        mux_alarm,
+    )
+    .finalize(components::udp_mux_component_static!(
+        Alarm,
+        Ieee802154MacDevice
+    ));
+
+    let udp_driver = components::udp_driver::UDPDriverComponent::new(
+        board_kernel,
+        capsules_extra::net::udp::DRIVER_NUM,
+        udp_send_mux,
+        udp_recv_mux,
+        udp_port_table,
+        local_ip_ifaces,
+    )
+    .finalize(components::udp_driver_component_static!(Alarm));
+
+    let icmp6 = components::icmpv6::ICMP6Component::new(
+        mux_mac,
+        ctx_table,
+        Some(ndp),
+        DST_MAC_ADDR,
+        MacAddress::Long(long_addr),
+        local_ip_ifaces,
+        mux_alarm,
+    )
+    .finalize(components::icmp6_component_static!(
+        Alarm,
+        Ieee802154MacDevice
+    ));
+    icmp6.set_error_client(udp_recv_mux);
+    icmp6.set_nd_client(ndp);
+    ndp.set_icmp(icmp6);
+    ndp.start();
+
+    let ping_driver = components::ping_driver::PingDriverComponent::new(
+        board_kernel,
+        capsules_extra::net::icmpv6::driver::DRIVER_NUM,
+        icmp6,
+    )
+    .finalize(components::ping_driver_component_static!());
+
+    (eui64, ieee802154_driver, udp_driver, ping_driver)
+}
+
+/// Sets up the kernel, its capsules and the processes.
+unsafe fn start() -> (&'static kernel::Kernel, PosixPlatform, &'static ChipHw) {
+    let options = parse_options();
+
+    // Acquire required capabilities
+    let process_mgmt_cap = create_capability!(capabilities::ProcessManagementCapability);
+    let memory_allocation_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+    // Create a board kernel instance
+    let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new(&*addr_of!(PROCESSES)));
+
+    // ---------- HOST PERIPHERALS ----------
+
+    let peripherals = static_init!(PosixDefaultPeripherals, PosixDefaultPeripherals::new());
+    peripherals.init();
+
+    peripherals
+        .flash
+        .open(&options.flash, FLASH_PAGES)
+        .unwrap_or_else(|err| panic!("cannot open {}: {}", options.flash.display(), err));
+
+    // Create a shared UART channel for the console and for kernel debug over
+    // the standard input and output.
+    let uart_mux = components::console::UartMuxComponent::new(&peripherals.uart, 115200)
+        .finalize(components::uart_mux_component_static!());
+
+    // Create a shared virtualization mux layer on top of the host clock.
+    let mux_alarm = components::alarm::AlarmMuxComponent::new(&peripherals.alarm)
+        .finalize(components::alarm_mux_component_static!(Alarm));
+
+    // Virtual alarm for the scheduler
+    let systick_virtual_alarm = static_init!(
+        VirtualMuxAlarm<'static, Alarm>,
+        VirtualMuxAlarm::new(mux_alarm)
+    );
+    systick_virtual_alarm.setup();
+
+    // Alarm driver for userspace
+    let alarm = components::alarm::AlarmDriverComponent::new(
+        board_kernel,
+        capsules_core::alarm::DRIVER_NUM,
+        mux_alarm,
+    )
+    .finalize(components::alarm_component_static!(Alarm));
+
+    // ---------- INITIALIZE CHIP ---------
+
+    let chip = static_init!(ChipHw, Posix::new(peripherals));
+
+    // ---------- FINAL SYSTEM INITIALIZATION ----------
+
+    // Create the process printer used in panic prints, etc.
+    let process_printer = components::process_printer::ProcessPrinterTextComponent::new()
+        .finalize(components::process_printer_text_component_static!());
+
+    // Initialize the kernel's process console.
+    let pconsole = components::process_console::ProcessConsoleComponent::new_with_kernel_addresses(
+        board_kernel,
+        uart_mux,
+        mux_alarm,
+        process_printer,
+        None,
+        kernel_addresses(),
+    )
+    .finalize(components::process_console_component_static!(Alarm));
+
+    // Record system calls at runtime, controlled with the `trace` command.
+    let syscall_trace =
+        components::syscall_trace::SyscallTraceComponent::new(board_kernel, &peripherals.alarm)
+            .finalize(components::syscall_trace_component_static!(Alarm, 64, 16));
+    pconsole.set_syscall_trace(syscall_trace);
+
+    // Setup the console.
+    let console = components::console::ConsoleComponent::new(
+        board_kernel,
+        capsules_core::console::DRIVER_NUM,
+        uart_mux,
+    )
+    .finalize(components::console_component_static!());
+    // Create the debugger object that handles calls to `debug!()`.
+    components::debug_writer::DebugWriterComponent::new(uart_mux)
+        .finalize(components::debug_writer_component_static!());
+
+    let lldb = components::lldb::LowLevelDebugComponent::new(
+        board_kernel,
+        capsules_core::low_level_debug::DRIVER_NUM,
+        uart_mux,
+    )
+    .finalize(components::low_level_debug_component_static!());
+
+    // ---------- KV STORE ----------
+
+    // Static buffer to use when reading/writing flash for TicKV.
+    let page_buffer = static_init!(
+        posix_chip::flash::FlashPage,
+        posix_chip::flash::FlashPage::default()
+    );
+
+    // SipHash for creating TicKV hashed keys.
+    let sip_hash = components::siphash::Siphasher24Component::new()
+        .finalize(components::siphasher24_component_static!());
+
+    // TicKV with Tock wrapper/interface, on the whole flash file.
+    let tickv = components::tickv::TicKVDedicatedFlashComponent::new(
+        sip_hash,
+        &peripherals.flash,
+        0,
+        peripherals.flash.page_count() * TICKV_PAGE_SIZE,
+        page_buffer,
+    )
+    .finalize(components::tickv_dedicated_flash_component_static!(
+        Flash,
+        Siphasher24,
+        TICKV_PAGE_SIZE,
+    ));
+
+    // KVSystem interface to KV (built on TicKV).
+    let tickv_kv_store = components::kv::TicKVKVStoreComponent::new(tickv).finalize(
+        components::tickv_kv_store_component_static!(
+            TicKVDedicatedFlash,
+            capsules_extra::tickv::TicKVKeyType,
+        ),
+    );
+
+    let kv_store_permissions = components::kv::KVStorePermissionsComponent::new(tickv_kv_store)
+        .finalize(components::kv_store_permissions_component_static!(
+            TicKVKVStore
+        ));
+
+    // Share the KV stack with a mux.
+    let mux_kv = components::kv::KVPermissionsMuxComponent::new(kv_store_permissions).finalize(
+        components::kv_permissions_mux_component_static!(KVStorePermissions),
+    );
+
+    // Create a virtual component for the userspace driver.
+    let virtual_kv_driver = components::kv::VirtualKVPermissionsComponent::new(mux_kv).finalize(
+        components::virtual_kv_permissions_component_static!(KVStorePermissions),
+    );
+
+    // Userspace driver for KV.
+    let kv_driver = components::kv::KVDriverComponent::new(
+        virtual_kv_driver,
+        board_kernel,
+        capsules_extra::kv_driver::DRIVER_NUM,
+    )
+    .finalize(components::kv_driver_component_static!(
+        VirtualKVPermissions
+    ));
+
+    // ---------- NETWORKING ----------
+
+    // Run the 802.15.4 stack if a hub is given to connect the radio to.
+    let ieee802154 = options.radio_hub.map(|radio_hub| {
+        peripherals
+            .ethernet
+            .connect(radio_hub.as_str())
+            .unwrap_or_else(|err| panic!("cannot connect to {}: {}", radio_hub, err));
+        let mut mac_addr = [options.radio_node; 6];
+        mac_addr[..RADIO_MAC_PREFIX.len()].copy_from_slice(&RADIO_MAC_PREFIX);
+        ieee802154_udp(board_kernel, &peripherals.ethernet, mac_addr, mux_alarm)
+    });
+
+    // ---------- SCHEDULER ----------
+
+    let scheduler = components::sched::round_robin::RoundRobinComponent::new(&*addr_of!(PROCESSES))
+        .finalize(components::round_robin_component_static!(NUM_PROCS));
+    pconsole.set_scheduler(scheduler);
+
+    let scheduler_timer = static_init!(
+        VirtualSchedulerTimer<VirtualMuxAlarm<'static, Alarm>>,
+        VirtualSchedulerTimer::new(systick_virtual_alarm)
+    );
+
+    let platform = PosixPlatform {
+        pconsole,
+        console,
+        alarm,
+        lldb,
+        kv_driver,
+        scheduler,
+        scheduler_timer,
+        udp_driver: ieee802154.map(|(_, _, udp_driver, _)| udp_driver),
+        eui64: ieee802154.map(|(eui64, _, _, _)| eui64),
+        ieee802154_driver: ieee802154.map(|(_, ieee802154_driver, _, _)| ieee802154_driver),
+        ping_driver: ieee802154.map(|(_, _, _, ping_driver)| ping_driver),
+        ipc: kernel::ipc::IPC::new(
+            board_kernel,
+            kernel::ipc::DRIVER_NUM,
+            &memory_allocation_cap,
+        ),
+    };
+
+    // Start the process console:
+    let _ = platform.pconsole.start();
+
+    debug!("POSIX host, initialization complete.");
+    debug!("Entering main loop.");
+
+    // ---------- PROCESS LOADING ----------
+
+    // Applications are not signed, and are identified by their name so that
+    // they keep their storage permissions across runs.
+    let checking_policy = components::appid::checker_null::AppCheckerNullComponent::new()
+        .finalize(components::app_checker_null_component_static!());
+    let assigner = components::appid::assigner_name::AppIdAssignerNamesComponent::new()
+        .finalize(components::appid_assigner_names_component_static!());
+    let checker = components::appid::checker::ProcessCheckerMachineComponent::new(checking_policy)
+        .finalize(components::process_checker_machine_component_static!());
+
+    let storage_permissions_policy =
+        components::storage_permissions::individual::StoragePermissionsIndividualComponent::new()
+            .finalize(components::storage_permissions_individual_component_static!(ChipHw));
+
+    // The sequential loader component takes the applications from the
+    // linker script, so the loader is created here with the host images.
+    const ARRAY_REPEAT_VALUE: Option<kernel::process::ProcessBinary> = None;
+    let process_binary_array = static_init!(
+        [Option<kernel::process::ProcessBinary>; NUM_PROCS],
+        [ARRAY_REPEAT_VALUE; NUM_PROCS]
+    );
+    let loader = static_init!(
+        kernel::process::SequentialProcessLoaderMachine<ChipHw>,
+        kernel::process::SequentialProcessLoaderMachine::new(
+            checker,
+            &mut *addr_of_mut!(PROCESSES),
+            process_binary_array,
+            board_kernel,
+            chip,
+            apps::image(),
+            &mut *addr_of_mut!(APP_MEMORY),
+            &FAULT_RESPONSE,
+            storage_permissions_policy,
+            assigner,
+            &process_mgmt_cap,
+        )
+    );
+    checker.set_client(loader);
+    loader.register();
+    loader.start();
+
+    (board_kernel, platform, chip)
+}
+
+fn main() {
+    let main_loop_capability = create_capability!(capabilities::MainLoopCapability);
+
+    // SAFETY: `start` is only called once, before the kernel loop.
+    let (board_kernel, platform, chip) = unsafe { start() };
+    board_kernel.kernel_loop(&platform, chip, Some(&platform.ipc), &main_loop_capability);
+}
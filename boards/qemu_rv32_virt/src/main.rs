@@ -13,6 +13,9 @@ use core::ptr::addr_of;
 use core::ptr::addr_of_mut;
 
 use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::net::icmpv6::icmpv6_recv::ICMP6Receiver;
+use capsules_extra::net::ieee802154::MacAddress;
+use capsules_extra::net::ipv6::ip_utils::IPAddr;
 use kernel::capabilities;
 use kernel::component::Component;
 use kernel::hil;
@@ -45,6 +48,32 @@ static mut PROCESS_PRINTER: Option<&'static capsules_system::process_printer::Pr
 const FAULT_RESPONSE: capsules_system::process_policies::PanicFaultPolicy =
     capsules_system::process_policies::PanicFaultPolicy {};
 
+// IPv6 prefix and router address of QEMU's user networking (slirp) backend,
+// as configured in the Makefile.
+const SLIRP_IPV6_PREFIX: [u8; 8] = [0xfe, 0xc0, 0, 0, 0, 0, 0, 0];
+const SLIRP_IPV6_ROUTER: IPAddr = IPAddr([0xfe, 0xc0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x02]);
+
+// VirtIO network cards whose MAC address starts with this prefix connect to
+// the 802.15.4 hub (see `RADIO` in the Makefile) and carry a simulated radio.
+const RADIO_MAC_PREFIX: [u8; 3] = [0x02, 0x15, 0x04];
+
+// Constants related to the configuration of the 15.4 network stack
+const PAN_ID: u16 = 0xABCD;
+const DST_MAC_ADDR: MacAddress = MacAddress::Short(0xFFFF);
+const DEFAULT_CTX_PREFIX_LEN: u8 = 8; //Length of context for 6LoWPAN compression
+const DEFAULT_CTX_PREFIX: [u8; 16] = [0x0_u8; 16]; //Context for 6LoWPAN Compression
+
+type Clint = qemu_rv32_virt_chip::chip::QemuRv32VirtClint<'static>;
+type VirtIONet = qemu_rv32_virt_chip::virtio::devices::virtio_net::VirtIONet<'static>;
+
+// IEEE 802.15.4
+type EthernetRadio = components::ethernet_radio::EthernetRadioComponentType<Clint>;
+type Aes128Software = components::aes::Aes128SoftwareComponentType;
+type Ieee802154MacDevice =
+    components::ieee802154::Ieee802154ComponentMacDeviceType<EthernetRadio, Aes128Software>;
+type Ieee802154Driver =
+    components::ieee802154::Ieee802154ComponentType<EthernetRadio, Aes128Software>;
+
 /// Dummy buffer that causes the linker to reserve enough space for the stack.
 #[no_mangle]
 #[link_section = ".stack_buffer"]
@@ -82,6 +111,10 @@ struct QemuRv32VirtPlatform {
             qemu_rv32_virt_chip::virtio::devices::virtio_rng::VirtIORng<'static, 'static>,
         >,
     >,
+    udp_driver: Option<&'static capsules_extra::net::udp::UDPDriver<'static>>,
+    eui64: Option<&'static components::eui64::Eui64ComponentType>,
+    ieee802154_driver: Option<&'static Ieee802154Driver>,
+    ping_driver: Option<&'static capsules_extra::net::icmpv6::driver::PingDriver<'static>>,
 }
 
 /// Mapping of integer syscalls to objects that implement syscalls.
@@ -101,6 +134,34 @@ impl SyscallDriverLookup for QemuRv32VirtPlatform {
                     f(None)
                 }
             }
+            capsules_extra::net::udp::DRIVER_NUM => {
+                if let Some(udp_driver) = self.udp_driver {
+                    f(Some(udp_driver))
+                } else {
+                    f(None)
+                }
+            }
+            capsules_extra::eui64::DRIVER_NUM => {
+                if let Some(eui64) = self.eui64 {
+                    f(Some(eui64))
+                } else {
+                    f(None)
+                }
+            }
+            capsules_extra::ieee802154::DRIVER_NUM => {
+                if let Some(ieee802154_driver) = self.ieee802154_driver {
+                    f(Some(ieee802154_driver))
+                } else {
+                    f(None)
+                }
+            }
+            capsules_extra::net::icmpv6::driver::DRIVER_NUM => {
+                if let Some(ping_driver) = self.ping_driver {
+                    f(Some(ping_driver))
+                } else {
+                    f(None)
+                }
+            }
             kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
             _ => f(None),
         }
@@ -148,6 +209,142 @@ impl
     }
 }
 
+/// Reads the MAC address of a VirtIO network card, which QEMU stores in the
+/// first 6 bytes of the device configuration space.
+fn read_mac_addr(
+    device: &qemu_rv32_virt_chip::virtio::transports::mmio::VirtIOMMIODevice,
+) -> [u8; 6] {
+    let mut mac_addr = [0; 6];
+    for (i, byte) in mac_addr.iter_mut().enumerate() {
+        *byte = device.read_device_config(i).unwrap_or(0);
+    }
+    mac_addr
+}
+
+/// Runs the 802.15.4 stack on a radio simulated over the VirtIO network card
+/// `virtio_net`, with UDP and ICMPv6 over 6LoWPAN on top of it.
+///
+/// The addresses of the node derive from the MAC address of the card: the
+/// extended address is its EUI-64 and the short address its last two bytes.
+unsafe fn ieee802154_udp(
+    board_kernel: &'static kernel::Kernel,
+    virtio_net: &'static VirtIONet,
+    mac_addr: [u8; 6],
+    mux_alarm: &'static MuxAlarm<'static, Clint>,
+) -> (
+    &'static components::eui64::Eui64ComponentType,
+    &'static Ieee802154Driver,
+    &'static capsules_extra::net::udp::UDPDriver<'static>,
+    &'static capsules_extra::net::icmpv6::driver::PingDriver<'static>,
+) {
+    let long_addr = [
+        mac_addr[0],
+        mac_addr[1],
+        mac_addr[2],
+        0xff,
+        0xfe,
+        mac_addr[3],
+        mac_addr[4],
+        mac_addr[5],
+    ];
+    let short_addr = u16::from_be_bytes([mac_addr[4], mac_addr[5]]);
+
+    let radio =
+        components::ethernet_radio::EthernetRadioComponent::new(virtio_net, mux_alarm, mac_addr)
+            .finalize(components::ethernet_radio_component_static!(Clint));
+
+    // The chip has no AES peripheral.
+    let aes = components::aes::Aes128SoftwareComponent::new()
+        .finalize(components::aes128_software_component_static!());
+    let aes_mux = components::ieee802154::MuxAes128ccmComponent::new(aes)
+        .finalize(components::mux_aes128ccm_component_static!(Aes128Software));
+
+    let eui64 = components::eui64::Eui64Component::new(u64::from_le_bytes(long_addr))
+        .finalize(components::eui64_component_static!());
+
+    let (ieee802154_driver, mux_mac) = components::ieee802154::Ieee802154Component::new(
+        board_kernel,
+        capsules_extra::ieee802154::DRIVER_NUM,
+        radio,
+        aes_mux,
+        PAN_ID,
+        short_addr,
+        long_addr,
+    )
+    .finalize(components::ieee802154_component_static!(
+        EthernetRadio,
+        Aes128Software
+    ));
+
+    let local_ip_ifaces = static_init!(
+        [IPAddr; 2],
+        [
+            IPAddr::generate_from_mac(MacAddress::Long(long_addr)),
+            IPAddr::generate_from_mac(MacAddress::Short(short_addr)),
+        ]
+    );
+
+    let ctx_table = components::sixlowpan_context::ContextTableComponent::new(
+        DEFAULT_CTX_PREFIX_LEN,
+        DEFAULT_CTX_PREFIX,
+    )
+    .finalize(components::context_table_component_static!());
+
+    let ndp = components::ndp::NdpComponent::new(mux_alarm, ctx_table, eui64, local_ip_ifaces)
+        .finalize(components::ndp_component_static!(Clint));
+
+    let (udp_send_mux, udp_recv_mux, udp_port_table) = components::udp_mux::UDPMuxComponent::new(
+        mux_mac,
+        ctx_table,
+        Some(ndp),
+        DST_MAC_ADDR,
+        MacAddress::Long(long_addr),
+        local_ip_ifaces,
+        mux_alarm,
+    )
+    .finalize(components::udp_mux_component_static!(
+        Clint,
+        Ieee802154MacDevice
+    ));
+
+    let udp_driver = components::udp_driver::UDPDriverComponent::new(
+        board_kernel,
+        capsules_extra::net::udp::DRIVER_NUM,
+        udp_send_mux,
+        udp_recv_mux,
+        udp_port_table,
+        local_ip_ifaces,
+    )
+    .finalize(components::udp_driver_component_static!(Clint));
+
+    let icmp6 = components::icmpv6::ICMP6Component::new(
+        mux_mac,
+        ctx_table,
+        Some(ndp),
+        DST_MAC_ADDR,
+        MacAddress::Long(long_addr),
+        local_ip_ifaces,
+        mux_alarm,
+    )
+    .finalize(components::icmp6_component_static!(
+        Clint,
+        Ieee802154MacDevice
+    ));
+    icmp6.set_error_client(udp_recv_mux);
+    icmp6.set_nd_client(ndp);
+    ndp.set_icmp(icmp6);
+    ndp.start();
+
+    let ping_driver = components::ping_driver::PingDriverComponent::new(
+        board_kernel,
+        capsules_extra::net::icmpv6::driver::DRIVER_NUM,
+        icmp6,
+    )
+    .finalize(components::ping_driver_component_static!());
+
+    (eui64, ieee802154_driver, udp_driver, ping_driver)
+}
+
 /// This is in a separate, inline(never) function so that its stack frame is
 /// removed when this function returns. Otherwise, the stack space used for
 /// these static_inits is wasted.
@@ -291,12 +488,16 @@ unsafe fn start() -> (
     // Collect supported VirtIO peripheral indicies and initialize them if they
     // are found. If there are two instances of a supported peripheral, the one
     // on a higher-indexed VirtIO transport is used.
-    let (mut virtio_net_idx, mut virtio_rng_idx) = (None, None);
+    let (mut virtio_net_idx, mut virtio_radio_idx, mut virtio_rng_idx) = (None, None, None);
     for (i, virtio_device) in peripherals.virtio_mmio.iter().enumerate() {
         use qemu_rv32_virt_chip::virtio::devices::VirtIODeviceType;
         match virtio_device.query() {
             Some(VirtIODeviceType::NetworkCard) => {
-                virtio_net_idx = Some(i);
+                if read_mac_addr(virtio_device)[..3] == RADIO_MAC_PREFIX {
+                    virtio_radio_idx = Some(i);
+                } else {
+                    virtio_net_idx = Some(i);
+                }
             }
             Some(VirtIODeviceType::EntropySource) => {
                 virtio_rng_idx = Some(i);
@@ -366,15 +567,11 @@ unsafe fn start() -> (
     };
 
     // If there is a VirtIO NetworkCard present, use the appropriate VirtIONet
-    // driver. Currently this is not used, as work on the userspace network
-    // driver and kernel network stack is in progress.
-    //
-    // A template dummy driver is provided to verify basic functionality of this
-    // interface.
-    let _virtio_net_if: Option<
-        &'static qemu_rv32_virt_chip::virtio::devices::virtio_net::VirtIONet<'static>,
-    > = if let Some(net_idx) = virtio_net_idx {
-        use qemu_rv32_virt_chip::virtio::devices::virtio_net::VirtIONet;
+    // driver and run the IPv6 / UDP stack on top of it, exposing it to
+    // userspace through the UDP driver. A NetworkCard connected to the
+    // 802.15.4 hub takes precedence, and the stack then runs over 6LoWPAN on
+    // the simulated radio.
+    let (udp_driver, ieee802154) = if let Some(net_idx) = virtio_radio_idx.or(virtio_net_idx) {
         use qemu_rv32_virt_chip::virtio::queues::split_queue::{
             SplitVirtqueue, VirtqueueAvailableRing, VirtqueueDescriptors, VirtqueueUsedRing,
         };
@@ -422,7 +619,7 @@ unsafe fn start() -> (
         // Instantiate the VirtIONet (NetworkCard) driver and set
         // the queues
         let virtio_net = static_init!(
-            VirtIONet<'static>,
+            VirtIONet,
             VirtIONet::new(
                 0,
                 tx_queue,
@@ -442,17 +639,57 @@ unsafe fn start() -> (
             .initialize(virtio_net, mmio_queues)
             .unwrap();
 
-        // Don't forget to enable RX once when integrating this into a
-        // proper Ethernet stack:
-        // virtio_net.enable_rx();
-
-        // TODO: When we have a proper Ethernet driver available for userspace,
-        // return that. For now, just return a reference to the raw VirtIONet
-        // driver:
-        Some(virtio_net as &'static VirtIONet)
+        let mac_addr = read_mac_addr(&peripherals.virtio_mmio[net_idx]);
+        if virtio_radio_idx.is_some() {
+            let (eui64, ieee802154_driver, udp_driver, ping_driver) =
+                ieee802154_udp(board_kernel, virtio_net, mac_addr, mux_alarm);
+            (
+                Some(udp_driver),
+                Some((eui64, ieee802154_driver, ping_driver)),
+            )
+        } else {
+            // The global address uses the prefix of QEMU's user
+            // networking (slirp) backend, which also acts as the default
+            // router. It comes first in the list so that it is used as the
+            // source address.
+            let link_local_addr =
+                capsules_extra::net::ipv6::ipv6_ethernet::link_local_from_mac(mac_addr);
+            let mut global_addr = link_local_addr;
+            global_addr.set_prefix(&SLIRP_IPV6_PREFIX, 64);
+            let local_ip_ifaces = static_init!([IPAddr; 2], [global_addr, link_local_addr]);
+
+            let (udp_send_mux, udp_recv_mux, udp_port_table, ip6_eth) =
+                components::udp_mux_ethernet::UDPMuxEthernetComponent::new(
+                    virtio_net,
+                    mac_addr,
+                    local_ip_ifaces,
+                    mux_alarm,
+                )
+                .finalize(components::udp_mux_ethernet_component_static!(
+                    qemu_rv32_virt_chip::chip::QemuRv32VirtClint
+                ));
+            ip6_eth.set_default_router(SLIRP_IPV6_ROUTER);
+
+            let udp_driver = components::udp_driver::UDPDriverComponent::new(
+                board_kernel,
+                capsules_extra::net::udp::DRIVER_NUM,
+                udp_send_mux,
+                udp_recv_mux,
+                udp_port_table,
+                local_ip_ifaces,
+            )
+            .finalize(components::udp_driver_ip6_sender_component_static!(
+                capsules_extra::net::ipv6::ipv6_ethernet::IP6EthernetStruct<
+                    'static,
+                    VirtualMuxAlarm<'static, qemu_rv32_virt_chip::chip::QemuRv32VirtClint>,
+                >
+            ));
+
+            (Some(udp_driver), None)
+        }
     } else {
         // No VirtIO NetworkCard discovered
-        None
+        (None, None)
     };
 
     // ---------- INITIALIZE CHIP, ENABLE INTERRUPTS ---------
@@ -528,6 +765,10 @@ unsafe fn start() -> (
         scheduler,
         scheduler_timer,
         virtio_rng: virtio_rng_driver,
+        udp_driver,
+        eui64: ieee802154.map(|(eui64, _, _)| eui64),
+        ieee802154_driver: ieee802154.map(|(_, ieee802154_driver, _)| ieee802154_driver),
+        ping_driver: ieee802154.map(|(_, _, ping_driver)| ping_driver),
         ipc: kernel::ipc::IPC::new(
             board_kernel,
             kernel::ipc::DRIVER_NUM,
@@ -26,6 +26,8 @@ pub enum NUM {
 
     // Kernel
     Ipc                   = 0x10000,
+    AppLoader             = 0x10001,
+    DeadlineScheduler     = 0x10002,
 
     // HW Buses
     Spi                   = 0x20001,
@@ -43,6 +45,9 @@ pub enum NUM {
     LoRaPhyGPIO           = 0x30004,
     Thread                = 0x30005,
     Eui64                 = 0x30006,
+    Tcp                   = 0x30007,
+    Ping                  = 0x30008,
+    Coap                  = 0x30009,
 
     // Cryptography
     Rng                   = 0x40001,
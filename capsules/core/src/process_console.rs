@@ -11,9 +11,13 @@ use core::cmp;
 use core::fmt;
 use core::fmt::write;
 use core::str;
+use enum_primitive::cast::FromPrimitive;
 use kernel::capabilities::ProcessManagementCapability;
+use kernel::core_dump::{self, CoreDumpWriter};
+use kernel::crash_log::{CrashKind, CrashLogControl};
 use kernel::hil::time::ConvertTicks;
 use kernel::utilities::cells::MapCell;
+use kernel::utilities::cells::OptionalCell;
 use kernel::utilities::cells::TakeCell;
 use kernel::ProcessId;
 
@@ -21,11 +25,15 @@ use kernel::debug;
 use kernel::hil::time::{Alarm, AlarmClient};
 use kernel::hil::uart;
 use kernel::introspection::KernelInfo;
-use kernel::process::{ProcessPrinter, ProcessPrinterContext, State};
+use kernel::process::{Process, ProcessPrinter, ProcessPrinterContext, State};
+use kernel::scheduler::SchedulerParameters;
+use kernel::syscall_trace::SyscallTraceControl;
 use kernel::utilities::binary_write::BinaryWrite;
 use kernel::ErrorCode;
 use kernel::Kernel;
 
+use crate::driver;
+
 /// Buffer to hold outgoing data that is passed to the UART hardware.
 pub const WRITE_BUF_LEN: usize = 500;
 /// Buffer responses are initially held in until copied to the TX buffer and
@@ -43,7 +51,32 @@ pub const DEFAULT_COMMAND_HISTORY_LEN: usize = 10;
 /// List of valid commands for printing help. Consolidated as these are
 /// displayed in a few different cases.
 const VALID_COMMANDS_STR: &[u8] =
-    b"help status list stop start fault boot terminate process kernel reset panic console-start console-stop\r\n";
+    b"help status list stop start fault boot terminate process kernel reset panic console-start console-stop trace crashlog coredump peek grants syscalls sched\r\n";
+
+/// Usage of the `trace` command.
+const TRACE_USAGE_STR: &[u8] =
+    b"Usage: trace [status|on|off|clear|dump|process <name>|all|driver <num>|all]\r\n";
+
+/// Usage of the `crashlog` command.
+const CRASHLOG_USAGE_STR: &[u8] = b"Usage: crashlog [show|erase]\r\n";
+
+/// Number of bytes of a crash report printed at a time.
+const CRASH_REPORT_CHUNK_LEN: usize = 128;
+
+/// Number of bytes of a core dump printed on each line.
+const CORE_DUMP_LINE_LEN: usize = 64;
+
+/// Usage of the `peek` command.
+const PEEK_USAGE_STR: &[u8] = b"Usage: peek <process name> <address> [length]\r\n";
+
+/// Number of bytes printed by `peek` when no length is given.
+const PEEK_DEFAULT_LEN: usize = 64;
+
+/// Number of bytes of process memory printed on each line by `peek`.
+const PEEK_LINE_LEN: usize = 16;
+
+/// Usage of the `sched` command.
+const SCHED_USAGE_STR: &[u8] = b"Usage: sched [<parameter> <value>]\r\n";
 
 /// Escape character for ANSI escape sequences.
 const ESC: u8 = b'\x1B';
@@ -90,6 +123,37 @@ enum WriterState {
         index: isize,
         total: isize,
     },
+    TraceDump {
+        index: isize,
+        total: isize,
+        /// Whether to re-enable tracing, which is paused during the dump.
+        resume: bool,
+    },
+    CrashReport {
+        chunk: isize,
+        total: isize,
+    },
+    CoreDump {
+        process_id: ProcessId,
+        line: isize,
+        total: isize,
+    },
+    Peek {
+        process_id: ProcessId,
+        address: usize,
+        len: usize,
+        line: isize,
+        total: isize,
+    },
+    Grants {
+        process_id: ProcessId,
+        index: isize,
+        total: isize,
+    },
+    DriverStats {
+        index: isize,
+        total: isize,
+    },
 }
 
 /// Key that can be part from an escape sequence.
@@ -272,6 +336,19 @@ pub struct ProcessConsole<
     /// This capsule needs to use potentially dangerous APIs related to
     /// processes, and requires a capability to access those APIs.
     capability: C,
+
+    /// Optional system call trace controlled by the `trace` command.
+    syscall_trace: OptionalCell<&'a dyn SyscallTraceControl>,
+
+    /// Optional crash log shown by the `crashlog` command.
+    crash_log: OptionalCell<&'a dyn CrashLogControl>,
+
+    /// Optional scheduler whose parameters the `sched` command tunes.
+    scheduler: OptionalCell<&'a dyn SchedulerParameters>,
+
+    /// Faulted process whose core dump is printed once the console finished
+    /// printing.
+    pending_core_dump: OptionalCell<ProcessId>,
 }
 
 #[derive(Copy, Clone)]
@@ -433,6 +510,14 @@ impl BinaryWrite for ConsoleWriter {
     }
 }
 
+/// Parses a decimal number, or a hexadecimal one prefixed with `0x`.
+fn parse_number(number: &str) -> Option<usize> {
+    match number.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => number.parse::<usize>().ok(),
+    }
+}
+
 impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCapability>
     ProcessConsole<'a, COMMAND_HISTORY_LEN, A, C>
 {
@@ -472,9 +557,29 @@ impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCap
             kernel_addresses,
             reset_function,
             capability,
+            syscall_trace: OptionalCell::empty(),
+            crash_log: OptionalCell::empty(),
+            scheduler: OptionalCell::empty(),
+            pending_core_dump: OptionalCell::empty(),
         }
     }
 
+    /// Set the system call trace the `trace` command controls and dumps.
+    pub fn set_syscall_trace(&self, syscall_trace: &'a dyn SyscallTraceControl) {
+        self.syscall_trace.set(syscall_trace);
+    }
+
+    /// Set the crash log the `crashlog` command shows and erases.
+    pub fn set_crash_log(&self, crash_log: &'a dyn CrashLogControl) {
+        self.crash_log.set(crash_log);
+    }
+
+    /// Set the scheduler whose parameters the `sched` command shows and
+    /// changes.
+    pub fn set_scheduler(&self, scheduler: &'a dyn SchedulerParameters) {
+        self.scheduler.set(scheduler);
+    }
+
     /// Start the process console listening for user commands.
     pub fn start(&self) -> Result<(), ErrorCode> {
         if self.mode.get() == ProcessConsoleState::Off {
@@ -557,6 +662,90 @@ impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCap
                     }
                 }
             }
+            WriterState::TraceDump {
+                index,
+                total,
+                resume,
+            } => {
+                if index + 1 == total {
+                    WriterState::Empty
+                } else {
+                    WriterState::TraceDump {
+                        index: index + 1,
+                        total,
+                        resume,
+                    }
+                }
+            }
+            WriterState::CrashReport { chunk, total } => {
+                if chunk + 1 == total {
+                    WriterState::Empty
+                } else {
+                    WriterState::CrashReport {
+                        chunk: chunk + 1,
+                        total,
+                    }
+                }
+            }
+            WriterState::CoreDump {
+                process_id,
+                line,
+                total,
+            } => {
+                if line + 1 == total {
+                    WriterState::Empty
+                } else {
+                    WriterState::CoreDump {
+                        process_id,
+                        line: line + 1,
+                        total,
+                    }
+                }
+            }
+            WriterState::Peek {
+                process_id,
+                address,
+                len,
+                line,
+                total,
+            } => {
+                if line + 1 == total {
+                    WriterState::Empty
+                } else {
+                    WriterState::Peek {
+                        process_id,
+                        address,
+                        len,
+                        line: line + 1,
+                        total,
+                    }
+                }
+            }
+            WriterState::Grants {
+                process_id,
+                index,
+                total,
+            } => {
+                if index + 1 == total {
+                    WriterState::Empty
+                } else {
+                    WriterState::Grants {
+                        process_id,
+                        index: index + 1,
+                        total,
+                    }
+                }
+            }
+            WriterState::DriverStats { index, total } => {
+                if index + 1 == total {
+                    WriterState::Empty
+                } else {
+                    WriterState::DriverStats {
+                        index: index + 1,
+                        total,
+                    }
+                }
+            }
             WriterState::Empty => WriterState::Empty,
         }
     }
@@ -741,6 +930,193 @@ impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCap
                         }
                     });
             }
+            WriterState::TraceDump {
+                index,
+                total,
+                resume,
+            } => {
+                self.syscall_trace.map(|trace| {
+                    if let Some(record) = trace.record(index as usize) {
+                        let mut console_writer = ConsoleWriter::new();
+                        let _ = write(&mut console_writer, format_args!("T "));
+                        for byte in record.encode() {
+                            let _ = write(&mut console_writer, format_args!("{:02x}", byte));
+                        }
+                        let _ = write(&mut console_writer, format_args!("\r\n"));
+                        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                    }
+                    if index + 1 == total {
+                        let _ = self.write_bytes(b"End of trace.\r\n");
+                        if resume {
+                            trace.set_enabled(true);
+                        }
+                    }
+                });
+            }
+            WriterState::CrashReport { chunk, total } => {
+                self.crash_log.map(|crash_log| {
+                    let mut buf = [0; CRASH_REPORT_CHUNK_LEN];
+                    let len = crash_log
+                        .read_last_report(chunk as usize * CRASH_REPORT_CHUNK_LEN, &mut buf);
+                    let _ = self.write_bytes(&buf[..len]);
+                    if chunk + 1 == total {
+                        let _ = self.write_bytes(b"\r\nEnd of crash report.\r\n");
+                    }
+                });
+            }
+            WriterState::CoreDump {
+                process_id,
+                line,
+                total,
+            } => {
+                let mut buf = [0; CORE_DUMP_LINE_LEN];
+                let len = self.kernel.process_map_or_external(
+                    0,
+                    process_id,
+                    |process| {
+                        core_dump::read(process, line as usize * CORE_DUMP_LINE_LEN, &mut buf)
+                    },
+                    &self.capability,
+                );
+                let mut console_writer = ConsoleWriter::new();
+                let _ = write(&mut console_writer, format_args!("C "));
+                for byte in &buf[..len] {
+                    let _ = write(&mut console_writer, format_args!("{:02x}", byte));
+                }
+                let _ = write(&mut console_writer, format_args!("\r\n"));
+                if line + 1 == total {
+                    let _ = write(&mut console_writer, format_args!("End of core dump.\r\n"));
+                }
+                let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+            }
+            WriterState::Peek {
+                process_id,
+                address,
+                len,
+                line,
+                total: _,
+            } => {
+                let offset = line as usize * PEEK_LINE_LEN;
+                let line_address = address + offset;
+                let line_len = cmp::min(PEEK_LINE_LEN, len - offset);
+                let mut buf = [0; PEEK_LINE_LEN];
+                let read = self.kernel.process_map_or_external(
+                    Err(ErrorCode::NODEVICE),
+                    process_id,
+                    |process| process.read_memory(line_address, &mut buf[..line_len]),
+                    &self.capability,
+                );
+
+                let mut console_writer = ConsoleWriter::new();
+                let _ = write(&mut console_writer, format_args!("{:08x}:", line_address));
+                match read {
+                    Ok(()) => {
+                        for byte in &buf[..line_len] {
+                            let _ = write(&mut console_writer, format_args!(" {:02x}", byte));
+                        }
+                        for _ in line_len..PEEK_LINE_LEN {
+                            let _ = write(&mut console_writer, format_args!("   "));
+                        }
+                        let _ = write(&mut console_writer, format_args!("  |"));
+                        for &byte in &buf[..line_len] {
+                            let c = if byte.is_ascii_graphic() || byte == b' ' {
+                                byte as char
+                            } else {
+                                '.'
+                            };
+                            let _ = write(&mut console_writer, format_args!("{}", c));
+                        }
+                        let _ = write(&mut console_writer, format_args!("|\r\n"));
+                    }
+                    Err(_) => {
+                        // The process may have exited or moved its break
+                        // since the command was checked.
+                        let _ = write(&mut console_writer, format_args!(" unreadable\r\n"));
+                    }
+                }
+                let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+            }
+            WriterState::Grants {
+                process_id,
+                index,
+                total: _,
+            } => {
+                let info: KernelInfo = KernelInfo::new(self.kernel);
+                let (_, grants_total) = info.number_app_grant_uses(process_id, &self.capability);
+                // Find the `index`th allocated grant.
+                let allocation = self.kernel.process_map_or_external(
+                    None,
+                    process_id,
+                    |process| {
+                        (0..grants_total)
+                            .filter_map(|grant_num| process.grant_allocation(grant_num))
+                            .nth(index as usize)
+                    },
+                    &self.capability,
+                );
+
+                let mut console_writer = ConsoleWriter::new();
+                match allocation {
+                    Some(allocation) => {
+                        let _ = write(
+                            &mut console_writer,
+                            format_args!(
+                                " {:#07x}  {:#010x} {:7}  ",
+                                allocation.driver_num, allocation.address, allocation.size,
+                            ),
+                        );
+                        match driver::NUM::from_usize(allocation.driver_num) {
+                            Some(name) => {
+                                let _ = write(&mut console_writer, format_args!("{:?}\r\n", name));
+                            }
+                            None => {
+                                let _ = write(&mut console_writer, format_args!("\r\n"));
+                            }
+                        }
+                    }
+                    None => {
+                        let _ = write(
+                            &mut console_writer,
+                            format_args!(" Grant no longer allocated.\r\n"),
+                        );
+                    }
+                }
+                let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+            }
+            WriterState::DriverStats { index, total: _ } => {
+                self.syscall_trace.map(|trace| {
+                    let mut console_writer = ConsoleWriter::new();
+                    match trace.driver_stats(index as usize) {
+                        Some(stats) => {
+                            let _ = write(
+                                &mut console_writer,
+                                format_args!(
+                                    " {:#07x}  {:10}{:10}{:10}{:10}  ",
+                                    stats.driver_number,
+                                    stats.commands,
+                                    stats.subscribes,
+                                    stats.allows,
+                                    stats.failures,
+                                ),
+                            );
+                            match driver::NUM::from_u32(stats.driver_number) {
+                                Some(name) => {
+                                    let _ =
+                                        write(&mut console_writer, format_args!("{:?}\r\n", name));
+                                }
+                                None => {
+                                    let _ = write(&mut console_writer, format_args!("\r\n"));
+                                }
+                            }
+                        }
+                        None => {
+                            // The counts were cleared while being printed.
+                            let _ = write(&mut console_writer, format_args!(" Cleared.\r\n"));
+                        }
+                    }
+                    let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                });
+            }
             WriterState::Empty => {
                 self.prompt();
             }
@@ -748,6 +1124,482 @@ impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCap
         }
     }
 
+    /// Run the `trace` command, which controls and dumps the system call
+    /// trace.
+    fn trace_command(&self, trace: &dyn SyscallTraceControl, command: &str) {
+        let mut arguments = command.split_whitespace().skip(1);
+        match (arguments.next(), arguments.next()) {
+            (None, _) | (Some("status"), _) => {
+                let mut console_writer = ConsoleWriter::new();
+                let _ = write(
+                    &mut console_writer,
+                    format_args!(
+                        "Syscall trace: {}, {} records ({} overwritten)\r\n Process: ",
+                        if trace.is_enabled() { "on" } else { "off" },
+                        trace.len(),
+                        trace.overwritten(),
+                    ),
+                );
+                match trace.process_filter() {
+                    Some(processid) => {
+                        let mut name = "(exited)";
+                        self.kernel
+                            .process_each_capability(&self.capability, |process| {
+                                if process.processid() == processid {
+                                    name = process.get_process_name();
+                                }
+                            });
+                        let _ = write(
+                            &mut console_writer,
+                            format_args!("{} ({:?})", name, processid),
+                        );
+                    }
+                    None => {
+                        let _ = write(&mut console_writer, format_args!("all"));
+                    }
+                }
+                match trace.driver_filter() {
+                    Some(driver) => {
+                        let _ = write(
+                            &mut console_writer,
+                            format_args!("\r\n Driver: {:#x}\r\n", driver),
+                        );
+                    }
+                    None => {
+                        let _ = write(&mut console_writer, format_args!("\r\n Driver: all\r\n"));
+                    }
+                }
+                let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+            }
+            (Some("on"), _) => {
+                trace.set_enabled(true);
+                let _ = self.write_bytes(b"Syscall tracing enabled.\r\n");
+            }
+            (Some("off"), _) => {
+                trace.set_enabled(false);
+                let _ = self.write_bytes(b"Syscall tracing disabled.\r\n");
+            }
+            (Some("clear"), _) => {
+                trace.clear();
+                let _ = self.write_bytes(b"Syscall trace cleared.\r\n");
+            }
+            (Some("process"), Some("all")) => {
+                trace.set_process_filter(None);
+                let _ = self.write_bytes(b"Tracing all processes.\r\n");
+            }
+            (Some("process"), Some(name)) => {
+                let mut found = None;
+                self.kernel
+                    .process_each_capability(&self.capability, |process| {
+                        if found.is_none() && process.get_process_name() == name {
+                            found = Some(process.processid());
+                        }
+                    });
+                match found {
+                    Some(processid) => {
+                        trace.set_process_filter(Some(processid));
+                        let mut console_writer = ConsoleWriter::new();
+                        let _ = write(
+                            &mut console_writer,
+                            format_args!("Tracing process {} only.\r\n", name),
+                        );
+                        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                    }
+                    None => {
+                        let _ = self.write_bytes(b"No such process.\r\n");
+                    }
+                }
+            }
+            (Some("driver"), Some("all")) => {
+                trace.set_driver_filter(None);
+                let _ = self.write_bytes(b"Tracing all drivers.\r\n");
+            }
+            (Some("driver"), Some(number)) => match parse_number(number) {
+                Some(driver) => {
+                    trace.set_driver_filter(Some(driver));
+                    let mut console_writer = ConsoleWriter::new();
+                    let _ = write(
+                        &mut console_writer,
+                        format_args!("Tracing driver {:#x} only.\r\n", driver),
+                    );
+                    let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                }
+                None => {
+                    let _ = self.write_bytes(TRACE_USAGE_STR);
+                }
+            },
+            (Some("dump"), _) => {
+                // Pause tracing so that the records do not move while they
+                // are printed.
+                let resume = trace.is_enabled();
+                trace.set_enabled(false);
+
+                let total = trace.len();
+                let mut console_writer = ConsoleWriter::new();
+                let _ = write(
+                    &mut console_writer,
+                    format_args!(
+                        "Syscall trace: {} records ({} overwritten), {} Hz\r\n",
+                        total,
+                        trace.overwritten(),
+                        trace.timestamp_frequency(),
+                    ),
+                );
+                let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+
+                if total > 0 {
+                    // Start the state machine to print each record separately.
+                    self.write_state(WriterState::TraceDump {
+                        index: -1,
+                        total: total as isize,
+                        resume,
+                    });
+                } else {
+                    let _ = self.write_bytes(b"End of trace.\r\n");
+                    trace.set_enabled(resume);
+                }
+            }
+            _ => {
+                let _ = self.write_bytes(TRACE_USAGE_STR);
+            }
+        }
+    }
+
+    /// Run the `crashlog` command, which shows and erases the saved crash
+    /// reports.
+    fn crash_log_command(&self, crash_log: &dyn CrashLogControl, command: &str) {
+        let mut arguments = command.split_whitespace().skip(1);
+        match arguments.next() {
+            None | Some("show") => {
+                let mut console_writer = ConsoleWriter::new();
+                let _ = write(
+                    &mut console_writer,
+                    format_args!("Crash reports saved: {}\r\n", crash_log.report_count()),
+                );
+                let report = crash_log.last_report();
+                if let Some(info) = report {
+                    let _ = write(
+                        &mut console_writer,
+                        format_args!(
+                            "Newest report ({}{}):\r\n",
+                            match info.kind {
+                                CrashKind::KernelPanic => "kernel panic",
+                                CrashKind::ProcessFault => "process fault",
+                            },
+                            if info.truncated { ", truncated" } else { "" },
+                        ),
+                    );
+                }
+                let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+
+                match report {
+                    Some(info) if info.len > 0 => {
+                        // Start the state machine to print the report in
+                        // chunks.
+                        self.write_state(WriterState::CrashReport {
+                            chunk: -1,
+                            total: info.len.div_ceil(CRASH_REPORT_CHUNK_LEN) as isize,
+                        });
+                    }
+                    Some(_) => {}
+                    None => {
+                        let _ = self.write_bytes(b"No crash report available.\r\n");
+                    }
+                }
+            }
+            Some("erase") => match crash_log.erase() {
+                Ok(()) => {
+                    let _ = self.write_bytes(b"Erasing crash reports.\r\n");
+                }
+                Err(_) => {
+                    let _ = self.write_bytes(b"Crash log busy, try again later.\r\n");
+                }
+            },
+            _ => {
+                let _ = self.write_bytes(CRASHLOG_USAGE_STR);
+            }
+        }
+    }
+
+    /// Run the `coredump` command, which prints the core dump of a process.
+    fn core_dump_command(&self, command: &str) {
+        let name = match command.split_whitespace().nth(1) {
+            Some(name) => name,
+            None => {
+                let _ = self.write_bytes(b"Usage: coredump <process name>\r\n");
+                return;
+            }
+        };
+
+        let mut found = None;
+        self.kernel
+            .process_each_capability(&self.capability, |process| {
+                if found.is_none() && process.get_process_name() == name {
+                    found = Some((
+                        process.processid(),
+                        core_dump::len(process),
+                        process.get_state(),
+                    ));
+                }
+            });
+
+        match found {
+            Some((process_id, len, state)) => {
+                if let State::Running | State::Yielded | State::YieldedFor(_) = state {
+                    let _ =
+                        self.write_bytes(b"Process is running, the dump may be inconsistent.\r\n");
+                }
+                self.start_core_dump(process_id, name, len);
+            }
+            None => {
+                let _ = self.write_bytes(b"No such process.\r\n");
+            }
+        }
+    }
+
+    /// Print the core dump of `len` bytes of the process `process_id`, named
+    /// `name`.
+    fn start_core_dump(&self, process_id: ProcessId, name: &str, len: usize) {
+        let mut console_writer = ConsoleWriter::new();
+        let _ = write(
+            &mut console_writer,
+            format_args!("Core dump of {}: {} bytes\r\n", name, len),
+        );
+        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+
+        // Start the state machine to print the dump one line at a time.
+        self.write_state(WriterState::CoreDump {
+            process_id,
+            line: -1,
+            total: len.div_ceil(CORE_DUMP_LINE_LEN) as isize,
+        });
+    }
+
+    /// Print the core dump of a faulted process that was held back while the
+    /// console was printing.
+    fn start_pending_core_dump(&self) {
+        self.pending_core_dump.take().map(|process_id| {
+            self.kernel.process_map_or_external(
+                (),
+                process_id,
+                |process| {
+                    self.start_core_dump(
+                        process_id,
+                        process.get_process_name(),
+                        core_dump::len(process),
+                    );
+                },
+                &self.capability,
+            )
+        });
+    }
+
+    /// Run the `peek` command, which prints a range of the memory of a
+    /// process.
+    fn peek_command(&self, command: &str) {
+        let mut arguments = command.split_whitespace().skip(1);
+        let (name, address, len) = match (
+            arguments.next(),
+            arguments.next().and_then(parse_number),
+            arguments
+                .next()
+                .map_or(Some(PEEK_DEFAULT_LEN), parse_number),
+        ) {
+            (Some(name), Some(address), Some(len)) if len > 0 => (name, address, len),
+            _ => {
+                let _ = self.write_bytes(PEEK_USAGE_STR);
+                return;
+            }
+        };
+
+        let mut found = None;
+        self.kernel
+            .process_each_capability(&self.capability, |process| {
+                if found.is_none() && process.get_process_name() == name {
+                    found = Some((process.processid(), process.get_addresses()));
+                }
+            });
+        let (process_id, addresses) = match found {
+            Some(found) => found,
+            None => {
+                let _ = self.write_bytes(b"No such process.\r\n");
+                return;
+            }
+        };
+
+        // Only the memory the process can access may be printed.
+        let in_range = |start: usize, end: usize| {
+            address >= start && address.checked_add(len).map_or(false, |last| last <= end)
+        };
+        if !in_range(addresses.sram_start, addresses.sram_app_brk)
+            && !in_range(addresses.flash_start, addresses.flash_end)
+        {
+            let mut console_writer = ConsoleWriter::new();
+            let _ = write(
+                &mut console_writer,
+                format_args!(
+                    "Range outside of the process memory: RAM {:#010x}-{:#010x}, flash {:#010x}-{:#010x}\r\n",
+                    addresses.sram_start,
+                    addresses.sram_app_brk,
+                    addresses.flash_start,
+                    addresses.flash_end,
+                ),
+            );
+            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+            return;
+        }
+
+        // Start the state machine to print one line at a time.
+        self.write_state(WriterState::Peek {
+            process_id,
+            address,
+            len,
+            line: -1,
+            total: len.div_ceil(PEEK_LINE_LEN) as isize,
+        });
+    }
+
+    /// Run the `grants` command, which lists the grants a process has
+    /// allocated.
+    fn grants_command(&self, command: &str) {
+        let name = match command.split_whitespace().nth(1) {
+            Some(name) => name,
+            None => {
+                let _ = self.write_bytes(b"Usage: grants <process name>\r\n");
+                return;
+            }
+        };
+
+        let mut found = None;
+        self.kernel
+            .process_each_capability(&self.capability, |process| {
+                if found.is_none() && process.get_process_name() == name {
+                    found = Some((process.processid(), process.get_addresses()));
+                }
+            });
+        let (process_id, addresses) = match found {
+            Some(found) => found,
+            None => {
+                let _ = self.write_bytes(b"No such process.\r\n");
+                return;
+            }
+        };
+
+        let info: KernelInfo = KernelInfo::new(self.kernel);
+        let (grants_used, grants_total) = info.number_app_grant_uses(process_id, &self.capability);
+        let mut console_writer = ConsoleWriter::new();
+        let _ = write(
+            &mut console_writer,
+            format_args!(
+                "Grants of {}: {}/{} allocated, grant region {:#010x}-{:#010x} ({} bytes)\r\n",
+                name,
+                grants_used,
+                grants_total,
+                addresses.sram_grant_start,
+                addresses.sram_end,
+                addresses.sram_end - addresses.sram_grant_start,
+            ),
+        );
+        if grants_used > 0 {
+            let _ = write(
+                &mut console_writer,
+                format_args!(" Driver   Address       Size  Name\r\n"),
+            );
+        }
+        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+
+        if grants_used > 0 {
+            // Start the state machine to print each grant separately.
+            self.write_state(WriterState::Grants {
+                process_id,
+                index: -1,
+                total: grants_used as isize,
+            });
+        }
+    }
+
+    /// Run the `syscalls` command, which prints or clears the number of
+    /// system calls made to each driver.
+    fn syscalls_command(&self, trace: &dyn SyscallTraceControl, command: &str) {
+        match command.split_whitespace().nth(1) {
+            None => {
+                let mut total = 0;
+                while trace.driver_stats(total).is_some() {
+                    total += 1;
+                }
+                if total == 0 {
+                    let _ = self.write_bytes(b"No system calls to drivers counted.\r\n");
+                    return;
+                }
+                let _ = self
+                    .write_bytes(b" Driver     Commands Subscribes    Allows  Failures  Name\r\n");
+
+                // Start the state machine to print each driver separately.
+                self.write_state(WriterState::DriverStats {
+                    index: -1,
+                    total: total as isize,
+                });
+            }
+            Some("clear") => {
+                trace.clear_driver_stats();
+                let _ = self.write_bytes(b"System call counts cleared.\r\n");
+            }
+            _ => {
+                let _ = self.write_bytes(b"Usage: syscalls [clear]\r\n");
+            }
+        }
+    }
+
+    /// Run the `sched` command, which shows and changes the scheduler
+    /// parameters.
+    fn sched_command(&self, scheduler: &dyn SchedulerParameters, command: &str) {
+        let mut arguments = command.split_whitespace().skip(1);
+        match (arguments.next(), arguments.next().map(parse_number)) {
+            (None, _) => {
+                let mut console_writer = ConsoleWriter::new();
+                let mut index = 0;
+                while let Some((name, value)) = scheduler.parameter(index) {
+                    let _ = write(
+                        &mut console_writer,
+                        format_args!(" {}: {}\r\n", name, value),
+                    );
+                    index += 1;
+                }
+                if index == 0 {
+                    let _ = write(
+                        &mut console_writer,
+                        format_args!("The scheduler has no parameters.\r\n"),
+                    );
+                }
+                let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+            }
+            (Some(name), Some(Some(value))) => {
+                let result = u32::try_from(value)
+                    .map_err(|_| ErrorCode::INVAL)
+                    .and_then(|value| scheduler.set_parameter(name, value));
+                let mut console_writer = ConsoleWriter::new();
+                let _ = match result {
+                    Ok(()) => write(
+                        &mut console_writer,
+                        format_args!("Set {} to {}.\r\n", name, value),
+                    ),
+                    Err(ErrorCode::NOSUPPORT) => write(
+                        &mut console_writer,
+                        format_args!("No scheduler parameter {}.\r\n", name),
+                    ),
+                    Err(_) => write(
+                        &mut console_writer,
+                        format_args!("Invalid value for {}.\r\n", name),
+                    ),
+                };
+                let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+            }
+            _ => {
+                let _ = self.write_bytes(SCHED_USAGE_STR);
+            }
+        }
+    }
+
     // Process the command in the command buffer and clear the buffer.
     fn read_command(&self) {
         self.command_buffer.map(|command| {
@@ -994,6 +1846,44 @@ impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCap
                                     f();
                                 },
                             );
+                        } else if clean_str.starts_with("trace") {
+                            self.syscall_trace.map_or_else(
+                                || {
+                                    let _ =
+                                        self.write_bytes(b"Syscall tracing is not available.\r\n");
+                                },
+                                |trace| self.trace_command(trace, clean_str),
+                            );
+                        } else if clean_str.starts_with("coredump") {
+                            self.core_dump_command(clean_str);
+                        } else if clean_str.starts_with("crashlog") {
+                            self.crash_log.map_or_else(
+                                || {
+                                    let _ = self.write_bytes(b"Crash log is not available.\r\n");
+                                },
+                                |crash_log| self.crash_log_command(crash_log, clean_str),
+                            );
+                        } else if clean_str.starts_with("peek") {
+                            self.peek_command(clean_str);
+                        } else if clean_str.starts_with("grants") {
+                            self.grants_command(clean_str);
+                        } else if clean_str.starts_with("syscalls") {
+                            self.syscall_trace.map_or_else(
+                                || {
+                                    let _ = self
+                                        .write_bytes(b"System call counts are not available.\r\n");
+                                },
+                                |trace| self.syscalls_command(trace, clean_str),
+                            );
+                        } else if clean_str.starts_with("sched") {
+                            self.scheduler.map_or_else(
+                                || {
+                                    let _ = self.write_bytes(
+                                        b"Scheduler parameters are not available.\r\n",
+                                    );
+                                },
+                                |scheduler| self.sched_command(scheduler, clean_str),
+                            );
                         } else if clean_str.starts_with("panic") {
                             panic!("Process Console forced a kernel panic.");
                         } else {
@@ -1122,6 +2012,28 @@ impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCap
     }
 }
 
+/// Prints the core dump of faulted processes, in the format of the `coredump`
+/// command, while the console is active.
+impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCapability>
+    CoreDumpWriter for ProcessConsole<'a, COMMAND_HISTORY_LEN, A, C>
+{
+    fn write_core_dump(&self, process: &dyn Process) {
+        if self.mode.get() != ProcessConsoleState::Active {
+            return;
+        }
+        if self.writer_state.get() != WriterState::Empty || self.tx_in_progress.get() {
+            // Only one dump is held back; a newer fault replaces it.
+            self.pending_core_dump.set(process.processid());
+            return;
+        }
+        self.start_core_dump(
+            process.processid(),
+            process.get_process_name(),
+            core_dump::len(process),
+        );
+    }
+}
+
 impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCapability> AlarmClient
     for ProcessConsole<'a, COMMAND_HISTORY_LEN, A, C>
 {
@@ -1164,6 +2076,8 @@ impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCap
             if self.execute.get() {
                 self.execute.set(false);
                 self.read_command();
+            } else {
+                self.start_pending_core_dump();
             }
         }
     }
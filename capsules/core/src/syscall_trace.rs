@@ -0,0 +1,223 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Records system calls in a ring buffer at runtime.
+//!
+//! `SyscallTrace` is registered with the kernel as its
+//! [`SyscallTracer`]. While enabled, it keeps the most recent system calls
+//! as [`SyscallRecord`]s, overwriting the oldest ones when the buffer is
+//! full. Recording can be restricted to one process and to one driver. The
+//! records are accessed through [`SyscallTraceControl`], which the process
+//! console uses to control the trace and dump it for decoding on the host
+//! with `tools/syscall_trace_decode.py`.
+//!
+//! Tracing starts disabled, so an idle trace only costs a check per system
+//! call. Independently of tracing, `SyscallTrace` counts the system calls
+//! made to each driver, for as many drivers as it has
+//! [`DriverSyscallStats`] entries. Calls to drivers beyond those are not
+//! counted.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let syscall_trace = components::syscall_trace::SyscallTraceComponent::new(
+//!     board_kernel,
+//!     &peripherals.rtc,
+//! )
+//! .finalize(components::syscall_trace_component_static!(nrf52840::rtc::Rtc, 64, 16));
+//! process_console.set_syscall_trace(syscall_trace);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::time::{Frequency, Ticks, Time};
+use kernel::syscall::{Syscall, SyscallReturn};
+use kernel::syscall_trace::{
+    DriverSyscallStats, SyscallRecord, SyscallTraceControl, SyscallTracer,
+};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ProcessId;
+
+pub struct SyscallTrace<'a, T: Time> {
+    time: &'a T,
+    records: TakeCell<'static, [SyscallRecord]>,
+    /// Index of the oldest record.
+    head: Cell<usize>,
+    len: Cell<usize>,
+    overwritten: Cell<usize>,
+    enabled: Cell<bool>,
+    process_filter: OptionalCell<ProcessId>,
+    driver_filter: OptionalCell<usize>,
+    driver_stats: TakeCell<'static, [DriverSyscallStats]>,
+    /// Number of drivers in `driver_stats`.
+    drivers_counted: Cell<usize>,
+}
+
+impl<'a, T: Time> SyscallTrace<'a, T> {
+    pub fn new(
+        time: &'a T,
+        records: &'static mut [SyscallRecord],
+        driver_stats: &'static mut [DriverSyscallStats],
+    ) -> Self {
+        Self {
+            time,
+            records: TakeCell::new(records),
+            head: Cell::new(0),
+            len: Cell::new(0),
+            overwritten: Cell::new(0),
+            enabled: Cell::new(false),
+            process_filter: OptionalCell::empty(),
+            driver_filter: OptionalCell::empty(),
+            driver_stats: TakeCell::new(driver_stats),
+            drivers_counted: Cell::new(0),
+        }
+    }
+
+    fn count(&self, syscall: &Syscall, result: Option<&SyscallReturn>) {
+        let driver_number = match syscall.driver_number() {
+            Some(driver_number) => driver_number as u32,
+            None => return,
+        };
+        self.driver_stats.map(|driver_stats| {
+            let counted = self.drivers_counted.get();
+            let index = match driver_stats[..counted]
+                .iter()
+                .position(|stats| stats.driver_number == driver_number)
+            {
+                Some(index) => index,
+                None if counted < driver_stats.len() => {
+                    driver_stats[counted] = DriverSyscallStats {
+                        driver_number,
+                        ..Default::default()
+                    };
+                    self.drivers_counted.set(counted + 1);
+                    counted
+                }
+                None => return,
+            };
+
+            let stats = &mut driver_stats[index];
+            let counter = match syscall {
+                Syscall::Subscribe { .. } => &mut stats.subscribes,
+                Syscall::Command { .. } => &mut stats.commands,
+                _ => &mut stats.allows,
+            };
+            *counter = counter.saturating_add(1);
+            if result.map_or(false, |result| !result.is_success()) {
+                stats.failures = stats.failures.saturating_add(1);
+            }
+        });
+    }
+
+    fn push(&self, record: SyscallRecord) {
+        self.records.map(|records| {
+            if records.is_empty() {
+                return;
+            }
+            let len = self.len.get();
+            let index = (self.head.get() + len) % records.len();
+            records[index] = record;
+            if len == records.len() {
+                // The oldest record was overwritten.
+                self.head.set((self.head.get() + 1) % records.len());
+                self.overwritten.set(self.overwritten.get() + 1);
+            } else {
+                self.len.set(len + 1);
+            }
+        });
+    }
+}
+
+impl<T: Time> SyscallTracer for SyscallTrace<'_, T> {
+    fn trace_syscall(
+        &self,
+        processid: ProcessId,
+        syscall: &Syscall,
+        result: Option<&SyscallReturn>,
+    ) {
+        self.count(syscall, result);
+
+        if !self.enabled.get() {
+            return;
+        }
+        if self
+            .process_filter
+            .map_or(false, |process| process != processid)
+        {
+            return;
+        }
+        if self
+            .driver_filter
+            .map_or(false, |driver| syscall.driver_number() != Some(driver))
+        {
+            return;
+        }
+        let timestamp = self.time.now().into_u32();
+        self.push(SyscallRecord::new(timestamp, processid, syscall, result));
+    }
+}
+
+impl<T: Time> SyscallTraceControl for SyscallTrace<'_, T> {
+    fn set_enabled(&self, enabled: bool) {
+        self.enabled.set(enabled);
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled.get()
+    }
+
+    fn set_process_filter(&self, process: Option<ProcessId>) {
+        self.process_filter.insert(process);
+    }
+
+    fn process_filter(&self) -> Option<ProcessId> {
+        self.process_filter.get()
+    }
+
+    fn set_driver_filter(&self, driver_number: Option<usize>) {
+        self.driver_filter.insert(driver_number);
+    }
+
+    fn driver_filter(&self) -> Option<usize> {
+        self.driver_filter.get()
+    }
+
+    fn clear(&self) {
+        self.head.set(0);
+        self.len.set(0);
+        self.overwritten.set(0);
+    }
+
+    fn len(&self) -> usize {
+        self.len.get()
+    }
+
+    fn overwritten(&self) -> usize {
+        self.overwritten.get()
+    }
+
+    fn record(&self, index: usize) -> Option<SyscallRecord> {
+        if index >= self.len.get() {
+            return None;
+        }
+        self.records
+            .map(|records| records[(self.head.get() + index) % records.len()])
+    }
+
+    fn timestamp_frequency(&self) -> u32 {
+        T::Frequency::frequency()
+    }
+
+    fn driver_stats(&self, index: usize) -> Option<DriverSyscallStats> {
+        if index >= self.drivers_counted.get() {
+            return None;
+        }
+        self.driver_stats.map(|driver_stats| driver_stats[index])
+    }
+
+    fn clear_driver_stats(&self) {
+        self.drivers_counted.set(0);
+    }
+}
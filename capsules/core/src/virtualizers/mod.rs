@@ -7,6 +7,7 @@ pub mod virtual_aes_ccm;
 pub mod virtual_alarm;
 pub mod virtual_flash;
 pub mod virtual_i2c;
+pub mod virtual_process_loading;
 pub mod virtual_pwm;
 pub mod virtual_rng;
 pub mod virtual_spi;
@@ -0,0 +1,123 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Virtualizer for loading processes while the kernel is running.
+//!
+//! This lets several capsules, for example the userspace app loader and the
+//! UART app loader, share the `DynamicProcessLoading` interface of the
+//! kernel's process loader. The process loader handles one process binary at
+//! a time and returns `BUSY` to any other request meanwhile, so requests are
+//! not queued. The outcome of loading a process binary is only signaled to
+//! the user that requested it.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let mux = static_init!(MuxDynamicProcessLoading<'static>, MuxDynamicProcessLoading::new(loader));
+//! loader.set_load_client(mux);
+//!
+//! let virtual_loader = static_init!(
+//!     VirtualDynamicProcessLoading<'static>,
+//!     VirtualDynamicProcessLoading::new(mux)
+//! );
+//! virtual_loader.setup();
+//! virtual_loader.set_load_client(app_loader);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::collections::list::{List, ListLink, ListNode};
+use kernel::process::{DynamicProcessLoading, DynamicProcessLoadingClient, ProcessLoadError};
+use kernel::utilities::cells::OptionalCell;
+use kernel::{ErrorCode, ProcessId};
+
+/// Shares the process loader between several users.
+pub struct MuxDynamicProcessLoading<'a> {
+    loader: &'a dyn DynamicProcessLoading<'a>,
+    users: List<'a, VirtualDynamicProcessLoading<'a>>,
+}
+
+impl<'a> MuxDynamicProcessLoading<'a> {
+    pub const fn new(loader: &'a dyn DynamicProcessLoading<'a>) -> MuxDynamicProcessLoading<'a> {
+        MuxDynamicProcessLoading {
+            loader,
+            users: List::new(),
+        }
+    }
+}
+
+impl DynamicProcessLoadingClient for MuxDynamicProcessLoading<'_> {
+    fn load_done(&self, result: Result<(), ProcessLoadError>) {
+        self.users
+            .iter()
+            .find(|user| user.loading.get())
+            .map(|user| {
+                user.loading.set(false);
+                user.client.map(|client| client.load_done(result));
+            });
+    }
+}
+
+/// A user of the process loader.
+pub struct VirtualDynamicProcessLoading<'a> {
+    mux: &'a MuxDynamicProcessLoading<'a>,
+    next: ListLink<'a, VirtualDynamicProcessLoading<'a>>,
+    client: OptionalCell<&'a dyn DynamicProcessLoadingClient>,
+    /// Whether the process loader is loading a process binary for this user.
+    loading: Cell<bool>,
+}
+
+impl<'a> ListNode<'a, VirtualDynamicProcessLoading<'a>> for VirtualDynamicProcessLoading<'a> {
+    fn next(&self) -> &'a ListLink<VirtualDynamicProcessLoading<'a>> {
+        &self.next
+    }
+}
+
+impl<'a> VirtualDynamicProcessLoading<'a> {
+    pub const fn new(mux: &'a MuxDynamicProcessLoading<'a>) -> VirtualDynamicProcessLoading<'a> {
+        VirtualDynamicProcessLoading {
+            mux,
+            next: ListLink::empty(),
+            client: OptionalCell::empty(),
+            loading: Cell::new(false),
+        }
+    }
+
+    /// Call this method immediately after new() to link this to the mux,
+    /// otherwise the outcome of loading process binaries is not signaled.
+    pub fn setup(&'a self) {
+        self.mux.users.push_head(self);
+    }
+}
+
+impl<'a> DynamicProcessLoading<'a> for VirtualDynamicProcessLoading<'a> {
+    fn set_load_client(&self, client: &'a dyn DynamicProcessLoadingClient) {
+        self.client.set(client);
+    }
+
+    fn free_flash(&self, length: usize) -> Result<&'static [u8], ErrorCode> {
+        self.mux.loader.free_flash(length)
+    }
+
+    fn load_new_process_binary(&self, address: usize) -> Result<(), ErrorCode> {
+        self.mux.loader.load_new_process_binary(address)?;
+        self.loading.set(true);
+        Ok(())
+    }
+
+    fn upgrade_process(
+        &self,
+        processid: ProcessId,
+        address: usize,
+    ) -> Result<(usize, usize), ErrorCode> {
+        let binary = self.mux.loader.upgrade_process(processid, address)?;
+        self.loading.set(true);
+        Ok(binary)
+    }
+
+    fn unload_process(&self, processid: ProcessId) -> Result<(usize, usize), ErrorCode> {
+        self.mux.loader.unload_process(processid)
+    }
+}
@@ -0,0 +1,423 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Software implementation of AES-128.
+//!
+//! Provides `AES128` with the CTR, CBC and ECB modes for chips without an AES
+//! peripheral, so that capsules such as `virtual_aes_ccm` can be used on any
+//! board. Encryption and decryption are performed in a deferred call, after
+//! which the client's `crypt_done()` is called.
+//!
+//! The implementation does not use lookup tables, whose access patterns would
+//! leak the key through the cache or timing. The S-box is instead computed as
+//! the inverse in GF(2^8) followed by the affine transformation, on the four
+//! bytes of a column at once, so the execution time does not depend on the key
+//! or the data.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let aes = static_init!(
+//!     capsules_extra::aes128::Aes128Software<'static>,
+//!     capsules_extra::aes128::Aes128Software::new()
+//! );
+//! kernel::deferred_call::DeferredCallClient::register(aes);
+//! ```
+
+use core::cell::Cell;
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::symmetric_encryption::{
+    AES128Ctr, Client, AES128, AES128CBC, AES128ECB, AES128_BLOCK_SIZE, AES128_KEY_SIZE,
+};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+const ROUNDS: usize = 10;
+const ROUND_KEY_WORDS: usize = 4 * (ROUNDS + 1);
+
+/// Multiplies each byte of `x` by `x` in GF(2^8).
+fn xtime(x: u32) -> u32 {
+    ((x & 0x7f7f7f7f) << 1) ^ (((x >> 7) & 0x01010101) * 0x1b)
+}
+
+/// Multiplies each byte of `a` by the corresponding byte of `b` in GF(2^8).
+fn gmul(mut a: u32, mut b: u32) -> u32 {
+    let mut result = 0;
+    for _ in 0..8 {
+        // Each byte of the mask is 0xff if the low bit of the byte of `b` is
+        // set, and 0 otherwise.
+        result ^= a & ((b & 0x01010101) * 0xff);
+        a = xtime(a);
+        b >>= 1;
+    }
+    result
+}
+
+/// Inverts each byte of `x` in GF(2^8), computing `x^254` so that 0 maps to 0.
+fn ginv(x: u32) -> u32 {
+    let x2 = gmul(x, x);
+    let x3 = gmul(x2, x);
+    let x6 = gmul(x3, x3);
+    let x12 = gmul(x6, x6);
+    let x15 = gmul(x12, x3);
+    let x30 = gmul(x15, x15);
+    let x60 = gmul(x30, x30);
+    let x120 = gmul(x60, x60);
+    let x240 = gmul(x120, x120);
+    let x252 = gmul(x240, x12);
+    gmul(x252, x2)
+}
+
+/// Rotates each byte of `x` left by `n` bits, for `n` in `1..8`.
+fn rotl_bytes(x: u32, n: u32) -> u32 {
+    let high = 0x01010101 * ((0xff << n) & 0xff);
+    let low = 0x01010101 * (0xff >> (8 - n));
+    ((x << n) & high) | ((x >> (8 - n)) & low)
+}
+
+/// Applies the S-box to each byte of `x`.
+fn sub_word(x: u32) -> u32 {
+    let inv = ginv(x);
+    inv ^ rotl_bytes(inv, 1)
+        ^ rotl_bytes(inv, 2)
+        ^ rotl_bytes(inv, 3)
+        ^ rotl_bytes(inv, 4)
+        ^ 0x63636363
+}
+
+/// Applies the inverse S-box to each byte of `x`.
+fn inv_sub_word(x: u32) -> u32 {
+    ginv(rotl_bytes(x, 1) ^ rotl_bytes(x, 3) ^ rotl_bytes(x, 6) ^ 0x05050505)
+}
+
+/// MixColumns on a column whose first byte is the least significant one.
+fn mix_column(a: u32) -> u32 {
+    let b = xtime(a);
+    b ^ (a ^ b).rotate_right(8) ^ a.rotate_right(16) ^ a.rotate_right(24)
+}
+
+/// InvMixColumns, computed as a multiplication by `4x^2 + 5` followed by
+/// MixColumns.
+fn inv_mix_column(a: u32) -> u32 {
+    mix_column(a ^ xtime(xtime(a ^ a.rotate_right(16))))
+}
+
+/// ShiftRows: row `r` of column `c` comes from column `c + r`.
+fn shift_rows(s: &[u32; 4]) -> [u32; 4] {
+    let mut out = [0; 4];
+    for (c, o) in out.iter_mut().enumerate() {
+        *o = (s[c] & 0x000000ff)
+            | (s[(c + 1) % 4] & 0x0000ff00)
+            | (s[(c + 2) % 4] & 0x00ff0000)
+            | (s[(c + 3) % 4] & 0xff000000);
+    }
+    out
+}
+
+/// InvShiftRows: row `r` of column `c` comes from column `c - r`.
+fn inv_shift_rows(s: &[u32; 4]) -> [u32; 4] {
+    let mut out = [0; 4];
+    for (c, o) in out.iter_mut().enumerate() {
+        *o = (s[c] & 0x000000ff)
+            | (s[(c + 3) % 4] & 0x0000ff00)
+            | (s[(c + 2) % 4] & 0x00ff0000)
+            | (s[(c + 1) % 4] & 0xff000000);
+    }
+    out
+}
+
+fn expand_key(key: &[u8; AES128_KEY_SIZE]) -> [u32; ROUND_KEY_WORDS] {
+    let mut w = [0; ROUND_KEY_WORDS];
+    for (i, chunk) in key.chunks_exact(4).enumerate() {
+        w[i] = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+    let mut rcon = 1;
+    for i in 4..ROUND_KEY_WORDS {
+        let mut t = w[i - 1];
+        if i % 4 == 0 {
+            t = sub_word(t.rotate_right(8)) ^ rcon;
+            rcon = xtime(rcon);
+        }
+        w[i] = w[i - 4] ^ t;
+    }
+    w
+}
+
+fn load_block(block: &[u8]) -> [u32; 4] {
+    let mut s = [0; 4];
+    for (c, chunk) in block.chunks_exact(4).enumerate() {
+        s[c] = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+    s
+}
+
+fn store_block(s: &[u32; 4], block: &mut [u8; AES128_BLOCK_SIZE]) {
+    for (c, chunk) in block.chunks_exact_mut(4).enumerate() {
+        chunk.copy_from_slice(&s[c].to_le_bytes());
+    }
+}
+
+fn add_round_key(s: &mut [u32; 4], round_key: &[u32]) {
+    for (c, k) in s.iter_mut().zip(round_key.iter()) {
+        *c ^= k;
+    }
+}
+
+fn encrypt_block(round_keys: &[u32; ROUND_KEY_WORDS], block: &mut [u8; AES128_BLOCK_SIZE]) {
+    let mut s = load_block(block);
+    add_round_key(&mut s, &round_keys[..4]);
+    for round in 1..=ROUNDS {
+        for c in s.iter_mut() {
+            *c = sub_word(*c);
+        }
+        s = shift_rows(&s);
+        if round != ROUNDS {
+            for c in s.iter_mut() {
+                *c = mix_column(*c);
+            }
+        }
+        add_round_key(&mut s, &round_keys[4 * round..4 * round + 4]);
+    }
+    store_block(&s, block);
+}
+
+fn decrypt_block(round_keys: &[u32; ROUND_KEY_WORDS], block: &mut [u8; AES128_BLOCK_SIZE]) {
+    let mut s = load_block(block);
+    add_round_key(&mut s, &round_keys[4 * ROUNDS..]);
+    for round in (0..ROUNDS).rev() {
+        s = inv_shift_rows(&s);
+        for c in s.iter_mut() {
+            *c = inv_sub_word(*c);
+        }
+        add_round_key(&mut s, &round_keys[4 * round..4 * round + 4]);
+        if round != 0 {
+            for c in s.iter_mut() {
+                *c = inv_mix_column(*c);
+            }
+        }
+    }
+    store_block(&s, block);
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Ctr,
+    Cbc,
+    Ecb,
+}
+
+pub struct Aes128Software<'a> {
+    client: OptionalCell<&'a dyn Client<'a>>,
+    round_keys: Cell<[u32; ROUND_KEY_WORDS]>,
+    iv: Cell<[u8; AES128_BLOCK_SIZE]>,
+    /// The counter in CTR mode, or the previous ciphertext block in CBC mode.
+    chain: Cell<[u8; AES128_BLOCK_SIZE]>,
+    mode: Cell<Mode>,
+    encrypting: Cell<bool>,
+
+    source: TakeCell<'static, [u8]>,
+    dest: TakeCell<'static, [u8]>,
+    start_index: Cell<usize>,
+    stop_index: Cell<usize>,
+
+    deferred_call: DeferredCall,
+}
+
+impl<'a> Aes128Software<'a> {
+    pub fn new() -> Self {
+        Self {
+            client: OptionalCell::empty(),
+            round_keys: Cell::new([0; ROUND_KEY_WORDS]),
+            iv: Cell::new([0; AES128_BLOCK_SIZE]),
+            chain: Cell::new([0; AES128_BLOCK_SIZE]),
+            mode: Cell::new(Mode::Ctr),
+            encrypting: Cell::new(true),
+            source: TakeCell::empty(),
+            dest: TakeCell::empty(),
+            start_index: Cell::new(0),
+            stop_index: Cell::new(0),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    pub fn busy(&self) -> bool {
+        self.dest.is_some()
+    }
+
+    fn set_mode(&self, mode: Mode, encrypting: bool) -> Result<(), ErrorCode> {
+        if self.busy() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.mode.set(mode);
+        self.encrypting.set(encrypting);
+        Ok(())
+    }
+
+    /// Transforms one block in the current mode, updating the chaining value.
+    fn crypt_block(
+        &self,
+        round_keys: &[u32; ROUND_KEY_WORDS],
+        block: &mut [u8; AES128_BLOCK_SIZE],
+    ) {
+        let mut chain = self.chain.get();
+        match self.mode.get() {
+            Mode::Ctr => {
+                let mut keystream = chain;
+                encrypt_block(round_keys, &mut keystream);
+                for (b, k) in block.iter_mut().zip(keystream.iter()) {
+                    *b ^= k;
+                }
+                // Increment the counter as a 128-bit big-endian number.
+                let mut carry = 1;
+                for byte in chain.iter_mut().rev() {
+                    let sum = *byte as u16 + carry;
+                    *byte = sum as u8;
+                    carry = sum >> 8;
+                }
+            }
+            Mode::Cbc => {
+                if self.encrypting.get() {
+                    for (b, c) in block.iter_mut().zip(chain.iter()) {
+                        *b ^= c;
+                    }
+                    encrypt_block(round_keys, block);
+                    chain = *block;
+                } else {
+                    let ciphertext = *block;
+                    decrypt_block(round_keys, block);
+                    for (b, c) in block.iter_mut().zip(chain.iter()) {
+                        *b ^= c;
+                    }
+                    chain = ciphertext;
+                }
+            }
+            Mode::Ecb => {
+                if self.encrypting.get() {
+                    encrypt_block(round_keys, block);
+                } else {
+                    decrypt_block(round_keys, block);
+                }
+            }
+        }
+        self.chain.set(chain);
+    }
+
+    fn do_crypt(&self, source: Option<&[u8]>, dest: &mut [u8]) {
+        let round_keys = self.round_keys.get();
+        let start = self.start_index.get();
+        let stop = self.stop_index.get();
+
+        for offset in (0..stop - start).step_by(AES128_BLOCK_SIZE) {
+            let mut block = [0; AES128_BLOCK_SIZE];
+            let input = match source {
+                Some(source) => &source[offset..offset + AES128_BLOCK_SIZE],
+                None => &dest[start + offset..start + offset + AES128_BLOCK_SIZE],
+            };
+            block.copy_from_slice(input);
+            self.crypt_block(&round_keys, &mut block);
+            dest[start + offset..start + offset + AES128_BLOCK_SIZE].copy_from_slice(&block);
+        }
+    }
+}
+
+impl<'a> AES128<'a> for Aes128Software<'a> {
+    fn enable(&self) {}
+
+    fn disable(&self) {}
+
+    fn set_client(&'a self, client: &'a dyn Client<'a>) {
+        self.client.set(client);
+    }
+
+    fn set_key(&self, key: &[u8]) -> Result<(), ErrorCode> {
+        if self.busy() {
+            return Err(ErrorCode::BUSY);
+        }
+        let key: &[u8; AES128_KEY_SIZE] = key.try_into().map_err(|_| ErrorCode::INVAL)?;
+        self.round_keys.set(expand_key(key));
+        Ok(())
+    }
+
+    fn set_iv(&self, iv: &[u8]) -> Result<(), ErrorCode> {
+        if self.busy() {
+            return Err(ErrorCode::BUSY);
+        }
+        let iv: [u8; AES128_BLOCK_SIZE] = iv.try_into().map_err(|_| ErrorCode::INVAL)?;
+        self.iv.set(iv);
+        self.chain.set(iv);
+        Ok(())
+    }
+
+    fn start_message(&self) {
+        if self.busy() {
+            return;
+        }
+        self.chain.set(self.iv.get());
+    }
+
+    fn crypt(
+        &self,
+        source: Option<&'static mut [u8]>,
+        dest: &'static mut [u8],
+        start_index: usize,
+        stop_index: usize,
+    ) -> Option<(
+        Result<(), ErrorCode>,
+        Option<&'static mut [u8]>,
+        &'static mut [u8],
+    )> {
+        if self.busy() {
+            return Some((Err(ErrorCode::BUSY), source, dest));
+        }
+        let len = match stop_index.checked_sub(start_index) {
+            Some(len) if stop_index <= dest.len() && len % AES128_BLOCK_SIZE == 0 => len,
+            _ => return Some((Err(ErrorCode::INVAL), source, dest)),
+        };
+        if source.as_ref().is_some_and(|source| source.len() != len) {
+            return Some((Err(ErrorCode::INVAL), source, dest));
+        }
+
+        self.start_index.set(start_index);
+        self.stop_index.set(stop_index);
+        if let Some(source) = source {
+            self.source.replace(source);
+        }
+        self.dest.replace(dest);
+        self.deferred_call.set();
+        None
+    }
+}
+
+impl AES128Ctr for Aes128Software<'_> {
+    fn set_mode_aes128ctr(&self, encrypting: bool) -> Result<(), ErrorCode> {
+        self.set_mode(Mode::Ctr, encrypting)
+    }
+}
+
+impl AES128CBC for Aes128Software<'_> {
+    fn set_mode_aes128cbc(&self, encrypting: bool) -> Result<(), ErrorCode> {
+        self.set_mode(Mode::Cbc, encrypting)
+    }
+}
+
+impl AES128ECB for Aes128Software<'_> {
+    fn set_mode_aes128ecb(&self, encrypting: bool) -> Result<(), ErrorCode> {
+        self.set_mode(Mode::Ecb, encrypting)
+    }
+}
+
+impl DeferredCallClient for Aes128Software<'_> {
+    fn handle_deferred_call(&self) {
+        let source = self.source.take();
+        if let Some(dest) = self.dest.take() {
+            self.do_crypt(source.as_deref(), dest);
+            self.client.map(|client| client.crypt_done(source, dest));
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
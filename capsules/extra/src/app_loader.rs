@@ -0,0 +1,488 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Install, upgrade and remove applications from userspace.
+//!
+//! This driver lets an application write a new TBF to free app flash, and
+//! then either start it as a new process, or swap it in place of a running
+//! process with the same AppID. It can also unload a running process.
+//!
+//! The binary of an unloaded or replaced process is erased by replacing its
+//! TBF header with the header of a padding entry covering the whole binary,
+//! so that it is skipped at the next boot while the applications after it
+//! are still found. The kernel then hands its flash out for new TBFs, and the
+//! RAM of the process is reused by the processes started afterwards. If the
+//! kernel rejects the new TBF, it is erased the same way, and the application
+//! can write another TBF.
+//!
+//! Before the first chunk of a new TBF is written, the free region holding it
+//! is turned into a single padding entry, and the rest of the region after
+//! the TBF into another one, so that the applications after it are still
+//! found while and after it is written.
+//!
+//! The kernel refuses to upgrade a process to a binary that is not newer than
+//! it, and to start a process that has the same AppID or ShortId as another
+//! running process. Processes to upgrade or unload are identified by their
+//! fixed ShortId. An application cannot upgrade or unload itself.
+//!
+//! Since this driver can stop any process, boards should only give access to
+//! it to a trusted application, for example with TBF command permissions.
+//! Only one application can use the driver at a time.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let app_loader = components::app_loader::AppLoaderComponent::new(
+//!     board_kernel,
+//!     capsules_extra::app_loader::DRIVER_NUM,
+//!     nv_to_page,
+//!     loader,
+//! )
+//! .finalize(components::app_loader_component_static!());
+//! ```
+
+use core::cell::Cell;
+use core::num::NonZeroU32;
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::nonvolatile_storage::{NonvolatileStorage, NonvolatileStorageClient};
+use kernel::process::{
+    padding_tbf_header, DynamicProcessLoading, DynamicProcessLoadingClient, ProcessLoadError,
+    PADDING_TBF_HEADER_LEN,
+};
+use kernel::processbuffer::ReadableProcessBuffer;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, Kernel, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::AppLoader as usize;
+
+/// IDs for subscribed upcalls.
+mod upcall {
+    /// A chunk of the new TBF was written to flash.
+    pub const WRITE_DONE: usize = 0;
+    /// The new TBF was loaded, or replaced a running process.
+    pub const LOAD_DONE: usize = 1;
+    /// A process was unloaded.
+    pub const UNLOAD_DONE: usize = 2;
+    /// Number of upcalls.
+    pub const COUNT: u8 = 3;
+}
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// Chunk of the new TBF to write to flash.
+    pub const BUFFER: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Idle,
+    /// Turning the free region holding the new TBF into padding, before
+    /// writing the chunk at the given offset.
+    WritingPadding(usize),
+    /// Writing a chunk of the new TBF.
+    Writing,
+    /// The kernel is loading the new TBF as a new process.
+    Loading,
+    /// The kernel is swapping the new TBF in place of the process whose
+    /// binary is at the given address and of the given size.
+    Upgrading(usize, usize),
+    /// Erasing the binary of a replaced process.
+    ErasingReplaced,
+    /// Erasing the binary of an unloaded process.
+    ErasingUnloaded,
+    /// Erasing a new TBF that the kernel rejected.
+    ErasingRejected,
+}
+
+#[derive(Default)]
+pub struct App;
+
+pub struct AppLoader<'a, C: ProcessManagementCapability> {
+    kernel: &'static Kernel,
+    storage: &'a dyn NonvolatileStorage<'a>,
+    loader: &'a dyn DynamicProcessLoading<'a>,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<0>,
+    >,
+    /// The application using the driver.
+    current_app: OptionalCell<ProcessId>,
+    state: Cell<State>,
+    /// Address and length of the new TBF in flash.
+    new_binary: OptionalCell<(usize, usize)>,
+    /// Address and size of the padding entries to write before the first
+    /// chunk of the new TBF: one covering the whole free region holding it,
+    /// so that writing the TBF does not break the list of applications in
+    /// flash, then one covering the rest of the region after the TBF.
+    padding: [OptionalCell<(usize, usize)>; 2],
+    /// Result of loading the new TBF, reported once it is erased after the
+    /// kernel rejected it.
+    load_result: OptionalCell<Result<(), ErrorCode>>,
+    buffer: TakeCell<'static, [u8]>,
+    capability: C,
+}
+
+impl<'a, C: ProcessManagementCapability> AppLoader<'a, C> {
+    pub fn new(
+        kernel: &'static Kernel,
+        storage: &'a dyn NonvolatileStorage<'a>,
+        loader: &'a dyn DynamicProcessLoading<'a>,
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<0>,
+        >,
+        buffer: &'static mut [u8],
+        capability: C,
+    ) -> AppLoader<'a, C> {
+        AppLoader {
+            kernel,
+            storage,
+            loader,
+            apps: grant,
+            current_app: OptionalCell::empty(),
+            state: Cell::new(State::Idle),
+            new_binary: OptionalCell::empty(),
+            padding: [OptionalCell::empty(), OptionalCell::empty()],
+            load_result: OptionalCell::empty(),
+            buffer: TakeCell::new(buffer),
+            capability,
+        }
+    }
+
+    /// Make `processid` the application using the driver, unless another
+    /// application that still exists is using it.
+    fn claim(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        let available = self.current_app.map_or(true, |owner| {
+            owner == processid || self.apps.enter(owner, |_, _| ()).is_err()
+        });
+        if !available {
+            return Err(ErrorCode::BUSY);
+        }
+        if self.current_app.map_or(true, |owner| owner != processid) {
+            // The previous application went away during its transfer.
+            self.state.set(State::Idle);
+            self.new_binary.clear();
+            self.padding.iter().for_each(OptionalCell::clear);
+        }
+        self.current_app.set(processid);
+        Ok(())
+    }
+
+    /// Find the running process with the fixed ShortId `short_id`.
+    fn find_process(&self, short_id: usize) -> Result<ProcessId, ErrorCode> {
+        let short_id = NonZeroU32::new(short_id as u32)
+            .map(kernel::process::ShortId::Fixed)
+            .ok_or(ErrorCode::INVAL)?;
+        let mut found = None;
+        self.kernel
+            .process_each_capability(&self.capability, |process| {
+                if process.short_app_id() == short_id {
+                    found = Some(process.processid());
+                }
+            });
+        found.ok_or(ErrorCode::INVAL)
+    }
+
+    /// Find the running process with the fixed ShortId `short_id`, other
+    /// than the calling process `processid`, whose RAM is in use until the
+    /// command returns.
+    fn find_other_process(
+        &self,
+        short_id: usize,
+        processid: ProcessId,
+    ) -> Result<ProcessId, ErrorCode> {
+        let target = self.find_process(short_id)?;
+        if target == processid {
+            return Err(ErrorCode::INVAL);
+        }
+        Ok(target)
+    }
+
+    /// Prepare writing a new TBF of `length` bytes to free app flash.
+    fn setup(&self, length: usize, processid: ProcessId) -> Result<(), ErrorCode> {
+        self.claim(processid)?;
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        let region = self.loader.free_flash(length)?;
+        let address = region.as_ptr() as usize;
+        self.new_binary.set((address, length));
+        self.padding[0].set((address, region.len()));
+        if region.len() > length {
+            self.padding[1].set((address + length, region.len() - length));
+        } else {
+            self.padding[1].clear();
+        }
+        Ok(())
+    }
+
+    /// Write the allowed buffer at `offset` in the new TBF.
+    fn write(&self, offset: usize, processid: ProcessId) -> Result<(), ErrorCode> {
+        self.claim(processid)?;
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        if self.new_binary.is_none() {
+            return Err(ErrorCode::RESERVE);
+        }
+        self.write_next(offset, processid)
+    }
+
+    /// Write the next padding entry that must precede the new TBF, or the
+    /// allowed buffer of `processid` at `offset` in the new TBF once they are
+    /// all written.
+    fn write_next(&self, offset: usize, processid: ProcessId) -> Result<(), ErrorCode> {
+        for padding in self.padding.iter() {
+            if let Some((address, size)) = padding.take() {
+                if let Err(e) = self.write_padding(address, size) {
+                    padding.set((address, size));
+                    return Err(e);
+                }
+                self.state.set(State::WritingPadding(offset));
+                return Ok(());
+            }
+        }
+        self.write_chunk(offset, processid)
+    }
+
+    /// Write the allowed buffer of `processid` at `offset` in the new TBF.
+    fn write_chunk(&self, offset: usize, processid: ProcessId) -> Result<(), ErrorCode> {
+        let (address, length) = self.new_binary.get().ok_or(ErrorCode::RESERVE)?;
+
+        self.apps
+            .enter(processid, |_app, kernel_data| {
+                kernel_data
+                    .get_readonly_processbuffer(ro_allow::BUFFER)
+                    .and_then(|buffer| {
+                        buffer.enter(|app_buffer| {
+                            let chunk_len = app_buffer.len();
+                            if chunk_len == 0 || offset + chunk_len > length {
+                                return Err(ErrorCode::INVAL);
+                            }
+                            self.buffer.take().map_or(Err(ErrorCode::BUSY), |buffer| {
+                                if chunk_len > buffer.len() {
+                                    self.buffer.replace(buffer);
+                                    return Err(ErrorCode::SIZE);
+                                }
+                                app_buffer.copy_to_slice(&mut buffer[..chunk_len]);
+                                self.storage.write(buffer, address + offset, chunk_len)
+                            })
+                        })
+                    })
+                    .unwrap_or(Err(ErrorCode::RESERVE))
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+            .map(|()| self.state.set(State::Writing))
+    }
+
+    /// Check that the new TBF has been set up by `processid` and that nothing
+    /// else is going on.
+    fn check_ready_to_load(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        self.claim(processid)?;
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        // Nothing was written yet if the region is not padding.
+        if self.new_binary.is_none() || self.padding.iter().any(OptionalCell::is_some) {
+            return Err(ErrorCode::RESERVE);
+        }
+        Ok(())
+    }
+
+    /// Start the new TBF as a new process.
+    fn load(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        self.check_ready_to_load(processid)?;
+        let (address, _) = self.new_binary.get().ok_or(ErrorCode::RESERVE)?;
+        self.loader.load_new_process_binary(address)?;
+        self.state.set(State::Loading);
+        Ok(())
+    }
+
+    /// Swap the new TBF in place of the process with ShortId `short_id`.
+    fn upgrade(&self, short_id: usize, processid: ProcessId) -> Result<(), ErrorCode> {
+        self.check_ready_to_load(processid)?;
+        let target = self.find_other_process(short_id, processid)?;
+        let (new_address, _) = self.new_binary.get().ok_or(ErrorCode::RESERVE)?;
+        let (address, size) = self.loader.upgrade_process(target, new_address)?;
+        self.state.set(State::Upgrading(address, size));
+        Ok(())
+    }
+
+    /// Unload the process with ShortId `short_id`.
+    fn unload(&self, short_id: usize, processid: ProcessId) -> Result<(), ErrorCode> {
+        self.claim(processid)?;
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        let target = self.find_other_process(short_id, processid)?;
+        let (address, size) = self.kernel.unload_process(target, &self.capability)?;
+        self.write_padding(address, size)?;
+        self.state.set(State::ErasingUnloaded);
+        Ok(())
+    }
+
+    /// Write the TBF header of a padding entry of `size` bytes at `address`,
+    /// which erases the process binary there. Only the header is written; the
+    /// rest of the binary stays in flash, skipped as padding.
+    fn write_padding(&self, address: usize, size: usize) -> Result<(), ErrorCode> {
+        let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+        if buffer.len() < PADDING_TBF_HEADER_LEN {
+            self.buffer.replace(buffer);
+            return Err(ErrorCode::SIZE);
+        }
+        buffer[..PADDING_TBF_HEADER_LEN].copy_from_slice(&padding_tbf_header(size as u32));
+        self.storage.write(buffer, address, PADDING_TBF_HEADER_LEN)
+    }
+
+    /// Erase the new TBF after the kernel rejected it, and report `result`
+    /// once done.
+    fn erase_rejected(&self, result: Result<(), ErrorCode>) {
+        let erased = self
+            .new_binary
+            .take()
+            .ok_or(ErrorCode::FAIL)
+            .and_then(|(address, length)| self.write_padding(address, length));
+        match erased {
+            Ok(()) => {
+                self.load_result.set(result);
+                self.state.set(State::ErasingRejected);
+            }
+            // The TBF is found again at the next boot, and rejected then.
+            Err(_) => self.done(upcall::LOAD_DONE, result),
+        }
+    }
+
+    /// Signal the end of an operation to the application using the driver.
+    fn done(&self, upcall_num: usize, result: Result<(), ErrorCode>) {
+        self.state.set(State::Idle);
+        self.current_app.map(|processid| {
+            let _ = self.apps.enter(processid, |_app, kernel_data| {
+                kernel_data
+                    .schedule_upcall(
+                        upcall_num,
+                        (kernel::errorcode::into_statuscode(result), 0, 0),
+                    )
+                    .ok();
+            });
+        });
+    }
+}
+
+impl<C: ProcessManagementCapability> NonvolatileStorageClient for AppLoader<'_, C> {
+    fn read_done(&self, buffer: &'static mut [u8], _length: usize) {
+        self.buffer.replace(buffer);
+    }
+
+    fn write_done(&self, buffer: &'static mut [u8], _length: usize) {
+        self.buffer.replace(buffer);
+        match self.state.get() {
+            State::WritingPadding(offset) => {
+                let result = self
+                    .current_app
+                    .get()
+                    .ok_or(ErrorCode::FAIL)
+                    .and_then(|processid| self.write_next(offset, processid));
+                if let Err(e) = result {
+                    self.done(upcall::WRITE_DONE, Err(e));
+                }
+            }
+            State::Writing => self.done(upcall::WRITE_DONE, Ok(())),
+            State::ErasingReplaced => {
+                self.new_binary.clear();
+                self.done(upcall::LOAD_DONE, Ok(()));
+            }
+            State::ErasingUnloaded => self.done(upcall::UNLOAD_DONE, Ok(())),
+            State::ErasingRejected => {
+                let result = self.load_result.take().unwrap_or(Err(ErrorCode::FAIL));
+                self.done(upcall::LOAD_DONE, result);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<C: ProcessManagementCapability> DynamicProcessLoadingClient for AppLoader<'_, C> {
+    fn load_done(&self, result: Result<(), ProcessLoadError>) {
+        let result = result.map_err(ErrorCode::from);
+        match self.state.get() {
+            State::Loading | State::Upgrading(_, _) if result.is_err() => {
+                // The new TBF is erased, and the application may write
+                // another one after setting it up again.
+                self.erase_rejected(result);
+            }
+            State::Loading => {
+                // The TBF is now part of the app flash.
+                self.new_binary.clear();
+                self.done(upcall::LOAD_DONE, result);
+            }
+            State::Upgrading(address, size) => {
+                match self.write_padding(address, size) {
+                    Ok(()) => self.state.set(State::ErasingReplaced),
+                    Err(e) => {
+                        // The new process runs, but the old binary is still in
+                        // flash. The kernel skips it at boot as it is older.
+                        self.new_binary.clear();
+                        self.done(upcall::LOAD_DONE, Err(e));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<C: ProcessManagementCapability> SyscallDriver for AppLoader<'_, C> {
+    /// Application loading control.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Prepare writing a new TBF of `arg1` bytes. Returns `SIZE` if it
+    ///   does not fit in free app flash.
+    /// - `2`: Write the allowed buffer at offset `arg1` in the new TBF.
+    /// - `3`: Start the new TBF as a new process. Returns `RESERVE` if nothing
+    ///   was written yet, and `ALREADY` in the upcall if a process with the
+    ///   same AppID is running. If the TBF is rejected, it is erased, and
+    ///   another TBF can be written after command `1`.
+    /// - `4`: Replace the process with ShortId `arg1` by the new TBF. Returns
+    ///   `INVAL` if that is the calling process, `ALREADY` in the upcall if the
+    ///   new TBF is not newer, or if another process has the same AppID, and
+    ///   `INVAL` in the upcall if the AppIDs differ.
+    /// - `5`: Unload the process with ShortId `arg1`. Returns `INVAL` if that
+    ///   is the calling process. If its binary cannot be erased, the error is
+    ///   returned, but the process is unloaded regardless.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+            1 => self.setup(arg1, processid).into(),
+            2 => self.write(arg1, processid).into(),
+            3 => self.load(processid).into(),
+            4 => self.upgrade(arg1, processid).into(),
+            5 => self.unload(arg1, processid).into(),
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -0,0 +1,348 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Keeps crash reports in a persistent log.
+//!
+//! `CrashLog` saves each crash report as one entry of a persistent log, such
+//! as [`Log`](crate::log::Log) on a storage volume in flash. Registered as
+//! the kernel's [`CrashRecorder`], it saves the report printed when the
+//! kernel panics. Wrapping the board's fault policy in a
+//! [`CrashLogFaultPolicy`] also saves a report with the process printer
+//! output and the process state whenever a process faults without
+//! panicking.
+//!
+//! When started, `CrashLog` reads through the log to count the saved reports
+//! and keep the newest one, which can then be read through
+//! [`CrashLogControl`], for example with the process console `crashlog`
+//! command.
+//!
+//! Each entry is a two byte header followed by the text of the report:
+//!
+//! ```text
+//! 0     Kind, a `CrashKind`
+//! 1     Flags, bit 0 is set if the report was truncated
+//! 2..   Report text
+//! ```
+//!
+//! Reports are at most as long as the buffer provided to `CrashLog`, which
+//! must not be larger than the largest entry the log accepts. A report that
+//! arrives while the previous one is still being saved is dropped.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let crash_log = components::crash_log::CrashLogComponent::new(
+//!     &CRASH_LOG_VOLUME,
+//!     &base_peripherals.nvmc,
+//!     process_printer,
+//! )
+//! .finalize(components::crash_log_component_static!(nrf52840::nvmc::Nvmc, 4000));
+//! process_console.set_crash_log(crash_log);
+//!
+//! let fault_policy = static_init!(
+//!     CrashLogFaultPolicy<'static, Log<'static, nrf52840::nvmc::Nvmc>>,
+//!     CrashLogFaultPolicy::new(crash_log, &FAULT_RESPONSE)
+//! );
+//! ```
+
+use core::cell::Cell;
+use core::fmt::{self, Write};
+
+use kernel::crash_log::{CrashKind, CrashLogControl, CrashRecorder, CrashReportInfo};
+use kernel::hil::log::{LogRead, LogReadClient, LogWrite, LogWriteClient};
+use kernel::process::{FaultAction, Process, ProcessFaultPolicy, ProcessPrinter};
+use kernel::utilities::binary_write::BinaryWrite;
+use kernel::utilities::cells::TakeCell;
+use kernel::ErrorCode;
+
+/// Length of the header of each entry.
+pub const HEADER_LEN: usize = 2;
+
+/// Flag set in the header of reports that did not fit in the buffer.
+pub const FLAG_TRUNCATED: u8 = 0x01;
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    /// Reading the saved reports at boot.
+    Loading,
+    /// A report is being written into the buffer.
+    Recording,
+    Appending,
+    Syncing,
+    Erasing,
+}
+
+pub struct CrashLog<'a, L: LogRead<'a> + LogWrite<'a>> {
+    log: &'a L,
+    process_printer: &'a dyn ProcessPrinter,
+    /// Holds the report being recorded, or the newest report.
+    report: TakeCell<'static, [u8]>,
+    /// Length of the entry in `report`, including the header, or zero if
+    /// there is none.
+    report_len: Cell<usize>,
+    state: Cell<State>,
+    count: Cell<usize>,
+}
+
+impl<'a, L: LogRead<'a> + LogWrite<'a>> CrashLog<'a, L> {
+    pub fn new(
+        log: &'a L,
+        process_printer: &'a dyn ProcessPrinter,
+        report: &'static mut [u8],
+    ) -> Self {
+        Self {
+            log,
+            process_printer,
+            report: TakeCell::new(report),
+            report_len: Cell::new(0),
+            state: Cell::new(State::Idle),
+            count: Cell::new(0),
+        }
+    }
+
+    /// Reads the saved reports, to count them and keep the newest one.
+    pub fn start(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.count.set(0);
+        self.report_len.set(0);
+        self.state.set(State::Loading);
+        self.read_next()
+    }
+
+    fn read_next(&self) -> Result<(), ErrorCode> {
+        let buffer = self.report.take().ok_or(ErrorCode::RESERVE)?;
+        let len = buffer.len();
+        self.log.read(buffer, len).map_err(|(error, buffer)| {
+            // Reading fails at the end of the log.
+            self.report.replace(buffer);
+            self.state.set(State::Idle);
+            error
+        })
+    }
+
+    /// Saves a report about `process`, which faulted and on which the kernel
+    /// is about to take `action`.
+    pub fn record_process_fault(&self, process: &dyn Process, action: FaultAction) {
+        if !self.begin_report(CrashKind::ProcessFault) {
+            return;
+        }
+
+        let mut writer = ReportWriter { recorder: self };
+        let _ = writer.write_fmt(format_args!(
+            "\r\nProcess {} faulted and will be {}.\r\n",
+            process.get_process_name(),
+            match action {
+                FaultAction::Restart => "restarted",
+                _ => "stopped",
+            }
+        ));
+        let mut context = None;
+        loop {
+            context = self
+                .process_printer
+                .print_overview(process, &mut writer, context);
+            if context.is_none() {
+                break;
+            }
+        }
+        process.print_full_process(&mut writer);
+
+        let _ = self.save_report();
+    }
+}
+
+impl<'a, L: LogRead<'a> + LogWrite<'a>> CrashRecorder for CrashLog<'a, L> {
+    fn begin_report(&self, kind: CrashKind) -> bool {
+        if self.state.get() != State::Idle {
+            return false;
+        }
+        self.report
+            .map(|report| {
+                report[0] = kind as u8;
+                report[1] = 0;
+                self.report_len.set(HEADER_LEN);
+                self.state.set(State::Recording);
+            })
+            .is_some()
+    }
+
+    fn write_report(&self, buf: &[u8]) {
+        if self.state.get() != State::Recording {
+            return;
+        }
+        self.report.map(|report| {
+            let start = self.report_len.get();
+            let len = core::cmp::min(buf.len(), report.len() - start);
+            report[start..start + len].copy_from_slice(&buf[..len]);
+            self.report_len.set(start + len);
+            if len < buf.len() {
+                report[1] |= FLAG_TRUNCATED;
+            }
+        });
+    }
+
+    fn save_report(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Recording {
+            return Err(ErrorCode::INVAL);
+        }
+        let report = self.report.take().ok_or(ErrorCode::RESERVE)?;
+        self.state.set(State::Appending);
+        self.log
+            .append(report, self.report_len.get())
+            .map_err(|(error, report)| {
+                // The report is not saved, but can still be read.
+                self.report.replace(report);
+                self.state.set(State::Idle);
+                error
+            })
+    }
+
+    fn is_saving(&self) -> bool {
+        matches!(self.state.get(), State::Appending | State::Syncing)
+    }
+}
+
+impl<'a, L: LogRead<'a> + LogWrite<'a>> CrashLogControl for CrashLog<'a, L> {
+    fn report_count(&self) -> usize {
+        self.count.get()
+    }
+
+    fn last_report(&self) -> Option<CrashReportInfo> {
+        if self.state.get() == State::Recording || self.report_len.get() < HEADER_LEN {
+            return None;
+        }
+        self.report.map_or(None, |report| {
+            CrashKind::try_from(report[0])
+                .ok()
+                .map(|kind| CrashReportInfo {
+                    kind,
+                    len: self.report_len.get() - HEADER_LEN,
+                    truncated: report[1] & FLAG_TRUNCATED != 0,
+                })
+        })
+    }
+
+    fn read_last_report(&self, offset: usize, buf: &mut [u8]) -> usize {
+        self.last_report().map_or(0, |info| {
+            let start = HEADER_LEN + core::cmp::min(offset, info.len);
+            let len = core::cmp::min(buf.len(), HEADER_LEN + info.len - start);
+            self.report.map_or(0, |report| {
+                buf[..len].copy_from_slice(&report[start..start + len]);
+                len
+            })
+        })
+    }
+
+    fn erase(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.log.erase()?;
+        self.state.set(State::Erasing);
+        Ok(())
+    }
+}
+
+impl<'a, L: LogRead<'a> + LogWrite<'a>> LogReadClient for CrashLog<'a, L> {
+    fn read_done(&self, buffer: &'static mut [u8], length: usize, error: Result<(), ErrorCode>) {
+        self.report.replace(buffer);
+        if self.state.get() != State::Loading {
+            return;
+        }
+        match error {
+            Ok(()) => {
+                self.count.set(self.count.get() + 1);
+                self.report_len.set(length);
+                let _ = self.read_next();
+            }
+            Err(_) => {
+                self.state.set(State::Idle);
+            }
+        }
+    }
+
+    fn seek_done(&self, _error: Result<(), ErrorCode>) {}
+}
+
+impl<'a, L: LogRead<'a> + LogWrite<'a>> LogWriteClient for CrashLog<'a, L> {
+    fn append_done(
+        &self,
+        buffer: &'static mut [u8],
+        _length: usize,
+        _records_lost: bool,
+        error: Result<(), ErrorCode>,
+    ) {
+        self.report.replace(buffer);
+        if error.is_err() {
+            self.state.set(State::Idle);
+            return;
+        }
+        self.count.set(self.count.get() + 1);
+
+        // Appended entries are only persistent once the log is synced.
+        match self.log.sync() {
+            Ok(()) => self.state.set(State::Syncing),
+            Err(_) => self.state.set(State::Idle),
+        }
+    }
+
+    fn sync_done(&self, _error: Result<(), ErrorCode>) {
+        self.state.set(State::Idle);
+    }
+
+    fn erase_done(&self, error: Result<(), ErrorCode>) {
+        if error.is_ok() {
+            self.count.set(0);
+            self.report_len.set(0);
+        }
+        self.state.set(State::Idle);
+    }
+}
+
+/// Writes text into the report being recorded.
+struct ReportWriter<'b> {
+    recorder: &'b dyn CrashRecorder,
+}
+
+impl Write for ReportWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.recorder.write_report(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl BinaryWrite for ReportWriter<'_> {
+    fn write_buffer(&mut self, buffer: &[u8]) -> Result<usize, ()> {
+        self.recorder.write_report(buffer);
+        Ok(buffer.len())
+    }
+}
+
+/// Fault policy that saves a crash report when a process faults, then takes
+/// the action decided by `policy`.
+pub struct CrashLogFaultPolicy<'a, L: LogRead<'a> + LogWrite<'a>> {
+    crash_log: &'a CrashLog<'a, L>,
+    policy: &'a dyn ProcessFaultPolicy,
+}
+
+impl<'a, L: LogRead<'a> + LogWrite<'a>> CrashLogFaultPolicy<'a, L> {
+    pub fn new(crash_log: &'a CrashLog<'a, L>, policy: &'a dyn ProcessFaultPolicy) -> Self {
+        Self { crash_log, policy }
+    }
+}
+
+impl<'a, L: LogRead<'a> + LogWrite<'a>> ProcessFaultPolicy for CrashLogFaultPolicy<'a, L> {
+    fn action(&self, process: &dyn Process) -> FaultAction {
+        let action = self.policy.action(process);
+        // The panic report already includes the state of the process.
+        if !matches!(action, FaultAction::Panic) {
+            self.crash_log.record_process_fault(process, action);
+        }
+        action
+    }
+}
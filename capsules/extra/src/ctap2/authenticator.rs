@@ -0,0 +1,1944 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! FIDO2 authenticator implementing CTAP 2.0 over CTAPHID.
+//!
+//! The authenticator is a client of a USB HID device carrying CTAPHID
+//! reports, such as [`CtapHid`](crate::usb::ctap::CtapHid). It reassembles
+//! the messages of one channel at a time and answers the following commands:
+//!
+//! - CTAPHID: INIT (channel allocation), PING, CBOR and CANCEL. MSG (U2F) is
+//!   not supported.
+//! - CTAP2: authenticatorMakeCredential, authenticatorGetAssertion,
+//!   authenticatorGetInfo and authenticatorReset. Client PINs and user
+//!   verification are not supported.
+//!
+//! Credentials use ES256 (ECDSA with P-256 and SHA-256) keys, which the
+//! authenticator generates and uses through the `public_key_crypto` HILs.
+//! Registrations are self attested with the credential key ("packed" format
+//! without a certificate). The credentials are kept in the KV store, see
+//! [`credentials`](super::credentials). The signature counter is not
+//! implemented and is always zero, which relying parties treat as
+//! unsupported.
+//!
+//! Creating a credential, getting an assertion (unless the client opts out)
+//! and resetting require the user to press a button. While waiting for the
+//! button, the authenticator sends keepalive messages to the client, and it
+//! gives up after 30 seconds.
+//!
+//! ```text
+//!  +---------------+    hil::usb_hid     +---------------+
+//!  | Authenticator | ------------------> |    CtapHid    |
+//!  +---------------+                     +---------------+
+//!     |    |    |  \
+//!     |    |    |   hil::gpio (button)
+//!     |    |    hil::kv (credentials)
+//!     |    hil::digest (SHA-256)
+//!     hil::public_key_crypto (key generation and signing)
+//! ```
+
+use core::cell::Cell;
+use core::cmp::min;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::digest;
+use kernel::hil::gpio;
+use kernel::hil::kv;
+use kernel::hil::public_key_crypto::{keys, signature};
+use kernel::hil::time::{self, ConvertTicks};
+use kernel::hil::usb_hid;
+use kernel::storage_permissions::StoragePermissions;
+use kernel::utilities::cells::TakeCell;
+use kernel::utilities::leasable_buffer::{SubSlice, SubSliceMut};
+use kernel::ErrorCode;
+
+use super::cbor::{self, Reader, Writer};
+use super::credentials::{self, Table, CREDENTIAL_ID_LEN};
+use super::ctaphid::{self, command, Packet, PACKET_LEN};
+
+/// Length of SHA-256 hashes.
+pub const HASH_LEN: usize = 32;
+/// Length of P-256 signatures.
+pub const SIGNATURE_LEN: usize = 64;
+/// Length of P-256 public keys.
+pub const PUBLIC_KEY_LEN: usize = 64;
+/// Length of P-256 private keys.
+pub const PRIVATE_KEY_LEN: usize = credentials::PRIVATE_KEY_LEN;
+
+/// Length of the AAGUID identifying the authenticator model.
+pub const AAGUID_LEN: usize = 16;
+
+/// Authenticator commands (sect. 5).
+mod ctap_command {
+    pub const MAKE_CREDENTIAL: u8 = 0x01;
+    pub const GET_ASSERTION: u8 = 0x02;
+    pub const GET_INFO: u8 = 0x04;
+    pub const RESET: u8 = 0x07;
+    pub const GET_NEXT_ASSERTION: u8 = 0x08;
+}
+
+/// A CTAP2 status code (sect. 6.3).
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Status(u8);
+
+impl Status {
+    const OK: Status = Status(0x00);
+    const INVALID_COMMAND: Status = Status(0x01);
+    const INVALID_PARAMETER: Status = Status(0x02);
+    const INVALID_LENGTH: Status = Status(0x03);
+    const CBOR_UNEXPECTED_TYPE: Status = Status(0x11);
+    const INVALID_CBOR: Status = Status(0x12);
+    const MISSING_PARAMETER: Status = Status(0x14);
+    const CREDENTIAL_EXCLUDED: Status = Status(0x19);
+    const UNSUPPORTED_ALGORITHM: Status = Status(0x26);
+    const KEY_STORE_FULL: Status = Status(0x28);
+    const UNSUPPORTED_OPTION: Status = Status(0x2b);
+    const INVALID_OPTION: Status = Status(0x2c);
+    const KEEPALIVE_CANCEL: Status = Status(0x2d);
+    const NO_CREDENTIALS: Status = Status(0x2e);
+    const USER_ACTION_TIMEOUT: Status = Status(0x2f);
+    const NOT_ALLOWED: Status = Status(0x30);
+    const PIN_AUTH_INVALID: Status = Status(0x33);
+    const PIN_NOT_SET: Status = Status(0x35);
+    const OTHER: Status = Status(0x7f);
+}
+
+impl From<cbor::Error> for Status {
+    fn from(error: cbor::Error) -> Self {
+        match error {
+            cbor::Error::Malformed => Status::INVALID_CBOR,
+            cbor::Error::UnexpectedType => Status::CBOR_UNEXPECTED_TYPE,
+            cbor::Error::Overflow => Status::OTHER,
+        }
+    }
+}
+
+/// COSE algorithm identifier of ES256.
+const ES256: i64 = -7;
+
+/// Flags of the authenticator data.
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+
+/// Length of the authenticator data without attested credential data.
+const AUTH_DATA_LEN: usize = HASH_LEN + 1 + 4;
+/// Length of the COSE encoding of a P-256 public key.
+const COSE_KEY_LEN: usize = 77;
+/// Length of the authenticator data with attested credential data.
+const ATTESTED_AUTH_DATA_LEN: usize =
+    AUTH_DATA_LEN + AAGUID_LEN + 2 + CREDENTIAL_ID_LEN + COSE_KEY_LEN;
+
+/// Time allowed between the packets of a message.
+const MESSAGE_TIMEOUT_MS: u32 = 500;
+/// Interval of the keepalive messages while waiting for the user.
+const KEEPALIVE_INTERVAL_MS: u32 = 100;
+/// Number of keepalive intervals to wait for the user.
+const USER_PRESENCE_INTERVALS: usize = 300;
+
+/// The state of the CTAPHID transaction. Only one channel can have a
+/// transaction at a time.
+#[derive(Clone, Copy, PartialEq)]
+enum Transaction {
+    Idle,
+    /// Receiving the continuation packets of a request.
+    Receiving {
+        cid: u32,
+        cmd: u8,
+        len: usize,
+        received: usize,
+        seq: u8,
+    },
+    /// Processing a CBOR request.
+    Processing {
+        cid: u32,
+    },
+    /// Sending the packets of a response.
+    Sending {
+        cid: u32,
+        cmd: u8,
+        len: usize,
+        offset: usize,
+        seq: u8,
+        started: bool,
+    },
+}
+
+/// The asynchronous step of a CBOR command in progress.
+#[derive(Clone, Copy, PartialEq)]
+enum Step {
+    Idle,
+    /// Reading the credential table.
+    Loading,
+    /// Hashing the relying party ID.
+    HashingRpId,
+    /// Waiting for the button to be pressed.
+    UserPresence,
+    /// Generating the key pair of a new credential.
+    Generating,
+    /// Writing the credential table.
+    Storing,
+    /// Deleting the credential table.
+    Resetting,
+    /// Hashing the authenticator data and the client data hash.
+    HashingAuthData,
+    /// Selecting the credential key.
+    SettingKey,
+    /// Signing the authenticator data.
+    Signing,
+}
+
+/// The parameters of a makeCredential or getAssertion request that are
+/// needed after parsing. Strings are kept as ranges of the request buffer.
+#[derive(Clone, Copy, Default)]
+struct Request {
+    command: u8,
+    client_data_hash: [u8; HASH_LEN],
+    /// Offset and length of the relying party ID.
+    rp_id: (usize, usize),
+    /// Offset and length of the user ID.
+    user_id: (usize, usize),
+    /// Offset of the exclude list or the allow list.
+    list: Option<usize>,
+    /// Whether to create a discoverable credential.
+    discoverable: bool,
+    /// Whether to check that the user is present.
+    user_presence: bool,
+    /// Status to return once the user is present, instead of carrying out
+    /// the request.
+    presence_status: Option<Status>,
+}
+
+/// Reads the `pinAuth` parameter. Without PIN support, a zero length
+/// `pinAuth` asks the user to select the authenticator, which then reports
+/// that no PIN is set (sect. 5.1 and 5.2).
+fn parse_pin_auth(r: &mut Reader, request: &mut Request) -> Result<(), Status> {
+    if !r.bytes()?.is_empty() {
+        return Err(Status::PIN_AUTH_INVALID);
+    }
+    request.presence_status = Some(Status::PIN_NOT_SET);
+    Ok(())
+}
+
+/// Reads the options map.
+fn parse_options(r: &mut Reader, request: &mut Request) -> Result<(), Status> {
+    for _ in 0..r.map()? {
+        match r.text()? {
+            "rk" => {
+                let rk = r.boolean()?;
+                if request.command == ctap_command::GET_ASSERTION {
+                    return Err(Status::INVALID_OPTION);
+                }
+                request.discoverable = rk;
+            }
+            "up" => {
+                let up = r.boolean()?;
+                if request.command == ctap_command::MAKE_CREDENTIAL && !up {
+                    return Err(Status::INVALID_OPTION);
+                }
+                request.user_presence = up;
+            }
+            "uv" => {
+                if r.boolean()? {
+                    return Err(Status::UNSUPPORTED_OPTION);
+                }
+            }
+            _ => r.skip()?,
+        }
+    }
+    Ok(())
+}
+
+/// Reads the client data hash.
+fn parse_client_data_hash(r: &mut Reader, request: &mut Request) -> Result<(), Status> {
+    let hash = r.bytes()?;
+    if hash.len() != HASH_LEN {
+        return Err(Status::INVALID_LENGTH);
+    }
+    request.client_data_hash.copy_from_slice(hash);
+    Ok(())
+}
+
+/// Reads the relying party ID, returning its range.
+fn parse_rp_id(r: &mut Reader) -> Result<(usize, usize), Status> {
+    let rp_id = r.text()?;
+    if rp_id.is_empty() {
+        return Err(Status::INVALID_PARAMETER);
+    }
+    Ok((r.position() - rp_id.len(), rp_id.len()))
+}
+
+/// Parses an authenticatorMakeCredential request (sect. 5.1).
+fn parse_make_credential(message: &[u8]) -> Result<Request, Status> {
+    let mut request = Request {
+        command: ctap_command::MAKE_CREDENTIAL,
+        user_presence: true,
+        ..Request::default()
+    };
+    let (mut client_data_hash, mut rp_id, mut user_id, mut es256) = (false, false, false, None);
+
+    let mut r = Reader::at(message, 1);
+    for _ in 0..r.map()? {
+        match r.integer()? {
+            1 => {
+                parse_client_data_hash(&mut r, &mut request)?;
+                client_data_hash = true;
+            }
+            2 => {
+                for _ in 0..r.map()? {
+                    match r.text()? {
+                        "id" => {
+                            request.rp_id = parse_rp_id(&mut r)?;
+                            rp_id = true;
+                        }
+                        _ => r.skip()?,
+                    }
+                }
+            }
+            3 => {
+                for _ in 0..r.map()? {
+                    match r.text()? {
+                        "id" => {
+                            let id = r.bytes()?;
+                            if id.len() > credentials::MAX_USER_ID_LEN {
+                                return Err(Status::INVALID_LENGTH);
+                            }
+                            request.user_id = (r.position() - id.len(), id.len());
+                            user_id = true;
+                        }
+                        _ => r.skip()?,
+                    }
+                }
+            }
+            4 => {
+                let mut found = false;
+                for _ in 0..r.array()? {
+                    let (mut alg, mut public_key) = (None, false);
+                    for _ in 0..r.map()? {
+                        match r.text()? {
+                            "alg" => alg = Some(r.integer()?),
+                            "type" => public_key = r.text()? == "public-key",
+                            _ => r.skip()?,
+                        }
+                    }
+                    found |= public_key && alg == Some(ES256);
+                }
+                es256 = Some(found);
+            }
+            5 => {
+                request.list = Some(r.position());
+                r.skip()?;
+            }
+            7 => parse_options(&mut r, &mut request)?,
+            8 => parse_pin_auth(&mut r, &mut request)?,
+            _ => r.skip()?,
+        }
+    }
+
+    match es256 {
+        _ if !client_data_hash || !rp_id || !user_id => Err(Status::MISSING_PARAMETER),
+        None => Err(Status::MISSING_PARAMETER),
+        Some(false) => Err(Status::UNSUPPORTED_ALGORITHM),
+        Some(true) => Ok(request),
+    }
+}
+
+/// Parses an authenticatorGetAssertion request (sect. 5.2).
+fn parse_get_assertion(message: &[u8]) -> Result<Request, Status> {
+    let mut request = Request {
+        command: ctap_command::GET_ASSERTION,
+        user_presence: true,
+        ..Request::default()
+    };
+    let (mut client_data_hash, mut rp_id) = (false, false);
+
+    let mut r = Reader::at(message, 1);
+    for _ in 0..r.map()? {
+        match r.integer()? {
+            1 => {
+                request.rp_id = parse_rp_id(&mut r)?;
+                rp_id = true;
+            }
+            2 => {
+                parse_client_data_hash(&mut r, &mut request)?;
+                client_data_hash = true;
+            }
+            3 => {
+                let offset = r.position();
+                if r.array()? > 0 {
+                    request.list = Some(offset);
+                }
+                r = Reader::at(message, offset);
+                r.skip()?;
+            }
+            5 => parse_options(&mut r, &mut request)?,
+            6 => parse_pin_auth(&mut r, &mut request)?,
+            _ => r.skip()?,
+        }
+    }
+
+    if !client_data_hash || !rp_id {
+        return Err(Status::MISSING_PARAMETER);
+    }
+    Ok(request)
+}
+
+/// Returns the index of the first credential of the list at offset `list`
+/// of `message` that is in `table`.
+fn find_in_list(
+    table: &Table,
+    message: &[u8],
+    list: usize,
+    rp_id_hash: &[u8],
+) -> Result<Option<usize>, Status> {
+    let mut r = Reader::at(message, list);
+    for _ in 0..r.array()? {
+        let (mut id, mut public_key) = (None, false);
+        for _ in 0..r.map()? {
+            match r.text()? {
+                "id" => id = Some(r.bytes()?),
+                "type" => public_key = r.text()? == "public-key",
+                _ => r.skip()?,
+            }
+        }
+        if let (Some(id), true) = (id, public_key) {
+            if let Some(index) = table.find(rp_id_hash, id) {
+                return Ok(Some(index));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Strips the leading zeros of a big endian integer, returning it with
+/// whether it needs a zero byte to stay positive in DER.
+fn der_integer(value: &[u8]) -> (&[u8], bool) {
+    let start = value
+        .iter()
+        .position(|&b| b != 0)
+        .unwrap_or(value.len() - 1);
+    let value = &value[start..];
+    (value, value[0] & 0x80 != 0)
+}
+
+/// Writes the `r` and `s` values of a signature as a DER encoded
+/// ECDSA-Sig-Value in a byte string.
+fn write_der_signature(w: &mut Writer, signature: &[u8; SIGNATURE_LEN]) {
+    let (r, r_pad) = der_integer(&signature[..32]);
+    let (s, s_pad) = der_integer(&signature[32..]);
+    let r_len = r.len() + r_pad as usize;
+    let s_len = s.len() + s_pad as usize;
+    let len = 2 + r_len + 2 + s_len;
+
+    w.bytes_header(2 + len).raw(&[0x30, len as u8]);
+    w.raw(&[0x02, r_len as u8])
+        .raw(&[0][..r_pad as usize])
+        .raw(r);
+    w.raw(&[0x02, s_len as u8])
+        .raw(&[0][..s_pad as usize])
+        .raw(s);
+}
+
+pub struct Authenticator<
+    'a,
+    U: usb_hid::UsbHid<'a, [u8; PACKET_LEN]>,
+    A: time::Alarm<'a>,
+    H: digest::Digest<'a, HASH_LEN> + digest::Sha256,
+    S: signature::SignatureSign<'a, HASH_LEN, SIGNATURE_LEN>
+        + keys::SetKeyBySlice<'a, PRIVATE_KEY_LEN>
+        + keys::KeyPairGenerate<'a, PUBLIC_KEY_LEN, PRIVATE_KEY_LEN>,
+> {
+    hid: &'a U,
+    alarm: &'a A,
+    digest: &'a H,
+    signer: &'a S,
+    kv: &'a dyn kv::KVPermissions<'a>,
+    permissions: StoragePermissions,
+    button: &'a dyn gpio::InterruptPin<'a>,
+    button_mode: gpio::ActivationMode,
+    aaguid: [u8; AAGUID_LEN],
+
+    /// The next channel ID to allocate.
+    next_cid: Cell<u32>,
+    transaction: Cell<Transaction>,
+    /// A packet received from the HID device, waiting to be processed.
+    rx_packet: TakeCell<'static, [u8; PACKET_LEN]>,
+    tx_packet: TakeCell<'static, [u8; PACKET_LEN]>,
+    request: TakeCell<'static, [u8]>,
+    request_len: Cell<usize>,
+    response: TakeCell<'static, [u8]>,
+    max_message_len: usize,
+
+    step: Cell<Step>,
+    parsed: Cell<Request>,
+    rp_id_hash: Cell<[u8; HASH_LEN]>,
+    /// Index of the credential being created or used.
+    credential: Cell<usize>,
+    /// Offset and length of the authenticator data in the response buffer.
+    /// The client data hash follows it until the signature is written.
+    auth_data: Cell<(usize, usize)>,
+    presence_intervals: Cell<usize>,
+    /// Whether the credential table in `kv_value` is valid.
+    loaded: Cell<bool>,
+
+    hash: TakeCell<'static, [u8; HASH_LEN]>,
+    signature: TakeCell<'static, [u8; SIGNATURE_LEN]>,
+    public_key: TakeCell<'static, [u8; PUBLIC_KEY_LEN]>,
+    private_key: TakeCell<'static, [u8; PRIVATE_KEY_LEN]>,
+    kv_key: TakeCell<'static, [u8]>,
+    kv_value: TakeCell<'static, [u8]>,
+
+    deferred_call: DeferredCall,
+}
+
+impl<
+        'a,
+        U: usb_hid::UsbHid<'a, [u8; PACKET_LEN]>,
+        A: time::Alarm<'a>,
+        H: digest::Digest<'a, HASH_LEN> + digest::Sha256,
+        S: signature::SignatureSign<'a, HASH_LEN, SIGNATURE_LEN>
+            + keys::SetKeyBySlice<'a, PRIVATE_KEY_LEN>
+            + keys::KeyPairGenerate<'a, PUBLIC_KEY_LEN, PRIVATE_KEY_LEN>,
+    > Authenticator<'a, U, A, H, S>
+{
+    /// Creates an authenticator.
+    ///
+    /// `request` and `response` bound the length of messages. `kv_key` must
+    /// fit [`credentials::KV_KEY`] and `kv_value` must fit the header of the
+    /// KV store followed by [`credentials::RECORD_LEN`] bytes.
+    pub fn new(
+        hid: &'a U,
+        alarm: &'a A,
+        digest: &'a H,
+        signer: &'a S,
+        kv: &'a dyn kv::KVPermissions<'a>,
+        permissions: StoragePermissions,
+        button: &'a dyn gpio::InterruptPin<'a>,
+        button_mode: gpio::ActivationMode,
+        aaguid: [u8; AAGUID_LEN],
+        buffers: (
+            &'static mut [u8; PACKET_LEN],
+            &'static mut [u8; PACKET_LEN],
+            &'static mut [u8; HASH_LEN],
+            &'static mut [u8; SIGNATURE_LEN],
+            &'static mut [u8; PUBLIC_KEY_LEN],
+            &'static mut [u8; PRIVATE_KEY_LEN],
+        ),
+        request: &'static mut [u8],
+        response: &'static mut [u8],
+        kv_key: &'static mut [u8],
+        kv_value: &'static mut [u8],
+    ) -> Self {
+        let (rx_packet, tx_packet, hash, signature, public_key, private_key) = buffers;
+        Self {
+            hid,
+            alarm,
+            digest,
+            signer,
+            kv,
+            permissions,
+            button,
+            button_mode,
+            aaguid,
+            next_cid: Cell::new(1),
+            transaction: Cell::new(Transaction::Idle),
+            rx_packet: TakeCell::new(rx_packet),
+            tx_packet: TakeCell::new(tx_packet),
+            max_message_len: min(request.len(), response.len()),
+            request: TakeCell::new(request),
+            request_len: Cell::new(0),
+            response: TakeCell::new(response),
+            step: Cell::new(Step::Idle),
+            parsed: Cell::new(Request::default()),
+            rp_id_hash: Cell::new([0; HASH_LEN]),
+            credential: Cell::new(0),
+            auth_data: Cell::new((0, 0)),
+            presence_intervals: Cell::new(0),
+            loaded: Cell::new(false),
+            hash: TakeCell::new(hash),
+            signature: TakeCell::new(signature),
+            public_key: TakeCell::new(public_key),
+            private_key: TakeCell::new(private_key),
+            kv_key: TakeCell::new(kv_key),
+            kv_value: TakeCell::new(kv_value),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    /// Starts receiving requests.
+    pub fn start(&self) -> Result<(), ErrorCode> {
+        let packet = self.rx_packet.take().ok_or(ErrorCode::ALREADY)?;
+        self.hid.receive_buffer(packet).map_err(|(e, packet)| {
+            self.rx_packet.replace(packet);
+            e
+        })
+    }
+
+    // CTAPHID
+
+    /// Sends a single packet message if the packet buffer is free. Messages
+    /// sent this way are errors and notifications, which the client recovers
+    /// from if they are dropped.
+    fn send_single(&self, cid: u32, cmd: u8, payload: &[u8]) {
+        if let Some(packet) = self.tx_packet.take() {
+            let data = ctaphid::write_init(packet, cid, cmd, payload.len());
+            data[..payload.len()].copy_from_slice(payload);
+            if let Err((_, packet)) = self.hid.send_buffer(packet) {
+                self.tx_packet.replace(packet);
+            }
+        }
+    }
+
+    fn send_error(&self, cid: u32, error: u8) {
+        self.send_single(cid, command::ERROR, &[error]);
+    }
+
+    /// Starts sending the first `len` bytes of the response buffer.
+    fn send_response(&self, cid: u32, cmd: u8, len: usize) {
+        self.transaction.set(Transaction::Sending {
+            cid,
+            cmd,
+            len,
+            offset: 0,
+            seq: 0,
+            started: false,
+        });
+        self.send_next_packet();
+    }
+
+    /// Sends the next packet of the response, if the packet buffer is free.
+    fn send_next_packet(&self) {
+        let Transaction::Sending {
+            cid,
+            cmd,
+            len,
+            offset,
+            seq,
+            started,
+        } = self.transaction.get()
+        else {
+            return;
+        };
+        if started && offset >= len {
+            self.transaction.set(Transaction::Idle);
+            return;
+        }
+        let Some(packet) = self.tx_packet.take() else {
+            return;
+        };
+
+        let sent = self.response.map_or(0, |response| {
+            let data = if started {
+                ctaphid::write_cont(packet, cid, seq)
+            } else {
+                ctaphid::write_init(packet, cid, cmd, len)
+            };
+            let n = min(data.len(), len - offset);
+            data[..n].copy_from_slice(&response[offset..offset + n]);
+            n
+        });
+        self.transaction.set(Transaction::Sending {
+            cid,
+            cmd,
+            len,
+            offset: offset + sent,
+            seq: if started { seq + 1 } else { 0 },
+            started: true,
+        });
+        if let Err((_, packet)) = self.hid.send_buffer(packet) {
+            self.tx_packet.replace(packet);
+            self.transaction.set(Transaction::Idle);
+        }
+    }
+
+    /// Returns whether `cid` is a channel that has been allocated.
+    fn is_allocated(&self, cid: u32) -> bool {
+        cid != 0 && cid < self.next_cid.get()
+    }
+
+    /// Handles a packet received from the HID device.
+    fn receive_packet(&self, packet: &[u8; PACKET_LEN]) {
+        match Packet::parse(packet) {
+            Packet::Init {
+                cid,
+                cmd: command::INIT,
+                len,
+                data,
+            } => self.init(cid, len, data),
+            Packet::Init {
+                cid,
+                cmd,
+                len,
+                data,
+            } => self.receive_init(cid, cmd, len, data),
+            Packet::Cont { cid, seq, data } => self.receive_cont(cid, seq, data),
+        }
+    }
+
+    /// Handles the INIT command, which allocates a channel when sent on the
+    /// broadcast channel and aborts the transaction of the channel otherwise.
+    fn init(&self, cid: u32, len: usize, data: &[u8]) {
+        if len != ctaphid::INIT_NONCE_LEN {
+            self.send_error(cid, ctaphid::error::INVALID_LEN);
+            return;
+        }
+        let new_cid = if cid == ctaphid::BROADCAST_CID {
+            let new_cid = self.next_cid.get();
+            self.next_cid.set(if new_cid + 1 == ctaphid::BROADCAST_CID {
+                1
+            } else {
+                new_cid + 1
+            });
+            new_cid
+        } else if self.is_allocated(cid) {
+            if !self.abort(cid) {
+                self.send_error(cid, ctaphid::error::CHANNEL_BUSY);
+                return;
+            }
+            cid
+        } else {
+            self.send_error(cid, ctaphid::error::INVALID_CHANNEL);
+            return;
+        };
+
+        let mut response = [0; ctaphid::INIT_RESPONSE_LEN];
+        response[..8].copy_from_slice(&data[..ctaphid::INIT_NONCE_LEN]);
+        response[8..12].copy_from_slice(&new_cid.to_be_bytes());
+        response[12] = ctaphid::PROTOCOL_VERSION;
+        response[16] = ctaphid::capability::CBOR | ctaphid::capability::NMSG;
+        self.send_single(cid, command::INIT, &response);
+    }
+
+    /// Aborts the transaction of channel `cid`, if it has one. Returns
+    /// `false` if it is waiting for an operation that cannot be cancelled.
+    fn abort(&self, cid: u32) -> bool {
+        match self.transaction.get() {
+            Transaction::Receiving { cid: busy, .. } | Transaction::Sending { cid: busy, .. }
+                if busy == cid =>
+            {
+                let _ = self.alarm.disarm();
+                self.transaction.set(Transaction::Idle);
+                true
+            }
+            Transaction::Processing { cid: busy } if busy == cid => {
+                if self.step.get() != Step::UserPresence {
+                    return false;
+                }
+                self.stop_waiting();
+                self.step.set(Step::Idle);
+                self.transaction.set(Transaction::Idle);
+                true
+            }
+            _ => true,
+        }
+    }
+
+    /// Handles an initialization packet of a request.
+    fn receive_init(&self, cid: u32, cmd: u8, len: usize, data: &[u8]) {
+        if !self.is_allocated(cid) {
+            self.send_error(cid, ctaphid::error::INVALID_CHANNEL);
+            return;
+        }
+        match self.transaction.get() {
+            Transaction::Idle => {}
+            Transaction::Processing { cid: busy } if busy == cid && cmd == command::CANCEL => {
+                self.cancel();
+                return;
+            }
+            Transaction::Receiving { cid: busy, .. } if busy == cid => {
+                let _ = self.alarm.disarm();
+                self.transaction.set(Transaction::Idle);
+                self.send_error(cid, ctaphid::error::INVALID_SEQ);
+                return;
+            }
+            _ => {
+                if cmd != command::CANCEL {
+                    self.send_error(cid, ctaphid::error::CHANNEL_BUSY);
+                }
+                return;
+            }
+        }
+        if cmd == command::CANCEL {
+            // There is nothing to cancel.
+            return;
+        }
+        if len > self.max_message_len {
+            self.send_error(cid, ctaphid::error::INVALID_LEN);
+            return;
+        }
+
+        let received = min(len, data.len());
+        self.request.map(|request| {
+            request[..received].copy_from_slice(&data[..received]);
+        });
+        if received == len {
+            self.dispatch(cid, cmd, len);
+        } else {
+            self.transaction.set(Transaction::Receiving {
+                cid,
+                cmd,
+                len,
+                received,
+                seq: 0,
+            });
+            self.alarm.set_alarm(
+                self.alarm.now(),
+                self.alarm.ticks_from_ms(MESSAGE_TIMEOUT_MS),
+            );
+        }
+    }
+
+    /// Handles a continuation packet of a request. Continuation packets of
+    /// other channels are ignored.
+    fn receive_cont(&self, cid: u32, seq: u8, data: &[u8]) {
+        let Transaction::Receiving {
+            cid: receiving,
+            cmd,
+            len,
+            received,
+            seq: expected,
+        } = self.transaction.get()
+        else {
+            return;
+        };
+        if receiving != cid {
+            return;
+        }
+        let _ = self.alarm.disarm();
+        if seq != expected {
+            self.transaction.set(Transaction::Idle);
+            self.send_error(cid, ctaphid::error::INVALID_SEQ);
+            return;
+        }
+
+        let n = min(len - received, data.len());
+        self.request.map(|request| {
+            request[received..received + n].copy_from_slice(&data[..n]);
+        });
+        if received + n == len {
+            self.dispatch(cid, cmd, len);
+        } else {
+            self.transaction.set(Transaction::Receiving {
+                cid,
+                cmd,
+                len,
+                received: received + n,
+                seq: seq + 1,
+            });
+            self.alarm.set_alarm(
+                self.alarm.now(),
+                self.alarm.ticks_from_ms(MESSAGE_TIMEOUT_MS),
+            );
+        }
+    }
+
+    /// Carries out a complete request.
+    fn dispatch(&self, cid: u32, cmd: u8, len: usize) {
+        self.request_len.set(len);
+        match cmd {
+            command::PING => {
+                if let (Some(request), Some(response)) = (self.request.take(), self.response.take())
+                {
+                    response[..len].copy_from_slice(&request[..len]);
+                    self.request.replace(request);
+                    self.response.replace(response);
+                }
+                self.send_response(cid, command::PING, len);
+            }
+            command::CBOR => {
+                self.transaction.set(Transaction::Processing { cid });
+                if let Err(status) = self.process_cbor(len) {
+                    self.finish(status);
+                }
+            }
+            _ => {
+                self.transaction.set(Transaction::Idle);
+                self.send_error(cid, ctaphid::error::INVALID_CMD);
+            }
+        }
+    }
+
+    // CTAP2
+
+    /// Ends the command with a response made of `status` alone.
+    fn finish(&self, status: Status) {
+        self.step.set(Step::Idle);
+        self.response.map(|response| response[0] = status.0);
+        self.respond(1);
+    }
+
+    /// Sends the first `len` bytes of the response buffer as the response of
+    /// the command.
+    fn respond(&self, len: usize) {
+        self.step.set(Step::Idle);
+        if let Transaction::Processing { cid } = self.transaction.get() {
+            self.send_response(cid, command::CBOR, len);
+        }
+    }
+
+    fn process_cbor(&self, len: usize) -> Result<(), Status> {
+        let request = self.request.map_or(Err(Status::OTHER), |request| {
+            let message = &request[..len];
+            match message.first() {
+                Some(&ctap_command::MAKE_CREDENTIAL) => parse_make_credential(message),
+                Some(&ctap_command::GET_ASSERTION) => parse_get_assertion(message),
+                Some(&ctap_command::RESET) => Ok(Request {
+                    command: ctap_command::RESET,
+                    ..Request::default()
+                }),
+                Some(&ctap_command::GET_INFO) => Ok(Request {
+                    command: ctap_command::GET_INFO,
+                    ..Request::default()
+                }),
+                // Assertions are only made for one credential.
+                Some(&ctap_command::GET_NEXT_ASSERTION) => Err(Status::NOT_ALLOWED),
+                Some(_) => Err(Status::INVALID_COMMAND),
+                None => Err(Status::INVALID_LENGTH),
+            }
+        })?;
+        self.parsed.set(request);
+
+        match request.command {
+            ctap_command::GET_INFO => {
+                let len = self.write_info()?;
+                self.respond(len);
+                Ok(())
+            }
+            ctap_command::RESET => {
+                self.wait_for_user();
+                Ok(())
+            }
+            _ if !self.loaded.get() => self.load_credentials(),
+            _ => self.hash_rp_id(),
+        }
+    }
+
+    /// Writes the authenticatorGetInfo response (sect. 5.4).
+    fn write_info(&self) -> Result<usize, Status> {
+        self.response.map_or(Err(Status::OTHER), |response| {
+            let mut w = Writer::new(response);
+            w.raw(&[Status::OK.0]).map(4);
+            w.integer(1).array(1).text("FIDO_2_0");
+            w.integer(3).bytes(&self.aaguid);
+            w.integer(4).map(3);
+            w.text("rk").boolean(true);
+            w.text("up").boolean(true);
+            w.text("plat").boolean(false);
+            w.integer(5).integer(self.max_message_len as i64);
+            Ok(w.finish()?)
+        })
+    }
+
+    /// Runs `f` on the credential table.
+    fn with_table<R>(&self, f: impl FnOnce(&mut Table) -> R) -> Option<R> {
+        let header_len = self.kv.header_size();
+        self.kv_value
+            .map(|value| f(&mut Table::new(&mut value[header_len..])))
+    }
+
+    /// Takes the KV key buffer, holding the key of the credential table.
+    fn kv_key(&self) -> Option<SubSliceMut<'static, u8>> {
+        let key = self.kv_key.take()?;
+        key[..credentials::KV_KEY.len()].copy_from_slice(credentials::KV_KEY);
+        let mut key = SubSliceMut::new(key);
+        key.slice(..credentials::KV_KEY.len());
+        Some(key)
+    }
+
+    fn load_credentials(&self) -> Result<(), Status> {
+        let key = self.kv_key().ok_or(Status::OTHER)?;
+        let Some(value) = self.kv_value.take() else {
+            self.kv_key.replace(key.take());
+            return Err(Status::OTHER);
+        };
+        self.kv
+            .get(key, SubSliceMut::new(value), self.permissions)
+            .map_err(|(key, value, _)| {
+                self.kv_key.replace(key.take());
+                self.kv_value.replace(value.take());
+                Status::OTHER
+            })?;
+        self.step.set(Step::Loading);
+        Ok(())
+    }
+
+    fn store_credentials(&self) -> Result<(), Status> {
+        let key = self.kv_key().ok_or(Status::OTHER)?;
+        let Some(value) = self.kv_value.take() else {
+            self.kv_key.replace(key.take());
+            return Err(Status::OTHER);
+        };
+        let mut value = SubSliceMut::new(value);
+        value.slice(..self.kv.header_size() + credentials::RECORD_LEN);
+        self.kv
+            .set(key, value, self.permissions)
+            .map_err(|(key, value, _)| {
+                self.kv_key.replace(key.take());
+                self.kv_value.replace(value.take());
+                // The table no longer matches the stored one.
+                self.loaded.set(false);
+                Status::OTHER
+            })?;
+        self.step.set(Step::Storing);
+        Ok(())
+    }
+
+    /// Starts hashing `len` bytes at `offset` of `buffer`.
+    fn hash(&self, buffer: &'static mut [u8], offset: usize, len: usize) -> Result<(), Status> {
+        let mut data = SubSliceMut::new(buffer);
+        data.slice(offset..offset + len);
+        let _ = self.digest.set_mode_sha256();
+        self.digest.add_mut_data(data).map_err(|(_, data)| {
+            match self.step.get() {
+                Step::HashingRpId => self.request.replace(data.take()),
+                _ => self.response.replace(data.take()),
+            };
+            Status::OTHER
+        })
+    }
+
+    fn hash_rp_id(&self) -> Result<(), Status> {
+        let (offset, len) = self.parsed.get().rp_id;
+        let request = self.request.take().ok_or(Status::OTHER)?;
+        self.step.set(Step::HashingRpId);
+        self.hash(request, offset, len)
+    }
+
+    /// Looks up the credentials of the request once the relying party ID
+    /// hash is known.
+    fn find_credentials(&self) -> Result<(), Status> {
+        let mut request = self.parsed.get();
+        let rp_id_hash = self.rp_id_hash.get();
+        let message_len = self.request_len.get();
+
+        let found = self
+            .request
+            .map(|message| {
+                self.with_table(|table| match request.list {
+                    Some(list) => find_in_list(table, &message[..message_len], list, &rp_id_hash),
+                    None if request.command == ctap_command::GET_ASSERTION => {
+                        Ok(table.find_discoverable(&rp_id_hash))
+                    }
+                    None => Ok(None),
+                })
+            })
+            .flatten()
+            .ok_or(Status::OTHER)??;
+
+        if request.presence_status.is_none() {
+            if request.command == ctap_command::MAKE_CREDENTIAL {
+                if found.is_some() {
+                    request.presence_status = Some(Status::CREDENTIAL_EXCLUDED);
+                    self.parsed.set(request);
+                } else {
+                    let (offset, len) = request.user_id;
+                    let slot = self
+                        .request
+                        .map(|message| {
+                            self.with_table(|table| {
+                                table.slot(
+                                    &rp_id_hash,
+                                    &message[offset..offset + len],
+                                    request.discoverable,
+                                )
+                            })
+                        })
+                        .flatten()
+                        .flatten();
+                    self.credential.set(slot.ok_or(Status::KEY_STORE_FULL)?);
+                }
+            } else {
+                self.credential.set(found.ok_or(Status::NO_CREDENTIALS)?);
+            }
+        }
+
+        if request.user_presence || request.presence_status.is_some() {
+            self.wait_for_user();
+            Ok(())
+        } else {
+            self.user_present()
+        }
+    }
+
+    /// Starts waiting for the user to press the button.
+    fn wait_for_user(&self) {
+        self.step.set(Step::UserPresence);
+        self.presence_intervals.set(USER_PRESENCE_INTERVALS);
+        self.button
+            .enable_interrupts(gpio::InterruptEdge::EitherEdge);
+        self.send_keepalive();
+        self.alarm.set_alarm(
+            self.alarm.now(),
+            self.alarm.ticks_from_ms(KEEPALIVE_INTERVAL_MS),
+        );
+    }
+
+    fn stop_waiting(&self) {
+        self.button.disable_interrupts();
+        let _ = self.alarm.disarm();
+    }
+
+    fn send_keepalive(&self) {
+        if let Transaction::Processing { cid } = self.transaction.get() {
+            self.send_single(cid, command::KEEPALIVE, &[ctaphid::keepalive::UPNEEDED]);
+        }
+    }
+
+    /// Handles CANCEL, which ends a command waiting for the user.
+    fn cancel(&self) {
+        if self.step.get() == Step::UserPresence {
+            self.stop_waiting();
+            self.finish(Status::KEEPALIVE_CANCEL);
+        }
+    }
+
+    /// Carries on with the command once the user is present, or if the
+    /// command does not need the user.
+    fn user_present(&self) -> Result<(), Status> {
+        let request = self.parsed.get();
+        if let Some(status) = request.presence_status {
+            return Err(status);
+        }
+        match request.command {
+            ctap_command::MAKE_CREDENTIAL => {
+                let public_key = self.public_key.take().ok_or(Status::OTHER)?;
+                let Some(private_key) = self.private_key.take() else {
+                    self.public_key.replace(public_key);
+                    return Err(Status::OTHER);
+                };
+                self.signer.generate(public_key, private_key).map_err(
+                    |(_, public_key, private_key)| {
+                        self.public_key.replace(public_key);
+                        self.private_key.replace(private_key);
+                        Status::OTHER
+                    },
+                )?;
+                self.step.set(Step::Generating);
+                Ok(())
+            }
+            ctap_command::GET_ASSERTION => {
+                self.write_assertion()?;
+                self.hash_auth_data()
+            }
+            _ => {
+                let key = self.kv_key().ok_or(Status::OTHER)?;
+                self.kv.delete(key, self.permissions).map_err(|(key, _)| {
+                    self.kv_key.replace(key.take());
+                    Status::OTHER
+                })?;
+                self.step.set(Step::Resetting);
+                Ok(())
+            }
+        }
+    }
+
+    /// Stores the new credential with the key pair, and writes the start of
+    /// the authenticatorMakeCredential response (sect. 5.1) up to the
+    /// authenticator data.
+    fn create_credential(
+        &self,
+        public_key: &[u8; PUBLIC_KEY_LEN],
+        private_key: &[u8; PRIVATE_KEY_LEN],
+    ) -> Result<(), Status> {
+        let request = self.parsed.get();
+        let rp_id_hash = self.rp_id_hash.get();
+        // The x coordinate of the public key is random, which makes it a
+        // good credential ID.
+        let credential_id = &public_key[..CREDENTIAL_ID_LEN];
+        let (offset, len) = request.user_id;
+        self.request
+            .map(|message| {
+                self.with_table(|table| {
+                    table.store(
+                        self.credential.get(),
+                        &rp_id_hash,
+                        credential_id,
+                        private_key,
+                        request.discoverable,
+                        &message[offset..offset + len],
+                    )
+                })
+            })
+            .flatten()
+            .ok_or(Status::OTHER)?;
+
+        self.response.map_or(Err(Status::OTHER), |response| {
+            let mut w = Writer::new(response);
+            w.raw(&[Status::OK.0]).map(3);
+            w.integer(1).text("packed");
+            w.integer(2).bytes_header(ATTESTED_AUTH_DATA_LEN);
+            let start = w.position();
+            w.raw(&rp_id_hash)
+                .raw(&[FLAG_USER_PRESENT | FLAG_ATTESTED_CREDENTIAL_DATA])
+                .raw(&0u32.to_be_bytes());
+            w.raw(&self.aaguid)
+                .raw(&(CREDENTIAL_ID_LEN as u16).to_be_bytes())
+                .raw(credential_id);
+            w.map(5);
+            w.integer(1).integer(2); // kty: EC2
+            w.integer(3).integer(ES256); // alg
+            w.integer(-1).integer(1); // crv: P-256
+            w.integer(-2).bytes(&public_key[..32]);
+            w.integer(-3).bytes(&public_key[32..]);
+            self.auth_data.set((start, w.position() - start));
+            w.raw(&request.client_data_hash);
+            w.finish()?;
+            Ok(())
+        })
+    }
+
+    /// Writes the start of the authenticatorGetAssertion response (sect. 5.2)
+    /// up to the authenticator data.
+    fn write_assertion(&self) -> Result<(), Status> {
+        let request = self.parsed.get();
+        let index = self.credential.get();
+        let mut credential_id = [0; CREDENTIAL_ID_LEN];
+        self.with_table(|table| credential_id.copy_from_slice(table.credential_id(index)))
+            .ok_or(Status::OTHER)?;
+        let flags = if request.user_presence {
+            FLAG_USER_PRESENT
+        } else {
+            0
+        };
+
+        self.response.map_or(Err(Status::OTHER), |response| {
+            let mut w = Writer::new(response);
+            // Discoverable credentials also return the user.
+            w.raw(&[Status::OK.0])
+                .map(if request.list.is_none() { 4 } else { 3 });
+            w.integer(1).map(2);
+            w.text("id").bytes(&credential_id);
+            w.text("type").text("public-key");
+            w.integer(2).bytes_header(AUTH_DATA_LEN);
+            let start = w.position();
+            w.raw(&self.rp_id_hash.get())
+                .raw(&[flags])
+                .raw(&0u32.to_be_bytes());
+            self.auth_data.set((start, w.position() - start));
+            w.raw(&request.client_data_hash);
+            w.finish()?;
+            Ok(())
+        })
+    }
+
+    /// Writes the end of the response after the authenticator data.
+    fn write_signature(&self, signature: &[u8; SIGNATURE_LEN]) -> Result<usize, Status> {
+        let request = self.parsed.get();
+        let (start, len) = self.auth_data.get();
+        let index = self.credential.get();
+        let mut user_id = [0; credentials::MAX_USER_ID_LEN];
+        let user_id_len = self
+            .with_table(|table| {
+                let id = table.user_id(index);
+                user_id[..id.len()].copy_from_slice(id);
+                id.len()
+            })
+            .ok_or(Status::OTHER)?;
+
+        self.response.map_or(Err(Status::OTHER), |response| {
+            let mut w = Writer::at(response, start + len);
+            if request.command == ctap_command::MAKE_CREDENTIAL {
+                w.integer(3).map(2);
+                w.text("alg").integer(ES256);
+                w.text("sig");
+                write_der_signature(&mut w, signature);
+            } else {
+                w.integer(3);
+                write_der_signature(&mut w, signature);
+                if request.list.is_none() {
+                    w.integer(4).map(1);
+                    w.text("id").bytes(&user_id[..user_id_len]);
+                }
+            }
+            Ok(w.finish()?)
+        })
+    }
+
+    /// Starts hashing the authenticator data followed by the client data
+    /// hash, the data to sign.
+    fn hash_auth_data(&self) -> Result<(), Status> {
+        let (start, len) = self.auth_data.get();
+        let response = self.response.take().ok_or(Status::OTHER)?;
+        self.step.set(Step::HashingAuthData);
+        self.hash(response, start, len + HASH_LEN)
+    }
+
+    /// Selects the key of the credential for signing.
+    fn set_key(&self) -> Result<(), Status> {
+        let key = self.private_key.take().ok_or(Status::OTHER)?;
+        let index = self.credential.get();
+        self.with_table(|table| key.copy_from_slice(table.private_key(index)));
+        self.signer.set_key(key).map_err(|(_, key)| {
+            key.fill(0);
+            self.private_key.replace(key);
+            Status::OTHER
+        })?;
+        self.step.set(Step::SettingKey);
+        Ok(())
+    }
+
+    fn sign(&self) -> Result<(), Status> {
+        let hash = self.hash.take().ok_or(Status::OTHER)?;
+        let Some(signature) = self.signature.take() else {
+            self.hash.replace(hash);
+            return Err(Status::OTHER);
+        };
+        self.signer
+            .sign(hash, signature)
+            .map_err(|(_, hash, signature)| {
+                self.hash.replace(hash);
+                self.signature.replace(signature);
+                Status::OTHER
+            })?;
+        self.step.set(Step::Signing);
+        Ok(())
+    }
+
+    /// Continues the command with `next` if the current step succeeded.
+    fn continue_with(&self, result: Result<(), Status>, next: impl FnOnce() -> Result<(), Status>) {
+        if let Err(status) = result.and_then(|()| next()) {
+            self.finish(status);
+        }
+    }
+}
+
+impl<
+        'a,
+        U: usb_hid::UsbHid<'a, [u8; PACKET_LEN]>,
+        A: time::Alarm<'a>,
+        H: digest::Digest<'a, HASH_LEN> + digest::Sha256,
+        S: signature::SignatureSign<'a, HASH_LEN, SIGNATURE_LEN>
+            + keys::SetKeyBySlice<'a, PRIVATE_KEY_LEN>
+            + keys::KeyPairGenerate<'a, PUBLIC_KEY_LEN, PRIVATE_KEY_LEN>,
+    > usb_hid::Client<'a, [u8; PACKET_LEN]> for Authenticator<'a, U, A, H, S>
+{
+    fn packet_received(
+        &'a self,
+        _result: Result<(), ErrorCode>,
+        buffer: &'static mut [u8; PACKET_LEN],
+        _endpoint: usize,
+    ) {
+        // The HID device does not expect the buffer back before this returns.
+        self.rx_packet.replace(buffer);
+        self.deferred_call.set();
+    }
+
+    fn packet_transmitted(
+        &'a self,
+        _result: Result<(), ErrorCode>,
+        buffer: &'static mut [u8; PACKET_LEN],
+        _endpoint: usize,
+    ) {
+        self.tx_packet.replace(buffer);
+        self.send_next_packet();
+    }
+
+    fn can_receive(&'a self) -> bool {
+        true
+    }
+}
+
+impl<
+        'a,
+        U: usb_hid::UsbHid<'a, [u8; PACKET_LEN]>,
+        A: time::Alarm<'a>,
+        H: digest::Digest<'a, HASH_LEN> + digest::Sha256,
+        S: signature::SignatureSign<'a, HASH_LEN, SIGNATURE_LEN>
+            + keys::SetKeyBySlice<'a, PRIVATE_KEY_LEN>
+            + keys::KeyPairGenerate<'a, PUBLIC_KEY_LEN, PRIVATE_KEY_LEN>,
+    > DeferredCallClient for Authenticator<'a, U, A, H, S>
+{
+    fn handle_deferred_call(&self) {
+        if let Some(packet) = self.rx_packet.take() {
+            self.receive_packet(packet);
+            if let Err((_, packet)) = self.hid.receive_buffer(packet) {
+                self.rx_packet.replace(packet);
+            }
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+impl<
+        'a,
+        U: usb_hid::UsbHid<'a, [u8; PACKET_LEN]>,
+        A: time::Alarm<'a>,
+        H: digest::Digest<'a, HASH_LEN> + digest::Sha256,
+        S: signature::SignatureSign<'a, HASH_LEN, SIGNATURE_LEN>
+            + keys::SetKeyBySlice<'a, PRIVATE_KEY_LEN>
+            + keys::KeyPairGenerate<'a, PUBLIC_KEY_LEN, PRIVATE_KEY_LEN>,
+    > time::AlarmClient for Authenticator<'a, U, A, H, S>
+{
+    fn alarm(&self) {
+        match self.transaction.get() {
+            Transaction::Receiving { cid, .. } => {
+                self.transaction.set(Transaction::Idle);
+                self.send_error(cid, ctaphid::error::MSG_TIMEOUT);
+            }
+            Transaction::Processing { .. } if self.step.get() == Step::UserPresence => {
+                let intervals = self.presence_intervals.get().saturating_sub(1);
+                self.presence_intervals.set(intervals);
+                if intervals == 0 {
+                    self.stop_waiting();
+                    self.finish(Status::USER_ACTION_TIMEOUT);
+                } else {
+                    self.send_keepalive();
+                    self.alarm.set_alarm(
+                        self.alarm.now(),
+                        self.alarm.ticks_from_ms(KEEPALIVE_INTERVAL_MS),
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<
+        'a,
+        U: usb_hid::UsbHid<'a, [u8; PACKET_LEN]>,
+        A: time::Alarm<'a>,
+        H: digest::Digest<'a, HASH_LEN> + digest::Sha256,
+        S: signature::SignatureSign<'a, HASH_LEN, SIGNATURE_LEN>
+            + keys::SetKeyBySlice<'a, PRIVATE_KEY_LEN>
+            + keys::KeyPairGenerate<'a, PUBLIC_KEY_LEN, PRIVATE_KEY_LEN>,
+    > gpio::Client for Authenticator<'a, U, A, H, S>
+{
+    fn fired(&self) {
+        if self.step.get() == Step::UserPresence
+            && self.button.read_activation(self.button_mode) == gpio::ActivationState::Active
+        {
+            self.stop_waiting();
+            if let Err(status) = self.user_present() {
+                self.finish(status);
+            }
+        }
+    }
+}
+
+impl<
+        'a,
+        U: usb_hid::UsbHid<'a, [u8; PACKET_LEN]>,
+        A: time::Alarm<'a>,
+        H: digest::Digest<'a, HASH_LEN> + digest::Sha256,
+        S: signature::SignatureSign<'a, HASH_LEN, SIGNATURE_LEN>
+            + keys::SetKeyBySlice<'a, PRIVATE_KEY_LEN>
+            + keys::KeyPairGenerate<'a, PUBLIC_KEY_LEN, PRIVATE_KEY_LEN>,
+    > kv::KVClient for Authenticator<'a, U, A, H, S>
+{
+    fn get_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.kv_key.replace(key.take());
+        self.kv_value.replace(value.take());
+        if self.step.get() != Step::Loading {
+            return;
+        }
+        let result = match result {
+            Ok(()) => Ok(()),
+            // There are no credentials yet.
+            Err(ErrorCode::NOSUPPORT) => {
+                self.with_table(|table| table.clear());
+                Ok(())
+            }
+            Err(_) => Err(Status::OTHER),
+        };
+        self.loaded.set(result.is_ok());
+        self.continue_with(result, || self.hash_rp_id());
+    }
+
+    fn set_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.kv_key.replace(key.take());
+        self.kv_value.replace(value.take());
+        if self.step.get() != Step::Storing {
+            return;
+        }
+        if result.is_err() {
+            self.loaded.set(false);
+        }
+        self.continue_with(result.map_err(|_| Status::OTHER), || self.hash_auth_data());
+    }
+
+    fn add_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.kv_key.replace(key.take());
+        self.kv_value.replace(value.take());
+    }
+
+    fn update_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.kv_key.replace(key.take());
+        self.kv_value.replace(value.take());
+    }
+
+    fn delete_complete(&self, result: Result<(), ErrorCode>, key: SubSliceMut<'static, u8>) {
+        self.kv_key.replace(key.take());
+        if self.step.get() != Step::Resetting {
+            return;
+        }
+        match result {
+            // There were no credentials.
+            Ok(()) | Err(ErrorCode::NOSUPPORT) => {
+                self.with_table(|table| table.clear());
+                self.loaded.set(true);
+                self.finish(Status::OK);
+            }
+            Err(_) => self.finish(Status::OTHER),
+        }
+    }
+}
+
+impl<
+        'a,
+        U: usb_hid::UsbHid<'a, [u8; PACKET_LEN]>,
+        A: time::Alarm<'a>,
+        H: digest::Digest<'a, HASH_LEN> + digest::Sha256,
+        S: signature::SignatureSign<'a, HASH_LEN, SIGNATURE_LEN>
+            + keys::SetKeyBySlice<'a, PRIVATE_KEY_LEN>
+            + keys::KeyPairGenerate<'a, PUBLIC_KEY_LEN, PRIVATE_KEY_LEN>,
+    > digest::ClientData<HASH_LEN> for Authenticator<'a, U, A, H, S>
+{
+    fn add_data_done(&self, _result: Result<(), ErrorCode>, _data: SubSlice<'static, u8>) {}
+
+    fn add_mut_data_done(&self, result: Result<(), ErrorCode>, data: SubSliceMut<'static, u8>) {
+        match self.step.get() {
+            Step::HashingRpId => self.request.replace(data.take()),
+            Step::HashingAuthData => self.response.replace(data.take()),
+            _ => return,
+        };
+        let result = result.map_err(|_| Status::OTHER);
+        self.continue_with(result, || {
+            let digest = self.hash.take().ok_or(Status::OTHER)?;
+            self.digest.run(digest).map_err(|(_, digest)| {
+                self.hash.replace(digest);
+                Status::OTHER
+            })
+        });
+    }
+}
+
+impl<
+        'a,
+        U: usb_hid::UsbHid<'a, [u8; PACKET_LEN]>,
+        A: time::Alarm<'a>,
+        H: digest::Digest<'a, HASH_LEN> + digest::Sha256,
+        S: signature::SignatureSign<'a, HASH_LEN, SIGNATURE_LEN>
+            + keys::SetKeyBySlice<'a, PRIVATE_KEY_LEN>
+            + keys::KeyPairGenerate<'a, PUBLIC_KEY_LEN, PRIVATE_KEY_LEN>,
+    > digest::ClientHash<HASH_LEN> for Authenticator<'a, U, A, H, S>
+{
+    fn hash_done(&self, result: Result<(), ErrorCode>, digest: &'static mut [u8; HASH_LEN]) {
+        let hash = *digest;
+        self.hash.replace(digest);
+        let result = result.map_err(|_| Status::OTHER);
+        match self.step.get() {
+            Step::HashingRpId => {
+                self.rp_id_hash.set(hash);
+                self.continue_with(result, || self.find_credentials());
+            }
+            Step::HashingAuthData => self.continue_with(result, || self.set_key()),
+            _ => {}
+        }
+    }
+}
+
+impl<
+        'a,
+        U: usb_hid::UsbHid<'a, [u8; PACKET_LEN]>,
+        A: time::Alarm<'a>,
+        H: digest::Digest<'a, HASH_LEN> + digest::Sha256,
+        S: signature::SignatureSign<'a, HASH_LEN, SIGNATURE_LEN>
+            + keys::SetKeyBySlice<'a, PRIVATE_KEY_LEN>
+            + keys::KeyPairGenerate<'a, PUBLIC_KEY_LEN, PRIVATE_KEY_LEN>,
+    > digest::ClientVerify<HASH_LEN> for Authenticator<'a, U, A, H, S>
+{
+    fn verification_done(
+        &self,
+        _result: Result<bool, ErrorCode>,
+        _compare: &'static mut [u8; HASH_LEN],
+    ) {
+    }
+}
+
+impl<
+        'a,
+        U: usb_hid::UsbHid<'a, [u8; PACKET_LEN]>,
+        A: time::Alarm<'a>,
+        H: digest::Digest<'a, HASH_LEN> + digest::Sha256,
+        S: signature::SignatureSign<'a, HASH_LEN, SIGNATURE_LEN>
+            + keys::SetKeyBySlice<'a, PRIVATE_KEY_LEN>
+            + keys::KeyPairGenerate<'a, PUBLIC_KEY_LEN, PRIVATE_KEY_LEN>,
+    > keys::KeyPairGenerateClient<PUBLIC_KEY_LEN, PRIVATE_KEY_LEN>
+    for Authenticator<'a, U, A, H, S>
+{
+    fn generation_done(
+        &self,
+        result: Result<(), ErrorCode>,
+        public_key: &'static mut [u8; PUBLIC_KEY_LEN],
+        private_key: &'static mut [u8; PRIVATE_KEY_LEN],
+    ) {
+        let result = result
+            .map_err(|_| Status::OTHER)
+            .and_then(|()| self.create_credential(public_key, private_key));
+        private_key.fill(0);
+        self.public_key.replace(public_key);
+        self.private_key.replace(private_key);
+        if self.step.get() == Step::Generating {
+            self.continue_with(result, || self.store_credentials());
+        }
+    }
+}
+
+impl<
+        'a,
+        U: usb_hid::UsbHid<'a, [u8; PACKET_LEN]>,
+        A: time::Alarm<'a>,
+        H: digest::Digest<'a, HASH_LEN> + digest::Sha256,
+        S: signature::SignatureSign<'a, HASH_LEN, SIGNATURE_LEN>
+            + keys::SetKeyBySlice<'a, PRIVATE_KEY_LEN>
+            + keys::KeyPairGenerate<'a, PUBLIC_KEY_LEN, PRIVATE_KEY_LEN>,
+    > keys::SetKeyBySliceClient<PRIVATE_KEY_LEN> for Authenticator<'a, U, A, H, S>
+{
+    fn set_key_done(&self, key: &'static mut [u8; PRIVATE_KEY_LEN], result: Result<(), ErrorCode>) {
+        key.fill(0);
+        self.private_key.replace(key);
+        if self.step.get() == Step::SettingKey {
+            self.continue_with(result.map_err(|_| Status::OTHER), || self.sign());
+        }
+    }
+}
+
+impl<
+        'a,
+        U: usb_hid::UsbHid<'a, [u8; PACKET_LEN]>,
+        A: time::Alarm<'a>,
+        H: digest::Digest<'a, HASH_LEN> + digest::Sha256,
+        S: signature::SignatureSign<'a, HASH_LEN, SIGNATURE_LEN>
+            + keys::SetKeyBySlice<'a, PRIVATE_KEY_LEN>
+            + keys::KeyPairGenerate<'a, PUBLIC_KEY_LEN, PRIVATE_KEY_LEN>,
+    > signature::ClientSign<HASH_LEN, SIGNATURE_LEN> for Authenticator<'a, U, A, H, S>
+{
+    fn signing_done(
+        &self,
+        result: Result<(), ErrorCode>,
+        hash: &'static mut [u8; HASH_LEN],
+        signature: &'static mut [u8; SIGNATURE_LEN],
+    ) {
+        let result = result
+            .map_err(|_| Status::OTHER)
+            .and_then(|()| self.write_signature(signature));
+        self.hash.replace(hash);
+        self.signature.replace(signature);
+        if self.step.get() == Step::Signing {
+            match result {
+                Ok(len) => self.respond(len),
+                Err(status) => self.finish(status),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIENT_DATA_HASH: [u8; HASH_LEN] = [0x5a; HASH_LEN];
+
+    fn ok(result: Result<Request, Status>) -> Request {
+        match result {
+            Ok(request) => request,
+            Err(status) => panic!("parsing failed with {:?}", status),
+        }
+    }
+
+    /// Writes the parameters of a makeCredential request after the command
+    /// byte, letting `options` append parameters 5 and above.
+    fn make_credential(
+        buf: &mut [u8],
+        algorithm: i64,
+        extra: usize,
+        options: impl FnOnce(&mut Writer),
+    ) -> usize {
+        buf[0] = ctap_command::MAKE_CREDENTIAL;
+        let mut w = Writer::at(buf, 1);
+        w.map(4 + extra)
+            .integer(1)
+            .bytes(&CLIENT_DATA_HASH)
+            .integer(2)
+            .map(2)
+            .text("id")
+            .text("example.com")
+            .text("name")
+            .text("Example")
+            .integer(3)
+            .map(2)
+            .text("id")
+            .bytes(&[1, 2, 3, 4])
+            .text("displayName")
+            .text("User")
+            .integer(4)
+            .array(2)
+            .map(2)
+            .text("alg")
+            .integer(-257)
+            .text("type")
+            .text("public-key")
+            .map(2)
+            .text("alg")
+            .integer(algorithm)
+            .text("type")
+            .text("public-key");
+        options(&mut w);
+        w.finish().unwrap()
+    }
+
+    /// Writes a getAssertion request allowing the credentials in `allow`.
+    fn get_assertion(
+        buf: &mut [u8],
+        allow: &[&[u8]],
+        extra: usize,
+        options: impl FnOnce(&mut Writer),
+    ) -> usize {
+        buf[0] = ctap_command::GET_ASSERTION;
+        let mut w = Writer::at(buf, 1);
+        w.map(3 + extra)
+            .integer(1)
+            .text("example.com")
+            .integer(2)
+            .bytes(&CLIENT_DATA_HASH)
+            .integer(3)
+            .array(allow.len());
+        for id in allow {
+            w.map(2)
+                .text("id")
+                .bytes(id)
+                .text("type")
+                .text("public-key");
+        }
+        options(&mut w);
+        w.finish().unwrap()
+    }
+
+    #[test]
+    fn make_credential_request() {
+        let mut buf = [0; 256];
+        let len = make_credential(&mut buf, ES256, 2, |w| {
+            w.integer(5)
+                .array(0)
+                .integer(7)
+                .map(2)
+                .text("rk")
+                .boolean(true)
+                .text("ext")
+                .integer(0);
+        });
+        let message = &buf[..len];
+
+        let request = ok(parse_make_credential(message));
+        assert_eq!(request.client_data_hash, CLIENT_DATA_HASH);
+        let (offset, len) = request.rp_id;
+        assert_eq!(&message[offset..offset + len], b"example.com");
+        let (offset, len) = request.user_id;
+        assert_eq!(&message[offset..offset + len], &[1, 2, 3, 4]);
+        assert_eq!(Reader::at(message, request.list.unwrap()).array(), Ok(0));
+        assert!(request.discoverable);
+        assert!(request.user_presence);
+        assert_eq!(request.presence_status, None);
+    }
+
+    #[test]
+    fn make_credential_errors() {
+        let mut buf = [0; 256];
+
+        let len = make_credential(&mut buf, -8, 0, |_| {});
+        assert_eq!(
+            parse_make_credential(&buf[..len]).err(),
+            Some(Status::UNSUPPORTED_ALGORITHM)
+        );
+
+        // Without pubKeyCredParams: keep the first three parameters only.
+        buf[1] = 0xa3;
+        let mut r = Reader::at(&buf, 2);
+        for _ in 0..3 * 2 {
+            r.skip().unwrap();
+        }
+        assert_eq!(
+            parse_make_credential(&buf[..r.position()]).err(),
+            Some(Status::MISSING_PARAMETER)
+        );
+
+        let len = make_credential(&mut buf, ES256, 1, |w| {
+            w.integer(7).map(1).text("up").boolean(false);
+        });
+        assert_eq!(
+            parse_make_credential(&buf[..len]).err(),
+            Some(Status::INVALID_OPTION)
+        );
+
+        let len = make_credential(&mut buf, ES256, 1, |w| {
+            w.integer(7).map(1).text("uv").boolean(true);
+        });
+        assert_eq!(
+            parse_make_credential(&buf[..len]).err(),
+            Some(Status::UNSUPPORTED_OPTION)
+        );
+
+        let len = make_credential(&mut buf, ES256, 1, |w| {
+            w.integer(8).bytes(&[0; 16]);
+        });
+        assert_eq!(
+            parse_make_credential(&buf[..len]).err(),
+            Some(Status::PIN_AUTH_INVALID)
+        );
+
+        // A client data hash of the wrong type and length.
+        let len = make_credential(&mut buf, ES256, 0, |_| {});
+        buf[3] = 0x78;
+        assert_eq!(
+            parse_make_credential(&buf[..len]).err(),
+            Some(Status::CBOR_UNEXPECTED_TYPE)
+        );
+        buf[3] = 0x58;
+        buf[4] = HASH_LEN as u8 - 1;
+        assert_eq!(
+            parse_make_credential(&buf[..len]).err(),
+            Some(Status::INVALID_LENGTH)
+        );
+    }
+
+    #[test]
+    fn make_credential_user_id_too_long() {
+        let mut buf = [0; 256];
+        buf[0] = ctap_command::MAKE_CREDENTIAL;
+        let mut w = Writer::at(&mut buf, 1);
+        w.map(1)
+            .integer(3)
+            .map(1)
+            .text("id")
+            .bytes(&[0; credentials::MAX_USER_ID_LEN + 1]);
+        let len = w.finish().unwrap();
+        assert_eq!(
+            parse_make_credential(&buf[..len]).err(),
+            Some(Status::INVALID_LENGTH)
+        );
+    }
+
+    #[test]
+    fn make_credential_truncated() {
+        let mut buf = [0; 256];
+        let len = make_credential(&mut buf, ES256, 1, |w| {
+            w.integer(7).map(1).text("rk").boolean(true);
+        });
+        ok(parse_make_credential(&buf[..len]));
+        for truncated in 0..len {
+            assert_eq!(
+                parse_make_credential(&buf[..truncated]).err(),
+                Some(Status::INVALID_CBOR),
+                "truncated to {} bytes",
+                truncated
+            );
+        }
+    }
+
+    #[test]
+    fn get_assertion_request() {
+        let mut buf = [0; 256];
+        let len = get_assertion(&mut buf, &[&[1; 16], &[2; 16]], 1, |w| {
+            w.integer(5).map(1).text("up").boolean(false);
+        });
+        let message = &buf[..len];
+
+        let request = ok(parse_get_assertion(message));
+        assert_eq!(request.client_data_hash, CLIENT_DATA_HASH);
+        let (offset, len) = request.rp_id;
+        assert_eq!(&message[offset..offset + len], b"example.com");
+        assert_eq!(Reader::at(message, request.list.unwrap()).array(), Ok(2));
+        assert!(!request.user_presence);
+
+        // An empty allow list is the same as no allow list.
+        let len = get_assertion(&mut buf, &[], 0, |_| {});
+        assert_eq!(ok(parse_get_assertion(&buf[..len])).list, None);
+
+        // Selecting the authenticator with an empty pinAuth.
+        let len = get_assertion(&mut buf, &[], 1, |w| {
+            w.integer(6).bytes(&[]);
+        });
+        let request = ok(parse_get_assertion(&buf[..len]));
+        assert_eq!(request.presence_status, Some(Status::PIN_NOT_SET));
+    }
+
+    #[test]
+    fn get_assertion_errors() {
+        let mut buf = [0; 256];
+
+        let len = get_assertion(&mut buf, &[], 1, |w| {
+            w.integer(5).map(1).text("rk").boolean(true);
+        });
+        assert_eq!(
+            parse_get_assertion(&buf[..len]).err(),
+            Some(Status::INVALID_OPTION)
+        );
+
+        buf[0] = ctap_command::GET_ASSERTION;
+        let mut w = Writer::at(&mut buf, 1);
+        w.map(1).integer(2).bytes(&CLIENT_DATA_HASH);
+        let len = w.finish().unwrap();
+        assert_eq!(
+            parse_get_assertion(&buf[..len]).err(),
+            Some(Status::MISSING_PARAMETER)
+        );
+
+        let mut w = Writer::at(&mut buf, 1);
+        w.map(1).integer(1).text("");
+        let len = w.finish().unwrap();
+        assert_eq!(
+            parse_get_assertion(&buf[..len]).err(),
+            Some(Status::INVALID_PARAMETER)
+        );
+
+        // Parameter keys must be integers.
+        let mut w = Writer::at(&mut buf, 1);
+        w.map(1).text("rpId").text("example.com");
+        let len = w.finish().unwrap();
+        assert_eq!(
+            parse_get_assertion(&buf[..len]).err(),
+            Some(Status::CBOR_UNEXPECTED_TYPE)
+        );
+    }
+
+    #[test]
+    fn get_assertion_truncated() {
+        let mut buf = [0; 256];
+        let len = get_assertion(&mut buf, &[&[1; 16]], 1, |w| {
+            w.integer(5).map(1).text("up").boolean(true);
+        });
+        ok(parse_get_assertion(&buf[..len]));
+        for truncated in 0..len {
+            assert_eq!(
+                parse_get_assertion(&buf[..truncated]).err(),
+                Some(Status::INVALID_CBOR),
+                "truncated to {} bytes",
+                truncated
+            );
+        }
+    }
+}
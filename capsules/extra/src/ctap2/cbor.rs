@@ -0,0 +1,484 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Minimal CBOR (RFC 8949) reader and writer for CTAP2 messages.
+//!
+//! CTAP2 only uses the canonical subset of CBOR: definite lengths, integers,
+//! byte and text strings, arrays, maps and simple values. Indefinite lengths,
+//! floats and tags are rejected by the reader. The reader borrows the
+//! message, so strings are returned as slices of it without copying.
+
+/// Major types.
+const UNSIGNED: u8 = 0;
+const NEGATIVE: u8 = 1;
+const BYTES: u8 = 2;
+const TEXT: u8 = 3;
+const ARRAY: u8 = 4;
+const MAP: u8 = 5;
+const SIMPLE: u8 = 7;
+
+/// Simple values.
+const FALSE: u64 = 20;
+const TRUE: u64 = 21;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    /// The message is not well-formed or uses unsupported encodings.
+    Malformed,
+    /// The item has a different type than expected.
+    UnexpectedType,
+    /// The writer ran out of space.
+    Overflow,
+}
+
+/// Reads items from a CBOR encoded message.
+pub struct Reader<'b> {
+    buf: &'b [u8],
+    pos: usize,
+}
+
+impl<'b> Reader<'b> {
+    pub fn new(buf: &'b [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    /// Creates a reader starting at offset `pos` of `buf`.
+    pub fn at(buf: &'b [u8], pos: usize) -> Self {
+        Self { buf, pos }
+    }
+
+    /// Returns the offset of the next item in the message.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Returns whether the whole message has been read.
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.buf.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'b [u8], Error> {
+        let end = self.pos.checked_add(len).ok_or(Error::Malformed)?;
+        let bytes = self.buf.get(self.pos..end).ok_or(Error::Malformed)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    /// Reads the header of an item, returning its major type and argument.
+    fn header(&mut self) -> Result<(u8, u64), Error> {
+        let initial = self.take(1)?[0];
+        let major = initial >> 5;
+        let argument = match initial & 0x1f {
+            info @ 0..=23 => info as u64,
+            24 => self.take(1)?[0] as u64,
+            25 => {
+                let b = self.take(2)?;
+                u16::from_be_bytes([b[0], b[1]]) as u64
+            }
+            26 => {
+                let b = self.take(4)?;
+                u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as u64
+            }
+            27 => {
+                let b = self.take(8)?;
+                u64::from_be_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]])
+            }
+            // Reserved values and indefinite lengths.
+            _ => return Err(Error::Malformed),
+        };
+        Ok((major, argument))
+    }
+
+    /// Reads the header of an item of major type `expected`.
+    fn expect(&mut self, expected: u8) -> Result<u64, Error> {
+        let start = self.pos;
+        let (major, argument) = self.header()?;
+        if major != expected {
+            self.pos = start;
+            return Err(Error::UnexpectedType);
+        }
+        Ok(argument)
+    }
+
+    fn length(&mut self, expected: u8) -> Result<usize, Error> {
+        usize::try_from(self.expect(expected)?).map_err(|_| Error::Malformed)
+    }
+
+    /// Reads an unsigned or negative integer.
+    pub fn integer(&mut self) -> Result<i64, Error> {
+        let start = self.pos;
+        let (major, argument) = self.header()?;
+        let value = i64::try_from(argument).map_err(|_| Error::Malformed)?;
+        match major {
+            UNSIGNED => Ok(value),
+            NEGATIVE => Ok(-1 - value),
+            _ => {
+                self.pos = start;
+                Err(Error::UnexpectedType)
+            }
+        }
+    }
+
+    /// Reads a byte string.
+    pub fn bytes(&mut self) -> Result<&'b [u8], Error> {
+        let len = self.length(BYTES)?;
+        self.take(len)
+    }
+
+    /// Reads a text string.
+    pub fn text(&mut self) -> Result<&'b str, Error> {
+        let len = self.length(TEXT)?;
+        core::str::from_utf8(self.take(len)?).map_err(|_| Error::Malformed)
+    }
+
+    /// Reads the header of an array, returning its number of items.
+    pub fn array(&mut self) -> Result<usize, Error> {
+        self.length(ARRAY)
+    }
+
+    /// Reads the header of a map, returning its number of pairs.
+    pub fn map(&mut self) -> Result<usize, Error> {
+        self.length(MAP)
+    }
+
+    /// Reads a boolean.
+    pub fn boolean(&mut self) -> Result<bool, Error> {
+        let start = self.pos;
+        match self.expect(SIMPLE)? {
+            FALSE => Ok(false),
+            TRUE => Ok(true),
+            _ => {
+                self.pos = start;
+                Err(Error::UnexpectedType)
+            }
+        }
+    }
+
+    /// Skips the next item, including all items nested in it.
+    pub fn skip(&mut self) -> Result<(), Error> {
+        let mut remaining: usize = 1;
+        while remaining > 0 {
+            remaining -= 1;
+            let (major, argument) = self.header()?;
+            let len = usize::try_from(argument).map_err(|_| Error::Malformed)?;
+            match major {
+                UNSIGNED | NEGATIVE | SIMPLE => {}
+                BYTES | TEXT => {
+                    self.take(len)?;
+                }
+                ARRAY => remaining = remaining.checked_add(len).ok_or(Error::Malformed)?,
+                MAP => {
+                    remaining = len
+                        .checked_mul(2)
+                        .and_then(|items| remaining.checked_add(items))
+                        .ok_or(Error::Malformed)?
+                }
+                // Tags.
+                _ => return Err(Error::Malformed),
+            }
+            // Every item takes at least a byte, which bounds the loop.
+            if remaining > self.buf.len() - self.pos {
+                return Err(Error::Malformed);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes items into a buffer.
+///
+/// Errors are sticky: after the buffer overflows, further writes are ignored
+/// and `finish()` returns `Error::Overflow`.
+pub struct Writer<'b> {
+    buf: &'b mut [u8],
+    pos: usize,
+    overflow: bool,
+}
+
+impl<'b> Writer<'b> {
+    pub fn new(buf: &'b mut [u8]) -> Self {
+        Self::at(buf, 0)
+    }
+
+    /// Creates a writer starting at offset `pos` of `buf`.
+    pub fn at(buf: &'b mut [u8], pos: usize) -> Self {
+        Self {
+            buf,
+            pos,
+            overflow: false,
+        }
+    }
+
+    /// Returns the offset of the next item in the buffer.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Writes `bytes` as they are, without a header.
+    pub fn raw(&mut self, bytes: &[u8]) -> &mut Self {
+        match self.buf.get_mut(self.pos..self.pos + bytes.len()) {
+            Some(dest) if !self.overflow => {
+                dest.copy_from_slice(bytes);
+                self.pos += bytes.len();
+            }
+            _ => self.overflow = true,
+        }
+        self
+    }
+
+    fn header(&mut self, major: u8, argument: u64) -> &mut Self {
+        let major = major << 5;
+        if argument < 24 {
+            self.raw(&[major | argument as u8])
+        } else if argument <= u8::MAX as u64 {
+            self.raw(&[major | 24, argument as u8])
+        } else if argument <= u16::MAX as u64 {
+            self.raw(&[major | 25])
+                .raw(&(argument as u16).to_be_bytes())
+        } else if argument <= u32::MAX as u64 {
+            self.raw(&[major | 26])
+                .raw(&(argument as u32).to_be_bytes())
+        } else {
+            self.raw(&[major | 27]).raw(&argument.to_be_bytes())
+        }
+    }
+
+    pub fn integer(&mut self, value: i64) -> &mut Self {
+        if value < 0 {
+            self.header(NEGATIVE, (-1 - value) as u64)
+        } else {
+            self.header(UNSIGNED, value as u64)
+        }
+    }
+
+    /// Writes the header of a byte string of `len` bytes. The contents must
+    /// follow with `raw()`.
+    pub fn bytes_header(&mut self, len: usize) -> &mut Self {
+        self.header(BYTES, len as u64)
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.bytes_header(bytes.len()).raw(bytes)
+    }
+
+    pub fn text(&mut self, text: &str) -> &mut Self {
+        self.header(TEXT, text.len() as u64).raw(text.as_bytes())
+    }
+
+    /// Writes the header of an array of `len` items.
+    pub fn array(&mut self, len: usize) -> &mut Self {
+        self.header(ARRAY, len as u64)
+    }
+
+    /// Writes the header of a map of `len` pairs.
+    pub fn map(&mut self, len: usize) -> &mut Self {
+        self.header(MAP, len as u64)
+    }
+
+    pub fn boolean(&mut self, value: bool) -> &mut Self {
+        self.header(SIMPLE, if value { TRUE } else { FALSE })
+    }
+
+    /// Returns the offset after the last item, or `Error::Overflow` if the
+    /// items did not fit.
+    pub fn finish(&self) -> Result<usize, Error> {
+        if self.overflow {
+            Err(Error::Overflow)
+        } else {
+            Ok(self.pos)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Examples of RFC 8949 Appendix A.
+    const INTEGERS: &[(i64, &[u8])] = &[
+        (0, &[0x00]),
+        (23, &[0x17]),
+        (24, &[0x18, 0x18]),
+        (100, &[0x18, 0x64]),
+        (1000, &[0x19, 0x03, 0xe8]),
+        (1000000, &[0x1a, 0x00, 0x0f, 0x42, 0x40]),
+        (
+            1000000000000,
+            &[0x1b, 0x00, 0x00, 0x00, 0xe8, 0xd4, 0xa5, 0x10, 0x00],
+        ),
+        (-1, &[0x20]),
+        (-10, &[0x29]),
+        (-100, &[0x38, 0x63]),
+        (-1000, &[0x39, 0x03, 0xe7]),
+    ];
+
+    /// `[1, [2, 3], [4, 5]]`
+    const NESTED_ARRAY: &[u8] = &[0x83, 0x01, 0x82, 0x02, 0x03, 0x82, 0x04, 0x05];
+    /// `{"a": 1, "b": [2, 3]}`
+    const MAP_WITH_ARRAY: &[u8] = &[0xa2, 0x61, 0x61, 0x01, 0x61, 0x62, 0x82, 0x02, 0x03];
+
+    #[test]
+    fn integers() {
+        for &(value, encoded) in INTEGERS {
+            let mut r = Reader::new(encoded);
+            assert_eq!(r.integer(), Ok(value), "{:x?}", encoded);
+            assert!(r.is_empty());
+
+            let mut buf = [0; 9];
+            let mut w = Writer::new(&mut buf);
+            w.integer(value);
+            assert_eq!(w.finish(), Ok(encoded.len()));
+            assert_eq!(&buf[..encoded.len()], encoded);
+        }
+
+        // Larger than i64::MAX.
+        let mut r = Reader::new(&[0x1b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(r.integer(), Err(Error::Malformed));
+    }
+
+    #[test]
+    fn strings() {
+        let mut r = Reader::new(&[
+            0x44, 0x01, 0x02, 0x03, 0x04, 0x64, b'I', b'E', b'T', b'F', 0x60,
+        ]);
+        assert_eq!(r.bytes(), Ok(&[1, 2, 3, 4][..]));
+        assert_eq!(r.text(), Ok("IETF"));
+        assert_eq!(r.text(), Ok(""));
+        assert!(r.is_empty());
+
+        // A one byte length.
+        let mut message = [0; 26];
+        message[..2].copy_from_slice(&[0x58, 24]);
+        assert_eq!(Reader::new(&message).bytes(), Ok(&[0; 24][..]));
+
+        // Invalid UTF-8.
+        assert_eq!(Reader::new(&[0x61, 0xff]).text(), Err(Error::Malformed));
+    }
+
+    #[test]
+    fn arrays_and_maps() {
+        let mut r = Reader::new(NESTED_ARRAY);
+        assert_eq!(r.array(), Ok(3));
+        assert_eq!(r.integer(), Ok(1));
+        assert_eq!(r.array(), Ok(2));
+        r.skip().unwrap();
+        r.skip().unwrap();
+        r.skip().unwrap();
+        assert!(r.is_empty());
+
+        let mut r = Reader::new(MAP_WITH_ARRAY);
+        assert_eq!(r.map(), Ok(2));
+        assert_eq!(r.text(), Ok("a"));
+        assert_eq!(r.integer(), Ok(1));
+        assert_eq!(r.text(), Ok("b"));
+        assert_eq!(r.array(), Ok(2));
+        assert_eq!(r.integer(), Ok(2));
+        assert_eq!(r.integer(), Ok(3));
+        assert!(r.is_empty());
+
+        let mut r = Reader::new(MAP_WITH_ARRAY);
+        r.skip().unwrap();
+        assert_eq!(r.position(), MAP_WITH_ARRAY.len());
+    }
+
+    #[test]
+    fn booleans() {
+        let mut r = Reader::new(&[0xf4, 0xf5, 0xf6]);
+        assert_eq!(r.boolean(), Ok(false));
+        assert_eq!(r.boolean(), Ok(true));
+        // null is a simple value, but not a boolean.
+        assert_eq!(r.boolean(), Err(Error::UnexpectedType));
+        assert_eq!(r.position(), 2);
+    }
+
+    #[test]
+    fn unexpected_type_does_not_consume() {
+        let mut r = Reader::new(&[0x64, b'I', b'E', b'T', b'F']);
+        assert_eq!(r.integer(), Err(Error::UnexpectedType));
+        assert_eq!(r.bytes(), Err(Error::UnexpectedType));
+        assert_eq!(r.map(), Err(Error::UnexpectedType));
+        assert_eq!(r.boolean(), Err(Error::UnexpectedType));
+        assert_eq!(r.position(), 0);
+        assert_eq!(r.text(), Ok("IETF"));
+    }
+
+    #[test]
+    fn unsupported_encodings() {
+        // Indefinite length byte string.
+        assert_eq!(
+            Reader::new(&[0x5f, 0x41, 0x01, 0xff]).skip(),
+            Err(Error::Malformed)
+        );
+        // Reserved additional information.
+        assert_eq!(Reader::new(&[0x1c]).integer(), Err(Error::Malformed));
+        // Tag 1 around an integer.
+        assert_eq!(
+            Reader::new(&[0xc1, 0x1a, 0, 0, 0, 0]).skip(),
+            Err(Error::Malformed)
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        let others: [&[u8]; 4] = [
+            &[0x44, 0x01, 0x02, 0x03, 0x04],
+            &[0x64, b'I', b'E', b'T', b'F'],
+            NESTED_ARRAY,
+            MAP_WITH_ARRAY,
+        ];
+        for item in INTEGERS.iter().map(|&(_, e)| e).chain(others) {
+            Reader::new(item).skip().unwrap();
+            for len in 0..item.len() {
+                let mut r = Reader::new(&item[..len]);
+                assert_eq!(r.skip(), Err(Error::Malformed), "{:x?}", &item[..len]);
+            }
+        }
+
+        // Lengths larger than the message.
+        assert_eq!(
+            Reader::new(&[0x5a, 0xff, 0xff, 0xff, 0xff]).bytes(),
+            Err(Error::Malformed)
+        );
+        assert_eq!(
+            Reader::new(&[0x9b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]).skip(),
+            Err(Error::Malformed)
+        );
+        assert_eq!(
+            Reader::new(&[0xbb, 0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]).skip(),
+            Err(Error::Malformed)
+        );
+    }
+
+    #[test]
+    fn writer() {
+        let mut buf = [0; 32];
+        let mut w = Writer::new(&mut buf);
+        w.map(2)
+            .text("a")
+            .integer(1)
+            .text("b")
+            .array(2)
+            .integer(2)
+            .integer(3);
+        assert_eq!(w.finish(), Ok(MAP_WITH_ARRAY.len()));
+        assert_eq!(&buf[..MAP_WITH_ARRAY.len()], MAP_WITH_ARRAY);
+
+        let mut w = Writer::at(&mut buf, 4);
+        w.boolean(true).bytes_header(300).raw(&[0xaa]);
+        assert_eq!(w.position(), 9);
+        assert_eq!(&buf[4..9], &[0xf5, 0x59, 0x01, 0x2c, 0xaa]);
+    }
+
+    #[test]
+    fn writer_overflow() {
+        let mut buf = [0; 4];
+        let mut w = Writer::new(&mut buf);
+        w.bytes(&[1, 2, 3, 4]);
+        assert_eq!(w.finish(), Err(Error::Overflow));
+        // Errors are sticky even when a later item would fit.
+        w.integer(0);
+        assert_eq!(w.finish(), Err(Error::Overflow));
+        assert_eq!(w.position(), 1);
+    }
+}
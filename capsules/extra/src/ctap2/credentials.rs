@@ -0,0 +1,169 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Credential table of the CTAP2 authenticator.
+//!
+//! All credentials are kept in a single record, stored in the KV store under
+//! [`KV_KEY`] with kernel-only permissions. The authenticator loads the
+//! record once and writes it back after every change. The record is a count
+//! followed by fixed size entries, most recently created last:
+//!
+//! ```text
+//! 0       Number of credentials
+//! 1..     Credentials
+//! ```
+//!
+//! Each credential has the following layout:
+//!
+//! ```text
+//! 0..32    SHA-256 hash of the relying party ID
+//! 32..48   Credential ID
+//! 48..80   P-256 private key
+//! 80       Flags, bit 0 is set for discoverable credentials
+//! 81       User ID length (at most 64)
+//! 82..146  User ID
+//! ```
+//!
+//! An all-zero record, as left by a failed read, is an empty table.
+
+/// Key under which the credential table is stored.
+pub const KV_KEY: &[u8] = b"ctap2-credentials";
+
+/// Maximum number of credentials.
+pub const MAX_CREDENTIALS: usize = 8;
+
+/// Length of a credential ID.
+pub const CREDENTIAL_ID_LEN: usize = 16;
+
+/// Length of a private key.
+pub const PRIVATE_KEY_LEN: usize = 32;
+
+/// Longest user ID (CTAP2 sect. 5.1).
+pub const MAX_USER_ID_LEN: usize = 64;
+
+const RP_ID_HASH: usize = 0;
+const CREDENTIAL_ID: usize = 32;
+const PRIVATE_KEY: usize = CREDENTIAL_ID + CREDENTIAL_ID_LEN;
+const FLAGS: usize = PRIVATE_KEY + PRIVATE_KEY_LEN;
+const USER_ID_LEN: usize = FLAGS + 1;
+const USER_ID: usize = USER_ID_LEN + 1;
+const ENTRY_LEN: usize = USER_ID + MAX_USER_ID_LEN;
+
+const FLAG_DISCOVERABLE: u8 = 0x01;
+
+/// Length of the credential table record.
+pub const RECORD_LEN: usize = 1 + MAX_CREDENTIALS * ENTRY_LEN;
+
+/// A credential table record.
+pub struct Table<'b> {
+    record: &'b mut [u8],
+}
+
+impl<'b> Table<'b> {
+    /// Wraps a record of at least `RECORD_LEN` bytes.
+    pub fn new(record: &'b mut [u8]) -> Self {
+        Self { record }
+    }
+
+    /// Empties the table.
+    pub fn clear(&mut self) {
+        self.record.iter_mut().for_each(|b| *b = 0);
+    }
+
+    pub fn len(&self) -> usize {
+        core::cmp::min(self.record[0] as usize, MAX_CREDENTIALS)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn entry(&self, index: usize) -> &[u8] {
+        let start = 1 + index * ENTRY_LEN;
+        &self.record[start..start + ENTRY_LEN]
+    }
+
+    fn entry_mut(&mut self, index: usize) -> &mut [u8] {
+        let start = 1 + index * ENTRY_LEN;
+        &mut self.record[start..start + ENTRY_LEN]
+    }
+
+    pub fn rp_id_hash(&self, index: usize) -> &[u8] {
+        &self.entry(index)[RP_ID_HASH..CREDENTIAL_ID]
+    }
+
+    pub fn credential_id(&self, index: usize) -> &[u8] {
+        &self.entry(index)[CREDENTIAL_ID..PRIVATE_KEY]
+    }
+
+    pub fn private_key(&self, index: usize) -> &[u8] {
+        &self.entry(index)[PRIVATE_KEY..FLAGS]
+    }
+
+    pub fn is_discoverable(&self, index: usize) -> bool {
+        self.entry(index)[FLAGS] & FLAG_DISCOVERABLE != 0
+    }
+
+    pub fn user_id(&self, index: usize) -> &[u8] {
+        let entry = self.entry(index);
+        let len = core::cmp::min(entry[USER_ID_LEN] as usize, MAX_USER_ID_LEN);
+        &entry[USER_ID..USER_ID + len]
+    }
+
+    /// Returns the index of the credential `credential_id` of the relying
+    /// party with the ID hash `rp_id_hash`.
+    pub fn find(&self, rp_id_hash: &[u8], credential_id: &[u8]) -> Option<usize> {
+        (0..self.len())
+            .find(|&i| self.rp_id_hash(i) == rp_id_hash && self.credential_id(i) == credential_id)
+    }
+
+    /// Returns the index of the most recent discoverable credential of the
+    /// relying party with the ID hash `rp_id_hash`.
+    pub fn find_discoverable(&self, rp_id_hash: &[u8]) -> Option<usize> {
+        (0..self.len())
+            .rev()
+            .find(|&i| self.is_discoverable(i) && self.rp_id_hash(i) == rp_id_hash)
+    }
+
+    /// Returns the index where a new credential will be stored, replacing the
+    /// discoverable credential of the same user and relying party if it is
+    /// discoverable too, or `None` if the table is full.
+    pub fn slot(&self, rp_id_hash: &[u8], user_id: &[u8], discoverable: bool) -> Option<usize> {
+        let replaced = (0..self.len()).find(|&i| {
+            discoverable
+                && self.is_discoverable(i)
+                && self.rp_id_hash(i) == rp_id_hash
+                && self.user_id(i) == user_id
+        });
+        match replaced {
+            Some(index) => Some(index),
+            None if self.len() < MAX_CREDENTIALS => Some(self.len()),
+            None => None,
+        }
+    }
+
+    /// Stores a credential at `index`, as returned by `slot()`.
+    pub fn store(
+        &mut self,
+        index: usize,
+        rp_id_hash: &[u8],
+        credential_id: &[u8],
+        private_key: &[u8],
+        discoverable: bool,
+        user_id: &[u8],
+    ) {
+        if index >= self.len() {
+            self.record[0] = index as u8 + 1;
+        }
+        let entry = self.entry_mut(index);
+        entry.iter_mut().for_each(|b| *b = 0);
+        entry[RP_ID_HASH..CREDENTIAL_ID].copy_from_slice(rp_id_hash);
+        entry[CREDENTIAL_ID..PRIVATE_KEY].copy_from_slice(credential_id);
+        entry[PRIVATE_KEY..FLAGS].copy_from_slice(private_key);
+        entry[FLAGS] = if discoverable { FLAG_DISCOVERABLE } else { 0 };
+        let user_id = &user_id[..core::cmp::min(user_id.len(), MAX_USER_ID_LEN)];
+        entry[USER_ID_LEN] = user_id.len() as u8;
+        entry[USER_ID..USER_ID + user_id.len()].copy_from_slice(user_id);
+    }
+}
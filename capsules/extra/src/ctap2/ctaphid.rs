@@ -0,0 +1,136 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! CTAPHID framing (CTAP2 sect. 8.1).
+//!
+//! Messages are split into 64-byte HID reports. The first report of a
+//! message is an initialization packet, the following ones are continuation
+//! packets:
+//!
+//! ```text
+//! initialization: CID (4) | CMD | 0x80 (1) | BCNT (2) | DATA (57)
+//! continuation:   CID (4) | SEQ (1)              | DATA (59)
+//! ```
+//!
+//! The channel ID (CID) identifies the client. Clients allocate a channel by
+//! sending an INIT command on the broadcast channel.
+
+/// Length of a HID report.
+pub const PACKET_LEN: usize = 64;
+
+/// Payload length of an initialization packet.
+pub const INIT_DATA_LEN: usize = PACKET_LEN - 7;
+
+/// Payload length of a continuation packet.
+pub const CONT_DATA_LEN: usize = PACKET_LEN - 5;
+
+/// Longest message that fits in an initialization packet and 128
+/// continuation packets.
+pub const MAX_MESSAGE_LEN: usize = INIT_DATA_LEN + 128 * CONT_DATA_LEN;
+
+/// The broadcast channel.
+pub const BROADCAST_CID: u32 = 0xffff_ffff;
+
+/// Commands.
+pub mod command {
+    pub const PING: u8 = 0x01;
+    pub const MSG: u8 = 0x03;
+    pub const INIT: u8 = 0x06;
+    pub const CBOR: u8 = 0x10;
+    pub const CANCEL: u8 = 0x11;
+    pub const KEEPALIVE: u8 = 0x3b;
+    pub const ERROR: u8 = 0x3f;
+}
+
+/// Error codes of the ERROR command.
+pub mod error {
+    pub const INVALID_CMD: u8 = 0x01;
+    pub const INVALID_LEN: u8 = 0x03;
+    pub const INVALID_SEQ: u8 = 0x04;
+    pub const MSG_TIMEOUT: u8 = 0x05;
+    pub const CHANNEL_BUSY: u8 = 0x06;
+    pub const INVALID_CHANNEL: u8 = 0x0b;
+}
+
+/// Status codes of the KEEPALIVE command.
+pub mod keepalive {
+    pub const PROCESSING: u8 = 0x01;
+    pub const UPNEEDED: u8 = 0x02;
+}
+
+/// Capability flags of the INIT response.
+pub mod capability {
+    pub const CBOR: u8 = 0x04;
+    /// The authenticator does not implement the MSG command.
+    pub const NMSG: u8 = 0x08;
+}
+
+/// CTAPHID protocol version reported by INIT.
+pub const PROTOCOL_VERSION: u8 = 2;
+
+/// Length of the nonce of the INIT command.
+pub const INIT_NONCE_LEN: usize = 8;
+
+/// Length of the INIT response.
+pub const INIT_RESPONSE_LEN: usize = 17;
+
+/// A received packet.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Packet<'b> {
+    Init {
+        cid: u32,
+        cmd: u8,
+        len: usize,
+        data: &'b [u8],
+    },
+    Cont {
+        cid: u32,
+        seq: u8,
+        data: &'b [u8],
+    },
+}
+
+impl<'b> Packet<'b> {
+    pub fn parse(packet: &'b [u8; PACKET_LEN]) -> Self {
+        let cid = u32::from_be_bytes([packet[0], packet[1], packet[2], packet[3]]);
+        if packet[4] & 0x80 != 0 {
+            Packet::Init {
+                cid,
+                cmd: packet[4] & 0x7f,
+                len: u16::from_be_bytes([packet[5], packet[6]]) as usize,
+                data: &packet[7..],
+            }
+        } else {
+            Packet::Cont {
+                cid,
+                seq: packet[4],
+                data: &packet[5..],
+            }
+        }
+    }
+
+    pub fn cid(&self) -> u32 {
+        match *self {
+            Packet::Init { cid, .. } | Packet::Cont { cid, .. } => cid,
+        }
+    }
+}
+
+/// Writes the initialization packet of a message of `len` bytes, returning
+/// the payload area.
+pub fn write_init(packet: &mut [u8; PACKET_LEN], cid: u32, cmd: u8, len: usize) -> &mut [u8] {
+    packet.iter_mut().for_each(|b| *b = 0);
+    packet[0..4].copy_from_slice(&cid.to_be_bytes());
+    packet[4] = cmd | 0x80;
+    packet[5..7].copy_from_slice(&(len as u16).to_be_bytes());
+    &mut packet[7..]
+}
+
+/// Writes a continuation packet, returning the payload area.
+pub fn write_cont(packet: &mut [u8; PACKET_LEN], cid: u32, seq: u8) -> &mut [u8] {
+    packet.iter_mut().for_each(|b| *b = 0);
+    packet[0..4].copy_from_slice(&cid.to_be_bytes());
+    packet[4] = seq;
+    &mut packet[5..]
+}
@@ -0,0 +1,13 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! FIDO2 authenticator (CTAP 2.0) running in the kernel.
+//!
+//! The [`authenticator`] speaks CTAPHID over a USB HID device, stores its
+//! credentials in the KV store and asks for user presence with a button.
+
+pub mod authenticator;
+pub mod cbor;
+pub mod credentials;
+pub mod ctaphid;
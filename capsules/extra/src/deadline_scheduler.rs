@@ -0,0 +1,80 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Lets processes declare their timing requirements to a deadline scheduler.
+//!
+//! A process requests a budget of CPU time every period. If the scheduler
+//! admits it, the process runs as a real-time process, and can query how often
+//! it overran its budget or missed its deadline.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let deadline_scheduler = static_init!(
+//!     capsules_extra::deadline_scheduler::DeadlineSchedulerDriver<
+//!         'static,
+//!         EDFSched<'static, VirtualMuxAlarm<'static, Rtc>>,
+//!     >,
+//!     capsules_extra::deadline_scheduler::DeadlineSchedulerDriver::new(scheduler)
+//! );
+//! ```
+
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::DeadlineScheduler as usize;
+
+use kernel::scheduler::edf::DeadlineScheduler;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+pub struct DeadlineSchedulerDriver<'a, S: DeadlineScheduler> {
+    scheduler: &'a S,
+}
+
+impl<'a, S: DeadlineScheduler> DeadlineSchedulerDriver<'a, S> {
+    pub fn new(scheduler: &'a S) -> DeadlineSchedulerDriver<'a, S> {
+        DeadlineSchedulerDriver { scheduler }
+    }
+}
+
+impl<'a, S: DeadlineScheduler> SyscallDriver for DeadlineSchedulerDriver<'a, S> {
+    /// Control the scheduling parameters of the calling process.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Request a budget of `data2` microseconds every `data1`
+    ///   microseconds. Returns `BUSY` if the process cannot be admitted.
+    /// - `2`: Run as a best-effort process.
+    /// - `3`: Return the number of budget overruns and deadline misses.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+            1 => self
+                .scheduler
+                .set_parameters(processid, data1 as u32, data2 as u32)
+                .into(),
+            2 => self.scheduler.clear_parameters(processid).into(),
+            3 => match self.scheduler.overrun_counts(processid) {
+                Ok((budget_overruns, deadline_misses)) => {
+                    CommandReturn::success_u32_u32(budget_overruns, deadline_misses)
+                }
+                Err(e) => CommandReturn::failure(e),
+            },
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, _: ProcessId) -> Result<(), kernel::process::Error> {
+        Ok(())
+    }
+}
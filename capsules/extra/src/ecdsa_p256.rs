@@ -0,0 +1,824 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Software implementation of ECDSA signatures over the NIST P-256 curve.
+//!
+//! The verifier checks signatures of 32-byte hashes (typically SHA-256
+//! digests) against a fixed public key. Signatures are the 32-byte big-endian
+//! `r` and `s` values concatenated, and the public key is the 32-byte
+//! big-endian `x` and `y` coordinates concatenated (the uncompressed SEC1
+//! encoding without the leading `0x04`).
+//!
+//! The signer signs hashes with a private key selected at runtime, a 32-byte
+//! big-endian scalar, and generates key pairs in the same encodings. It draws
+//! the private keys and the per-signature nonces from an RNG.
+//!
+//! Numbers are stored as eight 32-bit little-endian limbs, and multiplications
+//! modulo the field prime and the group order use Montgomery multiplication.
+//! Points use Jacobian coordinates. The implementation is not constant time.
+//! This is fine for verification, which only handles public data, but the
+//! signer can leak its keys through timing. It is meant for boards without a
+//! cryptographic engine where that is acceptable, such as development boards.
+//!
+//! A verification takes several million cycles on a Cortex-M4, during which
+//! the kernel does not run anything else. It is performed in a deferred
+//! call. Signing and key generation take about half as long and run in the
+//! RNG callback.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let verifier = static_init!(
+//!     capsules_extra::ecdsa_p256::EcdsaP256SignatureVerifier<'static>,
+//!     capsules_extra::ecdsa_p256::EcdsaP256SignatureVerifier::new(&PUBLIC_KEY)
+//! );
+//! kernel::deferred_call::DeferredCallClient::register(verifier);
+//!
+//! let signer = static_init!(
+//!     capsules_extra::ecdsa_p256::EcdsaP256SignatureSigner<'static, Rng>,
+//!     capsules_extra::ecdsa_p256::EcdsaP256SignatureSigner::new(rng)
+//! );
+//! rng.set_client(signer);
+//! kernel::deferred_call::DeferredCallClient::register(signer);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::public_key_crypto::{keys, signature};
+use kernel::hil::rng;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// Length of the hashes being verified.
+pub const HASH_LEN: usize = 32;
+/// Length of a signature.
+pub const SIGNATURE_LEN: usize = 64;
+/// Length of a public key.
+pub const PUBLIC_KEY_LEN: usize = 64;
+/// Length of a private key.
+pub const PRIVATE_KEY_LEN: usize = 32;
+
+/// A 256-bit number, least significant limb first.
+type U256 = [u32; 8];
+
+/// The parameters of Montgomery arithmetic modulo `m`, with `R = 2^256`.
+struct Modulus {
+    m: U256,
+    /// `-m^-1 mod 2^32`.
+    m_inv: u32,
+    /// `R^2 mod m`, to convert numbers into Montgomery form.
+    r2: U256,
+}
+
+/// The field prime `p`.
+const FIELD: Modulus = Modulus {
+    m: [
+        0xffffffff, 0xffffffff, 0xffffffff, 0x00000000, 0x00000000, 0x00000000, 0x00000001,
+        0xffffffff,
+    ],
+    m_inv: 0x00000001,
+    r2: [
+        0x00000003, 0x00000000, 0xffffffff, 0xfffffffb, 0xfffffffe, 0xffffffff, 0xfffffffd,
+        0x00000004,
+    ],
+};
+
+/// The order `n` of the base point.
+const ORDER: Modulus = Modulus {
+    m: [
+        0xfc632551, 0xf3b9cac2, 0xa7179e84, 0xbce6faad, 0xffffffff, 0xffffffff, 0x00000000,
+        0xffffffff,
+    ],
+    m_inv: 0xee00bc4f,
+    r2: [
+        0xbe79eea2, 0x83244c95, 0x49bd6fa6, 0x4699799c, 0x2b6bec59, 0x2845b239, 0xf3d95620,
+        0x66e12d94,
+    ],
+};
+
+/// The coefficient `b` of the curve equation `y^2 = x^3 - 3x + b`.
+const B: U256 = [
+    0x27d2604b, 0x3bce3c3e, 0xcc53b0f6, 0x651d06b0, 0x769886bc, 0xb3ebbd55, 0xaa3a93e7, 0x5ac635d8,
+];
+
+/// The coordinates of the base point `G`.
+const GX: U256 = [
+    0xd898c296, 0xf4a13945, 0x2deb33a0, 0x77037d81, 0x63a440f2, 0xf8bce6e5, 0xe12c4247, 0x6b17d1f2,
+];
+const GY: U256 = [
+    0x37bf51f5, 0xcbb64068, 0x6b315ece, 0x2bce3357, 0x7c0f9e16, 0x8ee7eb4a, 0xfe1a7f9b, 0x4fe342e2,
+];
+
+const ONE: U256 = [1, 0, 0, 0, 0, 0, 0, 0];
+const ZERO: U256 = [0; 8];
+
+fn from_be_bytes(bytes: &[u8]) -> U256 {
+    let mut n = ZERO;
+    for (i, chunk) in bytes.rchunks_exact(4).take(8).enumerate() {
+        n[i] = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+    n
+}
+
+fn to_be_bytes(n: &U256, bytes: &mut [u8]) {
+    for (chunk, limb) in bytes.rchunks_exact_mut(4).zip(n.iter()) {
+        chunk.copy_from_slice(&limb.to_be_bytes());
+    }
+}
+
+fn is_zero(a: &U256) -> bool {
+    a.iter().all(|&limb| limb == 0)
+}
+
+/// Returns `a >= b`.
+fn geq(a: &U256, b: &U256) -> bool {
+    for i in (0..8).rev() {
+        if a[i] != b[i] {
+            return a[i] > b[i];
+        }
+    }
+    true
+}
+
+/// Returns `a + b` and the carry.
+fn add(a: &U256, b: &U256) -> (U256, bool) {
+    let mut r = ZERO;
+    let mut carry = 0u64;
+    for i in 0..8 {
+        let s = a[i] as u64 + b[i] as u64 + carry;
+        r[i] = s as u32;
+        carry = s >> 32;
+    }
+    (r, carry != 0)
+}
+
+/// Returns `a - b` and the borrow.
+fn sub(a: &U256, b: &U256) -> (U256, bool) {
+    let mut r = ZERO;
+    let mut borrow = 0i64;
+    for i in 0..8 {
+        let d = a[i] as i64 - b[i] as i64 + borrow;
+        r[i] = d as u32;
+        borrow = d >> 32;
+    }
+    (r, borrow != 0)
+}
+
+impl Modulus {
+    fn add(&self, a: &U256, b: &U256) -> U256 {
+        let (s, carry) = add(a, b);
+        if carry || geq(&s, &self.m) {
+            sub(&s, &self.m).0
+        } else {
+            s
+        }
+    }
+
+    fn sub(&self, a: &U256, b: &U256) -> U256 {
+        let (d, borrow) = sub(a, b);
+        if borrow {
+            add(&d, &self.m).0
+        } else {
+            d
+        }
+    }
+
+    /// Montgomery multiplication: returns `a * b / R mod m`.
+    fn mul(&self, a: &U256, b: &U256) -> U256 {
+        let mut t = [0u32; 10];
+        for bi in b.iter() {
+            let mut carry = 0u64;
+            for j in 0..8 {
+                let sum = t[j] as u64 + a[j] as u64 * *bi as u64 + carry;
+                t[j] = sum as u32;
+                carry = sum >> 32;
+            }
+            let sum = t[8] as u64 + carry;
+            t[8] = sum as u32;
+            t[9] = (sum >> 32) as u32;
+
+            let factor = t[0].wrapping_mul(self.m_inv);
+            let mut carry = (t[0] as u64 + factor as u64 * self.m[0] as u64) >> 32;
+            for j in 1..8 {
+                let sum = t[j] as u64 + factor as u64 * self.m[j] as u64 + carry;
+                t[j - 1] = sum as u32;
+                carry = sum >> 32;
+            }
+            let sum = t[8] as u64 + carry;
+            t[7] = sum as u32;
+            t[8] = t[9] + (sum >> 32) as u32;
+        }
+
+        let mut result = ZERO;
+        result.copy_from_slice(&t[..8]);
+        if t[8] != 0 || geq(&result, &self.m) {
+            sub(&result, &self.m).0
+        } else {
+            result
+        }
+    }
+
+    fn square(&self, a: &U256) -> U256 {
+        self.mul(a, a)
+    }
+
+    fn to_montgomery(&self, a: &U256) -> U256 {
+        self.mul(a, &self.r2)
+    }
+
+    fn out_of_montgomery(&self, a: &U256) -> U256 {
+        self.mul(a, &ONE)
+    }
+
+    /// Returns `a^-1` for `a` in Montgomery form, as `a^(m - 2)`.
+    fn invert(&self, a: &U256) -> U256 {
+        let exponent = sub(&self.m, &[2, 0, 0, 0, 0, 0, 0, 0]).0;
+        let mut r = self.to_montgomery(&ONE);
+        for i in (0..256).rev() {
+            r = self.square(&r);
+            if (exponent[i / 32] >> (i % 32)) & 1 == 1 {
+                r = self.mul(&r, a);
+            }
+        }
+        r
+    }
+}
+
+/// A point in Jacobian coordinates, with coordinates in Montgomery form. The
+/// point at infinity has `z == 0`.
+#[derive(Clone, Copy)]
+struct Point {
+    x: U256,
+    y: U256,
+    z: U256,
+}
+
+impl Point {
+    const INFINITY: Point = Point {
+        x: ZERO,
+        y: ZERO,
+        z: ZERO,
+    };
+
+    /// Creates a point from affine coordinates, if they are on the curve.
+    fn from_affine(x: &U256, y: &U256) -> Option<Point> {
+        if geq(x, &FIELD.m) || geq(y, &FIELD.m) {
+            return None;
+        }
+        let x = FIELD.to_montgomery(x);
+        let y = FIELD.to_montgomery(y);
+
+        // Check that y^2 = x^3 - 3x + b.
+        let x3 = FIELD.mul(&FIELD.square(&x), &x);
+        let three_x = FIELD.add(&FIELD.add(&x, &x), &x);
+        let rhs = FIELD.add(&FIELD.sub(&x3, &three_x), &FIELD.to_montgomery(&B));
+        if FIELD.square(&y) != rhs {
+            return None;
+        }
+
+        Some(Point {
+            x,
+            y,
+            z: FIELD.to_montgomery(&ONE),
+        })
+    }
+
+    fn is_infinity(&self) -> bool {
+        is_zero(&self.z)
+    }
+
+    /// Returns `2 * self`, using `a = -3`.
+    fn double(&self) -> Point {
+        let delta = FIELD.square(&self.z);
+        let gamma = FIELD.square(&self.y);
+        let beta = FIELD.mul(&self.x, &gamma);
+
+        // alpha = 3 * (x - delta) * (x + delta)
+        let t = FIELD.mul(&FIELD.sub(&self.x, &delta), &FIELD.add(&self.x, &delta));
+        let alpha = FIELD.add(&FIELD.add(&t, &t), &t);
+
+        let beta2 = FIELD.add(&beta, &beta);
+        let beta4 = FIELD.add(&beta2, &beta2);
+        let beta8 = FIELD.add(&beta4, &beta4);
+        let x = FIELD.sub(&FIELD.square(&alpha), &beta8);
+
+        let yz = FIELD.add(&self.y, &self.z);
+        let z = FIELD.sub(&FIELD.sub(&FIELD.square(&yz), &gamma), &delta);
+
+        let gamma2 = FIELD.square(&gamma);
+        let gamma2_2 = FIELD.add(&gamma2, &gamma2);
+        let gamma2_4 = FIELD.add(&gamma2_2, &gamma2_2);
+        let gamma2_8 = FIELD.add(&gamma2_4, &gamma2_4);
+        let y = FIELD.sub(&FIELD.mul(&alpha, &FIELD.sub(&beta4, &x)), &gamma2_8);
+
+        Point { x, y, z }
+    }
+
+    /// Returns `self + other`.
+    fn add(&self, other: &Point) -> Point {
+        if self.is_infinity() {
+            return *other;
+        }
+        if other.is_infinity() {
+            return *self;
+        }
+
+        let z1z1 = FIELD.square(&self.z);
+        let z2z2 = FIELD.square(&other.z);
+        let u1 = FIELD.mul(&self.x, &z2z2);
+        let u2 = FIELD.mul(&other.x, &z1z1);
+        let s1 = FIELD.mul(&FIELD.mul(&self.y, &other.z), &z2z2);
+        let s2 = FIELD.mul(&FIELD.mul(&other.y, &self.z), &z1z1);
+
+        let h = FIELD.sub(&u2, &u1);
+        let rr = FIELD.sub(&s2, &s1);
+        if is_zero(&h) {
+            return if is_zero(&rr) {
+                self.double()
+            } else {
+                Point::INFINITY
+            };
+        }
+
+        let hh = FIELD.square(&h);
+        let hhh = FIELD.mul(&h, &hh);
+        let u1hh = FIELD.mul(&u1, &hh);
+
+        let x = FIELD.sub(
+            &FIELD.sub(&FIELD.square(&rr), &hhh),
+            &FIELD.add(&u1hh, &u1hh),
+        );
+        let y = FIELD.sub(
+            &FIELD.mul(&rr, &FIELD.sub(&u1hh, &x)),
+            &FIELD.mul(&s1, &hhh),
+        );
+        let z = FIELD.mul(&FIELD.mul(&self.z, &other.z), &h);
+
+        Point { x, y, z }
+    }
+
+    /// Returns the affine `x` coordinate, not in Montgomery form.
+    fn affine_x(&self) -> U256 {
+        let z_inv = FIELD.invert(&self.z);
+        FIELD.out_of_montgomery(&FIELD.mul(&self.x, &FIELD.square(&z_inv)))
+    }
+
+    /// Returns the affine coordinates, not in Montgomery form.
+    fn affine(&self) -> (U256, U256) {
+        let z_inv = FIELD.invert(&self.z);
+        let z_inv2 = FIELD.square(&z_inv);
+        let x = FIELD.mul(&self.x, &z_inv2);
+        let y = FIELD.mul(&self.y, &FIELD.mul(&z_inv2, &z_inv));
+        (FIELD.out_of_montgomery(&x), FIELD.out_of_montgomery(&y))
+    }
+}
+
+/// Returns `u1 * G + u2 * q`, using Shamir's trick.
+fn double_scalar_mul(u1: &U256, u2: &U256, q: &Point) -> Point {
+    let g = Point {
+        x: FIELD.to_montgomery(&GX),
+        y: FIELD.to_montgomery(&GY),
+        z: FIELD.to_montgomery(&ONE),
+    };
+    let g_plus_q = g.add(q);
+
+    let mut r = Point::INFINITY;
+    for i in (0..256).rev() {
+        r = r.double();
+        let bit1 = (u1[i / 32] >> (i % 32)) & 1 == 1;
+        let bit2 = (u2[i / 32] >> (i % 32)) & 1 == 1;
+        match (bit1, bit2) {
+            (true, true) => r = r.add(&g_plus_q),
+            (true, false) => r = r.add(&g),
+            (false, true) => r = r.add(q),
+            (false, false) => {}
+        }
+    }
+    r
+}
+
+/// Returns whether `d` is a valid scalar, in `[1, n - 1]`.
+fn is_valid_scalar(d: &U256) -> bool {
+    !is_zero(d) && !geq(d, &ORDER.m)
+}
+
+/// Reduces a hash to a scalar. The hash is as long as the order, so it only
+/// needs to be reduced once.
+fn hash_to_scalar(hash: &[u8; HASH_LEN]) -> U256 {
+    let digest = from_be_bytes(hash);
+    if geq(&digest, &ORDER.m) {
+        sub(&digest, &ORDER.m).0
+    } else {
+        digest
+    }
+}
+
+/// Sign `hash` with the private key `d` and the nonce `k`, both valid
+/// scalars. Returns `None` if the nonce does not yield a signature, in which
+/// case signing must be retried with another nonce.
+fn sign_hash(d: &U256, k: &U256, hash: &[u8; HASH_LEN]) -> Option<[u8; SIGNATURE_LEN]> {
+    let point = double_scalar_mul(k, &ZERO, &Point::INFINITY);
+    if point.is_infinity() {
+        return None;
+    }
+    // x < p < 2n, so it only needs to be reduced once.
+    let mut r = point.affine_x();
+    if geq(&r, &ORDER.m) {
+        r = sub(&r, &ORDER.m).0;
+    }
+    if is_zero(&r) {
+        return None;
+    }
+
+    // s = k^-1 * (z + r * d)
+    let rd = ORDER.mul(&ORDER.to_montgomery(&r), &ORDER.to_montgomery(d));
+    let sum = ORDER.add(&ORDER.to_montgomery(&hash_to_scalar(hash)), &rd);
+    let k_inv = ORDER.invert(&ORDER.to_montgomery(k));
+    let s = ORDER.out_of_montgomery(&ORDER.mul(&sum, &k_inv));
+    if is_zero(&s) {
+        return None;
+    }
+
+    let mut signature = [0; SIGNATURE_LEN];
+    to_be_bytes(&r, &mut signature[..32]);
+    to_be_bytes(&s, &mut signature[32..]);
+    Some(signature)
+}
+
+/// Verify the ECDSA signature `signature` of `hash` with the public key
+/// `public_key`.
+fn verify_signature(
+    public_key: &Point,
+    hash: &[u8; HASH_LEN],
+    signature: &[u8; SIGNATURE_LEN],
+) -> bool {
+    let r = from_be_bytes(&signature[..32]);
+    let s = from_be_bytes(&signature[32..]);
+    if is_zero(&r) || is_zero(&s) || geq(&r, &ORDER.m) || geq(&s, &ORDER.m) {
+        return false;
+    }
+
+    let digest = hash_to_scalar(hash);
+
+    let s_inv = ORDER.invert(&ORDER.to_montgomery(&s));
+    let u1 = ORDER.out_of_montgomery(&ORDER.mul(&ORDER.to_montgomery(&digest), &s_inv));
+    let u2 = ORDER.out_of_montgomery(&ORDER.mul(&ORDER.to_montgomery(&r), &s_inv));
+
+    let point = double_scalar_mul(&u1, &u2, public_key);
+    if point.is_infinity() {
+        return false;
+    }
+
+    // x < p < 2n, so it only needs to be reduced once.
+    let mut point_x = point.affine_x();
+    if geq(&point_x, &ORDER.m) {
+        point_x = sub(&point_x, &ORDER.m).0;
+    }
+    point_x == r
+}
+
+pub struct EcdsaP256SignatureVerifier<'a> {
+    /// The public key, or `None` if it is not a point on the curve.
+    public_key: Option<Point>,
+    client: OptionalCell<&'a dyn signature::ClientVerify<HASH_LEN, SIGNATURE_LEN>>,
+    hash: TakeCell<'static, [u8; HASH_LEN]>,
+    signature: TakeCell<'static, [u8; SIGNATURE_LEN]>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a> EcdsaP256SignatureVerifier<'a> {
+    pub fn new(public_key: &[u8; PUBLIC_KEY_LEN]) -> Self {
+        Self {
+            public_key: Point::from_affine(
+                &from_be_bytes(&public_key[..32]),
+                &from_be_bytes(&public_key[32..]),
+            ),
+            client: OptionalCell::empty(),
+            hash: TakeCell::empty(),
+            signature: TakeCell::empty(),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+}
+
+impl<'a> signature::SignatureVerify<'a, HASH_LEN, SIGNATURE_LEN>
+    for EcdsaP256SignatureVerifier<'a>
+{
+    fn set_verify_client(&self, client: &'a dyn signature::ClientVerify<HASH_LEN, SIGNATURE_LEN>) {
+        self.client.replace(client);
+    }
+
+    fn verify(
+        &self,
+        hash: &'static mut [u8; HASH_LEN],
+        signature: &'static mut [u8; SIGNATURE_LEN],
+    ) -> Result<
+        (),
+        (
+            ErrorCode,
+            &'static mut [u8; HASH_LEN],
+            &'static mut [u8; SIGNATURE_LEN],
+        ),
+    > {
+        if self.hash.is_some() {
+            return Err((ErrorCode::BUSY, hash, signature));
+        }
+        self.hash.replace(hash);
+        self.signature.replace(signature);
+        self.deferred_call.set();
+        Ok(())
+    }
+}
+
+impl<'a> DeferredCallClient for EcdsaP256SignatureVerifier<'a> {
+    fn handle_deferred_call(&self) {
+        if let (Some(hash), Some(signature)) = (self.hash.take(), self.signature.take()) {
+            // A public key that is not on the curve cannot verify anything.
+            let result = self
+                .public_key
+                .as_ref()
+                .ok_or(ErrorCode::FAIL)
+                .map(|public_key| verify_signature(public_key, hash, signature));
+            self.client.map(|client| {
+                client.verification_done(result, hash, signature);
+            });
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+/// The operation of the signer.
+#[derive(Clone, Copy, PartialEq)]
+enum Operation {
+    Idle,
+    SetKey,
+    Sign,
+    Generate,
+}
+
+pub struct EcdsaP256SignatureSigner<'a, R: rng::Rng<'a>> {
+    rng: &'a R,
+    /// The selected private key.
+    private_key: Cell<Option<U256>>,
+    operation: Cell<Operation>,
+    /// The random scalar being collected from the RNG, and the number of its
+    /// limbs collected so far.
+    random: Cell<U256>,
+    random_limbs: Cell<usize>,
+    key_client: OptionalCell<&'a dyn keys::SetKeyBySliceClient<PRIVATE_KEY_LEN>>,
+    sign_client: OptionalCell<&'a dyn signature::ClientSign<HASH_LEN, SIGNATURE_LEN>>,
+    generate_client:
+        OptionalCell<&'a dyn keys::KeyPairGenerateClient<PUBLIC_KEY_LEN, PRIVATE_KEY_LEN>>,
+    key: TakeCell<'static, [u8; PRIVATE_KEY_LEN]>,
+    public_key: TakeCell<'static, [u8; PUBLIC_KEY_LEN]>,
+    hash: TakeCell<'static, [u8; HASH_LEN]>,
+    signature: TakeCell<'static, [u8; SIGNATURE_LEN]>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a, R: rng::Rng<'a>> EcdsaP256SignatureSigner<'a, R> {
+    pub fn new(rng: &'a R) -> Self {
+        Self {
+            rng,
+            private_key: Cell::new(None),
+            operation: Cell::new(Operation::Idle),
+            random: Cell::new(ZERO),
+            random_limbs: Cell::new(0),
+            key_client: OptionalCell::empty(),
+            sign_client: OptionalCell::empty(),
+            generate_client: OptionalCell::empty(),
+            key: TakeCell::empty(),
+            public_key: TakeCell::empty(),
+            hash: TakeCell::empty(),
+            signature: TakeCell::empty(),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    /// Starts collecting a random scalar for `operation`.
+    fn start_random(&self, operation: Operation) -> Result<(), ErrorCode> {
+        if self.operation.get() != Operation::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.random_limbs.set(0);
+        self.rng.get()?;
+        self.operation.set(operation);
+        Ok(())
+    }
+
+    /// Finishes the current operation with an error.
+    fn fail(&self, error: ErrorCode) {
+        match self.operation.replace(Operation::Idle) {
+            Operation::Sign => {
+                if let (Some(hash), Some(signature)) = (self.hash.take(), self.signature.take()) {
+                    self.sign_client.map(|client| {
+                        client.signing_done(Err(error), hash, signature);
+                    });
+                }
+            }
+            Operation::Generate => {
+                if let (Some(public_key), Some(private_key)) =
+                    (self.public_key.take(), self.key.take())
+                {
+                    self.generate_client.map(|client| {
+                        client.generation_done(Err(error), public_key, private_key);
+                    });
+                }
+            }
+            Operation::Idle | Operation::SetKey => {}
+        }
+    }
+
+    /// Uses the random scalar `random` for the current operation. Returns
+    /// `false` if it is not usable and another one is needed.
+    fn use_random(&self, random: &U256) -> bool {
+        if !is_valid_scalar(random) {
+            return false;
+        }
+        match self.operation.get() {
+            Operation::Sign => {
+                let signature = match (self.private_key.get(), self.hash.map(|hash| *hash)) {
+                    (Some(d), Some(hash)) => match sign_hash(&d, random, &hash) {
+                        Some(signature) => signature,
+                        None => return false,
+                    },
+                    _ => {
+                        self.fail(ErrorCode::FAIL);
+                        return true;
+                    }
+                };
+                if let (Some(hash), Some(signature_buffer)) =
+                    (self.hash.take(), self.signature.take())
+                {
+                    signature_buffer.copy_from_slice(&signature);
+                    self.operation.set(Operation::Idle);
+                    self.sign_client.map(|client| {
+                        client.signing_done(Ok(()), hash, signature_buffer);
+                    });
+                }
+                true
+            }
+            Operation::Generate => {
+                let (x, y) = double_scalar_mul(random, &ZERO, &Point::INFINITY).affine();
+                if let (Some(public_key), Some(private_key)) =
+                    (self.public_key.take(), self.key.take())
+                {
+                    to_be_bytes(&x, &mut public_key[..32]);
+                    to_be_bytes(&y, &mut public_key[32..]);
+                    to_be_bytes(random, private_key);
+                    self.operation.set(Operation::Idle);
+                    self.generate_client.map(|client| {
+                        client.generation_done(Ok(()), public_key, private_key);
+                    });
+                }
+                true
+            }
+            Operation::Idle | Operation::SetKey => true,
+        }
+    }
+}
+
+impl<'a, R: rng::Rng<'a>> keys::SetKeyBySlice<'a, PRIVATE_KEY_LEN>
+    for EcdsaP256SignatureSigner<'a, R>
+{
+    fn set_key_client(&self, client: &'a dyn keys::SetKeyBySliceClient<PRIVATE_KEY_LEN>) {
+        self.key_client.replace(client);
+    }
+
+    fn set_key(
+        &self,
+        key: &'static mut [u8; PRIVATE_KEY_LEN],
+    ) -> Result<(), (ErrorCode, &'static mut [u8; PRIVATE_KEY_LEN])> {
+        if self.operation.get() != Operation::Idle {
+            return Err((ErrorCode::BUSY, key));
+        }
+        self.key.replace(key);
+        self.operation.set(Operation::SetKey);
+        self.deferred_call.set();
+        Ok(())
+    }
+}
+
+impl<'a, R: rng::Rng<'a>> signature::SignatureSign<'a, HASH_LEN, SIGNATURE_LEN>
+    for EcdsaP256SignatureSigner<'a, R>
+{
+    fn set_sign_client(&self, client: &'a dyn signature::ClientSign<HASH_LEN, SIGNATURE_LEN>) {
+        self.sign_client.replace(client);
+    }
+
+    fn sign(
+        &self,
+        hash: &'static mut [u8; HASH_LEN],
+        signature: &'static mut [u8; SIGNATURE_LEN],
+    ) -> Result<
+        (),
+        (
+            ErrorCode,
+            &'static mut [u8; HASH_LEN],
+            &'static mut [u8; SIGNATURE_LEN],
+        ),
+    > {
+        if self.private_key.get().is_none() {
+            return Err((ErrorCode::RESERVE, hash, signature));
+        }
+        if let Err(e) = self.start_random(Operation::Sign) {
+            return Err((e, hash, signature));
+        }
+        self.hash.replace(hash);
+        self.signature.replace(signature);
+        Ok(())
+    }
+}
+
+impl<'a, R: rng::Rng<'a>> keys::KeyPairGenerate<'a, PUBLIC_KEY_LEN, PRIVATE_KEY_LEN>
+    for EcdsaP256SignatureSigner<'a, R>
+{
+    fn set_generate_client(
+        &self,
+        client: &'a dyn keys::KeyPairGenerateClient<PUBLIC_KEY_LEN, PRIVATE_KEY_LEN>,
+    ) {
+        self.generate_client.replace(client);
+    }
+
+    fn generate(
+        &self,
+        public_key: &'static mut [u8; PUBLIC_KEY_LEN],
+        private_key: &'static mut [u8; PRIVATE_KEY_LEN],
+    ) -> Result<
+        (),
+        (
+            ErrorCode,
+            &'static mut [u8; PUBLIC_KEY_LEN],
+            &'static mut [u8; PRIVATE_KEY_LEN],
+        ),
+    > {
+        if let Err(e) = self.start_random(Operation::Generate) {
+            return Err((e, public_key, private_key));
+        }
+        self.public_key.replace(public_key);
+        self.key.replace(private_key);
+        Ok(())
+    }
+}
+
+impl<'a, R: rng::Rng<'a>> rng::Client for EcdsaP256SignatureSigner<'a, R> {
+    fn randomness_available(
+        &self,
+        randomness: &mut dyn Iterator<Item = u32>,
+        error: Result<(), ErrorCode>,
+    ) -> rng::Continue {
+        if let Err(e) = error {
+            self.fail(e);
+            return rng::Continue::Done;
+        }
+
+        let mut random = self.random.get();
+        let mut limbs = self.random_limbs.get();
+        for word in randomness {
+            random[limbs] = word;
+            limbs += 1;
+            if limbs == random.len() {
+                if self.use_random(&random) {
+                    // Do not keep the private key or nonce around.
+                    self.random.set(ZERO);
+                    return rng::Continue::Done;
+                }
+                limbs = 0;
+            }
+        }
+        self.random.set(random);
+        self.random_limbs.set(limbs);
+        rng::Continue::More
+    }
+}
+
+impl<'a, R: rng::Rng<'a>> DeferredCallClient for EcdsaP256SignatureSigner<'a, R> {
+    fn handle_deferred_call(&self) {
+        if self.operation.get() != Operation::SetKey {
+            return;
+        }
+        self.operation.set(Operation::Idle);
+        if let Some(key) = self.key.take() {
+            let d = from_be_bytes(key);
+            let result = if is_valid_scalar(&d) {
+                self.private_key.set(Some(d));
+                Ok(())
+            } else {
+                Err(ErrorCode::INVAL)
+            };
+            self.key_client.map(|client| {
+                client.set_key_done(key, result);
+            });
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
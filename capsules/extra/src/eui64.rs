@@ -18,6 +18,13 @@ impl Eui64 {
     pub fn new(eui64: u64) -> Eui64 {
         Eui64 { eui64 }
     }
+
+    /// Returns the EUI-64 as the bytes of the 802.15.4 extended address of
+    /// the node (`MacAddress::Long`), e.g. to derive its IPv6 interface
+    /// identifier.
+    pub fn long_addr(&self) -> [u8; 8] {
+        self.eui64.to_le_bytes()
+    }
 }
 
 impl SyscallDriver for Eui64 {
@@ -0,0 +1,834 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Always-on IEEE 802.15.4 MAC layer with unslotted CSMA-CA, acknowledgement
+//! waiting and retransmission (IEEE 802.15.4-2015, sect. 6.2.5.1 and 6.7.4).
+//!
+//! `AwakeMac` hands every frame to the radio once and reports whatever the
+//! radio reports. This layer adds the reliability the standard expects from
+//! the MAC, for radios that do not implement it in hardware:
+//!
+//!   * Before each transmission attempt, the layer backs off a random number
+//!     of unit backoff periods, between 0 and 2^BE - 1. The radio performs the
+//!     clear channel assessment when it transmits, and reports a busy channel
+//!     with `Err(ErrorCode::BUSY)`. On a busy channel, the backoff exponent BE
+//!     grows up to `MAC_MAX_BE` and the layer backs off again, until it gives
+//!     up after `MAC_MAX_CSMA_BACKOFFS` further attempts.
+//!   * Frames requesting an acknowledgement are only done once an
+//!     acknowledgement frame with their sequence number arrives. If none
+//!     arrives within the acknowledgement wait duration, the frame is sent
+//!     again, with a new CSMA-CA procedure, up to `MAC_MAX_FRAME_RETRIES`
+//!     times. Radios that receive acknowledgements themselves report them
+//!     with `acked`, which ends the transmission right away.
+//!
+//! Each frame completes with exactly one `send_done` carrying its status:
+//!
+//!   * `acked == true` and `Ok(())`: the destination acknowledged the frame.
+//!   * `acked == false` and `Ok(())`: the frame did not request an
+//!     acknowledgement (e.g. a broadcast frame) and was sent.
+//!   * `Err(ErrorCode::NOACK)`: no acknowledgement arrived after all retries.
+//!   * `Err(ErrorCode::BUSY)`: channel access failure; the channel was never
+//!     clear.
+//!   * Any other error the radio reported.
+//!
+//! Usage
+//! -----
+//! The layer needs a `kernel::hil::time::Alarm` for the backoffs and the
+//! acknowledgement timeout, and a `kernel::hil::rng::Rng` for the random
+//! backoffs. Like `AwakeMac`, it is the backend of a
+//! `capsules::ieee802154::device::MacDevice`:
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let csma_mac = static_init!(
+//!     capsules::ieee802154::csma::CsmaMac<'static, Radio, VirtualMuxAlarm<'static, Rtc>>,
+//!     capsules::ieee802154::csma::CsmaMac::new(radio, alarm, rng)
+//! );
+//! alarm.set_alarm_client(csma_mac);
+//! rng.set_client(csma_mac);
+//! radio.set_transmit_client(csma_mac);
+//! radio.set_receive_client(csma_mac);
+//! ```
+
+use crate::ieee802154::mac::Mac;
+use crate::net::ieee802154::{FrameType, Header, MacAddress};
+use core::cell::Cell;
+use kernel::hil::radio::{self, MAX_FRAME_SIZE, PSDU_OFFSET};
+use kernel::hil::rng::{self, Rng};
+use kernel::hil::time::{self, Alarm, ConvertTicks, Ticks};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// Initial backoff exponent (macMinBe).
+pub const MAC_MIN_BE: u8 = 3;
+/// Largest backoff exponent (macMaxBe).
+pub const MAC_MAX_BE: u8 = 5;
+/// Backoffs after a busy channel before a channel access failure
+/// (macMaxCsmaBackoffs).
+pub const MAC_MAX_CSMA_BACKOFFS: u8 = 4;
+/// Retransmissions of a frame that was not acknowledged (macMaxFrameRetries).
+pub const MAC_MAX_FRAME_RETRIES: u8 = 3;
+
+/// Duration of a symbol of the 2.4 GHz O-QPSK PHY.
+const SYMBOL_US: u32 = 16;
+/// Length of a backoff period (aUnitBackoffPeriod), in symbols.
+const UNIT_BACKOFF_PERIOD: u32 = 20;
+/// Time to wait for an acknowledgement (macAckWaitDuration), in symbols:
+/// aUnitBackoffPeriod + aTurnaroundTime + phySHRDuration + 6 * phySymbolsPerOctet.
+const ACK_WAIT_DURATION: u32 = UNIT_BACKOFF_PERIOD + 12 + 10 + 6 * 2;
+/// Extra time to wait for an acknowledgement, as the radio only reports the
+/// end of the transmission after an interrupt, and peers that acknowledge in
+/// software answer later than aTurnaroundTime.
+const ACK_WAIT_MARGIN_US: u32 = 1000;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum TxState {
+    /// No frame is being transmitted.
+    Idle,
+    /// Waiting for randomness to pick the next backoff.
+    Random,
+    /// Backing off before the next transmission attempt.
+    Backoff,
+    /// The radio is transmitting the frame.
+    Transmitting,
+    /// Waiting for the acknowledgement of the frame.
+    AwaitingAck,
+}
+
+pub struct CsmaMac<'a, R: radio::Radio<'a>, A: Alarm<'a>> {
+    radio: &'a R,
+    alarm: &'a A,
+    rng: &'a dyn Rng<'a>,
+
+    tx_client: OptionalCell<&'a dyn radio::TxClient>,
+    rx_client: OptionalCell<&'a dyn radio::RxClient>,
+
+    state: Cell<TxState>,
+    /// The frame being transmitted, while the radio does not hold it.
+    tx_buf: TakeCell<'static, [u8]>,
+    tx_len: Cell<usize>,
+    /// Sequence number of the frame, if it requested an acknowledgement.
+    tx_seq: OptionalCell<u8>,
+    /// Number of backoffs of the current CSMA-CA procedure (NB).
+    backoffs: Cell<u8>,
+    /// Backoff exponent (BE).
+    exponent: Cell<u8>,
+    retries: Cell<u8>,
+}
+
+impl<'a, R: radio::Radio<'a>, A: Alarm<'a>> CsmaMac<'a, R, A> {
+    pub fn new(radio: &'a R, alarm: &'a A, rng: &'a dyn Rng<'a>) -> CsmaMac<'a, R, A> {
+        CsmaMac {
+            radio,
+            alarm,
+            rng,
+            tx_client: OptionalCell::empty(),
+            rx_client: OptionalCell::empty(),
+            state: Cell::new(TxState::Idle),
+            tx_buf: TakeCell::empty(),
+            tx_len: Cell::new(0),
+            tx_seq: OptionalCell::empty(),
+            backoffs: Cell::new(0),
+            exponent: Cell::new(MAC_MIN_BE),
+            retries: Cell::new(0),
+        }
+    }
+
+    /// Starts a CSMA-CA procedure for the frame in `tx_buf`.
+    fn start_csma(&self) {
+        self.backoffs.set(0);
+        self.exponent.set(MAC_MIN_BE);
+        self.backoff();
+    }
+
+    /// Backs off a random number of backoff periods before the next
+    /// transmission attempt.
+    fn backoff(&self) {
+        self.state.set(TxState::Random);
+        if self.rng.get().is_err() {
+            // Fall back to the low bits of the clock, which still separate
+            // contending nodes.
+            self.start_backoff(self.alarm.now().into_u32());
+        }
+    }
+
+    fn start_backoff(&self, random: u32) {
+        let periods = random % (1 << self.exponent.get());
+        self.state.set(TxState::Backoff);
+        self.alarm.set_alarm(
+            self.alarm.now(),
+            self.alarm
+                .ticks_from_us(periods * UNIT_BACKOFF_PERIOD * SYMBOL_US),
+        );
+    }
+
+    /// Hands the frame to the radio, which assesses the channel first.
+    fn attempt(&self) {
+        if let Some(buf) = self.tx_buf.take() {
+            self.state.set(TxState::Transmitting);
+            if let Err((ecode, buf)) = self.radio.transmit(buf, self.tx_len.get()) {
+                self.done(buf, false, Err(ecode));
+            }
+        }
+    }
+
+    /// Sends the frame again if retries are left.
+    fn retry(&self) {
+        if self.retries.get() < MAC_MAX_FRAME_RETRIES {
+            self.retries.set(self.retries.get() + 1);
+            self.start_csma();
+        } else if let Some(buf) = self.tx_buf.take() {
+            self.done(buf, false, Err(ErrorCode::NOACK));
+        }
+    }
+
+    fn done(&self, buf: &'static mut [u8], acked: bool, result: Result<(), ErrorCode>) {
+        self.state.set(TxState::Idle);
+        self.tx_seq.clear();
+        self.tx_client.map(move |c| {
+            c.send_done(buf, acked, result);
+        });
+    }
+
+    /// Whether `frame` acknowledges the frame being transmitted.
+    fn is_ack(&self, frame: &[u8]) -> bool {
+        self.state.get() == TxState::AwaitingAck
+            && Header::decode(frame, false)
+                .done()
+                .map_or(false, |(_, (header, _))| {
+                    header.frame_type == FrameType::Acknowledgement
+                        && header.seq.is_some()
+                        && header.seq == self.tx_seq.get()
+                })
+    }
+}
+
+impl<'a, R: radio::Radio<'a>, A: Alarm<'a>> Mac<'a> for CsmaMac<'a, R, A> {
+    fn initialize(&self) -> Result<(), ErrorCode> {
+        // do nothing, extra buffer unnecessary
+        Ok(())
+    }
+
+    fn is_on(&self) -> bool {
+        self.radio.is_on()
+    }
+
+    fn set_config_client(&self, client: &'a dyn radio::ConfigClient) {
+        self.radio.set_config_client(client)
+    }
+
+    fn set_address(&self, addr: u16) {
+        self.radio.set_address(addr)
+    }
+
+    fn set_address_long(&self, addr: [u8; 8]) {
+        self.radio.set_address_long(addr)
+    }
+
+    fn set_pan(&self, id: u16) {
+        self.radio.set_pan(id)
+    }
+
+    fn get_address(&self) -> u16 {
+        self.radio.get_address()
+    }
+
+    fn get_address_long(&self) -> [u8; 8] {
+        self.radio.get_address_long()
+    }
+
+    fn get_pan(&self) -> u16 {
+        self.radio.get_pan()
+    }
+
+    fn config_commit(&self) {
+        self.radio.config_commit()
+    }
+
+    fn set_transmit_client(&self, client: &'a dyn radio::TxClient) {
+        self.tx_client.set(client);
+    }
+
+    fn set_receive_client(&self, client: &'a dyn radio::RxClient) {
+        self.rx_client.set(client);
+    }
+
+    fn set_receive_buffer(&self, buffer: &'static mut [u8]) {
+        self.radio.set_receive_buffer(buffer);
+    }
+
+    fn transmit(
+        &self,
+        full_mac_frame: &'static mut [u8],
+        frame_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.state.get() != TxState::Idle {
+            return Err((ErrorCode::BUSY, full_mac_frame));
+        }
+
+        if full_mac_frame.len() < frame_len + PSDU_OFFSET {
+            return Err((ErrorCode::NOMEM, full_mac_frame));
+        }
+
+        if frame_len > MAX_FRAME_SIZE {
+            return Err((ErrorCode::INVAL, full_mac_frame));
+        }
+
+        // Retransmissions reuse the frame, so shift it by the `PSDU_OFFSET`
+        // the radio requires only once.
+        full_mac_frame.copy_within(0..frame_len, PSDU_OFFSET);
+
+        let seq = Header::decode(&full_mac_frame[PSDU_OFFSET..], false)
+            .done()
+            .and_then(|(_, (header, _))| {
+                // Broadcast frames are never acknowledged.
+                let broadcast = header.dst_addr == Some(MacAddress::Short(0xFFFF));
+                header.seq.filter(|_| header.ack_requested && !broadcast)
+            });
+        self.tx_seq.insert(seq);
+        self.tx_len.set(frame_len);
+        self.tx_buf.replace(full_mac_frame);
+        self.retries.set(0);
+        self.start_csma();
+        Ok(())
+    }
+}
+
+impl<'a, R: radio::Radio<'a>, A: Alarm<'a>> radio::TxClient for CsmaMac<'a, R, A> {
+    fn send_done(&self, buf: &'static mut [u8], acked: bool, result: Result<(), ErrorCode>) {
+        if self.state.get() != TxState::Transmitting {
+            self.done(buf, acked, result);
+            return;
+        }
+        match result {
+            Err(ErrorCode::BUSY) => {
+                // The channel was busy: back off longer.
+                let backoffs = self.backoffs.get() + 1;
+                self.backoffs.set(backoffs);
+                self.exponent
+                    .set(core::cmp::min(self.exponent.get() + 1, MAC_MAX_BE));
+                if backoffs > MAC_MAX_CSMA_BACKOFFS {
+                    self.done(buf, false, result);
+                } else {
+                    self.tx_buf.replace(buf);
+                    self.backoff();
+                }
+            }
+            Ok(()) if !acked && self.tx_seq.is_some() => {
+                self.tx_buf.replace(buf);
+                self.state.set(TxState::AwaitingAck);
+                self.alarm.set_alarm(
+                    self.alarm.now(),
+                    self.alarm
+                        .ticks_from_us(ACK_WAIT_DURATION * SYMBOL_US + ACK_WAIT_MARGIN_US),
+                );
+            }
+            result => self.done(buf, acked, result),
+        }
+    }
+}
+
+impl<'a, R: radio::Radio<'a>, A: Alarm<'a>> radio::RxClient for CsmaMac<'a, R, A> {
+    fn receive(
+        &self,
+        buf: &'static mut [u8],
+        frame_len: usize,
+        lqi: u8,
+        crc_valid: bool,
+        result: Result<(), ErrorCode>,
+    ) {
+        if crc_valid && self.is_ack(&buf[radio::PSDU_OFFSET..]) {
+            let _ = self.alarm.disarm();
+            self.radio.set_receive_buffer(buf);
+            if let Some(tx_buf) = self.tx_buf.take() {
+                self.done(tx_buf, true, Ok(()));
+            }
+            return;
+        }
+
+        // Filter packets by destination because radio is in promiscuous mode
+        let mut addr_match = false;
+        if let Some((_, (header, _))) = Header::decode(&buf[radio::PSDU_OFFSET..], false).done() {
+            if let Some(dst_addr) = header.dst_addr {
+                addr_match = match dst_addr {
+                    MacAddress::Short(addr) => {
+                        // Check if address matches radio or is set to multicast short addr 0xFFFF
+                        (addr == self.radio.get_address()) || (addr == 0xFFFF)
+                    }
+                    MacAddress::Long(long_addr) => long_addr == self.radio.get_address_long(),
+                };
+            }
+        }
+        if addr_match {
+            self.rx_client.map(move |c| {
+                c.receive(buf, frame_len, lqi, crc_valid, result);
+            });
+        } else {
+            self.radio.set_receive_buffer(buf);
+        }
+    }
+}
+
+impl<'a, R: radio::Radio<'a>, A: Alarm<'a>> time::AlarmClient for CsmaMac<'a, R, A> {
+    fn alarm(&self) {
+        match self.state.get() {
+            TxState::Backoff => self.attempt(),
+            // No acknowledgement arrived in time.
+            TxState::AwaitingAck => self.retry(),
+            TxState::Idle | TxState::Random | TxState::Transmitting => {}
+        }
+    }
+}
+
+impl<'a, R: radio::Radio<'a>, A: Alarm<'a>> rng::Client for CsmaMac<'a, R, A> {
+    fn randomness_available(
+        &self,
+        randomness: &mut dyn Iterator<Item = u32>,
+        _error: Result<(), ErrorCode>,
+    ) -> rng::Continue {
+        if self.state.get() != TxState::Random {
+            return rng::Continue::Done;
+        }
+        match randomness.next() {
+            Some(random) => {
+                self.start_backoff(random);
+                rng::Continue::Done
+            }
+            None => rng::Continue::More,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use kernel::hil::radio::{
+        RadioChannel, RadioConfig, RadioData, RxClient, TxClient, MAX_BUF_SIZE,
+    };
+    use kernel::hil::rng::Client as _;
+    use kernel::hil::time::{AlarmClient, Freq1MHz, Ticks32, Time};
+    use std::boxed::Box;
+
+    const OWN_ADDR: u16 = 0x1234;
+    const PEER_ADDR: u16 = 0x5678;
+    const SEQ: u8 = 0x42;
+    const FRAME_LEN: usize = 12;
+
+    /// Time the layer waits for an acknowledgement.
+    const ACK_WAIT_US: u32 = ACK_WAIT_DURATION * SYMBOL_US + ACK_WAIT_MARGIN_US;
+
+    fn backoff_us(periods: u32) -> u32 {
+        periods * UNIT_BACKOFF_PERIOD * SYMBOL_US
+    }
+
+    /// A data frame with short addresses and a compressed PAN ID, followed
+    /// by 3 bytes of payload.
+    fn data_frame(dst: u16, ack_requested: bool) -> &'static mut [u8] {
+        let frame = Box::leak(Box::new([0; MAX_BUF_SIZE]));
+        let frame_control: u16 = 0x8841 | if ack_requested { 0x20 } else { 0 };
+        frame[0..2].copy_from_slice(&frame_control.to_le_bytes());
+        frame[2] = SEQ;
+        frame[3..5].copy_from_slice(&0xabcdu16.to_le_bytes());
+        frame[5..7].copy_from_slice(&dst.to_le_bytes());
+        frame[7..9].copy_from_slice(&OWN_ADDR.to_le_bytes());
+        frame[9..FRAME_LEN].copy_from_slice(&[1, 2, 3]);
+        frame
+    }
+
+    /// A received acknowledgement frame for sequence number `seq`.
+    fn ack_frame(seq: u8) -> &'static mut [u8] {
+        let frame = Box::leak(Box::new([0; MAX_BUF_SIZE]));
+        frame[PSDU_OFFSET..PSDU_OFFSET + 3].copy_from_slice(&[0x02, 0x00, seq]);
+        frame
+    }
+
+    struct FakeRadio {
+        tx_buf: TakeCell<'static, [u8]>,
+        tx_count: Cell<usize>,
+        rx_buf: TakeCell<'static, [u8]>,
+    }
+
+    impl<'a> RadioConfig<'a> for FakeRadio {
+        fn initialize(&self) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+        fn reset(&self) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+        fn start(&self) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+        fn stop(&self) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+        fn is_on(&self) -> bool {
+            true
+        }
+        fn busy(&self) -> bool {
+            self.tx_buf.is_some()
+        }
+        fn set_power_client(&self, _client: &'a dyn radio::PowerClient) {}
+        fn config_commit(&self) {}
+        fn set_config_client(&self, _client: &'a dyn radio::ConfigClient) {}
+        fn get_address(&self) -> u16 {
+            OWN_ADDR
+        }
+        fn get_address_long(&self) -> [u8; 8] {
+            [0; 8]
+        }
+        fn get_pan(&self) -> u16 {
+            0xabcd
+        }
+        fn get_tx_power(&self) -> i8 {
+            0
+        }
+        fn get_channel(&self) -> u8 {
+            26
+        }
+        fn set_address(&self, _addr: u16) {}
+        fn set_address_long(&self, _addr: [u8; 8]) {}
+        fn set_pan(&self, _id: u16) {}
+        fn set_tx_power(&self, _power: i8) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+        fn set_channel(&self, _chan: RadioChannel) {}
+    }
+
+    impl<'a> RadioData<'a> for FakeRadio {
+        fn set_transmit_client(&self, _client: &'a dyn radio::TxClient) {}
+        fn set_receive_client(&self, _client: &'a dyn radio::RxClient) {}
+        fn set_receive_buffer(&self, receive_buffer: &'static mut [u8]) {
+            self.rx_buf.replace(receive_buffer);
+        }
+        fn transmit(
+            &self,
+            buf: &'static mut [u8],
+            frame_len: usize,
+        ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+            assert_eq!(frame_len, FRAME_LEN);
+            assert_eq!(buf[PSDU_OFFSET + 2], SEQ);
+            self.tx_buf.replace(buf);
+            self.tx_count.set(self.tx_count.get() + 1);
+            Ok(())
+        }
+    }
+
+    struct FakeAlarm<'a> {
+        dt: Cell<Option<u32>>,
+        client: OptionalCell<&'a dyn AlarmClient>,
+    }
+
+    impl Time for FakeAlarm<'_> {
+        type Ticks = Ticks32;
+        type Frequency = Freq1MHz;
+
+        fn now(&self) -> Ticks32 {
+            0u32.into()
+        }
+    }
+
+    impl<'a> Alarm<'a> for FakeAlarm<'a> {
+        fn set_alarm_client(&self, client: &'a dyn AlarmClient) {
+            self.client.set(client);
+        }
+        fn set_alarm(&self, _reference: Self::Ticks, dt: Self::Ticks) {
+            self.dt.set(Some(dt.into_u32()));
+        }
+        fn get_alarm(&self) -> Self::Ticks {
+            self.dt.get().unwrap_or(0).into()
+        }
+        fn disarm(&self) -> Result<(), ErrorCode> {
+            self.dt.set(None);
+            Ok(())
+        }
+        fn is_armed(&self) -> bool {
+            self.dt.get().is_some()
+        }
+        fn minimum_dt(&self) -> Self::Ticks {
+            0u32.into()
+        }
+    }
+
+    struct FakeRng {
+        requested: Cell<bool>,
+    }
+
+    impl<'a> Rng<'a> for FakeRng {
+        fn get(&self) -> Result<(), ErrorCode> {
+            self.requested.set(true);
+            Ok(())
+        }
+        fn cancel(&self) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+        fn set_client(&'a self, _client: &'a dyn rng::Client) {}
+    }
+
+    struct Recorder {
+        tx_done: Cell<Option<(bool, Result<(), ErrorCode>)>>,
+        rx_count: Cell<usize>,
+    }
+
+    impl TxClient for Recorder {
+        fn send_done(&self, _buf: &'static mut [u8], acked: bool, result: Result<(), ErrorCode>) {
+            assert!(self.tx_done.get().is_none());
+            self.tx_done.set(Some((acked, result)));
+        }
+    }
+
+    impl RxClient for Recorder {
+        fn receive(
+            &self,
+            _buf: &'static mut [u8],
+            _frame_len: usize,
+            _lqi: u8,
+            _crc_valid: bool,
+            _result: Result<(), ErrorCode>,
+        ) {
+            self.rx_count.set(self.rx_count.get() + 1);
+        }
+    }
+
+    struct Harness<'a> {
+        mac: &'a CsmaMac<'a, FakeRadio, FakeAlarm<'a>>,
+        radio: &'a FakeRadio,
+        alarm: &'a FakeAlarm<'a>,
+        rng: &'a FakeRng,
+        client: &'a Recorder,
+    }
+
+    impl Harness<'_> {
+        /// Provides the random number for the pending backoff, and checks
+        /// that the backoff lasts `periods` backoff periods.
+        fn backoff(&self, random: u32, periods: u32) {
+            assert!(self.rng.requested.take());
+            let mut randomness = core::iter::once(random);
+            self.mac.randomness_available(&mut randomness, Ok(()));
+            assert_eq!(self.alarm.dt.get(), Some(backoff_us(periods)));
+        }
+
+        /// Fires the armed alarm.
+        fn fire(&self) {
+            assert!(self.alarm.dt.take().is_some());
+            self.mac.alarm();
+        }
+
+        /// Backs off, and has the radio transmit the frame with `result`.
+        fn attempt(&self, acked: bool, result: Result<(), ErrorCode>) {
+            self.backoff(0, 0);
+            self.fire();
+            let buf = self.radio.tx_buf.take().expect("frame not transmitted");
+            self.mac.send_done(buf, acked, result);
+        }
+
+        fn receive(&self, frame: &'static mut [u8]) {
+            self.mac.receive(frame, FRAME_LEN, 0, true, Ok(()));
+        }
+    }
+
+    fn with_harness(test: impl FnOnce(&Harness)) {
+        let radio = FakeRadio {
+            tx_buf: TakeCell::empty(),
+            tx_count: Cell::new(0),
+            rx_buf: TakeCell::empty(),
+        };
+        let alarm = FakeAlarm {
+            dt: Cell::new(None),
+            client: OptionalCell::empty(),
+        };
+        let rng = FakeRng {
+            requested: Cell::new(false),
+        };
+        let client = Recorder {
+            tx_done: Cell::new(None),
+            rx_count: Cell::new(0),
+        };
+        let mac = CsmaMac::new(&radio, &alarm, &rng);
+        alarm.set_alarm_client(&mac);
+        mac.set_transmit_client(&client);
+        mac.set_receive_client(&client);
+        test(&Harness {
+            mac: &mac,
+            radio: &radio,
+            alarm: &alarm,
+            rng: &rng,
+            client: &client,
+        });
+    }
+
+    #[test]
+    fn test_acknowledged() {
+        with_harness(|h| {
+            assert_eq!(
+                h.mac.transmit(data_frame(PEER_ADDR, true), FRAME_LEN).err(),
+                None
+            );
+            // A random backoff of 0 to 2^MAC_MIN_BE - 1 periods.
+            h.backoff(13, 13 % 8);
+            h.fire();
+            assert_eq!(h.radio.tx_count.get(), 1);
+            h.mac
+                .send_done(h.radio.tx_buf.take().unwrap(), false, Ok(()));
+            assert_eq!(h.alarm.dt.get(), Some(ACK_WAIT_US));
+            assert_eq!(h.client.tx_done.get(), None);
+
+            // Acknowledgements of other frames are ignored.
+            h.receive(ack_frame(SEQ + 1));
+            assert_eq!(h.client.tx_done.get(), None);
+            assert!(h.radio.rx_buf.take().is_some());
+
+            h.receive(ack_frame(SEQ));
+            assert_eq!(h.client.tx_done.get(), Some((true, Ok(()))));
+            assert!(!h.alarm.is_armed());
+            assert!(h.radio.rx_buf.take().is_some());
+            assert_eq!(h.client.rx_count.get(), 0);
+        });
+    }
+
+    #[test]
+    fn test_retransmitted_until_noack() {
+        with_harness(|h| {
+            assert_eq!(
+                h.mac.transmit(data_frame(PEER_ADDR, true), FRAME_LEN).err(),
+                None
+            );
+            for _ in 0..=MAC_MAX_FRAME_RETRIES {
+                assert_eq!(h.client.tx_done.get(), None);
+                h.attempt(false, Ok(()));
+                assert_eq!(h.alarm.dt.get(), Some(ACK_WAIT_US));
+                h.fire();
+            }
+            assert_eq!(h.radio.tx_count.get(), 1 + MAC_MAX_FRAME_RETRIES as usize);
+            assert_eq!(h.client.tx_done.get(), Some((false, Err(ErrorCode::NOACK))));
+
+            // An acknowledgement arriving late is not mistaken for one of
+            // the next frame.
+            h.receive(ack_frame(SEQ));
+            assert!(h.radio.rx_buf.take().is_some());
+        });
+    }
+
+    #[test]
+    fn test_retransmission_acknowledged() {
+        with_harness(|h| {
+            assert_eq!(
+                h.mac.transmit(data_frame(PEER_ADDR, true), FRAME_LEN).err(),
+                None
+            );
+            h.attempt(false, Ok(()));
+            h.fire();
+            h.attempt(false, Ok(()));
+            h.receive(ack_frame(SEQ));
+            assert_eq!(h.radio.tx_count.get(), 2);
+            assert_eq!(h.client.tx_done.get(), Some((true, Ok(()))));
+        });
+    }
+
+    #[test]
+    fn test_acknowledged_by_radio() {
+        with_harness(|h| {
+            assert_eq!(
+                h.mac.transmit(data_frame(PEER_ADDR, true), FRAME_LEN).err(),
+                None
+            );
+            h.attempt(true, Ok(()));
+            assert_eq!(h.client.tx_done.get(), Some((true, Ok(()))));
+            assert!(!h.alarm.is_armed());
+        });
+    }
+
+    #[test]
+    fn test_no_ack_requested() {
+        with_harness(|h| {
+            assert_eq!(
+                h.mac
+                    .transmit(data_frame(PEER_ADDR, false), FRAME_LEN)
+                    .err(),
+                None
+            );
+            h.attempt(false, Ok(()));
+            assert_eq!(h.client.tx_done.get(), Some((false, Ok(()))));
+        });
+    }
+
+    #[test]
+    fn test_broadcast_not_acknowledged() {
+        with_harness(|h| {
+            assert_eq!(
+                h.mac.transmit(data_frame(0xffff, true), FRAME_LEN).err(),
+                None
+            );
+            h.attempt(false, Ok(()));
+            assert_eq!(h.client.tx_done.get(), Some((false, Ok(()))));
+            assert!(!h.alarm.is_armed());
+        });
+    }
+
+    #[test]
+    fn test_channel_access_failure() {
+        with_harness(|h| {
+            assert_eq!(
+                h.mac.transmit(data_frame(PEER_ADDR, true), FRAME_LEN).err(),
+                None
+            );
+            // The largest backoff doubles with each busy channel, up to
+            // 2^MAC_MAX_BE - 1 periods.
+            let mut exponent = MAC_MIN_BE;
+            for _ in 0..=MAC_MAX_CSMA_BACKOFFS {
+                h.backoff(u32::MAX, (1 << exponent) - 1);
+                h.fire();
+                h.mac
+                    .send_done(h.radio.tx_buf.take().unwrap(), false, Err(ErrorCode::BUSY));
+                exponent = core::cmp::min(exponent + 1, MAC_MAX_BE);
+            }
+            assert_eq!(h.radio.tx_count.get(), 1 + MAC_MAX_CSMA_BACKOFFS as usize);
+            assert_eq!(h.client.tx_done.get(), Some((false, Err(ErrorCode::BUSY))));
+        });
+    }
+
+    #[test]
+    fn test_busy_channel_then_acknowledged() {
+        with_harness(|h| {
+            assert_eq!(
+                h.mac.transmit(data_frame(PEER_ADDR, true), FRAME_LEN).err(),
+                None
+            );
+            h.attempt(false, Err(ErrorCode::BUSY));
+            h.attempt(false, Ok(()));
+            h.receive(ack_frame(SEQ));
+            assert_eq!(h.client.tx_done.get(), Some((true, Ok(()))));
+        });
+    }
+
+    #[test]
+    fn test_transmit_while_busy() {
+        with_harness(|h| {
+            assert_eq!(
+                h.mac.transmit(data_frame(PEER_ADDR, true), FRAME_LEN).err(),
+                None
+            );
+            let result = h.mac.transmit(data_frame(PEER_ADDR, true), FRAME_LEN);
+            assert_eq!(result.err().map(|(ecode, _)| ecode), Some(ErrorCode::BUSY));
+        });
+    }
+
+    #[test]
+    fn test_receive_filtered_by_address() {
+        with_harness(|h| {
+            let frame = data_frame(OWN_ADDR, false);
+            frame.copy_within(0..FRAME_LEN, PSDU_OFFSET);
+            h.receive(frame);
+            assert_eq!(h.client.rx_count.get(), 1);
+
+            let frame = data_frame(PEER_ADDR, false);
+            frame.copy_within(0..FRAME_LEN, PSDU_OFFSET);
+            h.receive(frame);
+            assert_eq!(h.client.rx_count.get(), 1);
+            assert!(h.radio.rx_buf.take().is_some());
+        });
+    }
+}
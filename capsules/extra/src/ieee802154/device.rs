@@ -91,7 +91,10 @@ pub trait TxClient {
     /// returned to the client here.
     /// - `acked`: Whether the transmission was acknowledged.
     /// - `result`: This is `Ok(())` if the frame was transmitted,
-    /// otherwise an error occurred in the transmission pipeline.
+    /// otherwise an error occurred in the transmission pipeline. MAC layers
+    /// that wait for acknowledgements report `Err(ErrorCode::NOACK)` if the
+    /// destination never acknowledged the frame, and `Err(ErrorCode::BUSY)`
+    /// on a channel access failure.
     fn send_done(&self, spi_buf: &'static mut [u8], acked: bool, result: Result<(), ErrorCode>);
 }
 
@@ -0,0 +1,459 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Simulated IEEE 802.15.4 radio on top of an Ethernet adapter.
+//!
+//! `EthernetRadio` implements `hil::radio::Radio` by broadcasting every frame
+//! on an Ethernet link, such as a VirtIO network card of QEMU, and by
+//! receiving the frames other nodes broadcast on the same link. Connecting
+//! the links of several emulated nodes, for instance with the hub in
+//! `tools/ieee802154_hub.py`, puts them into one simulated PAN, in which the
+//! MAC, 6LoWPAN and UDP layers of the kernel run unchanged.
+//!
+//! Each 802.15.4 frame travels in an Ethernet frame sent to the broadcast
+//! address with the EtherType `ETHERTYPE`. The Ethernet payload starts with
+//! the channel and the length of the 802.15.4 frame without its FCS, followed
+//! by the frame:
+//!
+//! ```text
+//! 0       Channel (11 to 26)
+//! 1       Frame length n
+//! 2..2+n  MAC frame, without FCS
+//! ```
+//!
+//! The link neither corrupts frames nor lets them collide, so the channel is
+//! always clear and received frames always have a valid FCS; the hub can drop
+//! frames to simulate losses. Frames on other channels are ignored, and the
+//! radio does not filter frames by address. Like radios with hardware support
+//! for acknowledgements, the radio acknowledges received frames that are
+//! addressed to it and request an acknowledgement, and waits for the
+//! acknowledgement of the frames it sends, reporting it with `acked`.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let radio = static_init!(
+//!     capsules_extra::ieee802154::ethernet_radio::EthernetRadio<'static, VirtualMuxAlarm<'static, Clint>>,
+//!     capsules_extra::ieee802154::ethernet_radio::EthernetRadio::new(adapter, alarm, mac_addr, frame_buf)
+//! );
+//! adapter.set_client(radio);
+//! alarm.set_alarm_client(radio);
+//! kernel::deferred_call::DeferredCallClient::register(radio);
+//! ```
+
+use crate::net::ieee802154::{FrameType, Header, MacAddress};
+use core::cell::Cell;
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::ethernet::{
+    EthernetAdapter, EthernetAdapterClient, ETHERNET_HDR_LEN, MAC_ADDR_LEN,
+};
+use kernel::hil::radio::{self, RadioChannel, MAX_FRAME_SIZE, MFR_SIZE, PHR_OFFSET, PSDU_OFFSET};
+use kernel::hil::time::{self, Alarm, ConvertTicks};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// EtherType of the Ethernet frames carrying 802.15.4 frames (IEEE Std 802
+/// Local Experimental EtherType 1).
+pub const ETHERTYPE: u16 = 0x88B5;
+
+/// Length of the channel and length fields preceding the 802.15.4 frame.
+const SIM_HDR_LEN: usize = 2;
+
+/// Length of the Ethernet frame buffer passed to `EthernetRadio::new`.
+pub const FRAME_BUF_LEN: usize = ETHERNET_HDR_LEN + SIM_HDR_LEN + MAX_FRAME_SIZE;
+
+/// Length of an acknowledgement frame without its FCS: the frame control
+/// field and the sequence number.
+const ACK_LEN: usize = 3;
+
+/// Time to wait for an acknowledgement. Peers behind the hub answer much
+/// later than the turnaround time of a real radio.
+const ACK_WAIT_MS: u32 = 10;
+
+/// Link quality reported for all received frames.
+const LQI: u8 = 0xFF;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum TxState {
+    /// No frame is being transmitted.
+    Idle,
+    /// The frame waits for an acknowledgement to be sent first.
+    Pending,
+    /// The adapter is transmitting the frame.
+    Transmitting,
+    /// Waiting for the acknowledgement of the frame.
+    AwaitingAck,
+}
+
+pub struct EthernetRadio<'a, A: Alarm<'a>> {
+    adapter: &'a dyn EthernetAdapter<'a>,
+    alarm: &'a A,
+    mac_addr: [u8; MAC_ADDR_LEN],
+
+    /// Ethernet frame buffer, while the adapter does not hold it.
+    frame: TakeCell<'static, [u8]>,
+    /// Whether the adapter is transmitting an acknowledgement.
+    sending_ack: Cell<bool>,
+    /// Sequence number of a received frame still to be acknowledged.
+    pending_ack: OptionalCell<u8>,
+
+    tx_state: Cell<TxState>,
+    tx_buf: TakeCell<'static, [u8]>,
+    tx_len: Cell<usize>,
+    /// Sequence number of the frame, if it requested an acknowledgement.
+    tx_seq: OptionalCell<u8>,
+    rx_buf: TakeCell<'static, [u8]>,
+
+    on: Cell<bool>,
+    receiving: Cell<bool>,
+    addr: Cell<u16>,
+    addr_long: Cell<[u8; 8]>,
+    pan: Cell<u16>,
+    tx_power: Cell<i8>,
+    channel: Cell<RadioChannel>,
+
+    tx_client: OptionalCell<&'a dyn radio::TxClient>,
+    rx_client: OptionalCell<&'a dyn radio::RxClient>,
+    config_client: OptionalCell<&'a dyn radio::ConfigClient>,
+    power_client: OptionalCell<&'a dyn radio::PowerClient>,
+
+    deferred_call: DeferredCall,
+    config_done_pending: Cell<bool>,
+    power_changed_pending: Cell<bool>,
+}
+
+impl<'a, A: Alarm<'a>> EthernetRadio<'a, A> {
+    /// Creates a radio sending from the adapter's MAC address `mac_addr`.
+    /// `frame` must be at least `FRAME_BUF_LEN` bytes long.
+    pub fn new(
+        adapter: &'a dyn EthernetAdapter<'a>,
+        alarm: &'a A,
+        mac_addr: [u8; MAC_ADDR_LEN],
+        frame: &'static mut [u8],
+    ) -> EthernetRadio<'a, A> {
+        EthernetRadio {
+            adapter,
+            alarm,
+            mac_addr,
+            frame: TakeCell::new(frame),
+            sending_ack: Cell::new(false),
+            pending_ack: OptionalCell::empty(),
+            tx_state: Cell::new(TxState::Idle),
+            tx_buf: TakeCell::empty(),
+            tx_len: Cell::new(0),
+            tx_seq: OptionalCell::empty(),
+            rx_buf: TakeCell::empty(),
+            on: Cell::new(false),
+            receiving: Cell::new(false),
+            addr: Cell::new(0),
+            addr_long: Cell::new([0; 8]),
+            pan: Cell::new(0),
+            tx_power: Cell::new(0),
+            channel: Cell::new(RadioChannel::Channel26),
+            tx_client: OptionalCell::empty(),
+            rx_client: OptionalCell::empty(),
+            config_client: OptionalCell::empty(),
+            power_client: OptionalCell::empty(),
+            deferred_call: DeferredCall::new(),
+            config_done_pending: Cell::new(false),
+            power_changed_pending: Cell::new(false),
+        }
+    }
+
+    /// Sends the pending acknowledgement or else the pending frame, if the
+    /// adapter does not hold the Ethernet frame buffer.
+    fn send_next(&self) {
+        let Some(frame) = self.frame.take() else {
+            return;
+        };
+        let payload = &mut frame[ETHERNET_HDR_LEN + SIM_HDR_LEN..];
+        let (len, ack) = if let Some(seq) = self.pending_ack.take() {
+            payload[..ACK_LEN].copy_from_slice(&[FrameType::Acknowledgement as u8, 0, seq]);
+            (ACK_LEN, true)
+        } else if self.tx_state.get() == TxState::Pending {
+            let len = self.tx_len.get();
+            self.tx_buf.map(|buf| {
+                payload[..len].copy_from_slice(&buf[PSDU_OFFSET..PSDU_OFFSET + len]);
+            });
+            self.tx_state.set(TxState::Transmitting);
+            (len, false)
+        } else {
+            self.frame.replace(frame);
+            return;
+        };
+
+        frame[..MAC_ADDR_LEN].copy_from_slice(&[0xFF; MAC_ADDR_LEN]);
+        frame[MAC_ADDR_LEN..2 * MAC_ADDR_LEN].copy_from_slice(&self.mac_addr);
+        frame[2 * MAC_ADDR_LEN..ETHERNET_HDR_LEN].copy_from_slice(&ETHERTYPE.to_be_bytes());
+        frame[ETHERNET_HDR_LEN] = self.channel.get().get_channel_number();
+        frame[ETHERNET_HDR_LEN + 1] = len as u8;
+
+        self.sending_ack.set(ack);
+        if let Err((_, frame)) = self
+            .adapter
+            .transmit(frame, ETHERNET_HDR_LEN + SIM_HDR_LEN + len)
+        {
+            self.frame.replace(frame);
+            if !ack {
+                self.tx_done(false, Err(ErrorCode::FAIL));
+            }
+        }
+    }
+
+    fn tx_done(&self, acked: bool, result: Result<(), ErrorCode>) {
+        self.tx_state.set(TxState::Idle);
+        self.tx_seq.clear();
+        if let Some(buf) = self.tx_buf.take() {
+            self.tx_client.map(move |c| {
+                c.send_done(buf, acked, result);
+            });
+        }
+    }
+
+    /// Whether the frame with `header` is addressed to this radio alone.
+    fn addressed_to_me(&self, header: &Header) -> bool {
+        let addr_match = match header.dst_addr {
+            Some(MacAddress::Short(addr)) => addr == self.addr.get(),
+            Some(MacAddress::Long(addr)) => addr == self.addr_long.get(),
+            None => false,
+        };
+        addr_match && header.dst_pan.map_or(true, |pan| pan == self.pan.get())
+    }
+}
+
+impl<'a, A: Alarm<'a>> EthernetAdapterClient for EthernetRadio<'a, A> {
+    fn transmit_done(&self, result: Result<(), ErrorCode>, frame: &'static mut [u8]) {
+        let was_ack = self.sending_ack.get();
+        self.frame.replace(frame);
+        if !was_ack && result.is_ok() && self.tx_seq.is_some() {
+            self.tx_state.set(TxState::AwaitingAck);
+            self.alarm
+                .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(ACK_WAIT_MS));
+        }
+        // Acknowledgements go first, before the client can pass another frame.
+        self.send_next();
+        if !was_ack && self.tx_state.get() == TxState::Transmitting {
+            self.tx_done(false, result.map_err(|_| ErrorCode::FAIL));
+        }
+    }
+
+    fn received_frame(&self, frame: &[u8]) {
+        if !self.on.get()
+            || frame.len() < ETHERNET_HDR_LEN + SIM_HDR_LEN
+            || frame[MAC_ADDR_LEN..2 * MAC_ADDR_LEN] == self.mac_addr
+            || frame[2 * MAC_ADDR_LEN..ETHERNET_HDR_LEN] != ETHERTYPE.to_be_bytes()
+        {
+            return;
+        }
+        let payload = &frame[ETHERNET_HDR_LEN..];
+        let len = payload[1] as usize;
+        if payload[0] != self.channel.get().get_channel_number()
+            || len + MFR_SIZE > MAX_FRAME_SIZE
+            || payload.len() < SIM_HDR_LEN + len
+        {
+            return;
+        }
+        let psdu = &payload[SIM_HDR_LEN..SIM_HDR_LEN + len];
+        let Some((_, (header, _))) = Header::decode(psdu, false).done() else {
+            return;
+        };
+
+        if header.frame_type == FrameType::Acknowledgement {
+            if self.tx_state.get() == TxState::AwaitingAck && header.seq == self.tx_seq.get() {
+                let _ = self.alarm.disarm();
+                self.tx_done(true, Ok(()));
+            }
+            return;
+        }
+
+        if header.ack_requested && self.addressed_to_me(&header) {
+            if let Some(seq) = header.seq {
+                self.pending_ack.set(seq);
+                self.send_next();
+            }
+        }
+
+        // Without a receive buffer, the client is still busy with the last
+        // frame and this one is lost.
+        if let Some(buf) = self.rx_buf.take() {
+            if buf.len() < PSDU_OFFSET + len + MFR_SIZE {
+                self.rx_buf.replace(buf);
+                return;
+            }
+            buf[PHR_OFFSET] = (len + MFR_SIZE) as u8;
+            buf[PSDU_OFFSET..PSDU_OFFSET + len].copy_from_slice(psdu);
+            match self.rx_client.get() {
+                Some(client) => client.receive(buf, len, LQI, true, Ok(())),
+                None => {
+                    self.rx_buf.replace(buf);
+                }
+            }
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>> time::AlarmClient for EthernetRadio<'a, A> {
+    fn alarm(&self) {
+        if self.tx_state.get() == TxState::AwaitingAck {
+            self.tx_done(false, Ok(()));
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>> DeferredCallClient for EthernetRadio<'a, A> {
+    fn handle_deferred_call(&self) {
+        if self.power_changed_pending.take() {
+            self.power_client.map(|c| c.changed(self.on.get()));
+        }
+        if self.config_done_pending.take() {
+            self.config_client.map(|c| c.config_done(Ok(())));
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+impl<'a, A: Alarm<'a>> radio::RadioConfig<'a> for EthernetRadio<'a, A> {
+    fn initialize(&self) -> Result<(), ErrorCode> {
+        Ok(())
+    }
+
+    fn reset(&self) -> Result<(), ErrorCode> {
+        Ok(())
+    }
+
+    fn start(&self) -> Result<(), ErrorCode> {
+        self.on.set(true);
+        if !self.receiving.get() {
+            self.receiving.set(true);
+            self.adapter.enable_receive();
+        }
+        self.power_changed_pending.set(true);
+        self.deferred_call.set();
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<(), ErrorCode> {
+        // The adapter keeps receiving, but frames are dropped while the
+        // radio is off.
+        self.on.set(false);
+        self.power_changed_pending.set(true);
+        self.deferred_call.set();
+        Ok(())
+    }
+
+    fn is_on(&self) -> bool {
+        self.on.get()
+    }
+
+    fn busy(&self) -> bool {
+        self.tx_state.get() != TxState::Idle
+    }
+
+    fn set_power_client(&self, client: &'a dyn radio::PowerClient) {
+        self.power_client.set(client);
+    }
+
+    fn config_commit(&self) {
+        // The configuration takes effect right away, there is no hardware to
+        // update.
+        self.config_done_pending.set(true);
+        self.deferred_call.set();
+    }
+
+    fn set_config_client(&self, client: &'a dyn radio::ConfigClient) {
+        self.config_client.set(client);
+    }
+
+    fn get_address(&self) -> u16 {
+        self.addr.get()
+    }
+
+    fn get_address_long(&self) -> [u8; 8] {
+        self.addr_long.get()
+    }
+
+    fn get_pan(&self) -> u16 {
+        self.pan.get()
+    }
+
+    fn get_tx_power(&self) -> i8 {
+        self.tx_power.get()
+    }
+
+    fn get_channel(&self) -> u8 {
+        self.channel.get().get_channel_number()
+    }
+
+    fn set_address(&self, addr: u16) {
+        self.addr.set(addr);
+    }
+
+    fn set_address_long(&self, addr: [u8; 8]) {
+        self.addr_long.set(addr);
+    }
+
+    fn set_pan(&self, id: u16) {
+        self.pan.set(id);
+    }
+
+    fn set_tx_power(&self, power: i8) -> Result<(), ErrorCode> {
+        // The link has no range, the power is only reported back.
+        self.tx_power.set(power);
+        Ok(())
+    }
+
+    fn set_channel(&self, chan: RadioChannel) {
+        self.channel.set(chan);
+    }
+}
+
+impl<'a, A: Alarm<'a>> radio::RadioData<'a> for EthernetRadio<'a, A> {
+    fn set_transmit_client(&self, client: &'a dyn radio::TxClient) {
+        self.tx_client.set(client);
+    }
+
+    fn set_receive_client(&self, client: &'a dyn radio::RxClient) {
+        self.rx_client.set(client);
+    }
+
+    fn set_receive_buffer(&self, receive_buffer: &'static mut [u8]) {
+        self.rx_buf.replace(receive_buffer);
+    }
+
+    fn transmit(
+        &self,
+        buf: &'static mut [u8],
+        frame_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if !self.on.get() {
+            return Err((ErrorCode::OFF, buf));
+        } else if self.tx_state.get() != TxState::Idle {
+            return Err((ErrorCode::BUSY, buf));
+        } else if frame_len + MFR_SIZE > MAX_FRAME_SIZE
+            || buf.len() < PSDU_OFFSET + frame_len + MFR_SIZE
+        {
+            return Err((ErrorCode::SIZE, buf));
+        }
+
+        let seq = Header::decode(&buf[PSDU_OFFSET..PSDU_OFFSET + frame_len], false)
+            .done()
+            .and_then(|(_, (header, _))| {
+                // Broadcast frames are never acknowledged.
+                let broadcast = header.dst_addr == Some(MacAddress::Short(0xFFFF));
+                header.seq.filter(|_| header.ack_requested && !broadcast)
+            });
+        self.tx_seq.insert(seq);
+        self.tx_len.set(frame_len);
+        self.tx_buf.replace(buf);
+        self.tx_state.set(TxState::Pending);
+        self.send_next();
+        Ok(())
+    }
+}
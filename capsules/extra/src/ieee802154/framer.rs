@@ -823,8 +823,9 @@ impl<'a, M: Mac<'a>, A: AES128CCM<'a>> MacDevice<'a> for Framer<'a, M, A> {
             /* TODO: determine this by looking at queue, and also set it in
              * hardware so that ACKs set this flag to the right value. */
             frame_pending: false,
-            // Unicast data frames request acknowledgement
-            ack_requested: true,
+            // Unicast data frames request acknowledgement. Broadcast frames
+            // must not, as no single node acknowledges them.
+            ack_requested: dst_addr != MacAddress::Short(0xFFFF),
             version: FrameVersion::V2006,
             seq: Some(self.data_sequence.get()),
             dst_pan: Some(dst_pan),
@@ -878,7 +879,8 @@ impl<'a, M: Mac<'a>, A: AES128CCM<'a>> MacDevice<'a> for Framer<'a, M, A> {
 
 impl<'a, M: Mac<'a>, A: AES128CCM<'a>> radio::TxClient for Framer<'a, M, A> {
     fn send_done(&self, buf: &'static mut [u8], acked: bool, result: Result<(), ErrorCode>) {
-        self.data_sequence.set(self.data_sequence.get() + 1);
+        self.data_sequence
+            .set(self.data_sequence.get().wrapping_add(1));
         self.tx_client.map(move |client| {
             client.send_done(buf, acked, result);
         });
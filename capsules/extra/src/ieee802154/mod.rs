@@ -4,7 +4,9 @@
 
 //! Support for IEEE 802.15.4.
 
+pub mod csma;
 pub mod device;
+pub mod ethernet_radio;
 pub mod framer;
 pub mod mac;
 pub mod virtual_mac;
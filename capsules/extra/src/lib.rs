@@ -2,7 +2,10 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 // Copyright Tock Contributors 2022.
 
-#![forbid(unsafe_code)]
+// Unit tests create capabilities with `create_capability!`, which needs a
+// locally allowed `unsafe impl`.
+#![cfg_attr(not(test), forbid(unsafe_code))]
+#![cfg_attr(test, deny(unsafe_code))]
 #![no_std]
 
 pub mod test;
@@ -12,12 +15,14 @@ pub mod tutorials;
 pub mod net;
 
 pub mod adc_microphone;
+pub mod aes128;
 pub mod air_quality;
 pub mod ambient_light;
 pub mod analog_comparator;
 pub mod analog_sensor;
 pub mod apds9960;
 pub mod app_flash_driver;
+pub mod app_loader;
 pub mod at24c_eeprom;
 pub mod atecc508a;
 pub mod ble_advertising_driver;
@@ -29,11 +34,15 @@ pub mod buzzer_driver;
 pub mod buzzer_pwm;
 pub mod can;
 pub mod ccs811;
+pub mod crash_log;
 pub mod crc;
+pub mod ctap2;
 pub mod cycle_count;
 pub mod dac;
 pub mod date_time;
+pub mod deadline_scheduler;
 pub mod debug_process_restart;
+pub mod ecdsa_p256;
 pub mod eui64;
 pub mod fm25cl;
 pub mod ft6x06;
@@ -42,6 +51,7 @@ pub mod gpio_async;
 pub mod hd44780;
 pub mod hmac;
 pub mod hmac_sha256;
+pub mod hmac_sha512;
 pub mod hs3003;
 pub mod hts221;
 pub mod humidity;
@@ -84,6 +94,7 @@ pub mod seven_segment;
 pub mod sh1106;
 pub mod sha;
 pub mod sha256;
+pub mod sha512;
 pub mod sht3x;
 pub mod sht4x;
 pub mod si7021;
@@ -100,6 +111,7 @@ pub mod tickv;
 pub mod tickv_kv_store;
 pub mod touch;
 pub mod tsl2561;
+pub mod uart_app_loader;
 pub mod usb;
 pub mod usb_hid_driver;
 pub mod virtual_kv;
@@ -0,0 +1,708 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! CoAP message format (RFC 7252 -- sect. 3) and block options (RFC 7959).
+//!
+//! A CoAP message starts with a fixed 4 byte header followed by a token of up
+//! to 8 bytes, a list of options and, optionally, a payload marker (`0xFF`)
+//! followed by the payload:
+//!
+//! ```text
+//!  0                   1                   2                   3
+//!  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+//! +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//! |Ver| T |  TKL  |      Code     |          Message ID           |
+//! +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//! |   Token (if any, TKL bytes) ...
+//! +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//! |   Options (if any) ...
+//! +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//! |1 1 1 1 1 1 1 1|    Payload (if any) ...
+//! +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//! ```
+//!
+//! Options are sorted by option number, and each option encodes the
+//! difference to the number of the previous option. `CoapMessage::decode`
+//! validates a received message and gives access to its options through
+//! `CoapOptionIter`, while `CoapMessageBuilder` encodes a message in place.
+
+use kernel::ErrorCode;
+
+/// The only CoAP version defined.
+pub const COAP_VERSION: u8 = 1;
+
+/// Length of the fixed CoAP header.
+pub const COAP_HDR_LEN: usize = 4;
+
+/// Maximum length of a token.
+pub const MAX_TOKEN_LEN: usize = 8;
+
+/// Marker separating the options from the payload.
+const PAYLOAD_MARKER: u8 = 0xff;
+
+/// Message codes (RFC 7252 -- sect. 12.1), written as `class << 5 | detail`.
+pub mod code {
+    pub const EMPTY: u8 = 0x00;
+    pub const GET: u8 = 0x01;
+    pub const POST: u8 = 0x02;
+    pub const PUT: u8 = 0x03;
+    pub const DELETE: u8 = 0x04;
+    /// 2.04 Changed
+    pub const CHANGED: u8 = 0x44;
+    /// 2.31 Continue (RFC 7959 -- sect. 2.9.1)
+    pub const CONTINUE: u8 = 0x5f;
+    /// 4.00 Bad Request
+    pub const BAD_REQUEST: u8 = 0x80;
+    /// 4.01 Unauthorized
+    pub const UNAUTHORIZED: u8 = 0x81;
+    /// 4.02 Bad Option
+    pub const BAD_OPTION: u8 = 0x82;
+    /// 4.04 Not Found
+    pub const NOT_FOUND: u8 = 0x84;
+    /// 5.03 Service Unavailable
+    pub const SERVICE_UNAVAILABLE: u8 = 0xa3;
+
+    /// Returns the class of a code: 0 for requests, 2 to 5 for responses.
+    pub fn class(code: u8) -> u8 {
+        code >> 5
+    }
+
+    /// Whether `code` is a request method.
+    pub fn is_request(code: u8) -> bool {
+        class(code) == 0 && code != EMPTY
+    }
+
+    /// Whether `code` is a response code.
+    pub fn is_response(code: u8) -> bool {
+        (2..=5).contains(&class(code))
+    }
+}
+
+/// Option numbers (RFC 7252 -- sect. 12.2, RFC 7959 -- sect. 2.1 and
+/// RFC 8613 -- sect. 2).
+pub mod option {
+    pub const URI_HOST: u16 = 3;
+    pub const URI_PORT: u16 = 7;
+    pub const OSCORE: u16 = 9;
+    pub const URI_PATH: u16 = 11;
+    pub const CONTENT_FORMAT: u16 = 12;
+    pub const URI_QUERY: u16 = 15;
+    pub const BLOCK2: u16 = 23;
+    pub const BLOCK1: u16 = 27;
+    pub const SIZE2: u16 = 28;
+    pub const SIZE1: u16 = 60;
+
+    /// Critical options must be understood by the receiver, which otherwise
+    /// has to reject the message (RFC 7252 -- sect. 5.4.1).
+    pub fn is_critical(number: u16) -> bool {
+        number & 1 == 1
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CoapType {
+    Confirmable = 0,
+    NonConfirmable = 1,
+    Acknowledgement = 2,
+    Reset = 3,
+}
+
+impl CoapType {
+    fn from_bits(bits: u8) -> CoapType {
+        match bits & 0b11 {
+            0 => CoapType::Confirmable,
+            1 => CoapType::NonConfirmable,
+            2 => CoapType::Acknowledgement,
+            _ => CoapType::Reset,
+        }
+    }
+}
+
+/// The fixed header and the token of a CoAP message.
+#[derive(Copy, Clone, Debug)]
+pub struct CoapHeader {
+    pub mtype: CoapType,
+    pub code: u8,
+    pub msg_id: u16,
+    token: [u8; MAX_TOKEN_LEN],
+    token_len: u8,
+}
+
+impl CoapHeader {
+    pub fn new(mtype: CoapType, code: u8, msg_id: u16, token: &[u8]) -> CoapHeader {
+        let token_len = core::cmp::min(token.len(), MAX_TOKEN_LEN);
+        let mut header = CoapHeader {
+            mtype,
+            code,
+            msg_id,
+            token: [0; MAX_TOKEN_LEN],
+            token_len: token_len as u8,
+        };
+        header.token[..token_len].copy_from_slice(&token[..token_len]);
+        header
+    }
+
+    pub fn get_token(&self) -> &[u8] {
+        &self.token[..self.token_len as usize]
+    }
+
+    /// Decodes the header and the token at the start of `buf`, returning
+    /// them along with the offset of the options.
+    pub fn decode(buf: &[u8]) -> Option<(usize, CoapHeader)> {
+        if buf.len() < COAP_HDR_LEN || buf[0] >> 6 != COAP_VERSION {
+            return None;
+        }
+        let token_len = (buf[0] & 0x0f) as usize;
+        if token_len > MAX_TOKEN_LEN || buf.len() < COAP_HDR_LEN + token_len {
+            return None;
+        }
+        let header = CoapHeader::new(
+            CoapType::from_bits(buf[0] >> 4),
+            buf[1],
+            u16::from_be_bytes([buf[2], buf[3]]),
+            &buf[COAP_HDR_LEN..COAP_HDR_LEN + token_len],
+        );
+        Some((COAP_HDR_LEN + token_len, header))
+    }
+}
+
+/// A received CoAP message, split into header, options and payload.
+pub struct CoapMessage<'b> {
+    pub header: CoapHeader,
+    options: &'b [u8],
+    pub payload: &'b [u8],
+}
+
+impl<'b> CoapMessage<'b> {
+    /// Decodes and validates a CoAP message. Returns `None` for messages
+    /// that have to be silently ignored (RFC 7252 -- sect. 4.2 and 4.3).
+    pub fn decode(buf: &'b [u8]) -> Option<CoapMessage<'b>> {
+        let (offset, header) = CoapHeader::decode(buf)?;
+        Self::decode_body(header, &buf[offset..])
+    }
+
+    /// Decodes the plaintext of a message protected with OSCORE, which
+    /// consists of the code followed by the options and the payload. The
+    /// rest of the header is the one of the outer message.
+    pub fn decode_inner(outer: &CoapHeader, plaintext: &'b [u8]) -> Option<CoapMessage<'b>> {
+        let (code, rest) = plaintext.split_first()?;
+        let mut header = *outer;
+        header.code = *code;
+        Self::decode_body(header, rest)
+    }
+
+    fn decode_body(header: CoapHeader, rest: &'b [u8]) -> Option<CoapMessage<'b>> {
+        // Walk the options to find the payload.
+        let mut iter = CoapOptionIter::new(rest);
+        for opt in iter.by_ref() {
+            opt.ok()?;
+        }
+        let options = &rest[..iter.pos];
+        let payload = match rest.get(iter.pos) {
+            Some(&PAYLOAD_MARKER) if rest.len() > iter.pos + 1 => &rest[iter.pos + 1..],
+            // A payload marker followed by an empty payload is a format error.
+            Some(_) => return None,
+            None => &rest[iter.pos..],
+        };
+
+        // An empty message only consists of the header.
+        if header.code == code::EMPTY
+            && (header.token_len != 0 || !options.is_empty() || !payload.is_empty())
+        {
+            return None;
+        }
+        Some(CoapMessage {
+            header,
+            options,
+            payload,
+        })
+    }
+
+    pub fn options(&self) -> CoapOptionIter<'b> {
+        CoapOptionIter::new(self.options)
+    }
+
+    /// Returns the value of the first option with the given number.
+    pub fn find_option(&self, number: u16) -> Option<&'b [u8]> {
+        self.options()
+            .filter_map(|opt| opt.ok())
+            .find(|(n, _)| *n == number)
+            .map(|(_, value)| value)
+    }
+}
+
+/// Iterator over the options of a message, yielding their number and value.
+/// Stops at the payload marker or at the end of the buffer.
+pub struct CoapOptionIter<'b> {
+    buf: &'b [u8],
+    pos: usize,
+    number: u16,
+    failed: bool,
+}
+
+impl<'b> CoapOptionIter<'b> {
+    fn new(buf: &'b [u8]) -> CoapOptionIter<'b> {
+        CoapOptionIter {
+            buf,
+            pos: 0,
+            number: 0,
+            failed: false,
+        }
+    }
+
+    /// Reads an extended option delta or length with the 4 bit `nibble`.
+    fn read_extended(&mut self, nibble: u8) -> Result<u16, ()> {
+        match nibble {
+            0..=12 => Ok(nibble as u16),
+            13 => {
+                let ext = *self.buf.get(self.pos).ok_or(())?;
+                self.pos += 1;
+                Ok(ext as u16 + 13)
+            }
+            14 => {
+                let ext = self.buf.get(self.pos..self.pos + 2).ok_or(())?;
+                self.pos += 2;
+                u16::from_be_bytes([ext[0], ext[1]])
+                    .checked_add(269)
+                    .ok_or(())
+            }
+            _ => Err(()),
+        }
+    }
+
+    fn read_option(&mut self) -> Result<(u16, &'b [u8]), ()> {
+        let first = self.buf[self.pos];
+        self.pos += 1;
+        let delta = self.read_extended(first >> 4)?;
+        let len = self.read_extended(first & 0x0f)? as usize;
+        let value = self.buf.get(self.pos..self.pos + len).ok_or(())?;
+        self.pos += len;
+        self.number = self.number.checked_add(delta).ok_or(())?;
+        Ok((self.number, value))
+    }
+}
+
+impl<'b> Iterator for CoapOptionIter<'b> {
+    type Item = Result<(u16, &'b [u8]), ()>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        match self.buf.get(self.pos) {
+            None | Some(&PAYLOAD_MARKER) => None,
+            Some(_) => {
+                let opt = self.read_option();
+                self.failed = opt.is_err();
+                Some(opt)
+            }
+        }
+    }
+}
+
+/// Decodes an unsigned integer option value.
+pub fn decode_uint(value: &[u8]) -> Option<u32> {
+    if value.len() > 4 {
+        return None;
+    }
+    Some(value.iter().fold(0, |acc, b| (acc << 8) | *b as u32))
+}
+
+/// Value of a Block1 or Block2 option (RFC 7959 -- sect. 2.2).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BlockOption {
+    /// Number of the block within the body.
+    pub num: u32,
+    /// Whether more blocks follow.
+    pub more: bool,
+    /// Size exponent: blocks are `16 << szx` bytes long.
+    pub szx: u8,
+}
+
+impl BlockOption {
+    /// Largest size exponent; 7 is reserved.
+    pub const MAX_SZX: u8 = 6;
+    /// Block numbers are at most 20 bits long.
+    pub const MAX_NUM: u32 = (1 << 20) - 1;
+
+    pub fn decode(value: &[u8]) -> Option<BlockOption> {
+        let value = decode_uint(value)?;
+        let szx = (value & 0x07) as u8;
+        if szx > Self::MAX_SZX || value >> 4 > Self::MAX_NUM {
+            return None;
+        }
+        Some(BlockOption {
+            num: value >> 4,
+            more: value & 0x08 != 0,
+            szx,
+        })
+    }
+
+    pub fn encode(&self) -> u32 {
+        (self.num << 4) | ((self.more as u32) << 3) | self.szx as u32
+    }
+
+    /// Size of a block in bytes.
+    pub fn size(&self) -> usize {
+        Self::size_of(self.szx)
+    }
+
+    pub fn size_of(szx: u8) -> usize {
+        16 << szx
+    }
+
+    /// Offset of this block within the body.
+    pub fn offset(&self) -> usize {
+        self.num as usize * self.size()
+    }
+}
+
+/// Encodes a CoAP message into a buffer. Options have to be added in
+/// ascending order of their number, followed by the payload.
+pub struct CoapMessageBuilder<'b> {
+    buf: &'b mut [u8],
+    len: usize,
+    last_option: u16,
+}
+
+impl<'b> CoapMessageBuilder<'b> {
+    /// Starts a message by writing `header`.
+    pub fn new(
+        buf: &'b mut [u8],
+        header: &CoapHeader,
+    ) -> Result<CoapMessageBuilder<'b>, ErrorCode> {
+        let token = header.get_token();
+        let len = COAP_HDR_LEN + token.len();
+        if buf.len() < len {
+            return Err(ErrorCode::SIZE);
+        }
+        buf[0] = (COAP_VERSION << 6) | ((header.mtype as u8) << 4) | token.len() as u8;
+        buf[1] = header.code;
+        buf[2..4].copy_from_slice(&header.msg_id.to_be_bytes());
+        buf[COAP_HDR_LEN..len].copy_from_slice(token);
+        Ok(CoapMessageBuilder {
+            buf,
+            len,
+            last_option: 0,
+        })
+    }
+
+    /// Starts the plaintext of a message protected with OSCORE by writing
+    /// `code`. Options and payload follow as in an unprotected message.
+    pub fn new_inner(buf: &'b mut [u8], code: u8) -> Result<CoapMessageBuilder<'b>, ErrorCode> {
+        *buf.first_mut().ok_or(ErrorCode::SIZE)? = code;
+        Ok(CoapMessageBuilder {
+            buf,
+            len: 1,
+            last_option: 0,
+        })
+    }
+
+    /// Returns the nibble and the extended bytes encoding `value`.
+    fn extended(value: u16) -> (u8, [u8; 2], usize) {
+        match value {
+            0..=12 => (value as u8, [0; 2], 0),
+            13..=268 => (13, [(value - 13) as u8, 0], 1),
+            _ => (14, (value - 269).to_be_bytes(), 2),
+        }
+    }
+
+    pub fn add_option(&mut self, number: u16, value: &[u8]) -> Result<(), ErrorCode> {
+        if number < self.last_option || value.len() > u16::MAX as usize {
+            return Err(ErrorCode::INVAL);
+        }
+        let (delta_nibble, delta_ext, delta_ext_len) = Self::extended(number - self.last_option);
+        let (len_nibble, len_ext, len_ext_len) = Self::extended(value.len() as u16);
+        let total = 1 + delta_ext_len + len_ext_len + value.len();
+        if self.buf.len() < self.len + total {
+            return Err(ErrorCode::SIZE);
+        }
+
+        let buf = &mut self.buf[self.len..self.len + total];
+        buf[0] = (delta_nibble << 4) | len_nibble;
+        let mut pos = 1;
+        buf[pos..pos + delta_ext_len].copy_from_slice(&delta_ext[..delta_ext_len]);
+        pos += delta_ext_len;
+        buf[pos..pos + len_ext_len].copy_from_slice(&len_ext[..len_ext_len]);
+        pos += len_ext_len;
+        buf[pos..].copy_from_slice(value);
+
+        self.len += total;
+        self.last_option = number;
+        Ok(())
+    }
+
+    /// Adds an option with an unsigned integer value, encoded in as few
+    /// bytes as possible.
+    pub fn add_uint_option(&mut self, number: u16, value: u32) -> Result<(), ErrorCode> {
+        let bytes = value.to_be_bytes();
+        let skip = (value.leading_zeros() / 8) as usize;
+        self.add_option(number, &bytes[skip..])
+    }
+
+    /// Adds one Uri-Path option per segment of a `/` separated path.
+    pub fn add_path(&mut self, path: &[u8]) -> Result<(), ErrorCode> {
+        for segment in path.split(|b| *b == b'/').filter(|s| !s.is_empty()) {
+            self.add_option(option::URI_PATH, segment)?;
+        }
+        Ok(())
+    }
+
+    /// Number of payload bytes that still fit into the buffer.
+    pub fn payload_capacity(&self) -> usize {
+        self.buf.len().saturating_sub(self.len + 1)
+    }
+
+    /// Adds a payload of `len` bytes and returns the space to fill it in.
+    pub fn payload_mut(&mut self, len: usize) -> Result<&mut [u8], ErrorCode> {
+        if len == 0 {
+            return Ok(&mut []);
+        }
+        if len > self.payload_capacity() {
+            return Err(ErrorCode::SIZE);
+        }
+        self.buf[self.len] = PAYLOAD_MARKER;
+        let start = self.len + 1;
+        self.len = start + len;
+        Ok(&mut self.buf[start..start + len])
+    }
+
+    /// Length of the encoded message.
+    pub fn encoded_len(&self) -> usize {
+        self.len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(buf: &mut [u8], build: impl FnOnce(&mut CoapMessageBuilder)) -> &[u8] {
+        let header = CoapHeader::new(CoapType::Confirmable, code::GET, 0x1234, &[0xab]);
+        let mut builder = CoapMessageBuilder::new(buf, &header).unwrap();
+        build(&mut builder);
+        let len = builder.encoded_len();
+        &buf[..len]
+    }
+
+    #[test]
+    fn test_header() {
+        let mut buf = [0; 16];
+        let msg = encode(&mut buf, |_| {});
+        assert_eq!(msg, [0x41, code::GET, 0x12, 0x34, 0xab]);
+
+        let (offset, header) = CoapHeader::decode(msg).unwrap();
+        assert_eq!(offset, 5);
+        assert_eq!(header.mtype, CoapType::Confirmable);
+        assert_eq!(header.code, code::GET);
+        assert_eq!(header.msg_id, 0x1234);
+        assert_eq!(header.get_token(), [0xab]);
+    }
+
+    #[test]
+    fn test_header_invalid() {
+        // Version 2
+        assert!(CoapHeader::decode(&[0x80, 0x01, 0, 0]).is_none());
+        // Token length 9
+        assert!(CoapHeader::decode(&[0x49, 0x01, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9]).is_none());
+        // Truncated token
+        assert!(CoapHeader::decode(&[0x42, 0x01, 0, 0, 1]).is_none());
+        assert!(CoapHeader::decode(&[0x40, 0x01, 0]).is_none());
+    }
+
+    #[test]
+    fn test_option_delta_and_length_encoding() {
+        let long = [0x55; 300];
+        let mut buf = [0; 512];
+        let msg = encode(&mut buf, |b| {
+            // Delta 11, length 12: both fit in the nibbles.
+            b.add_option(option::URI_PATH, &long[..12]).unwrap();
+            // Delta 0, length 13: one extended length byte.
+            b.add_option(option::URI_PATH, &long[..13]).unwrap();
+            // Delta 16, length 268
+            b.add_option(option::BLOCK1, &long[..268]).unwrap();
+            // Delta 269 - 27 = 242 uses one extended delta byte, length 0.
+            b.add_option(269, &[]).unwrap();
+            // Delta 1000 uses two extended delta bytes.
+            b.add_option(1269, &[1]).unwrap();
+        });
+        let opts = &msg[5..];
+        assert_eq!(opts[0], 0xbc);
+        assert_eq!(opts[13], 0x0d);
+        assert_eq!(opts[14], 0);
+        assert_eq!(&opts[28..30], [0xdd, 3]);
+        assert_eq!(opts[30], 255);
+        assert_eq!(&opts[299..301], [0xd0, 242 - 13]);
+        assert_eq!(&opts[301..305], [0xe1, 0x02, 0xdb, 1]);
+        assert_eq!(opts.len(), 305);
+
+        let msg = CoapMessage::decode(msg).unwrap();
+        let mut iter = msg.options();
+        assert_eq!(iter.next(), Some(Ok((option::URI_PATH, &long[..12]))));
+        assert_eq!(iter.next(), Some(Ok((option::URI_PATH, &long[..13]))));
+        assert_eq!(iter.next(), Some(Ok((option::BLOCK1, &long[..268]))));
+        assert_eq!(iter.next(), Some(Ok((269, &[][..]))));
+        assert_eq!(iter.next(), Some(Ok((1269, &[1][..]))));
+        assert_eq!(iter.next(), None);
+        assert!(msg.payload.is_empty());
+    }
+
+    #[test]
+    fn test_option_length_269() {
+        let long = [0x55; 300];
+        let mut buf = [0; 512];
+        let msg = encode(&mut buf, |b| {
+            b.add_option(option::URI_HOST, &long[..269]).unwrap()
+        });
+        assert_eq!(&msg[5..8], [0x3e, 0, 0]);
+        let msg = CoapMessage::decode(msg).unwrap();
+        assert_eq!(msg.find_option(option::URI_HOST), Some(&long[..269]));
+    }
+
+    #[test]
+    fn test_builder_errors() {
+        let mut buf = [0; 16];
+        let header = CoapHeader::new(CoapType::Confirmable, code::GET, 1, &[]);
+        let mut builder = CoapMessageBuilder::new(&mut buf, &header).unwrap();
+        builder.add_option(option::URI_PATH, b"a").unwrap();
+        assert_eq!(
+            builder.add_option(option::URI_HOST, b"a"),
+            Err(ErrorCode::INVAL)
+        );
+        assert_eq!(
+            builder.add_option(option::URI_QUERY, &[0; 16]),
+            Err(ErrorCode::SIZE)
+        );
+        assert_eq!(builder.payload_capacity(), 16 - 6 - 1);
+        assert_eq!(builder.payload_mut(10).err(), Some(ErrorCode::SIZE));
+        assert_eq!(builder.payload_mut(9).unwrap().len(), 9);
+        assert_eq!(builder.encoded_len(), 16);
+
+        let header = CoapHeader::new(CoapType::Confirmable, code::GET, 1, &[0; 8]);
+        assert!(CoapMessageBuilder::new(&mut buf[..11], &header).is_err());
+    }
+
+    #[test]
+    fn test_uint_option_and_path() {
+        let mut buf = [0; 64];
+        let msg = encode(&mut buf, |b| {
+            b.add_path(b"/sensors//temp/").unwrap();
+            b.add_uint_option(option::CONTENT_FORMAT, 0).unwrap();
+            b.add_uint_option(option::BLOCK2, 0x100).unwrap();
+            b.payload_mut(2).unwrap().copy_from_slice(b"hi");
+        });
+        let msg = CoapMessage::decode(msg).unwrap();
+        let mut iter = msg.options().map(|opt| opt.unwrap());
+        assert_eq!(iter.next(), Some((option::URI_PATH, &b"sensors"[..])));
+        assert_eq!(iter.next(), Some((option::URI_PATH, &b"temp"[..])));
+        assert_eq!(iter.next(), Some((option::CONTENT_FORMAT, &[][..])));
+        assert_eq!(iter.next(), Some((option::BLOCK2, &[1, 0][..])));
+        assert_eq!(iter.next(), None);
+        assert_eq!(msg.payload, b"hi");
+        assert_eq!(
+            decode_uint(msg.find_option(option::BLOCK2).unwrap()),
+            Some(0x100)
+        );
+    }
+
+    #[test]
+    fn test_malformed_options() {
+        // Reserved delta nibble 15
+        assert!(CoapMessage::decode(&[0x40, code::GET, 0, 0, 0xf0]).is_none());
+        // Reserved length nibble 15
+        assert!(CoapMessage::decode(&[0x40, code::GET, 0, 0, 0x0f]).is_none());
+        // Missing extended delta byte
+        assert!(CoapMessage::decode(&[0x40, code::GET, 0, 0, 0xd0]).is_none());
+        // Value longer than the message
+        assert!(CoapMessage::decode(&[0x40, code::GET, 0, 0, 0xb3, b'a']).is_none());
+        // Option number overflow
+        assert!(
+            CoapMessage::decode(&[0x40, code::GET, 0, 0, 0xe0, 0xff, 0xff, 0xe0, 0xff, 0xff])
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_payload_marker() {
+        let msg = CoapMessage::decode(&[0x40, code::GET, 0, 0, 0xff, 1, 2]).unwrap();
+        assert_eq!(msg.payload, [1, 2]);
+        // A marker must be followed by a payload.
+        assert!(CoapMessage::decode(&[0x40, code::GET, 0, 0, 0xff]).is_none());
+    }
+
+    #[test]
+    fn test_empty_message() {
+        assert!(CoapMessage::decode(&[0x60, code::EMPTY, 0, 1]).is_some());
+        assert!(CoapMessage::decode(&[0x61, code::EMPTY, 0, 1, 0xab]).is_none());
+        assert!(CoapMessage::decode(&[0x60, code::EMPTY, 0, 1, 0xff, 1]).is_none());
+    }
+
+    #[test]
+    fn test_inner_message() {
+        let mut buf = [0; 16];
+        let mut builder = CoapMessageBuilder::new_inner(&mut buf, code::PUT).unwrap();
+        builder.add_option(option::URI_PATH, b"led").unwrap();
+        builder.payload_mut(1).unwrap()[0] = 1;
+        let len = builder.encoded_len();
+        assert_eq!(buf[..len], [code::PUT, 0xb3, b'l', b'e', b'd', 0xff, 1]);
+
+        let outer = CoapHeader::new(CoapType::Confirmable, code::POST, 7, &[1, 2]);
+        let msg = CoapMessage::decode_inner(&outer, &buf[..len]).unwrap();
+        assert_eq!(msg.header.code, code::PUT);
+        assert_eq!(msg.header.msg_id, 7);
+        assert_eq!(msg.header.get_token(), [1, 2]);
+        assert_eq!(msg.find_option(option::URI_PATH), Some(&b"led"[..]));
+        assert_eq!(msg.payload, [1]);
+    }
+
+    #[test]
+    fn test_block_option() {
+        // Examples from RFC 7959, section 2.2
+        let block = BlockOption::decode(&[0x16]).unwrap();
+        assert_eq!(
+            block,
+            BlockOption {
+                num: 1,
+                more: false,
+                szx: 6
+            }
+        );
+        assert_eq!(block.size(), 1024);
+        assert_eq!(block.offset(), 1024);
+
+        let block = BlockOption::decode(&[0x0a]).unwrap();
+        assert_eq!(
+            block,
+            BlockOption {
+                num: 0,
+                more: true,
+                szx: 2
+            }
+        );
+        assert_eq!(block.size(), 64);
+        assert_eq!(block.encode(), 0x0a);
+
+        // An empty value is block 0 without more blocks and 16 byte blocks.
+        assert_eq!(BlockOption::decode(&[]).unwrap().size(), 16);
+
+        let block = BlockOption {
+            num: BlockOption::MAX_NUM,
+            more: true,
+            szx: 0,
+        };
+        let encoded = block.encode().to_be_bytes();
+        assert_eq!(BlockOption::decode(&encoded[1..]), Some(block));
+        assert_eq!(block.offset(), BlockOption::MAX_NUM as usize * 16);
+    }
+
+    #[test]
+    fn test_block_option_invalid() {
+        // Reserved size exponent
+        assert!(BlockOption::decode(&[0x07]).is_none());
+        // Block number longer than 20 bits
+        assert!(BlockOption::decode(&[0x01, 0x00, 0x00, 0x00]).is_none());
+        // Value longer than 3 bytes
+        assert!(BlockOption::decode(&[0, 0, 0, 0, 0]).is_none());
+    }
+}
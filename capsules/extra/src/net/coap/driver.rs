@@ -0,0 +1,2248 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! CoAP userspace interface.
+//!
+//! Implements a CoAP (RFC 7252) endpoint on top of the UDP stack, so that
+//! processes can act as CoAP clients and servers without implementing
+//! message reliability themselves. The driver is bound to the CoAP port
+//! (5683) and keeps the state of all exchanges in the grants of the
+//! processes.
+//!
+//! Client
+//! ------
+//! Each process can have a single request outstanding. Confirmable requests
+//! are retransmitted with an exponential backoff on a virtual alarm until
+//! they are acknowledged, following the transmission parameters of RFC 7252
+//! -- sect. 4.8. Both piggybacked and separate responses are accepted;
+//! separate confirmable responses are acknowledged by the driver.
+//!
+//! Request payloads larger than a block are sent with the Block1 option, and
+//! response bodies sent with the Block2 option are requested block by block
+//! and reassembled in the response buffer of the process (RFC 7959). The
+//! process is only notified once the whole response has been received.
+//!
+//! Server
+//! ------
+//! A process registers resources by their path. Requests for a registered
+//! resource are delivered to the process, which answers them with a single
+//! command. The response is piggybacked on the acknowledgement of
+//! confirmable requests. Responses larger than a block are sent with the
+//! Block2 option; the driver serves the following blocks from the response
+//! buffer of the process without notifying it again. Requests for unknown
+//! resources are answered with 4.04 Not Found, and requests carrying
+//! critical options the driver does not support (such as Uri-Query or
+//! Block1) with 4.02 Bad Option.
+//!
+//! Security
+//! --------
+//! Processes can protect their exchanges end to end with OSCORE (RFC 8613),
+//! whatever path the datagrams take. A process provisions its security
+//! context in the KV store, in the record format described in the
+//! [`oscore`](crate::net::coap::oscore) module, and asks the driver to set it
+//! up. The driver reads the record with the storage permissions of the
+//! process and derives the keys with HMAC-SHA256. From then on, the requests
+//! of the process are protected and only protected responses are accepted
+//! for them, and its resources are only served to clients that protect their
+//! requests with the matching context. Responses carry a Partial IV of their
+//! own, so that they never reuse the nonce of a request.
+//!
+//! Retransmissions of a protected request keep its Partial IV and are
+//! encrypted again, so the process must not modify the request payload
+//! while the request is outstanding. Error responses the driver generates,
+//! such as 4.04 Not Found, are not protected, and the replay window of a
+//! security context starts empty whenever it is set up.
+//!
+//! Messages the driver generates on its own (acknowledgements, resets and
+//! error responses) are dropped if the transmitter is busy, as the peer
+//! retransmits its confirmable message anyway. Likewise, only one
+//! cryptographic operation runs at a time, and protected messages received
+//! in the meantime are dropped.
+
+use crate::net::coap::oscore::{
+    self, ContextRecord, Id, OscoreOption, PartialIv, SecurityContext, KEY_LEN, NONCE_LEN, TAG_LEN,
+};
+use crate::net::coap::{
+    code, option, BlockOption, CoapHeader, CoapMessage, CoapMessageBuilder, CoapType,
+};
+use crate::net::ipv6::ip_utils::IPAddr;
+use crate::net::network_capabilities::NetworkCapability;
+use crate::net::udp::udp_recv::UDPRecvClient;
+use crate::net::udp::udp_send::{UDPSendClient, UDPSender};
+
+use core::cell::Cell;
+use core::cmp::min;
+use core::mem::size_of;
+
+use kernel::debug;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, GrantKernelData, UpcallCount};
+use kernel::hil::digest;
+use kernel::hil::kv;
+use kernel::hil::symmetric_encryption::{CCMClient, AES128CCM};
+use kernel::hil::time::{self, ConvertTicks, Ticks};
+use kernel::processbuffer::{ReadableProcessBuffer, ReadableProcessSlice, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{MapCell, OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::{ErrorCode, ProcessId};
+
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Coap as usize;
+
+/// Default UDP port of CoAP.
+pub const COAP_PORT: u16 = 5683;
+
+/// Maximum length of the path of a resource or a request.
+pub const MAX_PATH_LEN: usize = 32;
+
+/// Number of resources a process can register.
+pub const MAX_RESOURCES: usize = 4;
+
+// Transmission parameters (RFC 7252 -- sect. 4.8).
+
+/// Initial timeout of a confirmable message.
+const ACK_TIMEOUT_MS: u32 = 2000;
+/// Range of the random part of the initial timeout,
+/// ACK_TIMEOUT * (ACK_RANDOM_FACTOR - 1).
+const ACK_RANDOM_MS: u32 = 1000;
+/// Number of retransmissions of a confirmable message.
+const MAX_RETRANSMIT: u8 = 4;
+/// Time to wait for a separate response, or for the response to a
+/// non-confirmable request (MAX_TRANSMIT_WAIT).
+const RESPONSE_TIMEOUT_MS: u32 = 93_000;
+/// Period of the retransmission timer.
+const COAP_TIMER_MS: u32 = 100;
+
+/// Room kept in a message for the header, the token and the options when
+/// choosing the block size.
+const MAX_OVERHEAD_LEN: usize = 64;
+/// Length of the tokens of the requests.
+const TOKEN_LEN: usize = 4;
+
+/// Number of sequence numbers of a security context reserved with one write
+/// to the KV store.
+const SSN_WINDOW: u64 = 128;
+
+/// IDs for subscribed upcalls.
+mod upcall {
+    /// The request of the process completed. The arguments are a status
+    /// code, the response code and the length of the response body. The
+    /// status is `NOACK` if the request timed out and `FAIL` if the server
+    /// rejected it with a reset.
+    pub const RESPONSE: usize = 0;
+    /// A request for a registered resource arrived. The arguments are the
+    /// resource ID, the method and the length of the request payload.
+    pub const REQUEST: usize = 1;
+    /// The set up of the security context of the process completed, or the
+    /// context was disabled because its sequence numbers could not be
+    /// reserved. The argument is a status code.
+    pub const SECURITY: usize = 2;
+    /// Number of upcalls.
+    pub const COUNT: u8 = 3;
+}
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// Path of the request to send or of the resource to register, with
+    /// segments separated by `/`.
+    pub const PATH: usize = 0;
+    /// Payload of the request to send.
+    pub const REQUEST_PAYLOAD: usize = 1;
+    /// Destination buffer. Contains the 16 byte IPv6 address of the server.
+    pub const DEST: usize = 2;
+    /// Payload of the response to an incoming request.
+    pub const RESPONSE_PAYLOAD: usize = 3;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 4;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Receives the body of the response to the request of the process.
+    pub const RESPONSE_BODY: usize = 0;
+    /// Receives the payload of an incoming request.
+    pub const REQUEST_BODY: usize = 1;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 2;
+}
+
+/// A normalized path: its segments separated by a single `/`.
+#[derive(Copy, Clone)]
+struct Path {
+    bytes: [u8; MAX_PATH_LEN],
+    len: usize,
+}
+
+impl Path {
+    fn new() -> Path {
+        Path {
+            bytes: [0; MAX_PATH_LEN],
+            len: 0,
+        }
+    }
+
+    fn from_process(buf: &ReadableProcessSlice) -> Result<Path, ErrorCode> {
+        let mut tmp = [0; MAX_PATH_LEN];
+        let tmp = tmp.get_mut(..buf.len()).ok_or(ErrorCode::SIZE)?;
+        buf.copy_to_slice(tmp);
+
+        let mut path = Path::new();
+        for segment in tmp.split(|b| *b == b'/').filter(|s| !s.is_empty()) {
+            path.push_segment(segment)?;
+        }
+        Ok(path)
+    }
+
+    fn push_segment(&mut self, segment: &[u8]) -> Result<(), ErrorCode> {
+        let start = if self.len == 0 { 0 } else { self.len + 1 };
+        let end = start + segment.len();
+        if end > MAX_PATH_LEN {
+            return Err(ErrorCode::SIZE);
+        }
+        if start > 0 {
+            self.bytes[self.len] = b'/';
+        }
+        self.bytes[start..end].copy_from_slice(segment);
+        self.len = end;
+        Ok(())
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum ClientState {
+    /// The current message waits for the transmitter.
+    Pending,
+    /// The current confirmable message was sent and awaits its
+    /// acknowledgement.
+    AwaitingAck,
+    /// The request was acknowledged or is non-confirmable, and awaits its
+    /// response.
+    AwaitingResponse,
+}
+
+/// The outstanding request of a process.
+struct ClientRequest {
+    dest: IPAddr,
+    port: u16,
+    method: u8,
+    confirmable: bool,
+    path: Path,
+    token: [u8; TOKEN_LEN],
+    /// Message ID of the current message of the exchange.
+    msg_id: u16,
+    state: ClientState,
+    /// Number of retransmissions of the current message.
+    retransmissions: u8,
+    /// Timeout of the current transmission of the message.
+    timeout_ms: u32,
+    /// Time left until the message times out.
+    timer_ms: u32,
+    /// Length of the request payload.
+    payload_len: usize,
+    /// Block of the request payload being sent, if it is sent block-wise.
+    block1: Option<BlockOption>,
+    /// Block of the response body being requested, after the first block.
+    block2: Option<BlockOption>,
+    /// Length of the response body received so far.
+    received: usize,
+    /// Whether the request is protected with the security context of the
+    /// process.
+    protected: bool,
+    /// Partial IV of the current message, once it was protected.
+    piv: Option<PartialIv>,
+}
+
+/// An incoming request waiting for its response.
+#[derive(Copy, Clone)]
+struct ServerExchange {
+    peer: IPAddr,
+    port: u16,
+    /// Header of the request, for its type, message ID and token.
+    request: CoapHeader,
+    resource: usize,
+    /// Block of the response body the client asked for.
+    block2: Option<BlockOption>,
+    /// Response code, once the process responded.
+    response: Option<u8>,
+    /// Partial IV of the request, if it was protected.
+    protected: Option<PartialIv>,
+}
+
+/// A response body that is being fetched by a client block by block.
+#[derive(Copy, Clone)]
+struct BlockTransfer {
+    peer: IPAddr,
+    port: u16,
+    resource: usize,
+    code: u8,
+}
+
+/// OSCORE state of a process.
+#[derive(Default)]
+enum Security {
+    #[default]
+    Disabled,
+    /// The process asked for its security context to be set up.
+    Requested,
+    /// The keys of the security context are being derived, and its first
+    /// sequence numbers reserved.
+    SettingUp(SecurityContext),
+    Enabled(SecurityContext),
+}
+
+impl Security {
+    /// Whether the exchanges of the process have to be protected.
+    fn required(&self) -> bool {
+        !matches!(self, Security::Disabled)
+    }
+
+    /// Returns the security context messages are protected with.
+    fn context_mut(&mut self) -> Result<&mut SecurityContext, ErrorCode> {
+        match self {
+            Security::Enabled(context) => Ok(context),
+            Security::Disabled => Err(ErrorCode::OFF),
+            Security::Requested | Security::SettingUp(_) => Err(ErrorCode::BUSY),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct App {
+    request: Option<ClientRequest>,
+    resources: [Option<Path>; MAX_RESOURCES],
+    incoming: Option<ServerExchange>,
+    block_transfer: Option<BlockTransfer>,
+    /// Counter making the tokens of the process unique.
+    next_token: u16,
+    security: Security,
+}
+
+/// Outcome of dispatching an incoming request to the processes.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Dispatch {
+    NotFound,
+    Unauthorized,
+    Busy,
+    Duplicate,
+    Delivered,
+}
+
+/// Steps of the derivation of a security context (RFC 8613 -- sect. 3.2).
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Derivation {
+    /// HKDF-Extract of the pseudorandom key from the Master Secret.
+    Extract,
+    SenderKey,
+    RecipientKey,
+    CommonIv,
+}
+
+#[derive(Copy, Clone)]
+enum OutgoingKind {
+    /// The current message of the request of the process, by its message ID.
+    Request(u16),
+    /// The response of the process, and whether more blocks follow.
+    Response(bool),
+}
+
+/// A message of a process being protected.
+#[derive(Copy, Clone)]
+struct Outgoing {
+    processid: ProcessId,
+    dest: IPAddr,
+    port: u16,
+    /// Header of the outer message.
+    header: CoapHeader,
+    option: OscoreOption,
+    /// Offset and length of the message in the crypt buffer.
+    m_off: usize,
+    m_len: usize,
+    kind: OutgoingKind,
+}
+
+/// A received protected message being verified.
+#[derive(Copy, Clone)]
+struct Incoming {
+    processid: ProcessId,
+    src_addr: IPAddr,
+    src_port: u16,
+    /// Header of the outer message.
+    header: CoapHeader,
+    /// Partial IV of a request; `None` for responses.
+    request: Option<PartialIv>,
+    /// Offset and length of the ciphertext in the crypt buffer.
+    m_off: usize,
+    m_len: usize,
+}
+
+/// The operation the KV store, the HMAC engine or the AES-CCM engine is used
+/// for. Only one runs at a time.
+#[derive(Copy, Clone)]
+enum CryptoOp {
+    /// Reading the security context record of the process.
+    Loading(ProcessId),
+    /// Running a step of the derivation of the security context of the
+    /// process, with the pseudorandom key once it was extracted.
+    Deriving(ProcessId, Derivation, [u8; 32]),
+    /// Storing a new sequence number limit for the process.
+    Storing(ProcessId, u64),
+    Protecting(Outgoing),
+    /// A protected message waits in the crypt buffer for the transmitter.
+    Protected(Outgoing),
+    Unprotecting(Incoming),
+}
+
+pub struct CoapDriver<'a, A: time::Alarm<'a>, H: digest::Digest<'a, 32> + digest::HmacSha256> {
+    sender: &'a dyn UDPSender<'a>,
+    alarm: &'a A,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    /// Buffer messages are encoded in. Empty while a message is being sent.
+    tx_buffer: MapCell<SubSliceMut<'static, u8>>,
+    /// Size exponent of the blocks the driver sends.
+    block_szx: u8,
+    /// Size exponent of the blocks of protected exchanges.
+    protected_block_szx: u8,
+    next_msg_id: Cell<u16>,
+    msg_id_seeded: Cell<bool>,
+    net_cap: &'static NetworkCapability,
+    ccm: &'a dyn AES128CCM<'a>,
+    hmac: &'a H,
+    kv: &'a dyn kv::KVPermissions<'a>,
+    /// Buffer AES-CCM runs on: the additional authenticated data followed by
+    /// the message and its tag.
+    crypt_buffer: TakeCell<'static, [u8]>,
+    hmac_data: TakeCell<'static, [u8]>,
+    hmac_digest: TakeCell<'static, [u8; 32]>,
+    kv_key: TakeCell<'static, [u8]>,
+    kv_value: TakeCell<'static, [u8]>,
+    crypto: OptionalCell<CryptoOp>,
+}
+
+impl<'a, A: time::Alarm<'a>, H: digest::Digest<'a, 32> + digest::HmacSha256> CoapDriver<'a, A, H> {
+    pub fn new(
+        sender: &'a dyn UDPSender<'a>,
+        alarm: &'a A,
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+        tx_buffer: &'static mut [u8],
+        net_cap: &'static NetworkCapability,
+        ccm: &'a dyn AES128CCM<'a>,
+        hmac: &'a H,
+        kv: &'a dyn kv::KVPermissions<'a>,
+        crypt_buffer: &'static mut [u8],
+        hmac_data: &'static mut [u8],
+        hmac_digest: &'static mut [u8; 32],
+        kv_key: &'static mut [u8],
+        kv_value: &'static mut [u8],
+    ) -> CoapDriver<'a, A, H> {
+        // Use the largest blocks that fit into a message.
+        let largest_szx = |room: usize| {
+            (0..=BlockOption::MAX_SZX)
+                .rev()
+                .find(|szx| BlockOption::size_of(*szx) <= room)
+                .unwrap_or(0)
+        };
+        let room = tx_buffer.len().saturating_sub(MAX_OVERHEAD_LEN);
+        let block_szx = largest_szx(room);
+        let protected_block_szx = largest_szx(room.saturating_sub(oscore::OVERHEAD_LEN));
+
+        CoapDriver {
+            sender,
+            alarm,
+            apps: grant,
+            tx_buffer: MapCell::new(SubSliceMut::new(tx_buffer)),
+            block_szx,
+            protected_block_szx,
+            next_msg_id: Cell::new(0),
+            msg_id_seeded: Cell::new(false),
+            net_cap,
+            ccm,
+            hmac,
+            kv,
+            crypt_buffer: TakeCell::new(crypt_buffer),
+            hmac_data: TakeCell::new(hmac_data),
+            hmac_digest: TakeCell::new(hmac_digest),
+            kv_key: TakeCell::new(kv_key),
+            kv_value: TakeCell::new(kv_value),
+            crypto: OptionalCell::empty(),
+        }
+    }
+
+    fn block_szx(&self, protected: bool) -> u8 {
+        if protected {
+            self.protected_block_szx
+        } else {
+            self.block_szx
+        }
+    }
+
+    fn next_msg_id(&self) -> u16 {
+        // Start from an unpredictable message ID (RFC 7252 -- sect. 4.4).
+        if !self.msg_id_seeded.get() {
+            self.next_msg_id.set(self.alarm.now().into_u32() as u16);
+            self.msg_id_seeded.set(true);
+        }
+        let msg_id = self.next_msg_id.get();
+        self.next_msg_id.set(msg_id.wrapping_add(1));
+        msg_id
+    }
+
+    /// Picks the initial timeout of a confirmable message between
+    /// ACK_TIMEOUT and ACK_TIMEOUT * ACK_RANDOM_FACTOR.
+    fn initial_timeout(&self) -> u32 {
+        ACK_TIMEOUT_MS + self.alarm.now().into_u32() % ACK_RANDOM_MS
+    }
+
+    /// Keeps the retransmission timer running while any request is
+    /// outstanding.
+    fn start_timer(&self) {
+        if self.alarm.is_armed() {
+            return;
+        }
+        let mut active = false;
+        self.apps.each(|_, app, _| active |= app.request.is_some());
+        if active {
+            self.alarm
+                .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(COAP_TIMER_MS));
+        }
+    }
+
+    /// Encodes a message with `encode`, which returns the length of the
+    /// message, and sends it to `dest`.
+    fn transmit<F>(&self, dest: IPAddr, port: u16, encode: F) -> Result<(), ErrorCode>
+    where
+        F: FnOnce(&mut [u8]) -> Result<usize, ErrorCode>,
+    {
+        let mut buf = self.tx_buffer.take().ok_or(ErrorCode::BUSY)?;
+        buf.reset();
+        match encode(buf.as_slice()) {
+            Ok(len) => {
+                buf.slice(0..len);
+                self.sender
+                    .send_to(dest, port, buf, self.net_cap)
+                    .map_err(|buf| {
+                        self.tx_buffer.replace(buf);
+                        ErrorCode::FAIL
+                    })
+            }
+            Err(e) => {
+                self.tx_buffer.replace(buf);
+                Err(e)
+            }
+        }
+    }
+
+    /// Sends an empty acknowledgement or reset.
+    fn send_empty(&self, dest: IPAddr, port: u16, mtype: CoapType, msg_id: u16) {
+        let header = CoapHeader::new(mtype, code::EMPTY, msg_id, &[]);
+        let _ = self.transmit(dest, port, |buf| {
+            CoapMessageBuilder::new(buf, &header).map(|msg| msg.encoded_len())
+        });
+    }
+
+    /// Answers `request` with an error response without payload.
+    fn send_error(&self, dest: IPAddr, port: u16, request: &CoapHeader, code: u8) {
+        let header = self.response_header(request, code);
+        let _ = self.transmit(dest, port, |buf| {
+            CoapMessageBuilder::new(buf, &header).map(|msg| msg.encoded_len())
+        });
+    }
+
+    /// Header of the response to `request`: piggybacked on the
+    /// acknowledgement of confirmable requests.
+    fn response_header(&self, request: &CoapHeader, code: u8) -> CoapHeader {
+        match request.mtype {
+            CoapType::Confirmable => CoapHeader::new(
+                CoapType::Acknowledgement,
+                code,
+                request.msg_id,
+                request.get_token(),
+            ),
+            _ => CoapHeader::new(
+                CoapType::NonConfirmable,
+                code,
+                self.next_msg_id(),
+                request.get_token(),
+            ),
+        }
+    }
+
+    /// Moves `request` to its next message, such as the next block.
+    fn next_message(&self, request: &mut ClientRequest) {
+        request.msg_id = self.next_msg_id();
+        request.retransmissions = 0;
+        request.timeout_ms = self.initial_timeout();
+        request.state = ClientState::Pending;
+        request.piv = None;
+    }
+
+    /// Notifies the process that its request failed.
+    fn request_failed(app: &mut App, kernel_data: &GrantKernelData, error: ErrorCode) {
+        app.request = None;
+        kernel_data
+            .schedule_upcall(
+                upcall::RESPONSE,
+                (kernel::errorcode::into_statuscode(Err(error)), 0, 0),
+            )
+            .ok();
+    }
+
+    /// Header of the current message of `request`, with the code `code`.
+    fn request_header(request: &ClientRequest, code: u8) -> CoapHeader {
+        let mtype = if request.confirmable {
+            CoapType::Confirmable
+        } else {
+            CoapType::NonConfirmable
+        };
+        CoapHeader::new(mtype, code, request.msg_id, &request.token)
+    }
+
+    /// Encodes the options and the payload of the current message of
+    /// `request`.
+    fn encode_request(
+        request: &ClientRequest,
+        kernel_data: &GrantKernelData,
+        msg: &mut CoapMessageBuilder,
+    ) -> Result<(), ErrorCode> {
+        msg.add_path(request.path.as_bytes())?;
+
+        // Requests for the following blocks of the response carry no
+        // payload.
+        if let Some(block2) = request.block2 {
+            return msg.add_uint_option(option::BLOCK2, block2.encode());
+        }
+
+        let (start, len) = match request.block1 {
+            Some(block1) => {
+                msg.add_uint_option(option::BLOCK1, block1.encode())?;
+                if block1.num == 0 {
+                    msg.add_uint_option(option::SIZE1, request.payload_len as u32)?;
+                }
+                let start = block1.offset();
+                (start, min(block1.size(), request.payload_len - start))
+            }
+            None => (0, request.payload_len),
+        };
+        let payload = msg.payload_mut(len)?;
+        kernel_data
+            .get_readonly_processbuffer(ro_allow::REQUEST_PAYLOAD)
+            .and_then(|buf| {
+                buf.enter(|buf| {
+                    buf.get(start..start + len)
+                        .map(|block| block.copy_to_slice(payload))
+                        .ok_or(ErrorCode::SIZE)
+                })
+            })
+            .unwrap_or(Err(ErrorCode::INVAL))
+    }
+
+    /// Encodes the options and the payload of the response of the process to
+    /// `exchange`. Returns whether more blocks of the body follow.
+    fn encode_response(
+        &self,
+        exchange: &ServerExchange,
+        kernel_data: &GrantKernelData,
+        msg: &mut CoapMessageBuilder,
+    ) -> Result<bool, ErrorCode> {
+        kernel_data
+            .get_readonly_processbuffer(ro_allow::RESPONSE_PAYLOAD)
+            .and_then(|payload| {
+                payload.enter(|payload| {
+                    let total = payload.len();
+                    let max_szx = self.block_szx(exchange.protected.is_some());
+                    let szx = exchange
+                        .block2
+                        .map_or(max_szx, |block| min(block.szx, max_szx));
+                    let size = BlockOption::size_of(szx);
+
+                    let (start, len, more) = match exchange.block2 {
+                        None if total <= size => (0, total, false),
+                        requested => {
+                            // A smaller block size than requested changes the
+                            // block number (RFC 7959 -- sect. 2.4).
+                            let start = min(requested.map_or(0, |block| block.offset()), total);
+                            let len = min(size, total - start);
+                            let more = start + len < total;
+                            let block = BlockOption {
+                                num: (start / size) as u32,
+                                more,
+                                szx,
+                            };
+                            msg.add_uint_option(option::BLOCK2, block.encode())?;
+                            if block.num == 0 {
+                                msg.add_uint_option(option::SIZE2, total as u32)?;
+                            }
+                            (start, len, more)
+                        }
+                    };
+                    let dest = msg.payload_mut(len)?;
+                    payload[start..start + len].copy_to_slice(dest);
+                    Ok(more)
+                })
+            })
+            .unwrap_or(Err(ErrorCode::INVAL))
+    }
+
+    /// Remembers the response body of `exchange` for the requests of the
+    /// following blocks.
+    fn block_transfer(exchange: &ServerExchange, code: u8, more: bool) -> Option<BlockTransfer> {
+        more.then_some(BlockTransfer {
+            peer: exchange.peer,
+            port: exchange.port,
+            resource: exchange.resource,
+            code,
+        })
+    }
+
+    /// Updates `request` once its current message was sent.
+    fn request_sent(request: &mut ClientRequest) {
+        if request.confirmable {
+            request.state = ClientState::AwaitingAck;
+            request.timer_ms = request.timeout_ms;
+        } else {
+            request.state = ClientState::AwaitingResponse;
+            request.timer_ms = RESPONSE_TIMEOUT_MS;
+        }
+    }
+
+    /// Sends the current message of `request`.
+    fn transmit_request(
+        &self,
+        request: &mut ClientRequest,
+        kernel_data: &GrantKernelData,
+    ) -> Result<(), ErrorCode> {
+        self.transmit(request.dest, request.port, |buf| {
+            let mut msg =
+                CoapMessageBuilder::new(buf, &Self::request_header(request, request.method))?;
+            Self::encode_request(request, kernel_data, &mut msg)?;
+            Ok(msg.encoded_len())
+        })?;
+        Self::request_sent(request);
+        Ok(())
+    }
+
+    /// Takes the next sequence number of a security context. If all reserved
+    /// sequence numbers were used, starts reserving more and returns BUSY.
+    fn next_piv(
+        &self,
+        processid: ProcessId,
+        context: &mut SecurityContext,
+    ) -> Result<PartialIv, ErrorCode> {
+        match context.next_piv() {
+            Some(piv) => Ok(piv),
+            None => {
+                self.store_ssn_limit(processid, &context.record)?;
+                Err(ErrorCode::BUSY)
+            }
+        }
+    }
+
+    /// Starts AES-CCM on a message of the exchange of the request with the
+    /// kid `request_kid` and the Partial IV `request_piv`. `fill` writes the
+    /// plaintext, or the ciphertext followed by the tag, into the buffer it
+    /// is passed and returns the length of the message without the tag.
+    /// Returns the offset and the length of the message in the crypt buffer.
+    fn start_ccm<F>(
+        &self,
+        key: &[u8; KEY_LEN],
+        nonce: &[u8; NONCE_LEN],
+        request_kid: &Id,
+        request_piv: &PartialIv,
+        encrypting: bool,
+        fill: F,
+    ) -> Result<(usize, usize), ErrorCode>
+    where
+        F: FnOnce(&mut [u8]) -> Result<usize, ErrorCode>,
+    {
+        if self.crypto.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        let buf = self.crypt_buffer.take().ok_or(ErrorCode::BUSY)?;
+        let prepare = |buf: &mut [u8]| -> Result<(usize, usize), ErrorCode> {
+            let m_off = oscore::encode_aad(request_kid, request_piv, buf)?;
+            let m_len = fill(&mut buf[m_off..])?;
+            if m_off + m_len + TAG_LEN > buf.len() {
+                return Err(ErrorCode::SIZE);
+            }
+            self.ccm.set_key(key)?;
+            self.ccm.set_nonce(nonce)?;
+            Ok((m_off, m_len))
+        };
+        match prepare(buf) {
+            Ok((m_off, m_len)) => self
+                .ccm
+                .crypt(buf, 0, m_off, m_len, TAG_LEN, true, encrypting)
+                .map(|()| (m_off, m_len))
+                .map_err(|(e, buf)| {
+                    self.crypt_buffer.replace(buf);
+                    e
+                }),
+            Err(e) => {
+                self.crypt_buffer.replace(buf);
+                Err(e)
+            }
+        }
+    }
+
+    /// Starts protecting the current message of `request` with the security
+    /// context of the process.
+    fn protect_request(
+        &self,
+        processid: ProcessId,
+        request: &mut ClientRequest,
+        security: &mut Security,
+        kernel_data: &GrantKernelData,
+    ) -> Result<(), ErrorCode> {
+        let context = security.context_mut()?;
+        if self.crypto.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        // Retransmissions keep the Partial IV, so that the server can match
+        // them with its response.
+        let piv = match request.piv {
+            Some(piv) => piv,
+            None => self.next_piv(processid, context)?,
+        };
+        request.piv = Some(piv);
+
+        let sender_id = context.record.sender_id;
+        let nonce = context.nonce(&sender_id, &piv);
+        let (m_off, m_len) =
+            self.start_ccm(&context.sender_key, &nonce, &sender_id, &piv, true, |buf| {
+                let mut msg = CoapMessageBuilder::new_inner(buf, request.method)?;
+                Self::encode_request(request, kernel_data, &mut msg)?;
+                Ok(msg.encoded_len())
+            })?;
+        self.crypto.set(CryptoOp::Protecting(Outgoing {
+            processid,
+            dest: request.dest,
+            port: request.port,
+            header: Self::request_header(request, code::POST),
+            option: OscoreOption {
+                piv: Some(piv),
+                kid: Some(sender_id),
+            },
+            m_off,
+            m_len,
+            kind: OutgoingKind::Request(request.msg_id),
+        }));
+        Ok(())
+    }
+
+    /// Starts protecting the response of the process to `exchange`, a
+    /// request that was protected with the Partial IV `request_piv`.
+    fn protect_response(
+        &self,
+        processid: ProcessId,
+        exchange: &ServerExchange,
+        code: u8,
+        request_piv: PartialIv,
+        security: &mut Security,
+        kernel_data: &GrantKernelData,
+    ) -> Result<(), ErrorCode> {
+        let context = security.context_mut()?;
+        if self.crypto.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        let piv = self.next_piv(processid, context)?;
+
+        let nonce = context.nonce(&context.record.sender_id, &piv);
+        let mut more = false;
+        let (m_off, m_len) = self.start_ccm(
+            &context.sender_key,
+            &nonce,
+            &context.record.recipient_id,
+            &request_piv,
+            true,
+            |buf| {
+                let mut msg = CoapMessageBuilder::new_inner(buf, code)?;
+                more = self.encode_response(exchange, kernel_data, &mut msg)?;
+                Ok(msg.encoded_len())
+            },
+        )?;
+        self.crypto.set(CryptoOp::Protecting(Outgoing {
+            processid,
+            dest: exchange.peer,
+            port: exchange.port,
+            header: self.response_header(&exchange.request, code::CHANGED),
+            option: OscoreOption {
+                piv: Some(piv),
+                kid: None,
+            },
+            m_off,
+            m_len,
+            kind: OutgoingKind::Response(more),
+        }));
+        Ok(())
+    }
+
+    /// Sends the next message a process waits to send. Returns whether the
+    /// transmitter is in use afterwards.
+    fn transmit_app(
+        &self,
+        processid: ProcessId,
+        app: &mut App,
+        kernel_data: &GrantKernelData,
+    ) -> bool {
+        if let Some(request) = app
+            .request
+            .as_mut()
+            .filter(|request| request.state == ClientState::Pending)
+        {
+            let result = if request.protected {
+                self.protect_request(processid, request, &mut app.security, kernel_data)
+            } else {
+                self.transmit_request(request, kernel_data)
+            };
+            match result {
+                Ok(()) | Err(ErrorCode::BUSY) => return true,
+                Err(e) => Self::request_failed(app, kernel_data, e),
+            }
+        }
+
+        if let Some(exchange) = app.incoming {
+            if let Some(code) = exchange.response {
+                let result = match exchange.protected {
+                    Some(request_piv) => self.protect_response(
+                        processid,
+                        &exchange,
+                        code,
+                        request_piv,
+                        &mut app.security,
+                        kernel_data,
+                    ),
+                    None => self.transmit(exchange.peer, exchange.port, |buf| {
+                        let header = self.response_header(&exchange.request, code);
+                        let mut msg = CoapMessageBuilder::new(buf, &header)?;
+                        let more = self.encode_response(&exchange, kernel_data, &mut msg)?;
+                        app.block_transfer = Self::block_transfer(&exchange, code, more);
+                        Ok(msg.encoded_len())
+                    }),
+                };
+                match result {
+                    Err(ErrorCode::BUSY) => return true,
+                    // The client retransmits confirmable requests if the
+                    // response could not be sent.
+                    result => {
+                        if exchange.protected.is_none() || result.is_err() {
+                            app.incoming = None;
+                        }
+                        return result.is_ok();
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    /// Sends the protected message waiting for the transmitter, if any.
+    /// Returns whether the transmitter is in use afterwards.
+    fn send_protected(&self) -> bool {
+        let outgoing = match self.crypto.get() {
+            Some(CryptoOp::Protected(outgoing)) => outgoing,
+            _ => return false,
+        };
+        if self.tx_buffer.is_none() {
+            return true;
+        }
+        self.crypto.clear();
+
+        self.apps
+            .enter(outgoing.processid, |app, kernel_data| {
+                // Drop the message if the process canceled its exchange.
+                let current = match outgoing.kind {
+                    OutgoingKind::Request(msg_id) => {
+                        app.request.as_ref().map_or(false, |request| {
+                            request.state == ClientState::Pending && request.msg_id == msg_id
+                        })
+                    }
+                    OutgoingKind::Response(_) => app
+                        .incoming
+                        .map_or(false, |exchange| exchange.response.is_some()),
+                };
+                if !current {
+                    return false;
+                }
+
+                let result = self.crypt_buffer.map_or(Err(ErrorCode::FAIL), |crypt| {
+                    self.transmit(outgoing.dest, outgoing.port, |buf| {
+                        let mut msg = CoapMessageBuilder::new(buf, &outgoing.header)?;
+                        let mut value = [0; oscore::MAX_OPTION_LEN];
+                        let len = outgoing.option.encode(&mut value);
+                        msg.add_option(option::OSCORE, &value[..len])?;
+                        let ciphertext =
+                            &crypt[outgoing.m_off..outgoing.m_off + outgoing.m_len + TAG_LEN];
+                        msg.payload_mut(ciphertext.len())?
+                            .copy_from_slice(ciphertext);
+                        Ok(msg.encoded_len())
+                    })
+                });
+                match outgoing.kind {
+                    OutgoingKind::Request(_) => match result {
+                        Ok(()) => {
+                            if let Some(request) = app.request.as_mut() {
+                                Self::request_sent(request);
+                            }
+                        }
+                        Err(e) => Self::request_failed(app, kernel_data, e),
+                    },
+                    OutgoingKind::Response(more) => {
+                        if let Some((exchange, code)) = app
+                            .incoming
+                            .take()
+                            .and_then(|exchange| exchange.response.map(|code| (exchange, code)))
+                        {
+                            app.block_transfer =
+                                Self::block_transfer(&exchange, code, more && result.is_ok());
+                        }
+                    }
+                }
+                result.is_ok()
+            })
+            .unwrap_or(false)
+    }
+
+    /// Sends the next message waiting for the transmitter, if any, and
+    /// starts setting up the security contexts processes asked for.
+    fn transmit_pending(&self) {
+        let mut busy = self.send_protected();
+        self.apps.each(|processid, app, kernel_data| {
+            if matches!(app.security, Security::Requested) && self.crypto.is_none() {
+                if let Err(e) = self.load_context(processid) {
+                    Self::security_failed(app, kernel_data, e);
+                }
+            }
+            if !busy {
+                busy = self.transmit_app(processid, app, kernel_data);
+            }
+        });
+        self.start_timer();
+    }
+
+    /// Disables the security context of a process after a failure, which
+    /// fails the protected messages waiting to be sent.
+    fn security_failed(app: &mut App, kernel_data: &GrantKernelData, error: ErrorCode) {
+        app.security = Security::Disabled;
+        kernel_data
+            .schedule_upcall(
+                upcall::SECURITY,
+                (kernel::errorcode::into_statuscode(Err(error)), 0, 0),
+            )
+            .ok();
+    }
+
+    /// Takes the KV buffers for an operation on the security context record.
+    fn kv_buffers(&self) -> Result<(SubSliceMut<'static, u8>, &'static mut [u8]), ErrorCode> {
+        let key = self.kv_key.take().ok_or(ErrorCode::BUSY)?;
+        let value = match self.kv_value.take() {
+            Some(value) => value,
+            None => {
+                self.kv_key.replace(key);
+                return Err(ErrorCode::BUSY);
+            }
+        };
+        let mut key = SubSliceMut::new(key);
+        match key.as_slice().get_mut(..oscore::KV_KEY.len()) {
+            Some(buf) => buf.copy_from_slice(oscore::KV_KEY),
+            None => {
+                self.kv_key.replace(key.take());
+                self.kv_value.replace(value);
+                return Err(ErrorCode::SIZE);
+            }
+        }
+        key.slice(..oscore::KV_KEY.len());
+        Ok((key, value))
+    }
+
+    /// Starts reading the security context record of a process.
+    fn load_context(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        let permissions = processid
+            .get_storage_permissions()
+            .ok_or(ErrorCode::NOSUPPORT)?;
+        let (key, value) = self.kv_buffers()?;
+        self.kv
+            .get(key, SubSliceMut::new(value), permissions)
+            .map_err(|(key, value, e)| {
+                self.kv_key.replace(key.take());
+                self.kv_value.replace(value.take());
+                e
+            })?;
+        self.crypto.set(CryptoOp::Loading(processid));
+        Ok(())
+    }
+
+    /// Starts reserving the next sequence numbers of a security context, by
+    /// storing a higher sequence number limit in its record.
+    fn store_ssn_limit(
+        &self,
+        processid: ProcessId,
+        record: &ContextRecord,
+    ) -> Result<(), ErrorCode> {
+        let ssn_limit = min(record.ssn_limit + SSN_WINDOW, oscore::MAX_SSN);
+        if ssn_limit <= record.ssn_limit {
+            return Err(ErrorCode::FAIL);
+        }
+        let permissions = processid
+            .get_storage_permissions()
+            .ok_or(ErrorCode::NOSUPPORT)?;
+        let (key, value) = self.kv_buffers()?;
+
+        let header_len = self.kv.header_size();
+        let mut updated = *record;
+        updated.ssn_limit = ssn_limit;
+        let len = match value
+            .get_mut(header_len..)
+            .ok_or(ErrorCode::SIZE)
+            .and_then(|buf| updated.encode(buf))
+        {
+            Ok(len) => len,
+            Err(e) => {
+                self.kv_key.replace(key.take());
+                self.kv_value.replace(value);
+                return Err(e);
+            }
+        };
+        let mut value = SubSliceMut::new(value);
+        value.slice(..header_len + len);
+        self.kv
+            .set(key, value, permissions)
+            .map_err(|(key, value, e)| {
+                self.kv_key.replace(key.take());
+                self.kv_value.replace(value.take());
+                e
+            })?;
+        self.crypto.set(CryptoOp::Storing(processid, ssn_limit));
+        Ok(())
+    }
+
+    /// Starts the derivation step `step` of a security context with the
+    /// pseudorandom key `prk`.
+    fn derive(
+        &self,
+        processid: ProcessId,
+        step: Derivation,
+        context: &SecurityContext,
+        prk: [u8; 32],
+    ) -> Result<(), ErrorCode> {
+        let data = self.hmac_data.take().ok_or(ErrorCode::BUSY)?;
+        let record = &context.record;
+        let prepared = match step {
+            Derivation::Extract => data
+                .get_mut(..KEY_LEN)
+                .map(|buf| buf.copy_from_slice(&record.master_secret))
+                .ok_or(ErrorCode::SIZE)
+                .map(|()| KEY_LEN),
+            Derivation::SenderKey => oscore::encode_info(record.sender_id.as_bytes(), false, data),
+            Derivation::RecipientKey => {
+                oscore::encode_info(record.recipient_id.as_bytes(), false, data)
+            }
+            Derivation::CommonIv => oscore::encode_info(&[], true, data),
+        }
+        .and_then(|len| {
+            // An empty Master Salt is the default salt of HKDF, a string of
+            // zeroes (RFC 5869 -- sect. 2.2).
+            let key = match step {
+                Derivation::Extract => record.master_salt(),
+                _ => &prk[..],
+            };
+            self.hmac.set_mode_hmacsha256(key).map(|()| len)
+        });
+        let len = match prepared {
+            Ok(len) => len,
+            Err(e) => {
+                self.hmac_data.replace(data);
+                return Err(e);
+            }
+        };
+
+        let mut data = SubSliceMut::new(data);
+        data.slice(..len);
+        self.hmac.add_mut_data(data).map_err(|(e, data)| {
+            self.hmac_data.replace(data.take());
+            e
+        })?;
+        self.crypto.set(CryptoOp::Deriving(processid, step, prk));
+        Ok(())
+    }
+
+    /// Aborts the set up of the security context of a process.
+    fn setup_failed(&self, processid: ProcessId, error: ErrorCode) {
+        let _ = self.apps.enter(processid, |app, kernel_data| {
+            if matches!(app.security, Security::SettingUp(_)) {
+                Self::security_failed(app, kernel_data, error);
+            }
+        });
+        self.transmit_pending();
+    }
+
+    fn send_request(
+        &self,
+        processid: ProcessId,
+        method: u8,
+        confirmable: bool,
+        port: u16,
+    ) -> Result<(), ErrorCode> {
+        if !code::is_request(method) {
+            return Err(ErrorCode::INVAL);
+        }
+
+        self.apps
+            .enter(processid, |app, kernel_data| {
+                if app.request.is_some() {
+                    return Err(ErrorCode::BUSY);
+                }
+                let path = kernel_data
+                    .get_readonly_processbuffer(ro_allow::PATH)
+                    .and_then(|path| path.enter(|path| Path::from_process(path)))
+                    .unwrap_or(Err(ErrorCode::INVAL))?;
+                let dest = kernel_data
+                    .get_readonly_processbuffer(ro_allow::DEST)
+                    .and_then(|dest| {
+                        dest.enter(|dest| {
+                            if dest.len() != size_of::<IPAddr>() {
+                                return Err(ErrorCode::INVAL);
+                            }
+                            let mut addr = IPAddr::new();
+                            dest.copy_to_slice(&mut addr.0);
+                            Ok(addr)
+                        })
+                    })
+                    .unwrap_or(Err(ErrorCode::INVAL))?;
+                let payload_len = kernel_data
+                    .get_readonly_processbuffer(ro_allow::REQUEST_PAYLOAD)
+                    .map_or(0, |payload| payload.len());
+
+                let protected = app.security.required();
+                let szx = self.block_szx(protected);
+                let block_size = BlockOption::size_of(szx);
+                let block1 = if payload_len > block_size {
+                    if payload_len > (BlockOption::MAX_NUM as usize + 1) * block_size {
+                        return Err(ErrorCode::SIZE);
+                    }
+                    Some(BlockOption {
+                        num: 0,
+                        more: true,
+                        szx,
+                    })
+                } else {
+                    None
+                };
+
+                app.next_token = app.next_token.wrapping_add(1);
+                let mut token = [0; TOKEN_LEN];
+                token[..2].copy_from_slice(&(processid.id() as u16).to_be_bytes());
+                token[2..].copy_from_slice(&app.next_token.to_be_bytes());
+
+                app.request = Some(ClientRequest {
+                    dest,
+                    port,
+                    method,
+                    confirmable,
+                    path,
+                    token,
+                    msg_id: self.next_msg_id(),
+                    state: ClientState::Pending,
+                    retransmissions: 0,
+                    timeout_ms: self.initial_timeout(),
+                    timer_ms: 0,
+                    payload_len,
+                    block1,
+                    block2: None,
+                    received: 0,
+                    protected,
+                    piv: None,
+                });
+                Ok(())
+            })
+            .unwrap_or_else(|err| Err(err.into()))?;
+
+        self.transmit_pending();
+        Ok(())
+    }
+
+    fn register_resource(&self, processid: ProcessId) -> Result<usize, ErrorCode> {
+        let path = self
+            .apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readonly_processbuffer(ro_allow::PATH)
+                    .and_then(|path| path.enter(|path| Path::from_process(path)))
+                    .unwrap_or(Err(ErrorCode::INVAL))
+            })
+            .unwrap_or_else(|err| Err(err.into()))?;
+        if path.len == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+
+        let mut registered = false;
+        self.apps.each(|_, app, _| {
+            registered |= app
+                .resources
+                .iter()
+                .flatten()
+                .any(|resource| resource.as_bytes() == path.as_bytes());
+        });
+        if registered {
+            return Err(ErrorCode::ALREADY);
+        }
+
+        self.apps
+            .enter(processid, |app, _| {
+                let id = app
+                    .resources
+                    .iter()
+                    .position(|resource| resource.is_none())
+                    .ok_or(ErrorCode::NOMEM)?;
+                app.resources[id] = Some(path);
+                Ok(id)
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+
+    fn unregister_resource(&self, processid: ProcessId, id: usize) -> Result<(), ErrorCode> {
+        self.apps
+            .enter(processid, |app, _| {
+                app.resources
+                    .get_mut(id)
+                    .and_then(|resource| resource.take())
+                    .ok_or(ErrorCode::INVAL)?;
+                if app
+                    .incoming
+                    .map_or(false, |exchange| exchange.resource == id)
+                {
+                    app.incoming = None;
+                }
+                if app
+                    .block_transfer
+                    .map_or(false, |transfer| transfer.resource == id)
+                {
+                    app.block_transfer = None;
+                }
+                Ok(())
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+
+    fn respond(&self, processid: ProcessId, code: u8) -> Result<(), ErrorCode> {
+        if !code::is_response(code) {
+            return Err(ErrorCode::INVAL);
+        }
+        self.apps
+            .enter(processid, |app, _| match app.incoming.as_mut() {
+                Some(exchange) if exchange.response.is_none() => {
+                    exchange.response = Some(code);
+                    Ok(())
+                }
+                Some(_) => Err(ErrorCode::ALREADY),
+                None => Err(ErrorCode::INVAL),
+            })
+            .unwrap_or_else(|err| Err(err.into()))?;
+        self.transmit_pending();
+        Ok(())
+    }
+
+    fn enable_security(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        self.apps
+            .enter(processid, |app, _| match app.security {
+                Security::Requested | Security::SettingUp(_) => Err(ErrorCode::BUSY),
+                _ => {
+                    app.security = Security::Requested;
+                    Ok(())
+                }
+            })
+            .unwrap_or_else(|err| Err(err.into()))?;
+        self.transmit_pending();
+        Ok(())
+    }
+
+    fn disable_security(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        self.apps
+            .enter(processid, |app, _| {
+                app.security = Security::Disabled;
+                if app
+                    .request
+                    .as_ref()
+                    .map_or(false, |request| request.protected)
+                {
+                    app.request = None;
+                }
+                if app
+                    .incoming
+                    .map_or(false, |exchange| exchange.protected.is_some())
+                {
+                    app.incoming = None;
+                }
+            })
+            .map_err(ErrorCode::from)
+    }
+
+    /// Handles a request. `protection` is the process the request was
+    /// protected for and the Partial IV of the request, if it was protected.
+    fn receive_request(
+        &self,
+        src_addr: IPAddr,
+        src_port: u16,
+        msg: &CoapMessage,
+        protection: Option<(ProcessId, PartialIv)>,
+    ) {
+        let header = msg.header;
+        let mut path = Path::new();
+        let mut block2 = None;
+        let mut error = None;
+        for (number, value) in msg.options().flatten() {
+            match number {
+                option::URI_PATH => {
+                    // No resource has a path this long.
+                    if path.push_segment(value).is_err() {
+                        error = error.or(Some(code::NOT_FOUND));
+                    }
+                }
+                option::BLOCK2 => match BlockOption::decode(value) {
+                    Some(block) => block2 = Some(block),
+                    None => error = Some(code::BAD_OPTION),
+                },
+                // The request is addressed to this node.
+                option::URI_HOST | option::URI_PORT => {}
+                number if option::is_critical(number) => error = Some(code::BAD_OPTION),
+                _ => {}
+            }
+        }
+        if let Some(code) = error {
+            self.send_error(src_addr, src_port, &header, code);
+            return;
+        }
+
+        let mut dispatch = Dispatch::NotFound;
+        self.apps.each(|processid, app, kernel_data| {
+            if dispatch != Dispatch::NotFound
+                || protection.map_or(false, |(target, _)| target != processid)
+            {
+                return;
+            }
+            let resource = match app.resources.iter().position(|resource| {
+                resource.map_or(false, |resource| resource.as_bytes() == path.as_bytes())
+            }) {
+                Some(resource) => resource,
+                None => return,
+            };
+            if protection.is_none() && app.security.required() {
+                dispatch = Dispatch::Unauthorized;
+                return;
+            }
+            if let Some(incoming) = app.incoming {
+                dispatch = if incoming.peer == src_addr
+                    && incoming.port == src_port
+                    && incoming.request.msg_id == header.msg_id
+                {
+                    Dispatch::Duplicate
+                } else {
+                    Dispatch::Busy
+                };
+                return;
+            }
+
+            let mut exchange = ServerExchange {
+                peer: src_addr,
+                port: src_port,
+                request: header,
+                resource,
+                block2,
+                response: None,
+                protected: protection.map(|(_, piv)| piv),
+            };
+            dispatch = Dispatch::Delivered;
+
+            // The following blocks of a response are served from the
+            // response buffer of the process.
+            if let (Some(block), Some(transfer)) = (block2, app.block_transfer) {
+                if block.num > 0
+                    && transfer.peer == src_addr
+                    && transfer.port == src_port
+                    && transfer.resource == resource
+                {
+                    exchange.response = Some(transfer.code);
+                    app.incoming = Some(exchange);
+                    return;
+                }
+            }
+
+            let _ = kernel_data
+                .get_readwrite_processbuffer(rw_allow::REQUEST_BODY)
+                .and_then(|body| {
+                    body.mut_enter(|body| {
+                        let len = min(body.len(), msg.payload.len());
+                        body[..len].copy_from_slice(&msg.payload[..len]);
+                    })
+                });
+            app.incoming = Some(exchange);
+            kernel_data
+                .schedule_upcall(
+                    upcall::REQUEST,
+                    (resource, header.code as usize, msg.payload.len()),
+                )
+                .ok();
+        });
+
+        match dispatch {
+            Dispatch::NotFound => self.send_error(src_addr, src_port, &header, code::NOT_FOUND),
+            Dispatch::Unauthorized => {
+                self.send_error(src_addr, src_port, &header, code::UNAUTHORIZED)
+            }
+            Dispatch::Busy => {
+                self.send_error(src_addr, src_port, &header, code::SERVICE_UNAVAILABLE)
+            }
+            // The process did not respond yet.
+            Dispatch::Duplicate => {}
+            Dispatch::Delivered => self.transmit_pending(),
+        }
+    }
+
+    /// Handles an empty acknowledgement or reset of a request.
+    fn receive_empty(&self, src_addr: IPAddr, src_port: u16, header: &CoapHeader) {
+        self.apps.each(|_, app, kernel_data| {
+            // The acknowledgement may arrive while a retransmission of the
+            // request waits for the transmitter.
+            let request = match app.request.as_mut() {
+                Some(request)
+                    if request.state != ClientState::AwaitingResponse
+                        && request.msg_id == header.msg_id
+                        && request.dest == src_addr
+                        && request.port == src_port =>
+                {
+                    request
+                }
+                _ => return,
+            };
+            if header.mtype == CoapType::Acknowledgement {
+                // The response follows separately.
+                request.state = ClientState::AwaitingResponse;
+                request.timer_ms = RESPONSE_TIMEOUT_MS;
+            } else {
+                Self::request_failed(app, kernel_data, ErrorCode::FAIL);
+            }
+        });
+    }
+
+    /// Whether a message with `header` from `src_addr` is a response to
+    /// `request`. Piggybacked responses also have to match the message ID.
+    fn is_response_to(
+        request: &ClientRequest,
+        src_addr: IPAddr,
+        src_port: u16,
+        header: &CoapHeader,
+    ) -> bool {
+        request.token == header.get_token()
+            && request.dest == src_addr
+            && request.port == src_port
+            && (header.mtype != CoapType::Acknowledgement
+                || (request.state != ClientState::AwaitingResponse
+                    && request.msg_id == header.msg_id))
+    }
+
+    /// Handles a response to `request`. Returns the response code and the
+    /// length of the response body once the request completed.
+    fn client_response(
+        &self,
+        request: &mut ClientRequest,
+        kernel_data: &GrantKernelData,
+        msg: &CoapMessage,
+    ) -> Option<Result<(u8, usize), ErrorCode>> {
+        let code = msg.header.code;
+
+        // Continue sending the request payload block-wise. Any other response
+        // ends the transfer of the payload.
+        if let Some(block1) = request.block1.filter(|_| code == code::CONTINUE) {
+            if !block1.more {
+                return Some(Err(ErrorCode::FAIL));
+            }
+            // The server may ask for smaller blocks.
+            let szx = msg
+                .find_option(option::BLOCK1)
+                .and_then(BlockOption::decode)
+                .map_or(block1.szx, |block| min(block.szx, block1.szx));
+            let size = BlockOption::size_of(szx);
+            let offset = block1.offset() + block1.size();
+            request.block1 = Some(BlockOption {
+                num: (offset / size) as u32,
+                more: offset + size < request.payload_len,
+                szx,
+            });
+            self.next_message(request);
+            return None;
+        }
+
+        let block2 = msg
+            .find_option(option::BLOCK2)
+            .and_then(BlockOption::decode);
+        let offset = block2.map_or(0, |block| block.offset());
+        if offset != request.received {
+            return Some(Err(ErrorCode::FAIL));
+        }
+        let _ = kernel_data
+            .get_readwrite_processbuffer(rw_allow::RESPONSE_BODY)
+            .and_then(|body| {
+                body.mut_enter(|body| {
+                    let end = min(body.len(), offset + msg.payload.len());
+                    if offset < end {
+                        body[offset..end].copy_from_slice(&msg.payload[..end - offset]);
+                    }
+                })
+            });
+        request.received = offset + msg.payload.len();
+
+        match block2 {
+            Some(block) if block.more && block.num < BlockOption::MAX_NUM => {
+                request.block1 = None;
+                request.block2 = Some(BlockOption {
+                    num: block.num + 1,
+                    more: false,
+                    szx: block.szx,
+                });
+                self.next_message(request);
+                None
+            }
+            _ => Some(Ok((code, request.received))),
+        }
+    }
+
+    /// Handles a response. `protected` is the process the response was
+    /// protected for, if it was protected; protected requests only accept
+    /// protected responses.
+    fn receive_response(
+        &self,
+        src_addr: IPAddr,
+        src_port: u16,
+        msg: &CoapMessage,
+        protected: Option<ProcessId>,
+    ) {
+        let header = msg.header;
+        let mut matched = false;
+        self.apps.each(|processid, app, kernel_data| {
+            let request = match app.request.as_mut() {
+                Some(request)
+                    if !matched
+                        && protected.map_or(!request.protected, |target| target == processid)
+                        && Self::is_response_to(request, src_addr, src_port, &header) =>
+                {
+                    request
+                }
+                _ => return,
+            };
+            matched = true;
+
+            if let Some(result) = self.client_response(request, kernel_data, msg) {
+                app.request = None;
+                let (code, len) = result.unwrap_or((0, 0));
+                kernel_data
+                    .schedule_upcall(
+                        upcall::RESPONSE,
+                        (
+                            kernel::errorcode::into_statuscode(result.map(|_| ())),
+                            code as usize,
+                            len,
+                        ),
+                    )
+                    .ok();
+            }
+        });
+
+        // Acknowledge separate responses, and reject unexpected ones.
+        if header.mtype == CoapType::Confirmable {
+            let mtype = if matched {
+                CoapType::Acknowledgement
+            } else {
+                CoapType::Reset
+            };
+            self.send_empty(src_addr, src_port, mtype, header.msg_id);
+        }
+        self.transmit_pending();
+    }
+
+    /// Handles a message carrying the OSCORE option with the value `value`.
+    fn receive_protected(&self, src_addr: IPAddr, src_port: u16, msg: &CoapMessage, value: &[u8]) {
+        let header = msg.header;
+        let option = OscoreOption::decode(value);
+        if code::is_request(header.code) {
+            if !matches!(
+                header.mtype,
+                CoapType::Confirmable | CoapType::NonConfirmable
+            ) {
+                return;
+            }
+            match option {
+                Some(OscoreOption {
+                    piv: Some(piv),
+                    kid: Some(kid),
+                }) => self.unprotect_request(src_addr, src_port, msg, piv, kid),
+                Some(_) => self.send_error(src_addr, src_port, &header, code::BAD_REQUEST),
+                None => self.send_error(src_addr, src_port, &header, code::BAD_OPTION),
+            }
+        } else if code::is_response(header.code) {
+            if let Some(option) = option {
+                self.unprotect_response(src_addr, src_port, msg, option);
+            }
+        }
+    }
+
+    /// Copies the ciphertext and the tag of a protected message, and returns
+    /// the length of the ciphertext.
+    fn copy_ciphertext(buf: &mut [u8], payload: &[u8]) -> Result<usize, ErrorCode> {
+        // The plaintext holds at least the code.
+        if payload.len() <= TAG_LEN {
+            return Err(ErrorCode::INVAL);
+        }
+        buf.get_mut(..payload.len())
+            .ok_or(ErrorCode::SIZE)?
+            .copy_from_slice(payload);
+        Ok(payload.len() - TAG_LEN)
+    }
+
+    /// Starts verifying a protected request from the client with the Sender
+    /// ID `kid`.
+    fn unprotect_request(
+        &self,
+        src_addr: IPAddr,
+        src_port: u16,
+        msg: &CoapMessage,
+        piv: PartialIv,
+        kid: Id,
+    ) {
+        let header = msg.header;
+        let mut duplicate = false;
+        let mut target = None;
+        self.apps.each(|processid, app, _| {
+            duplicate |= app.incoming.map_or(false, |incoming| {
+                incoming.peer == src_addr
+                    && incoming.port == src_port
+                    && incoming.request.msg_id == header.msg_id
+            });
+            if let Security::Enabled(context) = &app.security {
+                if context.record.recipient_id == kid {
+                    target = Some((processid, *context));
+                }
+            }
+        });
+        // The process did not respond yet.
+        if duplicate {
+            return;
+        }
+        let (processid, context) = match target {
+            Some(target) if target.1.is_fresh(&piv) => target,
+            // No security context, or a replayed request (RFC 8613 --
+            // sect. 8.2).
+            _ => {
+                self.send_error(src_addr, src_port, &header, code::UNAUTHORIZED);
+                return;
+            }
+        };
+
+        let nonce = context.nonce(&kid, &piv);
+        match self.start_ccm(&context.recipient_key, &nonce, &kid, &piv, false, |buf| {
+            Self::copy_ciphertext(buf, msg.payload)
+        }) {
+            Ok((m_off, m_len)) => self.crypto.set(CryptoOp::Unprotecting(Incoming {
+                processid,
+                src_addr,
+                src_port,
+                header,
+                request: Some(piv),
+                m_off,
+                m_len,
+            })),
+            // The client retransmits confirmable requests.
+            Err(ErrorCode::BUSY) => {}
+            Err(_) => self.send_error(src_addr, src_port, &header, code::BAD_REQUEST),
+        }
+    }
+
+    /// Starts verifying a protected response.
+    fn unprotect_response(
+        &self,
+        src_addr: IPAddr,
+        src_port: u16,
+        msg: &CoapMessage,
+        option: OscoreOption,
+    ) {
+        let header = msg.header;
+        let mut target = None;
+        self.apps.each(|processid, app, _| {
+            if let (Some(request), Security::Enabled(context)) = (&app.request, &app.security) {
+                if let Some(request_piv) = request.piv.filter(|_| {
+                    request.protected && Self::is_response_to(request, src_addr, src_port, &header)
+                }) {
+                    target = Some((processid, *context, request_piv));
+                }
+            }
+        });
+        let (processid, context, request_piv) = match target {
+            Some(target) => target,
+            None => return,
+        };
+
+        // Responses without a Partial IV use the nonce of the request.
+        let nonce = match option.piv {
+            Some(piv) => context.nonce(&context.record.recipient_id, &piv),
+            None => context.nonce(&context.record.sender_id, &request_piv),
+        };
+        if let Ok((m_off, m_len)) = self.start_ccm(
+            &context.recipient_key,
+            &nonce,
+            &context.record.sender_id,
+            &request_piv,
+            false,
+            |buf| Self::copy_ciphertext(buf, msg.payload),
+        ) {
+            self.crypto.set(CryptoOp::Unprotecting(Incoming {
+                processid,
+                src_addr,
+                src_port,
+                header,
+                request: None,
+                m_off,
+                m_len,
+            }));
+        }
+    }
+
+    /// Handles a verified protected message with the plaintext `plaintext`.
+    fn receive_verified(&self, incoming: &Incoming, plaintext: &[u8]) {
+        let (src_addr, src_port) = (incoming.src_addr, incoming.src_port);
+        let msg = CoapMessage::decode_inner(&incoming.header, plaintext);
+        match (incoming.request, msg) {
+            (Some(piv), Some(msg)) if code::is_request(msg.header.code) => {
+                // Accept every request only once.
+                let fresh = self
+                    .apps
+                    .enter(incoming.processid, |app, _| match &mut app.security {
+                        Security::Enabled(context) if context.is_fresh(&piv) => {
+                            context.received(&piv);
+                            true
+                        }
+                        _ => false,
+                    })
+                    .unwrap_or(false);
+                if fresh {
+                    self.receive_request(src_addr, src_port, &msg, Some((incoming.processid, piv)));
+                } else {
+                    self.send_error(src_addr, src_port, &incoming.header, code::UNAUTHORIZED);
+                }
+            }
+            (Some(_), _) => {
+                self.send_error(src_addr, src_port, &incoming.header, code::BAD_REQUEST)
+            }
+            (None, Some(msg)) if code::is_response(msg.header.code) => {
+                self.receive_response(src_addr, src_port, &msg, Some(incoming.processid))
+            }
+            (None, _) => {}
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, H: digest::Digest<'a, 32> + digest::HmacSha256> SyscallDriver
+    for CoapDriver<'a, A, H>
+{
+    /// CoAP control
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Send a request to the address in the destination buffer, for
+    ///        the path in the path buffer, with the payload in the request
+    ///        payload buffer. The lower 8 bits of `arg1` are the method, and
+    ///        bit 8 requests a non-confirmable message. `arg2` is the
+    ///        destination port, or 0 for the CoAP port. Returns BUSY if the
+    ///        process has a request outstanding.
+    /// - `2`: Cancel the outstanding request of the process, without
+    ///        notifying it.
+    /// - `3`: Register a resource with the path in the path buffer. Returns
+    ///        the resource ID, ALREADY if a process registered the path
+    ///        already, and NOMEM if the process registered too many
+    ///        resources.
+    /// - `4`: Unregister the resource with ID `arg1`.
+    /// - `5`: Respond to the incoming request with the code `arg1` and the
+    ///        payload in the response payload buffer.
+    /// - `6`: Returns the size of the blocks of block-wise transfers of the
+    ///        process.
+    /// - `7`: Set up the OSCORE security context of the process from its
+    ///        record in the KV store. Completion is signaled with the
+    ///        security upcall. Returns BUSY if a set up is in progress.
+    /// - `8`: Disable the security context of the process, canceling its
+    ///        protected exchanges.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        arg2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+            1 => {
+                let port = match arg2 {
+                    0 => COAP_PORT,
+                    port => match u16::try_from(port) {
+                        Ok(port) => port,
+                        Err(_) => return CommandReturn::failure(ErrorCode::INVAL),
+                    },
+                };
+                let confirmable = arg1 & (1 << 8) == 0;
+                self.send_request(processid, arg1 as u8, confirmable, port)
+                    .into()
+            }
+            2 => self
+                .apps
+                .enter(processid, |app, _| {
+                    app.request = None;
+                })
+                .map_err(ErrorCode::from)
+                .into(),
+            3 => match self.register_resource(processid) {
+                Ok(id) => CommandReturn::success_u32(id as u32),
+                Err(e) => CommandReturn::failure(e),
+            },
+            4 => self.unregister_resource(processid, arg1).into(),
+            5 => match u8::try_from(arg1) {
+                Ok(code) => self.respond(processid, code).into(),
+                Err(_) => CommandReturn::failure(ErrorCode::INVAL),
+            },
+            6 => match self.apps.enter(processid, |app, _| app.security.required()) {
+                Ok(protected) => CommandReturn::success_u32(BlockOption::size_of(
+                    self.block_szx(protected),
+                ) as u32),
+                Err(err) => CommandReturn::failure(err.into()),
+            },
+            7 => self.enable_security(processid).into(),
+            8 => self.disable_security(processid).into(),
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, H: digest::Digest<'a, 32> + digest::HmacSha256> UDPSendClient
+    for CoapDriver<'a, A, H>
+{
+    fn send_done(&self, result: Result<(), ErrorCode>, dgram: SubSliceMut<'static, u8>) {
+        if result.is_err() {
+            debug!("[CoAP] Message transmission failed: {:?}", result);
+        }
+        self.tx_buffer.replace(dgram);
+        self.transmit_pending();
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, H: digest::Digest<'a, 32> + digest::HmacSha256> UDPRecvClient
+    for CoapDriver<'a, A, H>
+{
+    fn receive(
+        &self,
+        src_addr: IPAddr,
+        _dst_addr: IPAddr,
+        src_port: u16,
+        _dst_port: u16,
+        payload: &[u8],
+    ) {
+        let msg = match CoapMessage::decode(payload) {
+            Some(msg) => msg,
+            None => return,
+        };
+        if let Some(value) = msg.find_option(option::OSCORE) {
+            self.receive_protected(src_addr, src_port, &msg, value);
+            return;
+        }
+        let header = msg.header;
+        if code::is_request(header.code) {
+            if matches!(
+                header.mtype,
+                CoapType::Confirmable | CoapType::NonConfirmable
+            ) {
+                self.receive_request(src_addr, src_port, &msg, None);
+            }
+        } else if code::is_response(header.code) {
+            self.receive_response(src_addr, src_port, &msg, None);
+        } else if header.code == code::EMPTY {
+            match header.mtype {
+                CoapType::Acknowledgement | CoapType::Reset => {
+                    self.receive_empty(src_addr, src_port, &header);
+                    self.transmit_pending();
+                }
+                // Answer CoAP pings (RFC 7252 -- sect. 4.3).
+                CoapType::Confirmable => {
+                    self.send_empty(src_addr, src_port, CoapType::Reset, header.msg_id)
+                }
+                CoapType::NonConfirmable => {}
+            }
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, H: digest::Digest<'a, 32> + digest::HmacSha256> time::AlarmClient
+    for CoapDriver<'a, A, H>
+{
+    fn alarm(&self) {
+        self.apps.each(|_, app, kernel_data| {
+            let expired = match app.request.as_mut() {
+                Some(request) if request.state != ClientState::Pending => {
+                    request.timer_ms = request.timer_ms.saturating_sub(COAP_TIMER_MS);
+                    if request.timer_ms > 0 {
+                        false
+                    } else if request.state == ClientState::AwaitingAck
+                        && request.retransmissions < MAX_RETRANSMIT
+                    {
+                        request.retransmissions += 1;
+                        request.timeout_ms *= 2;
+                        request.state = ClientState::Pending;
+                        false
+                    } else {
+                        true
+                    }
+                }
+                _ => false,
+            };
+            if expired {
+                Self::request_failed(app, kernel_data, ErrorCode::NOACK);
+            }
+        });
+        self.transmit_pending();
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, H: digest::Digest<'a, 32> + digest::HmacSha256> CCMClient
+    for CoapDriver<'a, A, H>
+{
+    fn crypt_done(&self, buf: &'static mut [u8], res: Result<(), ErrorCode>, tag_is_valid: bool) {
+        match self.crypto.take() {
+            Some(CryptoOp::Protecting(outgoing)) => {
+                self.crypt_buffer.replace(buf);
+                match res {
+                    Ok(()) => self.crypto.set(CryptoOp::Protected(outgoing)),
+                    Err(e) => {
+                        let _ =
+                            self.apps
+                                .enter(outgoing.processid, |app, kernel_data| {
+                                    match outgoing.kind {
+                                        OutgoingKind::Request(_) => {
+                                            Self::request_failed(app, kernel_data, e)
+                                        }
+                                        OutgoingKind::Response(_) => app.incoming = None,
+                                    }
+                                });
+                    }
+                }
+            }
+            Some(CryptoOp::Unprotecting(incoming)) => {
+                if res.is_ok() && tag_is_valid {
+                    self.receive_verified(
+                        &incoming,
+                        &buf[incoming.m_off..incoming.m_off + incoming.m_len],
+                    );
+                } else if incoming.request.is_some() {
+                    // Decryption failed (RFC 8613 -- sect. 8.2).
+                    self.send_error(
+                        incoming.src_addr,
+                        incoming.src_port,
+                        &incoming.header,
+                        code::BAD_REQUEST,
+                    );
+                }
+                self.crypt_buffer.replace(buf);
+            }
+            op => {
+                self.crypto.insert(op);
+                self.crypt_buffer.replace(buf);
+            }
+        }
+        self.transmit_pending();
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, H: digest::Digest<'a, 32> + digest::HmacSha256> digest::ClientData<32>
+    for CoapDriver<'a, A, H>
+{
+    fn add_data_done(
+        &self,
+        _result: Result<(), ErrorCode>,
+        _data: kernel::utilities::leasable_buffer::SubSlice<'static, u8>,
+    ) {
+    }
+
+    fn add_mut_data_done(&self, result: Result<(), ErrorCode>, data: SubSliceMut<'static, u8>) {
+        self.hmac_data.replace(data.take());
+        let result = result.and_then(|()| {
+            let digest = self.hmac_digest.take().ok_or(ErrorCode::BUSY)?;
+            self.hmac.run(digest).map_err(|(e, digest)| {
+                self.hmac_digest.replace(digest);
+                e
+            })
+        });
+        if let Err(e) = result {
+            if let Some(CryptoOp::Deriving(processid, _, _)) = self.crypto.take() {
+                self.setup_failed(processid, e);
+            }
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, H: digest::Digest<'a, 32> + digest::HmacSha256> digest::ClientHash<32>
+    for CoapDriver<'a, A, H>
+{
+    fn hash_done(&self, result: Result<(), ErrorCode>, digest: &'static mut [u8; 32]) {
+        let hash = *digest;
+        digest.fill(0);
+        self.hmac_digest.replace(digest);
+        let (processid, step, prk) = match self.crypto.take() {
+            Some(CryptoOp::Deriving(processid, step, prk)) => (processid, step, prk),
+            op => {
+                self.crypto.insert(op);
+                return;
+            }
+        };
+
+        let _ = self.apps.enter(processid, |app, kernel_data| {
+            let context = match &mut app.security {
+                Security::SettingUp(context) => context,
+                // The process disabled its security context meanwhile.
+                _ => return,
+            };
+            let result = result.and_then(|()| match step {
+                Derivation::Extract => self.derive(processid, Derivation::SenderKey, context, hash),
+                Derivation::SenderKey => {
+                    context.sender_key.copy_from_slice(&hash[..KEY_LEN]);
+                    self.derive(processid, Derivation::RecipientKey, context, prk)
+                }
+                Derivation::RecipientKey => {
+                    context.recipient_key.copy_from_slice(&hash[..KEY_LEN]);
+                    self.derive(processid, Derivation::CommonIv, context, prk)
+                }
+                Derivation::CommonIv => {
+                    context.common_iv.copy_from_slice(&hash[..NONCE_LEN]);
+                    self.store_ssn_limit(processid, &context.record)
+                }
+            });
+            if let Err(e) = result {
+                Self::security_failed(app, kernel_data, e);
+            }
+        });
+        self.transmit_pending();
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, H: digest::Digest<'a, 32> + digest::HmacSha256>
+    digest::ClientVerify<32> for CoapDriver<'a, A, H>
+{
+    fn verification_done(&self, _result: Result<bool, ErrorCode>, _compare: &'static mut [u8; 32]) {
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, H: digest::Digest<'a, 32> + digest::HmacSha256> kv::KVClient
+    for CoapDriver<'a, A, H>
+{
+    fn get_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        let record =
+            result.and_then(|()| ContextRecord::decode(&value[..]).ok_or(ErrorCode::INVAL));
+        self.kv_key.replace(key.take());
+        self.kv_value.replace(value.take());
+        let processid = match self.crypto.take() {
+            Some(CryptoOp::Loading(processid)) => processid,
+            op => {
+                self.crypto.insert(op);
+                return;
+            }
+        };
+
+        let _ = self.apps.enter(processid, |app, kernel_data| {
+            // The process disabled its security context meanwhile.
+            if !matches!(app.security, Security::Requested) {
+                return;
+            }
+            let result = record.and_then(|record| {
+                let context = SecurityContext::new(record);
+                app.security = Security::SettingUp(context);
+                self.derive(processid, Derivation::Extract, &context, [0; 32])
+            });
+            if let Err(e) = result {
+                Self::security_failed(app, kernel_data, e);
+            }
+        });
+        self.transmit_pending();
+    }
+
+    fn set_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.kv_key.replace(key.take());
+        self.kv_value.replace(value.take());
+        let (processid, ssn_limit) = match self.crypto.take() {
+            Some(CryptoOp::Storing(processid, ssn_limit)) => (processid, ssn_limit),
+            op => {
+                self.crypto.insert(op);
+                return;
+            }
+        };
+
+        let _ = self.apps.enter(processid, |app, kernel_data| {
+            let context = match &mut app.security {
+                Security::SettingUp(context) | Security::Enabled(context) => context,
+                _ => return,
+            };
+            if let Err(e) = result {
+                Self::security_failed(app, kernel_data, e);
+                return;
+            }
+            context.record.ssn_limit = ssn_limit;
+            if let Security::SettingUp(context) = app.security {
+                app.security = Security::Enabled(context);
+                kernel_data
+                    .schedule_upcall(
+                        upcall::SECURITY,
+                        (kernel::errorcode::into_statuscode(Ok(())), 0, 0),
+                    )
+                    .ok();
+            }
+        });
+        self.transmit_pending();
+    }
+
+    fn add_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        _key: SubSliceMut<'static, u8>,
+        _value: SubSliceMut<'static, u8>,
+    ) {
+    }
+
+    fn update_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        _key: SubSliceMut<'static, u8>,
+        _value: SubSliceMut<'static, u8>,
+    ) {
+    }
+
+    fn delete_complete(&self, _result: Result<(), ErrorCode>, _key: SubSliceMut<'static, u8>) {}
+}
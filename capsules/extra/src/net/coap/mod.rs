@@ -0,0 +1,17 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+pub mod driver;
+pub mod oscore;
+
+pub use self::driver::CoapDriver;
+pub use self::driver::DRIVER_NUM;
+
+// Reexport the exports of the [`coap`] module, to avoid redundant
+// module paths (e.g. `capsules::net::coap::coap::CoapHeader`)
+mod coap;
+pub use coap::{
+    code, decode_uint, option, BlockOption, CoapHeader, CoapMessage, CoapMessageBuilder,
+    CoapOptionIter, CoapType, COAP_HDR_LEN, COAP_VERSION, MAX_TOKEN_LEN,
+};
@@ -0,0 +1,671 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Object Security for Constrained RESTful Environments (OSCORE, RFC 8613).
+//!
+//! OSCORE protects CoAP messages end to end, independently of the UDP path
+//! they take. The code, the options only the endpoints act on (class E
+//! options, such as Uri-Path and the block options) and the payload of a
+//! message are encrypted with AES-CCM-16-64-128 into the payload of an outer
+//! message. The outer message carries the OSCORE option, from which the
+//! receiver finds the security context and rebuilds the nonce:
+//!
+//! ```text
+//! outer message: header | token | OSCORE option | 0xFF | ciphertext | tag
+//! plaintext:     code | class E options | 0xFF | payload
+//! ```
+//!
+//! The endpoints derive their keys and the Common IV from a shared master
+//! secret with HKDF-SHA256 (sect. 3.2). This module holds the security
+//! contexts and encodes the inputs of the cryptographic operations: the
+//! nonce, the additional authenticated data and the key derivation input.
+//! The operations themselves run asynchronously in the CoAP driver.
+//!
+//! Security context records
+//! ------------------------
+//! Processes provision their security context in the KV store under the key
+//! [`KV_KEY`]. The value is a record with the following layout:
+//!
+//! ```text
+//! 0       Sender ID length (at most 7)
+//! 1       Recipient ID length (at most 7)
+//! 2       Master Salt length (at most 32)
+//! 3..19   Master Secret
+//! 19..24  Sequence number limit (40 bit, network byte order)
+//! 24..    Sender ID | Recipient ID | Master Salt
+//! ```
+//!
+//! Sequence numbers below the limit may have been used already, and are
+//! never used again. The CoAP driver raises the limit in the KV store before
+//! it uses the sequence numbers below the new limit, so that nonces are not
+//! reused after a reboot (sect. 7.5.1). Provisioning writes a limit of 0.
+
+use kernel::hil::symmetric_encryption::CCM_NONCE_LENGTH;
+use kernel::ErrorCode;
+
+/// Key under which processes store their security context record.
+pub const KV_KEY: &[u8] = b"oscore";
+
+/// Length of the keys of AES-CCM-16-64-128.
+pub const KEY_LEN: usize = 16;
+
+/// Length of the nonce of AES-CCM-16-64-128.
+pub const NONCE_LEN: usize = CCM_NONCE_LENGTH;
+
+/// Length of the authentication tag of AES-CCM-16-64-128.
+pub const TAG_LEN: usize = 8;
+
+/// Longest Sender and Recipient ID (sect. 3.3).
+pub const MAX_ID_LEN: usize = NONCE_LEN - 6;
+
+/// Longest Partial IV.
+pub const MAX_PIV_LEN: usize = 5;
+
+/// Largest sequence number, which has to fit into the Partial IV.
+pub const MAX_SSN: u64 = (1 << (8 * MAX_PIV_LEN)) - 1;
+
+/// Longest Master Salt accepted in a record.
+pub const MAX_SALT_LEN: usize = 32;
+
+/// Longest value of the OSCORE option: the flags, the Partial IV and the kid.
+pub const MAX_OPTION_LEN: usize = 1 + MAX_PIV_LEN + MAX_ID_LEN;
+
+/// Longest additional authenticated data: the `Enc_structure` and the
+/// `external_aad` (sect. 5.4).
+pub const MAX_AAD_LEN: usize = 12 + 7 + MAX_ID_LEN + MAX_PIV_LEN;
+
+/// Longest input of the expansion of a key or of the Common IV.
+pub const MAX_INFO_LEN: usize = 10 + MAX_ID_LEN;
+
+/// Bytes protection adds to a message: the inner code, the OSCORE option and
+/// the authentication tag.
+pub const OVERHEAD_LEN: usize = 1 + 2 + MAX_OPTION_LEN + TAG_LEN;
+
+/// Length of the fixed part of a security context record.
+const RECORD_HDR_LEN: usize = 3 + KEY_LEN + MAX_PIV_LEN;
+
+/// Longest security context record.
+pub const MAX_RECORD_LEN: usize = RECORD_HDR_LEN + 2 * MAX_ID_LEN + MAX_SALT_LEN;
+
+/// COSE algorithm identifier of AES-CCM-16-64-128.
+const ALG_AES_CCM_16_64_128: u8 = 10;
+
+/// Number of sequence numbers before the highest one received that the
+/// replay window keeps track of.
+const REPLAY_WINDOW_LEN: u64 = 32;
+
+/// Appends bytes to a buffer, failing once it is full.
+struct Writer<'b> {
+    buf: &'b mut [u8],
+    len: usize,
+}
+
+impl<'b> Writer<'b> {
+    fn new(buf: &'b mut [u8]) -> Writer<'b> {
+        Writer { buf, len: 0 }
+    }
+
+    fn push(&mut self, bytes: &[u8]) -> Result<(), ErrorCode> {
+        let end = self.len + bytes.len();
+        self.buf
+            .get_mut(self.len..end)
+            .ok_or(ErrorCode::SIZE)?
+            .copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+
+    /// Appends a CBOR byte string shorter than 24 bytes.
+    fn push_bstr(&mut self, bytes: &[u8]) -> Result<(), ErrorCode> {
+        if bytes.len() >= 24 {
+            return Err(ErrorCode::SIZE);
+        }
+        self.push(&[0x40 | bytes.len() as u8])?;
+        self.push(bytes)
+    }
+}
+
+/// A Sender or Recipient ID.
+#[derive(Copy, Clone, Default, PartialEq, Eq)]
+pub struct Id {
+    bytes: [u8; MAX_ID_LEN],
+    len: u8,
+}
+
+impl Id {
+    pub fn new(id: &[u8]) -> Option<Id> {
+        let mut bytes = [0; MAX_ID_LEN];
+        bytes.get_mut(..id.len())?.copy_from_slice(id);
+        Some(Id {
+            bytes,
+            len: id.len() as u8,
+        })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+/// A Partial IV, as sent in the OSCORE option.
+#[derive(Copy, Clone, Default, PartialEq, Eq)]
+pub struct PartialIv {
+    bytes: [u8; MAX_PIV_LEN],
+    len: u8,
+}
+
+impl PartialIv {
+    /// Encodes a sequence number in network byte order without leading
+    /// zeroes, but with at least one byte.
+    pub fn from_ssn(ssn: u64) -> PartialIv {
+        let all = ssn.to_be_bytes();
+        let len = (8 - ssn.leading_zeros() as usize / 8).clamp(1, MAX_PIV_LEN);
+        let mut bytes = [0; MAX_PIV_LEN];
+        bytes[..len].copy_from_slice(&all[8 - len..]);
+        PartialIv {
+            bytes,
+            len: len as u8,
+        }
+    }
+
+    pub fn new(piv: &[u8]) -> Option<PartialIv> {
+        if piv.is_empty() {
+            return None;
+        }
+        let mut bytes = [0; MAX_PIV_LEN];
+        bytes.get_mut(..piv.len())?.copy_from_slice(piv);
+        Some(PartialIv {
+            bytes,
+            len: piv.len() as u8,
+        })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+
+    /// The sequence number encoded in the Partial IV.
+    pub fn ssn(&self) -> u64 {
+        self.as_bytes()
+            .iter()
+            .fold(0, |acc, b| (acc << 8) | *b as u64)
+    }
+}
+
+/// Value of the OSCORE option (sect. 6.1).
+#[derive(Copy, Clone, Default)]
+pub struct OscoreOption {
+    pub piv: Option<PartialIv>,
+    pub kid: Option<Id>,
+}
+
+impl OscoreOption {
+    /// Decodes the value of an OSCORE option. Returns `None` for malformed
+    /// values, and for values with a kid context, which is not supported.
+    pub fn decode(value: &[u8]) -> Option<OscoreOption> {
+        let (flags, rest) = match value.split_first() {
+            Some((flags, rest)) => (*flags, rest),
+            None => return Some(OscoreOption::default()),
+        };
+        // Reserved bits and the kid context flag.
+        if flags & 0xf0 != 0 {
+            return None;
+        }
+        let piv_len = (flags & 0x07) as usize;
+        if piv_len > MAX_PIV_LEN || rest.len() < piv_len {
+            return None;
+        }
+        let (piv, kid) = rest.split_at(piv_len);
+        let kid = if flags & 0x08 != 0 {
+            Some(Id::new(kid)?)
+        } else if kid.is_empty() {
+            None
+        } else {
+            return None;
+        };
+        Some(OscoreOption {
+            piv: PartialIv::new(piv),
+            kid,
+        })
+    }
+
+    /// Encodes the option into `buf` and returns its length.
+    pub fn encode(&self, buf: &mut [u8; MAX_OPTION_LEN]) -> usize {
+        if self.piv.is_none() && self.kid.is_none() {
+            return 0;
+        }
+        let piv = self.piv.as_ref().map_or(&[][..], |piv| piv.as_bytes());
+        buf[0] = piv.len() as u8 | if self.kid.is_some() { 0x08 } else { 0 };
+        buf[1..1 + piv.len()].copy_from_slice(piv);
+        let mut len = 1 + piv.len();
+        if let Some(kid) = self.kid.as_ref() {
+            let kid = kid.as_bytes();
+            buf[len..len + kid.len()].copy_from_slice(kid);
+            len += kid.len();
+        }
+        len
+    }
+}
+
+/// The input security context, as stored in the KV store.
+#[derive(Copy, Clone)]
+pub struct ContextRecord {
+    pub master_secret: [u8; KEY_LEN],
+    master_salt: [u8; MAX_SALT_LEN],
+    salt_len: u8,
+    pub sender_id: Id,
+    pub recipient_id: Id,
+    /// Sequence numbers below this one may have been used already.
+    pub ssn_limit: u64,
+}
+
+impl ContextRecord {
+    pub fn decode(buf: &[u8]) -> Option<ContextRecord> {
+        let header = buf.get(..RECORD_HDR_LEN)?;
+        let (sender_len, recipient_len, salt_len) =
+            (header[0] as usize, header[1] as usize, header[2] as usize);
+        if salt_len > MAX_SALT_LEN {
+            return None;
+        }
+        let mut master_secret = [0; KEY_LEN];
+        master_secret.copy_from_slice(&header[3..3 + KEY_LEN]);
+        let ssn_limit = header[3 + KEY_LEN..]
+            .iter()
+            .fold(0, |acc, b| (acc << 8) | *b as u64);
+
+        let ids =
+            buf.get(RECORD_HDR_LEN..RECORD_HDR_LEN + sender_len + recipient_len + salt_len)?;
+        let (sender_id, rest) = ids.split_at(sender_len);
+        let (recipient_id, salt) = rest.split_at(recipient_len);
+        let sender_id = Id::new(sender_id)?;
+        let recipient_id = Id::new(recipient_id)?;
+        // Both endpoints use the same keys otherwise (sect. 3.3).
+        if sender_id == recipient_id {
+            return None;
+        }
+        let mut master_salt = [0; MAX_SALT_LEN];
+        master_salt[..salt_len].copy_from_slice(salt);
+
+        Some(ContextRecord {
+            master_secret,
+            master_salt,
+            salt_len: salt_len as u8,
+            sender_id,
+            recipient_id,
+            ssn_limit,
+        })
+    }
+
+    /// Encodes the record into `buf` and returns its length.
+    pub fn encode(&self, buf: &mut [u8]) -> Result<usize, ErrorCode> {
+        let mut out = Writer::new(buf);
+        out.push(&[self.sender_id.len, self.recipient_id.len, self.salt_len])?;
+        out.push(&self.master_secret)?;
+        out.push(&self.ssn_limit.to_be_bytes()[8 - MAX_PIV_LEN..])?;
+        out.push(self.sender_id.as_bytes())?;
+        out.push(self.recipient_id.as_bytes())?;
+        out.push(self.master_salt())?;
+        Ok(out.len)
+    }
+
+    pub fn master_salt(&self) -> &[u8] {
+        &self.master_salt[..self.salt_len as usize]
+    }
+}
+
+/// Tracks the Partial IVs of the requests received with a security context
+/// (sect. 7.4).
+#[derive(Copy, Clone, Default)]
+struct ReplayWindow {
+    highest: Option<u64>,
+    /// Bit `i` is set if the sequence number `highest - i` was received.
+    received: u32,
+}
+
+impl ReplayWindow {
+    fn is_fresh(&self, ssn: u64) -> bool {
+        match self.highest {
+            Some(highest) if ssn <= highest => {
+                let age = highest - ssn;
+                age < REPLAY_WINDOW_LEN && self.received & (1 << age) == 0
+            }
+            _ => true,
+        }
+    }
+
+    fn update(&mut self, ssn: u64) {
+        match self.highest {
+            Some(highest) if ssn <= highest => self.received |= 1 << (highest - ssn),
+            highest => {
+                let shift = highest.map_or(REPLAY_WINDOW_LEN, |highest| ssn - highest);
+                self.received = if shift < REPLAY_WINDOW_LEN {
+                    (self.received << shift) | 1
+                } else {
+                    1
+                };
+                self.highest = Some(ssn);
+            }
+        }
+    }
+}
+
+/// The security context of an endpoint (sect. 3.1).
+#[derive(Copy, Clone)]
+pub struct SecurityContext {
+    pub record: ContextRecord,
+    pub sender_key: [u8; KEY_LEN],
+    pub recipient_key: [u8; KEY_LEN],
+    pub common_iv: [u8; NONCE_LEN],
+    /// Next sender sequence number.
+    ssn: u64,
+    replay_window: ReplayWindow,
+}
+
+impl SecurityContext {
+    /// Creates the context of a record. The keys still have to be derived,
+    /// and no sequence number is available until a higher limit is stored.
+    pub fn new(record: ContextRecord) -> SecurityContext {
+        SecurityContext {
+            record,
+            sender_key: [0; KEY_LEN],
+            recipient_key: [0; KEY_LEN],
+            common_iv: [0; NONCE_LEN],
+            ssn: record.ssn_limit,
+            replay_window: ReplayWindow::default(),
+        }
+    }
+
+    /// Whether all sequence numbers below the stored limit were used.
+    pub fn ssn_exhausted(&self) -> bool {
+        self.ssn >= self.record.ssn_limit
+    }
+
+    /// Takes the next sender sequence number.
+    pub fn next_piv(&mut self) -> Option<PartialIv> {
+        if self.ssn_exhausted() || self.ssn > MAX_SSN {
+            return None;
+        }
+        let piv = PartialIv::from_ssn(self.ssn);
+        self.ssn += 1;
+        Some(piv)
+    }
+
+    /// Builds the nonce of a message from the ID of the sender of the
+    /// Partial IV and the Partial IV (sect. 5.2).
+    pub fn nonce(&self, id: &Id, piv: &PartialIv) -> [u8; NONCE_LEN] {
+        let mut nonce = [0; NONCE_LEN];
+        let id = id.as_bytes();
+        let piv = piv.as_bytes();
+        nonce[0] = id.len() as u8;
+        nonce[1 + MAX_ID_LEN - id.len()..1 + MAX_ID_LEN].copy_from_slice(id);
+        nonce[NONCE_LEN - piv.len()..].copy_from_slice(piv);
+        for (n, iv) in nonce.iter_mut().zip(self.common_iv.iter()) {
+            *n ^= iv;
+        }
+        nonce
+    }
+
+    /// Whether a request with this Partial IV was not received before.
+    pub fn is_fresh(&self, piv: &PartialIv) -> bool {
+        self.replay_window.is_fresh(piv.ssn())
+    }
+
+    /// Records a verified request.
+    pub fn received(&mut self, piv: &PartialIv) {
+        self.replay_window.update(piv.ssn());
+    }
+}
+
+/// Encodes the `info` input of the expansion of a key, for the Sender or
+/// Recipient ID `id`, or of the Common IV (sect. 3.2.1). The counter byte of
+/// the first block of HKDF-Expand follows, so that the output is a single
+/// HMAC of the pseudorandom key (RFC 5869 -- sect. 2.3).
+pub fn encode_info(id: &[u8], iv: bool, buf: &mut [u8]) -> Result<usize, ErrorCode> {
+    let mut out = Writer::new(buf);
+    out.push(&[0x85])?;
+    out.push_bstr(id)?;
+    // No ID Context.
+    out.push(&[0xf6, ALG_AES_CCM_16_64_128])?;
+    if iv {
+        out.push(&[0x62, b'I', b'V', NONCE_LEN as u8])?;
+    } else {
+        out.push(&[0x63, b'K', b'e', b'y', KEY_LEN as u8])?;
+    }
+    out.push(&[0x01])?;
+    Ok(out.len)
+}
+
+/// Encodes the additional authenticated data of a message, bound to the
+/// request of the exchange by its kid and Partial IV (sect. 5.4). No class I
+/// options are used.
+pub fn encode_aad(
+    request_kid: &Id,
+    request_piv: &PartialIv,
+    buf: &mut [u8],
+) -> Result<usize, ErrorCode> {
+    let mut external_aad = [0; MAX_AAD_LEN];
+    let mut ext = Writer::new(&mut external_aad);
+    ext.push(&[0x85, 0x01, 0x81, ALG_AES_CCM_16_64_128])?;
+    ext.push_bstr(request_kid.as_bytes())?;
+    ext.push_bstr(request_piv.as_bytes())?;
+    ext.push(&[0x40])?;
+    let ext_len = ext.len;
+
+    let mut out = Writer::new(buf);
+    out.push(&[0x83, 0x68])?;
+    out.push(b"Encrypt0")?;
+    out.push(&[0x40])?;
+    out.push_bstr(&external_aad[..ext_len])?;
+    Ok(out.len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The client context of RFC 8613, appendix C.1.1.
+    fn client_context(ssn_limit: u64) -> SecurityContext {
+        let mut buf = [0; MAX_RECORD_LEN];
+        buf[..3].copy_from_slice(&[0, 1, 8]);
+        buf[3..19].copy_from_slice(&[
+            0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+            0x0f, 0x10,
+        ]);
+        buf[19..24].copy_from_slice(&ssn_limit.to_be_bytes()[3..]);
+        buf[24] = 0x01;
+        buf[25..33].copy_from_slice(&[0x9e, 0x7c, 0xa9, 0x22, 0x23, 0x78, 0x63, 0x40]);
+        let mut context = SecurityContext::new(ContextRecord::decode(&buf[..33]).unwrap());
+        context.common_iv = [
+            0x46, 0x22, 0xd4, 0xdd, 0x6d, 0x94, 0x41, 0x68, 0xee, 0xfb, 0x54, 0x98, 0x7c,
+        ];
+        context
+    }
+
+    #[test]
+    fn test_key_derivation_info() {
+        // RFC 8613, appendix C.1.1, followed by the HKDF-Expand counter.
+        let mut buf = [0; MAX_INFO_LEN];
+        let len = encode_info(&[], false, &mut buf).unwrap();
+        assert_eq!(
+            buf[..len],
+            [0x85, 0x40, 0xf6, 0x0a, 0x63, 0x4b, 0x65, 0x79, 0x10, 0x01]
+        );
+        let len = encode_info(&[0x01], false, &mut buf).unwrap();
+        assert_eq!(
+            buf[..len],
+            [0x85, 0x41, 0x01, 0xf6, 0x0a, 0x63, 0x4b, 0x65, 0x79, 0x10, 0x01]
+        );
+        let len = encode_info(&[], true, &mut buf).unwrap();
+        assert_eq!(
+            buf[..len],
+            [0x85, 0x40, 0xf6, 0x0a, 0x62, 0x49, 0x56, 0x0d, 0x01]
+        );
+
+        let len = encode_info(&[0; MAX_ID_LEN], false, &mut buf).unwrap();
+        assert_eq!(len, MAX_INFO_LEN);
+        assert_eq!(
+            encode_info(&[0; MAX_ID_LEN], false, &mut buf[..len - 1]),
+            Err(ErrorCode::SIZE)
+        );
+    }
+
+    #[test]
+    fn test_nonce() {
+        // RFC 8613, appendix C.4
+        let context = client_context(21);
+        let piv = PartialIv::from_ssn(20);
+        assert_eq!(
+            context.nonce(&context.record.sender_id, &piv),
+            [0x46, 0x22, 0xd4, 0xdd, 0x6d, 0x94, 0x41, 0x68, 0xee, 0xfb, 0x54, 0x98, 0x68]
+        );
+
+        // The ID is left-padded to the maximum ID length after its length
+        // byte, and the Partial IV fills the end.
+        let mut context = client_context(0);
+        context.common_iv = [0; NONCE_LEN];
+        let id = Id::new(&[0xaa, 0xbb]).unwrap();
+        let piv = PartialIv::new(&[1, 2, 3]).unwrap();
+        assert_eq!(
+            context.nonce(&id, &piv),
+            [2, 0, 0, 0, 0, 0, 0xaa, 0xbb, 0, 0, 1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn test_aad() {
+        // RFC 8613, appendix C.4
+        let mut buf = [0; MAX_AAD_LEN];
+        let kid = Id::new(&[]).unwrap();
+        let len = encode_aad(&kid, &PartialIv::from_ssn(20), &mut buf).unwrap();
+        assert_eq!(
+            buf[..len],
+            [
+                0x83, 0x68, 0x45, 0x6e, 0x63, 0x72, 0x79, 0x70, 0x74, 0x30, 0x40, 0x48, 0x85, 0x01,
+                0x81, 0x0a, 0x40, 0x41, 0x14, 0x40
+            ]
+        );
+
+        let kid = Id::new(&[0; MAX_ID_LEN]).unwrap();
+        let piv = PartialIv::from_ssn(MAX_SSN);
+        let len = encode_aad(&kid, &piv, &mut buf).unwrap();
+        assert_eq!(len, MAX_AAD_LEN);
+        assert_eq!(
+            encode_aad(&kid, &piv, &mut buf[..len - 1]),
+            Err(ErrorCode::SIZE)
+        );
+    }
+
+    #[test]
+    fn test_partial_iv() {
+        assert_eq!(PartialIv::from_ssn(0).as_bytes(), [0]);
+        assert_eq!(PartialIv::from_ssn(20).as_bytes(), [0x14]);
+        assert_eq!(PartialIv::from_ssn(0x100).as_bytes(), [1, 0]);
+        assert_eq!(PartialIv::from_ssn(MAX_SSN).as_bytes(), [0xff; 5]);
+        assert_eq!(PartialIv::new(&[0, 1, 2]).unwrap().ssn(), 0x102);
+        assert!(PartialIv::new(&[]).is_none());
+        assert!(PartialIv::new(&[0; MAX_PIV_LEN + 1]).is_none());
+    }
+
+    #[test]
+    fn test_option() {
+        // RFC 8613, appendix C.4: Partial IV 0x14 and an empty kid.
+        let option = OscoreOption::decode(&[0x09, 0x14]).unwrap();
+        assert_eq!(option.piv.unwrap().as_bytes(), [0x14]);
+        assert_eq!(option.kid.unwrap().as_bytes(), []);
+        let mut buf = [0; MAX_OPTION_LEN];
+        let len = option.encode(&mut buf);
+        assert_eq!(buf[..len], [0x09, 0x14]);
+
+        // A response without Partial IV has an empty option.
+        let option = OscoreOption::decode(&[]).unwrap();
+        assert!(option.piv.is_none() && option.kid.is_none());
+        assert_eq!(option.encode(&mut buf), 0);
+
+        let option = OscoreOption::decode(&[0x0a, 1, 2, 0xaa]).unwrap();
+        assert_eq!(option.piv.unwrap().ssn(), 0x102);
+        assert_eq!(option.kid.unwrap().as_bytes(), [0xaa]);
+    }
+
+    #[test]
+    fn test_option_invalid() {
+        // Kid context flag
+        assert!(OscoreOption::decode(&[0x19, 0x14, 1, 0]).is_none());
+        // Reserved Partial IV lengths
+        assert!(OscoreOption::decode(&[0x06, 1, 2, 3, 4, 5, 6]).is_none());
+        // Truncated Partial IV
+        assert!(OscoreOption::decode(&[0x02, 1]).is_none());
+        // Trailing bytes without the kid flag
+        assert!(OscoreOption::decode(&[0x01, 1, 2]).is_none());
+        // Kid too long
+        assert!(OscoreOption::decode(&[0x08, 1, 2, 3, 4, 5, 6, 7, 8]).is_none());
+    }
+
+    #[test]
+    fn test_record() {
+        let context = client_context(0x0102030405);
+        let record = context.record;
+        assert_eq!(record.sender_id.as_bytes(), []);
+        assert_eq!(record.recipient_id.as_bytes(), [0x01]);
+        assert_eq!(record.master_salt().len(), 8);
+        assert_eq!(record.ssn_limit, 0x0102030405);
+
+        let mut buf = [0; MAX_RECORD_LEN];
+        let len = record.encode(&mut buf).unwrap();
+        assert_eq!(len, 33);
+        assert_eq!(buf[19..24], [1, 2, 3, 4, 5]);
+        let decoded = ContextRecord::decode(&buf[..len]).unwrap();
+        assert_eq!(decoded.master_secret, record.master_secret);
+        assert_eq!(decoded.master_salt(), record.master_salt());
+        assert!(ContextRecord::decode(&buf[..len - 1]).is_none());
+
+        // Equal Sender and Recipient IDs
+        buf[0] = 1;
+        buf[24] = 0x01;
+        buf[25] = 0x01;
+        assert!(ContextRecord::decode(&buf[..len + 1]).is_none());
+    }
+
+    #[test]
+    fn test_sequence_numbers() {
+        let mut context = client_context(0);
+        assert!(context.ssn_exhausted());
+        assert!(context.next_piv().is_none());
+
+        // Sequence numbers below the stored limit are never reused.
+        let mut context = client_context(5);
+        assert!(context.next_piv().is_none());
+        context.record.ssn_limit = 7;
+        assert_eq!(context.next_piv().unwrap().ssn(), 5);
+        assert_eq!(context.next_piv().unwrap().ssn(), 6);
+        assert!(context.next_piv().is_none());
+    }
+
+    #[test]
+    fn test_replay_window() {
+        let mut context = client_context(0);
+        let piv = |ssn| PartialIv::from_ssn(ssn);
+        assert!(context.is_fresh(&piv(100)));
+        context.received(&piv(100));
+        assert!(!context.is_fresh(&piv(100)));
+
+        // Older requests within the window are accepted once.
+        assert!(context.is_fresh(&piv(90)));
+        context.received(&piv(90));
+        assert!(!context.is_fresh(&piv(90)));
+        assert!(context.is_fresh(&piv(69)));
+        assert!(!context.is_fresh(&piv(68)));
+
+        // Moving the window forward keeps what was received.
+        context.received(&piv(110));
+        assert!(!context.is_fresh(&piv(100)));
+        assert!(!context.is_fresh(&piv(90)));
+        assert!(context.is_fresh(&piv(95)));
+        assert!(!context.is_fresh(&piv(78)));
+
+        // A jump beyond the window forgets everything before it.
+        context.received(&piv(1000));
+        assert!(context.is_fresh(&piv(999)));
+        assert!(!context.is_fresh(&piv(110)));
+    }
+}
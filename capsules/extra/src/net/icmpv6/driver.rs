@@ -0,0 +1,278 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Ping userspace interface.
+//!
+//! Implements a userspace interface for sending ICMPv6 echo requests and
+//! waiting for the matching echo reply. Each process can have a single echo
+//! request outstanding. The identifier of the echo requests is derived from
+//! the process, and the process picks the sequence number, so that replies
+//! to an earlier, timed out request are ignored once a new request is sent.
+//!
+//! The driver is the client of an `ICMP6RecvStruct`, which answers echo
+//! requests from other nodes on its own.
+
+use crate::net::icmpv6::icmpv6_recv::ICMP6RecvClient;
+use crate::net::icmpv6::icmpv6_send::{ICMP6SendClient, ICMP6Sender};
+use crate::net::icmpv6::{ICMP6Header, ICMP6HeaderOptions, ICMP6Type};
+use crate::net::ipv6::ip_utils::{ip6_nh, IPAddr};
+use crate::net::ipv6::IP6Header;
+use crate::net::network_capabilities::NetworkCapability;
+
+use core::mem::size_of;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::processbuffer::ReadableProcessBuffer;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{MapCell, OptionalCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::{ErrorCode, ProcessId};
+
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Ping as usize;
+
+/// Length of an IPv6 header without extension headers.
+const IP6_HDR_LEN: usize = 40;
+
+/// IDs for subscribed upcalls.
+mod upcall {
+    /// The echo request completed. The arguments are a status code, the
+    /// sequence number of the request, and either the payload length of the
+    /// echo reply (on success), the ICMPv6 type and code of the error message
+    /// received instead as `(type << 8) | code` (on `FAIL`), or 0 if the
+    /// request could not be sent.
+    pub const ECHO_DONE: usize = 0;
+    /// Number of upcalls.
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// Destination buffer. Contains the 16 byte IPv6 address to ping.
+    pub const DEST: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+#[derive(Default)]
+pub struct App {
+    /// Sequence number of the outstanding echo request.
+    pending_seqno: Option<u16>,
+}
+
+pub struct PingDriver<'a> {
+    icmp_sender: &'a dyn ICMP6Sender<'a>,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<0>,
+    >,
+    /// Payload buffer for echo requests. Empty while a request is being sent.
+    kernel_buffer: MapCell<SubSliceMut<'static, u8>>,
+    /// Maximum payload length of an echo request.
+    max_payload_len: usize,
+    /// Process whose echo request is being sent.
+    current_app: OptionalCell<ProcessId>,
+    net_cap: &'static NetworkCapability,
+}
+
+impl<'a> PingDriver<'a> {
+    pub fn new(
+        icmp_sender: &'a dyn ICMP6Sender<'a>,
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<0>,
+        >,
+        kernel_buffer: &'static mut [u8],
+        net_cap: &'static NetworkCapability,
+    ) -> PingDriver<'a> {
+        PingDriver {
+            icmp_sender,
+            apps: grant,
+            max_payload_len: kernel_buffer.len(),
+            kernel_buffer: MapCell::new(SubSliceMut::new(kernel_buffer)),
+            current_app: OptionalCell::empty(),
+            net_cap,
+        }
+    }
+
+    /// The identifier of the echo requests sent by `processid`.
+    fn echo_id(processid: ProcessId) -> u16 {
+        processid.id() as u16
+    }
+
+    fn send_echo_request(
+        &self,
+        processid: ProcessId,
+        seqno: u16,
+        len: usize,
+    ) -> Result<(), ErrorCode> {
+        let dest = self.apps.enter(processid, |_, kernel_data| {
+            kernel_data
+                .get_readonly_processbuffer(ro_allow::DEST)
+                .and_then(|dest| {
+                    dest.enter(|dest| {
+                        if dest.len() != size_of::<IPAddr>() {
+                            return Err(ErrorCode::INVAL);
+                        }
+                        let mut addr = IPAddr::new();
+                        dest.copy_to_slice(&mut addr.0);
+                        Ok(addr)
+                    })
+                })
+                .unwrap_or(Err(ErrorCode::INVAL))
+        })??;
+
+        if len > self.max_payload_len {
+            return Err(ErrorCode::SIZE);
+        }
+        let mut buf = self.kernel_buffer.take().ok_or(ErrorCode::BUSY)?;
+        buf.reset();
+        buf.slice(0..len);
+        for (i, byte) in buf.as_slice().iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+
+        let mut icmp_header = ICMP6Header::new(ICMP6Type::Type128);
+        icmp_header.set_options(ICMP6HeaderOptions::Type128 {
+            id: Self::echo_id(processid),
+            seqno,
+        });
+        match self.icmp_sender.send(dest, icmp_header, buf, self.net_cap) {
+            Ok(()) => {
+                self.current_app.set(processid);
+                Ok(())
+            }
+            Err((e, buf)) => {
+                self.kernel_buffer.replace(buf);
+                Err(e)
+            }
+        }
+    }
+
+    /// Completes the outstanding echo request with identifier `id` and
+    /// sequence number `seqno`, if there is one.
+    fn complete(&self, id: u16, seqno: u16, status: Result<(), ErrorCode>, arg: usize) {
+        self.apps.each(|processid, app, kernel_data| {
+            if Self::echo_id(processid) == id && app.pending_seqno == Some(seqno) {
+                app.pending_seqno = None;
+                kernel_data
+                    .schedule_upcall(
+                        upcall::ECHO_DONE,
+                        (
+                            kernel::errorcode::into_statuscode(status),
+                            seqno as usize,
+                            arg,
+                        ),
+                    )
+                    .ok();
+            }
+        });
+    }
+}
+
+impl<'a> SyscallDriver for PingDriver<'a> {
+    /// Ping control
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Send an echo request to the address in the destination buffer.
+    ///        `arg1` is the sequence number and `arg2` the payload length.
+    ///        Replaces the outstanding echo request of the process, if any.
+    ///        Returns BUSY if another echo request is being sent, INVAL if
+    ///        the destination buffer is not 16 bytes long, and SIZE if the
+    ///        payload is too long.
+    /// - `2`: Returns the maximum payload length of an echo request.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        arg2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+            1 => {
+                let seqno = arg1 as u16;
+                match self.send_echo_request(processid, seqno, arg2) {
+                    Ok(()) => {
+                        let _ = self.apps.enter(processid, |app, _| {
+                            app.pending_seqno = Some(seqno);
+                        });
+                        CommandReturn::success()
+                    }
+                    Err(e) => CommandReturn::failure(e),
+                }
+            }
+            2 => CommandReturn::success_u32(self.max_payload_len as u32),
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+impl<'a> ICMP6SendClient for PingDriver<'a> {
+    fn send_done(&self, result: Result<(), ErrorCode>, buf: SubSliceMut<'static, u8>) {
+        self.kernel_buffer.replace(buf);
+        if let Some(processid) = self.current_app.take() {
+            if result.is_err() {
+                let _ = self.apps.enter(processid, |app, kernel_data| {
+                    if let Some(seqno) = app.pending_seqno.take() {
+                        kernel_data
+                            .schedule_upcall(
+                                upcall::ECHO_DONE,
+                                (
+                                    kernel::errorcode::into_statuscode(result),
+                                    seqno as usize,
+                                    0,
+                                ),
+                            )
+                            .ok();
+                    }
+                });
+            }
+        }
+    }
+}
+
+impl<'a> ICMP6RecvClient for PingDriver<'a> {
+    fn receive(&self, _src_addr: IPAddr, icmp_header: ICMP6Header, payload: &[u8]) {
+        match icmp_header.get_options() {
+            ICMP6HeaderOptions::Type129 { id, seqno } => {
+                self.complete(id, seqno, Ok(()), payload.len());
+            }
+            ICMP6HeaderOptions::Type1 { .. }
+            | ICMP6HeaderOptions::Type3 { .. }
+            | ICMP6HeaderOptions::Type4 { .. } => {
+                // The payload starts with the echo request that caused the
+                // error.
+                if payload.len() < IP6_HDR_LEN {
+                    return;
+                }
+                let invoking_header = match IP6Header::decode(payload).done() {
+                    Some((_, header)) => header,
+                    None => return,
+                };
+                if invoking_header.get_next_header() != ip6_nh::ICMP {
+                    return;
+                }
+                if let Some((_, request)) = ICMP6Header::decode(&payload[IP6_HDR_LEN..]).done() {
+                    if let ICMP6HeaderOptions::Type128 { id, seqno } = request.get_options() {
+                        let error = ((icmp_header.get_type_as_int() as usize) << 8)
+                            | icmp_header.get_code() as usize;
+                        self.complete(id, seqno, Err(ErrorCode::FAIL), error);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,331 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! This file contains the definition and implementation of the ICMPv6
+//! receive path. The [ICMP6RecvStruct](struct.ICMP6RecvStruct.html) is the
+//! `IP6RecvClient` of an IP receiver and handles all ICMPv6 messages
+//! received by that IP stack:
+//!
+//! - Echo requests addressed to this node are answered directly in the
+//!   kernel with an echo reply.
+//! - Echo replies and error messages are passed to the
+//!   [ICMP6RecvClient](trait.ICMP6RecvClient.html), e.g. a ping driver.
+//! - Destination unreachable, time exceeded and parameter problem messages
+//!   are also passed to the [ICMP6ErrorClient](trait.ICMP6ErrorClient.html)
+//!   together with the header of the packet that caused the error, so that
+//!   transport layers can report send errors to their users.
+//! - Router and Neighbor Solicitations and Advertisements are passed to the
+//!   [ICMP6NdClient](trait.ICMP6NdClient.html), which implements Neighbor
+//!   Discovery. Kernel layers like it send their messages with
+//!   `send_message`.
+//!
+//! The `ICMP6RecvStruct` is also the `IP6RecvErrorClient` of the IP
+//! receiver, and sends Parameter Problem messages for received packets that
+//! the IP layer discards because of their headers.
+//!
+//! Because the `ICMP6Sender` below this layer can only send one packet at a
+//! time, the `ICMP6RecvStruct` also implements `ICMP6Sender`, and the client
+//! sends its ICMPv6 packets through it rather than directly. An echo request
+//! that arrives while a packet is being sent is dropped.
+//!
+//! ```rust,ignore
+//! let icmp_recv = static_init!(
+//!     ICMP6RecvStruct<'static>,
+//!     ICMP6RecvStruct::new(icmp_send_struct, local_addrs, reply_buf, net_cap)
+//! );
+//! icmp_send_struct.set_client(icmp_recv);
+//! ip_receive.set_client(icmp_recv);
+//! ip_receive.set_error_client(icmp_recv);
+//! icmp_recv.set_error_client(udp_recv_mux);
+//! ```
+
+use crate::net::icmpv6::icmpv6_send::{ICMP6SendClient, ICMP6Sender};
+use crate::net::icmpv6::{ICMP6Header, ICMP6HeaderOptions, ICMP6Type};
+use crate::net::ipv6::ip_utils::{ip6_nh, IPAddr};
+use crate::net::ipv6::ipv6_recv::{IP6RecvClient, IP6RecvErrorClient};
+use crate::net::ipv6::IP6Header;
+use crate::net::network_capabilities::NetworkCapability;
+
+use core::cell::Cell;
+use core::cmp;
+use kernel::utilities::cells::{MapCell, OptionalCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::ErrorCode;
+
+/// Length of an IPv6 header without extension headers.
+const IP6_HDR_LEN: usize = 40;
+
+/// A trait for a client of an `ICMP6Receiver` that receives echo replies
+/// and ICMPv6 error messages.
+pub trait ICMP6RecvClient {
+    /// Called when an echo reply or an error message is received.
+    ///
+    /// # Arguments
+    ///
+    /// `src_addr` - The address of the node that sent the message
+    /// `icmp_header` - The ICMPv6 header of the message
+    /// `payload` - The ICMPv6 payload. For error messages, this is the
+    /// beginning of the packet that caused the error.
+    fn receive(&self, src_addr: IPAddr, icmp_header: ICMP6Header, payload: &[u8]);
+}
+
+/// A trait for a transport layer that wants to learn about ICMPv6 errors
+/// caused by the packets it sent.
+pub trait ICMP6ErrorClient {
+    /// Called when a destination unreachable, time exceeded or parameter
+    /// problem message is received.
+    ///
+    /// # Arguments
+    ///
+    /// `icmp_header` - The ICMPv6 header of the error message
+    /// `invoking_header` - The IPv6 header of the packet that caused the
+    /// error
+    /// `invoking_payload` - The part of the payload of that packet included
+    /// in the error message, starting with its transport header
+    fn receive_error(
+        &self,
+        icmp_header: ICMP6Header,
+        invoking_header: IP6Header,
+        invoking_payload: &[u8],
+    );
+}
+
+/// A trait for the Neighbor Discovery layer, which handles the Router and
+/// Neighbor Discovery messages and may configure addresses in addition to
+/// the static local addresses.
+pub trait ICMP6NdClient {
+    /// Called when a Router Solicitation, Router Advertisement, Neighbor
+    /// Solicitation or Neighbor Advertisement is received.
+    ///
+    /// # Arguments
+    ///
+    /// `ip_header` - The IPv6 header of the message
+    /// `icmp_header` - The ICMPv6 header of the message
+    /// `body` - The rest of the message following the ICMPv6 header
+    fn receive_nd(&self, ip_header: &IP6Header, icmp_header: ICMP6Header, body: &[u8]);
+
+    /// Returns whether `addr` is an address configured by this layer.
+    fn is_local_addr(&self, addr: &IPAddr) -> bool;
+}
+
+/// A trait that defines an interface for receiving ICMPv6 messages.
+pub trait ICMP6Receiver<'a> {
+    /// Sets the client that receives echo replies and error messages.
+    fn set_client(&self, client: &'a dyn ICMP6RecvClient);
+
+    /// Sets the client that is notified of errors caused by sent packets.
+    fn set_error_client(&self, client: &'a dyn ICMP6ErrorClient);
+
+    /// Sets the client that handles Neighbor Discovery messages.
+    fn set_nd_client(&self, client: &'a dyn ICMP6NdClient);
+}
+
+/// A struct that implements the `ICMP6Receiver` trait.
+pub struct ICMP6RecvStruct<'a> {
+    icmp_sender: &'a dyn ICMP6Sender<'a>,
+    local_addrs: &'a [IPAddr],
+    reply_buf: MapCell<SubSliceMut<'static, u8>>,
+    reply_buf_len: usize,
+    sending_reply: Cell<bool>,
+    net_cap: &'static NetworkCapability,
+    client: OptionalCell<&'a dyn ICMP6RecvClient>,
+    error_client: OptionalCell<&'a dyn ICMP6ErrorClient>,
+    nd_client: OptionalCell<&'a dyn ICMP6NdClient>,
+    send_client: OptionalCell<&'a dyn ICMP6SendClient>,
+}
+
+impl<'a> ICMP6RecvStruct<'a> {
+    /// Creates a new `ICMP6RecvStruct`. Echo requests are only answered if
+    /// they are sent to one of the `local_addrs` or to a multicast address,
+    /// and if their payload fits in `reply_buf`.
+    pub fn new(
+        icmp_sender: &'a dyn ICMP6Sender<'a>,
+        local_addrs: &'a [IPAddr],
+        reply_buf: &'static mut [u8],
+        net_cap: &'static NetworkCapability,
+    ) -> ICMP6RecvStruct<'a> {
+        ICMP6RecvStruct {
+            icmp_sender,
+            local_addrs,
+            reply_buf_len: reply_buf.len(),
+            reply_buf: MapCell::new(SubSliceMut::new(reply_buf)),
+            sending_reply: Cell::new(false),
+            net_cap,
+            client: OptionalCell::empty(),
+            error_client: OptionalCell::empty(),
+            nd_client: OptionalCell::empty(),
+            send_client: OptionalCell::empty(),
+        }
+    }
+
+    fn is_local(&self, addr: &IPAddr) -> bool {
+        addr.is_multicast()
+            || self.local_addrs.iter().any(|local| local == addr)
+            || self
+                .nd_client
+                .map_or(false, |client| client.is_local_addr(addr))
+    }
+
+    /// Sends an ICMPv6 message generated in the kernel, such as an echo
+    /// reply or a Neighbor Discovery message, with `body` following the
+    /// ICMPv6 header. Returns `BUSY` if a kernel message or a packet of the
+    /// client is being sent, and `SIZE` if `body` does not fit in the reply
+    /// buffer.
+    pub fn send_message(
+        &self,
+        dst_addr: IPAddr,
+        icmp_header: ICMP6Header,
+        body: &[u8],
+    ) -> Result<(), ErrorCode> {
+        // Empty while the previous message is being sent
+        let mut buf = self.reply_buf.take().ok_or(ErrorCode::BUSY)?;
+        buf.reset();
+        if body.len() > buf.len() {
+            self.reply_buf.replace(buf);
+            return Err(ErrorCode::SIZE);
+        }
+        buf.slice(0..body.len());
+        buf.as_slice().copy_from_slice(body);
+
+        match self
+            .icmp_sender
+            .send(dst_addr, icmp_header, buf, self.net_cap)
+        {
+            Ok(()) => {
+                self.sending_reply.set(true);
+                Ok(())
+            }
+            Err((e, buf)) => {
+                self.reply_buf.replace(buf);
+                Err(e)
+            }
+        }
+    }
+
+    fn send_echo_reply(&self, dst_addr: IPAddr, id: u16, seqno: u16, data: &[u8]) {
+        let mut icmp_header = ICMP6Header::new(ICMP6Type::Type129);
+        icmp_header.set_options(ICMP6HeaderOptions::Type129 { id, seqno });
+        // If the sender is busy, the request is dropped
+        let _ = self.send_message(dst_addr, icmp_header, data);
+    }
+
+    fn receive_error(&self, icmp_header: ICMP6Header, invoking: &[u8]) {
+        if invoking.len() < IP6_HDR_LEN {
+            return;
+        }
+        if let Some((_, invoking_header)) = IP6Header::decode(invoking).done() {
+            self.error_client.map(|client| {
+                client.receive_error(icmp_header, invoking_header, &invoking[IP6_HDR_LEN..])
+            });
+        }
+    }
+}
+
+impl<'a> ICMP6Receiver<'a> for ICMP6RecvStruct<'a> {
+    fn set_client(&self, client: &'a dyn ICMP6RecvClient) {
+        self.client.set(client);
+    }
+
+    fn set_error_client(&self, client: &'a dyn ICMP6ErrorClient) {
+        self.error_client.set(client);
+    }
+
+    fn set_nd_client(&self, client: &'a dyn ICMP6NdClient) {
+        self.nd_client.set(client);
+    }
+}
+
+impl<'a> IP6RecvClient for ICMP6RecvStruct<'a> {
+    fn receive(&self, ip_header: IP6Header, payload: &[u8]) {
+        // Other transports may share the same IP receiver.
+        if ip_header.get_next_header() != ip6_nh::ICMP {
+            return;
+        }
+        let (offset, icmp_header) = match ICMP6Header::decode(payload).done() {
+            Some(decoded) => decoded,
+            // Unsupported message types are silently dropped
+            None => return,
+        };
+        let data = &payload[offset..];
+        match icmp_header.get_options() {
+            ICMP6HeaderOptions::Type128 { id, seqno } => {
+                let src_addr = ip_header.get_src_addr();
+                if icmp_header.get_code() == 0
+                    && self.is_local(&ip_header.get_dst_addr())
+                    && !src_addr.is_multicast()
+                    && !src_addr.is_unspecified()
+                {
+                    self.send_echo_reply(src_addr, id, seqno, data);
+                }
+            }
+            ICMP6HeaderOptions::Type129 { .. } => {
+                self.client
+                    .map(|client| client.receive(ip_header.get_src_addr(), icmp_header, data));
+            }
+            ICMP6HeaderOptions::Type1 { .. }
+            | ICMP6HeaderOptions::Type3 { .. }
+            | ICMP6HeaderOptions::Type4 { .. } => {
+                self.receive_error(icmp_header, data);
+                self.client
+                    .map(|client| client.receive(ip_header.get_src_addr(), icmp_header, data));
+            }
+            ICMP6HeaderOptions::Type133 { .. }
+            | ICMP6HeaderOptions::Type134 { .. }
+            | ICMP6HeaderOptions::Type135 { .. }
+            | ICMP6HeaderOptions::Type136 { .. } => {
+                self.nd_client
+                    .map(|client| client.receive_nd(&ip_header, icmp_header, data));
+            }
+        }
+    }
+}
+
+impl<'a> IP6RecvErrorClient for ICMP6RecvStruct<'a> {
+    fn parameter_problem(&self, header: &IP6Header, code: u8, pointer: u32, packet: &[u8]) {
+        // Errors are not sent in response to packets that do not identify a
+        // single source, nor for packets that were not meant for this node
+        // (RFC 4443, section 2.4).
+        let src_addr = header.get_src_addr();
+        if src_addr.is_multicast()
+            || src_addr.is_unspecified()
+            || !self.is_local(&header.get_dst_addr())
+        {
+            return;
+        }
+        let mut icmp_header = ICMP6Header::new(ICMP6Type::Type4);
+        icmp_header.set_code(code);
+        icmp_header.set_options(ICMP6HeaderOptions::Type4 { pointer });
+        // As much of the invoking packet as fits is included. If the sender
+        // is busy, the error is not reported.
+        let len = cmp::min(packet.len(), self.reply_buf_len);
+        let _ = self.send_message(src_addr, icmp_header, &packet[..len]);
+    }
+}
+
+impl<'a> ICMP6Sender<'a> for ICMP6RecvStruct<'a> {
+    fn set_client(&self, client: &'a dyn ICMP6SendClient) {
+        self.send_client.set(client);
+    }
+
+    fn send(
+        &self,
+        dest: IPAddr,
+        icmp_header: ICMP6Header,
+        buf: SubSliceMut<'static, u8>,
+        net_cap: &'static NetworkCapability,
+    ) -> Result<(), (ErrorCode, SubSliceMut<'static, u8>)> {
+        self.icmp_sender.send(dest, icmp_header, buf, net_cap)
+    }
+}
+
+impl<'a> ICMP6SendClient for ICMP6RecvStruct<'a> {
+    fn send_done(&self, result: Result<(), ErrorCode>, buf: SubSliceMut<'static, u8>) {
+        if self.sending_reply.get() {
+            self.sending_reply.set(false);
+            self.reply_buf.replace(buf);
+        } else {
+            self.send_client.map(|client| client.send_done(result, buf));
+        }
+    }
+}
@@ -7,7 +7,8 @@
 //! an interface for an upper layer to send an ICMPv6 packet, and the
 //! [ICMP6SendClient](trait.ICMP6SendClient.html) trait is implemented by the
 //! upper layer to allow them to receive the `send_done` callback once
-//! transmission has completed.
+//! transmission has completed. The payload buffer passed to `send` is handed
+//! back to the client in the `send_done` callback.
 //!
 //! - Author: Conor McAvity <cmcavity@stanford.edu>
 
@@ -17,15 +18,17 @@ use crate::net::ipv6::ipv6_send::{IP6SendClient, IP6Sender};
 use crate::net::ipv6::TransportHeader;
 use crate::net::network_capabilities::NetworkCapability;
 
-use kernel::utilities::cells::OptionalCell;
+use core::cell::Cell;
+
+use kernel::utilities::cells::{MapCell, OptionalCell};
 use kernel::utilities::leasable_buffer::SubSliceMut;
 use kernel::ErrorCode;
 
 /// A trait for a client of an `ICMP6Sender`.
 pub trait ICMP6SendClient {
     /// A client callback invoked after an ICMP6Sender has completed sending
-    /// a requested packet.
-    fn send_done(&self, result: Result<(), ErrorCode>);
+    /// a requested packet. `buf` is the payload buffer passed to `send`.
+    fn send_done(&self, result: Result<(), ErrorCode>, buf: SubSliceMut<'static, u8>);
 }
 
 /// A trait that defines an interface for sending ICMPv6 packets.
@@ -45,25 +48,29 @@ pub trait ICMP6Sender<'a> {
     ///
     /// `dest` - The destination IP address
     /// `icmp_header` - The ICMPv6 header to be sent
-    /// `buf` - The byte array containing the ICMPv6 payload
+    /// `buf` - The buffer containing the ICMPv6 payload
     ///
     /// # Return Value
     ///
     /// This function returns a code reporting either success or any
-    /// synchronous errors. Note that any asynchronous errors are returned
-    /// via the callback.
+    /// synchronous errors, in which case `buf` is returned with the error.
+    /// `BUSY` is returned if a previous packet is still being sent. Note that
+    /// any asynchronous errors are returned via the callback.
     fn send(
         &self,
         dest: IPAddr,
         icmp_header: ICMP6Header,
-        buf: &'static mut [u8],
+        buf: SubSliceMut<'static, u8>,
         net_cap: &'static NetworkCapability,
-    ) -> Result<(), ErrorCode>;
+    ) -> Result<(), (ErrorCode, SubSliceMut<'static, u8>)>;
 }
 
 /// A struct that implements the `ICMP6Sender` trait.
 pub struct ICMP6SendStruct<'a, T: IP6Sender<'a>> {
     ip_send_struct: &'a T,
+    buf: MapCell<SubSliceMut<'static, u8>>,
+    sending: Cell<bool>,
+    sync_result: OptionalCell<Result<(), ErrorCode>>,
     client: OptionalCell<&'a dyn ICMP6SendClient>,
 }
 
@@ -71,6 +78,9 @@ impl<'a, T: IP6Sender<'a>> ICMP6SendStruct<'a, T> {
     pub fn new(ip_send_struct: &'a T) -> ICMP6SendStruct<'a, T> {
         ICMP6SendStruct {
             ip_send_struct,
+            buf: MapCell::empty(),
+            sending: Cell::new(false),
+            sync_result: OptionalCell::empty(),
             client: OptionalCell::empty(),
         }
     }
@@ -85,14 +95,32 @@ impl<'a, T: IP6Sender<'a>> ICMP6Sender<'a> for ICMP6SendStruct<'a, T> {
         &self,
         dest: IPAddr,
         mut icmp_header: ICMP6Header,
-        buf: &'static mut [u8],
+        buf: SubSliceMut<'static, u8>,
         net_cap: &'static NetworkCapability,
-    ) -> Result<(), ErrorCode> {
+    ) -> Result<(), (ErrorCode, SubSliceMut<'static, u8>)> {
+        if self.buf.is_some() || self.sending.get() {
+            return Err((ErrorCode::BUSY, buf));
+        }
         let total_len = buf.len() + icmp_header.get_hdr_size();
         icmp_header.set_len(total_len as u16);
         let transport_header = TransportHeader::ICMP(icmp_header);
-        self.ip_send_struct
-            .send_to(dest, transport_header, &SubSliceMut::new(buf), net_cap)
+        // The IP layer copies the payload before `send_to` returns, but
+        // it may also complete the send (with an error) before returning.
+        self.sending.set(true);
+        let result = self
+            .ip_send_struct
+            .send_to(dest, transport_header, &buf, net_cap);
+        self.sending.set(false);
+        match (result, self.sync_result.take()) {
+            (Err(e), _) => Err((e, buf)),
+            // The first fragment always needs a transmission, so a send that
+            // completed synchronously failed.
+            (Ok(()), Some(sync_result)) => Err((sync_result.err().unwrap_or(ErrorCode::FAIL), buf)),
+            (Ok(()), None) => {
+                self.buf.replace(buf);
+                Ok(())
+            }
+        }
     }
 }
 
@@ -100,6 +128,10 @@ impl<'a, T: IP6Sender<'a>> IP6SendClient for ICMP6SendStruct<'a, T> {
     /// Forwards callback received from the `IP6Sender` to the
     /// `ICMP6SendClient`.
     fn send_done(&self, result: Result<(), ErrorCode>) {
-        self.client.map(|client| client.send_done(result));
+        if self.sending.get() {
+            self.sync_result.set(result);
+        } else if let Some(buf) = self.buf.take() {
+            self.client.map(|client| client.send_done(result, buf));
+        }
     }
 }
@@ -0,0 +1,637 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Neighbor Discovery (RFC 4861) and stateless address autoconfiguration
+//! (RFC 4862) for hosts on a 6LoWPAN network, so that a node can join a
+//! network behind a border router without configuring its addresses in the
+//! board:
+//!
+//! - Router Solicitations are sent to all routers when started, up to
+//!   `MAX_RTR_SOLICITATIONS` times until a Router Advertisement arrives.
+//! - Router Advertisements configure the default router. An autonomous /64
+//!   Prefix Information option configures a global address made of the prefix
+//!   and the interface identifier derived from the EUI-64 of the node. The
+//!   6LoWPAN Context Options (RFC 6775, section 4.2) of the advertisement
+//!   are stored in the shared `ContextTable`.
+//! - Neighbor Solicitations for a local address are answered with a Neighbor
+//!   Advertisement, and the link-layer addresses in solicitations and
+//!   advertisements are kept in a neighbor cache.
+//! - Router, address and contexts are removed when their lifetimes expire.
+//!
+//! `Ndp` is the `NeighborResolver` of the `IP6SendStruct`s of the node:
+//! packets to cached neighbors are sent to their link-layer address, packets
+//! to on-link destinations to the address derived from their interface
+//! identifier, and everything else to the default router. Packets that are
+//! not link-local use the autoconfigured address as their source.
+//!
+//! Duplicate Address Detection is not performed, as the interface identifier
+//! comes from the globally unique EUI-64. Only one autoconfigured prefix is
+//! supported, and address registration with the router (RFC 6775) is not
+//! implemented, so the router must resolve the node with Neighbor
+//! Solicitations.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let ndp = static_init!(
+//!     Ndp<'static, VirtualMuxAlarm<'static, Rtc>>,
+//!     Ndp::new(ndp_alarm, ctx_table, MacAddress::Long(eui64), local_ip_ifaces)
+//! );
+//! ndp_alarm.set_alarm_client(ndp);
+//! // Pass `ndp` as the resolver of the UDP, TCP and ICMPv6 stacks, then:
+//! icmp_recv.set_nd_client(ndp);
+//! ndp.set_icmp(icmp_recv);
+//! ndp.start();
+//! ```
+
+use crate::net::icmpv6::icmpv6_recv::{ICMP6NdClient, ICMP6RecvStruct};
+use crate::net::icmpv6::{ICMP6Header, ICMP6HeaderOptions, ICMP6Type};
+use crate::net::ieee802154::MacAddress;
+use crate::net::ipv6::ip_utils::IPAddr;
+use crate::net::ipv6::ipv6_send::NeighborResolver;
+use crate::net::ipv6::neighbor_cache::NeighborCache;
+use crate::net::ipv6::IP6Header;
+use crate::net::sixlowpan::sixlowpan_compression::{
+    compute_iid, Context, ContextTable, CONTEXT_TABLE_SIZE,
+};
+
+use core::cell::Cell;
+
+use kernel::hil::time::{self, ConvertTicks, Ticks};
+use kernel::utilities::cells::OptionalCell;
+
+/// Number of Router Solicitations sent when started (RFC 4861,
+/// MAX_RTR_SOLICITATIONS).
+pub const MAX_RTR_SOLICITATIONS: u8 = 3;
+/// Time between Router Solicitations (RFC 4861, RTR_SOLICITATION_INTERVAL).
+const RTR_SOLICITATION_INTERVAL_MS: u64 = 4000;
+/// Longest time between two alarms, which keeps the millisecond clock
+/// correct even if the alarm counter wraps around quickly.
+const MAX_ALARM_MS: u64 = 60_000;
+/// Valid lifetime an advertisement can always shorten an address to (RFC
+/// 4862, section 5.5.3).
+const TWO_HOURS_MS: u64 = 2 * 60 * 60 * 1000;
+
+/// Hop limit of all Neighbor Discovery messages, which also proves to the
+/// receiver that they have not been forwarded.
+const NDP_HOP_LIMIT: u8 = 255;
+/// Length of the fixed part of a Router Advertisement following the ICMPv6
+/// header (reachable time and retransmission timer).
+const RA_FIXED_LEN: usize = 8;
+/// Length of the target address of a Neighbor Solicitation or
+/// Advertisement.
+const TARGET_LEN: usize = 16;
+/// Length of a link-layer address option for an EUI-64 (RFC 4944, section
+/// 8).
+const LL_OPTION_LEN: usize = 16;
+
+mod ndp_option {
+    pub const SOURCE_LL_ADDR: u8 = 1;
+    pub const TARGET_LL_ADDR: u8 = 2;
+    pub const PREFIX_INFO: u8 = 3;
+    pub const SIXLOWPAN_CONTEXT: u8 = 34;
+}
+
+mod prefix_flags {
+    pub const ON_LINK: u8 = 0x80;
+    pub const AUTONOMOUS: u8 = 0x40;
+}
+
+mod context_flags {
+    pub const COMPRESS: u8 = 0x10;
+    pub const CID_MASK: u8 = 0x0f;
+}
+
+mod na_flags {
+    pub const SOLICITED: u32 = 0x4000_0000;
+    pub const OVERRIDE: u32 = 0x2000_0000;
+}
+
+const ALL_NODES_MULTICAST: IPAddr = IPAddr([
+    0xff, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+]);
+
+const ALL_ROUTERS_MULTICAST: IPAddr = IPAddr([
+    0xff, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+]);
+
+/// Returns the 802.15.4 address an interface identifier was derived from
+/// (RFC 4944, section 6 and RFC 6282, section 3.2.2).
+fn mac_from_iid(addr: &IPAddr) -> MacAddress {
+    let iid = &addr.0[8..16];
+    if iid[0..6] == [0, 0, 0, 0xff, 0xfe, 0] {
+        MacAddress::Short(u16::from_be_bytes([iid[6], iid[7]]))
+    } else {
+        let mut long_addr = [0; 8];
+        long_addr.copy_from_slice(iid);
+        long_addr[0] ^= 0x02;
+        MacAddress::Long(long_addr)
+    }
+}
+
+/// Converts a lifetime in seconds to milliseconds, where all ones means
+/// infinity.
+fn lifetime_ms(seconds: u32) -> u64 {
+    if seconds == u32::MAX {
+        u64::MAX
+    } else {
+        seconds as u64 * 1000
+    }
+}
+
+/// Returns an iterator over the options of a Neighbor Discovery message, or
+/// `None` if an option has length zero or runs past the end of the message,
+/// in which case the message must be discarded (RFC 4861, section 4.6).
+fn nd_options(options: &[u8]) -> Option<impl Iterator<Item = (u8, &[u8])>> {
+    let mut rest = options;
+    while !rest.is_empty() {
+        let len = *rest.get(1)? as usize * 8;
+        if len == 0 || len > rest.len() {
+            return None;
+        }
+        rest = &rest[len..];
+    }
+    let mut rest = options;
+    Some(core::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let (option, next) = rest.split_at(rest[1] as usize * 8);
+        rest = next;
+        Some((option[0], option))
+    }))
+}
+
+/// Decodes a source or target link-layer address option.
+fn link_layer_addr(option: &[u8]) -> Option<MacAddress> {
+    match option[1] {
+        1 => Some(MacAddress::Short(u16::from_be_bytes([
+            option[2], option[3],
+        ]))),
+        2 => {
+            let mut long_addr = [0; 8];
+            long_addr.copy_from_slice(&option[2..10]);
+            Some(MacAddress::Long(long_addr))
+        }
+        _ => None,
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Router {
+    addr: IPAddr,
+    expires: u64,
+}
+
+#[derive(Copy, Clone)]
+struct Prefix {
+    /// The autoconfigured address, whose first 64 bits are the prefix.
+    addr: IPAddr,
+    on_link: bool,
+    valid_until: u64,
+}
+
+impl Prefix {
+    fn contains(&self, addr: &IPAddr) -> bool {
+        self.addr.0[0..8] == addr.0[0..8]
+    }
+}
+
+pub struct Ndp<'a, A: time::Alarm<'a>> {
+    alarm: &'a A,
+    icmp: OptionalCell<&'a ICMP6RecvStruct<'a>>,
+    ctx_store: &'a ContextTable,
+    mac_addr: MacAddress,
+    local_addrs: &'a [IPAddr],
+    neighbors: NeighborCache<MacAddress>,
+
+    router: Cell<Option<Router>>,
+    prefix: Cell<Option<Prefix>>,
+    context_expiry: [Cell<Option<u64>>; CONTEXT_TABLE_SIZE],
+    /// Number of Router Solicitations sent, and when to send the next one.
+    solicitations: Cell<u8>,
+    next_solicitation: Cell<Option<u64>>,
+
+    /// Milliseconds since `start`, and the alarm ticks they were counted up
+    /// to.
+    clock_ms: Cell<u64>,
+    clock_ticks: Cell<A::Ticks>,
+}
+
+impl<'a, A: time::Alarm<'a>> Ndp<'a, A> {
+    /// Creates a Neighbor Discovery instance for the node with the 802.15.4
+    /// address `mac_addr`, which should be its EUI-64, and the static
+    /// addresses `local_addrs`.
+    pub fn new(
+        alarm: &'a A,
+        ctx_store: &'a ContextTable,
+        mac_addr: MacAddress,
+        local_addrs: &'a [IPAddr],
+    ) -> Ndp<'a, A> {
+        Ndp {
+            alarm,
+            icmp: OptionalCell::empty(),
+            ctx_store,
+            mac_addr,
+            local_addrs,
+            neighbors: NeighborCache::new(),
+            router: Cell::new(None),
+            prefix: Cell::new(None),
+            context_expiry: Default::default(),
+            solicitations: Cell::new(0),
+            next_solicitation: Cell::new(None),
+            clock_ms: Cell::new(0),
+            clock_ticks: Cell::new(A::Ticks::from(0)),
+        }
+    }
+
+    /// Sets the ICMPv6 layer used to send Neighbor Discovery messages.
+    pub fn set_icmp(&self, icmp: &'a ICMP6RecvStruct<'a>) {
+        self.icmp.set(icmp);
+    }
+
+    /// Starts soliciting routers.
+    pub fn start(&self) {
+        self.clock_ticks.set(self.alarm.now());
+        self.solicitations.set(0);
+        self.next_solicitation.set(Some(self.now_ms()));
+        time::AlarmClient::alarm(self);
+    }
+
+    /// Returns the autoconfigured global address, if any.
+    pub fn autoconfigured_addr(&self) -> Option<IPAddr> {
+        self.prefix.get().map(|prefix| prefix.addr)
+    }
+
+    fn now_ms(&self) -> u64 {
+        let elapsed = self.alarm.now().wrapping_sub(self.clock_ticks.get());
+        let ms = self.alarm.ticks_to_ms(elapsed);
+        // Only count whole milliseconds so that no time is lost.
+        self.clock_ticks.set(
+            self.clock_ticks
+                .get()
+                .wrapping_add(self.alarm.ticks_from_ms(ms)),
+        );
+        self.clock_ms.set(self.clock_ms.get() + ms as u64);
+        self.clock_ms.get()
+    }
+
+    fn is_own_addr(&self, addr: &IPAddr) -> bool {
+        *addr == IPAddr::generate_from_mac(self.mac_addr)
+            || self.local_addrs.iter().any(|local| local == addr)
+            || self.is_local_addr(addr)
+    }
+
+    fn link_layer_option(&self, option_type: u8) -> [u8; LL_OPTION_LEN] {
+        let mut option = [0; LL_OPTION_LEN];
+        option[0] = option_type;
+        option[1] = (LL_OPTION_LEN / 8) as u8;
+        match self.mac_addr {
+            MacAddress::Long(long_addr) => option[2..10].copy_from_slice(&long_addr),
+            MacAddress::Short(short_addr) => {
+                option[1] = 1;
+                option[2..4].copy_from_slice(&short_addr.to_be_bytes());
+            }
+        }
+        option
+    }
+
+    fn send_router_solicitation(&self, now: u64) {
+        let count = self.solicitations.get();
+        if count >= MAX_RTR_SOLICITATIONS {
+            self.next_solicitation.set(None);
+            return;
+        }
+        let option = self.link_layer_option(ndp_option::SOURCE_LL_ADDR);
+        let len = option[1] as usize * 8;
+        let sent = self.icmp.map_or(false, |icmp| {
+            icmp.send_message(
+                ALL_ROUTERS_MULTICAST,
+                ICMP6Header::new(ICMP6Type::Type133),
+                &option[..len],
+            )
+            .is_ok()
+        });
+        // If the ICMPv6 layer is busy, try again at the next interval.
+        if sent {
+            self.solicitations.set(count + 1);
+        }
+        self.next_solicitation
+            .set(Some(now + RTR_SOLICITATION_INTERVAL_MS));
+    }
+
+    fn send_neighbor_advertisement(&self, dst: IPAddr, target: &IPAddr, flags: u32) {
+        let mut body = [0; TARGET_LEN + LL_OPTION_LEN];
+        body[..TARGET_LEN].copy_from_slice(&target.0);
+        let option = self.link_layer_option(ndp_option::TARGET_LL_ADDR);
+        let len = TARGET_LEN + option[1] as usize * 8;
+        body[TARGET_LEN..].copy_from_slice(&option);
+
+        let mut icmp_header = ICMP6Header::new(ICMP6Type::Type136);
+        icmp_header.set_options(ICMP6HeaderOptions::Type136 { flags });
+        // If the ICMPv6 layer is busy, the neighbor solicits again.
+        self.icmp
+            .map(|icmp| icmp.send_message(dst, icmp_header, &body[..len]));
+    }
+
+    fn receive_router_advertisement(
+        &self,
+        ip_header: &IP6Header,
+        router_lifetime: u16,
+        body: &[u8],
+    ) {
+        let src = ip_header.get_src_addr();
+        if !src.is_unicast_link_local() || body.len() < RA_FIXED_LEN {
+            return;
+        }
+        let options = match nd_options(&body[RA_FIXED_LEN..]) {
+            Some(options) => options,
+            None => return,
+        };
+        let now = self.now_ms();
+
+        let mut router_mac = None;
+        for (option_type, option) in options {
+            match option_type {
+                ndp_option::SOURCE_LL_ADDR => router_mac = link_layer_addr(option),
+                ndp_option::PREFIX_INFO => self.receive_prefix(option, now),
+                ndp_option::SIXLOWPAN_CONTEXT => self.receive_context(option, now),
+                _ => {}
+            }
+        }
+        self.neighbors
+            .insert(src, router_mac.unwrap_or_else(|| mac_from_iid(&src)));
+
+        if router_lifetime > 0 {
+            self.router.set(Some(Router {
+                addr: src,
+                expires: now + router_lifetime as u64 * 1000,
+            }));
+            self.next_solicitation.set(None);
+        } else if self.router.get().map_or(false, |router| router.addr == src) {
+            self.router.set(None);
+        }
+        self.schedule(now);
+    }
+
+    /// Handles a Prefix Information option (RFC 4861, section 4.6.2).
+    fn receive_prefix(&self, option: &[u8], now: u64) {
+        if option.len() != 32 {
+            return;
+        }
+        let prefix_len = option[2];
+        let flags = option[3];
+        let valid = lifetime_ms(u32::from_be_bytes([
+            option[4], option[5], option[6], option[7],
+        ]));
+        let preferred = lifetime_ms(u32::from_be_bytes([
+            option[8], option[9], option[10], option[11],
+        ]));
+        let mut addr = IPAddr::new();
+        addr.0[0..8].copy_from_slice(&option[16..24]);
+        // Interface identifiers are 64 bits long, so only /64 prefixes can be
+        // used for autoconfiguration.
+        if flags & prefix_flags::AUTONOMOUS == 0
+            || prefix_len != 64
+            || preferred > valid
+            || addr.is_unicast_link_local()
+        {
+            return;
+        }
+        addr.0[8..16].copy_from_slice(&compute_iid(&self.mac_addr));
+        let on_link = flags & prefix_flags::ON_LINK != 0;
+
+        match self.prefix.get() {
+            Some(prefix) if prefix.addr == addr => {
+                // Keep an unauthenticated advertisement from shortening the
+                // lifetime below two hours (RFC 4862, section 5.5.3).
+                let new_valid_until = now.saturating_add(valid);
+                let valid_until = if valid > TWO_HOURS_MS || new_valid_until > prefix.valid_until {
+                    new_valid_until
+                } else if prefix.valid_until - now <= TWO_HOURS_MS {
+                    prefix.valid_until
+                } else {
+                    now + TWO_HOURS_MS
+                };
+                self.prefix.set(Some(Prefix {
+                    addr,
+                    on_link,
+                    valid_until,
+                }));
+            }
+            // Only a single prefix is supported.
+            Some(_) => {}
+            None => {
+                if valid > 0 {
+                    self.prefix.set(Some(Prefix {
+                        addr,
+                        on_link,
+                        valid_until: now.saturating_add(valid),
+                    }));
+                }
+            }
+        }
+    }
+
+    /// Handles a 6LoWPAN Context Option (RFC 6775, section 4.2).
+    fn receive_context(&self, option: &[u8], now: u64) {
+        if option.len() < 8 {
+            return;
+        }
+        let context_len = option[2];
+        let prefix_bytes = (context_len as usize + 7) / 8;
+        if context_len > 128 || option.len() < 8 + prefix_bytes {
+            return;
+        }
+        let id = option[3] & context_flags::CID_MASK;
+        let lifetime_minutes = u16::from_be_bytes([option[6], option[7]]);
+        if lifetime_minutes == 0 {
+            self.ctx_store.remove_context(id);
+            self.context_expiry[id as usize].set(None);
+            return;
+        }
+        let mut prefix = [0; 16];
+        prefix[..prefix_bytes].copy_from_slice(&option[8..8 + prefix_bytes]);
+        let context = Context {
+            prefix,
+            prefix_len: context_len,
+            id,
+            compress: option[3] & context_flags::COMPRESS != 0,
+        };
+        if self.ctx_store.set_context(context).is_ok() {
+            self.context_expiry[id as usize].set(Some(now + lifetime_minutes as u64 * 60_000));
+        }
+    }
+
+    fn receive_neighbor_solicitation(&self, ip_header: &IP6Header, body: &[u8]) {
+        if body.len() < TARGET_LEN {
+            return;
+        }
+        let mut target = IPAddr::new();
+        target.0.copy_from_slice(&body[..TARGET_LEN]);
+        if target.is_multicast() {
+            return;
+        }
+        let mut source_mac = None;
+        match nd_options(&body[TARGET_LEN..]) {
+            Some(options) => {
+                for (option_type, option) in options {
+                    if option_type == ndp_option::SOURCE_LL_ADDR {
+                        source_mac = link_layer_addr(option);
+                    }
+                }
+            }
+            None => return,
+        }
+        if !self.is_own_addr(&target) {
+            return;
+        }
+
+        let src = ip_header.get_src_addr();
+        if src.is_unspecified() {
+            // Duplicate Address Detection by another node
+            if source_mac.is_none() {
+                self.send_neighbor_advertisement(ALL_NODES_MULTICAST, &target, na_flags::OVERRIDE);
+            }
+        } else {
+            if let Some(mac) = source_mac {
+                self.neighbors.insert(src, mac);
+            }
+            self.send_neighbor_advertisement(
+                src,
+                &target,
+                na_flags::SOLICITED | na_flags::OVERRIDE,
+            );
+        }
+    }
+
+    fn receive_neighbor_advertisement(&self, body: &[u8]) {
+        if body.len() < TARGET_LEN {
+            return;
+        }
+        let mut target = IPAddr::new();
+        target.0.copy_from_slice(&body[..TARGET_LEN]);
+        if target.is_multicast() {
+            return;
+        }
+        if let Some(options) = nd_options(&body[TARGET_LEN..]) {
+            for (option_type, option) in options {
+                if option_type == ndp_option::TARGET_LL_ADDR {
+                    if let Some(mac) = link_layer_addr(option) {
+                        // No solicitations are sent, so only update entries
+                        // learned from other messages.
+                        self.neighbors.update(target, mac);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Removes the router, address and contexts whose lifetime ended.
+    fn expire(&self, now: u64) {
+        if let Some(router) = self.router.get() {
+            if router.expires <= now {
+                self.router.set(None);
+                self.solicitations.set(0);
+                self.next_solicitation.set(Some(now));
+            }
+        }
+        if let Some(prefix) = self.prefix.get() {
+            if prefix.valid_until <= now {
+                self.prefix.set(None);
+            }
+        }
+        for (id, expiry) in self.context_expiry.iter().enumerate() {
+            if expiry.get().map_or(false, |expires| expires <= now) {
+                expiry.set(None);
+                self.ctx_store.remove_context(id as u8);
+            }
+        }
+    }
+
+    /// Sets the alarm for the next lifetime or solicitation to expire.
+    fn schedule(&self, now: u64) {
+        let deadlines = [
+            self.router.get().map(|router| router.expires),
+            self.prefix.get().map(|prefix| prefix.valid_until),
+            self.next_solicitation.get(),
+        ];
+        let next = deadlines
+            .into_iter()
+            .flatten()
+            .chain(self.context_expiry.iter().filter_map(|expiry| expiry.get()))
+            .fold(now + MAX_ALARM_MS, u64::min);
+        let delay = next.saturating_sub(now);
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(delay as u32));
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> time::AlarmClient for Ndp<'a, A> {
+    fn alarm(&self) {
+        let now = self.now_ms();
+        self.expire(now);
+        if self
+            .next_solicitation
+            .get()
+            .map_or(false, |next| next <= now)
+        {
+            self.send_router_solicitation(now);
+        }
+        self.schedule(now);
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> ICMP6NdClient for Ndp<'a, A> {
+    fn receive_nd(&self, ip_header: &IP6Header, icmp_header: ICMP6Header, body: &[u8]) {
+        // Messages that may have been forwarded by a router are invalid
+        // (RFC 4861, section 6.1 and 7.1).
+        if ip_header.get_hop_limit() != NDP_HOP_LIMIT || icmp_header.get_code() != 0 {
+            return;
+        }
+        match icmp_header.get_options() {
+            ICMP6HeaderOptions::Type134 {
+                router_lifetime, ..
+            } => self.receive_router_advertisement(ip_header, router_lifetime, body),
+            ICMP6HeaderOptions::Type135 { .. } => {
+                self.receive_neighbor_solicitation(ip_header, body)
+            }
+            ICMP6HeaderOptions::Type136 { .. } => self.receive_neighbor_advertisement(body),
+            // Router Solicitations are only handled by routers.
+            _ => {}
+        }
+    }
+
+    fn is_local_addr(&self, addr: &IPAddr) -> bool {
+        self.autoconfigured_addr() == Some(*addr)
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> NeighborResolver for Ndp<'a, A> {
+    fn next_hop(&self, dst: &IPAddr) -> Option<MacAddress> {
+        if let Some(mac) = self.neighbors.lookup(dst) {
+            return Some(mac);
+        }
+        let on_link = self
+            .prefix
+            .get()
+            .map_or(false, |prefix| prefix.on_link && prefix.contains(dst));
+        if dst.is_unicast_link_local() || on_link {
+            return Some(mac_from_iid(dst));
+        }
+        self.router
+            .get()
+            .and_then(|router| self.neighbors.lookup(&router.addr))
+    }
+
+    fn source_addr(&self, dst: &IPAddr) -> Option<IPAddr> {
+        // Link-local and link-scope multicast destinations use the
+        // link-local address.
+        if dst.is_unicast_link_local() || (dst.is_multicast() && dst.0[1] & 0x0f <= 2) {
+            return None;
+        }
+        self.autoconfigured_addr()
+    }
+}
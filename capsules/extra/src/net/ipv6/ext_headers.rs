@@ -0,0 +1,386 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Processing of the IPv6 extension header chain of received packets, as
+//! described in RFC 8200, section 4.
+//!
+//! [walk](fn.walk.html) follows the Next Header fields from the IPv6 header
+//! to the upper-layer header (UDP, TCP or ICMPv6), processing the headers in
+//! between:
+//!
+//! - Hop-by-Hop and Destination Options headers are checked for options
+//!   that must be understood. Unrecognized options are skipped or cause the
+//!   packet to be discarded, depending on the two high-order bits of the
+//!   option type.
+//! - Routing headers with segments left are rejected, since this node is not
+//!   a router and recognizes no routing type.
+//! - A Fragment header ends the walk, so the fragment can be reassembled
+//!   before the rest of the chain is processed.
+//! - Any other next header is unrecognized.
+//!
+//! Packets that are discarded may have to be reported to their source with
+//! an ICMPv6 Parameter Problem message. The pointer of the message is the
+//! offset of the erroneous field from the start of the packet.
+
+use crate::net::ipv6::ip_utils::ip6_nh;
+
+/// Codes of the ICMPv6 Parameter Problem message (RFC 4443, section 3.4).
+pub mod param_problem {
+    pub const ERRONEOUS_HEADER_FIELD: u8 = 0;
+    pub const UNRECOGNIZED_NEXT_HEADER: u8 = 1;
+    pub const UNRECOGNIZED_OPTION: u8 = 2;
+}
+
+/// Length of an IPv6 header without extension headers.
+pub const IP6_HDR_LEN: usize = 40;
+/// Offset of the Next Header field in the IPv6 header.
+pub const IP6_NEXT_HEADER_OFFSET: usize = 6;
+/// Offset of the Payload Length field in the IPv6 header.
+pub const IP6_PAYLOAD_LEN_OFFSET: usize = 4;
+/// Length of the Fragment header.
+pub const FRAGMENT_HDR_LEN: usize = 8;
+
+mod option_type {
+    pub const PAD1: u8 = 0;
+    pub const PADN: u8 = 1;
+}
+
+/// The fields of a Fragment header.
+#[derive(Copy, Clone, Debug)]
+pub struct FragmentHeader {
+    /// The first header of the fragmentable part of the original packet.
+    pub next_header: u8,
+    /// Offset of the fragment data in the fragmentable part, in bytes.
+    pub offset: usize,
+    /// Whether more fragments follow.
+    pub more: bool,
+    pub id: u32,
+}
+
+/// The result of walking the extension header chain.
+#[derive(Copy, Clone, Debug)]
+pub enum ExtHeaders {
+    /// The upper-layer header `next_header` starts at `offset`.
+    UpperLayer { next_header: u8, offset: usize },
+    /// The packet is a fragment. The Fragment header starts at
+    /// `header_offset`, and the fragment data follows it.
+    Fragment {
+        header: FragmentHeader,
+        header_offset: usize,
+    },
+    /// The chain ends with No Next Header, so there is nothing to deliver.
+    NoNextHeader,
+    /// The packet must be discarded. If `report` is set, the source should
+    /// be sent a Parameter Problem message with its code and pointer.
+    Discard { report: Option<(u8, u32)> },
+}
+
+/// Returns the error to discard the packet with when the field at `pointer`
+/// is erroneous. Errors are not reported for multicast packets, except for
+/// unrecognized options that request it (RFC 4443, section 2.4).
+fn discard(code: u8, pointer: usize, report: bool) -> ExtHeaders {
+    ExtHeaders::Discard {
+        report: if report {
+            Some((code, pointer as u32))
+        } else {
+            None
+        },
+    }
+}
+
+/// Checks the options of a Hop-by-Hop or Destination Options header
+/// starting at `start` in `data`, where `start` is `base` bytes into the
+/// packet. Returns the error to discard the packet with, if any.
+fn check_options(
+    data: &[u8],
+    start: usize,
+    len: usize,
+    base: usize,
+    multicast_dst: bool,
+) -> Option<ExtHeaders> {
+    // The options follow the Next Header and Hdr Ext Len fields.
+    let mut i = start + 2;
+    let end = start + len;
+    while i < end {
+        let opt_type = data[i];
+        if opt_type == option_type::PAD1 {
+            i += 1;
+            continue;
+        }
+        if i + 2 > end || i + 2 + data[i + 1] as usize > end {
+            return Some(ExtHeaders::Discard { report: None });
+        }
+        let opt_len = 2 + data[i + 1] as usize;
+        if opt_type != option_type::PADN {
+            // No other options are recognized, so the high-order bits of
+            // the type decide what to do.
+            match opt_type >> 6 {
+                0b00 => {}
+                0b01 => return Some(ExtHeaders::Discard { report: None }),
+                0b10 => return Some(discard(param_problem::UNRECOGNIZED_OPTION, base + i, true)),
+                _ => {
+                    return Some(discard(
+                        param_problem::UNRECOGNIZED_OPTION,
+                        base + i,
+                        !multicast_dst,
+                    ))
+                }
+            }
+        }
+        i += opt_len;
+    }
+    None
+}
+
+/// Walks the extension header chain in `data`, which starts with a header
+/// of type `next_header`.
+///
+/// # Arguments
+///
+/// `data` - The headers and payload to walk
+/// `next_header` - The type of the first header in `data`
+/// `base` - The offset of `data` from the start of the packet, used for the
+/// pointers of Parameter Problem messages. This is `IP6_HDR_LEN` for the
+/// payload of a packet.
+/// `nh_pointer` - The offset of the field holding `next_header` from the
+/// start of the packet
+/// `multicast_dst` - Whether the packet was sent to a multicast address
+pub fn walk(
+    data: &[u8],
+    mut next_header: u8,
+    base: usize,
+    mut nh_pointer: usize,
+    multicast_dst: bool,
+) -> ExtHeaders {
+    let mut offset = 0;
+    loop {
+        match next_header {
+            ip6_nh::UDP | ip6_nh::TCP | ip6_nh::ICMP => {
+                return ExtHeaders::UpperLayer {
+                    next_header,
+                    offset,
+                };
+            }
+            ip6_nh::NO_NEXT => return ExtHeaders::NoNextHeader,
+            ip6_nh::HOP_OPTS | ip6_nh::DST_OPTS | ip6_nh::ROUTING => {
+                // The Hop-by-Hop Options header is only allowed right after
+                // the IPv6 header.
+                if next_header == ip6_nh::HOP_OPTS && base + offset != IP6_HDR_LEN {
+                    return discard(
+                        param_problem::UNRECOGNIZED_NEXT_HEADER,
+                        nh_pointer,
+                        !multicast_dst,
+                    );
+                }
+                if offset + 8 > data.len() {
+                    return ExtHeaders::Discard { report: None };
+                }
+                let len = (data[offset + 1] as usize + 1) * 8;
+                if offset + len > data.len() {
+                    return ExtHeaders::Discard { report: None };
+                }
+                if next_header == ip6_nh::ROUTING {
+                    let segments_left = data[offset + 3];
+                    if segments_left != 0 {
+                        // Point to the Routing Type field.
+                        return discard(
+                            param_problem::ERRONEOUS_HEADER_FIELD,
+                            base + offset + 2,
+                            !multicast_dst,
+                        );
+                    }
+                } else if let Some(error) = check_options(data, offset, len, base, multicast_dst) {
+                    return error;
+                }
+                nh_pointer = base + offset;
+                next_header = data[offset];
+                offset += len;
+            }
+            ip6_nh::FRAGMENT => {
+                if offset + FRAGMENT_HDR_LEN > data.len() {
+                    return ExtHeaders::Discard { report: None };
+                }
+                let fragment = &data[offset..offset + FRAGMENT_HDR_LEN];
+                let offset_flags = u16::from_be_bytes([fragment[2], fragment[3]]);
+                return ExtHeaders::Fragment {
+                    header: FragmentHeader {
+                        next_header: fragment[0],
+                        offset: (offset_flags & 0xfff8) as usize,
+                        more: offset_flags & 0x1 != 0,
+                        id: u32::from_be_bytes([
+                            fragment[4],
+                            fragment[5],
+                            fragment[6],
+                            fragment[7],
+                        ]),
+                    },
+                    header_offset: offset,
+                };
+            }
+            _ => {
+                return discard(
+                    param_problem::UNRECOGNIZED_NEXT_HEADER,
+                    nh_pointer,
+                    !multicast_dst,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Walks `data` as the payload of a packet with next header
+    /// `next_header`.
+    fn walk_payload(data: &[u8], next_header: u8, multicast_dst: bool) -> ExtHeaders {
+        walk(
+            data,
+            next_header,
+            IP6_HDR_LEN,
+            IP6_NEXT_HEADER_OFFSET,
+            multicast_dst,
+        )
+    }
+
+    fn assert_upper_layer(result: ExtHeaders, expected_nh: u8, expected_offset: usize) {
+        match result {
+            ExtHeaders::UpperLayer {
+                next_header,
+                offset,
+            } => {
+                assert_eq!(next_header, expected_nh);
+                assert_eq!(offset, expected_offset);
+            }
+            other => panic!("expected upper layer, got {:?}", other),
+        }
+    }
+
+    fn assert_discard(result: ExtHeaders, expected: Option<(u8, u32)>) {
+        match result {
+            ExtHeaders::Discard { report } => assert_eq!(report, expected),
+            other => panic!("expected discard, got {:?}", other),
+        }
+    }
+
+    /// A Hop-by-Hop or Destination Options header followed by `next_header`,
+    /// with a single option of type `opt_type` and 4 bytes of data.
+    fn options_header(next_header: u8, opt_type: u8) -> [u8; 8] {
+        [next_header, 0, opt_type, 4, 0, 0, 0, 0]
+    }
+
+    #[test]
+    fn test_no_extension_headers() {
+        assert_upper_layer(walk_payload(&[0; 8], ip6_nh::UDP, false), ip6_nh::UDP, 0);
+        assert!(matches!(
+            walk_payload(&[], ip6_nh::NO_NEXT, false),
+            ExtHeaders::NoNextHeader
+        ));
+    }
+
+    #[test]
+    fn test_options_headers_skipped() {
+        let mut data = [0; 24];
+        // Hop-by-Hop with Pad1 and PadN, then Destination Options with an
+        // option that may be skipped.
+        data[..8].copy_from_slice(&[ip6_nh::DST_OPTS, 0, 0, 1, 3, 0, 0, 0]);
+        data[8..16].copy_from_slice(&options_header(ip6_nh::ICMP, 0x3e));
+        assert_upper_layer(
+            walk_payload(&data, ip6_nh::HOP_OPTS, false),
+            ip6_nh::ICMP,
+            16,
+        );
+    }
+
+    #[test]
+    fn test_unrecognized_options() {
+        // Discard silently
+        let data = options_header(ip6_nh::UDP, 0x7e);
+        assert_discard(walk_payload(&data, ip6_nh::DST_OPTS, false), None);
+
+        // Discard and report, even to multicast destinations
+        let data = options_header(ip6_nh::UDP, 0xbe);
+        let report = Some((param_problem::UNRECOGNIZED_OPTION, 42));
+        assert_discard(walk_payload(&data, ip6_nh::DST_OPTS, false), report);
+        assert_discard(walk_payload(&data, ip6_nh::DST_OPTS, true), report);
+
+        // Discard and report only to unicast destinations
+        let data = options_header(ip6_nh::UDP, 0xfe);
+        assert_discard(walk_payload(&data, ip6_nh::DST_OPTS, false), report);
+        assert_discard(walk_payload(&data, ip6_nh::DST_OPTS, true), None);
+    }
+
+    #[test]
+    fn test_option_overruns_header() {
+        let data = [ip6_nh::UDP, 0, 0x3e, 5, 0, 0, 0, 0];
+        assert_discard(walk_payload(&data, ip6_nh::DST_OPTS, false), None);
+    }
+
+    #[test]
+    fn test_truncated_header() {
+        assert_discard(
+            walk_payload(&[ip6_nh::UDP, 0, 1, 2], ip6_nh::HOP_OPTS, false),
+            None,
+        );
+        // Hdr Ext Len claims 16 bytes
+        let data = [ip6_nh::UDP, 1, 1, 4, 0, 0, 0, 0];
+        assert_discard(walk_payload(&data, ip6_nh::HOP_OPTS, false), None);
+        assert_discard(walk_payload(&[0; 7], ip6_nh::FRAGMENT, false), None);
+    }
+
+    #[test]
+    fn test_hop_by_hop_not_first() {
+        let mut data = [0; 16];
+        data[..8].copy_from_slice(&options_header(ip6_nh::HOP_OPTS, option_type::PADN));
+        data[8..].copy_from_slice(&options_header(ip6_nh::UDP, option_type::PADN));
+        // The pointer is the Next Header field of the Destination Options
+        // header.
+        let report = Some((param_problem::UNRECOGNIZED_NEXT_HEADER, 40));
+        assert_discard(walk_payload(&data, ip6_nh::DST_OPTS, false), report);
+        assert_discard(walk_payload(&data, ip6_nh::DST_OPTS, true), None);
+    }
+
+    #[test]
+    fn test_routing_header() {
+        let mut data = [ip6_nh::UDP, 0, 4, 0, 0, 0, 0, 0];
+        assert_upper_layer(walk_payload(&data, ip6_nh::ROUTING, false), ip6_nh::UDP, 8);
+
+        // Segments left: point to the Routing Type field.
+        data[3] = 1;
+        let report = Some((param_problem::ERRONEOUS_HEADER_FIELD, 42));
+        assert_discard(walk_payload(&data, ip6_nh::ROUTING, false), report);
+    }
+
+    #[test]
+    fn test_unrecognized_next_header() {
+        let report = Some((param_problem::UNRECOGNIZED_NEXT_HEADER, 6));
+        assert_discard(walk_payload(&[0; 8], ip6_nh::MOBILITY, false), report);
+
+        let data = options_header(ip6_nh::MOBILITY, option_type::PADN);
+        let report = Some((param_problem::UNRECOGNIZED_NEXT_HEADER, 40));
+        assert_discard(walk_payload(&data, ip6_nh::HOP_OPTS, false), report);
+    }
+
+    #[test]
+    fn test_fragment_header() {
+        let mut data = [0; 16];
+        data[..8].copy_from_slice(&options_header(ip6_nh::FRAGMENT, option_type::PADN));
+        // Offset 0x123 blocks, more fragments, id 0xdeadbeef
+        data[8..].copy_from_slice(&[ip6_nh::UDP, 0, 0x09, 0x19, 0xde, 0xad, 0xbe, 0xef]);
+        match walk_payload(&data, ip6_nh::HOP_OPTS, false) {
+            ExtHeaders::Fragment {
+                header,
+                header_offset,
+            } => {
+                assert_eq!(header_offset, 8);
+                assert_eq!(header.next_header, ip6_nh::UDP);
+                assert_eq!(header.offset, 0x123 * 8);
+                assert!(header.more);
+                assert_eq!(header.id, 0xdeadbeef);
+            }
+            other => panic!("expected fragment, got {:?}", other),
+        }
+    }
+}
@@ -9,6 +9,7 @@
 use crate::net::icmpv6::{ICMP6Header, ICMP6HeaderOptions};
 use crate::net::ieee802154::MacAddress;
 use crate::net::ipv6::IP6Header;
+use crate::net::tcp::TCPHeader;
 use crate::net::udp::UDPHeader;
 
 #[derive(Copy, Clone, PartialEq)]
@@ -184,15 +185,22 @@ pub fn compute_udp_checksum(
     sum as u16 //Return result as u16 in host byte order */
 }
 
+/// Computes the ICMPv6 checksum of an outgoing message. The checksum covers
+/// the IPv6 pseudo-header, the ICMPv6 header (with a zero checksum field) and
+/// the payload. The `len` field of the `ICMP6Header` must already be set to
+/// the length of the header plus payload. The result is in host byte order.
 pub fn compute_icmp_checksum(
     ipv6_header: &IP6Header,
     icmp_header: &ICMP6Header,
     payload: &[u8],
 ) -> u16 {
-    let mut sum: u32 = 0;
-
-    // add ipv6 pseudo-header
-    sum += compute_ipv6_ph_sum(ipv6_header);
+    let icmp_len = icmp_header.get_len();
+    let mut sum = compute_upper_layer_ph_sum(
+        &ipv6_header.src_addr,
+        &ipv6_header.dst_addr,
+        icmp_len as u32,
+        ip6_nh::ICMP,
+    );
 
     // add type and code
     let msb = (icmp_header.get_type_as_int() as u32) << 8;
@@ -201,7 +209,12 @@ pub fn compute_icmp_checksum(
 
     // add options
     match icmp_header.get_options() {
-        ICMP6HeaderOptions::Type1 { unused } | ICMP6HeaderOptions::Type3 { unused } => {
+        ICMP6HeaderOptions::Type1 { unused }
+        | ICMP6HeaderOptions::Type3 { unused }
+        | ICMP6HeaderOptions::Type4 { pointer: unused }
+        | ICMP6HeaderOptions::Type133 { unused }
+        | ICMP6HeaderOptions::Type135 { unused }
+        | ICMP6HeaderOptions::Type136 { flags: unused } => {
             sum += unused >> 16; // upper 16 bits
             sum += unused & 0xffff; // lower 16 bits
         }
@@ -209,22 +222,115 @@ pub fn compute_icmp_checksum(
             sum += id as u32;
             sum += seqno as u32;
         }
+        ICMP6HeaderOptions::Type134 {
+            cur_hop_limit,
+            flags,
+            router_lifetime,
+        } => {
+            sum += ((cur_hop_limit as u32) << 8) + flags as u32;
+            sum += router_lifetime as u32;
+        }
     }
 
     // add icmp payload
-    let payload_len = icmp_header.get_len() - icmp_header.get_hdr_size() as u16;
-    sum += compute_sum(payload, payload_len);
+    let payload_len = icmp_len as usize - icmp_header.get_hdr_size();
+    sum += compute_sum_padded(&payload[..payload_len]);
 
-    // carry overflow
-    while sum > 0xffff {
-        let sum_upper = sum >> 16;
-        let sum_lower = sum & 0xffff;
-        sum += sum_upper + sum_lower;
-    }
+    !fold_sum(sum)
+}
 
-    sum = !sum;
-    sum &= 0xffff;
+/// Verifies the checksum of a received ICMPv6 message, where `message`
+/// contains the full ICMPv6 header and payload exactly as received. Returns
+/// `true` if the checksum is correct.
+pub fn verify_icmp_checksum(ip6_header: &IP6Header, message: &[u8]) -> bool {
+    let mut sum = compute_upper_layer_ph_sum(
+        &ip6_header.src_addr,
+        &ip6_header.dst_addr,
+        message.len() as u32,
+        ip6_nh::ICMP,
+    );
+    sum += compute_sum_padded(message);
+    fold_sum(sum) == 0xffff
+}
+
+/// Computes the TCP checksum of an outgoing segment. The checksum covers the
+/// IPv6 pseudo-header, the encoded TCP header (with a zero checksum field) and
+/// the payload. The `len` field of the `TCPHeader` must already be set to the
+/// length of the header plus payload. The result is in host byte order.
+pub fn compute_tcp_checksum(ip6_header: &IP6Header, tcp_header: &TCPHeader, payload: &[u8]) -> u16 {
+    let tcp_len = tcp_header.get_len();
+    let mut sum = compute_upper_layer_ph_sum(
+        &ip6_header.src_addr,
+        &ip6_header.dst_addr,
+        tcp_len as u32,
+        ip6_nh::TCP,
+    );
+
+    // Sum over the header as it will appear on the wire, minus the checksum
+    let mut hdr = *tcp_header;
+    hdr.set_cksum(0);
+    let mut hdr_buf = [0u8; 60];
+    let hdr_len = match hdr.encode(&mut hdr_buf, 0).done() {
+        Some((off, _)) => off,
+        None => 0,
+    };
+    sum += compute_sum_padded(&hdr_buf[..hdr_len]);
+
+    let payload_len = tcp_len as usize - hdr_len;
+    sum += compute_sum_padded(&payload[..payload_len]);
+
+    !fold_sum(sum)
+}
+
+/// Verifies the checksum of a received TCP segment, where `segment` contains
+/// the full TCP header and payload exactly as received. Returns `true` if the
+/// checksum is correct.
+pub fn verify_tcp_checksum(ip6_header: &IP6Header, segment: &[u8]) -> bool {
+    let mut sum = compute_upper_layer_ph_sum(
+        &ip6_header.src_addr,
+        &ip6_header.dst_addr,
+        segment.len() as u32,
+        ip6_nh::TCP,
+    );
+    sum += compute_sum_padded(segment);
+    fold_sum(sum) == 0xffff
+}
 
+/// Computes the (unfolded) sum of the IPv6 pseudo-header used by upper-layer
+/// checksums, as described in RFC 8200, section 8.1.
+pub fn compute_upper_layer_ph_sum(
+    src_addr: &IPAddr,
+    dst_addr: &IPAddr,
+    upper_layer_len: u32,
+    next_header: u8,
+) -> u32 {
+    let mut sum = compute_sum_padded(&src_addr.0);
+    sum += compute_sum_padded(&dst_addr.0);
+    sum += upper_layer_len >> 16;
+    sum += upper_layer_len & 0xffff;
+    sum += next_header as u32;
+    sum
+}
+
+/// Sums a buffer as a sequence of big-endian 16 bit words, padding an odd
+/// trailing byte with zero.
+pub fn compute_sum_padded(buf: &[u8]) -> u32 {
+    let mut sum: u32 = 0;
+    let mut chunks = buf.chunks_exact(2);
+    for word in &mut chunks {
+        sum += ((word[0] as u32) << 8) | word[1] as u32;
+    }
+    if let [last] = chunks.remainder() {
+        sum += (*last as u32) << 8;
+    }
+    sum
+}
+
+/// Folds the carries of a one's complement sum back into the lower 16 bits.
+pub fn fold_sum(mut sum: u32) -> u16 {
+    while sum > 0xffff {
+        sum = (sum >> 16) + (sum & 0xffff);
+    }
     sum as u16
 }
 
@@ -245,7 +351,7 @@ pub fn compute_ipv6_ph_sum(ip6_header: &IP6Header) -> u32 {
         i += 2;
     }
 
-    sum += ip6_header.payload_len as u32;
+    sum += ip6_header.get_payload_len() as u32;
     sum += ip6_header.next_header as u32;
 
     sum
@@ -257,7 +363,11 @@ pub fn compute_sum(buf: &[u8], len: u16) -> u32 {
     let mut i: usize = 0;
     while i < (len as usize) {
         let msb = (buf[i] as u32) << 8;
-        let lsb = buf[i + 1] as u32;
+        let lsb = if i + 1 < len as usize {
+            buf[i + 1] as u32
+        } else {
+            0
+        };
         sum += msb + lsb;
         i += 2;
     }
@@ -0,0 +1,645 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! This file contains an IPv6 interface over Ethernet. The
+//! [IP6EthernetStruct](struct.IP6EthernetStruct.html) implements both the
+//! `IP6Sender` and the `IP6Receiver` traits on top of any adapter
+//! implementing the `hil::ethernet::EthernetAdapter` interface, so the
+//! transport layers (such as the UDP muxes) can be used unmodified on wired or
+//! virtual (e.g. VirtIO) network cards instead of 6LoWPAN.
+//!
+//! Link-layer addresses are resolved with the Neighbor Solicitation and
+//! Neighbor Advertisement messages of Neighbor Discovery (RFC 4861), which
+//! take the role ARP plays for IPv4:
+//!
+//! - Outgoing unicast packets are sent to the link-layer address of their
+//!   next hop, as found in a small neighbor cache. If the next hop is not in
+//!   the cache, a Neighbor Solicitation is multicast to its solicited-node
+//!   address and the packet is held until an advertisement arrives, or
+//!   dropped with `NOACK` after `MAX_MULTICAST_SOLICIT` attempts.
+//! - Neighbor Solicitations for any of the local addresses are answered with
+//!   a Neighbor Advertisement, and the sender is added to the cache.
+//! - Multicast packets are mapped to `33:33:xx:xx:xx:xx` (RFC 2464).
+//!
+//! Destinations sharing a /64 prefix with a local address, and link-local
+//! destinations, are considered on-link. Everything else is sent through the
+//! default router, if one is configured, or resolved directly otherwise.
+//! Router Discovery and Duplicate Address Detection are not implemented, so
+//! the local addresses must be configured statically.
+//!
+//! Like `IP6SendStruct`, only one packet is sent at a time. Neighbor
+//! Discovery messages use a separate, smaller frame buffer so they can be
+//! answered while a packet is pending.
+
+use crate::net::ieee802154::MacAddress;
+use crate::net::ipv6::ip_utils::{
+    compute_sum_padded, compute_upper_layer_ph_sum, fold_sum, ip6_nh, IPAddr,
+};
+use crate::net::ipv6::ipv6_recv::{IP6Receiver, IP6RecvClient};
+use crate::net::ipv6::ipv6_send::{IP6SendClient, IP6Sender};
+use crate::net::ipv6::neighbor_cache::NeighborCache;
+use crate::net::ipv6::{IP6Header, IP6Packet, TransportHeader};
+use crate::net::network_capabilities::{IpVisibilityCapability, NetworkCapability};
+
+use core::cell::Cell;
+
+use kernel::debug;
+use kernel::hil::ethernet::{
+    EthernetAdapter, EthernetAdapterClient, ETHERNET_HDR_LEN, MAC_ADDR_LEN,
+};
+use kernel::hil::time::{self, ConvertTicks};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::ErrorCode;
+
+/// EtherType of IPv6 (RFC 2464).
+pub const ETHERTYPE_IPV6: u16 = 0x86dd;
+
+/// Size of the buffer needed for Neighbor Discovery messages: an Ethernet
+/// and IPv6 header, followed by a Neighbor Solicitation or Advertisement
+/// carrying a single link-layer address option.
+pub const NDP_FRAME_LEN: usize = ETHERNET_HDR_LEN + IP6_HDR_LEN + NDP_MSG_LEN;
+
+/// Time between Neighbor Solicitations for an unresolved address (RFC 4861,
+/// RETRANS_TIMER).
+const RETRANS_TIMER_MS: u32 = 1000;
+/// Number of Neighbor Solicitations sent before giving up on an address
+/// (RFC 4861, MAX_MULTICAST_SOLICIT).
+const MAX_MULTICAST_SOLICIT: u8 = 3;
+
+const IP6_HDR_LEN: usize = 40;
+/// Length of a Neighbor Solicitation or Advertisement with a link-layer
+/// address option for an Ethernet address.
+const NDP_MSG_LEN: usize = 24 + NDP_LL_OPTION_LEN;
+const NDP_LL_OPTION_LEN: usize = 8;
+/// Hop limit of all Neighbor Discovery messages, which also proves to the
+/// receiver that they have not been forwarded.
+const NDP_HOP_LIMIT: u8 = 255;
+
+mod icmp_type {
+    pub const NEIGHBOR_SOLICITATION: u8 = 135;
+    pub const NEIGHBOR_ADVERTISEMENT: u8 = 136;
+}
+
+mod ndp_option {
+    pub const SOURCE_LL_ADDR: u8 = 1;
+    pub const TARGET_LL_ADDR: u8 = 2;
+}
+
+mod na_flags {
+    pub const SOLICITED: u8 = 0x40;
+    pub const OVERRIDE: u8 = 0x20;
+}
+
+const BROADCAST_MAC: [u8; MAC_ADDR_LEN] = [0xff; MAC_ADDR_LEN];
+
+const ALL_NODES_MULTICAST: IPAddr = IPAddr([
+    0xff, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+]);
+
+/// Returns the modified EUI-64 of an Ethernet MAC address (RFC 4291,
+/// appendix A), for use with `IPAddr::generate_from_mac`.
+pub fn eui64_from_mac(mac: [u8; MAC_ADDR_LEN]) -> [u8; 8] {
+    [mac[0], mac[1], mac[2], 0xff, 0xfe, mac[3], mac[4], mac[5]]
+}
+
+/// Returns the link-local address derived from an Ethernet MAC address.
+pub fn link_local_from_mac(mac: [u8; MAC_ADDR_LEN]) -> IPAddr {
+    IPAddr::generate_from_mac(MacAddress::Long(eui64_from_mac(mac)))
+}
+
+/// Returns the solicited-node multicast address of `addr` (RFC 4291, 2.7.1).
+fn solicited_node(addr: &IPAddr) -> IPAddr {
+    let mut group = IPAddr([
+        0xff, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0xff, 0x00, 0x00,
+        0x00,
+    ]);
+    group.0[13..].copy_from_slice(&addr.0[13..]);
+    group
+}
+
+/// Returns the Ethernet address an IPv6 multicast address maps to (RFC 2464,
+/// section 7).
+fn multicast_mac(addr: &IPAddr) -> [u8; MAC_ADDR_LEN] {
+    [0x33, 0x33, addr.0[12], addr.0[13], addr.0[14], addr.0[15]]
+}
+
+/// The frame the adapter is currently transmitting, if any.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum TxFrame {
+    Idle,
+    Data,
+    Control,
+}
+
+/// An IPv6 interface over an Ethernet adapter.
+pub struct IP6EthernetStruct<'a, A: time::Alarm<'a>> {
+    adapter: &'a dyn EthernetAdapter<'a>,
+    alarm: &'a A,
+    mac_addr: [u8; MAC_ADDR_LEN],
+    interface_list: &'a [IPAddr],
+    src_addr: Cell<IPAddr>,
+    default_router: OptionalCell<IPAddr>,
+    neighbors: NeighborCache<[u8; MAC_ADDR_LEN]>,
+
+    ip6_packet: TakeCell<'static, IP6Packet<'static>>,
+    /// Frame holding the outgoing packet, and its length once it is ready
+    /// to be transmitted.
+    data_frame: TakeCell<'static, [u8]>,
+    data_len: Cell<usize>,
+    /// Frame for Neighbor Discovery messages, and its length once ready.
+    ctrl_frame: TakeCell<'static, [u8]>,
+    ctrl_len: Cell<usize>,
+    transmitting: Cell<TxFrame>,
+    /// Whether a packet passed to `send_to` has not completed yet.
+    sending: Cell<bool>,
+    /// Next hop of the pending packet being resolved, and the number of
+    /// solicitations sent for it.
+    resolving: Cell<Option<(IPAddr, u8)>>,
+
+    send_client: OptionalCell<&'a dyn IP6SendClient>,
+    recv_client: OptionalCell<&'a dyn IP6RecvClient>,
+    ip_vis: &'static IpVisibilityCapability,
+}
+
+impl<'a, A: time::Alarm<'a>> IP6EthernetStruct<'a, A> {
+    /// Creates an IPv6 interface with the given MAC address. The first entry
+    /// of `interface_list` is used as the source address until `set_addr` is
+    /// called. `data_frame` must be able to hold an Ethernet header plus the
+    /// largest encoded `ip6_packet`, and `ctrl_frame` must be at least
+    /// `NDP_FRAME_LEN` bytes.
+    pub fn new(
+        adapter: &'a dyn EthernetAdapter<'a>,
+        alarm: &'a A,
+        mac_addr: [u8; MAC_ADDR_LEN],
+        interface_list: &'a [IPAddr],
+        ip6_packet: &'static mut IP6Packet<'static>,
+        data_frame: &'static mut [u8],
+        ctrl_frame: &'static mut [u8],
+        ip_vis: &'static IpVisibilityCapability,
+    ) -> IP6EthernetStruct<'a, A> {
+        IP6EthernetStruct {
+            adapter,
+            alarm,
+            mac_addr,
+            interface_list,
+            src_addr: Cell::new(interface_list.first().copied().unwrap_or(IPAddr::new())),
+            default_router: OptionalCell::empty(),
+            neighbors: NeighborCache::new(),
+            ip6_packet: TakeCell::new(ip6_packet),
+            data_frame: TakeCell::new(data_frame),
+            data_len: Cell::new(0),
+            ctrl_frame: TakeCell::new(ctrl_frame),
+            ctrl_len: Cell::new(0),
+            transmitting: Cell::new(TxFrame::Idle),
+            sending: Cell::new(false),
+            resolving: Cell::new(None),
+            send_client: OptionalCell::empty(),
+            recv_client: OptionalCell::empty(),
+            ip_vis,
+        }
+    }
+
+    pub fn get_mac_addr(&self) -> [u8; MAC_ADDR_LEN] {
+        self.mac_addr
+    }
+
+    /// Sets the router packets to off-link destinations are sent to.
+    pub fn set_default_router(&self, router: IPAddr) {
+        self.default_router.set(router);
+    }
+
+    /// Adds a static entry to the neighbor cache, which avoids resolving the
+    /// address before the first packet is sent to it.
+    pub fn add_neighbor(&self, ip_addr: IPAddr, mac_addr: [u8; MAC_ADDR_LEN]) {
+        self.neighbors.insert(ip_addr, mac_addr);
+    }
+
+    fn is_local_addr(&self, addr: &IPAddr) -> bool {
+        self.interface_list.iter().any(|local| local == addr)
+    }
+
+    /// Returns whether a packet sent to `addr` is meant for this interface.
+    fn accepts_dst(&self, addr: &IPAddr) -> bool {
+        self.is_local_addr(addr)
+            || *addr == ALL_NODES_MULTICAST
+            || self
+                .interface_list
+                .iter()
+                .any(|local| solicited_node(local) == *addr)
+    }
+
+    fn next_hop(&self, dst: IPAddr) -> IPAddr {
+        let on_link = dst.is_unicast_link_local()
+            || self
+                .interface_list
+                .iter()
+                .any(|local| !local.is_unicast_link_local() && local.0[..8] == dst.0[..8]);
+        if on_link {
+            dst
+        } else {
+            self.default_router.unwrap_or(dst)
+        }
+    }
+
+    /// Writes the Ethernet header of a frame sent from this interface.
+    fn encode_eth_header(&self, frame: &mut [u8], dst_mac: &[u8; MAC_ADDR_LEN]) {
+        frame[0..6].copy_from_slice(dst_mac);
+        frame[6..12].copy_from_slice(&self.mac_addr);
+        frame[12..14].copy_from_slice(&ETHERTYPE_IPV6.to_be_bytes());
+    }
+
+    /// Encodes the pending packet into the data frame, addressed to
+    /// `dst_mac`, and marks it ready for transmission.
+    fn prepare_data_frame(&self, dst_mac: &[u8; MAC_ADDR_LEN]) -> Result<(), ErrorCode> {
+        let len = self
+            .data_frame
+            .map(|frame| {
+                self.ip6_packet.map(|ip6_packet| {
+                    if frame.len() < ETHERNET_HDR_LEN + ip6_packet.get_total_len() as usize {
+                        return Err(ErrorCode::SIZE);
+                    }
+                    self.encode_eth_header(frame, dst_mac);
+                    match ip6_packet.encode(&mut frame[ETHERNET_HDR_LEN..]).done() {
+                        Some((len, _)) => Ok(ETHERNET_HDR_LEN + len),
+                        None => Err(ErrorCode::SIZE),
+                    }
+                })
+            })
+            .flatten()
+            .ok_or(ErrorCode::NOMEM)??;
+        self.data_len.set(len);
+        Ok(())
+    }
+
+    /// Builds a Neighbor Solicitation or Advertisement in the control frame.
+    /// `body` holds the message following the checksum field; the link-layer
+    /// address option is appended. Returns `BUSY` if the control frame is
+    /// in use.
+    fn prepare_ndp_frame(
+        &self,
+        dst_mac: &[u8; MAC_ADDR_LEN],
+        dst: IPAddr,
+        msg_type: u8,
+        body: &[u8; 20],
+        option_type: u8,
+    ) -> Result<(), ErrorCode> {
+        if self.ctrl_len.get() != 0 {
+            return Err(ErrorCode::BUSY);
+        }
+        let src = self.src_addr.get();
+        let len = self
+            .ctrl_frame
+            .map(|frame| {
+                if frame.len() < NDP_FRAME_LEN {
+                    return Err(ErrorCode::SIZE);
+                }
+                self.encode_eth_header(frame, dst_mac);
+
+                let mut ip6_header = IP6Header::new();
+                ip6_header.set_payload_len(NDP_MSG_LEN as u16);
+                ip6_header.set_next_header(ip6_nh::ICMP);
+                ip6_header.set_hop_limit(NDP_HOP_LIMIT);
+                ip6_header.src_addr = src;
+                ip6_header.dst_addr = dst;
+                let _ = ip6_header.encode(&mut frame[ETHERNET_HDR_LEN..]);
+
+                let msg = &mut frame[ETHERNET_HDR_LEN + IP6_HDR_LEN..NDP_FRAME_LEN];
+                msg[0] = msg_type;
+                msg[1] = 0;
+                msg[2..4].copy_from_slice(&[0, 0]);
+                msg[4..24].copy_from_slice(body);
+                msg[24] = option_type;
+                msg[25] = (NDP_LL_OPTION_LEN / 8) as u8;
+                msg[26..32].copy_from_slice(&self.mac_addr);
+
+                let sum = compute_upper_layer_ph_sum(&src, &dst, NDP_MSG_LEN as u32, ip6_nh::ICMP)
+                    + compute_sum_padded(msg);
+                msg[2..4].copy_from_slice(&(!fold_sum(sum)).to_be_bytes());
+                Ok(NDP_FRAME_LEN)
+            })
+            .ok_or(ErrorCode::BUSY)??;
+        self.ctrl_len.set(len);
+        Ok(())
+    }
+
+    fn send_solicitation(&self, target: IPAddr) {
+        let mut body = [0; 20];
+        body[4..20].copy_from_slice(&target.0);
+        let group = solicited_node(&target);
+        let _ = self.prepare_ndp_frame(
+            &multicast_mac(&group),
+            group,
+            icmp_type::NEIGHBOR_SOLICITATION,
+            &body,
+            ndp_option::SOURCE_LL_ADDR,
+        );
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(RETRANS_TIMER_MS));
+    }
+
+    /// Hands the next ready frame to the adapter, if it is idle. Neighbor
+    /// Discovery messages take precedence over the pending packet. Returns
+    /// the error if the pending packet could not be transmitted, in which
+    /// case it has been dropped.
+    fn transmit_next(&self) -> Result<(), ErrorCode> {
+        if self.transmitting.get() != TxFrame::Idle {
+            return Ok(());
+        }
+        let ctrl_len = self.ctrl_len.get();
+        if ctrl_len != 0 {
+            if let Some(frame) = self.ctrl_frame.take() {
+                match self.adapter.transmit(frame, ctrl_len) {
+                    Ok(()) => {
+                        self.ctrl_len.set(0);
+                        self.transmitting.set(TxFrame::Control);
+                        return Ok(());
+                    }
+                    Err((ErrorCode::BUSY, frame)) => {
+                        self.ctrl_frame.replace(frame);
+                        return Ok(());
+                    }
+                    Err((ecode, frame)) => {
+                        debug!("[IP6_ETH] NDP transmit failed: {:?}", ecode);
+                        self.ctrl_frame.replace(frame);
+                        self.ctrl_len.set(0);
+                    }
+                }
+            }
+        }
+        let data_len = self.data_len.get();
+        if data_len != 0 {
+            if let Some(frame) = self.data_frame.take() {
+                match self.adapter.transmit(frame, data_len) {
+                    Ok(()) => {
+                        self.data_len.set(0);
+                        self.transmitting.set(TxFrame::Data);
+                    }
+                    Err((ErrorCode::BUSY, frame)) => {
+                        self.data_frame.replace(frame);
+                    }
+                    Err((ecode, frame)) => {
+                        self.data_frame.replace(frame);
+                        self.data_len.set(0);
+                        return Err(ecode);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Like `transmit_next`, but reports a failed packet to the client. Must
+    /// not be called from `send_to`.
+    fn transmit_next_or_complete(&self) {
+        if let Err(ecode) = self.transmit_next() {
+            self.send_completed(Err(ecode));
+        }
+    }
+
+    fn send_completed(&self, result: Result<(), ErrorCode>) {
+        self.sending.set(false);
+        self.send_client.map(|client| client.send_done(result));
+    }
+
+    fn receive_ndp(&self, header: &IP6Header, src_mac: [u8; MAC_ADDR_LEN], msg: &[u8]) {
+        if header.get_hop_limit() != NDP_HOP_LIMIT || msg.len() < 24 || msg[1] != 0 {
+            return;
+        }
+        let sum = compute_upper_layer_ph_sum(
+            &header.src_addr,
+            &header.dst_addr,
+            msg.len() as u32,
+            ip6_nh::ICMP,
+        ) + compute_sum_padded(msg);
+        if fold_sum(sum) != 0xffff {
+            return;
+        }
+
+        let mut target = IPAddr::new();
+        target.0.copy_from_slice(&msg[8..24]);
+
+        // Find the link-layer address option, if any.
+        let mut ll_option = None;
+        let mut options = &msg[24..];
+        while options.len() >= 8 {
+            let len = options[1] as usize * 8;
+            if len == 0 || len > options.len() {
+                return;
+            }
+            if len == NDP_LL_OPTION_LEN
+                && (options[0] == ndp_option::SOURCE_LL_ADDR
+                    || options[0] == ndp_option::TARGET_LL_ADDR)
+            {
+                let mut mac = [0; MAC_ADDR_LEN];
+                mac.copy_from_slice(&options[2..8]);
+                ll_option = Some(mac);
+            }
+            options = &options[len..];
+        }
+
+        match msg[0] {
+            icmp_type::NEIGHBOR_SOLICITATION => {
+                if !self.is_local_addr(&target) {
+                    return;
+                }
+                let src = header.get_src_addr();
+                let (dst, dst_mac, flags) = if src.is_unspecified() {
+                    // Duplicate Address Detection by another node
+                    (
+                        ALL_NODES_MULTICAST,
+                        multicast_mac(&ALL_NODES_MULTICAST),
+                        na_flags::OVERRIDE,
+                    )
+                } else {
+                    let mac = ll_option.unwrap_or(src_mac);
+                    self.neighbors.insert(src, mac);
+                    (src, mac, na_flags::SOLICITED | na_flags::OVERRIDE)
+                };
+                let mut body = [0; 20];
+                body[0] = flags;
+                body[4..20].copy_from_slice(&target.0);
+                if self
+                    .prepare_ndp_frame(
+                        &dst_mac,
+                        dst,
+                        icmp_type::NEIGHBOR_ADVERTISEMENT,
+                        &body,
+                        ndp_option::TARGET_LL_ADDR,
+                    )
+                    .is_ok()
+                {
+                    self.transmit_next_or_complete();
+                }
+            }
+            icmp_type::NEIGHBOR_ADVERTISEMENT => {
+                let mac = ll_option.unwrap_or(src_mac);
+                match self.resolving.get() {
+                    Some((next_hop, _)) if next_hop == target => {
+                        self.neighbors.insert(target, mac);
+                        self.resolving.set(None);
+                        let _ = self.alarm.disarm();
+                        match self.prepare_data_frame(&mac) {
+                            Ok(()) => self.transmit_next_or_complete(),
+                            Err(ecode) => self.send_completed(Err(ecode)),
+                        }
+                    }
+                    _ => self.neighbors.update(target, mac),
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> IP6Sender<'a> for IP6EthernetStruct<'a, A> {
+    fn set_client(&self, client: &'a dyn IP6SendClient) {
+        self.send_client.set(client);
+    }
+
+    fn set_addr(&self, src_addr: IPAddr) {
+        self.src_addr.set(src_addr);
+    }
+
+    /// Next hops are resolved with Neighbor Discovery, so a fixed gateway
+    /// link-layer address does not apply to Ethernet; use
+    /// `set_default_router` instead.
+    fn set_gateway(&self, _gateway: MacAddress) {}
+
+    fn set_header(&mut self, ip6_header: IP6Header) {
+        self.ip6_packet
+            .map(|ip6_packet| ip6_packet.header = ip6_header);
+    }
+
+    fn send_to(
+        &self,
+        dst: IPAddr,
+        transport_header: TransportHeader,
+        payload: &SubSliceMut<'static, u8>,
+        net_cap: &'static NetworkCapability,
+    ) -> Result<(), ErrorCode> {
+        if !net_cap.remote_addr_valid(dst, self.ip_vis) {
+            return Err(ErrorCode::FAIL);
+        }
+        if self.sending.get() {
+            return Err(ErrorCode::BUSY);
+        }
+
+        self.ip6_packet
+            .map(|ip6_packet| {
+                ip6_packet.header = IP6Header::default();
+                ip6_packet.header.src_addr = self.src_addr.get();
+                ip6_packet.header.dst_addr = dst;
+                ip6_packet.set_payload(transport_header, payload);
+                ip6_packet.set_transport_checksum();
+            })
+            .ok_or(ErrorCode::NOMEM)?;
+
+        let next_hop = self.next_hop(dst);
+        let dst_mac = if dst.is_multicast() {
+            Some(multicast_mac(&dst))
+        } else {
+            self.neighbors.lookup(&next_hop)
+        };
+        self.sending.set(true);
+        let result = match dst_mac {
+            Some(dst_mac) => self.prepare_data_frame(&dst_mac),
+            None => {
+                self.resolving.set(Some((next_hop, 1)));
+                self.send_solicitation(next_hop);
+                Ok(())
+            }
+        }
+        .and_then(|()| self.transmit_next());
+        if result.is_err() {
+            self.sending.set(false);
+        }
+        result
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> IP6Receiver<'a> for IP6EthernetStruct<'a, A> {
+    fn set_client(&self, client: &'a dyn IP6RecvClient) {
+        self.recv_client.set(client);
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> time::AlarmClient for IP6EthernetStruct<'a, A> {
+    fn alarm(&self) {
+        if let Some((next_hop, solicitations)) = self.resolving.get() {
+            if solicitations >= MAX_MULTICAST_SOLICIT {
+                self.resolving.set(None);
+                self.send_completed(Err(ErrorCode::NOACK));
+            } else {
+                self.resolving.set(Some((next_hop, solicitations + 1)));
+                self.send_solicitation(next_hop);
+                self.transmit_next_or_complete();
+            }
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> EthernetAdapterClient for IP6EthernetStruct<'a, A> {
+    fn transmit_done(&self, result: Result<(), ErrorCode>, frame: &'static mut [u8]) {
+        let sent = self.transmitting.replace(TxFrame::Idle);
+        match sent {
+            TxFrame::Data => {
+                self.data_frame.replace(frame);
+                self.send_completed(result);
+            }
+            TxFrame::Control | TxFrame::Idle => {
+                self.ctrl_frame.replace(frame);
+            }
+        }
+        self.transmit_next_or_complete();
+    }
+
+    fn received_frame(&self, frame: &[u8]) {
+        if frame.len() < ETHERNET_HDR_LEN + IP6_HDR_LEN {
+            return;
+        }
+        let mut dst_mac = [0; MAC_ADDR_LEN];
+        dst_mac.copy_from_slice(&frame[0..6]);
+        let mut src_mac = [0; MAC_ADDR_LEN];
+        src_mac.copy_from_slice(&frame[6..12]);
+        let ethertype = u16::from_be_bytes([frame[12], frame[13]]);
+        let for_us =
+            dst_mac == self.mac_addr || dst_mac == BROADCAST_MAC || dst_mac[0..2] == [0x33, 0x33];
+        if ethertype != ETHERTYPE_IPV6 || !for_us {
+            return;
+        }
+
+        let packet = &frame[ETHERNET_HDR_LEN..];
+        let ip6_header = match IP6Header::decode(packet).done() {
+            Some((_, ip6_header)) => ip6_header,
+            None => return,
+        };
+        // Frames may be padded to the minimum Ethernet frame size, so the
+        // payload length of the IPv6 header determines the packet length.
+        let len = IP6_HDR_LEN + ip6_header.get_payload_len() as usize;
+        if ip6_header.get_version() != 6 || len > packet.len() {
+            return;
+        }
+        if !self.accepts_dst(&ip6_header.get_dst_addr()) {
+            return;
+        }
+        let payload = &packet[IP6_HDR_LEN..len];
+
+        if ip6_header.get_next_header() == ip6_nh::ICMP
+            && matches!(
+                payload.first(),
+                Some(&icmp_type::NEIGHBOR_SOLICITATION) | Some(&icmp_type::NEIGHBOR_ADVERTISEMENT)
+            )
+        {
+            self.receive_ndp(&ip6_header, src_mac, payload);
+            return;
+        }
+
+        if ip6_header.check_transport_checksum(payload) == Err(ErrorCode::FAIL) {
+            return;
+        }
+        self.recv_client
+            .map(|client| client.receive(ip6_header, payload));
+    }
+}
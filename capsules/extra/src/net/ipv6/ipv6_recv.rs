@@ -2,11 +2,19 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 // Copyright Tock Contributors 2022.
 
+use crate::net::ipv6::ext_headers::{
+    self, param_problem, ExtHeaders, FragmentHeader, FRAGMENT_HDR_LEN, IP6_HDR_LEN,
+    IP6_NEXT_HEADER_OFFSET, IP6_PAYLOAD_LEN_OFFSET,
+};
+use crate::net::ipv6::ip_utils::IPAddr;
 use crate::net::ipv6::IP6Header;
 use crate::net::sixlowpan::sixlowpan_state::SixlowpanRxClient;
 
+use core::cell::Cell;
+use core::cmp;
+
 use kernel::debug;
-use kernel::utilities::cells::OptionalCell;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
 use kernel::ErrorCode;
 
 // To provide some context for the entire rx chain:
@@ -30,12 +38,38 @@ use kernel::ErrorCode;
   udp_recv, a `UDPReceive` struct.
 - The UDPReceive struct is a field of the UDPDriver, which ultimately passes the
   packets up to userland.
+
+Before a packet is passed to the client, `IP6RecvStruct` processes its
+extension headers (see `ext_headers`) and, if it is given a buffer for it,
+reassembles IPv6 fragments. Only one packet is reassembled at a time: a
+fragment of another packet replaces the packet being reassembled, so that
+lost fragments do not block reassembly forever. Packets with headers this
+node does not recognize are discarded, and reported to the error client,
+which sends the ICMPv6 Parameter Problem message required by RFC 8200.
 */
 
 pub trait IP6RecvClient {
+    /// Called when a packet is received. The next header and payload length
+    /// of `header` are those of the upper-layer `payload`, after any
+    /// extension headers have been removed and fragments reassembled.
     fn receive(&self, header: IP6Header, payload: &[u8]);
 }
 
+/// A trait for the layer that reports discarded packets to their source,
+/// i.e. ICMPv6.
+pub trait IP6RecvErrorClient {
+    /// Called when a packet is discarded because of a problem in its headers
+    /// that must be reported with an ICMPv6 Parameter Problem message.
+    ///
+    /// # Arguments
+    ///
+    /// `header` - The IPv6 header of the packet
+    /// `code` - The code of the Parameter Problem message
+    /// `pointer` - The offset of the erroneous field in the packet
+    /// `packet` - The packet, starting with its IPv6 header
+    fn parameter_problem(&self, header: &IP6Header, code: u8, pointer: u32, packet: &[u8]);
+}
+
 /// Currently only one implementation of this trait should exist,
 /// as we do not multiplex received packets based on the address.
 /// The receiver receives IP packets destined for any local address.
@@ -45,8 +79,38 @@ pub trait IP6Receiver<'a> {
     fn set_client(&self, client: &'a dyn IP6RecvClient);
 }
 
+/// Number of 8-byte blocks of a packet that can be reassembled, which limits
+/// the fragmentable part of reassembled packets to 2048 bytes.
+const REASSEMBLY_BLOCKS: usize = 256;
+
+/// The state of the packet being reassembled.
+#[derive(Copy, Clone)]
+struct Reassembly {
+    src_addr: IPAddr,
+    dst_addr: IPAddr,
+    id: u32,
+    next_header: u8,
+    /// Length of the fragmentable part, known once the last fragment arrived.
+    total_len: Option<usize>,
+    /// Bitmap of the 8-byte blocks received.
+    received: [u32; REASSEMBLY_BLOCKS / 32],
+}
+
+impl Reassembly {
+    fn is_received(&self, block: usize) -> bool {
+        self.received[block / 32] & (1 << (block % 32)) != 0
+    }
+
+    fn set_received(&mut self, block: usize) {
+        self.received[block / 32] |= 1 << (block % 32);
+    }
+}
+
 pub struct IP6RecvStruct<'a> {
     client: OptionalCell<&'a dyn IP6RecvClient>,
+    error_client: OptionalCell<&'a dyn IP6RecvErrorClient>,
+    reassembly_buf: TakeCell<'static, [u8]>,
+    reassembly: Cell<Option<Reassembly>>,
 }
 
 impl<'a> IP6Receiver<'a> for IP6RecvStruct<'a> {
@@ -59,33 +123,478 @@ impl<'a> IP6RecvStruct<'a> {
     pub fn new() -> IP6RecvStruct<'a> {
         IP6RecvStruct {
             client: OptionalCell::empty(),
+            error_client: OptionalCell::empty(),
+            reassembly_buf: TakeCell::empty(),
+            reassembly: Cell::new(None),
         }
     }
+
+    /// Sets the client that reports discarded packets. If several
+    /// `IP6RecvStruct`s receive the same packets, only one of them should
+    /// have an error client.
+    pub fn set_error_client(&self, client: &'a dyn IP6RecvErrorClient) {
+        self.error_client.set(client);
+    }
+
+    /// Sets the buffer fragmented packets are reassembled in. Without it,
+    /// fragments are dropped.
+    pub fn set_reassembly_buffer(&self, buf: &'static mut [u8]) {
+        self.reassembly_buf.replace(buf);
+    }
+
+    fn report(&self, header: &IP6Header, report: Option<(u8, u32)>, packet: Option<&[u8]>) {
+        if let (Some((code, pointer)), Some(packet)) = (report, packet) {
+            self.error_client
+                .map(|client| client.parameter_problem(header, code, pointer, packet));
+        }
+    }
+
+    fn deliver(&self, mut header: IP6Header, next_header: u8, payload: &[u8]) {
+        header.set_next_header(next_header);
+        header.set_payload_len(payload.len() as u16);
+        let checksum_result = header.check_transport_checksum(payload);
+        if checksum_result == Err(ErrorCode::FAIL) {
+            debug!("cksum fail!: {:?}", checksum_result);
+            return; //Dropped.
+        }
+        self.client.map(|client| client.receive(header, payload));
+    }
+
+    /// Processes the fragmentable part of a packet, which starts at `base` in
+    /// the original packet. `packet` is the original packet if it was not
+    /// reassembled, for error reports.
+    fn receive_fragmentable(
+        &self,
+        header: IP6Header,
+        next_header: u8,
+        data: &[u8],
+        base: usize,
+        packet: Option<&[u8]>,
+    ) {
+        let multicast_dst = header.get_dst_addr().is_multicast();
+        // The Next Header field of the Fragment header is its first byte.
+        match ext_headers::walk(
+            data,
+            next_header,
+            base,
+            base - FRAGMENT_HDR_LEN,
+            multicast_dst,
+        ) {
+            ExtHeaders::UpperLayer {
+                next_header,
+                offset,
+            } => self.deliver(header, next_header, &data[offset..]),
+            ExtHeaders::Discard { report } => self.report(&header, report, packet),
+            // Fragments within fragments are not allowed.
+            ExtHeaders::Fragment { .. } | ExtHeaders::NoNextHeader => {}
+        }
+    }
+
+    fn receive_fragment(
+        &self,
+        header: IP6Header,
+        fragment: FragmentHeader,
+        packet: &[u8],
+        header_offset: usize,
+    ) {
+        let base = header_offset + FRAGMENT_HDR_LEN;
+        let data = &packet[base..];
+        // An atomic fragment is processed like an unfragmented packet (RFC
+        // 6946).
+        if fragment.offset == 0 && !fragment.more {
+            self.receive_fragmentable(header, fragment.next_header, data, base, Some(packet));
+            return;
+        }
+        let multicast_dst = header.get_dst_addr().is_multicast();
+        // All fragments but the last must be a multiple of 8 bytes long.
+        if fragment.more && data.len() % 8 != 0 {
+            if !multicast_dst {
+                let report = (
+                    param_problem::ERRONEOUS_HEADER_FIELD,
+                    IP6_PAYLOAD_LEN_OFFSET as u32,
+                );
+                self.report(&header, Some(report), Some(packet));
+            }
+            return;
+        }
+        let end = fragment.offset + data.len();
+        if end > u16::MAX as usize {
+            if !multicast_dst {
+                // Point to the Fragment Offset field.
+                let report = (
+                    param_problem::ERRONEOUS_HEADER_FIELD,
+                    (header_offset + 2) as u32,
+                );
+                self.report(&header, Some(report), Some(packet));
+            }
+            return;
+        }
+
+        self.reassembly_buf.map(|buf| {
+            let src_addr = header.get_src_addr();
+            let dst_addr = header.get_dst_addr();
+            let mut reassembly = match self.reassembly.get() {
+                Some(r)
+                    if r.src_addr == src_addr && r.dst_addr == dst_addr && r.id == fragment.id =>
+                {
+                    r
+                }
+                _ => Reassembly {
+                    src_addr,
+                    dst_addr,
+                    id: fragment.id,
+                    next_header: fragment.next_header,
+                    total_len: None,
+                    received: [0; REASSEMBLY_BLOCKS / 32],
+                },
+            };
+            // Packets that do not fit, and packets with overlapping
+            // fragments (RFC 5722), are dropped.
+            self.reassembly.set(None);
+            if end > cmp::min(buf.len(), REASSEMBLY_BLOCKS * 8) {
+                return;
+            }
+            let blocks = fragment.offset / 8..(end + 7) / 8;
+            if blocks.clone().any(|block| reassembly.is_received(block)) {
+                return;
+            }
+            if fragment.more {
+                if reassembly.total_len.map_or(false, |total| end > total) {
+                    return;
+                }
+            } else {
+                if reassembly.total_len.is_some()
+                    || (blocks.end..REASSEMBLY_BLOCKS).any(|block| reassembly.is_received(block))
+                {
+                    return;
+                }
+                reassembly.total_len = Some(end);
+            }
+            if fragment.offset == 0 {
+                reassembly.next_header = fragment.next_header;
+            }
+            buf[fragment.offset..end].copy_from_slice(data);
+            blocks.for_each(|block| reassembly.set_received(block));
+
+            match reassembly.total_len {
+                Some(total) if (0..(total + 7) / 8).all(|block| reassembly.is_received(block)) => {
+                    self.receive_fragmentable(
+                        header,
+                        reassembly.next_header,
+                        &buf[..total],
+                        base,
+                        None,
+                    );
+                }
+                _ => self.reassembly.set(Some(reassembly)),
+            }
+        });
+    }
 }
 
 impl<'a> SixlowpanRxClient for IP6RecvStruct<'a> {
     fn receive(&self, buf: &[u8], len: usize, result: Result<(), ErrorCode>) {
-        // TODO: Drop here?
         if len > buf.len() || result != Ok(()) {
             return;
         }
-        match IP6Header::decode(buf).done() {
-            Some((offset, ip6_header)) => {
-                let checksum_result = ip6_header.check_transport_checksum(&buf[offset..len]);
-                if checksum_result == Err(ErrorCode::FAIL) {
-                    debug!("cksum fail!: {:?}", checksum_result);
-                    return; //Dropped.
-                }
-                // Note: Protocols for which checksum verification is not implemented (TCP, etc.)
-                // are automatically assumed as fine, rather than dropped
-
-                self.client
-                    .map(|client| client.receive(ip6_header, &buf[offset..len]));
-            }
+        let header = match IP6Header::decode(buf).done() {
+            Some((_, header)) => header,
             None => {
                 debug!("failed to decode ipv6 header");
-                // TODO: Report the error somewhere...
+                return;
+            }
+        };
+        let payload_len = header.get_payload_len() as usize;
+        if header.get_version() != 6 || IP6_HDR_LEN + payload_len > len {
+            return;
+        }
+        let packet = &buf[..IP6_HDR_LEN + payload_len];
+        let multicast_dst = header.get_dst_addr().is_multicast();
+        match ext_headers::walk(
+            &packet[IP6_HDR_LEN..],
+            header.get_next_header(),
+            IP6_HDR_LEN,
+            IP6_NEXT_HEADER_OFFSET,
+            multicast_dst,
+        ) {
+            ExtHeaders::UpperLayer {
+                next_header,
+                offset,
+            } => self.deliver(header, next_header, &packet[IP6_HDR_LEN + offset..]),
+            ExtHeaders::Fragment {
+                header: fragment,
+                header_offset,
+            } => self.receive_fragment(header, fragment, packet, IP6_HDR_LEN + header_offset),
+            ExtHeaders::NoNextHeader => {}
+            ExtHeaders::Discard { report } => self.report(&header, report, Some(packet)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::net::ipv6::ip_utils::{compute_udp_checksum, ip6_nh};
+    use crate::net::udp::UDPHeader;
+    use std::boxed::Box;
+
+    /// Length of the test datagram: a UDP header and 24 bytes of data.
+    const DATAGRAM_LEN: usize = 32;
+
+    fn ip_header(next_header: u8, payload_len: usize) -> IP6Header {
+        let mut header = IP6Header::new();
+        header.src_addr = IPAddr([0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        header.dst_addr = IPAddr([0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+        header.set_next_header(next_header);
+        header.set_payload_len(payload_len as u16);
+        header
+    }
+
+    /// Returns a UDP datagram with a valid checksum.
+    fn datagram() -> [u8; DATAGRAM_LEN] {
+        let mut buf = [0; DATAGRAM_LEN];
+        buf[0..2].copy_from_slice(&1000u16.to_be_bytes());
+        buf[2..4].copy_from_slice(&2000u16.to_be_bytes());
+        buf[4..6].copy_from_slice(&(DATAGRAM_LEN as u16).to_be_bytes());
+        for (i, byte) in buf.iter_mut().enumerate().skip(8) {
+            *byte = i as u8;
+        }
+        let (_, udp_header) = UDPHeader::decode(&buf).done().unwrap();
+        let cksum = compute_udp_checksum(
+            &ip_header(ip6_nh::UDP, DATAGRAM_LEN),
+            &udp_header,
+            DATAGRAM_LEN as u16,
+            &buf[8..],
+        );
+        buf[6..8].copy_from_slice(&cksum.to_be_bytes());
+        buf
+    }
+
+    fn fragment_header(offset: usize, more: bool, id: u32) -> [u8; FRAGMENT_HDR_LEN] {
+        let offset_flags = offset as u16 | u16::from(more);
+        let mut header = [0; FRAGMENT_HDR_LEN];
+        header[0] = ip6_nh::UDP;
+        header[2..4].copy_from_slice(&offset_flags.to_be_bytes());
+        header[4..].copy_from_slice(&id.to_be_bytes());
+        header
+    }
+
+    /// Records the packets and errors passed up by the receiver.
+    struct Recorder {
+        received: Cell<usize>,
+        payload: Cell<[u8; DATAGRAM_LEN]>,
+        error: Cell<Option<(u8, u32)>>,
+    }
+
+    impl IP6RecvClient for Recorder {
+        fn receive(&self, header: IP6Header, payload: &[u8]) {
+            assert_eq!(header.get_next_header(), ip6_nh::UDP);
+            assert_eq!(header.get_payload_len() as usize, payload.len());
+            let mut copy = [0; DATAGRAM_LEN];
+            copy.copy_from_slice(payload);
+            self.payload.set(copy);
+            self.received.set(self.received.get() + 1);
+        }
+    }
+
+    impl IP6RecvErrorClient for Recorder {
+        fn parameter_problem(&self, _header: &IP6Header, code: u8, pointer: u32, _packet: &[u8]) {
+            self.error.set(Some((code, pointer)));
+        }
+    }
+
+    struct Harness<'a> {
+        recv: &'a IP6RecvStruct<'a>,
+        recorder: &'a Recorder,
+    }
+
+    impl Harness<'_> {
+        /// Receives a packet made of the IPv6 header, `ext` and `data`.
+        fn receive(&self, next_header: u8, ext: &[u8], data: &[u8]) {
+            let mut buf = [0; 128];
+            let len = IP6_HDR_LEN + ext.len() + data.len();
+            ip_header(next_header, ext.len() + data.len())
+                .encode(&mut buf)
+                .done()
+                .unwrap();
+            buf[IP6_HDR_LEN..IP6_HDR_LEN + ext.len()].copy_from_slice(ext);
+            buf[IP6_HDR_LEN + ext.len()..len].copy_from_slice(data);
+            self.recv.receive(&buf, len, Ok(()));
+        }
+
+        /// Receives the part of the datagram starting at `offset`.
+        fn receive_fragment(&self, id: u32, offset: usize, data: &[u8], more: bool) {
+            self.receive(
+                ip6_nh::FRAGMENT,
+                &fragment_header(offset, more, id),
+                &data[offset..],
+            );
+        }
+
+        fn assert_delivered(&self, count: usize) {
+            assert_eq!(self.recorder.received.get(), count);
+            if count > 0 {
+                assert_eq!(self.recorder.payload.get(), datagram());
             }
         }
     }
+
+    fn with_harness(test: impl FnOnce(&Harness)) {
+        let recv = IP6RecvStruct::new();
+        let recorder = Recorder {
+            received: Cell::new(0),
+            payload: Cell::new([0; DATAGRAM_LEN]),
+            error: Cell::new(None),
+        };
+        recv.set_client(&recorder);
+        recv.set_error_client(&recorder);
+        recv.set_reassembly_buffer(Box::leak(Box::new([0; REASSEMBLY_BLOCKS * 8])));
+        test(&Harness {
+            recv: &recv,
+            recorder: &recorder,
+        });
+    }
+
+    #[test]
+    fn test_unfragmented() {
+        with_harness(|h| {
+            h.receive(ip6_nh::UDP, &[], &datagram());
+            h.assert_delivered(1);
+        });
+    }
+
+    #[test]
+    fn test_extension_headers_removed() {
+        with_harness(|h| {
+            let hop_by_hop = [ip6_nh::DST_OPTS, 0, 1, 4, 0, 0, 0, 0];
+            let dst_opts = [ip6_nh::UDP, 0, 0x3e, 4, 0, 0, 0, 0];
+            let mut ext = [0; 16];
+            ext[..8].copy_from_slice(&hop_by_hop);
+            ext[8..].copy_from_slice(&dst_opts);
+            h.receive(ip6_nh::HOP_OPTS, &ext, &datagram());
+            h.assert_delivered(1);
+        });
+    }
+
+    #[test]
+    fn test_unrecognized_header_reported() {
+        with_harness(|h| {
+            h.receive(ip6_nh::MOBILITY, &[], &datagram());
+            h.assert_delivered(0);
+            assert_eq!(
+                h.recorder.error.get(),
+                Some((param_problem::UNRECOGNIZED_NEXT_HEADER, 6))
+            );
+        });
+    }
+
+    #[test]
+    fn test_atomic_fragment() {
+        with_harness(|h| {
+            h.receive_fragment(1, 0, &datagram(), false);
+            h.assert_delivered(1);
+        });
+    }
+
+    #[test]
+    fn test_reassembly_in_order() {
+        with_harness(|h| {
+            let data = datagram();
+            h.receive(ip6_nh::FRAGMENT, &fragment_header(0, true, 1), &data[..16]);
+            h.assert_delivered(0);
+            h.receive_fragment(1, 16, &data, false);
+            h.assert_delivered(1);
+        });
+    }
+
+    #[test]
+    fn test_reassembly_out_of_order() {
+        with_harness(|h| {
+            let data = datagram();
+            h.receive_fragment(7, 24, &data, false);
+            h.receive(ip6_nh::FRAGMENT, &fragment_header(8, true, 7), &data[8..24]);
+            h.assert_delivered(0);
+            h.receive(ip6_nh::FRAGMENT, &fragment_header(0, true, 7), &data[..8]);
+            h.assert_delivered(1);
+        });
+    }
+
+    #[test]
+    fn test_overlapping_fragments_dropped() {
+        with_harness(|h| {
+            let data = datagram();
+            h.receive(ip6_nh::FRAGMENT, &fragment_header(0, true, 1), &data[..16]);
+            h.receive(ip6_nh::FRAGMENT, &fragment_header(8, true, 1), &data[8..24]);
+            h.receive_fragment(1, 24, &data, false);
+            h.assert_delivered(0);
+        });
+    }
+
+    #[test]
+    fn test_duplicate_fragment_dropped() {
+        with_harness(|h| {
+            let data = datagram();
+            h.receive(ip6_nh::FRAGMENT, &fragment_header(0, true, 1), &data[..16]);
+            h.receive(ip6_nh::FRAGMENT, &fragment_header(0, true, 1), &data[..16]);
+            h.receive_fragment(1, 16, &data, false);
+            h.assert_delivered(0);
+        });
+    }
+
+    #[test]
+    fn test_fragment_beyond_last_dropped() {
+        with_harness(|h| {
+            let data = datagram();
+            h.receive_fragment(1, 16, &data, false);
+            h.receive(ip6_nh::FRAGMENT, &fragment_header(32, true, 1), &data[..8]);
+            h.receive(ip6_nh::FRAGMENT, &fragment_header(0, true, 1), &data[..16]);
+            h.assert_delivered(0);
+        });
+    }
+
+    #[test]
+    fn test_fragment_of_other_packet_replaces() {
+        with_harness(|h| {
+            let data = datagram();
+            h.receive(ip6_nh::FRAGMENT, &fragment_header(0, true, 1), &data[..16]);
+            h.receive(ip6_nh::FRAGMENT, &fragment_header(0, true, 2), &data[..16]);
+            h.receive_fragment(2, 16, &data, false);
+            h.assert_delivered(1);
+            h.receive_fragment(1, 16, &data, false);
+            h.assert_delivered(1);
+        });
+    }
+
+    #[test]
+    fn test_fragment_length_not_multiple_of_8() {
+        with_harness(|h| {
+            let data = datagram();
+            h.receive(ip6_nh::FRAGMENT, &fragment_header(0, true, 1), &data[..12]);
+            assert_eq!(
+                h.recorder.error.get(),
+                Some((
+                    param_problem::ERRONEOUS_HEADER_FIELD,
+                    IP6_PAYLOAD_LEN_OFFSET as u32
+                ))
+            );
+        });
+    }
+
+    #[test]
+    fn test_fragment_too_large_for_buffer() {
+        with_harness(|h| {
+            let data = datagram();
+            let offset = REASSEMBLY_BLOCKS * 8;
+            h.receive(
+                ip6_nh::FRAGMENT,
+                &fragment_header(offset, false, 1),
+                &data[..8],
+            );
+            h.receive(ip6_nh::FRAGMENT, &fragment_header(0, true, 1), &data[..16]);
+            h.assert_delivered(0);
+            assert_eq!(h.recorder.error.get(), None);
+        });
+    }
 }
@@ -26,7 +26,7 @@ use crate::net::ipv6::ip_utils::IPAddr;
 use crate::net::ipv6::{IP6Header, IP6Packet, TransportHeader};
 use crate::net::network_capabilities::{IpVisibilityCapability, NetworkCapability};
 use crate::net::sixlowpan::sixlowpan_state::TxState;
-use crate::net::thread::thread_utils::{mac_from_ipv6, MULTICAST_IPV6};
+use crate::net::thread::thread_utils::mac_from_ipv6;
 
 use core::cell::Cell;
 
@@ -44,6 +44,21 @@ pub trait IP6SendClient {
     fn send_done(&self, result: Result<(), ErrorCode>);
 }
 
+/// This trait is implemented by a layer that knows the link-layer addresses
+/// of the neighbors and the addresses of this node, such as Neighbor
+/// Discovery. An `IP6SendStruct` with a resolver asks it where to send each
+/// packet and which source address to use.
+pub trait NeighborResolver {
+    /// Returns the MAC address of the next hop towards `dst`, or `None` if
+    /// it is not known. Packets to unknown next hops are sent to the MAC
+    /// address derived from a link-local `dst`, or to the gateway otherwise.
+    fn next_hop(&self, dst: &IPAddr) -> Option<MacAddress>;
+
+    /// Returns the source address for packets sent to `dst`, or `None` to
+    /// use the address set with `IP6Sender::set_addr`.
+    fn source_addr(&self, dst: &IPAddr) -> Option<IPAddr>;
+}
+
 /// This trait provides a basic IPv6 sending interface. It exposes basic
 /// configuration information for the IPv6 layer (setting the source address,
 /// setting the gateway MAC address), as well as a way to send an IPv6
@@ -108,9 +123,9 @@ pub struct IP6SendStruct<'a, A: time::Alarm<'a>> {
     tx_buf: TakeCell<'static, [u8]>,
     sixlowpan: TxState<'a>,
     radio: &'a dyn MacDevice<'a>,
-    dst_mac_addr: MacAddress,
     src_mac_addr: MacAddress,
     client: OptionalCell<&'a dyn IP6SendClient>,
+    resolver: OptionalCell<&'a dyn NeighborResolver>,
     ip_vis: &'static IpVisibilityCapability,
 }
 
@@ -149,15 +164,17 @@ impl<'a, A: time::Alarm<'a>> IP6Sender<'a> for IP6SendStruct<'a, A> {
         // but may conflict with some other or future protocol
         // that sits above and uses IPV6
         let dst_mac_addr;
-        if dst == MULTICAST_IPV6 {
+        if dst.is_multicast() {
             // use short multicast ipv6 for dst mac address
             dst_mac_addr = MacAddress::Short(0xFFFF)
+        } else if let Some(next_hop) = self.resolver.get().and_then(|r| r.next_hop(&dst)) {
+            dst_mac_addr = next_hop;
         } else if dst.0[0..8] == [0xfe, 0x80, 0, 0, 0, 0, 0, 0] {
             // ipv6 address is of form fe80::MAC; use mac_from_ipv6
             // helper function to determine ipv6 to send to
             dst_mac_addr = MacAddress::Long(mac_from_ipv6(dst))
         } else {
-            dst_mac_addr = self.dst_mac_addr;
+            dst_mac_addr = self.gateway.get();
         }
 
         // TODO: add error handling here
@@ -190,13 +207,19 @@ impl<'a, A: time::Alarm<'a>> IP6SendStruct<'a, A> {
             tx_buf: TakeCell::new(tx_buf),
             sixlowpan,
             radio,
-            dst_mac_addr,
             src_mac_addr,
             client: OptionalCell::empty(),
+            resolver: OptionalCell::empty(),
             ip_vis,
         }
     }
 
+    /// Sets the `NeighborResolver` used to pick the next hop and source
+    /// address of each packet.
+    pub fn set_resolver(&self, resolver: &'a dyn NeighborResolver) {
+        self.resolver.set(resolver);
+    }
+
     fn init_packet(
         &self,
         dst_addr: IPAddr,
@@ -209,7 +232,11 @@ impl<'a, A: time::Alarm<'a>> IP6SendStruct<'a, A> {
             },
             |ip6_packet| {
                 ip6_packet.header = IP6Header::default();
-                ip6_packet.header.src_addr = self.src_addr.get();
+                ip6_packet.header.src_addr = self
+                    .resolver
+                    .get()
+                    .and_then(|r| r.source_addr(&dst_addr))
+                    .unwrap_or(self.src_addr.get());
                 ip6_packet.header.dst_addr = dst_addr;
                 ip6_packet.set_payload(transport_header, payload);
                 ip6_packet.set_transport_checksum();
@@ -2,9 +2,12 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 // Copyright Tock Contributors 2022.
 
+pub mod ext_headers;
 pub mod ip_utils;
+pub mod ipv6_ethernet;
 pub mod ipv6_recv;
 pub mod ipv6_send;
+pub mod neighbor_cache;
 
 // Reexport the exports of the [`ipv6`] module, to avoid redundant
 // module paths (e.g. `capsules::net::ipv6::ipv6::IP6Header`)
@@ -0,0 +1,102 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! A small neighbor cache (RFC 4861, section 5.1) shared by the IPv6
+//! interfaces. It maps the IPv6 addresses of neighbors to their link-layer
+//! addresses, which are Ethernet addresses for `IP6EthernetStruct` and
+//! 802.15.4 addresses for Neighbor Discovery over 6LoWPAN.
+
+use crate::net::ipv6::ip_utils::IPAddr;
+
+use core::cell::Cell;
+
+/// Number of entries in the neighbor cache. When full, the least recently
+/// used entry is replaced.
+pub const NEIGHBOR_CACHE_SIZE: usize = 8;
+
+#[derive(Copy, Clone)]
+struct Neighbor<L: Copy> {
+    ip_addr: IPAddr,
+    link_addr: L,
+    last_used: u32,
+}
+
+/// A fixed size cache mapping IPv6 addresses of neighbors to their
+/// link-layer addresses of type `L`.
+pub struct NeighborCache<L: Copy + PartialEq> {
+    entries: [Cell<Option<Neighbor<L>>>; NEIGHBOR_CACHE_SIZE],
+    clock: Cell<u32>,
+}
+
+impl<L: Copy + PartialEq> NeighborCache<L> {
+    pub fn new() -> NeighborCache<L> {
+        NeighborCache {
+            entries: Default::default(),
+            clock: Cell::new(0),
+        }
+    }
+
+    fn tick(&self) -> u32 {
+        let now = self.clock.get().wrapping_add(1);
+        self.clock.set(now);
+        now
+    }
+
+    /// Returns the link-layer address of `ip_addr`, if known.
+    pub fn lookup(&self, ip_addr: &IPAddr) -> Option<L> {
+        let now = self.tick();
+        self.entries.iter().find_map(|entry| {
+            let mut neighbor = entry.get()?;
+            if neighbor.ip_addr != *ip_addr {
+                return None;
+            }
+            neighbor.last_used = now;
+            entry.set(Some(neighbor));
+            Some(neighbor.link_addr)
+        })
+    }
+
+    /// Adds or updates the link-layer address of `ip_addr`.
+    pub fn insert(&self, ip_addr: IPAddr, link_addr: L) {
+        let neighbor = Neighbor {
+            ip_addr,
+            link_addr,
+            last_used: self.tick(),
+        };
+        let slot = self
+            .entries
+            .iter()
+            .find(|entry| entry.get().map_or(false, |n| n.ip_addr == ip_addr))
+            .or_else(|| self.entries.iter().find(|entry| entry.get().is_none()))
+            .or_else(|| {
+                // Replace the least recently used entry.
+                self.entries.iter().max_by_key(|entry| {
+                    entry
+                        .get()
+                        .map_or(0, |n| neighbor.last_used.wrapping_sub(n.last_used))
+                })
+            });
+        if let Some(slot) = slot {
+            slot.set(Some(neighbor));
+        }
+    }
+
+    /// Updates the link-layer address of `ip_addr` only if it is already
+    /// cached, as required for unsolicited messages (RFC 4861, 7.2.5).
+    pub fn update(&self, ip_addr: IPAddr, link_addr: L) {
+        if self.lookup(&ip_addr).is_some() {
+            self.insert(ip_addr, link_addr);
+        }
+    }
+
+    /// Removes `ip_addr` from the cache, e.g. when a neighbor stops being a
+    /// router.
+    pub fn remove(&self, ip_addr: &IPAddr) {
+        for entry in self.entries.iter() {
+            if entry.get().map_or(false, |n| n.ip_addr == *ip_addr) {
+                entry.set(None);
+            }
+        }
+    }
+}
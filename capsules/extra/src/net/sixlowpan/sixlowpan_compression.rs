@@ -10,8 +10,11 @@ use crate::net::util;
 use crate::net::util::{network_slice_to_u16, u16_to_network_slice};
 /// Implements the 6LoWPAN specification for sending IPv6 datagrams over
 /// 802.15.4 packets efficiently, as detailed in RFC 6282.
+use core::cell::Cell;
 use core::mem;
 
+use kernel::ErrorCode;
+
 /// Contains bit masks and constants related to the two-byte header of the
 /// LoWPAN_IPHC encoding format.
 mod iphc {
@@ -155,6 +158,97 @@ impl ContextStore for Context {
     }
 }
 
+impl<T: ContextStore + ?Sized> ContextStore for &T {
+    fn get_context_from_addr(&self, ip_addr: IPAddr) -> Option<Context> {
+        (**self).get_context_from_addr(ip_addr)
+    }
+
+    fn get_context_from_id(&self, ctx_id: u8) -> Option<Context> {
+        (**self).get_context_from_id(ctx_id)
+    }
+
+    fn get_context_from_prefix(&self, prefix: &[u8], prefix_len: u8) -> Option<Context> {
+        (**self).get_context_from_prefix(prefix, prefix_len)
+    }
+}
+
+/// Number of contexts a `ContextTable` can hold, one for each of the 4-bit
+/// context identifiers of RFC 6282.
+pub const CONTEXT_TABLE_SIZE: usize = 16;
+
+/// A `ContextStore` holding up to 16 contexts that can be changed at runtime,
+/// e.g. from the 6LoWPAN Context Options of Router Advertisements (RFC 6775,
+/// section 4.2). A reference to the table can be shared by several
+/// `Sixlowpan` instances, so that all of them compress and decompress
+/// addresses with the same contexts.
+///
+/// Context 0 is always available: it starts out as the default context
+/// passed to `new`, and is restored to it when removed.
+pub struct ContextTable {
+    default_context: Context,
+    contexts: [Cell<Option<Context>>; CONTEXT_TABLE_SIZE],
+}
+
+impl ContextTable {
+    pub fn new(default_context: Context) -> ContextTable {
+        let table = ContextTable {
+            default_context: Context {
+                id: 0,
+                ..default_context
+            },
+            contexts: Default::default(),
+        };
+        table.contexts[0].set(Some(table.default_context));
+        table
+    }
+
+    /// Adds `context`, replacing any context with the same identifier.
+    /// Returns `INVAL` if the identifier or prefix length is invalid.
+    pub fn set_context(&self, context: Context) -> Result<(), ErrorCode> {
+        if context.id as usize >= CONTEXT_TABLE_SIZE || context.prefix_len > 128 {
+            return Err(ErrorCode::INVAL);
+        }
+        self.contexts[context.id as usize].set(Some(context));
+        Ok(())
+    }
+
+    /// Removes the context with identifier `ctx_id`.
+    pub fn remove_context(&self, ctx_id: u8) {
+        match ctx_id {
+            0 => self.contexts[0].set(Some(self.default_context)),
+            id if (id as usize) < CONTEXT_TABLE_SIZE => self.contexts[id as usize].set(None),
+            _ => {}
+        }
+    }
+}
+
+impl ContextStore for ContextTable {
+    /// Returns the context with the longest prefix matching `ip_addr`,
+    /// preferring contexts that may be used for compression.
+    fn get_context_from_addr(&self, ip_addr: IPAddr) -> Option<Context> {
+        self.contexts
+            .iter()
+            .filter_map(|ctx| ctx.get())
+            .filter(|ctx| util::matches_prefix(&ip_addr.0, &ctx.prefix, ctx.prefix_len))
+            .max_by_key(|ctx| (ctx.compress, ctx.prefix_len))
+    }
+
+    fn get_context_from_id(&self, ctx_id: u8) -> Option<Context> {
+        self.contexts.get(ctx_id as usize).and_then(|ctx| ctx.get())
+    }
+
+    fn get_context_from_prefix(&self, prefix: &[u8], prefix_len: u8) -> Option<Context> {
+        self.contexts
+            .iter()
+            .filter_map(|ctx| ctx.get())
+            .filter(|ctx| {
+                prefix_len == ctx.prefix_len
+                    && util::matches_prefix(prefix, &ctx.prefix, prefix_len)
+            })
+            .max_by_key(|ctx| ctx.compress)
+    }
+}
+
 pub fn is_lowpan(packet: &[u8]) -> bool {
     (packet[0] & iphc::DISPATCH[0]) == iphc::DISPATCH[0]
 }
@@ -0,0 +1,473 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! TCP userspace interface.
+//!
+//! Implements a userspace interface for opening TCP connections, either
+//! actively (connect) or passively (listen), and exchanging a byte stream
+//! over them. Each process can own a single connection at a time, backed by
+//! one of a fixed pool of `TCPSocket`s provided by the board.
+//!
+//! Transmitted data is never copied into the kernel: the process shares its
+//! write buffer and tells the driver how many bytes of it to send, and the TCP
+//! layer reads segments (and retransmissions) straight out of that buffer
+//! until all of them are acknowledged. The process must therefore not modify
+//! the buffer until the `SENT` upcall. Received data is appended to the read
+//! buffer, whose free space is advertised to the peer as the receive window.
+
+use crate::net::ipv6::ip_utils::IPAddr;
+use crate::net::network_capabilities::NetworkCapability;
+use crate::net::tcp::tcp_mux::{TCPClient, TCPSocket, TCPStack};
+use crate::net::tcp::TCPState;
+use crate::net::util::host_slice_to_u16;
+
+use core::cmp;
+use core::mem::size_of;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Tcp as usize;
+
+/// Size of an address/port pair as used in the config buffer: a 16 byte IPv6
+/// address followed by a 2 byte port in host byte order, matching the UDP
+/// driver.
+const ENDPOINT_LEN: usize = size_of::<IPAddr>() + size_of::<u16>();
+
+/// IDs for subscribed upcalls.
+mod upcall {
+    /// The connection has been established. For a passive open, the remote
+    /// address and port are written to the second half of the config buffer.
+    pub const CONNECTED: usize = 0;
+    /// Data was appended to the read buffer. The argument is the total number
+    /// of unconsumed bytes in the read buffer.
+    pub const RECEIVED: usize = 1;
+    /// All bytes of the last send have been acknowledged by the peer. The
+    /// arguments are a status code and the number of bytes sent.
+    pub const SENT: usize = 2;
+    /// The connection was closed. The arguments are a status code (`CANCEL`
+    /// if reset by the peer, `NOACK` on timeout) and 1 if only the peer has
+    /// closed its side, in which case the process may still send.
+    pub const CLOSED: usize = 3;
+    /// Number of upcalls.
+    pub const COUNT: u8 = 4;
+}
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// Write buffer. Contains the bytes to send.
+    pub const WRITE: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Read buffer. Received bytes are appended to it.
+    pub const READ: usize = 0;
+    /// Config buffer. Holds two address/port pairs: the local endpoint
+    /// followed by the remote endpoint.
+    pub const CFG: usize = 1;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 2;
+}
+
+#[derive(Default)]
+pub struct App {
+    /// Index of the socket in the driver's pool owned by this process.
+    socket: Option<usize>,
+    /// Number of bytes of the write buffer passed to the last send.
+    tx_len: usize,
+    /// Number of those bytes already acknowledged by the peer.
+    tx_acked: usize,
+    /// Number of unconsumed bytes in the read buffer.
+    rx_len: usize,
+}
+
+pub struct TCPDriver<'a> {
+    stack: &'a dyn TCPStack<'a>,
+    sockets: &'a [TCPSocket<'a>],
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    net_cap: &'static NetworkCapability,
+}
+
+impl<'a> TCPDriver<'a> {
+    pub fn new(
+        stack: &'a dyn TCPStack<'a>,
+        sockets: &'a [TCPSocket<'a>],
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+        net_cap: &'static NetworkCapability,
+    ) -> TCPDriver<'a> {
+        TCPDriver {
+            stack,
+            sockets,
+            apps: grant,
+            net_cap,
+        }
+    }
+
+    /// Returns the process that owns socket `id`, if any.
+    fn owner(&self, id: usize) -> Option<ProcessId> {
+        self.apps.iter().find_map(|app| {
+            let processid = app.processid();
+            app.enter(|app, _| app.socket == Some(id))
+                .then_some(processid)
+        })
+    }
+
+    /// Returns the index of a socket not owned by any process. A socket that
+    /// is unowned but still open was left behind by a process that has since
+    /// terminated, so its connection is aborted before the socket is reused.
+    fn free_socket(&self) -> Option<usize> {
+        let id = (0..self.sockets.len()).find(|&id| self.owner(id).is_none())?;
+        if self.sockets[id].get_state() != TCPState::Closed {
+            self.stack.abort(&self.sockets[id]);
+        }
+        Some(id)
+    }
+
+    /// Returns the socket owned by `processid`, allocating one if needed.
+    fn socket_for(&self, processid: ProcessId, allocate: bool) -> Result<usize, ErrorCode> {
+        let owned = self
+            .apps
+            .enter(processid, |app, _| app.socket)
+            .map_err(ErrorCode::from)?;
+        match owned {
+            Some(id) => Ok(id),
+            None if allocate => {
+                let id = self.free_socket().ok_or(ErrorCode::NOMEM)?;
+                self.apps
+                    .enter(processid, |app, _| {
+                        **app = App::default();
+                        app.socket = Some(id);
+                    })
+                    .map_err(ErrorCode::from)?;
+                Ok(id)
+            }
+            None => Err(ErrorCode::RESERVE),
+        }
+    }
+
+    /// Releases the socket held by `processid` if it is no longer in use.
+    fn release_if_closed(&self, processid: ProcessId, id: usize) {
+        if self.sockets[id].get_state() == TCPState::Closed {
+            let _ = self.apps.enter(processid, |app, _| **app = App::default());
+        }
+    }
+
+    fn parse_endpoint(buf: &[u8]) -> (IPAddr, u16) {
+        let mut addr = IPAddr::new();
+        addr.0.copy_from_slice(&buf[..size_of::<IPAddr>()]);
+        (
+            addr,
+            host_slice_to_u16(&buf[size_of::<IPAddr>()..ENDPOINT_LEN]),
+        )
+    }
+
+    fn connect(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        let endpoints = self
+            .apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readwrite_processbuffer(rw_allow::CFG)
+                    .and_then(|cfg| {
+                        cfg.enter(|cfg| {
+                            if cfg.len() != 2 * ENDPOINT_LEN {
+                                return None;
+                            }
+                            let mut tmp = [0; 2 * ENDPOINT_LEN];
+                            cfg.copy_to_slice(&mut tmp);
+                            Some((
+                                Self::parse_endpoint(&tmp[..ENDPOINT_LEN]),
+                                Self::parse_endpoint(&tmp[ENDPOINT_LEN..]),
+                            ))
+                        })
+                    })
+                    .unwrap_or(None)
+            })
+            .map_err(ErrorCode::from)?;
+        let ((_, src_port), (dst_addr, dst_port)) = endpoints.ok_or(ErrorCode::INVAL)?;
+        let id = self.socket_for(processid, true)?;
+        let result = self.stack.connect(
+            &self.sockets[id],
+            dst_addr,
+            dst_port,
+            src_port,
+            self.net_cap,
+        );
+        if result.is_err() {
+            self.release_if_closed(processid, id);
+        }
+        result
+    }
+}
+
+impl<'a> SyscallDriver for TCPDriver<'a> {
+    /// TCP control
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Listen for a connection on port `arg1`. Returns BUSY if the
+    ///        port is in use or the process already has an open connection,
+    ///        and NOMEM if no socket is free.
+    /// - `2`: Connect to the remote endpoint in the second half of the config
+    ///        buffer, from the local port in the first half (0 for an
+    ///        ephemeral port). Returns INVAL if the config buffer is not the
+    ///        size of two endpoints.
+    /// - `3`: Send the first `arg1` bytes of the write buffer. Returns BUSY
+    ///        if a previous send has not been fully acknowledged yet, and
+    ///        SIZE if `arg1` is larger than the write buffer.
+    /// - `4`: Mark the read buffer as consumed, making all of it available
+    ///        for new data again.
+    /// - `5`: Close the connection gracefully after all sent data has been
+    ///        delivered.
+    /// - `6`: Abort the connection by sending a reset.
+    /// - `7`: Get the connection state, as the index of the state in RFC
+    ///        9293 order (0 = CLOSED, 1 = LISTEN, ... 10 = TIME-WAIT).
+    /// - `8`: Get the maximum number of bytes sent in a single segment.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => {
+                let result = self.socket_for(processid, true).and_then(|id| {
+                    let result = self
+                        .stack
+                        .listen(&self.sockets[id], arg1 as u16, self.net_cap);
+                    if result.is_err() {
+                        self.release_if_closed(processid, id);
+                    }
+                    result
+                });
+                result.into()
+            }
+
+            2 => self.connect(processid).into(),
+
+            3 => {
+                let result = self.socket_for(processid, false).and_then(|id| {
+                    self.apps
+                        .enter(processid, |app, kernel_data| {
+                            if app.tx_len != 0 {
+                                return Err(ErrorCode::BUSY);
+                            }
+                            let write_len = kernel_data
+                                .get_readonly_processbuffer(ro_allow::WRITE)
+                                .map_or(0, |write| write.len());
+                            if arg1 == 0 || arg1 > write_len {
+                                return Err(ErrorCode::SIZE);
+                            }
+                            app.tx_len = arg1;
+                            app.tx_acked = 0;
+                            Ok(())
+                        })
+                        .map_err(ErrorCode::from)??;
+                    let result = self.stack.send(&self.sockets[id], arg1);
+                    if result.is_err() {
+                        let _ = self.apps.enter(processid, |app, _| app.tx_len = 0);
+                    }
+                    result
+                });
+                result.into()
+            }
+
+            4 => {
+                let result = self.socket_for(processid, false).and_then(|id| {
+                    self.apps
+                        .enter(processid, |app, _| app.rx_len = 0)
+                        .map_err(ErrorCode::from)?;
+                    self.stack.window_update(&self.sockets[id]);
+                    Ok(())
+                });
+                result.into()
+            }
+
+            5 => {
+                let result = self.socket_for(processid, false).and_then(|id| {
+                    let result = self.stack.close(&self.sockets[id]);
+                    self.release_if_closed(processid, id);
+                    result
+                });
+                result.into()
+            }
+
+            6 => {
+                let result = self.socket_for(processid, false).map(|id| {
+                    self.stack.abort(&self.sockets[id]);
+                    self.release_if_closed(processid, id);
+                });
+                result.into()
+            }
+
+            7 => match self.socket_for(processid, false) {
+                Ok(id) => CommandReturn::success_u32(self.sockets[id].get_state() as u32),
+                Err(ErrorCode::RESERVE) => CommandReturn::success_u32(0),
+                Err(e) => CommandReturn::failure(e),
+            },
+
+            8 => CommandReturn::success_u32(self.stack.max_segment_size() as u32),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+impl<'a> TCPClient for TCPDriver<'a> {
+    fn connected(&self, socket_id: usize) {
+        let (remote_addr, remote_port) = self.sockets[socket_id].get_remote_endpoint();
+        self.owner(socket_id).map(|processid| {
+            let _ = self.apps.enter(processid, |_, kernel_data| {
+                let _ = kernel_data
+                    .get_readwrite_processbuffer(rw_allow::CFG)
+                    .and_then(|cfg| {
+                        cfg.mut_enter(|cfg| {
+                            if cfg.len() == 2 * ENDPOINT_LEN {
+                                cfg[ENDPOINT_LEN..ENDPOINT_LEN + size_of::<IPAddr>()]
+                                    .copy_from_slice(&remote_addr.0);
+                                cfg[ENDPOINT_LEN + size_of::<IPAddr>()..]
+                                    .copy_from_slice(&remote_port.to_le_bytes());
+                            }
+                        })
+                    });
+                kernel_data
+                    .schedule_upcall(upcall::CONNECTED, (0, 0, 0))
+                    .ok();
+            });
+        });
+    }
+
+    fn receive(&self, socket_id: usize, data: &[u8]) -> usize {
+        self.owner(socket_id).map_or(0, |processid| {
+            self.apps
+                .enter(processid, |app, kernel_data| {
+                    let accepted = kernel_data
+                        .get_readwrite_processbuffer(rw_allow::READ)
+                        .and_then(|read| {
+                            read.mut_enter(|rbuf| {
+                                let space = rbuf.len().saturating_sub(app.rx_len);
+                                let len = cmp::min(space, data.len());
+                                rbuf[app.rx_len..app.rx_len + len].copy_from_slice(&data[..len]);
+                                len
+                            })
+                        })
+                        .unwrap_or(0);
+                    if accepted > 0 {
+                        app.rx_len += accepted;
+                        kernel_data
+                            .schedule_upcall(upcall::RECEIVED, (app.rx_len, 0, 0))
+                            .ok();
+                    }
+                    accepted
+                })
+                .unwrap_or(0)
+        })
+    }
+
+    fn receive_window(&self, socket_id: usize) -> usize {
+        self.owner(socket_id).map_or(0, |processid| {
+            self.apps
+                .enter(processid, |app, kernel_data| {
+                    kernel_data
+                        .get_readwrite_processbuffer(rw_allow::READ)
+                        .map_or(0, |read| read.len().saturating_sub(app.rx_len))
+                })
+                .unwrap_or(0)
+        })
+    }
+
+    fn send_data(&self, socket_id: usize, offset: usize, buf: &mut [u8]) -> usize {
+        self.owner(socket_id).map_or(0, |processid| {
+            self.apps
+                .enter(processid, |app, kernel_data| {
+                    let start = app.tx_acked + offset;
+                    let end = cmp::min(start + buf.len(), app.tx_len);
+                    if start >= end {
+                        return 0;
+                    }
+                    kernel_data
+                        .get_readonly_processbuffer(ro_allow::WRITE)
+                        .and_then(|write| {
+                            write.enter(|wbuf| match wbuf.get(start..end) {
+                                Some(src) => {
+                                    src.copy_to_slice(&mut buf[..end - start]);
+                                    end - start
+                                }
+                                // The buffer was shrunk after the send.
+                                None => 0,
+                            })
+                        })
+                        .unwrap_or(0)
+                })
+                .unwrap_or(0)
+        })
+    }
+
+    fn send_acked(&self, socket_id: usize, len: usize) {
+        self.owner(socket_id).map(|processid| {
+            let _ = self.apps.enter(processid, |app, kernel_data| {
+                app.tx_acked += len;
+                if app.tx_len != 0 && app.tx_acked >= app.tx_len {
+                    let sent = app.tx_len;
+                    app.tx_len = 0;
+                    app.tx_acked = 0;
+                    kernel_data.schedule_upcall(upcall::SENT, (0, sent, 0)).ok();
+                }
+            });
+        });
+    }
+
+    fn remote_closed(&self, socket_id: usize) {
+        self.owner(socket_id).map(|processid| {
+            let _ = self.apps.enter(processid, |_, kernel_data| {
+                kernel_data.schedule_upcall(upcall::CLOSED, (0, 1, 0)).ok();
+            });
+        });
+    }
+
+    fn closed(&self, socket_id: usize, result: Result<(), ErrorCode>) {
+        self.owner(socket_id).map(|processid| {
+            let _ = self.apps.enter(processid, |app, kernel_data| {
+                if app.tx_len != 0 && result.is_err() {
+                    kernel_data
+                        .schedule_upcall(
+                            upcall::SENT,
+                            (kernel::errorcode::into_statuscode(result), app.tx_acked, 0),
+                        )
+                        .ok();
+                }
+                **app = App::default();
+                kernel_data
+                    .schedule_upcall(
+                        upcall::CLOSED,
+                        (kernel::errorcode::into_statuscode(result), 0, 0),
+                    )
+                    .ok();
+            });
+        });
+    }
+}
@@ -0,0 +1,16 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+pub mod driver;
+pub mod tcp_connection;
+pub mod tcp_mux;
+
+pub use self::driver::TCPDriver;
+pub use self::driver::DRIVER_NUM;
+pub use self::tcp_connection::TCPState;
+
+// Reexport the exports of the [`tcp`] module, to avoid redundant
+// module paths (e.g. `capsules::net::tcp::tcp::TCPHeader`)
+mod tcp;
+pub use tcp::{tcp_flags, TCPHeader, TCP_HDR_LEN};
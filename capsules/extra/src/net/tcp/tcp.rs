@@ -0,0 +1,269 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! This file contains the structs and methods associated with the TCP header.
+//! This includes getters and setters for the various header fields, as well
+//! as the standard encode/decode functionality required for serializing
+//! the struct for transmission.
+//!
+//! The only TCP option that is understood is the Maximum Segment Size (MSS)
+//! option, which is sent on SYN segments. All other options are skipped when
+//! decoding a received header.
+
+use crate::net::stream::SResult;
+use crate::net::stream::{decode_u16, decode_u32, decode_u8};
+use crate::net::stream::{encode_u16, encode_u32, encode_u8};
+
+/// Length of a TCP header without any options.
+pub const TCP_HDR_LEN: usize = 20;
+
+/// Length of the MSS option as sent on SYN segments.
+const MSS_OPTION_LEN: usize = 4;
+
+/// Bit flags of the TCP control field.
+pub mod tcp_flags {
+    pub const FIN: u8 = 0x01;
+    pub const SYN: u8 = 0x02;
+    pub const RST: u8 = 0x04;
+    pub const PSH: u8 = 0x08;
+    pub const ACK: u8 = 0x10;
+    pub const URG: u8 = 0x20;
+}
+
+mod tcp_option {
+    pub const END: u8 = 0;
+    pub const NOP: u8 = 1;
+    pub const MSS: u8 = 2;
+}
+
+/// The `TCPHeader` struct follows the layout for the TCP segment header.
+/// Unlike `UDPHeader`, all fields are stored in host byte order and are
+/// converted when the header is encoded or decoded.
+#[derive(Copy, Clone, Debug)]
+pub struct TCPHeader {
+    src_port: u16,
+    dst_port: u16,
+    seq_num: u32,
+    ack_num: u32,
+    flags: u8,
+    window: u16,
+    cksum: u16,
+    urg_ptr: u16,
+    mss: Option<u16>,
+    /// Length of the data offset field in bytes, as received. Only set on
+    /// decoded headers, since encoded headers compute their own size.
+    rx_hdr_len: usize,
+    len: u16, // Not a real TCP field, here for convenience (header + payload)
+}
+
+impl Default for TCPHeader {
+    fn default() -> TCPHeader {
+        TCPHeader {
+            src_port: 0,
+            dst_port: 0,
+            seq_num: 0,
+            ack_num: 0,
+            flags: 0,
+            window: 0,
+            cksum: 0,
+            urg_ptr: 0,
+            mss: None,
+            rx_hdr_len: 0,
+            len: TCP_HDR_LEN as u16,
+        }
+    }
+}
+
+impl TCPHeader {
+    pub fn new() -> TCPHeader {
+        TCPHeader::default()
+    }
+
+    pub fn set_src_port(&mut self, port: u16) {
+        self.src_port = port;
+    }
+
+    pub fn set_dst_port(&mut self, port: u16) {
+        self.dst_port = port;
+    }
+
+    pub fn set_seq_num(&mut self, seq_num: u32) {
+        self.seq_num = seq_num;
+    }
+
+    pub fn set_ack_num(&mut self, ack_num: u32) {
+        self.ack_num = ack_num;
+    }
+
+    pub fn set_flags(&mut self, flags: u8) {
+        self.flags = flags;
+    }
+
+    pub fn set_window(&mut self, window: u16) {
+        self.window = window;
+    }
+
+    pub fn set_cksum(&mut self, cksum: u16) {
+        self.cksum = cksum;
+    }
+
+    /// Sets the MSS option. The option is only encoded when it is set, and
+    /// should only be set on segments carrying SYN.
+    pub fn set_mss(&mut self, mss: Option<u16>) {
+        self.mss = mss;
+    }
+
+    pub fn set_len(&mut self, len: u16) {
+        self.len = len;
+    }
+
+    pub fn get_src_port(&self) -> u16 {
+        self.src_port
+    }
+
+    pub fn get_dst_port(&self) -> u16 {
+        self.dst_port
+    }
+
+    pub fn get_seq_num(&self) -> u32 {
+        self.seq_num
+    }
+
+    pub fn get_ack_num(&self) -> u32 {
+        self.ack_num
+    }
+
+    pub fn get_flags(&self) -> u8 {
+        self.flags
+    }
+
+    pub fn has_flags(&self, flags: u8) -> bool {
+        self.flags & flags == flags
+    }
+
+    pub fn get_window(&self) -> u16 {
+        self.window
+    }
+
+    pub fn get_cksum(&self) -> u16 {
+        self.cksum
+    }
+
+    pub fn get_mss(&self) -> Option<u16> {
+        self.mss
+    }
+
+    pub fn get_len(&self) -> u16 {
+        self.len
+    }
+
+    /// Returns the size of the header, including options. For a decoded
+    /// header this is the size indicated by the data offset field.
+    pub fn get_hdr_size(&self) -> usize {
+        if self.rx_hdr_len != 0 {
+            self.rx_hdr_len
+        } else if self.mss.is_some() {
+            TCP_HDR_LEN + MSS_OPTION_LEN
+        } else {
+            TCP_HDR_LEN
+        }
+    }
+
+    /// This function serializes the `TCPHeader` into the provided buffer.
+    ///
+    /// # Arguments
+    ///
+    /// `buf` - A mutable buffer to serialize the `TCPHeader` into
+    /// `offset` - The current offset into the provided buffer
+    ///
+    /// # Return Value
+    ///
+    /// This function returns the new offset into the buffer wrapped in an
+    /// SResult.
+    pub fn encode(&self, buf: &mut [u8], offset: usize) -> SResult<usize> {
+        let hdr_size = if self.mss.is_some() {
+            TCP_HDR_LEN + MSS_OPTION_LEN
+        } else {
+            TCP_HDR_LEN
+        };
+        stream_len_cond!(buf, hdr_size + offset);
+
+        let data_offset = ((hdr_size / 4) as u16) << 12;
+        let mut off = offset;
+        off = enc_consume!(buf, off; encode_u16, self.src_port);
+        off = enc_consume!(buf, off; encode_u16, self.dst_port);
+        off = enc_consume!(buf, off; encode_u32, self.seq_num);
+        off = enc_consume!(buf, off; encode_u32, self.ack_num);
+        off = enc_consume!(buf, off; encode_u16, data_offset | self.flags as u16);
+        off = enc_consume!(buf, off; encode_u16, self.window);
+        off = enc_consume!(buf, off; encode_u16, self.cksum);
+        off = enc_consume!(buf, off; encode_u16, self.urg_ptr);
+        if let Some(mss) = self.mss {
+            off = enc_consume!(buf, off; encode_u8, tcp_option::MSS);
+            off = enc_consume!(buf, off; encode_u8, MSS_OPTION_LEN as u8);
+            off = enc_consume!(buf, off; encode_u16, mss);
+        }
+        stream_done!(off, off);
+    }
+
+    /// This function deserializes the `TCPHeader` from the provided buffer.
+    /// The returned offset points to the start of the segment payload, i.e.
+    /// past any options.
+    ///
+    /// # Arguments
+    ///
+    /// `buf` - The byte array corresponding to a serialized `TCPHeader`
+    ///
+    /// # Return Value
+    ///
+    /// This function returns a `TCPHeader` struct wrapped in an SResult
+    pub fn decode(buf: &[u8]) -> SResult<TCPHeader> {
+        stream_len_cond!(buf, TCP_HDR_LEN);
+        let mut tcp_header = Self::new();
+        let off = 0;
+        let (off, src_port) = dec_try!(buf, off; decode_u16);
+        tcp_header.src_port = src_port;
+        let (off, dst_port) = dec_try!(buf, off; decode_u16);
+        tcp_header.dst_port = dst_port;
+        let (off, seq_num) = dec_try!(buf, off; decode_u32);
+        tcp_header.seq_num = seq_num;
+        let (off, ack_num) = dec_try!(buf, off; decode_u32);
+        tcp_header.ack_num = ack_num;
+        let (off, offset_and_control) = dec_try!(buf, off; decode_u16);
+        tcp_header.flags = (offset_and_control & 0x3f) as u8;
+        let (off, window) = dec_try!(buf, off; decode_u16);
+        tcp_header.window = window;
+        let (off, cksum) = dec_try!(buf, off; decode_u16);
+        tcp_header.cksum = cksum;
+        let (mut off, urg_ptr) = dec_try!(buf, off; decode_u16);
+        tcp_header.urg_ptr = urg_ptr;
+
+        let hdr_len = ((offset_and_control >> 12) as usize) * 4;
+        stream_cond!(hdr_len >= TCP_HDR_LEN);
+        stream_len_cond!(buf, hdr_len);
+        tcp_header.rx_hdr_len = hdr_len;
+        tcp_header.len = buf.len() as u16;
+
+        // Walk the options, only keeping the MSS.
+        while off < hdr_len {
+            let (next, kind) = dec_try!(buf, off; decode_u8);
+            match kind {
+                tcp_option::END => break,
+                tcp_option::NOP => off = next,
+                _ => {
+                    stream_cond!(next < hdr_len);
+                    let (_, opt_len) = dec_try!(buf, next; decode_u8);
+                    let opt_len = opt_len as usize;
+                    stream_cond!(opt_len >= 2 && off + opt_len <= hdr_len);
+                    if kind == tcp_option::MSS && opt_len == MSS_OPTION_LEN {
+                        let (_, mss) = dec_try!(buf, off + 2; decode_u16);
+                        tcp_header.mss = Some(mss);
+                    }
+                    off += opt_len;
+                }
+            }
+        }
+        stream_done!(hdr_len, tcp_header);
+    }
+}
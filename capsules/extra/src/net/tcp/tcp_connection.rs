@@ -0,0 +1,432 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! This file contains the transmission control block (TCB) kept for each TCP
+//! connection, along with the connection states defined in RFC 9293 and the
+//! helpers for comparing sequence numbers modulo 2^32.
+//!
+//! The `TCPConnection` struct only holds state; the segment processing that
+//! drives the state machine lives in `tcp_mux.rs`, since it needs access to
+//! the client of each socket.
+
+use crate::net::ipv6::ip_utils::IPAddr;
+
+/// Granularity of all TCP timers, in milliseconds.
+pub const TCP_TIMER_MS: u32 = 250;
+/// Initial retransmission timeout (RFC 6298, section 2.1).
+pub const INITIAL_RTO_MS: u32 = 1000;
+/// Lower bound on the retransmission timeout (RFC 6298, section 2.4).
+pub const MIN_RTO_MS: u32 = 1000;
+/// Upper bound on the retransmission timeout.
+pub const MAX_RTO_MS: u32 = 60_000;
+/// Number of retransmissions of a segment before the connection is aborted.
+pub const MAX_RETRANSMISSIONS: u8 = 6;
+/// Time spent in TIME-WAIT. RFC 9293 asks for 2*MSL, which is impractically
+/// long for devices with only a handful of sockets, so this is shortened.
+pub const TIME_WAIT_MS: u32 = 4000;
+/// Default send MSS if the peer does not send an MSS option (RFC 9293,
+/// section 3.7.1, for IPv6).
+pub const DEFAULT_IPV6_MSS: u16 = 1220;
+
+/// Connection states, as defined in RFC 9293, section 3.3.2.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TCPState {
+    Closed,
+    Listen,
+    SynSent,
+    SynReceived,
+    Established,
+    FinWait1,
+    FinWait2,
+    CloseWait,
+    Closing,
+    LastAck,
+    TimeWait,
+}
+
+impl TCPState {
+    /// Returns true once the three-way handshake has completed, i.e. the
+    /// state is one in which both sides have synchronized sequence numbers.
+    pub fn is_synchronized(&self) -> bool {
+        !matches!(
+            self,
+            TCPState::Closed | TCPState::Listen | TCPState::SynSent | TCPState::SynReceived
+        )
+    }
+
+    /// Returns true in states where data from the peer can be accepted.
+    pub fn can_receive(&self) -> bool {
+        matches!(
+            self,
+            TCPState::Established | TCPState::FinWait1 | TCPState::FinWait2
+        )
+    }
+
+    /// Returns true in states where the local side may still queue data.
+    pub fn can_send(&self) -> bool {
+        matches!(self, TCPState::Established | TCPState::CloseWait)
+    }
+}
+
+/// Returns true if `a` precedes `b` in sequence space.
+pub fn seq_lt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+/// Returns true if `a` precedes or is equal to `b` in sequence space.
+pub fn seq_le(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) <= 0
+}
+
+/// The transmission control block of a single connection. Timers are
+/// expressed in multiples of `TCP_TIMER_MS`, with 0 meaning the timer is not
+/// running.
+#[derive(Copy, Clone, Debug)]
+pub struct TCPConnection {
+    pub state: TCPState,
+    /// Whether the connection was opened passively (from LISTEN), in which
+    /// case a reset in SYN-RECEIVED returns the socket to LISTEN.
+    pub passive: bool,
+    pub local_port: u16,
+    pub remote_addr: IPAddr,
+    pub remote_port: u16,
+
+    // Send sequence variables
+    pub iss: u32,
+    pub snd_una: u32,
+    pub snd_nxt: u32,
+    pub snd_wnd: u16,
+    pub snd_mss: u16,
+    /// Number of data bytes the client has queued, counted from `snd_una`.
+    /// This does not include the SYN or FIN.
+    pub tx_len: usize,
+    /// Set once the client has closed its side of the connection. The FIN
+    /// is sent after all of `tx_len` has been sent.
+    pub fin_queued: bool,
+
+    // Receive sequence variables
+    pub irs: u32,
+    pub rcv_nxt: u32,
+
+    /// An ACK should be sent even if there is no data to send.
+    pub ack_pending: bool,
+
+    // Retransmission state (RFC 6298), in milliseconds
+    pub srtt_ms: u32,
+    pub rttvar_ms: u32,
+    pub rto_ms: u32,
+    pub retx_timer: u16,
+    pub retx_count: u8,
+    /// Send a one byte window probe when the retransmission timer fires
+    /// while the peer advertises a zero window.
+    pub probe_pending: bool,
+    /// Sequence number being timed for an RTT sample, and elapsed ticks.
+    pub rtt_seq: Option<u32>,
+    pub rtt_ticks: u16,
+
+    pub time_wait_timer: u16,
+}
+
+impl Default for TCPConnection {
+    fn default() -> TCPConnection {
+        TCPConnection {
+            state: TCPState::Closed,
+            passive: false,
+            local_port: 0,
+            remote_addr: IPAddr::new(),
+            remote_port: 0,
+            iss: 0,
+            snd_una: 0,
+            snd_nxt: 0,
+            snd_wnd: 0,
+            snd_mss: DEFAULT_IPV6_MSS,
+            tx_len: 0,
+            fin_queued: false,
+            irs: 0,
+            rcv_nxt: 0,
+            ack_pending: false,
+            srtt_ms: 0,
+            rttvar_ms: 0,
+            rto_ms: INITIAL_RTO_MS,
+            retx_timer: 0,
+            retx_count: 0,
+            probe_pending: false,
+            rtt_seq: None,
+            rtt_ticks: 0,
+            time_wait_timer: 0,
+        }
+    }
+}
+
+impl TCPConnection {
+    pub fn new() -> TCPConnection {
+        TCPConnection::default()
+    }
+
+    /// Returns a fresh TCB for an active or passive open, keeping only the
+    /// local port.
+    pub fn reset(&mut self) {
+        let local_port = self.local_port;
+        *self = TCPConnection::default();
+        self.local_port = local_port;
+    }
+
+    /// Number of sequence numbers sent but not yet acknowledged.
+    pub fn in_flight(&self) -> usize {
+        self.snd_nxt.wrapping_sub(self.snd_una) as usize
+    }
+
+    /// Whether the SYN of this connection has been sent (and not rewound for
+    /// retransmission).
+    pub fn syn_sent(&self) -> bool {
+        self.snd_nxt != self.iss
+    }
+
+    /// Whether the FIN has been sent and not rewound for retransmission. In
+    /// FIN-WAIT-2 and TIME-WAIT the FIN has already been acknowledged.
+    pub fn fin_sent(&self) -> bool {
+        self.fin_queued
+            && (self.in_flight() > self.tx_len
+                || matches!(self.state, TCPState::FinWait2 | TCPState::TimeWait))
+    }
+
+    /// Number of queued data bytes that have not been sent yet.
+    pub fn unsent(&self) -> usize {
+        self.tx_len.saturating_sub(self.in_flight())
+    }
+
+    /// Returns whether a segment occupying `seg_len` sequence numbers and
+    /// starting at `seq` falls at least partially in the receive window, as
+    /// defined in RFC 9293, section 3.10.7.4.
+    pub fn segment_acceptable(&self, seq: u32, seg_len: u32, rcv_wnd: u32) -> bool {
+        let rcv_end = self.rcv_nxt.wrapping_add(rcv_wnd);
+        let in_window = |s: u32| seq_le(self.rcv_nxt, s) && seq_lt(s, rcv_end);
+        match (seg_len, rcv_wnd) {
+            (0, 0) => seq == self.rcv_nxt,
+            (0, _) => in_window(seq),
+            (_, 0) => false,
+            (_, _) => in_window(seq) || in_window(seq.wrapping_add(seg_len - 1)),
+        }
+    }
+
+    /// Starts (or restarts) the retransmission timer with the current RTO.
+    pub fn arm_retx_timer(&mut self) {
+        self.retx_timer = self.rto_ms.div_ceil(TCP_TIMER_MS).max(1) as u16;
+    }
+
+    /// Incorporates a new round-trip time measurement, following RFC 6298,
+    /// section 2.
+    pub fn update_rtt(&mut self, rtt_ms: u32) {
+        if self.srtt_ms == 0 {
+            self.srtt_ms = rtt_ms.max(1);
+            self.rttvar_ms = rtt_ms / 2;
+        } else {
+            let delta = self.srtt_ms.abs_diff(rtt_ms);
+            self.rttvar_ms = (3 * self.rttvar_ms + delta) / 4;
+            self.srtt_ms = (7 * self.srtt_ms + rtt_ms) / 8;
+        }
+        self.rto_ms =
+            (self.srtt_ms + TCP_TIMER_MS.max(4 * self.rttvar_ms)).clamp(MIN_RTO_MS, MAX_RTO_MS);
+    }
+
+    /// Backs off the retransmission timer after a timeout (RFC 6298, 5.5).
+    pub fn back_off(&mut self) {
+        self.rto_ms = (self.rto_ms * 2).min(MAX_RTO_MS);
+        self.rtt_seq = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seq_compare() {
+        assert!(seq_lt(1, 2));
+        assert!(!seq_lt(2, 1));
+        assert!(!seq_lt(7, 7));
+        assert!(seq_le(7, 7));
+        assert!(seq_le(1, 2));
+        assert!(!seq_le(2, 1));
+    }
+
+    #[test]
+    fn test_seq_compare_wraparound() {
+        assert!(seq_lt(0xFFFF_FFF0, 5));
+        assert!(!seq_lt(5, 0xFFFF_FFF0));
+        assert!(seq_lt(u32::MAX, 0));
+        assert!(seq_le(u32::MAX, 0));
+        assert!(!seq_le(0, u32::MAX));
+        // Half the sequence space away is the furthest a number can be and
+        // still precede another.
+        assert!(seq_lt(0, 0x7FFF_FFFF));
+        assert!(!seq_lt(0, 0x8000_0001));
+    }
+
+    #[test]
+    fn test_state_predicates() {
+        for state in [
+            TCPState::Closed,
+            TCPState::Listen,
+            TCPState::SynSent,
+            TCPState::SynReceived,
+        ] {
+            assert!(!state.is_synchronized());
+            assert!(!state.can_receive());
+            assert!(!state.can_send());
+        }
+        for state in [
+            TCPState::Established,
+            TCPState::FinWait1,
+            TCPState::FinWait2,
+            TCPState::CloseWait,
+            TCPState::Closing,
+            TCPState::LastAck,
+            TCPState::TimeWait,
+        ] {
+            assert!(state.is_synchronized());
+        }
+        assert!(TCPState::Established.can_receive());
+        assert!(TCPState::FinWait2.can_receive());
+        assert!(!TCPState::CloseWait.can_receive());
+        assert!(TCPState::CloseWait.can_send());
+        assert!(!TCPState::FinWait1.can_send());
+    }
+
+    #[test]
+    fn test_reset_keeps_local_port() {
+        let mut conn = TCPConnection::new();
+        conn.local_port = 8080;
+        conn.state = TCPState::Established;
+        conn.snd_nxt = 100;
+        conn.rto_ms = 8000;
+        conn.reset();
+        assert_eq!(conn.local_port, 8080);
+        assert_eq!(conn.state, TCPState::Closed);
+        assert_eq!(conn.snd_nxt, 0);
+        assert_eq!(conn.rto_ms, INITIAL_RTO_MS);
+    }
+
+    #[test]
+    fn test_send_sequence_accounting() {
+        let mut conn = TCPConnection::new();
+        conn.iss = u32::MAX - 1;
+        conn.snd_una = conn.iss;
+        conn.snd_nxt = conn.iss;
+        assert!(!conn.syn_sent());
+
+        // SYN, then 10 data bytes of the 15 queued, across the wrap.
+        conn.snd_nxt = conn.snd_nxt.wrapping_add(11);
+        conn.tx_len = 15;
+        assert!(conn.syn_sent());
+        assert_eq!(conn.in_flight(), 11);
+        assert_eq!(conn.unsent(), 4);
+
+        // The SYN is acknowledged, then the rest of the data and the FIN are
+        // sent.
+        conn.snd_una = conn.snd_una.wrapping_add(1);
+        conn.fin_queued = true;
+        assert!(!conn.fin_sent());
+        conn.snd_nxt = conn.snd_nxt.wrapping_add(5);
+        assert_eq!(conn.unsent(), 0);
+        assert!(!conn.fin_sent());
+        conn.snd_nxt = conn.snd_nxt.wrapping_add(1);
+        assert!(conn.fin_sent());
+
+        // Once the FIN is acknowledged it stays sent.
+        conn.snd_una = conn.snd_nxt;
+        conn.tx_len = 0;
+        conn.state = TCPState::FinWait2;
+        assert!(conn.fin_sent());
+    }
+
+    #[test]
+    fn test_segment_acceptable() {
+        let mut conn = TCPConnection::new();
+        conn.rcv_nxt = 1000;
+
+        // Zero length segments
+        assert!(conn.segment_acceptable(1000, 0, 0));
+        assert!(!conn.segment_acceptable(1001, 0, 0));
+        assert!(conn.segment_acceptable(1099, 0, 100));
+        assert!(!conn.segment_acceptable(1100, 0, 100));
+        assert!(!conn.segment_acceptable(999, 0, 100));
+
+        // Segments with data
+        assert!(!conn.segment_acceptable(1000, 10, 0));
+        assert!(conn.segment_acceptable(1000, 10, 100));
+        // Partially overlapping either edge of the window
+        assert!(conn.segment_acceptable(995, 10, 100));
+        assert!(conn.segment_acceptable(1095, 10, 100));
+        // Entirely before or after the window
+        assert!(!conn.segment_acceptable(990, 10, 100));
+        assert!(!conn.segment_acceptable(1100, 10, 100));
+    }
+
+    #[test]
+    fn test_segment_acceptable_wraparound() {
+        let mut conn = TCPConnection::new();
+        conn.rcv_nxt = u32::MAX - 9;
+
+        assert!(conn.segment_acceptable(u32::MAX - 9, 20, 100));
+        assert!(conn.segment_acceptable(5, 0, 100));
+        assert!(conn.segment_acceptable(89, 1, 100));
+        assert!(!conn.segment_acceptable(90, 1, 100));
+        assert!(conn.segment_acceptable(u32::MAX - 14, 10, 100));
+        assert!(!conn.segment_acceptable(u32::MAX - 19, 10, 100));
+    }
+
+    #[test]
+    fn test_rtt_estimation() {
+        let mut conn = TCPConnection::new();
+        assert_eq!(conn.rto_ms, INITIAL_RTO_MS);
+
+        // The first sample sets SRTT to R and RTTVAR to R/2.
+        conn.update_rtt(2000);
+        assert_eq!(conn.srtt_ms, 2000);
+        assert_eq!(conn.rttvar_ms, 1000);
+        assert_eq!(conn.rto_ms, 6000);
+
+        conn.update_rtt(1000);
+        assert_eq!(conn.rttvar_ms, (3 * 1000 + 1000) / 4);
+        assert_eq!(conn.srtt_ms, (7 * 2000 + 1000) / 8);
+        assert_eq!(conn.rto_ms, 1875 + 4 * 1000);
+    }
+
+    #[test]
+    fn test_rto_clamped() {
+        let mut conn = TCPConnection::new();
+        conn.update_rtt(10);
+        assert_eq!(conn.rto_ms, MIN_RTO_MS);
+
+        let mut conn = TCPConnection::new();
+        conn.update_rtt(50_000);
+        assert_eq!(conn.rto_ms, MAX_RTO_MS);
+    }
+
+    #[test]
+    fn test_back_off() {
+        let mut conn = TCPConnection::new();
+        conn.rtt_seq = Some(42);
+        conn.back_off();
+        assert_eq!(conn.rto_ms, 2 * INITIAL_RTO_MS);
+        assert_eq!(conn.rtt_seq, None);
+
+        for _ in 0..10 {
+            conn.back_off();
+        }
+        assert_eq!(conn.rto_ms, MAX_RTO_MS);
+    }
+
+    #[test]
+    fn test_arm_retx_timer() {
+        let mut conn = TCPConnection::new();
+        conn.arm_retx_timer();
+        assert_eq!(conn.retx_timer as u32, INITIAL_RTO_MS / TCP_TIMER_MS);
+
+        conn.rto_ms = TCP_TIMER_MS + 1;
+        conn.arm_retx_timer();
+        assert_eq!(conn.retx_timer, 2);
+    }
+}
@@ -0,0 +1,1413 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! This file contains the TCP layer of the networking stack. The
+//! [MuxTcp](struct.MuxTcp.html) struct multiplexes a set of
+//! [TCPSocket](struct.TCPSocket.html)s over a single `IP6Sender`, demultiplexes
+//! received segments to them, and runs the connection state machine from
+//! RFC 9293 along with retransmission on a virtual alarm.
+//!
+//! Sockets do not own any data buffers. Instead, the layer above a socket
+//! (its [TCPClient](trait.TCPClient.html)) keeps the bytes it has queued for
+//! transmission until they are acknowledged, and the TCP layer pulls them out
+//! with `TCPClient::send_data` whenever a segment (or a retransmission) is
+//! built. Received in-order data is pushed to the client with
+//! `TCPClient::receive`, and the advertised window is whatever the client
+//! reports through `TCPClient::receive_window`. Out-of-order segments are
+//! dropped and recovered through retransmission by the peer, which keeps the
+//! per-connection state to a few dozen bytes.
+//!
+//! Only one segment is handed to the IP layer at a time; every socket with
+//! pending output is serviced in turn as previous segments complete.
+//!
+//! Usage
+//! -----
+//! A kernel capsule creates a `TCPSocket`, registers it with
+//! `TCPStack::add_socket`, sets itself as the socket's client, and then opens
+//! the connection with `TCPStack::connect` or `TCPStack::listen`.
+
+use crate::net::ipv6::ip_utils::{ip6_nh, IPAddr};
+use crate::net::ipv6::ipv6_recv::IP6RecvClient;
+use crate::net::ipv6::ipv6_send::{IP6SendClient, IP6Sender};
+use crate::net::ipv6::{IP6Header, TransportHeader};
+use crate::net::network_capabilities::NetworkCapability;
+use crate::net::tcp::tcp_connection::{
+    seq_le, seq_lt, TCPConnection, TCPState, MAX_RETRANSMISSIONS, TCP_TIMER_MS, TIME_WAIT_MS,
+};
+use crate::net::tcp::tcp_flags;
+use crate::net::tcp::TCPHeader;
+
+use core::cell::Cell;
+use core::cmp;
+
+use kernel::collections::list::{List, ListLink, ListNode};
+use kernel::debug;
+use kernel::hil::time::{self, ConvertTicks, Ticks};
+use kernel::utilities::cells::{MapCell, OptionalCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::ErrorCode;
+
+/// First port handed out for active opens without an explicit local port
+/// (RFC 6335 dynamic port range).
+const EPHEMERAL_PORT_START: u16 = 49152;
+
+/// The interface a user of a `TCPSocket` implements to be notified of
+/// connection events and to exchange data with the TCP layer. Every method
+/// receives the id the socket was created with, so that a single client can
+/// serve several sockets.
+pub trait TCPClient {
+    /// The three-way handshake has completed and the connection is
+    /// established.
+    fn connected(&self, socket_id: usize);
+
+    /// In-order data has been received. Returns how many bytes of `data`
+    /// the client has accepted; anything not accepted is not acknowledged
+    /// and will be retransmitted by the peer.
+    fn receive(&self, socket_id: usize, data: &[u8]) -> usize;
+
+    /// Returns how many more bytes the client is able to receive. This is
+    /// used as the advertised receive window.
+    fn receive_window(&self, socket_id: usize) -> usize;
+
+    /// Copies queued transmit data into `buf`, starting `offset` bytes after
+    /// the oldest unacknowledged byte. Returns the number of bytes copied.
+    fn send_data(&self, socket_id: usize, offset: usize, buf: &mut [u8]) -> usize;
+
+    /// The peer has acknowledged the oldest `len` queued bytes, which the
+    /// client can now release.
+    fn send_acked(&self, socket_id: usize, len: usize);
+
+    /// The peer has closed its side of the connection; no more data will be
+    /// received.
+    fn remote_closed(&self, socket_id: usize);
+
+    /// The connection is now closed. `Ok(())` indicates a graceful close,
+    /// `CANCEL` that it was reset by the peer, and `NOACK` that it timed out.
+    fn closed(&self, socket_id: usize, result: Result<(), ErrorCode>);
+}
+
+/// A single TCP connection endpoint. Sockets are allocated statically by the
+/// board and registered with a `TCPStack`.
+pub struct TCPSocket<'a> {
+    id: usize,
+    conn: Cell<TCPConnection>,
+    client: OptionalCell<&'a dyn TCPClient>,
+    net_cap: OptionalCell<&'static NetworkCapability>,
+    next: ListLink<'a, TCPSocket<'a>>,
+}
+
+impl<'a> ListNode<'a, TCPSocket<'a>> for TCPSocket<'a> {
+    fn next(&'a self) -> &'a ListLink<'a, TCPSocket<'a>> {
+        &self.next
+    }
+}
+
+impl<'a> TCPSocket<'a> {
+    pub fn new(id: usize) -> TCPSocket<'a> {
+        TCPSocket {
+            id,
+            conn: Cell::new(TCPConnection::new()),
+            client: OptionalCell::empty(),
+            net_cap: OptionalCell::empty(),
+            next: ListLink::empty(),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn TCPClient) {
+        self.client.set(client);
+    }
+
+    pub fn get_id(&self) -> usize {
+        self.id
+    }
+
+    pub fn get_state(&self) -> TCPState {
+        self.conn.get().state
+    }
+
+    pub fn get_local_port(&self) -> u16 {
+        self.conn.get().local_port
+    }
+
+    /// Returns the remote address and port of the connection. Only
+    /// meaningful once a connection has been initiated or accepted.
+    pub fn get_remote_endpoint(&self) -> (IPAddr, u16) {
+        let conn = self.conn.get();
+        (conn.remote_addr, conn.remote_port)
+    }
+
+    /// Returns the number of queued bytes that have not yet been
+    /// acknowledged by the peer.
+    pub fn get_unacked_len(&self) -> usize {
+        self.conn.get().tx_len
+    }
+
+    fn update<R, F: FnOnce(&mut TCPConnection) -> R>(&self, f: F) -> R {
+        let mut conn = self.conn.get();
+        let ret = f(&mut conn);
+        self.conn.set(conn);
+        ret
+    }
+
+    fn has_timers(&self) -> bool {
+        let conn = self.conn.get();
+        conn.retx_timer != 0 || conn.time_wait_timer != 0
+    }
+}
+
+/// This trait provides the interface used by kernel capsules and the
+/// userspace driver to open, use, and close TCP connections.
+pub trait TCPStack<'a> {
+    /// Registers a socket with this stack. A socket must be added before any
+    /// of the other operations are used on it.
+    fn add_socket(&self, socket: &'a TCPSocket<'a>);
+
+    /// Passively opens `socket` on `local_port`. Returns `BUSY` if the port
+    /// is already in use or the socket is not closed.
+    fn listen(
+        &self,
+        socket: &'a TCPSocket<'a>,
+        local_port: u16,
+        net_cap: &'static NetworkCapability,
+    ) -> Result<(), ErrorCode>;
+
+    /// Actively opens a connection from `socket` to `dst:dst_port`. If
+    /// `src_port` is 0 an ephemeral port is chosen.
+    fn connect(
+        &self,
+        socket: &'a TCPSocket<'a>,
+        dst: IPAddr,
+        dst_port: u16,
+        src_port: u16,
+        net_cap: &'static NetworkCapability,
+    ) -> Result<(), ErrorCode>;
+
+    /// Informs the stack that the client has queued `len` more bytes for
+    /// transmission, to be read through `TCPClient::send_data`.
+    fn send(&self, socket: &'a TCPSocket<'a>, len: usize) -> Result<(), ErrorCode>;
+
+    /// Informs the stack that the client has freed receive space, so that a
+    /// window update can be sent to the peer.
+    fn window_update(&self, socket: &'a TCPSocket<'a>);
+
+    /// Gracefully closes the local side of the connection once all queued
+    /// data has been sent. A listening socket is closed immediately.
+    fn close(&self, socket: &'a TCPSocket<'a>) -> Result<(), ErrorCode>;
+
+    /// Resets the connection and closes the socket immediately. No callback
+    /// is issued.
+    fn abort(&self, socket: &'a TCPSocket<'a>);
+
+    /// Returns the largest payload this stack puts in a single segment.
+    fn max_segment_size(&self) -> usize;
+}
+
+/// A reset that must be sent in response to a segment that does not belong
+/// to any connection.
+#[derive(Copy, Clone)]
+struct PendingReset {
+    dst: IPAddr,
+    header: TCPHeader,
+}
+
+pub struct MuxTcp<'a, A: time::Alarm<'a>> {
+    ip_sender: &'a dyn IP6Sender<'a>,
+    alarm: &'a A,
+    sockets: List<'a, TCPSocket<'a>>,
+    tx_buffer: MapCell<SubSliceMut<'static, u8>>,
+    sending: Cell<bool>,
+    pending_rst: OptionalCell<PendingReset>,
+    rst_net_cap: &'static NetworkCapability,
+    next_port: Cell<u16>,
+    iss_counter: Cell<u32>,
+}
+
+impl<'a, A: time::Alarm<'a>> MuxTcp<'a, A> {
+    /// Creates the TCP layer. `tx_buffer` is used to assemble segment
+    /// payloads and its length is the MSS announced to peers; the IP sender's
+    /// packet buffer must be at least as large, and `rst_net_cap` is the
+    /// capability used to send resets for segments that match no socket.
+    pub fn new(
+        ip_sender: &'a dyn IP6Sender<'a>,
+        alarm: &'a A,
+        tx_buffer: &'static mut [u8],
+        rst_net_cap: &'static NetworkCapability,
+    ) -> MuxTcp<'a, A> {
+        MuxTcp {
+            ip_sender,
+            alarm,
+            sockets: List::new(),
+            tx_buffer: MapCell::new(SubSliceMut::new(tx_buffer)),
+            sending: Cell::new(false),
+            pending_rst: OptionalCell::empty(),
+            rst_net_cap,
+            next_port: Cell::new(EPHEMERAL_PORT_START),
+            iss_counter: Cell::new(0),
+        }
+    }
+
+    fn port_in_use(&self, port: u16) -> bool {
+        self.sockets.iter().any(|sock| {
+            let conn = sock.conn.get();
+            conn.state != TCPState::Closed && conn.local_port == port
+        })
+    }
+
+    fn alloc_ephemeral_port(&self) -> Option<u16> {
+        for _ in EPHEMERAL_PORT_START..=u16::MAX {
+            let port = self.next_port.get();
+            self.next_port.set(if port == u16::MAX {
+                EPHEMERAL_PORT_START
+            } else {
+                port + 1
+            });
+            if !self.port_in_use(port) {
+                return Some(port);
+            }
+        }
+        None
+    }
+
+    /// Picks an initial sequence number. This combines the clock with a
+    /// counter stepped for every connection, which is weaker than RFC 6528
+    /// but avoids reusing sequence numbers across quick reconnects.
+    fn new_iss(&self) -> u32 {
+        let counter = self.iss_counter.get().wrapping_add(64000);
+        self.iss_counter.set(counter);
+        self.alarm
+            .now()
+            .into_u32()
+            .wrapping_mul(251)
+            .wrapping_add(counter)
+    }
+
+    fn rcv_window(&self, socket: &TCPSocket<'a>) -> u16 {
+        socket
+            .client
+            .map_or(0, |client| client.receive_window(socket.id))
+            .min(u16::MAX as usize) as u16
+    }
+
+    /// Ensures the timer is running while any socket has a timer armed.
+    fn start_timer(&self) {
+        if !self.alarm.is_armed() && self.sockets.iter().any(|sock| sock.has_timers()) {
+            self.alarm
+                .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(TCP_TIMER_MS));
+        }
+    }
+
+    /// Moves `socket` to CLOSED and notifies its client.
+    fn close_socket(&self, socket: &TCPSocket<'a>, result: Result<(), ErrorCode>) {
+        socket.update(|conn| {
+            conn.reset();
+            conn.local_port = 0;
+        });
+        socket.client.map(|client| client.closed(socket.id, result));
+    }
+
+    /// Builds the next segment `socket` needs to send, if any, copying its
+    /// payload into `buf`. Returns the header and the payload length.
+    fn build_segment(&self, socket: &TCPSocket<'a>, buf: &mut [u8]) -> Option<(TCPHeader, usize)> {
+        let mut conn = socket.conn.get();
+        let mut header = TCPHeader::new();
+        header.set_src_port(conn.local_port);
+        header.set_dst_port(conn.remote_port);
+        header.set_window(self.rcv_window(socket));
+
+        let mut flags = 0;
+        let mut len = 0;
+        let seq = conn.snd_nxt;
+
+        match conn.state {
+            TCPState::SynSent | TCPState::SynReceived if !conn.syn_sent() => {
+                flags = tcp_flags::SYN;
+                if conn.state == TCPState::SynReceived {
+                    flags |= tcp_flags::ACK;
+                }
+                // The transmit buffer is taken while segments are built, so
+                // the MSS comes from `buf` rather than `max_segment_size`.
+                header.set_mss(Some(buf.len().min(u16::MAX as usize) as u16));
+                conn.snd_nxt = conn.snd_nxt.wrapping_add(1);
+            }
+            state if state.is_synchronized() => {
+                let in_flight = conn.in_flight();
+                let mut usable = (conn.snd_wnd as usize).saturating_sub(in_flight);
+                if conn.probe_pending {
+                    usable = cmp::max(usable, 1);
+                }
+                len = cmp::min(cmp::min(conn.unsent(), usable), conn.snd_mss as usize);
+                len = cmp::min(len, buf.len());
+                if len > 0 {
+                    len = socket.client.map_or(0, |client| {
+                        client.send_data(socket.id, in_flight, &mut buf[..len])
+                    });
+                }
+                if len > 0 {
+                    flags = tcp_flags::ACK | tcp_flags::PSH;
+                    conn.snd_nxt = conn.snd_nxt.wrapping_add(len as u32);
+                    conn.probe_pending = false;
+                }
+
+                // The FIN goes out once every queued byte has been sent.
+                if conn.fin_queued && !conn.fin_sent() && conn.unsent() == 0 {
+                    flags |= tcp_flags::FIN | tcp_flags::ACK;
+                    conn.snd_nxt = conn.snd_nxt.wrapping_add(1);
+                    conn.state = match conn.state {
+                        TCPState::Established => TCPState::FinWait1,
+                        TCPState::CloseWait => TCPState::LastAck,
+                        other => other,
+                    };
+                }
+
+                if flags == 0 && conn.ack_pending {
+                    flags = tcp_flags::ACK;
+                }
+            }
+            _ => {}
+        }
+
+        if flags == 0 {
+            // Data is waiting on a zero window: arm the persist timer so the
+            // window is probed.
+            if conn.state.is_synchronized()
+                && conn.unsent() > 0
+                && conn.snd_wnd == 0
+                && conn.retx_timer == 0
+            {
+                conn.arm_retx_timer();
+                socket.conn.set(conn);
+            }
+            return None;
+        }
+
+        header.set_flags(flags);
+        header.set_seq_num(seq);
+        if flags & tcp_flags::ACK != 0 {
+            header.set_ack_num(conn.rcv_nxt);
+        }
+        conn.ack_pending = false;
+
+        // Anything occupying sequence space is covered by the retransmission
+        // timer, and one segment at a time is timed for an RTT sample.
+        if conn.snd_nxt != seq {
+            if conn.retx_timer == 0 {
+                conn.arm_retx_timer();
+            }
+            if conn.rtt_seq.is_none() && conn.retx_count == 0 {
+                conn.rtt_seq = Some(seq);
+                conn.rtt_ticks = 0;
+            }
+        }
+        socket.conn.set(conn);
+        Some((header, len))
+    }
+
+    /// Hands the next pending segment to the IP layer, if it is idle.
+    fn do_output(&self) {
+        if self.sending.get() {
+            return;
+        }
+        self.tx_buffer.take().map(|mut buf| {
+            if let Some(rst) = self.pending_rst.take() {
+                buf.slice(0..0);
+                self.transmit(rst.dst, rst.header, &buf, self.rst_net_cap);
+            } else {
+                for socket in self.sockets.iter() {
+                    let built = self.build_segment(socket, buf.as_slice());
+                    if let Some((header, len)) = built {
+                        buf.slice(0..len);
+                        let net_cap = socket.net_cap.get();
+                        if let Some(net_cap) = net_cap {
+                            let dst = socket.conn.get().remote_addr;
+                            self.transmit(dst, header, &buf, net_cap);
+                        }
+                        break;
+                    }
+                }
+            }
+            buf.reset();
+            self.tx_buffer.replace(buf);
+        });
+        self.start_timer();
+    }
+
+    fn transmit(
+        &self,
+        dst: IPAddr,
+        header: TCPHeader,
+        buf: &SubSliceMut<'static, u8>,
+        net_cap: &'static NetworkCapability,
+    ) {
+        match self
+            .ip_sender
+            .send_to(dst, TransportHeader::TCP(header), buf, net_cap)
+        {
+            Ok(()) => self.sending.set(true),
+            // The segment is lost; retransmission will recover it.
+            Err(e) => debug!("[TCP] IP send_to failed: {:?}", e),
+        }
+    }
+
+    /// Queues a reset in response to `header`, which matched no connection
+    /// (RFC 9293, section 3.10.7.1).
+    fn send_reset_for(&self, src: IPAddr, header: &TCPHeader, seg_len: u32) {
+        if header.has_flags(tcp_flags::RST) {
+            return;
+        }
+        let mut rst = TCPHeader::new();
+        rst.set_src_port(header.get_dst_port());
+        rst.set_dst_port(header.get_src_port());
+        if header.has_flags(tcp_flags::ACK) {
+            rst.set_seq_num(header.get_ack_num());
+            rst.set_flags(tcp_flags::RST);
+        } else {
+            rst.set_ack_num(header.get_seq_num().wrapping_add(seg_len));
+            rst.set_flags(tcp_flags::RST | tcp_flags::ACK);
+        }
+        // Only a single reset is kept; further ones are dropped, which the
+        // peer recovers from by retransmitting.
+        if self.pending_rst.is_none() {
+            self.pending_rst.set(PendingReset {
+                dst: src,
+                header: rst,
+            });
+        }
+    }
+
+    fn find_socket(&self, src: IPAddr, header: &TCPHeader) -> Option<&'a TCPSocket<'a>> {
+        let port = header.get_dst_port();
+        self.sockets
+            .iter()
+            .find(|sock| {
+                let conn = sock.conn.get();
+                conn.state != TCPState::Closed
+                    && conn.state != TCPState::Listen
+                    && conn.local_port == port
+                    && conn.remote_addr == src
+                    && conn.remote_port == header.get_src_port()
+            })
+            .or_else(|| {
+                self.sockets.iter().find(|sock| {
+                    let conn = sock.conn.get();
+                    conn.state == TCPState::Listen && conn.local_port == port
+                })
+            })
+    }
+
+    /// Segment processing for a socket in LISTEN (RFC 9293, 3.10.7.2).
+    fn segment_listen(
+        &self,
+        socket: &TCPSocket<'a>,
+        src: IPAddr,
+        header: &TCPHeader,
+        seg_len: u32,
+    ) {
+        if header.has_flags(tcp_flags::RST) {
+            return;
+        }
+        if header.has_flags(tcp_flags::ACK) || !header.has_flags(tcp_flags::SYN) {
+            self.send_reset_for(src, header, seg_len);
+            return;
+        }
+        let iss = self.new_iss();
+        socket.update(|conn| {
+            conn.reset();
+            conn.passive = true;
+            conn.state = TCPState::SynReceived;
+            conn.remote_addr = src;
+            conn.remote_port = header.get_src_port();
+            conn.irs = header.get_seq_num();
+            conn.rcv_nxt = header.get_seq_num().wrapping_add(1);
+            conn.iss = iss;
+            conn.snd_una = iss;
+            conn.snd_nxt = iss;
+            conn.snd_wnd = header.get_window();
+            if let Some(mss) = header.get_mss() {
+                conn.snd_mss = mss;
+            }
+        });
+    }
+
+    /// Segment processing for a socket in SYN-SENT (RFC 9293, 3.10.7.3).
+    fn segment_syn_sent(
+        &self,
+        socket: &TCPSocket<'a>,
+        src: IPAddr,
+        header: &TCPHeader,
+        seg_len: u32,
+    ) {
+        let conn = socket.conn.get();
+        let has_ack = header.has_flags(tcp_flags::ACK);
+        let ack = header.get_ack_num();
+        let ack_ok = seq_lt(conn.iss, ack) && seq_le(ack, conn.snd_nxt);
+        if has_ack && !ack_ok {
+            self.send_reset_for(src, header, seg_len);
+            return;
+        }
+        if header.has_flags(tcp_flags::RST) {
+            if has_ack {
+                // Connection refused
+                self.close_socket(socket, Err(ErrorCode::CANCEL));
+            }
+            return;
+        }
+        if !header.has_flags(tcp_flags::SYN) {
+            return;
+        }
+        let established = socket.update(|conn| {
+            conn.irs = header.get_seq_num();
+            conn.rcv_nxt = header.get_seq_num().wrapping_add(1);
+            conn.snd_wnd = header.get_window();
+            if let Some(mss) = header.get_mss() {
+                conn.snd_mss = mss;
+            }
+            conn.ack_pending = true;
+            if has_ack {
+                Self::take_rtt_sample(conn, ack);
+                conn.snd_una = ack;
+                conn.retx_timer = 0;
+                conn.retx_count = 0;
+                conn.state = TCPState::Established;
+                true
+            } else {
+                // Simultaneous open: resend our SYN along with an ACK.
+                conn.state = TCPState::SynReceived;
+                conn.snd_nxt = conn.iss;
+                false
+            }
+        });
+        if established {
+            socket.client.map(|client| client.connected(socket.id));
+        }
+    }
+
+    fn take_rtt_sample(conn: &mut TCPConnection, ack: u32) {
+        if let Some(seq) = conn.rtt_seq {
+            if seq_lt(seq, ack) {
+                conn.update_rtt(conn.rtt_ticks as u32 * TCP_TIMER_MS);
+                conn.rtt_seq = None;
+            }
+        }
+    }
+
+    /// Segment processing for all synchronized states and SYN-RECEIVED
+    /// (RFC 9293, 3.10.7.4).
+    fn segment_synchronized(&self, socket: &TCPSocket<'a>, header: &TCPHeader, data: &[u8]) {
+        let seq = header.get_seq_num();
+        let fin = header.has_flags(tcp_flags::FIN);
+        let seg_len =
+            data.len() as u32 + u32::from(header.has_flags(tcp_flags::SYN)) + u32::from(fin);
+        let rcv_wnd = self.rcv_window(socket) as u32;
+
+        // First, check the sequence number.
+        if !socket.conn.get().segment_acceptable(seq, seg_len, rcv_wnd) {
+            if !header.has_flags(tcp_flags::RST) {
+                socket.update(|conn| conn.ack_pending = true);
+            }
+            return;
+        }
+
+        // Second, check the RST bit.
+        if header.has_flags(tcp_flags::RST) {
+            let conn = socket.conn.get();
+            if conn.state == TCPState::SynReceived && conn.passive {
+                socket.update(|conn| {
+                    let port = conn.local_port;
+                    conn.reset();
+                    conn.local_port = port;
+                    conn.state = TCPState::Listen;
+                });
+            } else {
+                self.close_socket(socket, Err(ErrorCode::CANCEL));
+            }
+            return;
+        }
+
+        // Fourth, a SYN in the window is answered with a challenge ACK
+        // (RFC 5961, section 4).
+        if header.has_flags(tcp_flags::SYN) {
+            socket.update(|conn| conn.ack_pending = true);
+            return;
+        }
+
+        // Fifth, check the ACK field.
+        if !header.has_flags(tcp_flags::ACK) {
+            return;
+        }
+        let ack = header.get_ack_num();
+        let conn = socket.conn.get();
+        if conn.state == TCPState::SynReceived {
+            if !(seq_lt(conn.snd_una, ack) && seq_le(ack, conn.snd_nxt)) {
+                self.send_reset_for(conn.remote_addr, header, seg_len);
+                return;
+            }
+            socket.update(|conn| {
+                Self::take_rtt_sample(conn, ack);
+                conn.snd_una = ack;
+                conn.snd_wnd = header.get_window();
+                conn.retx_timer = 0;
+                conn.retx_count = 0;
+                conn.state = TCPState::Established;
+            });
+            socket.client.map(|client| client.connected(socket.id));
+        } else if seq_lt(conn.snd_nxt, ack) {
+            // Acknowledges something not yet sent.
+            socket.update(|conn| conn.ack_pending = true);
+            return;
+        } else if seq_lt(conn.snd_una, ack) {
+            let mut fin_acked = false;
+            let data_acked = socket.update(|conn| {
+                Self::take_rtt_sample(conn, ack);
+                let acked = ack.wrapping_sub(conn.snd_una) as usize;
+                let data_acked = cmp::min(acked, conn.tx_len);
+                fin_acked = conn.fin_queued && acked > conn.tx_len;
+                conn.tx_len -= data_acked;
+                conn.snd_una = ack;
+                conn.retx_count = 0;
+                if conn.snd_una == conn.snd_nxt {
+                    conn.retx_timer = 0;
+                } else {
+                    conn.arm_retx_timer();
+                }
+                data_acked
+            });
+            if data_acked > 0 {
+                socket
+                    .client
+                    .map(|client| client.send_acked(socket.id, data_acked));
+            }
+            if fin_acked {
+                match socket.conn.get().state {
+                    TCPState::FinWait1 => socket.update(|conn| conn.state = TCPState::FinWait2),
+                    TCPState::Closing => socket.update(|conn| {
+                        conn.state = TCPState::TimeWait;
+                        conn.time_wait_timer = (TIME_WAIT_MS / TCP_TIMER_MS) as u16;
+                    }),
+                    TCPState::LastAck => {
+                        self.close_socket(socket, Ok(()));
+                        return;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        socket.update(|conn| {
+            conn.snd_wnd = header.get_window();
+            if conn.snd_wnd > 0 {
+                conn.probe_pending = false;
+            } else {
+                // The peer is alive and answering window probes, which may
+                // continue indefinitely (RFC 9293, section 3.8.6.1).
+                conn.retx_count = 0;
+            }
+        });
+
+        // Seventh, process the segment text.
+        let mut all_data_taken = true;
+        if !data.is_empty() && socket.conn.get().state.can_receive() {
+            let rcv_nxt = socket.conn.get().rcv_nxt;
+            // Skip any part of the segment that was already received.
+            let skip = rcv_nxt.wrapping_sub(seq) as usize;
+            if seq_le(seq, rcv_nxt) && skip <= data.len() {
+                let new_data = &data[skip..];
+                let accepted = socket.client.map_or(0, |client| {
+                    cmp::min(client.receive(socket.id, new_data), new_data.len())
+                });
+                all_data_taken = accepted == new_data.len();
+                socket.update(|conn| {
+                    conn.rcv_nxt = conn.rcv_nxt.wrapping_add(accepted as u32);
+                });
+            } else {
+                // Out of order, drop it and let the duplicate ACK tell the
+                // peer what we expect.
+                all_data_taken = false;
+            }
+            socket.update(|conn| conn.ack_pending = true);
+        }
+
+        // Eighth, check the FIN bit.
+        let fin_seq = seq.wrapping_add(data.len() as u32);
+        if fin && all_data_taken && fin_seq == socket.conn.get().rcv_nxt {
+            let state = socket.update(|conn| {
+                conn.rcv_nxt = conn.rcv_nxt.wrapping_add(1);
+                conn.ack_pending = true;
+                conn.state = match conn.state {
+                    TCPState::SynReceived | TCPState::Established => TCPState::CloseWait,
+                    TCPState::FinWait1 if conn.snd_una == conn.snd_nxt => TCPState::TimeWait,
+                    TCPState::FinWait1 => TCPState::Closing,
+                    TCPState::FinWait2 => TCPState::TimeWait,
+                    other => other,
+                };
+                if conn.state == TCPState::TimeWait {
+                    conn.retx_timer = 0;
+                    conn.time_wait_timer = (TIME_WAIT_MS / TCP_TIMER_MS) as u16;
+                }
+                conn.state
+            });
+            if matches!(
+                state,
+                TCPState::CloseWait | TCPState::Closing | TCPState::TimeWait
+            ) {
+                socket.client.map(|client| client.remote_closed(socket.id));
+            }
+        }
+    }
+
+    /// Advances all timers by one tick.
+    fn tick_socket(&self, socket: &TCPSocket<'a>) {
+        let mut timed_out = false;
+        let mut time_wait_over = false;
+        socket.update(|conn| {
+            if conn.rtt_seq.is_some() {
+                conn.rtt_ticks = conn.rtt_ticks.saturating_add(1);
+            }
+            if conn.time_wait_timer > 0 {
+                conn.time_wait_timer -= 1;
+                time_wait_over = conn.time_wait_timer == 0;
+            }
+            if conn.retx_timer > 0 {
+                conn.retx_timer -= 1;
+                if conn.retx_timer == 0 {
+                    if conn.retx_count >= MAX_RETRANSMISSIONS {
+                        timed_out = true;
+                        return;
+                    }
+                    conn.retx_count += 1;
+                    conn.back_off();
+                    if conn.state.is_synchronized() {
+                        // Go back N: resend everything from snd_una. With a
+                        // zero window this acts as the persist timer and
+                        // forces a one byte probe.
+                        conn.snd_nxt = conn.snd_una;
+                        conn.probe_pending = conn.snd_wnd == 0;
+                    } else {
+                        conn.snd_nxt = conn.iss;
+                    }
+                    conn.arm_retx_timer();
+                }
+            }
+        });
+        if timed_out {
+            self.close_socket(socket, Err(ErrorCode::NOACK));
+        } else if time_wait_over {
+            self.close_socket(socket, Ok(()));
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> TCPStack<'a> for MuxTcp<'a, A> {
+    fn add_socket(&self, socket: &'a TCPSocket<'a>) {
+        self.sockets.push_tail(socket);
+    }
+
+    fn listen(
+        &self,
+        socket: &'a TCPSocket<'a>,
+        local_port: u16,
+        net_cap: &'static NetworkCapability,
+    ) -> Result<(), ErrorCode> {
+        if local_port == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+        if socket.get_state() != TCPState::Closed || self.port_in_use(local_port) {
+            return Err(ErrorCode::BUSY);
+        }
+        socket.net_cap.set(net_cap);
+        socket.update(|conn| {
+            conn.reset();
+            conn.local_port = local_port;
+            conn.state = TCPState::Listen;
+        });
+        Ok(())
+    }
+
+    fn connect(
+        &self,
+        socket: &'a TCPSocket<'a>,
+        dst: IPAddr,
+        dst_port: u16,
+        src_port: u16,
+        net_cap: &'static NetworkCapability,
+    ) -> Result<(), ErrorCode> {
+        if dst_port == 0 || dst.is_unspecified() || dst.is_multicast() {
+            return Err(ErrorCode::INVAL);
+        }
+        if socket.get_state() != TCPState::Closed {
+            return Err(ErrorCode::BUSY);
+        }
+        let local_port = if src_port == 0 {
+            self.alloc_ephemeral_port().ok_or(ErrorCode::NOMEM)?
+        } else if self.port_in_use(src_port) {
+            return Err(ErrorCode::BUSY);
+        } else {
+            src_port
+        };
+        let iss = self.new_iss();
+        socket.net_cap.set(net_cap);
+        socket.update(|conn| {
+            conn.reset();
+            conn.local_port = local_port;
+            conn.remote_addr = dst;
+            conn.remote_port = dst_port;
+            conn.iss = iss;
+            conn.snd_una = iss;
+            conn.snd_nxt = iss;
+            conn.state = TCPState::SynSent;
+        });
+        self.do_output();
+        Ok(())
+    }
+
+    fn send(&self, socket: &'a TCPSocket<'a>, len: usize) -> Result<(), ErrorCode> {
+        let conn = socket.conn.get();
+        if !conn.state.can_send() || conn.fin_queued {
+            return Err(ErrorCode::OFF);
+        }
+        socket.update(|conn| conn.tx_len += len);
+        self.do_output();
+        Ok(())
+    }
+
+    fn window_update(&self, socket: &'a TCPSocket<'a>) {
+        if socket.get_state().can_receive() {
+            socket.update(|conn| conn.ack_pending = true);
+            self.do_output();
+        }
+    }
+
+    fn close(&self, socket: &'a TCPSocket<'a>) -> Result<(), ErrorCode> {
+        match socket.get_state() {
+            TCPState::Closed => Err(ErrorCode::ALREADY),
+            TCPState::Listen | TCPState::SynSent => {
+                socket.update(|conn| {
+                    conn.reset();
+                    conn.local_port = 0;
+                });
+                Ok(())
+            }
+            TCPState::SynReceived | TCPState::Established | TCPState::CloseWait => {
+                socket.update(|conn| conn.fin_queued = true);
+                self.do_output();
+                Ok(())
+            }
+            _ => Err(ErrorCode::ALREADY),
+        }
+    }
+
+    fn abort(&self, socket: &'a TCPSocket<'a>) {
+        let conn = socket.conn.get();
+        if conn.state.is_synchronized() || conn.state == TCPState::SynReceived {
+            let mut rst = TCPHeader::new();
+            rst.set_src_port(conn.local_port);
+            rst.set_dst_port(conn.remote_port);
+            rst.set_seq_num(conn.snd_nxt);
+            rst.set_flags(tcp_flags::RST);
+            if self.pending_rst.is_none() {
+                self.pending_rst.set(PendingReset {
+                    dst: conn.remote_addr,
+                    header: rst,
+                });
+            }
+        }
+        socket.update(|conn| {
+            conn.reset();
+            conn.local_port = 0;
+        });
+        self.do_output();
+    }
+
+    fn max_segment_size(&self) -> usize {
+        self.tx_buffer.map_or(0, |buf| buf.len())
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> IP6RecvClient for MuxTcp<'a, A> {
+    fn receive(&self, ip_header: IP6Header, payload: &[u8]) {
+        if ip_header.get_next_header() != ip6_nh::TCP {
+            return;
+        }
+        let (offset, header) = match TCPHeader::decode(payload).done() {
+            Some(decoded) => decoded,
+            None => return,
+        };
+        let data = &payload[offset..];
+        let seg_len = data.len() as u32
+            + u32::from(header.has_flags(tcp_flags::SYN))
+            + u32::from(header.has_flags(tcp_flags::FIN));
+        let src = ip_header.get_src_addr();
+
+        match self.find_socket(src, &header) {
+            Some(socket) => match socket.get_state() {
+                TCPState::Listen => self.segment_listen(socket, src, &header, seg_len),
+                TCPState::SynSent => self.segment_syn_sent(socket, src, &header, seg_len),
+                _ => self.segment_synchronized(socket, &header, data),
+            },
+            None => self.send_reset_for(src, &header, seg_len),
+        }
+        self.do_output();
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> IP6SendClient for MuxTcp<'a, A> {
+    fn send_done(&self, result: Result<(), ErrorCode>) {
+        if result != Ok(()) {
+            debug!("[TCP] Segment transmission failed: {:?}", result);
+        }
+        self.sending.set(false);
+        self.do_output();
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> time::AlarmClient for MuxTcp<'a, A> {
+    fn alarm(&self) {
+        for socket in self.sockets.iter() {
+            self.tick_socket(socket);
+        }
+        self.do_output();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::net::ieee802154::MacAddress;
+    use crate::net::network_capabilities::{AddrRange, PortRange};
+    use crate::net::tcp::tcp_connection::INITIAL_RTO_MS;
+    use kernel::capabilities::NetworkCapabilityCreationCapability;
+    use kernel::create_capability;
+    use kernel::hil::time::{Alarm, AlarmClient, Freq1KHz, Ticks32, Time};
+    use std::boxed::Box;
+
+    const PEER_PORT: u16 = 4000;
+    const LOCAL_PORT: u16 = 80;
+    const PEER_ISS: u32 = u32::MAX - 2;
+
+    struct FakeAlarm<'a> {
+        now: Cell<Ticks32>,
+        armed: Cell<bool>,
+        client: OptionalCell<&'a dyn AlarmClient>,
+    }
+
+    impl Time for FakeAlarm<'_> {
+        type Ticks = Ticks32;
+        type Frequency = Freq1KHz;
+
+        fn now(&self) -> Ticks32 {
+            self.now.get()
+        }
+    }
+
+    impl<'a> Alarm<'a> for FakeAlarm<'a> {
+        fn set_alarm_client(&self, client: &'a dyn AlarmClient) {
+            self.client.set(client);
+        }
+
+        fn set_alarm(&self, _reference: Self::Ticks, _dt: Self::Ticks) {
+            self.armed.set(true);
+        }
+
+        fn get_alarm(&self) -> Self::Ticks {
+            self.now.get()
+        }
+
+        fn disarm(&self) -> Result<(), ErrorCode> {
+            self.armed.set(false);
+            Ok(())
+        }
+
+        fn is_armed(&self) -> bool {
+            self.armed.get()
+        }
+
+        fn minimum_dt(&self) -> Self::Ticks {
+            0u32.into()
+        }
+    }
+
+    /// Records the last segment handed to the IP layer.
+    struct FakeSender {
+        sent: Cell<Option<(TCPHeader, usize)>>,
+    }
+
+    impl<'a> IP6Sender<'a> for FakeSender {
+        fn set_client(&self, _client: &'a dyn IP6SendClient) {}
+
+        fn set_addr(&self, _src_addr: IPAddr) {}
+
+        fn set_gateway(&self, _gateway: MacAddress) {}
+
+        fn set_header(&mut self, _ip6_header: IP6Header) {}
+
+        fn send_to(
+            &self,
+            dst: IPAddr,
+            transport_header: TransportHeader,
+            payload: &SubSliceMut<'static, u8>,
+            _net_cap: &'static NetworkCapability,
+        ) -> Result<(), ErrorCode> {
+            assert_eq!(dst, peer_addr());
+            match transport_header {
+                TransportHeader::TCP(header) => self.sent.set(Some((header, payload.len()))),
+                _ => panic!("not a TCP segment"),
+            }
+            Ok(())
+        }
+    }
+
+    /// Queues `tx_len` bytes and accepts everything it is given.
+    struct FakeClient {
+        tx_len: Cell<usize>,
+        connected: Cell<bool>,
+        received: Cell<usize>,
+        acked: Cell<usize>,
+        remote_closed: Cell<bool>,
+        closed: OptionalCell<Result<(), ErrorCode>>,
+    }
+
+    impl TCPClient for FakeClient {
+        fn connected(&self, _socket_id: usize) {
+            self.connected.set(true);
+        }
+
+        fn receive(&self, _socket_id: usize, data: &[u8]) -> usize {
+            self.received.set(self.received.get() + data.len());
+            data.len()
+        }
+
+        fn receive_window(&self, _socket_id: usize) -> usize {
+            1000
+        }
+
+        fn send_data(&self, _socket_id: usize, offset: usize, buf: &mut [u8]) -> usize {
+            let len = cmp::min(self.tx_len.get().saturating_sub(offset), buf.len());
+            buf[..len].fill(0xAA);
+            len
+        }
+
+        fn send_acked(&self, _socket_id: usize, len: usize) {
+            self.acked.set(self.acked.get() + len);
+            self.tx_len.set(self.tx_len.get() - len);
+        }
+
+        fn remote_closed(&self, _socket_id: usize) {
+            self.remote_closed.set(true);
+        }
+
+        fn closed(&self, _socket_id: usize, result: Result<(), ErrorCode>) {
+            self.closed.set(result);
+        }
+    }
+
+    fn peer_addr() -> IPAddr {
+        IPAddr([0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x02])
+    }
+
+    fn net_cap() -> &'static NetworkCapability {
+        let create_cap = create_capability!(NetworkCapabilityCreationCapability);
+        Box::leak(Box::new(NetworkCapability::new(
+            AddrRange::Any,
+            PortRange::Any,
+            PortRange::Any,
+            &create_cap,
+        )))
+    }
+
+    struct Harness<'a> {
+        alarm: &'a FakeAlarm<'a>,
+        sender: &'a FakeSender,
+        client: &'a FakeClient,
+        socket: &'a TCPSocket<'a>,
+        tcp: &'a MuxTcp<'a, FakeAlarm<'a>>,
+    }
+
+    impl<'a> Harness<'a> {
+        /// Delivers a segment from the peer.
+        fn deliver(&self, seq: u32, ack: u32, flags: u8, data: &[u8]) {
+            let mut header = TCPHeader::new();
+            header.set_src_port(PEER_PORT);
+            header.set_dst_port(self.socket.get_local_port());
+            header.set_seq_num(seq);
+            header.set_ack_num(ack);
+            header.set_flags(flags);
+            header.set_window(1000);
+            let mut buf = [0; 64];
+            let (off, _) = header.encode(&mut buf, 0).done().unwrap();
+            buf[off..off + data.len()].copy_from_slice(data);
+
+            let mut ip_header = IP6Header::new();
+            ip_header.set_next_header(ip6_nh::TCP);
+            ip_header.src_addr = peer_addr();
+            self.tcp.receive(ip_header, &buf[..off + data.len()]);
+        }
+
+        /// Returns the segment sent to the peer and completes its
+        /// transmission.
+        fn expect_sent(&self) -> (TCPHeader, usize) {
+            let sent = self.sender.sent.take().expect("no segment sent");
+            self.tcp.send_done(Ok(()));
+            sent
+        }
+
+        fn expect_nothing_sent(&self) {
+            assert!(self.sender.sent.take().is_none());
+        }
+
+        /// Fires the TCP timer `ticks` times.
+        fn tick(&self, ticks: usize) {
+            for _ in 0..ticks {
+                self.alarm.armed.set(false);
+                self.tcp.alarm();
+            }
+        }
+    }
+
+    fn with_harness(test: impl FnOnce(&Harness)) {
+        let alarm = FakeAlarm {
+            now: Cell::new(1234u32.into()),
+            armed: Cell::new(false),
+            client: OptionalCell::empty(),
+        };
+        let sender = FakeSender {
+            sent: Cell::new(None),
+        };
+        let client = FakeClient {
+            tx_len: Cell::new(0),
+            connected: Cell::new(false),
+            received: Cell::new(0),
+            acked: Cell::new(0),
+            remote_closed: Cell::new(false),
+            closed: OptionalCell::empty(),
+        };
+        let socket = TCPSocket::new(0);
+        let tcp = MuxTcp::new(&sender, &alarm, Box::leak(Box::new([0; 64])), net_cap());
+        alarm.set_alarm_client(&tcp);
+        socket.set_client(&client);
+        tcp.add_socket(&socket);
+        test(&Harness {
+            alarm: &alarm,
+            sender: &sender,
+            client: &client,
+            socket: &socket,
+            tcp: &tcp,
+        });
+    }
+
+    #[test]
+    fn test_active_open_and_close() {
+        with_harness(|h| {
+            assert_eq!(
+                h.tcp
+                    .connect(h.socket, peer_addr(), PEER_PORT, 0, net_cap()),
+                Ok(())
+            );
+            assert_eq!(h.socket.get_state(), TCPState::SynSent);
+            assert_eq!(h.socket.get_local_port(), EPHEMERAL_PORT_START);
+            let (syn, _) = h.expect_sent();
+            assert_eq!(syn.get_flags(), tcp_flags::SYN);
+            assert_eq!(syn.get_mss(), Some(64));
+            let iss = syn.get_seq_num();
+
+            h.deliver(
+                PEER_ISS,
+                iss.wrapping_add(1),
+                tcp_flags::SYN | tcp_flags::ACK,
+                &[],
+            );
+            assert_eq!(h.socket.get_state(), TCPState::Established);
+            assert!(h.client.connected.get());
+            let (ack, _) = h.expect_sent();
+            assert_eq!(ack.get_flags(), tcp_flags::ACK);
+            assert_eq!(ack.get_ack_num(), PEER_ISS.wrapping_add(1));
+
+            // Send data and have it acknowledged.
+            h.client.tx_len.set(10);
+            assert_eq!(h.tcp.send(h.socket, 10), Ok(()));
+            let (data, len) = h.expect_sent();
+            assert_eq!(len, 10);
+            assert_eq!(data.get_seq_num(), iss.wrapping_add(1));
+            h.deliver(
+                PEER_ISS.wrapping_add(1),
+                iss.wrapping_add(11),
+                tcp_flags::ACK,
+                &[],
+            );
+            assert_eq!(h.client.acked.get(), 10);
+            assert_eq!(h.socket.get_unacked_len(), 0);
+            h.expect_nothing_sent();
+
+            // Receive data across the wrap of the peer's sequence space.
+            h.deliver(
+                PEER_ISS.wrapping_add(1),
+                iss.wrapping_add(11),
+                tcp_flags::ACK | tcp_flags::PSH,
+                b"hello",
+            );
+            assert_eq!(h.client.received.get(), 5);
+            let (ack, _) = h.expect_sent();
+            assert_eq!(ack.get_ack_num(), PEER_ISS.wrapping_add(6));
+
+            // Close our side, then the peer closes.
+            assert_eq!(h.tcp.close(h.socket), Ok(()));
+            let (fin, _) = h.expect_sent();
+            assert!(fin.has_flags(tcp_flags::FIN | tcp_flags::ACK));
+            assert_eq!(h.socket.get_state(), TCPState::FinWait1);
+            h.deliver(
+                PEER_ISS.wrapping_add(6),
+                iss.wrapping_add(12),
+                tcp_flags::ACK,
+                &[],
+            );
+            assert_eq!(h.socket.get_state(), TCPState::FinWait2);
+            h.expect_nothing_sent();
+            h.deliver(
+                PEER_ISS.wrapping_add(6),
+                iss.wrapping_add(12),
+                tcp_flags::FIN | tcp_flags::ACK,
+                &[],
+            );
+            assert_eq!(h.socket.get_state(), TCPState::TimeWait);
+            assert!(h.client.remote_closed.get());
+            let (ack, _) = h.expect_sent();
+            assert_eq!(ack.get_ack_num(), PEER_ISS.wrapping_add(7));
+
+            h.tick((TIME_WAIT_MS / TCP_TIMER_MS) as usize);
+            assert_eq!(h.socket.get_state(), TCPState::Closed);
+            assert_eq!(h.client.closed.take(), Some(Ok(())));
+        });
+    }
+
+    #[test]
+    fn test_passive_open_and_close() {
+        with_harness(|h| {
+            assert_eq!(h.tcp.listen(h.socket, LOCAL_PORT, net_cap()), Ok(()));
+            assert_eq!(h.socket.get_state(), TCPState::Listen);
+
+            h.deliver(PEER_ISS, 0, tcp_flags::SYN, &[]);
+            assert_eq!(h.socket.get_state(), TCPState::SynReceived);
+            assert_eq!(h.socket.get_remote_endpoint(), (peer_addr(), PEER_PORT));
+            let (syn_ack, _) = h.expect_sent();
+            assert_eq!(syn_ack.get_flags(), tcp_flags::SYN | tcp_flags::ACK);
+            assert_eq!(syn_ack.get_ack_num(), PEER_ISS.wrapping_add(1));
+            let iss = syn_ack.get_seq_num();
+
+            // An ACK that does not acknowledge our SYN is answered with a
+            // reset.
+            h.deliver(PEER_ISS.wrapping_add(1), iss, tcp_flags::ACK, &[]);
+            assert_eq!(h.socket.get_state(), TCPState::SynReceived);
+            let (rst, _) = h.expect_sent();
+            assert_eq!(rst.get_flags(), tcp_flags::RST);
+            assert_eq!(rst.get_seq_num(), iss);
+
+            h.deliver(
+                PEER_ISS.wrapping_add(1),
+                iss.wrapping_add(1),
+                tcp_flags::ACK,
+                &[],
+            );
+            assert_eq!(h.socket.get_state(), TCPState::Established);
+            assert!(h.client.connected.get());
+
+            h.deliver(
+                PEER_ISS.wrapping_add(1),
+                iss.wrapping_add(1),
+                tcp_flags::FIN | tcp_flags::ACK,
+                &[],
+            );
+            assert_eq!(h.socket.get_state(), TCPState::CloseWait);
+            assert!(h.client.remote_closed.get());
+            h.expect_sent();
+
+            assert_eq!(h.tcp.close(h.socket), Ok(()));
+            assert_eq!(h.socket.get_state(), TCPState::LastAck);
+            let (fin, _) = h.expect_sent();
+            assert!(fin.has_flags(tcp_flags::FIN));
+            h.deliver(
+                PEER_ISS.wrapping_add(2),
+                iss.wrapping_add(2),
+                tcp_flags::ACK,
+                &[],
+            );
+            assert_eq!(h.socket.get_state(), TCPState::Closed);
+            assert_eq!(h.client.closed.take(), Some(Ok(())));
+        });
+    }
+
+    #[test]
+    fn test_reset_in_syn_received_returns_to_listen() {
+        with_harness(|h| {
+            assert_eq!(h.tcp.listen(h.socket, LOCAL_PORT, net_cap()), Ok(()));
+            h.deliver(PEER_ISS, 0, tcp_flags::SYN, &[]);
+            h.expect_sent();
+            h.deliver(PEER_ISS.wrapping_add(1), 0, tcp_flags::RST, &[]);
+            assert_eq!(h.socket.get_state(), TCPState::Listen);
+            assert_eq!(h.socket.get_local_port(), LOCAL_PORT);
+            assert!(h.client.closed.is_none());
+        });
+    }
+
+    #[test]
+    fn test_connection_refused() {
+        with_harness(|h| {
+            assert_eq!(
+                h.tcp
+                    .connect(h.socket, peer_addr(), PEER_PORT, LOCAL_PORT, net_cap()),
+                Ok(())
+            );
+            let (syn, _) = h.expect_sent();
+            let iss = syn.get_seq_num();
+
+            // A reset that does not acknowledge our SYN is dropped, without
+            // answering it with a reset.
+            h.deliver(0, iss, tcp_flags::RST | tcp_flags::ACK, &[]);
+            assert_eq!(h.socket.get_state(), TCPState::SynSent);
+            h.expect_nothing_sent();
+
+            h.deliver(0, iss.wrapping_add(1), tcp_flags::RST | tcp_flags::ACK, &[]);
+            assert_eq!(h.socket.get_state(), TCPState::Closed);
+            assert_eq!(h.client.closed.take(), Some(Err(ErrorCode::CANCEL)));
+        });
+    }
+
+    #[test]
+    fn test_segment_without_socket_is_reset() {
+        with_harness(|h| {
+            h.deliver(PEER_ISS, 0, tcp_flags::SYN, b"abc");
+            let (rst, _) = h.expect_sent();
+            assert_eq!(rst.get_flags(), tcp_flags::RST | tcp_flags::ACK);
+            assert_eq!(rst.get_ack_num(), PEER_ISS.wrapping_add(4));
+            assert_eq!(rst.get_dst_port(), PEER_PORT);
+        });
+    }
+
+    #[test]
+    fn test_syn_retransmitted_until_timeout() {
+        with_harness(|h| {
+            assert_eq!(
+                h.tcp
+                    .connect(h.socket, peer_addr(), PEER_PORT, LOCAL_PORT, net_cap()),
+                Ok(())
+            );
+            let (syn, _) = h.expect_sent();
+            assert!(h.alarm.is_armed());
+
+            let mut rto_ms = INITIAL_RTO_MS;
+            for _ in 0..MAX_RETRANSMISSIONS {
+                h.tick((rto_ms / TCP_TIMER_MS) as usize - 1);
+                h.expect_nothing_sent();
+                h.tick(1);
+                let (retx, _) = h.expect_sent();
+                assert_eq!(retx.get_flags(), tcp_flags::SYN);
+                assert_eq!(retx.get_seq_num(), syn.get_seq_num());
+                rto_ms *= 2;
+            }
+            assert!(h.client.closed.is_none());
+
+            h.tick((rto_ms / TCP_TIMER_MS) as usize);
+            assert_eq!(h.socket.get_state(), TCPState::Closed);
+            assert_eq!(h.client.closed.take(), Some(Err(ErrorCode::NOACK)));
+        });
+    }
+}
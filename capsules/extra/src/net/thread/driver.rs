@@ -4,19 +4,40 @@
 
 //! This file contains the structs and methods associated with the Thread
 //! networking layer. This represents a first attempt in Tock
-//! to support Thread networking. The current implementation successfully
-//! joins a Tock device as a child node to a Thread parent (tested using
-//! OpenThread). This Thread capsule is a client to the UDP Mux.
-//! The associated ThreadNetwork struct must be created in the `thread_network.rs`
-//! component.
+//! to support Thread networking. The current implementation joins a Tock
+//! device as a sleepy end device (SED) child to a Thread parent (tested using
+//! OpenThread) and keeps it attached. This Thread capsule is a client to the
+//! UDP Mux. The associated ThreadNetwork struct must be created in the
+//! `thread_network.rs` component.
 //!
 //! The Userland interface is incredibly simple at this juncture. An application
 //! can begin the Thread child/parent joining by issuing a syscall command
-//! with the MLE/MAC key as an argument. Only one userspace application can use/join
+//! with the network key as an argument. Only one userspace application can use/join
 //! the Thread network. Once a userspace application has joined the Thread network,
 //! the Thread network is considered locked. After the Thread network
 //! is "locked", other userspace applications attempting to join the network
 //! will return a failure. This is temporary and will eventually be replaced.
+//!
+//! Attach procedure
+//! ----------------
+//! The driver follows the attach process of Thread Spec v1.3.0 -- sect. 4.5:
+//!
+//! - Every Parent Request and Child Update Request carries a fresh random
+//!   challenge obtained from the RNG, and only responses echoing that
+//!   challenge are accepted.
+//! - Parent Requests are retried following the retry procedure described at
+//!   the end of `thread_utils.rs`. Child ID Requests are retried a few times
+//!   before moving on to the next Parent Request. If the whole attach cycle
+//!   fails, the driver waits for an exponentially growing backoff (with
+//!   random jitter) and starts over.
+//! - Once attached, the child sends a Child Update Request to its parent
+//!   every half child timeout. If the parent does not answer or no longer
+//!   knows the child, the driver detaches and attaches again.
+//! - When the application provides the 16 byte Thread network key, the MLE
+//!   and MAC keys are derived with HMAC-SHA256 (Thread Spec v1.3.0 -- sect.
+//!   7.1.4) for the current and next key sequence. A message authenticated
+//!   with the next key sequence switches the node to it, keeping the
+//!   previous keys around for neighbors that did not switch yet.
 
 // ------------------------------------------------------------------------------
 // Current Limitations
@@ -24,9 +45,8 @@
 // (1) A majority of the TLV fields used in the parent request/child id request
 //     are hardcoded. Future implementations need to provide options for specifying
 //     varied security policies.
-// (2) Current implementation joins the Thread network sucessfully and consistently
-//     but does not send update/heart beat messages to the parent prior to the child
-//     timing out.
+// (2) The first valid Parent Response is accepted; the link quality of
+//     candidate parents is not compared.
 // (3) Currently no support for sending UDP messages across Thread interface. The
 //     current interface is unusable for sending data. It can only be used to
 //     join a network.
@@ -42,9 +62,12 @@ use crate::net::thread::thread_utils::ThreadState;
 use crate::net::thread::thread_utils::MULTICAST_IPV6;
 use crate::net::thread::thread_utils::THREAD_PORT_NUMBER;
 use crate::net::thread::thread_utils::{
-    encode_cryp_data, form_child_id_req, form_parent_req, mac_from_ipv6, MleCommand, NetworkKey,
-    AUTH_DATA_LEN, AUX_SEC_HEADER_LENGTH, IPV6_LEN, SECURITY_SUITE_LEN,
+    encode_cryp_data, find_challenge, find_tlv, form_child_id_req, form_child_update_req,
+    form_parent_req, key_id_from_sequence, key_index, key_sequence_from_id, mac_from_ipv6,
+    response_matches, MleCommand, NetworkKey, AUTH_DATA_LEN, AUX_SEC_HEADER_LENGTH, CHALLENGE_LEN,
+    CHILD_TIMEOUT_S, IPV6_LEN, KEY_DERIVATION_STR, SECURITY_SUITE_LEN,
 };
+use crate::net::thread::tlv::TlvType;
 use crate::net::udp::udp_port_table::UdpPortManager;
 use crate::net::udp::udp_recv::UDPRecvClient;
 use crate::net::udp::udp_send::{UDPSendClient, UDPSender};
@@ -55,18 +78,52 @@ use core::cell::Cell;
 use kernel::capabilities::UdpDriverCapability;
 use kernel::errorcode::into_statuscode;
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::digest;
+use kernel::hil::rng;
 use kernel::hil::symmetric_encryption::CCMClient;
 use kernel::hil::symmetric_encryption::AES128CCM;
-use kernel::hil::time;
+use kernel::hil::time::{self, ConvertTicks};
 use kernel::processbuffer::ReadableProcessBuffer;
 use kernel::syscall::{CommandReturn, SyscallDriver};
-use kernel::utilities::cells::MapCell;
+use kernel::utilities::cells::{MapCell, OptionalCell, TakeCell};
 use kernel::utilities::leasable_buffer::SubSliceMut;
 use kernel::{ErrorCode, ProcessId};
 
 const SECURITY_SUITE_ENCRYP: u8 = 0;
 pub const DRIVER_NUM: usize = driver::NUM::Thread as usize;
 
+/// Length of the HMAC input used to derive the MLE and MAC keys: the key
+/// sequence followed by the string "Thread".
+pub const KEY_DERIVATION_DATA_LEN: usize = 4 + KEY_DERIVATION_STR.len();
+
+// Timeouts and retry counts of the attach process (Thread Spec v1.3.0 --
+// sect. 4.5.1 and 4.7.3).
+
+/// Time to wait for Parent Responses to a request soliciting only routers.
+const PARENT_RSP_TIMEOUT_ROUTERS_MS: u32 = 750;
+/// Time to wait for Parent Responses to a request soliciting routers and
+/// REEDs.
+const PARENT_RSP_TIMEOUT_REEDS_MS: u32 = 1250;
+/// Number of Parent Requests soliciting only routers in an attach cycle.
+const PARENT_REQ_ROUTER_ATTEMPTS: u8 = 2;
+/// Total number of Parent Requests in an attach cycle.
+const PARENT_REQ_ATTEMPTS: u8 = 6;
+/// Time to wait for a Child ID Response.
+const CHILD_ID_RSP_TIMEOUT_MS: u32 = 1250;
+/// Number of Child ID Requests sent to a parent before giving up on it.
+const CHILD_ID_REQ_ATTEMPTS: u8 = 3;
+/// Time to wait for a Child Update Response.
+const CHILD_UPDATE_RSP_TIMEOUT_MS: u32 = 1000;
+/// Number of unanswered Child Update Requests after which the child
+/// considers its parent lost.
+const CHILD_UPDATE_REQ_ATTEMPTS: u8 = 3;
+/// Interval between keep-alive Child Update Requests, well within the
+/// timeout requested from the parent.
+const KEEP_ALIVE_INTERVAL_S: u32 = CHILD_TIMEOUT_S / 2;
+/// Bounds of the (vendor-specific) backoff between failed attach cycles.
+const ATTACH_BACKOFF_MIN_S: u32 = 8;
+const ATTACH_BACKOFF_MAX_S: u32 = 300;
+
 /// Ids for read-only allow buffers
 mod ro_allow {
     pub const WRITE: usize = 0;
@@ -91,8 +148,22 @@ mod upcall {
 #[derive(Default)]
 pub struct App {}
 
+/// Operation underway in the AES-128CCM engine.
+#[derive(Clone, Copy)]
+enum CryptOp {
+    /// Securing an outgoing MLE message.
+    Encrypt,
+    /// Authenticating and decrypting a received MLE message secured with the
+    /// keys of the given key sequence.
+    Decrypt(u32),
+}
+
 #[allow(dead_code)]
-pub struct ThreadNetworkDriver<'a, A: time::Alarm<'a>> {
+pub struct ThreadNetworkDriver<
+    'a,
+    A: time::Alarm<'a>,
+    H: digest::Digest<'a, 32> + digest::HmacSha256,
+> {
     /// UDP sender
     sender: &'a dyn UDPSender<'a>,
 
@@ -102,6 +173,12 @@ pub struct ThreadNetworkDriver<'a, A: time::Alarm<'a>> {
     /// Alarm for timeouts
     alarm: &'a A,
 
+    /// Random number generator for MLE challenges and backoff jitter
+    rng: &'a dyn rng::Rng<'a>,
+
+    /// HMAC-SHA256 engine used to derive the MLE/MAC keys
+    hmac: &'a H,
+
     /// Grant of apps that use this thread driver.
     apps: Grant<App, UpcallCount<1>, AllowRoCount<{ ro_allow::COUNT }>, AllowRwCount<0>>,
 
@@ -132,9 +209,53 @@ pub struct ThreadNetworkDriver<'a, A: time::Alarm<'a>> {
     /// Frame counter for Thread MLE
     frame_count: Cell<u32>,
 
-    /// Stored Thread network containing mac/MLE key
+    /// Stored Thread network containing mac/MLE key of the current key
+    /// sequence
     networkkey: MapCell<NetworkKey>,
 
+    /// Keys of the previous and next key sequence, if known
+    prev_networkkey: OptionalCell<NetworkKey>,
+    next_networkkey: OptionalCell<NetworkKey>,
+
+    /// Current key sequence
+    key_sequence: Cell<u32>,
+
+    /// Thread network key the MLE/MAC keys are derived from. Empty if the
+    /// application provided pre-derived keys.
+    thread_key: OptionalCell<[u8; 16]>,
+
+    /// Key sequence whose keys are being derived
+    deriving_key: OptionalCell<u32>,
+
+    /// Buffers for the HMAC key derivation
+    hmac_data: TakeCell<'static, [u8]>,
+    hmac_digest: TakeCell<'static, [u8; 32]>,
+
+    /// Challenge sent in the last Parent Request or Child Update Request
+    challenge: Cell<[u8; CHALLENGE_LEN]>,
+
+    /// Challenge received in the Parent Response of the selected parent
+    parent_challenge: Cell<[u8; CHALLENGE_LEN]>,
+
+    /// Number of Parent Requests sent in the current attach cycle
+    attach_attempt: Cell<u8>,
+
+    /// Number of retransmissions of the current Child ID Request or Child
+    /// Update Request
+    retries: Cell<u8>,
+
+    /// Backoff before the next attach cycle, in seconds
+    backoff_s: Cell<u32>,
+
+    /// Random value used to spread the attach backoff
+    jitter: Cell<u32>,
+
+    /// The application awaits the result of its join command
+    join_pending: Cell<bool>,
+
+    /// Operation underway in the crypto engine
+    crypt_op: OptionalCell<CryptOp>,
+
     /// Length of the message passed to the crypto engine
     crypto_sizelock: MapCell<usize>,
 }
@@ -146,24 +267,32 @@ pub struct ThreadNetworkDriver<'a, A: time::Alarm<'a>> {
 // For now, Tock only supports one application using the Thread network.
 // After the network is "locked" to one application, other userspace
 // applications requesting to join a Thread network will fail.
-impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
+impl<'a, A: time::Alarm<'a>, H: digest::Digest<'a, 32> + digest::HmacSha256>
+    ThreadNetworkDriver<'a, A, H>
+{
     pub fn new(
         sender: &'a dyn UDPSender<'a>,
         aes_crypto: &'a dyn AES128CCM<'a>,
         alarm: &'a A,
+        rng: &'a dyn rng::Rng<'a>,
+        hmac: &'a H,
         grant: Grant<App, UpcallCount<1>, AllowRoCount<{ ro_allow::COUNT }>, AllowRwCount<0>>,
         src_mac_addr: [u8; 8],
         max_tx_pyld_len: usize,
         port_table: &'static UdpPortManager,
         send_buffer: SubSliceMut<'static, u8>,
         recv_buffer: SubSliceMut<'static, u8>,
+        hmac_data: &'static mut [u8],
+        hmac_digest: &'static mut [u8; 32],
         driver_send_cap: &'static dyn UdpDriverCapability,
         net_cap: &'static NetworkCapability,
-    ) -> ThreadNetworkDriver<'a, A> {
+    ) -> ThreadNetworkDriver<'a, A, H> {
         ThreadNetworkDriver {
             sender,
             aes_crypto,
             alarm,
+            rng,
+            hmac,
             apps: grant,
             src_mac_addr,
             max_tx_pyld_len,
@@ -175,6 +304,21 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
             net_cap,
             frame_count: Cell::new(5),
             networkkey: MapCell::empty(),
+            prev_networkkey: OptionalCell::empty(),
+            next_networkkey: OptionalCell::empty(),
+            key_sequence: Cell::new(0),
+            thread_key: OptionalCell::empty(),
+            deriving_key: OptionalCell::empty(),
+            hmac_data: TakeCell::new(hmac_data),
+            hmac_digest: TakeCell::new(hmac_digest),
+            challenge: Cell::new([0; CHALLENGE_LEN]),
+            parent_challenge: Cell::new([0; CHALLENGE_LEN]),
+            attach_attempt: Cell::new(0),
+            retries: Cell::new(0),
+            backoff_s: Cell::new(ATTACH_BACKOFF_MIN_S),
+            jitter: Cell::new(0),
+            join_pending: Cell::new(false),
+            crypt_op: OptionalCell::empty(),
             crypto_sizelock: MapCell::empty(),
         }
     }
@@ -184,53 +328,223 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
         self.networkkey.replace(NetworkKey { mle_key, mac_key });
     }
 
-    fn send_parent_req(&self) {
-        // UNCOMMENT TO DEBUG THREAD //
-        // kernel::debug!("[Thread] Sending parent request...");
-
-        // Panicking on unwrap indicates the state was taken without replacement
-        // (unreachable with proper state machine implementation)
-        let curr_state = self.state.take().unwrap();
-
-        match curr_state {
-            ThreadState::Detached => {
-                // A parent request can only begin from a detached state. We utilize
-                // helper functions to form the request and send the parent request
-                // to the multicast IP/Mac Address
-                self.state.replace(ThreadState::SendParentReq);
-                let parent_req_mle = form_parent_req();
-                let src_ipv6 = generate_src_ipv6(&self.src_mac_addr);
-                self.thread_mle_send(&parent_req_mle, MULTICAST_IPV6, src_ipv6)
-                    .err()
-                    .map(|code| {
-                        // Thread send failed sending parent req so we terminate and return
-                        // to a detached state.
-                        self.state.replace(ThreadState::Detached);
-
-                        // UNCOMMENT TO DEBUG THREAD //
-                        // kernel::debug!(
-                        //     "[Thread] Failed sending MLE parent request - crypto operation error."
-                        // );
-                        self.terminate_child_join(Err(code));
-                    });
+    /// Returns the MLE/MAC keys of `key_sequence`, if it is the current,
+    /// next or previous key sequence and its keys are known.
+    fn lookup_networkkey(&self, key_sequence: u32) -> Option<NetworkKey> {
+        let current = self.key_sequence.get();
+        if key_sequence == current {
+            self.networkkey.get()
+        } else if key_sequence == current.wrapping_add(1) {
+            self.next_networkkey.get()
+        } else if key_sequence == current.wrapping_sub(1) {
+            self.prev_networkkey.get()
+        } else {
+            None
+        }
+    }
+
+    /// Starts deriving the MLE and MAC keys of `key_sequence` from the
+    /// Thread network key: HMAC-SHA256(network key, key sequence || "Thread")
+    /// (Thread Spec v1.3.0 -- sect. 7.1.4).
+    fn derive_keys(&self, key_sequence: u32) -> Result<(), ErrorCode> {
+        let thread_key = self.thread_key.get().ok_or(ErrorCode::NOSUPPORT)?;
+        if self.deriving_key.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        let data = self.hmac_data.take().ok_or(ErrorCode::BUSY)?;
+        if data.len() < KEY_DERIVATION_DATA_LEN {
+            self.hmac_data.replace(data);
+            return Err(ErrorCode::SIZE);
+        }
+        data[..4].copy_from_slice(&key_sequence.to_be_bytes());
+        data[4..KEY_DERIVATION_DATA_LEN].copy_from_slice(KEY_DERIVATION_STR);
+
+        if let Err(code) = self.hmac.set_mode_hmacsha256(&thread_key) {
+            self.hmac_data.replace(data);
+            return Err(code);
+        }
+        let mut data = SubSliceMut::new(data);
+        data.slice(..KEY_DERIVATION_DATA_LEN);
+        self.hmac.add_mut_data(data).map_err(|(code, data)| {
+            self.hmac_data.replace(data.take());
+            code
+        })?;
+        self.deriving_key.set(key_sequence);
+        Ok(())
+    }
+
+    /// Called when the derivation of the keys of a key sequence completes.
+    fn keys_derived(&self, result: Result<NetworkKey, ErrorCode>) {
+        let key_sequence = match self.deriving_key.take() {
+            Some(key_sequence) => key_sequence,
+            None => return,
+        };
+        let current = self.key_sequence.get();
+
+        match result {
+            Ok(keys) if key_sequence == current => {
+                self.networkkey.replace(keys);
+                // Also derive the keys of the next key sequence, so that
+                // the node can follow a key switch of the network.
+                let _ = self.derive_keys(current.wrapping_add(1));
+                if matches!(self.state.get(), Some(ThreadState::Detached))
+                    && self.join_pending.get()
+                {
+                    self.start_attach();
+                }
+            }
+            Ok(keys) if key_sequence == current.wrapping_add(1) => {
+                self.next_networkkey.set(keys);
             }
-            ThreadState::SEDActive(_, _)
-            | ThreadState::SendUpdate(_, _)
-            | ThreadState::SendUDPMsg => {
-                // These states constitute a device that has previously sucessfully
-                // joined the network. There is no need to issue a new parent request.
-                // Replace state, and terminate.
-                self.state.replace(curr_state);
-                self.terminate_child_join(Err(ErrorCode::ALREADY));
+            // The key sequence changed while the keys were derived.
+            Ok(_) => (),
+            Err(code) => {
+                if key_sequence == current && self.networkkey.is_none() {
+                    // Without keys the node cannot attach; release the
+                    // Thread network for other applications.
+                    self.state.take();
+                    self.join_pending.set(false);
+                    self.terminate_child_join(Err(code));
+                }
             }
-            _ => {
-                // All other Thread states indicate that the thread device has already
-                // begun the process of connecting to a parent device. Terminate the parent request.
-                // Replace state, and terminate.
-                self.state.replace(curr_state);
-                self.terminate_child_join(Err(ErrorCode::BUSY));
+        }
+    }
+
+    /// Switches to `key_sequence` after a message secured with it has been
+    /// authenticated. Only a switch to the next key sequence is possible,
+    /// since the keys of other sequences are unknown.
+    fn switch_key_sequence(&self, key_sequence: u32) {
+        let current = self.key_sequence.get();
+        if key_sequence != current.wrapping_add(1) {
+            return;
+        }
+        let next = match self.next_networkkey.take() {
+            Some(next) => next,
+            None => return,
+        };
+
+        // UNCOMMENT TO DEBUG THREAD //
+        // kernel::debug!("[Thread] Switching to key sequence {}", key_sequence);
+
+        if let Some(current_key) = self.networkkey.get() {
+            self.prev_networkkey.set(current_key);
+        }
+        self.networkkey.replace(next);
+        self.key_sequence.set(key_sequence);
+        // The MLE frame counter restarts with each key sequence.
+        self.frame_count.set(0);
+        let _ = self.derive_keys(key_sequence.wrapping_add(1));
+    }
+
+    fn set_timer_ms(&self, ms: u32) {
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(ms));
+    }
+
+    /// Starts a new attach cycle.
+    fn start_attach(&self) {
+        // UNCOMMENT TO DEBUG THREAD //
+        // kernel::debug!("[Thread] Starting attach cycle...");
+        self.attach_attempt.set(0);
+        self.send_parent_req();
+    }
+
+    /// Sends the next Parent Request of the attach cycle, or fails the cycle
+    /// if all attempts were used.
+    fn send_parent_req(&self) {
+        let attempt = self.attach_attempt.get();
+        if attempt >= PARENT_REQ_ATTEMPTS {
+            self.attach_failed();
+            return;
+        }
+        self.attach_attempt.set(attempt + 1);
+        self.state.replace(ThreadState::SendParentReq);
+        self.request_challenge();
+    }
+
+    fn send_child_id_req(&self, parent: IPAddr) {
+        // UNCOMMENT TO DEBUG THREAD //
+        // kernel::debug!("[Thread] Sending Child ID Request...");
+        self.state.replace(ThreadState::SendChildIdReq(parent));
+        let (output, offset) =
+            form_child_id_req(self.parent_challenge.get(), self.frame_count.get());
+        self.send_mle(&output[..offset], parent);
+    }
+
+    fn send_child_update_req(&self, parent: IPAddr, parent_mac: MacAddress) {
+        self.state
+            .replace(ThreadState::SendUpdate(parent, parent_mac));
+        self.request_challenge();
+    }
+
+    /// Requests randomness for the challenge of the message about to be
+    /// sent. The message is sent once the randomness is available.
+    fn request_challenge(&self) {
+        if self.rng.get().is_err() {
+            self.await_response();
+        }
+    }
+
+    /// Secures and sends an MLE message. If the message cannot be sent, it
+    /// is treated as lost so that the response timeout triggers a retry.
+    fn send_mle(&self, mle_buf: &[u8], dest_addr: IPAddr) {
+        let src_ipv6 = generate_src_ipv6(&self.src_mac_addr);
+        if self.thread_mle_send(mle_buf, dest_addr, src_ipv6).is_err() {
+            // UNCOMMENT TO DEBUG THREAD //
+            // kernel::debug!("[Thread] Failed sending MLE message.");
+            self.await_response();
+        }
+    }
+
+    /// Advances the state machine once a request has been sent (or lost)
+    /// and arms the timeout for its response.
+    fn await_response(&self) {
+        let (next_state, timeout_ms) = match self.state.get() {
+            Some(ThreadState::SendParentReq) => {
+                let timeout_ms = if self.attach_attempt.get() <= PARENT_REQ_ROUTER_ATTEMPTS {
+                    PARENT_RSP_TIMEOUT_ROUTERS_MS
+                } else {
+                    PARENT_RSP_TIMEOUT_REEDS_MS
+                };
+                (ThreadState::WaitingParentRsp, timeout_ms)
             }
+            Some(ThreadState::SendChildIdReq(parent)) => (
+                ThreadState::WaitingChildRsp(parent),
+                CHILD_ID_RSP_TIMEOUT_MS,
+            ),
+            Some(ThreadState::SendUpdate(parent, parent_mac)) => (
+                ThreadState::WaitingUpdateRsp(parent, parent_mac),
+                CHILD_UPDATE_RSP_TIMEOUT_MS,
+            ),
+            _ => return,
         };
+        self.state.replace(next_state);
+        self.set_timer_ms(timeout_ms);
+    }
+
+    /// Detaches from the parent and immediately starts attaching again.
+    fn detach(&self) {
+        // UNCOMMENT TO DEBUG THREAD //
+        // kernel::debug!("[Thread] Detached from parent.");
+        let _ = self.alarm.disarm();
+        self.state.replace(ThreadState::Detached);
+        self.start_attach();
+    }
+
+    /// Ends an attach cycle that did not find a parent, and waits for the
+    /// backoff before starting the next one.
+    fn attach_failed(&self) {
+        self.state.replace(ThreadState::Detached);
+        if self.join_pending.take() {
+            self.terminate_child_join(Err(ErrorCode::NOACK));
+        }
+
+        // Up to 50% of random jitter keeps nodes that lost their parent at
+        // the same time from retrying in lockstep.
+        let backoff_s = self.backoff_s.get();
+        self.backoff_s
+            .set((backoff_s * 2).min(ATTACH_BACKOFF_MAX_S));
+        self.set_timer_ms(backoff_s * 1000 + self.jitter.get() % (backoff_s * 500));
     }
 
     fn thread_mle_send(
@@ -246,66 +560,97 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
             level: SecurityLevel::EncMic32,
             asn_in_nonce: false,
             frame_counter: Some(self.frame_count.get()),
-            key_id: KeyId::Source4Index([0, 0, 0, 0], 1),
+            key_id: key_id_from_sequence(self.key_sequence.get()),
         };
 
         // Begin cryptographic and sending procedure for the MLE message
         self.send_buffer
             .take()
             .map_or(Err(ErrorCode::NOMEM), |send_buffer| {
-                self.perform_crypt_op(src_addr, dest_addr, security, mle_buf, send_buffer.take())
-                    .map_err(|(code, buf)| {
-                        // Error occured with cryptographic operation, replace buffer
-                        // for future transmissions and return error code
-                        self.send_buffer.replace(SubSliceMut::new(buf));
-                        code
-                    })
+                self.perform_crypt_op(
+                    src_addr,
+                    dest_addr,
+                    security,
+                    mle_buf,
+                    send_buffer.take(),
+                    CryptOp::Encrypt,
+                )
+                .map_err(|(code, buf)| {
+                    // Error occured with cryptographic operation, replace buffer
+                    // for future transmissions and return error code
+                    self.send_buffer.replace(SubSliceMut::new(buf));
+                    code
+                })
             })
     }
 
-    fn recv_logic(&self, sender_ip: IPAddr) -> Result<(), ErrorCode> {
+    fn recv_logic(&self, sender_ip: IPAddr) {
         // This function is called once the received MLE payload has been placed
         // into the recv_buffer. The function handles the message and responds accordingly
+        if let Some(mut recv_buf) = self.recv_buffer.take() {
+            if let Some((&command, tlvs)) = recv_buf.as_slice().split_first() {
+                self.handle_mle(command, tlvs, sender_ip);
+            }
+            recv_buf.reset();
+            self.recv_buffer.replace(recv_buf);
+        }
+    }
 
-        self.recv_buffer
-            .take()
-            .map_or(Err(ErrorCode::NOMEM), |mut recv_buf| {
-                if recv_buf[0] == MleCommand::ParentResponse as u8 {
-                    // Received Parent Response -> form Child ID Request
+    /// Handles a decrypted MLE message (`command` followed by `tlvs`)
+    /// depending on the state of the attach process. Messages that are not
+    /// expected in the current state are ignored.
+    fn handle_mle(&self, command: u8, tlvs: &[u8], sender_ip: IPAddr) {
+        match self.state.get() {
+            Some(ThreadState::WaitingParentRsp) if command == MleCommand::ParentResponse as u8 => {
+                // UNCOMMENT TO DEBUG THREAD //
+                // kernel::debug!("[Thread] Received Parent Response.");
 
-                    // UNCOMMENT TO DEBUG THREAD //
-                    // kernel::debug!("[Thread] Received Parent Response.");
-                    // kernel::debug!("[Thread] Sending Child ID Request...");
-
-                    let src_ipv6 = generate_src_ipv6(&self.src_mac_addr);
-
-                    let (output, offset) =
-                        form_child_id_req(recv_buf.as_slice(), self.frame_count.get())?;
-
-                    // Advance state machine
-                    self.state.replace(ThreadState::SendChildIdReq(sender_ip));
-
-                    self.thread_mle_send(&output[..offset], sender_ip, src_ipv6)?;
-                } else if recv_buf[0] == MleCommand::ChildIdResponse as u8 {
-                    // Receive child id response -> advance state machine
-                    self.state.replace(ThreadState::SEDActive(
-                        sender_ip,
-                        MacAddress::Long(mac_from_ipv6(sender_ip)),
-                    ));
-
-                    // TODO: once heart beats are implemented, we will set
-                    // the timer here (as seen below)
-                    // let curr_time = self.alarm.now();
-                    // self.alarm.set_alarm(
-                    //     curr_time,
-                    //     time::ConvertTicks::ticks_from_seconds(self.alarm, 5),
-                    // );
+                if !response_matches(tlvs, &self.challenge.get()) {
+                    return;
                 }
-
-                recv_buf.reset();
-                self.recv_buffer.replace(recv_buf);
-                Ok(())
-            })
+                let parent_challenge = match find_challenge(tlvs) {
+                    Ok(challenge) => challenge,
+                    Err(_) => return,
+                };
+                let _ = self.alarm.disarm();
+                self.parent_challenge.set(parent_challenge);
+                self.retries.set(0);
+                self.send_child_id_req(sender_ip);
+            }
+            Some(ThreadState::WaitingChildRsp(parent))
+                if command == MleCommand::ChildIdResponse as u8 && sender_ip == parent =>
+            {
+                // UNCOMMENT TO DEBUG THREAD //
+                // kernel::debug!("[Thread] Attached to parent.");
+
+                self.state.replace(ThreadState::SEDActive(
+                    parent,
+                    MacAddress::Long(mac_from_ipv6(parent)),
+                ));
+                self.backoff_s.set(ATTACH_BACKOFF_MIN_S);
+                self.set_timer_ms(KEEP_ALIVE_INTERVAL_S * 1000);
+                if self.join_pending.take() {
+                    self.terminate_child_join(Ok(()));
+                }
+            }
+            Some(ThreadState::WaitingUpdateRsp(parent, parent_mac))
+                if command == MleCommand::ChildUpdateResponse as u8 && sender_ip == parent =>
+            {
+                if !response_matches(tlvs, &self.challenge.get()) {
+                    return;
+                }
+                if find_tlv(tlvs, TlvType::Status).is_some() {
+                    // The parent no longer has this node as a child
+                    // (Thread Spec v1.3.0 -- sect. 4.7.3).
+                    self.detach();
+                    return;
+                }
+                self.state
+                    .replace(ThreadState::SEDActive(parent, parent_mac));
+                self.set_timer_ms(KEEP_ALIVE_INTERVAL_S * 1000);
+            }
+            _ => (),
+        }
     }
 
     fn terminate_child_join(&self, res: Result<(), ErrorCode>) {
@@ -326,12 +671,14 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
         security: Security,
         payload: &[u8],
         buf: &'static mut [u8],
+        op: CryptOp,
     ) -> Result<(), (ErrorCode, &'static mut [u8])> {
-        // Wrapper function for performing the AES-128CCM encryption. This function generates the nonce,
-        // sets the nonce/key for the crypto engine, generates the authenticated data, and initiates
-        // the crypto operation.
+        // Wrapper function for performing the AES-128CCM encryption and decryption. This
+        // function generates the nonce, sets the nonce/key for the crypto engine, generates
+        // the authenticated data, and initiates the crypto operation.
 
-        // Note: The payload argument does not include aux sec header
+        // Note: The payload argument does not include aux sec header. When decrypting, it
+        // includes the mic, which the crypto engine checks.
 
         // Obtain and unwrap frame counter
         let frame_counter = security.frame_counter;
@@ -341,13 +688,18 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
             return Err((ErrorCode::INVAL, buf));
         }
 
-        // Generate nonce, obtain network key and set crypto engine accordingly
+        // Generate nonce, obtain network key of the key sequence and set
+        // crypto engine accordingly
         let nonce = get_ccm_nonce(
             &mac_from_ipv6(src_addr),
             frame_counter.unwrap(),
             security.level,
         );
-        let mle_key = self.networkkey.get();
+        let (key_sequence, encrypting) = match op {
+            CryptOp::Encrypt => (self.key_sequence.get(), true),
+            CryptOp::Decrypt(key_sequence) => (key_sequence, false),
+        };
+        let mle_key = self.lookup_networkkey(key_sequence);
         let mic_len = security.level.mic_len();
         match mle_key {
             Some(netkey) => {
@@ -388,7 +740,13 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
         let aux_sec_header = &mut [0u8; AUX_SEC_HEADER_LENGTH];
         Security::encode(&security, aux_sec_header);
 
-        let m_data_len = payload.len();
+        let m_data_len = if encrypting {
+            payload.len()
+        } else if payload.len() >= mic_len {
+            payload.len() - mic_len
+        } else {
+            return Err((ErrorCode::SIZE, buf));
+        };
 
         // Encode auth data and payload into `buf`
         let encode_res = encode_cryp_data(src_addr, dst_addr, aux_sec_header, payload, buf).done();
@@ -400,8 +758,6 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
             return Err((ErrorCode::FAIL, buf));
         }
 
-        let (offset, ()) = encode_res.unwrap();
-
         // GENERAL NOTE: `self.crypto_sizelock`
         // This does not seem to be the most elegant solution. The `crypto_sizelock` arose from the fact
         // that we must know the length of the payload when the `crypt_done` callback
@@ -426,28 +782,44 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
             return Err((ErrorCode::BUSY, buf));
         }
 
-        // Store the length of the payload.
-        self.crypto_sizelock.replace(offset + mic_len);
+        // Store the length of the payload (including the mic).
+        self.crypto_sizelock
+            .replace(AUTH_DATA_LEN + m_data_len + mic_len);
+        self.crypt_op.set(op);
         self.aes_crypto
-            .crypt(buf, 0, AUTH_DATA_LEN, m_data_len, mic_len, true, true)
+            .crypt(buf, 0, AUTH_DATA_LEN, m_data_len, mic_len, true, encrypting)
+            .inspect_err(|_| {
+                self.crypto_sizelock.take();
+                self.crypt_op.clear();
+            })
     }
 }
 
-impl<'a, A: time::Alarm<'a>> framer::KeyProcedure for ThreadNetworkDriver<'a, A> {
+impl<'a, A: time::Alarm<'a>, H: digest::Digest<'a, 32> + digest::HmacSha256> framer::KeyProcedure
+    for ThreadNetworkDriver<'a, A, H>
+{
     /// Gets the key corresponding to the key that matches the given security
     /// level `level` and key ID `key_id`. If no such key matches, returns
     /// `None`.
-    // TODO: This implementation only supports one key
-    fn lookup_key(&self, _level: SecurityLevel, _key_id: KeyId) -> Option<[u8; 16]> {
-        if let Some(netkey) = self.networkkey.get() {
-            Some(netkey.mac_key)
-        } else {
-            None
-        }
+    fn lookup_key(&self, _level: SecurityLevel, key_id: KeyId) -> Option<[u8; 16]> {
+        // Link-layer frames identify the key sequence by its key index, or
+        // by the full key sequence in the key source.
+        let current = self.key_sequence.get();
+        [current, current.wrapping_add(1), current.wrapping_sub(1)]
+            .into_iter()
+            .find(|&key_sequence| match key_id {
+                KeyId::Index(index) => key_index(key_sequence) == index,
+                KeyId::Source4Index(..) => key_sequence_from_id(key_id) == Some(key_sequence),
+                _ => key_sequence == current,
+            })
+            .and_then(|key_sequence| self.lookup_networkkey(key_sequence))
+            .map(|netkey| netkey.mac_key)
     }
 }
 
-impl<'a, A: time::Alarm<'a>> framer::DeviceProcedure for ThreadNetworkDriver<'a, A> {
+impl<'a, A: time::Alarm<'a>, H: digest::Digest<'a, 32> + digest::HmacSha256> framer::DeviceProcedure
+    for ThreadNetworkDriver<'a, A, H>
+{
     /// Gets the key corresponding to the key that matches the given security
     /// level `level` and key ID `key_id`. If no such key matches, returns
     /// `None`.
@@ -457,15 +829,21 @@ impl<'a, A: time::Alarm<'a>> framer::DeviceProcedure for ThreadNetworkDriver<'a,
     }
 }
 
-impl<'a, A: time::Alarm<'a>> SyscallDriver for ThreadNetworkDriver<'a, A> {
+impl<'a, A: time::Alarm<'a>, H: digest::Digest<'a, 32> + digest::HmacSha256> SyscallDriver
+    for ThreadNetworkDriver<'a, A, H>
+{
     /// ### `command_num`
     /// - `0`: Driver Check
-    /// - `1`: Add a new mle/mac networkkey and initiate a parent request.
+    /// - `1`: Add a network key and initiate the attach process. The allowed
+    ///        buffer holds either the 16 byte Thread network key, from which
+    ///        the MLE/MAC keys are derived, or the 32 byte MLE key || MAC key
+    ///        pair, which disables key rotation. `arg1` is the current key
+    ///        sequence.
 
     fn command(
         &self,
         command_num: usize,
-        _arg1: usize,
+        arg1: usize,
         _: usize,
         processid: ProcessId,
     ) -> CommandReturn {
@@ -483,36 +861,59 @@ impl<'a, A: time::Alarm<'a>> SyscallDriver for ThreadNetworkDriver<'a, A> {
                                 // another userspace application has control of the Thread
                                 // network and other requesting applications should fail.
                                 if self.state.is_some() {
-                                    return CommandReturn::failure(ErrorCode::BUSY);
+                                    return Err(ErrorCode::BUSY);
                                 }
 
-                                // src key consists of the mle and mac keys; Thread
-                                // hash is performed in userland and 32 byte hash is
-                                // passed to thread capsule and entered as mac/mle key
-                                // (For key generation see Thread spec v1.3.0 7.1.4)
-                                if src_key.len() != 32 {
-                                    return CommandReturn::failure(ErrorCode::SIZE);
+                                match src_key.len() {
+                                    // Thread network key; the MLE/MAC keys are derived
+                                    // from it (For key generation see Thread spec v1.3.0
+                                    // 7.1.4)
+                                    16 => {
+                                        let mut thread_key = [0u8; 16];
+                                        src_key.copy_to_slice(&mut thread_key);
+                                        self.thread_key.set(thread_key);
+                                    }
+                                    // src key consists of the mle and mac keys; Thread
+                                    // hash is performed in userland and 32 byte hash is
+                                    // passed to thread capsule and entered as mac/mle key
+                                    32 => {
+                                        let mut mle_key = [0u8; 16];
+                                        let mut mac_key = [0u8; 16];
+                                        src_key[..16].copy_to_slice(&mut mle_key);
+                                        src_key[16..32].copy_to_slice(&mut mac_key);
+                                        self.thread_key.clear();
+                                        self.set_networkkey(mle_key, mac_key);
+                                    }
+                                    _ => return Err(ErrorCode::SIZE),
                                 }
-                                let mut mle_key = [0u8; 16];
-                                let mut mac_key = [0u8; 16];
-                                src_key[..16].copy_to_slice(&mut mle_key);
-                                src_key[16..32].copy_to_slice(&mut mac_key);
-                                self.set_networkkey(mle_key, mac_key);
+                                self.key_sequence.set(arg1 as u32);
 
                                 // Thread state begins as detached if sucessfully joined
                                 self.state.replace(ThreadState::Detached);
-                                CommandReturn::success()
+                                Ok(())
                             })
                         })
-                        .unwrap_or(CommandReturn::failure(ErrorCode::INVAL))
+                        .unwrap_or(Err(ErrorCode::INVAL))
                 })
                 .map_or_else(
                     |err| CommandReturn::failure(err.into()),
-                    |ok_val| {
-                        // If no failure in saving the mle/mac key, initiate
-                        // sending the parent request
-                        self.send_parent_req();
-                        ok_val
+                    |res| {
+                        if res.is_ok() {
+                            // If no failure in saving the key, initiate the attach
+                            // process once the MLE/MAC keys are available
+                            self.join_pending.set(true);
+                            self.backoff_s.set(ATTACH_BACKOFF_MIN_S);
+                            if self.thread_key.is_some() {
+                                let key_sequence = self.key_sequence.get();
+                                if let Err(code) = self.derive_keys(key_sequence) {
+                                    self.deriving_key.set(key_sequence);
+                                    self.keys_derived(Err(code));
+                                }
+                            } else {
+                                self.start_attach();
+                            }
+                        }
+                        CommandReturn::from(res)
                     },
                 ),
 
@@ -525,56 +926,158 @@ impl<'a, A: time::Alarm<'a>> SyscallDriver for ThreadNetworkDriver<'a, A> {
     }
 }
 
-impl<'a, A: time::Alarm<'a>> UDPSendClient for ThreadNetworkDriver<'a, A> {
+impl<'a, A: time::Alarm<'a>, H: digest::Digest<'a, 32> + digest::HmacSha256> UDPSendClient
+    for ThreadNetworkDriver<'a, A, H>
+{
     fn send_done(&self, _result: Result<(), ErrorCode>, mut dgram: SubSliceMut<'static, u8>) {
-        // TODO: handle result from send done and respond accordingly
-
-        // Panicking on unwrap indicates the state was taken without replacement
-        // (unreachable with proper state machine implementation)
-        let curr_state = self.state.take().unwrap();
-
-        // Advance state machine
-        let next_state = match curr_state {
-            ThreadState::SendUpdate(dst_ip, dst_mac) => ThreadState::SEDActive(dst_ip, dst_mac),
-            ThreadState::SendUDPMsg => unimplemented!(),
-            ThreadState::SendChildIdReq(_) => ThreadState::WaitingChildRsp,
-            ThreadState::SendParentReq => {
-                // UNCOMMENT TO DEBUG THREAD //
-                // kernel::debug!("[Thread] Completed sending parent request to multicast IP");
-                ThreadState::WaitingParentRsp
-            }
-            _ => panic!("Thread state machine diverged"),
-        };
-
+        // The frame counter was used to secure the message, so it must not
+        // be reused even if sending failed.
         self.frame_count.set(self.frame_count.get() + 1);
 
-        // Replace the returned buffer and state
+        // Replace the returned buffer
         dgram.reset();
         self.send_buffer.replace(dgram);
-        self.state.replace(next_state);
+
+        // A message that failed to send is treated as lost, and the response
+        // timeout retries it.
+        self.await_response();
     }
 }
 
-impl<'a, A: time::Alarm<'a>> time::AlarmClient for ThreadNetworkDriver<'a, A> {
-    // TODO: This function is mainly here as a place holder as it will be needed
-    // for implementing timeouts/timing for sending heartbeat messages to the parent
-    // node
+impl<'a, A: time::Alarm<'a>, H: digest::Digest<'a, 32> + digest::HmacSha256> time::AlarmClient
+    for ThreadNetworkDriver<'a, A, H>
+{
     fn alarm(&self) {
-        match self.state.take().unwrap() {
-            // TODO: Implement retries as defined in the thread spec (when timeouts occur)
-            ThreadState::Detached => unimplemented!("[Thread ALARM] Detached"),
-            ThreadState::SendParentReq => unimplemented!("[Thread ALARM] Send Parent Req"),
-            ThreadState::SendChildIdReq(_) => unimplemented!("[Thread ALARM] Send Child ID Req"),
-            ThreadState::SEDActive(_ipaddr, _mac) => {
-                // TODO: SEND HEARTBEAT to parent node
-                unimplemented!("[Thread ALARM] Send Heartbeat")
+        match self.state.get() {
+            // The attach backoff expired
+            Some(ThreadState::Detached) => self.start_attach(),
+            // No acceptable Parent Response was received
+            Some(ThreadState::WaitingParentRsp) => self.send_parent_req(),
+            Some(ThreadState::WaitingChildRsp(parent)) => {
+                let retries = self.retries.get() + 1;
+                self.retries.set(retries);
+                if retries < CHILD_ID_REQ_ATTEMPTS {
+                    self.send_child_id_req(parent);
+                } else {
+                    // Give up on this parent and continue the attach cycle
+                    self.send_parent_req();
+                }
+            }
+            // Keep-alive
+            Some(ThreadState::SEDActive(parent, parent_mac)) => {
+                self.retries.set(0);
+                self.send_child_update_req(parent, parent_mac);
+            }
+            Some(ThreadState::WaitingUpdateRsp(parent, parent_mac)) => {
+                let retries = self.retries.get() + 1;
+                self.retries.set(retries);
+                if retries < CHILD_UPDATE_REQ_ATTEMPTS {
+                    self.send_child_update_req(parent, parent_mac);
+                } else {
+                    // The parent is considered lost
+                    self.detach();
+                }
+            }
+            // A message is being prepared; the timeout for its response is
+            // armed once it has been sent.
+            Some(ThreadState::SendParentReq)
+            | Some(ThreadState::SendChildIdReq(_))
+            | Some(ThreadState::SendUpdate(_, _))
+            | None => (),
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, H: digest::Digest<'a, 32> + digest::HmacSha256> rng::Client
+    for ThreadNetworkDriver<'a, A, H>
+{
+    fn randomness_available(
+        &self,
+        randomness: &mut dyn Iterator<Item = u32>,
+        error: Result<(), ErrorCode>,
+    ) -> rng::Continue {
+        if error.is_err() {
+            // Without a challenge the message cannot be sent; treat it as
+            // lost so that it is retried.
+            self.await_response();
+            return rng::Continue::Done;
+        }
+
+        let (first, second, jitter) =
+            match (randomness.next(), randomness.next(), randomness.next()) {
+                (Some(first), Some(second), Some(jitter)) => (first, second, jitter),
+                _ => return rng::Continue::More,
+            };
+        let mut challenge = [0u8; CHALLENGE_LEN];
+        challenge[..4].copy_from_slice(&first.to_ne_bytes());
+        challenge[4..].copy_from_slice(&second.to_ne_bytes());
+        self.challenge.set(challenge);
+        self.jitter.set(jitter);
+
+        match self.state.get() {
+            Some(ThreadState::SendParentReq) => {
+                // UNCOMMENT TO DEBUG THREAD //
+                // kernel::debug!("[Thread] Sending parent request...");
+                let include_reeds = self.attach_attempt.get() > PARENT_REQ_ROUTER_ATTEMPTS;
+                let parent_req_mle = form_parent_req(challenge, include_reeds);
+                self.send_mle(&parent_req_mle, MULTICAST_IPV6);
+            }
+            Some(ThreadState::SendUpdate(parent, _)) => {
+                let child_update_mle = form_child_update_req(challenge);
+                self.send_mle(&child_update_mle, parent);
+            }
+            _ => (),
+        }
+        rng::Continue::Done
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, H: digest::Digest<'a, 32> + digest::HmacSha256> digest::ClientData<32>
+    for ThreadNetworkDriver<'a, A, H>
+{
+    fn add_data_done(
+        &self,
+        _result: Result<(), ErrorCode>,
+        _data: kernel::utilities::leasable_buffer::SubSlice<'static, u8>,
+    ) {
+    }
+
+    fn add_mut_data_done(&self, result: Result<(), ErrorCode>, data: SubSliceMut<'static, u8>) {
+        self.hmac_data.replace(data.take());
+        if let Err(code) = result {
+            self.keys_derived(Err(code));
+            return;
+        }
+        if let Some(digest) = self.hmac_digest.take() {
+            if let Err((code, digest)) = self.hmac.run(digest) {
+                self.hmac_digest.replace(digest);
+                self.keys_derived(Err(code));
             }
-            _ => panic!(""),
         }
     }
 }
 
-impl<'a, A: time::Alarm<'a>> UDPRecvClient for ThreadNetworkDriver<'a, A> {
+impl<'a, A: time::Alarm<'a>, H: digest::Digest<'a, 32> + digest::HmacSha256> digest::ClientHash<32>
+    for ThreadNetworkDriver<'a, A, H>
+{
+    fn hash_done(&self, result: Result<(), ErrorCode>, digest: &'static mut [u8; 32]) {
+        let keys = result.map(|()| NetworkKey::from_hash(digest));
+        digest.fill(0);
+        self.hmac_digest.replace(digest);
+        self.keys_derived(keys);
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, H: digest::Digest<'a, 32> + digest::HmacSha256>
+    digest::ClientVerify<32> for ThreadNetworkDriver<'a, A, H>
+{
+    fn verification_done(&self, _result: Result<bool, ErrorCode>, _compare: &'static mut [u8; 32]) {
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, H: digest::Digest<'a, 32> + digest::HmacSha256> UDPRecvClient
+    for ThreadNetworkDriver<'a, A, H>
+{
     fn receive(
         &self,
         src_addr: IPAddr,
@@ -583,29 +1086,36 @@ impl<'a, A: time::Alarm<'a>> UDPRecvClient for ThreadNetworkDriver<'a, A> {
         _dst_port: u16,
         payload: &[u8],
     ) {
-        if payload[0] != SECURITY_SUITE_ENCRYP {
+        if payload.first() != Some(&SECURITY_SUITE_ENCRYP) {
             // Tock's current implementation of Thread ignores all messages that do not possess MLE encryption. This
             // is due to the Thread spec stating "Except for when specifically indicated, incoming
             // messages that are not secured with either MLE or link-layer security SHOULD be ignored." (v.1.3.0 sect 4.10)
             // UNCOMMENT TO DEBUG THREAD //
             // kernel::debug!("[Thread] DROPPED PACKET - Received unencrypted MLE packet.");
+            return;
         }
 
         // decode aux security header from packet into Security data type
         let sec_res = ieee802154::Security::decode(&payload[1..]).done();
 
-        // Guard statement for improperly formated aux sec header
-        if sec_res.is_none() {
-            // UNCOMMENT TO DEBUG THREAD //
-            // kernel::debug!("[Thread] DROPPED PACKET - Malformed auxiliary security header.");
-            return;
-        }
-
-        let security = sec_res.unwrap().1;
+        // Guard statement for improperly formated aux sec header. MLE messages
+        // identify their key by the key sequence (key id mode 2), which yields
+        // the fixed size aux sec header assumed below.
+        let (security, key_sequence) = match sec_res {
+            Some((_, security)) => match key_sequence_from_id(security.key_id) {
+                Some(key_sequence) => (security, key_sequence),
+                None => return,
+            },
+            None => {
+                // UNCOMMENT TO DEBUG THREAD //
+                // kernel::debug!("[Thread] DROPPED PACKET - Malformed auxiliary security header.");
+                return;
+            }
+        };
 
         // Take the receive buffer and pass to the `perform_crypto_op` wrapper function. This
         // initiates encoding all relevant auth data, setting crypto engine and initiating the
-        // crypto operation.
+        // crypto operation. The crypto engine checks the mic of the message.
         self.recv_buffer.take().map_or_else(
             || {
                 // UNCOMMENT TO DEBUG THREAD //
@@ -616,9 +1126,11 @@ impl<'a, A: time::Alarm<'a>> UDPRecvClient for ThreadNetworkDriver<'a, A> {
                     src_addr,
                     dst_addr,
                     security,
-                    &payload[SECURITY_SUITE_LEN + AUX_SEC_HEADER_LENGTH
-                        ..payload.len() - security.level.mic_len()],
+                    payload
+                        .get(SECURITY_SUITE_LEN + AUX_SEC_HEADER_LENGTH..)
+                        .unwrap_or(&[]),
                     recv_buf.take(),
+                    CryptOp::Decrypt(key_sequence),
                 )
                 .map_or_else(
                     // Error check on crypto operation. If the crypto operation
@@ -639,10 +1151,10 @@ impl<'a, A: time::Alarm<'a>> UDPRecvClient for ThreadNetworkDriver<'a, A> {
     }
 }
 
-impl<'a, A: time::Alarm<'a>> CCMClient for ThreadNetworkDriver<'a, A> {
-    fn crypt_done(&self, buf: &'static mut [u8], _res: Result<(), ErrorCode>, _tag_is_valid: bool) {
-        // TODO: check validity of result/tag and handle accordingly
-
+impl<'a, A: time::Alarm<'a>, H: digest::Digest<'a, 32> + digest::HmacSha256> CCMClient
+    for ThreadNetworkDriver<'a, A, H>
+{
+    fn crypt_done(&self, buf: &'static mut [u8], res: Result<(), ErrorCode>, tag_is_valid: bool) {
         // Obtain the length of the payload from the sizelock
         let buf_len = self.crypto_sizelock.take().unwrap();
 
@@ -677,66 +1189,62 @@ impl<'a, A: time::Alarm<'a>> CCMClient for ThreadNetworkDriver<'a, A> {
         // We create a new subslice that we will slice accordingly depending on if we are sending/receiving
         let mut assembled_subslice = SubSliceMut::new(buf);
 
-        // Panicking on unwrap indicates the state was taken without replacement
-        // (unreachable with proper state machine implementation)
-        let curr_state = self.state.take().unwrap();
-
-        match curr_state {
-            ThreadState::SendParentReq | ThreadState::SendChildIdReq(_) => {
-                //TODO: Add alarm for timeouts
+        match self.crypt_op.take() {
+            Some(CryptOp::Encrypt) => {
+                if res.is_err() {
+                    // The message is treated as lost and retried on timeout
+                    self.send_buffer.replace(assembled_subslice);
+                    self.await_response();
+                    return;
+                }
 
                 // To send, we need to send: security suite || aux sec header || mle payload || mic
                 // which correlates to the assembled_buf_len
                 assembled_subslice.slice(..assembled_buf_len);
 
-                let dest_ipv6 = match curr_state {
-                    // Determine destination IP depending on message type
-                    ThreadState::SendParentReq => MULTICAST_IPV6,
-                    ThreadState::SendChildIdReq(dst_ipv6) => dst_ipv6,
-                    _ => unreachable!(),
-                };
-
-                // we replace the state with the current state here
-                // because we cannot advance the state machine until
-                // after the `send_done` callback is received
-                self.state.replace(curr_state);
-
-                // Begin sending the transmission
+                // Begin sending the transmission; the state machine advances
+                // once the `send_done` callback is received
                 self.sender
                     .driver_send_to(
-                        dest_ipv6,
+                        IPAddr(dst_ipv6),
                         THREAD_PORT_NUMBER,
                         THREAD_PORT_NUMBER,
                         assembled_subslice,
                         self.driver_send_cap,
                         self.net_cap,
                     )
-                    .map_err(|buf| {
+                    .unwrap_or_else(|mut buf| {
                         // if the sending fails prior to transmission, replace
-                        // the buffer and pass error accordingly to terminate_child_join
-                        // in following unwrap statement
+                        // the buffer and wait for the response timeout
+                        buf.reset();
                         self.send_buffer.replace(buf);
-                        ErrorCode::FAIL
-                    })
-                    .unwrap_or_else(|code| self.terminate_child_join(Err(code)));
-            }
-            ThreadState::WaitingChildRsp => {
-                // TODO: Receive child response
+                        self.await_response();
+                    });
             }
-            ThreadState::WaitingParentRsp => {
+            Some(CryptOp::Decrypt(key_sequence)) => {
+                if res.is_err() || !tag_is_valid {
+                    // UNCOMMENT TO DEBUG THREAD //
+                    // kernel::debug!("[Thread] DROPPED PACKET - Invalid mic.");
+                    self.recv_buffer.replace(assembled_subslice);
+                    return;
+                }
+
+                // The message is authentic, so the network may have switched
+                // to the next key sequence.
+                self.switch_key_sequence(key_sequence);
+
                 // Upon receiving messages, the receive logic only requires the MLE payload. Subsequently,
                 // we slice the assembled_subslice to exclude the security suite, aux sec header, and mic.
                 assembled_subslice
                     .slice(AUX_SEC_HEADER_LENGTH + SECURITY_SUITE_LEN..assembled_buf_len - mic_len);
 
-                // Move the decrypted MLE message into the recv_buf and execute the receiving logic. Upon
-                // an error in `recv_logic`, joining the network fails and schedule termination upcall
+                // Move the decrypted MLE message into the recv_buf and execute the receiving logic.
                 self.recv_buffer.replace(assembled_subslice);
-                self.recv_logic(IPAddr(src_ipv6))
-                    .err()
-                    .map(|code| self.terminate_child_join(Err(code)));
+                self.recv_logic(IPAddr(src_ipv6));
             }
-            _ => (),
-        };
+            None => {
+                self.recv_buffer.replace(assembled_subslice);
+            }
+        }
     }
 }
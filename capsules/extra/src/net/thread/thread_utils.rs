@@ -2,8 +2,9 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 // Copyright Tock Contributors 2023.
 
+use crate::net::ieee802154::KeyId;
 use crate::net::stream::{encode_bytes, SResult};
-use crate::net::thread::tlv::{unwrap_tlv_offset, LinkMode, MulticastResponder, Tlv};
+use crate::net::thread::tlv::{unwrap_tlv_offset, LinkMode, MulticastResponder, Tlv, TlvType};
 use crate::net::{ieee802154::MacAddress, ipv6::ip_utils::IPAddr};
 pub const THREAD_PORT_NUMBER: u16 = 19788;
 
@@ -14,6 +15,17 @@ pub const AUX_SEC_HEADER_LENGTH: usize = 10;
 pub const AUTH_DATA_LEN: usize = 42;
 pub const IPV6_LEN: usize = 16;
 const PARENT_REQUEST_MLE_SIZE: usize = 21;
+const CHILD_UPDATE_REQUEST_MLE_SIZE: usize = 20;
+pub const CHALLENGE_LEN: usize = 8;
+
+/// Timeout requested from the parent in the Timeout TLV, in seconds. The
+/// parent removes the child if it has not heard from it for this long, so
+/// keep-alive messages are sent well before it expires.
+pub const CHILD_TIMEOUT_S: u32 = 240;
+
+/// String appended to the key sequence when deriving the MLE and MAC keys
+/// (Thread Spec v1.3.0 -- sect. 7.1.4).
+pub const KEY_DERIVATION_STR: &[u8; 6] = b"Thread";
 pub const MULTICAST_IPV6: IPAddr = IPAddr([
     0xff, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
 ]);
@@ -24,17 +36,34 @@ pub struct NetworkKey {
     pub mac_key: [u8; 16],
 }
 
+impl NetworkKey {
+    /// Splits the output of the key derivation function,
+    /// HMAC-SHA256(Network Key, Key Sequence || "Thread"), into the MLE key
+    /// (first 16 bytes) and MAC key (last 16 bytes).
+    pub fn from_hash(hash: &[u8; 32]) -> NetworkKey {
+        let mut mle_key = [0u8; 16];
+        let mut mac_key = [0u8; 16];
+        mle_key.copy_from_slice(&hash[..16]);
+        mac_key.copy_from_slice(&hash[16..]);
+        NetworkKey { mle_key, mac_key }
+    }
+}
+
+/// States of the attach process of a Thread child. The parent's address is
+/// kept in every state following the reception of a Parent Response.
+#[derive(Clone, Copy)]
 pub enum ThreadState {
+    /// Not attached; either waiting for the keys or backing off before the
+    /// next attach attempt.
+    Detached,
     SendParentReq,
     WaitingParentRsp,
-    RecvParentRsp(IPAddr),
     SendChildIdReq(IPAddr),
-    WaitingChildRsp,
-    RecvChildRsp(IPAddr),
+    WaitingChildRsp(IPAddr),
+    /// Attached to the parent; the keep-alive timer is running.
     SEDActive(IPAddr, MacAddress),
     SendUpdate(IPAddr, MacAddress),
-    SendUDPMsg,
-    Detached,
+    WaitingUpdateRsp(IPAddr, MacAddress),
 }
 
 pub enum MleCommand {
@@ -92,20 +121,62 @@ pub fn mac_from_ipv6(ipv6: IPAddr) -> [u8; 8] {
     output
 }
 
-/// Helper function to locate the challenge TLV in a received
-/// MLE packet. Return the challenge to be used as a response
-/// TLV in reply.
-fn find_challenge(buf: &[u8]) -> Result<&[u8], ErrorCode> {
+/// Returns the key identifier of MLE messages secured with the keys of
+/// `key_sequence` (Thread Spec v1.3.0 -- sect. 4.9).
+pub fn key_id_from_sequence(key_sequence: u32) -> KeyId {
+    KeyId::Source4Index(key_sequence.to_be_bytes(), key_index(key_sequence))
+}
+
+/// Returns the key index used in the auxiliary security header for
+/// `key_sequence`.
+pub fn key_index(key_sequence: u32) -> u8 {
+    ((key_sequence & 0x7f) + 1) as u8
+}
+
+/// Returns the key sequence of an MLE message from its key identifier.
+pub fn key_sequence_from_id(key_id: KeyId) -> Option<u32> {
+    match key_id {
+        KeyId::Source4Index(key_source, _) => Some(u32::from_be_bytes(key_source)),
+        _ => None,
+    }
+}
+
+/// Helper function to locate a TLV in the TLVs of a received MLE
+/// message (i.e. following the command byte). Returns the value of the
+/// first TLV of type `tlv_type`, or `None` if it is absent or the TLVs are
+/// malformed.
+pub fn find_tlv(buf: &[u8], tlv_type: TlvType) -> Option<&[u8]> {
+    let tlv_type = tlv_type as u8;
     let mut index = 0;
-    while index < buf.len() {
+    while index + 2 <= buf.len() {
         let tlv_len = buf[index + 1] as usize;
-        if buf[index] == 3 {
-            return Ok(&buf[index + 2..index + 2 + tlv_len]);
-        } else {
-            index += tlv_len + 2;
+        let value = buf.get(index + 2..index + 2 + tlv_len)?;
+        if buf[index] == tlv_type {
+            return Some(value);
         }
+        index += tlv_len + 2;
     }
-    Err(ErrorCode::FAIL)
+    None
+}
+
+/// Helper function to locate the challenge TLV in a received
+/// MLE packet. Return the challenge to be used as a response
+/// TLV in reply.
+pub fn find_challenge(buf: &[u8]) -> Result<[u8; CHALLENGE_LEN], ErrorCode> {
+    find_tlv(buf, TlvType::Challenge)
+        .and_then(|challenge| challenge.try_into().ok())
+        .ok_or(ErrorCode::FAIL)
+}
+
+/// Returns whether a received MLE message (following the command byte)
+/// carries a Response TLV matching `challenge`, the challenge as passed to
+/// `form_parent_req` or `form_child_update_req`.
+pub fn response_matches(buf: &[u8], challenge: &[u8; CHALLENGE_LEN]) -> bool {
+    // Challenges are serialized in reverse byte order (see `Tlv::encode`),
+    // and echoed byte for byte in the response.
+    find_tlv(buf, TlvType::Response).map_or(false, |response| {
+        response.len() == CHALLENGE_LEN && response.iter().eq(challenge.iter().rev())
+    })
 }
 
 /// Function to encode the crypt data into a/m data
@@ -125,11 +196,13 @@ pub fn encode_cryp_data(
     stream_done!(off)
 }
 
-/// This helper function creates a parent request. For now,
-/// this implementation hard codes all values for the parent request
-pub fn form_parent_req() -> [u8; PARENT_REQUEST_MLE_SIZE] {
-    // TODO: form parent request from alterable values, generate
-    // challenge from random number generator
+/// This helper function creates a parent request with the given random
+/// challenge. When `include_reeds` is set, REEDs are solicited in addition
+/// to routers (see the retry procedure below).
+pub fn form_parent_req(
+    challenge: [u8; CHALLENGE_LEN],
+    include_reeds: bool,
+) -> [u8; PARENT_REQUEST_MLE_SIZE] {
     let mut output = [0u8; PARENT_REQUEST_MLE_SIZE];
     let mut offset = 0;
 
@@ -149,15 +222,19 @@ pub fn form_parent_req() -> [u8; PARENT_REQUEST_MLE_SIZE] {
     ));
 
     // Challenge TLV //
-    // TODO: challenge is hardcoded currently; randomly generate number
     offset += unwrap_tlv_offset(Tlv::encode(
-        &Tlv::Challenge([0, 0, 0, 0, 0, 0, 0, 0]),
+        &Tlv::Challenge(challenge),
         &mut output[offset..],
     ));
 
     // Scan Mask TLV //
+    let scan_mask = if include_reeds {
+        MulticastResponder::Router as u8 + MulticastResponder::EndDevice as u8
+    } else {
+        MulticastResponder::Router as u8
+    };
     offset += unwrap_tlv_offset(Tlv::encode(
-        &Tlv::ScanMask(MulticastResponder::Router as u8),
+        &Tlv::ScanMask(scan_mask),
         &mut output[offset..],
     ));
 
@@ -167,12 +244,14 @@ pub fn form_parent_req() -> [u8; PARENT_REQUEST_MLE_SIZE] {
     output
 }
 
-/// This helper function creates a child id request. For now,
-/// this implementation hard codes many of the values
+/// This helper function creates a child id request answering the
+/// challenge of the parent's Parent Response, as returned by
+/// `find_challenge`. For now, this implementation hard codes many of the
+/// values
 pub fn form_child_id_req(
-    recv_buf: &[u8],
+    parent_challenge: [u8; CHALLENGE_LEN],
     frame_count: u32,
-) -> Result<([u8; 200], usize), ErrorCode> {
+) -> ([u8; 200], usize) {
     let mut output: [u8; 200] = [0; 200];
     let mut offset = 0;
 
@@ -194,18 +273,9 @@ pub fn form_child_id_req(
     offset += 1;
 
     // Response TLV //
-    let received_challenge_tlv: Result<&[u8], ErrorCode> = find_challenge(&recv_buf[1..]);
-
-    if received_challenge_tlv.is_err() {
-        // Challenge TLV not found; malformed request
-        return Err(ErrorCode::FAIL);
-    } else {
-        // Encode response into output
-        let mut rsp_buf: [u8; 8] = [0; 8];
-        rsp_buf.copy_from_slice(received_challenge_tlv.unwrap());
-        rsp_buf.reverse(); // NEED TO DISCUSS BIG/LITTLE ENDIAN ASSUMPTIONS
-        offset += unwrap_tlv_offset(Tlv::encode(&Tlv::Response(rsp_buf), &mut output[offset..]));
-    }
+    let mut rsp_buf = parent_challenge;
+    rsp_buf.reverse(); // NEED TO DISCUSS BIG/LITTLE ENDIAN ASSUMPTIONS
+    offset += unwrap_tlv_offset(Tlv::encode(&Tlv::Response(rsp_buf), &mut output[offset..]));
 
     // Link-layer Frame Counter TLV //
     offset += unwrap_tlv_offset(Tlv::encode(
@@ -227,7 +297,7 @@ pub fn form_child_id_req(
 
     // Timeout TLV //
     offset += unwrap_tlv_offset(Tlv::encode(
-        &Tlv::Timeout((10_u32).to_be()),
+        &Tlv::Timeout(CHILD_TIMEOUT_S.to_be()),
         &mut output[offset..],
     ));
 
@@ -243,11 +313,45 @@ pub fn form_child_id_req(
         &mut output[offset..],
     ));
 
-    Ok((output, offset))
+    (output, offset)
+}
+
+/// This helper function creates a child update request, which the child
+/// periodically sends to its parent to keep its link alive
+/// (Thread Spec v1.3.0 -- sect. 4.7.3).
+pub fn form_child_update_req(
+    challenge: [u8; CHALLENGE_LEN],
+) -> [u8; CHILD_UPDATE_REQUEST_MLE_SIZE] {
+    let mut output = [0u8; CHILD_UPDATE_REQUEST_MLE_SIZE];
+    let mut offset = 0;
+
+    // Command: Child Update Request //
+    output[0..1].copy_from_slice(&[MleCommand::ChildUpdateRequest as u8]);
+    offset += 1;
+
+    // Mode TLV //
+    offset += unwrap_tlv_offset(Tlv::encode(
+        &Tlv::Mode(LinkMode::FullThreadDevice as u8 + LinkMode::ReceiverOnWhenIdle as u8),
+        &mut output[offset..],
+    ));
+
+    // Challenge TLV //
+    offset += unwrap_tlv_offset(Tlv::encode(
+        &Tlv::Challenge(challenge),
+        &mut output[offset..],
+    ));
+
+    // Timeout TLV //
+    unwrap_tlv_offset(Tlv::encode(
+        &Tlv::Timeout(CHILD_TIMEOUT_S.to_be()),
+        &mut output[offset..],
+    ));
+
+    output
 }
 
 /*
-This is just here as a note for when retries are added
+Retries of the Parent Request, as implemented by the Thread driver
 ==================================================================================================
 THREAD SPEC v1.3.0 -- section 4.5.1
 A Thread Device attempting to attach MUST first attempt to attach with the Scan Mask TLV of
@@ -259,22 +363,13 @@ Routers and REEDs. There is no requirement on minimum link quality for
 responses to this request. If no responses are received, this request is deemed to have failed. If
 this Parent Request failed it MUST be retried up to three times.
 
-If the Thread Device is a REED and it still fails to successfully attach to a parent after all retries,
-it forms a new Partition as described in Section 5.16, Thread Network Partitions in Chapter 5,
-Network Layer.
-
 If the Thread Device is not a REED and it fails to successfully attach to a parent after all retries,
 then it SHOULD first wait for a vendor-specific timeout and then attempt to attach again using a
-Parent Request set to only solicit responses from Routers. If no responses are received, or if
-there is no response with the highest link quality, this request is deemed to have failed. If it
-failed, the Device MUST attempt to attach with the Scan Mask TLV of the Parent Request set to
-solicit responses from both Routers and REEDs. There is no requirement on minimum link quality
-for responses to this request. If this request still failed, the Thread Device again waits for a
-vendor-specific timeout and repeats the cycle defined in this paragraph.
+Parent Request set to only solicit responses from Routers. [...] If this request still failed,
+the Thread Device again waits for a vendor-specific timeout and repeats the cycle defined in this
+paragraph.
 
 SENDING/RETRYING PARENT REQUESTS THREAD SPEC v1.3.0 -- section 4.5.1
-**Attempt 1/2 considered to fail if no response received or no response with highest link quality
-**Attempt 3-6 considered to fail if no response received
     (Attempt 1) Send parent request with scan mask only set to routers
     (Attempt 2) Repeat attempt 1
     (Attempt 3) Send parent request with scan mask set to routers and REEDs
@@ -282,6 +377,7 @@ SENDING/RETRYING PARENT REQUESTS THREAD SPEC v1.3.0 -- section 4.5.1
     (Attempt 5) Repeat attempt 3
     (Attempt 6) Repeat attempt 3
 
-
-
+The first Parent Response carrying a valid Response TLV is accepted, so the
+link quality of the candidate parents is not compared. The vendor-specific
+timeout between cycles grows exponentially, with random jitter.
 */
@@ -47,8 +47,13 @@ mod upcall {
     /// currently pass information regarding whether packets were acked at the
     /// link layer.
     pub const PACKET_TRANSMITTED: usize = 1;
+    /// Callback for when an ICMPv6 destination unreachable or time exceeded
+    /// message is received for a packet sent from the bound port. The
+    /// callback receives the ICMPv6 type and code, and the destination port
+    /// of the packet that caused the error.
+    pub const SEND_ERROR: usize = 2;
     /// Number of upcalls.
-    pub const COUNT: u8 = 2;
+    pub const COUNT: u8 = 3;
 }
 
 /// Ids for read-only allow buffers
@@ -109,6 +114,13 @@ impl UDPEndpoint {
     pub fn is_zero(&self) -> bool {
         self.addr.is_unspecified() && self.port == 0
     }
+
+    /// This function checks if the UDPEndpoint accepts packets sent to
+    /// `addr`. An endpoint bound to the unspecified address accepts packets
+    /// for all local addresses, including autoconfigured ones.
+    pub fn accepts_addr(&self, addr: &IPAddr) -> bool {
+        self.addr.is_unspecified() || self.addr == *addr
+    }
 }
 
 #[derive(Default)]
@@ -365,6 +377,8 @@ impl<'a> SyscallDriver for UDPDriver<'a> {
     /// - `3`: Bind to the address in rx_cfg. Returns Ok(()) if that addr/port combo is free,
     ///        returns INVAL if the address requested is not a local interface, or if the port
     ///        requested is 0. Returns BUSY if that port is already bound to by another app.
+    ///        Binding to the address 0::0 with a nonzero port receives packets sent to that
+    ///        port on any local address.
     ///        This command should be called after allow() is called on the rx_cfg buffer, and
     ///        before subscribe() is used to set up the recv callback. Additionally, apps can only
     ///        send on ports after they have bound to said port. If this command is called
@@ -502,8 +516,9 @@ impl<'a> SyscallDriver for UDPDriver<'a> {
                                 app.bound_port = None;
                                 return Ok(None);
                             }
-                            // Check that requested addr is a local interface
-                            let mut requested_is_local = false;
+                            // Check that requested addr is a local interface, or
+                            // the unspecified address for all of them
+                            let mut requested_is_local = requested_addr.addr.is_unspecified();
                             for i in 0..self.interface_list.len() {
                                 if requested_addr.addr == self.interface_list[i] {
                                     requested_is_local = true;
@@ -586,7 +601,7 @@ impl<'a> UDPRecvClient for UDPDriver<'a> {
             if app.bound_port.is_some() {
                 let mut for_me = false;
                 app.bound_port.as_ref().map(|requested_addr| {
-                    if requested_addr.addr == dst_addr && requested_addr.port == dst_port {
+                    if requested_addr.accepts_addr(&dst_addr) && requested_addr.port == dst_port {
                         for_me = true;
                     }
                 });
@@ -636,6 +651,33 @@ impl<'a> UDPRecvClient for UDPDriver<'a> {
     }
 }
 
+impl<'a> UDPDriver<'a> {
+    /// Reports an ICMPv6 error message received for a packet sent from
+    /// `src_addr` and `src_port` to the app bound to that port.
+    pub fn report_send_error(
+        &self,
+        src_addr: IPAddr,
+        src_port: u16,
+        dst_port: u16,
+        icmp_type: u8,
+        icmp_code: u8,
+    ) {
+        self.apps.each(|_, app, kernel_data| {
+            let for_me = app.bound_port.map_or(false, |bound| {
+                bound.accepts_addr(&src_addr) && bound.port == src_port
+            });
+            if for_me {
+                kernel_data
+                    .schedule_upcall(
+                        upcall::SEND_ERROR,
+                        (icmp_type as usize, icmp_code as usize, dst_port as usize),
+                    )
+                    .ok();
+            }
+        });
+    }
+}
+
 impl<'a> PortQuery for UDPDriver<'a> {
     // Returns true if |port| is bound (on any iface), false otherwise.
     fn is_bound(&self, port: u16) -> bool {
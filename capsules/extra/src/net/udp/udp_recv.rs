@@ -9,7 +9,9 @@
 //! by the UDP userspace driver, which must correctly check bindings of kernel apps to ensure
 //! correctness when dispatching received packets to the appropriate client.
 
-use crate::net::ipv6::ip_utils::IPAddr;
+use crate::net::icmpv6::icmpv6_recv::ICMP6ErrorClient;
+use crate::net::icmpv6::ICMP6Header;
+use crate::net::ipv6::ip_utils::{ip6_nh, IPAddr};
 use crate::net::ipv6::ipv6_recv::IP6RecvClient;
 use crate::net::ipv6::IP6Header;
 use crate::net::udp::driver::UDPDriver;
@@ -44,6 +46,10 @@ impl<'a> MuxUdpReceiver<'a> {
 
 impl<'a> IP6RecvClient for MuxUdpReceiver<'a> {
     fn receive(&self, ip_header: IP6Header, payload: &[u8]) {
+        // Other transports may share the same IP receiver.
+        if ip_header.get_next_header() != ip6_nh::UDP {
+            return;
+        }
         match UDPHeader::decode(payload).done() {
             Some((offset, udp_header)) => {
                 let len = udp_header.get_len() as usize;
@@ -96,6 +102,32 @@ impl<'a> IP6RecvClient for MuxUdpReceiver<'a> {
     }
 }
 
+/// ICMPv6 errors caused by UDP packets are reported to the userspace driver
+/// if the packet was sent from a port bound by an app.
+impl<'a> ICMP6ErrorClient for MuxUdpReceiver<'a> {
+    fn receive_error(
+        &self,
+        icmp_header: ICMP6Header,
+        invoking_header: IP6Header,
+        invoking_payload: &[u8],
+    ) {
+        if invoking_header.get_next_header() != ip6_nh::UDP {
+            return;
+        }
+        if let Some((_, udp_header)) = UDPHeader::decode(invoking_payload).done() {
+            self.driver.map(|driver| {
+                driver.report_send_error(
+                    invoking_header.get_src_addr(),
+                    udp_header.get_src_port(),
+                    udp_header.get_dst_port(),
+                    icmp_header.get_type_as_int(),
+                    icmp_header.get_code(),
+                )
+            });
+        }
+    }
+}
+
 /// The UDP driver implements this client interface trait to receive
 /// packets passed up the network stack to the UDPReceiver, and then
 /// distributes them to userland applications from there.
@@ -797,7 +797,7 @@ impl<'a, S: spi::SpiMasterDevice<'a>> spi::SpiMasterClient for RF233<'a, S> {
                 if status == ExternalState::RX_AACK_ON as u8 {
                     let return_code = if (result & TRX_TRAC_MASK) == TRX_TRAC_CHANNEL_ACCESS_FAILURE
                     {
-                        Err(ErrorCode::FAIL)
+                        Err(ErrorCode::BUSY)
                     } else {
                         Ok(())
                     };
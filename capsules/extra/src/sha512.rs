@@ -0,0 +1,574 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Software implementation of SHA-384 and SHA-512.
+//!
+//! Both digests use the same 64-bit compression function and only differ by
+//! their initial hash values and the length of the output, so they share one
+//! implementation that is generic over the digest length `L`:
+//! `Sha512Software<'a, 48>` computes SHA-384 and `Sha512Software<'a, 64>`
+//! computes SHA-512.
+//!
+//! Like `Sha256Software`, data is hashed in the call that adds it, and the
+//! callbacks are issued from a deferred call.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let sha384 = static_init!(
+//!     capsules_extra::sha512::Sha384Software<'static>,
+//!     capsules_extra::sha512::Sha384Software::new()
+//! );
+//! kernel::deferred_call::DeferredCallClient::register(sha384);
+//! ```
+
+use core::cell::Cell;
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+
+use kernel::hil::digest::{Client, ClientData, ClientHash, ClientVerify};
+use kernel::hil::digest::{ClientDataHash, ClientDataVerify, DigestDataHash, DigestDataVerify};
+use kernel::hil::digest::{Digest, DigestData, DigestHash, DigestVerify};
+use kernel::hil::digest::{Sha256, Sha384, Sha512};
+use kernel::utilities::cells::{MapCell, OptionalCell};
+use kernel::utilities::leasable_buffer::SubSlice;
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::utilities::leasable_buffer::SubSliceMutImmut;
+use kernel::ErrorCode;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum State {
+    Idle,
+    Data,
+    Hash,
+    Verify,
+    CancelData,
+    CancelHash,
+    CancelVerify,
+}
+
+pub const SHA_384_OUTPUT_LEN_BYTES: usize = 48;
+pub const SHA_512_OUTPUT_LEN_BYTES: usize = 64;
+
+const SHA_BLOCK_LEN_BYTES: usize = 128;
+/// The message length is appended as a 128-bit number.
+const SHA_LENGTH_LEN_BYTES: usize = 16;
+const NUM_ROUND_CONSTANTS: usize = 80;
+
+const ROUND_CONSTANTS: [u64; NUM_ROUND_CONSTANTS] = [
+    0x428a2f98d728ae22,
+    0x7137449123ef65cd,
+    0xb5c0fbcfec4d3b2f,
+    0xe9b5dba58189dbbc,
+    0x3956c25bf348b538,
+    0x59f111f1b605d019,
+    0x923f82a4af194f9b,
+    0xab1c5ed5da6d8118,
+    0xd807aa98a3030242,
+    0x12835b0145706fbe,
+    0x243185be4ee4b28c,
+    0x550c7dc3d5ffb4e2,
+    0x72be5d74f27b896f,
+    0x80deb1fe3b1696b1,
+    0x9bdc06a725c71235,
+    0xc19bf174cf692694,
+    0xe49b69c19ef14ad2,
+    0xefbe4786384f25e3,
+    0x0fc19dc68b8cd5b5,
+    0x240ca1cc77ac9c65,
+    0x2de92c6f592b0275,
+    0x4a7484aa6ea6e483,
+    0x5cb0a9dcbd41fbd4,
+    0x76f988da831153b5,
+    0x983e5152ee66dfab,
+    0xa831c66d2db43210,
+    0xb00327c898fb213f,
+    0xbf597fc7beef0ee4,
+    0xc6e00bf33da88fc2,
+    0xd5a79147930aa725,
+    0x06ca6351e003826f,
+    0x142929670a0e6e70,
+    0x27b70a8546d22ffc,
+    0x2e1b21385c26c926,
+    0x4d2c6dfc5ac42aed,
+    0x53380d139d95b3df,
+    0x650a73548baf63de,
+    0x766a0abb3c77b2a8,
+    0x81c2c92e47edaee6,
+    0x92722c851482353b,
+    0xa2bfe8a14cf10364,
+    0xa81a664bbc423001,
+    0xc24b8b70d0f89791,
+    0xc76c51a30654be30,
+    0xd192e819d6ef5218,
+    0xd69906245565a910,
+    0xf40e35855771202a,
+    0x106aa07032bbd1b8,
+    0x19a4c116b8d2d0c8,
+    0x1e376c085141ab53,
+    0x2748774cdf8eeb99,
+    0x34b0bcb5e19b48a8,
+    0x391c0cb3c5c95a63,
+    0x4ed8aa4ae3418acb,
+    0x5b9cca4f7763e373,
+    0x682e6ff3d6b2b8a3,
+    0x748f82ee5defb2fc,
+    0x78a5636f43172f60,
+    0x84c87814a1f0ab72,
+    0x8cc702081a6439ec,
+    0x90befffa23631e28,
+    0xa4506cebde82bde9,
+    0xbef9a3f7b2c67915,
+    0xc67178f2e372532b,
+    0xca273eceea26619c,
+    0xd186b8c721c0c207,
+    0xeada7dd6cde0eb1e,
+    0xf57d4f7fee6ed178,
+    0x06f067aa72176fba,
+    0x0a637dc5a2c898a6,
+    0x113f9804bef90dae,
+    0x1b710b35131c471b,
+    0x28db77f523047d84,
+    0x32caab7b40c72493,
+    0x3c9ebe0a15c9bebc,
+    0x431d67c49c100d4c,
+    0x4cc5d4becb3e42b6,
+    0x597f299cfc657e2a,
+    0x5fcb6fab3ad6faec,
+    0x6c44198c4a475817,
+];
+
+const SHA_384_INITIAL_VALUES: [u64; 8] = [
+    0xcbbb9d5dc1059ed8,
+    0x629a292a367cd507,
+    0x9159015a3070dd17,
+    0x152fecd8f70e5939,
+    0x67332667ffc00b31,
+    0x8eb44a8768581511,
+    0xdb0c2e0d64f98fa7,
+    0x47b5481dbefa4fa4,
+];
+
+const SHA_512_INITIAL_VALUES: [u64; 8] = [
+    0x6a09e667f3bcc908,
+    0xbb67ae8584caa73b,
+    0x3c6ef372fe94f82b,
+    0xa54ff53a5f1d36f1,
+    0x510e527fade682d1,
+    0x9b05688c2b3e6c1f,
+    0x1f83d9abfb41bd6b,
+    0x5be0cd19137e2179,
+];
+
+/// SHA-384 software hash.
+pub type Sha384Software<'a> = Sha512Software<'a, SHA_384_OUTPUT_LEN_BYTES>;
+
+pub struct Sha512Software<'a, const L: usize> {
+    state: Cell<State>,
+
+    client: OptionalCell<&'a dyn Client<L>>,
+    input_data: OptionalCell<SubSliceMutImmut<'static, u8>>,
+    data_buffer: MapCell<[u8; SHA_BLOCK_LEN_BYTES]>,
+    buffered_length: Cell<usize>,
+    total_length: Cell<usize>,
+
+    // Used to store the hash or the hash to compare against with verify
+    output_data: Cell<Option<&'static mut [u8; L]>>,
+
+    hash_values: Cell<[u64; 8]>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a, const L: usize> Sha512Software<'a, L> {
+    /// The initial hash values of the digest, which also restricts `L` to the
+    /// supported digest lengths at compile time.
+    const INITIAL_VALUES: [u64; 8] = match L {
+        SHA_384_OUTPUT_LEN_BYTES => SHA_384_INITIAL_VALUES,
+        SHA_512_OUTPUT_LEN_BYTES => SHA_512_INITIAL_VALUES,
+        _ => panic!("Sha512Software only supports 48 or 64 byte digests"),
+    };
+
+    pub fn new() -> Self {
+        let s = Self {
+            state: Cell::new(State::Idle),
+            client: OptionalCell::empty(),
+            input_data: OptionalCell::empty(),
+            data_buffer: MapCell::new([0; SHA_BLOCK_LEN_BYTES]),
+            buffered_length: Cell::new(0),
+            total_length: Cell::new(0),
+
+            output_data: Cell::new(None),
+            hash_values: Cell::new(Self::INITIAL_VALUES),
+
+            deferred_call: DeferredCall::new(),
+        };
+        s.initialize();
+        s
+    }
+
+    pub fn busy(&self) -> bool {
+        self.state.get() != State::Idle
+    }
+
+    fn initialize(&self) {
+        let new_state = match self.state.get() {
+            State::Idle => State::Idle,
+            State::Data | State::CancelData => State::CancelData,
+            State::Hash | State::CancelHash => State::CancelHash,
+            State::Verify | State::CancelVerify => State::CancelVerify,
+        };
+        self.state.set(new_state);
+
+        self.buffered_length.set(0);
+        self.total_length.set(0);
+        self.data_buffer.map(|b| b.fill(0));
+        self.hash_values.set(Self::INITIAL_VALUES);
+    }
+
+    /// Pads the buffered data and computes the final block(s).
+    fn complete_sha512(&self) {
+        let buffered_length = self.buffered_length.get();
+        let total_length = self.total_length.get();
+        self.data_buffer.map(|b| {
+            // The buffer is never full, as full blocks are computed when
+            // data is added, so there is always room for the 1 bit.
+            b[buffered_length] = 0x80;
+            b[buffered_length + 1..].fill(0);
+
+            // If the length does not fit in this block, it goes in an
+            // additional one.
+            if buffered_length + 1 > SHA_BLOCK_LEN_BYTES - SHA_LENGTH_LEN_BYTES {
+                self.compute_block(b);
+                b.fill(0);
+            }
+
+            let length_bits = (total_length as u128) * 8;
+            b[SHA_BLOCK_LEN_BYTES - SHA_LENGTH_LEN_BYTES..]
+                .copy_from_slice(&length_bits.to_be_bytes());
+            self.compute_block(b);
+        });
+    }
+
+    // This method computes SHA-512 on data in input_data, updating the
+    // internal hash state. `data_buffer` contains input data that did not fill
+    // a block: the implementation first fills it and computes on it, then
+    // operates on input_data. If the end of input_data does not complete a
+    // block then the remainder is stored in data_buffer.
+    fn compute_sha512(&self) {
+        if let Some(mut data) = self.input_data.take() {
+            self.total_length.set(self.total_length.get() + data.len());
+            let mut buffered_length = self.buffered_length.get();
+            if buffered_length != 0 {
+                self.data_buffer.map(|b| {
+                    let copy_len =
+                        core::cmp::min(data.len(), SHA_BLOCK_LEN_BYTES - buffered_length);
+                    b[buffered_length..buffered_length + copy_len]
+                        .copy_from_slice(&data[..copy_len]);
+                    data.slice(copy_len..data.len());
+                    buffered_length += copy_len;
+
+                    if buffered_length == SHA_BLOCK_LEN_BYTES {
+                        self.compute_block(b);
+                        buffered_length = 0;
+                    }
+                });
+            }
+            // Process blocks
+            while data.len() >= SHA_BLOCK_LEN_BYTES {
+                self.compute_buffer(&data[..SHA_BLOCK_LEN_BYTES]);
+                data.slice(SHA_BLOCK_LEN_BYTES..data.len());
+            }
+            // Process tail end of block
+            if data.len() != 0 {
+                self.data_buffer.map(|b| {
+                    b[..data.len()].copy_from_slice(&data[..]);
+                    buffered_length = data.len();
+                    // Go to end of data.
+                    data.slice(data.len()..data.len());
+                });
+            }
+            self.input_data.set(data);
+            self.buffered_length.set(buffered_length);
+        }
+    }
+
+    // Note: slice MUST be >= 128 bytes long
+    fn compute_buffer(&self, buffer: &[u8]) {
+        let mut message_schedule: [u64; NUM_ROUND_CONSTANTS] = [0; NUM_ROUND_CONSTANTS];
+        for (word, bytes) in message_schedule.iter_mut().zip(buffer.chunks_exact(8)) {
+            let mut val = [0; 8];
+            val.copy_from_slice(bytes);
+            *word = u64::from_be_bytes(val);
+        }
+        self.perform_sha(&mut message_schedule);
+    }
+
+    fn compute_block(&self, data: &[u8; SHA_BLOCK_LEN_BYTES]) {
+        self.compute_buffer(data);
+    }
+
+    fn perform_sha(&self, message_schedule: &mut [u64; NUM_ROUND_CONSTANTS]) {
+        // Message schedule
+        for i in 16..NUM_ROUND_CONSTANTS {
+            let w15 = message_schedule[i - 15];
+            let w2 = message_schedule[i - 2];
+            let s0 = w15.rotate_right(1) ^ w15.rotate_right(8) ^ (w15 >> 7);
+            let s1 = w2.rotate_right(19) ^ w2.rotate_right(61) ^ (w2 >> 6);
+            message_schedule[i] = message_schedule[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(message_schedule[i - 7])
+                .wrapping_add(s1);
+        }
+
+        // Compression
+        let mut hashes = self.hash_values.get();
+        for i in 0..NUM_ROUND_CONSTANTS {
+            let s1 = hashes[4].rotate_right(14)
+                ^ hashes[4].rotate_right(18)
+                ^ hashes[4].rotate_right(41);
+            let ch = (hashes[4] & hashes[5]) ^ ((!hashes[4]) & hashes[6]);
+            let temp1 = hashes[7]
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(ROUND_CONSTANTS[i])
+                .wrapping_add(message_schedule[i]);
+            let s0 = hashes[0].rotate_right(28)
+                ^ hashes[0].rotate_right(34)
+                ^ hashes[0].rotate_right(39);
+            let maj = (hashes[0] & hashes[1]) ^ (hashes[0] & hashes[2]) ^ (hashes[1] & hashes[2]);
+            let temp2 = s0.wrapping_add(maj);
+
+            hashes[7] = hashes[6];
+            hashes[6] = hashes[5];
+            hashes[5] = hashes[4];
+            hashes[4] = hashes[3].wrapping_add(temp1);
+            hashes[3] = hashes[2];
+            hashes[2] = hashes[1];
+            hashes[1] = hashes[0];
+            hashes[0] = temp1.wrapping_add(temp2);
+        }
+
+        let mut new_hashes = self.hash_values.get();
+        for i in 0..8 {
+            new_hashes[i] = new_hashes[i].wrapping_add(hashes[i]);
+        }
+        self.hash_values.set(new_hashes);
+    }
+
+    /// Returns the first `L` bytes of the big-endian hash values.
+    fn digest(&self) -> [u8; L] {
+        let mut digest = [0; L];
+        for (bytes, val) in digest.chunks_mut(8).zip(self.hash_values.get().iter()) {
+            bytes.copy_from_slice(&val.to_be_bytes()[..bytes.len()]);
+        }
+        digest
+    }
+}
+
+impl<'a, const L: usize> DigestData<'a, L> for Sha512Software<'a, L> {
+    fn add_data(
+        &self,
+        data: SubSlice<'static, u8>,
+    ) -> Result<(), (ErrorCode, SubSlice<'static, u8>)> {
+        if self.busy() {
+            Err((ErrorCode::BUSY, data))
+        } else {
+            self.state.set(State::Data);
+            self.deferred_call.set();
+            self.input_data.set(SubSliceMutImmut::Immutable(data));
+            self.compute_sha512();
+            Ok(())
+        }
+    }
+
+    fn add_mut_data(
+        &self,
+        data: SubSliceMut<'static, u8>,
+    ) -> Result<(), (ErrorCode, SubSliceMut<'static, u8>)> {
+        if self.busy() {
+            Err((ErrorCode::BUSY, data))
+        } else {
+            self.state.set(State::Data);
+            self.deferred_call.set();
+            self.input_data.set(SubSliceMutImmut::Mutable(data));
+            self.compute_sha512();
+            Ok(())
+        }
+    }
+
+    fn clear_data(&self) {
+        self.initialize();
+    }
+
+    fn set_data_client(&'a self, _client: &'a (dyn ClientData<L> + 'a)) {
+        unimplemented!()
+    }
+}
+
+impl<'a, const L: usize> DigestHash<'a, L> for Sha512Software<'a, L> {
+    fn run(
+        &'a self,
+        digest: &'static mut [u8; L],
+    ) -> Result<(), (ErrorCode, &'static mut [u8; L])> {
+        if self.busy() {
+            Err((ErrorCode::BUSY, digest))
+        } else {
+            self.state.set(State::Hash);
+            self.complete_sha512();
+            *digest = self.digest();
+            self.output_data.set(Some(digest));
+            self.deferred_call.set();
+            Ok(())
+        }
+    }
+
+    fn set_hash_client(&'a self, _client: &'a (dyn ClientHash<L> + 'a)) {
+        unimplemented!()
+    }
+}
+
+impl<'a, const L: usize> DigestVerify<'a, L> for Sha512Software<'a, L> {
+    fn verify(
+        &'a self,
+        compare: &'static mut [u8; L],
+    ) -> Result<(), (ErrorCode, &'static mut [u8; L])> {
+        if self.busy() {
+            Err((ErrorCode::BUSY, compare))
+        } else {
+            self.state.set(State::Verify);
+            self.complete_sha512();
+            self.output_data.set(Some(compare));
+            self.deferred_call.set();
+            Ok(())
+        }
+    }
+
+    fn set_verify_client(&'a self, _client: &'a (dyn ClientVerify<L> + 'a)) {
+        unimplemented!()
+    }
+}
+
+impl<'a, const L: usize> Digest<'a, L> for Sha512Software<'a, L> {
+    fn set_client(&'a self, client: &'a dyn Client<L>) {
+        self.client.set(client);
+    }
+}
+
+impl<'a, const L: usize> DeferredCallClient for Sha512Software<'a, L> {
+    fn handle_deferred_call(&self) {
+        let prior = self.state.get();
+        self.state.set(State::Idle);
+        match prior {
+            State::Idle => {}
+            State::Verify => {
+                // Do the verification here so we don't have to store
+                // the result across the callback.
+                let output = self.output_data.replace(None).unwrap();
+                let pass = *output == self.digest();
+                self.clear_data();
+                self.client.map(|c| {
+                    c.verification_done(Ok(pass), output);
+                });
+            }
+            State::Data => {
+                // Data already computed in method call
+                let data = self.input_data.take().unwrap();
+                match data {
+                    SubSliceMutImmut::Mutable(buffer) => {
+                        self.client.map(|client| {
+                            client.add_mut_data_done(Ok(()), buffer);
+                        });
+                    }
+                    SubSliceMutImmut::Immutable(buffer) => {
+                        self.client.map(|client| {
+                            client.add_data_done(Ok(()), buffer);
+                        });
+                    }
+                }
+            }
+            State::Hash => {
+                // Hash already copied in method call.
+                let output = self.output_data.replace(None).unwrap();
+                self.clear_data();
+                self.client.map(|c| {
+                    c.hash_done(Ok(()), output);
+                });
+            }
+            State::CancelData => {
+                self.clear_data();
+                let data = self.input_data.take().unwrap();
+                match data {
+                    SubSliceMutImmut::Mutable(buffer) => {
+                        self.client.map(|client| {
+                            client.add_mut_data_done(Err(ErrorCode::CANCEL), buffer);
+                        });
+                    }
+                    SubSliceMutImmut::Immutable(buffer) => {
+                        self.client.map(|client| {
+                            client.add_data_done(Err(ErrorCode::CANCEL), buffer);
+                        });
+                    }
+                }
+            }
+            State::CancelVerify => {
+                self.clear_data();
+                let output = self.output_data.replace(None).unwrap();
+                self.client.map(|client| {
+                    client.verification_done(Err(ErrorCode::CANCEL), output);
+                });
+            }
+            State::CancelHash => {
+                self.clear_data();
+                let output = self.output_data.replace(None).unwrap();
+                self.client.map(|client| {
+                    client.hash_done(Err(ErrorCode::CANCEL), output);
+                });
+            }
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+impl<const L: usize> Sha256 for Sha512Software<'_, L> {
+    fn set_mode_sha256(&self) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+}
+
+impl<const L: usize> Sha384 for Sha512Software<'_, L> {
+    /// Call before adding data to perform Sha384
+    fn set_mode_sha384(&self) -> Result<(), ErrorCode> {
+        if L == SHA_384_OUTPUT_LEN_BYTES {
+            Ok(())
+        } else {
+            Err(ErrorCode::NOSUPPORT)
+        }
+    }
+}
+
+impl<const L: usize> Sha512 for Sha512Software<'_, L> {
+    /// Call before adding data to perform Sha512
+    fn set_mode_sha512(&self) -> Result<(), ErrorCode> {
+        if L == SHA_512_OUTPUT_LEN_BYTES {
+            Ok(())
+        } else {
+            Err(ErrorCode::NOSUPPORT)
+        }
+    }
+}
+
+impl<'a, const L: usize> DigestDataHash<'a, L> for Sha512Software<'a, L> {
+    fn set_client(&'a self, _client: &'a dyn ClientDataHash<L>) {
+        unimplemented!()
+    }
+}
+
+impl<'a, const L: usize> DigestDataVerify<'a, L> for Sha512Software<'a, L> {
+    fn set_client(&'a self, _client: &'a dyn ClientDataVerify<L>) {
+        unimplemented!()
+    }
+}
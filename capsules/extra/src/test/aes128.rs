@@ -0,0 +1,331 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Test an AES-128 implementation with known-answer examples.
+//!
+//! `TestAes128Fips197` uses the examples of FIPS-197: each example is
+//! encrypted and the ciphertext decrypted again, in place, using ECB mode on a
+//! single block.
+//!
+//! `TestAes128Sp80038a` uses the CBC (F.2.1, F.2.2) and CTR (F.5.1, F.5.2)
+//! examples of NIST SP 800-38A. The four blocks of each example are encrypted
+//! and decrypted, in place, in a single call to `crypt`, so the chaining of
+//! CBC and the counter increment of CTR are exercised across blocks.
+
+use capsules_core::test::capsule_test::{CapsuleTest, CapsuleTestClient, CapsuleTestError};
+use core::cell::Cell;
+use kernel::hil;
+use kernel::hil::symmetric_encryption::{
+    AES128Ctr, AES128, AES128CBC, AES128ECB, AES128_BLOCK_SIZE, AES128_KEY_SIZE,
+};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+
+struct Example {
+    key: [u8; AES128_KEY_SIZE],
+    plaintext: [u8; AES128_BLOCK_SIZE],
+    ciphertext: [u8; AES128_BLOCK_SIZE],
+}
+
+const EXAMPLES: [Example; 2] = [
+    // Appendix B, cipher example.
+    Example {
+        key: [
+            0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf,
+            0x4f, 0x3c,
+        ],
+        plaintext: [
+            0x32, 0x43, 0xf6, 0xa8, 0x88, 0x5a, 0x30, 0x8d, 0x31, 0x31, 0x98, 0xa2, 0xe0, 0x37,
+            0x07, 0x34,
+        ],
+        ciphertext: [
+            0x39, 0x25, 0x84, 0x1d, 0x02, 0xdc, 0x09, 0xfb, 0xdc, 0x11, 0x85, 0x97, 0x19, 0x6a,
+            0x0b, 0x32,
+        ],
+    },
+    // Appendix C.1, AES-128 example vector.
+    Example {
+        key: [
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d,
+            0x0e, 0x0f,
+        ],
+        plaintext: [
+            0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd,
+            0xee, 0xff,
+        ],
+        ciphertext: [
+            0x69, 0xc4, 0xe0, 0xd8, 0x6a, 0x7b, 0x04, 0x30, 0xd8, 0xcd, 0xb7, 0x80, 0x70, 0xb4,
+            0xc5, 0x5a,
+        ],
+    },
+];
+
+pub struct TestAes128Fips197<'a, A: 'a> {
+    aes: &'a A,
+    data: TakeCell<'static, [u8]>, // A buffer of at least one block
+    example: Cell<usize>,          // The index of the current example
+    encrypting: Cell<bool>,
+    client: OptionalCell<&'static dyn CapsuleTestClient>,
+}
+
+impl<'a, A: AES128<'a> + AES128ECB> TestAes128Fips197<'a, A> {
+    pub fn new(aes: &'a A, data: &'static mut [u8]) -> Self {
+        TestAes128Fips197 {
+            aes,
+            data: TakeCell::new(data),
+            example: Cell::new(0),
+            encrypting: Cell::new(true),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn run(&self) {
+        let example = &EXAMPLES[self.example.get()];
+        let encrypting = self.encrypting.get();
+
+        self.aes.enable();
+        self.aes.set_mode_aes128ecb(encrypting).unwrap();
+        self.aes.set_key(&example.key).unwrap();
+
+        let data = self.data.take().unwrap();
+        data[..AES128_BLOCK_SIZE].copy_from_slice(if encrypting {
+            &example.plaintext
+        } else {
+            &example.ciphertext
+        });
+
+        self.aes.start_message();
+        if let Some((result, _, data)) = self.aes.crypt(None, data, 0, AES128_BLOCK_SIZE) {
+            self.data.replace(data);
+            panic!("Aes128Fips197Test: crypt() failed: {:?}", result);
+        }
+    }
+}
+
+impl<'a, A: AES128<'a> + AES128ECB> hil::symmetric_encryption::Client<'a>
+    for TestAes128Fips197<'a, A>
+{
+    fn crypt_done(&'a self, _source: Option<&'static mut [u8]>, dest: &'static mut [u8]) {
+        self.aes.disable();
+
+        let example = &EXAMPLES[self.example.get()];
+        let expected = if self.encrypting.get() {
+            &example.ciphertext
+        } else {
+            &example.plaintext
+        };
+        let correct = dest[..AES128_BLOCK_SIZE] == *expected;
+        self.data.replace(dest);
+
+        if !correct {
+            kernel::debug!(
+                "Aes128Fips197Test: incorrect {} of example {}",
+                if self.encrypting.get() {
+                    "encryption"
+                } else {
+                    "decryption"
+                },
+                self.example.get()
+            );
+            self.client.map(|client| {
+                client.done(Err(CapsuleTestError::IncorrectResult));
+            });
+            return;
+        }
+
+        if self.encrypting.get() {
+            self.encrypting.set(false);
+            self.run();
+        } else if self.example.get() + 1 < EXAMPLES.len() {
+            self.example.set(self.example.get() + 1);
+            self.encrypting.set(true);
+            self.run();
+        } else {
+            kernel::debug!("Aes128Fips197Test: all examples passed");
+            self.client.map(|client| {
+                client.done(Ok(()));
+            });
+        }
+    }
+}
+
+impl<'a, A: AES128<'a> + AES128ECB> CapsuleTest for TestAes128Fips197<'a, A> {
+    fn set_client(&self, client: &'static dyn CapsuleTestClient) {
+        self.client.set(client);
+    }
+}
+
+/// Length of the SP 800-38A examples.
+pub const SP800_38A_LEN: usize = 4 * AES128_BLOCK_SIZE;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Sp80038aMode {
+    Cbc,
+    Ctr,
+}
+
+struct Sp80038aExample {
+    mode: Sp80038aMode,
+    iv: [u8; AES128_BLOCK_SIZE],
+    ciphertext: [u8; SP800_38A_LEN],
+}
+
+// All examples use the same key and plaintext.
+const SP800_38A_KEY: [u8; AES128_KEY_SIZE] = [
+    0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f, 0x3c,
+];
+
+#[rustfmt::skip]
+const SP800_38A_PLAINTEXT: [u8; SP800_38A_LEN] = [
+    0x6b, 0xc1, 0xbe, 0xe2, 0x2e, 0x40, 0x9f, 0x96,
+    0xe9, 0x3d, 0x7e, 0x11, 0x73, 0x93, 0x17, 0x2a,
+    0xae, 0x2d, 0x8a, 0x57, 0x1e, 0x03, 0xac, 0x9c,
+    0x9e, 0xb7, 0x6f, 0xac, 0x45, 0xaf, 0x8e, 0x51,
+    0x30, 0xc8, 0x1c, 0x46, 0xa3, 0x5c, 0xe4, 0x11,
+    0xe5, 0xfb, 0xc1, 0x19, 0x1a, 0x0a, 0x52, 0xef,
+    0xf6, 0x9f, 0x24, 0x45, 0xdf, 0x4f, 0x9b, 0x17,
+    0xad, 0x2b, 0x41, 0x7b, 0xe6, 0x6c, 0x37, 0x10,
+];
+
+#[rustfmt::skip]
+const SP800_38A_EXAMPLES: [Sp80038aExample; 2] = [
+    // F.2.1 and F.2.2, CBC-AES128.
+    Sp80038aExample {
+        mode: Sp80038aMode::Cbc,
+        iv: [
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07,
+            0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+        ],
+        ciphertext: [
+            0x76, 0x49, 0xab, 0xac, 0x81, 0x19, 0xb2, 0x46,
+            0xce, 0xe9, 0x8e, 0x9b, 0x12, 0xe9, 0x19, 0x7d,
+            0x50, 0x86, 0xcb, 0x9b, 0x50, 0x72, 0x19, 0xee,
+            0x95, 0xdb, 0x11, 0x3a, 0x91, 0x76, 0x78, 0xb2,
+            0x73, 0xbe, 0xd6, 0xb8, 0xe3, 0xc1, 0x74, 0x3b,
+            0x71, 0x16, 0xe6, 0x9e, 0x22, 0x22, 0x95, 0x16,
+            0x3f, 0xf1, 0xca, 0xa1, 0x68, 0x1f, 0xac, 0x09,
+            0x12, 0x0e, 0xca, 0x30, 0x75, 0x86, 0xe1, 0xa7,
+        ],
+    },
+    // F.5.1 and F.5.2, CTR-AES128. The initial counter block is the IV.
+    Sp80038aExample {
+        mode: Sp80038aMode::Ctr,
+        iv: [
+            0xf0, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7,
+            0xf8, 0xf9, 0xfa, 0xfb, 0xfc, 0xfd, 0xfe, 0xff,
+        ],
+        ciphertext: [
+            0x87, 0x4d, 0x61, 0x91, 0xb6, 0x20, 0xe3, 0x26,
+            0x1b, 0xef, 0x68, 0x64, 0x99, 0x0d, 0xb6, 0xce,
+            0x98, 0x06, 0xf6, 0x6b, 0x79, 0x70, 0xfd, 0xff,
+            0x86, 0x17, 0x18, 0x7b, 0xb9, 0xff, 0xfd, 0xff,
+            0x5a, 0xe4, 0xdf, 0x3e, 0xdb, 0xd5, 0xd3, 0x5e,
+            0x5b, 0x4f, 0x09, 0x02, 0x0d, 0xb0, 0x3e, 0xab,
+            0x1e, 0x03, 0x1d, 0xda, 0x2f, 0xbe, 0x03, 0xd1,
+            0x79, 0x21, 0x70, 0xa0, 0xf3, 0x00, 0x9c, 0xee,
+        ],
+    },
+];
+
+pub struct TestAes128Sp80038a<'a, A: 'a> {
+    aes: &'a A,
+    data: TakeCell<'static, [u8]>, // A buffer of at least `SP800_38A_LEN` bytes
+    example: Cell<usize>,          // The index of the current example
+    encrypting: Cell<bool>,
+    client: OptionalCell<&'static dyn CapsuleTestClient>,
+}
+
+impl<'a, A: AES128<'a> + AES128CBC + AES128Ctr> TestAes128Sp80038a<'a, A> {
+    pub fn new(aes: &'a A, data: &'static mut [u8]) -> Self {
+        TestAes128Sp80038a {
+            aes,
+            data: TakeCell::new(data),
+            example: Cell::new(0),
+            encrypting: Cell::new(true),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn run(&self) {
+        let example = &SP800_38A_EXAMPLES[self.example.get()];
+        let encrypting = self.encrypting.get();
+
+        self.aes.enable();
+        match example.mode {
+            Sp80038aMode::Cbc => self.aes.set_mode_aes128cbc(encrypting).unwrap(),
+            Sp80038aMode::Ctr => self.aes.set_mode_aes128ctr(encrypting).unwrap(),
+        }
+        self.aes.set_key(&SP800_38A_KEY).unwrap();
+        self.aes.set_iv(&example.iv).unwrap();
+
+        let data = self.data.take().unwrap();
+        data[..SP800_38A_LEN].copy_from_slice(if encrypting {
+            &SP800_38A_PLAINTEXT
+        } else {
+            &example.ciphertext
+        });
+
+        self.aes.start_message();
+        if let Some((result, _, data)) = self.aes.crypt(None, data, 0, SP800_38A_LEN) {
+            self.data.replace(data);
+            panic!("Aes128Sp80038aTest: crypt() failed: {:?}", result);
+        }
+    }
+}
+
+impl<'a, A: AES128<'a> + AES128CBC + AES128Ctr> hil::symmetric_encryption::Client<'a>
+    for TestAes128Sp80038a<'a, A>
+{
+    fn crypt_done(&'a self, _source: Option<&'static mut [u8]>, dest: &'static mut [u8]) {
+        self.aes.disable();
+
+        let example = &SP800_38A_EXAMPLES[self.example.get()];
+        let expected = if self.encrypting.get() {
+            &example.ciphertext
+        } else {
+            &SP800_38A_PLAINTEXT
+        };
+        let correct = dest[..SP800_38A_LEN] == *expected;
+        self.data.replace(dest);
+
+        if !correct {
+            kernel::debug!(
+                "Aes128Sp80038aTest: incorrect {} {}",
+                if example.mode == Sp80038aMode::Cbc {
+                    "CBC"
+                } else {
+                    "CTR"
+                },
+                if self.encrypting.get() {
+                    "encryption"
+                } else {
+                    "decryption"
+                }
+            );
+            self.client.map(|client| {
+                client.done(Err(CapsuleTestError::IncorrectResult));
+            });
+            return;
+        }
+
+        if self.encrypting.get() {
+            self.encrypting.set(false);
+            self.run();
+        } else if self.example.get() + 1 < SP800_38A_EXAMPLES.len() {
+            self.example.set(self.example.get() + 1);
+            self.encrypting.set(true);
+            self.run();
+        } else {
+            kernel::debug!("Aes128Sp80038aTest: all examples passed");
+            self.client.map(|client| {
+                client.done(Ok(()));
+            });
+        }
+    }
+}
+
+impl<'a, A: AES128<'a> + AES128CBC + AES128Ctr> CapsuleTest for TestAes128Sp80038a<'a, A> {
+    fn set_client(&self, client: &'static dyn CapsuleTestClient) {
+        self.client.set(client);
+    }
+}
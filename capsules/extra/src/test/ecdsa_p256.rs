@@ -0,0 +1,87 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Test the software implementation of ECDSA P-256 signature verification by
+//! verifying a signature and checking the result against the expected one.
+
+use crate::ecdsa_p256::EcdsaP256SignatureVerifier;
+use capsules_core::test::capsule_test::{CapsuleTest, CapsuleTestClient, CapsuleTestError};
+use kernel::hil::public_key_crypto::signature;
+use kernel::hil::public_key_crypto::signature::SignatureVerify;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+pub struct TestEcdsaP256Verify {
+    verifier: &'static EcdsaP256SignatureVerifier<'static>,
+    hash: TakeCell<'static, [u8; 32]>, // The hash that was signed
+    signature: TakeCell<'static, [u8; 64]>, // The signature to verify
+    correct: bool,                     // Whether the signature is valid
+    client: OptionalCell<&'static dyn CapsuleTestClient>,
+}
+
+impl TestEcdsaP256Verify {
+    pub fn new(
+        verifier: &'static EcdsaP256SignatureVerifier<'static>,
+        hash: &'static mut [u8; 32],
+        signature: &'static mut [u8; 64],
+        correct: bool,
+    ) -> Self {
+        TestEcdsaP256Verify {
+            verifier,
+            hash: TakeCell::new(hash),
+            signature: TakeCell::new(signature),
+            correct,
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn run(&'static self) {
+        self.verifier.set_verify_client(self);
+
+        let hash = self.hash.take().unwrap();
+        let signature = self.signature.take().unwrap();
+        if let Err((e, hash, signature)) = self.verifier.verify(hash, signature) {
+            self.hash.replace(hash);
+            self.signature.replace(signature);
+            panic!("EcdsaP256Test: failed to verify: {:?}", e);
+        }
+    }
+}
+
+impl signature::ClientVerify<32, 64> for TestEcdsaP256Verify {
+    fn verification_done(
+        &self,
+        result: Result<bool, ErrorCode>,
+        hash: &'static mut [u8; 32],
+        signature: &'static mut [u8; 64],
+    ) {
+        self.hash.replace(hash);
+        self.signature.replace(signature);
+
+        kernel::debug!("EcdsaP256Test: Verification result: {:?}", result);
+        match result {
+            Ok(valid) if valid == self.correct => {
+                self.client.map(|client| {
+                    client.done(Ok(()));
+                });
+            }
+            Ok(_) => {
+                self.client.map(|client| {
+                    client.done(Err(CapsuleTestError::IncorrectResult));
+                });
+            }
+            Err(e) => {
+                self.client.map(|client| {
+                    client.done(Err(CapsuleTestError::ErrorCode(e)));
+                });
+            }
+        }
+    }
+}
+
+impl CapsuleTest for TestEcdsaP256Verify {
+    fn set_client(&self, client: &'static dyn CapsuleTestClient) {
+        self.client.set(client);
+    }
+}
@@ -0,0 +1,128 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Test the software implementation of HMAC-SHA384 and HMAC-SHA512 by
+//! performing an HMAC and checking it against the expected value.
+
+use crate::hmac_sha512::HmacSha512Software;
+use crate::sha512::{Sha512Software, SHA_384_OUTPUT_LEN_BYTES};
+use capsules_core::test::capsule_test::{CapsuleTest, CapsuleTestClient, CapsuleTestError};
+use kernel::hil::digest;
+use kernel::hil::digest::{DigestData, DigestHash, HmacSha384, HmacSha512};
+use kernel::utilities::cells::OptionalCell;
+use kernel::utilities::cells::TakeCell;
+use kernel::utilities::leasable_buffer::SubSlice;
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::ErrorCode;
+
+pub struct TestHmacSha512<const L: usize> {
+    hmac: &'static HmacSha512Software<'static, Sha512Software<'static, L>, L>,
+    key: &'static [u8],                 // The key to use for HMAC
+    data: TakeCell<'static, [u8]>,      // The data to hash
+    digest: TakeCell<'static, [u8; L]>, // The buffer for the HMAC
+    correct: &'static [u8; L],          // The expected HMAC
+    client: OptionalCell<&'static dyn CapsuleTestClient>,
+}
+
+impl<const L: usize> TestHmacSha512<L> {
+    pub fn new(
+        hmac: &'static HmacSha512Software<'static, Sha512Software<'static, L>, L>,
+        key: &'static [u8],
+        data: &'static mut [u8],
+        digest: &'static mut [u8; L],
+        correct: &'static [u8; L],
+    ) -> Self {
+        TestHmacSha512 {
+            hmac,
+            key,
+            data: TakeCell::new(data),
+            digest: TakeCell::new(digest),
+            correct,
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn run(&'static self) {
+        kernel::hil::digest::Digest::set_client(self.hmac, self);
+
+        let r = if L == SHA_384_OUTPUT_LEN_BYTES {
+            self.hmac.set_mode_hmacsha384(self.key)
+        } else {
+            self.hmac.set_mode_hmacsha512(self.key)
+        };
+        if r.is_err() {
+            panic!("HmacSha512Test: failed to set key: {:?}", r);
+        }
+        let data = self.data.take().unwrap();
+        let buffer = SubSliceMut::new(data);
+        if let Err((e, _)) = self.hmac.add_mut_data(buffer) {
+            panic!("HmacSha512Test: failed to add data: {:?}", e);
+        }
+    }
+}
+
+impl<const L: usize> digest::ClientData<L> for TestHmacSha512<L> {
+    fn add_data_done(&self, _result: Result<(), ErrorCode>, _data: SubSlice<'static, u8>) {
+        unimplemented!()
+    }
+
+    fn add_mut_data_done(&self, result: Result<(), ErrorCode>, data: SubSliceMut<'static, u8>) {
+        self.data.replace(data.take());
+
+        if let Err(e) = result {
+            kernel::debug!("HmacSha512Test: failed to add data: {:?}", e);
+            self.client.map(|client| {
+                client.done(Err(CapsuleTestError::ErrorCode(e)));
+            });
+            return;
+        }
+
+        if let Err((e, d)) = self.hmac.run(self.digest.take().unwrap()) {
+            kernel::debug!("HmacSha512Test: failed to run HMAC: {:?}", e);
+
+            self.digest.replace(d);
+            self.client.map(|client| {
+                client.done(Err(CapsuleTestError::ErrorCode(e)));
+            });
+        }
+    }
+}
+
+impl<const L: usize> digest::ClientHash<L> for TestHmacSha512<L> {
+    fn hash_done(&self, result: Result<(), ErrorCode>, digest: &'static mut [u8; L]) {
+        let correct = digest == self.correct;
+        self.digest.replace(digest);
+
+        match result {
+            Ok(()) if correct => {
+                kernel::debug!("HMAC-SHA{} matches!", L * 8);
+                self.client.map(|client| {
+                    client.done(Ok(()));
+                });
+            }
+            Ok(()) => {
+                kernel::debug!("HmacSha512Test: incorrect HMAC output!");
+                self.client.map(|client| {
+                    client.done(Err(CapsuleTestError::IncorrectResult));
+                });
+            }
+            Err(e) => {
+                kernel::debug!("HmacSha512Test: HMAC failed: {:?}", e);
+                self.client.map(|client| {
+                    client.done(Err(CapsuleTestError::ErrorCode(e)));
+                });
+            }
+        }
+    }
+}
+
+impl<const L: usize> digest::ClientVerify<L> for TestHmacSha512<L> {
+    fn verification_done(&self, _result: Result<bool, ErrorCode>, _compare: &'static mut [u8; L]) {}
+}
+
+impl<const L: usize> CapsuleTest for TestHmacSha512<L> {
+    fn set_client(&self, client: &'static dyn CapsuleTestClient) {
+        self.client.set(client);
+    }
+}
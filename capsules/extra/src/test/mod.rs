@@ -3,11 +3,15 @@
 // Copyright Tock Contributors 2023.
 
 pub mod aes;
+pub mod aes128;
 pub mod aes_ccm;
 pub mod aes_gcm;
 pub mod crc;
+pub mod ecdsa_p256;
 pub mod hmac_sha256;
+pub mod hmac_sha512;
 pub mod kv_system;
 pub mod sha256;
+pub mod sha512;
 pub mod siphash24;
 pub mod udp;
@@ -0,0 +1,129 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Test the software implementation of SHA-384 and SHA-512 by performing a
+//! hash and checking it against the expected hash value. It uses
+//! DigestData::add_mut_data and DigestVerify::verify through the Digest
+//! trait.
+
+use core::cell::Cell;
+use core::cmp;
+
+use crate::sha512::Sha512Software;
+use capsules_core::test::capsule_test::{CapsuleTest, CapsuleTestClient, CapsuleTestError};
+use kernel::hil::digest;
+use kernel::hil::digest::{Digest, DigestData, DigestVerify};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::SubSlice;
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::ErrorCode;
+
+pub struct TestSha512<const L: usize> {
+    sha: &'static Sha512Software<'static, L>,
+    data: TakeCell<'static, [u8]>,    // The data to hash
+    hash: TakeCell<'static, [u8; L]>, // The supplied hash
+    position: Cell<usize>,            // Keep track of position in data
+    correct: bool,                    // Whether supplied hash is correct
+    client: OptionalCell<&'static dyn CapsuleTestClient>,
+}
+
+// We add data in chunks of 40 bytes, which do not divide the 128-byte
+// blocks, to ensure that the underlying buffering mechanism works correctly.
+const CHUNK_SIZE: usize = 40;
+
+impl<const L: usize> TestSha512<L> {
+    pub fn new(
+        sha: &'static Sha512Software<'static, L>,
+        data: &'static mut [u8],
+        hash: &'static mut [u8; L],
+        correct: bool,
+    ) -> Self {
+        TestSha512 {
+            sha,
+            data: TakeCell::new(data),
+            hash: TakeCell::new(hash),
+            position: Cell::new(0),
+            correct,
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn run(&'static self) {
+        self.sha.set_client(self);
+        let data = self.data.take().unwrap();
+        let chunk_size = cmp::min(CHUNK_SIZE, data.len());
+        self.position.set(chunk_size);
+        let mut buffer = SubSliceMut::new(data);
+        buffer.slice(0..chunk_size);
+        if let Err((e, _)) = self.sha.add_mut_data(buffer) {
+            panic!("Sha512Test: failed to add data: {:?}", e);
+        }
+    }
+}
+
+impl<const L: usize> digest::ClientData<L> for TestSha512<L> {
+    fn add_data_done(&self, _result: Result<(), ErrorCode>, _data: SubSlice<'static, u8>) {
+        unimplemented!()
+    }
+
+    fn add_mut_data_done(&self, result: Result<(), ErrorCode>, mut data: SubSliceMut<'static, u8>) {
+        if let Err(e) = result {
+            kernel::debug!("Sha512Test: adding data failed: {:?}", e);
+            self.data.replace(data.take());
+            self.client.map(|client| {
+                client.done(Err(CapsuleTestError::ErrorCode(e)));
+            });
+            return;
+        }
+
+        data.reset();
+        if self.position.get() < data.len() {
+            let new_position = cmp::min(data.len(), self.position.get() + CHUNK_SIZE);
+            data.slice(self.position.get()..new_position);
+            self.position.set(new_position);
+            if let Err((e, _)) = self.sha.add_mut_data(data) {
+                panic!("Sha512Test: failed to add data: {:?}", e);
+            }
+        } else {
+            self.data.replace(data.take());
+            if let Err((e, _)) = self.sha.verify(self.hash.take().unwrap()) {
+                panic!("Sha512Test: failed to verify: {:?}", e);
+            }
+        }
+    }
+}
+
+impl<const L: usize> digest::ClientVerify<L> for TestSha512<L> {
+    fn verification_done(&self, result: Result<bool, ErrorCode>, compare: &'static mut [u8; L]) {
+        self.hash.replace(compare);
+        kernel::debug!("Sha512Test: Verification result: {:?}", result);
+        match result {
+            Ok(success) if success == self.correct => {
+                self.client.map(|client| {
+                    client.done(Ok(()));
+                });
+            }
+            Ok(_) => {
+                self.client.map(|client| {
+                    client.done(Err(CapsuleTestError::IncorrectResult));
+                });
+            }
+            Err(e) => {
+                self.client.map(|client| {
+                    client.done(Err(CapsuleTestError::ErrorCode(e)));
+                });
+            }
+        }
+    }
+}
+
+impl<const L: usize> digest::ClientHash<L> for TestSha512<L> {
+    fn hash_done(&self, _result: Result<(), ErrorCode>, _digest: &'static mut [u8; L]) {}
+}
+
+impl<const L: usize> CapsuleTest for TestSha512<L> {
+    fn set_client(&self, client: &'static dyn CapsuleTestClient) {
+        self.client.set(client);
+    }
+}
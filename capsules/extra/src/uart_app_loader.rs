@@ -0,0 +1,454 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Load new applications received over a UART into free app flash.
+//!
+//! The loader listens on a UART for a TBF sent by a host, writes it to a free
+//! region of app flash, and asks the kernel's process loader to check and
+//! start it. No reboot is required, and since the TBF is linked into the
+//! applications in flash, it is also found at the next boot.
+//!
+//! The UART receive path is shared with any other user of the same UART mux,
+//! so boards should give the loader a UART that is not used by the process
+//! console.
+//!
+//! Protocol
+//! --------
+//!
+//! All responses from the loader are a single status byte: 0 for success, or
+//! the value of an `ErrorCode` otherwise.
+//!
+//! 1. The host sends the magic `TBFL` followed by the length of the TBF as a
+//!    32-bit little-endian integer. The loader answers `SIZE` if the TBF is
+//!    too short to hold a TBF header or does not fit in free flash,
+//!    `BUSY` if the kernel is loading processes, `NOMEM` if the loader can no
+//!    longer write to flash, and success otherwise.
+//! 2. The host sends the TBF in chunks of the size of the loader's buffers
+//!    (`BUF_LEN` with the component), the last chunk being shorter. The loader
+//!    answers each chunk once it has been written to flash, or with the error
+//!    if writing it could not be started, which abandons the transfer.
+//! 3. Once the last chunk has been written, the loader answers with the result
+//!    of loading the application: `INVAL` if the TBF could not be parsed,
+//!    `NOSUPPORT` if its credentials were not accepted, `ALREADY` if an
+//!    application with the same AppID is running, `NOMEM` if there is not
+//!    enough memory or no free process slot, and `FAIL` otherwise.
+//!
+//! If the host stops sending for more than a second during a transfer, the
+//! transfer is abandoned and the loader waits for a new magic.
+//!
+//! The start of the TBF header is only written once the rest of the TBF is in
+//! flash. Until then, a padding entry covering the whole free region is
+//! written in its place, so an abandoned transfer is skipped at the next boot
+//! while the applications after it are still found. Before the header is
+//! written, the rest of the region after the TBF is turned into another
+//! padding entry. If the kernel rejects the TBF, its header is replaced with
+//! a padding entry again. The flash of abandoned and rejected transfers is
+//! free for the next transfer.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let uart_app_loader = components::uart_app_loader::UartAppLoaderComponent::new(
+//!     uart_mux,
+//!     nv_to_page,
+//!     loader,
+//!     mux_alarm,
+//! )
+//! .finalize(components::uart_app_loader_component_static!(
+//!     nrf52840::rtc::Rtc<'static>
+//! ));
+//! ```
+
+use core::cell::Cell;
+use core::cmp;
+
+use kernel::hil::nonvolatile_storage::{NonvolatileStorage, NonvolatileStorageClient};
+use kernel::hil::time::{self, ConvertTicks};
+use kernel::hil::uart;
+use kernel::process::{
+    padding_tbf_header, DynamicProcessLoading, DynamicProcessLoadingClient, ProcessLoadError,
+    PADDING_TBF_HEADER_LEN,
+};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// Magic starting a transfer.
+pub const MAGIC: &[u8; 4] = b"TBFL";
+
+/// Time after which an interrupted transfer is abandoned.
+const TRANSFER_TIMEOUT_MS: u32 = 1000;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Waiting for the magic. The value is the number of bytes of the magic
+    /// matched so far.
+    Idle(usize),
+    /// Receiving the length of the TBF.
+    Header,
+    /// Receiving a chunk of the TBF.
+    Receiving,
+    /// Writing a chunk to flash.
+    Writing,
+    /// Turning the rest of the free region after the TBF into padding, once
+    /// the TBF is in flash.
+    WritingPadding,
+    /// Writing the start of the TBF header, once the rest of the TBF is in
+    /// flash.
+    Committing,
+    /// Waiting for the kernel to load the new application.
+    Loading,
+    /// Erasing the TBF after the kernel rejected it.
+    ErasingRejected,
+}
+
+pub struct UartAppLoader<'a, A: time::Alarm<'a>> {
+    uart: &'a dyn uart::UartData<'a>,
+    storage: &'a dyn NonvolatileStorage<'a>,
+    loader: &'a dyn DynamicProcessLoading<'a>,
+    alarm: &'a A,
+    state: Cell<State>,
+    /// Buffer for received bytes.
+    buffer: TakeCell<'static, [u8]>,
+    /// Buffer from which received chunks are written to flash. The storage
+    /// does not return it if a write cannot be started, after which the loader
+    /// answers every transfer with `NOMEM`.
+    write_buffer: TakeCell<'static, [u8]>,
+    /// Buffer holding the status byte sent to the host.
+    tx_buffer: TakeCell<'static, [u8]>,
+    /// Address of the TBF in flash.
+    start: Cell<usize>,
+    /// Length of the TBF.
+    length: Cell<usize>,
+    /// Length of the free region holding the TBF.
+    region_length: Cell<usize>,
+    /// Start of the TBF header, written once the rest of the TBF is in flash.
+    header: Cell<[u8; PADDING_TBF_HEADER_LEN]>,
+    /// Address at which the next chunk is written.
+    address: Cell<usize>,
+    /// Number of bytes of the TBF not received yet.
+    remaining: Cell<usize>,
+    /// Result of loading the application, reported to the host once a
+    /// rejected TBF is erased.
+    load_result: OptionalCell<Result<(), ErrorCode>>,
+}
+
+impl<'a, A: time::Alarm<'a>> UartAppLoader<'a, A> {
+    pub fn new(
+        uart: &'a dyn uart::UartData<'a>,
+        storage: &'a dyn NonvolatileStorage<'a>,
+        loader: &'a dyn DynamicProcessLoading<'a>,
+        alarm: &'a A,
+        buffer: &'static mut [u8],
+        write_buffer: &'static mut [u8],
+        tx_buffer: &'static mut [u8],
+    ) -> UartAppLoader<'a, A> {
+        UartAppLoader {
+            uart,
+            storage,
+            loader,
+            alarm,
+            state: Cell::new(State::Idle(0)),
+            buffer: TakeCell::new(buffer),
+            write_buffer: TakeCell::new(write_buffer),
+            tx_buffer: TakeCell::new(tx_buffer),
+            start: Cell::new(0),
+            length: Cell::new(0),
+            region_length: Cell::new(0),
+            header: Cell::new([0; PADDING_TBF_HEADER_LEN]),
+            address: Cell::new(0),
+            remaining: Cell::new(0),
+            load_result: OptionalCell::empty(),
+        }
+    }
+
+    /// Start listening for transfers.
+    pub fn start(&self) -> Result<(), ErrorCode> {
+        self.receive(State::Idle(0), 1)
+    }
+
+    /// Receive `len` bytes in `state`. Transfers are abandoned if the host
+    /// does not send them in time.
+    fn receive(&self, state: State, len: usize) -> Result<(), ErrorCode> {
+        self.state.set(state);
+        let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+        if let Err((e, buffer)) = self.uart.receive_buffer(buffer, len) {
+            self.buffer.replace(buffer);
+            return Err(e);
+        }
+        if !matches!(state, State::Idle(_)) {
+            self.alarm.set_alarm(
+                self.alarm.now(),
+                self.alarm.ticks_from_ms(TRANSFER_TIMEOUT_MS),
+            );
+        }
+        Ok(())
+    }
+
+    /// Receive the next chunk of the TBF, or finish writing it if it has been
+    /// received completely.
+    fn receive_next_chunk(&self) -> Result<(), ErrorCode> {
+        let remaining = self.remaining.get();
+        if remaining == 0 {
+            let length = self.length.get();
+            let region_length = self.region_length.get();
+            if region_length > length {
+                let padding = padding_tbf_header((region_length - length) as u32);
+                return self.write_header(
+                    self.start.get() + length,
+                    padding,
+                    State::WritingPadding,
+                );
+            }
+            return self.write_header(self.start.get(), self.header.get(), State::Committing);
+        }
+        let len = cmp::min(remaining, self.chunk_len());
+        self.receive(State::Receiving, len)
+    }
+
+    /// Size of the largest chunk that can be received and written to flash.
+    fn chunk_len(&self) -> usize {
+        cmp::min(
+            self.buffer.map_or(0, |buffer| buffer.len()),
+            self.write_buffer.map_or(0, |buffer| buffer.len()),
+        )
+    }
+
+    /// Send a status byte to the host.
+    fn respond(&self, result: Result<(), ErrorCode>) {
+        let status = match result {
+            Ok(()) => 0,
+            Err(e) => usize::from(e) as u8,
+        };
+        self.tx_buffer.take().map(|tx_buffer| {
+            tx_buffer[0] = status;
+            if let Err((_, tx_buffer)) = self.uart.transmit_buffer(tx_buffer, 1) {
+                self.tx_buffer.replace(tx_buffer);
+            }
+        });
+    }
+
+    /// Write the TBF header `header` at `address` in `state`.
+    fn write_header(
+        &self,
+        address: usize,
+        header: [u8; PADDING_TBF_HEADER_LEN],
+        state: State,
+    ) -> Result<(), ErrorCode> {
+        let buffer = self.write_buffer.take().ok_or(ErrorCode::NOMEM)?;
+        buffer[..PADDING_TBF_HEADER_LEN].copy_from_slice(&header);
+        self.state.set(state);
+        self.storage.write(buffer, address, PADDING_TBF_HEADER_LEN)
+    }
+
+    /// Ask the kernel to load the TBF, now that it is completely in flash.
+    fn load(&self) {
+        self.state.set(State::Loading);
+        if let Err(e) = self.loader.load_new_process_binary(self.start.get()) {
+            self.reject(Err(e));
+        }
+    }
+
+    /// Erase the TBF after it was rejected, and report `result` to the host
+    /// once done.
+    fn reject(&self, result: Result<(), ErrorCode>) {
+        let padding = padding_tbf_header(self.length.get() as u32);
+        match self.write_header(self.start.get(), padding, State::ErasingRejected) {
+            Ok(()) => self.load_result.set(result),
+            // The TBF is found again at the next boot, and rejected then.
+            Err(_) => {
+                self.respond(result);
+                let _ = self.receive(State::Idle(0), 1);
+            }
+        }
+    }
+
+    /// Abandon the current transfer, report `error` and wait for a new one.
+    fn abort(&self, error: ErrorCode) {
+        let _ = self.alarm.disarm();
+        self.respond(Err(error));
+        let _ = self.receive(State::Idle(0), 1);
+    }
+
+    /// Handle the length of the TBF sent by the host.
+    fn start_transfer(&self, length: usize) -> Result<(), ErrorCode> {
+        if self.write_buffer.is_none() {
+            return Err(ErrorCode::NOMEM);
+        }
+        if length < PADDING_TBF_HEADER_LEN || self.chunk_len() < PADDING_TBF_HEADER_LEN {
+            return Err(ErrorCode::SIZE);
+        }
+        let region = self.loader.free_flash(length)?;
+        self.start.set(region.as_ptr() as usize);
+        self.length.set(length);
+        self.region_length.set(region.len());
+        self.address.set(region.as_ptr() as usize);
+        self.remaining.set(length);
+        self.receive_next_chunk()
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> uart::ReceiveClient for UartAppLoader<'a, A> {
+    fn received_buffer(
+        &self,
+        buffer: &'static mut [u8],
+        rx_len: usize,
+        rcode: Result<(), ErrorCode>,
+        _error: uart::Error,
+    ) {
+        let state = self.state.get();
+        if rcode.is_err() {
+            self.buffer.replace(buffer);
+            match state {
+                // A transfer timed out or failed.
+                State::Header | State::Receiving => {
+                    self.abort(rcode.err().unwrap_or(ErrorCode::FAIL));
+                }
+                _ => {
+                    let _ = self.receive(State::Idle(0), 1);
+                }
+            }
+            return;
+        }
+
+        match state {
+            State::Idle(matched) => {
+                let byte = buffer[0];
+                self.buffer.replace(buffer);
+                // A mismatching byte may still start a new magic.
+                let matched = if byte == MAGIC[matched] {
+                    matched + 1
+                } else {
+                    usize::from(byte == MAGIC[0])
+                };
+                let _ = if matched == MAGIC.len() {
+                    self.receive(State::Header, 4)
+                } else {
+                    self.receive(State::Idle(matched), 1)
+                };
+            }
+            State::Header => {
+                let length = u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]);
+                self.buffer.replace(buffer);
+                match self.start_transfer(length as usize) {
+                    Ok(()) => self.respond(Ok(())),
+                    Err(e) => self.abort(e),
+                }
+            }
+            State::Receiving => {
+                let _ = self.alarm.disarm();
+                let Some(write_buffer) = self.write_buffer.take() else {
+                    self.buffer.replace(buffer);
+                    self.abort(ErrorCode::NOMEM);
+                    return;
+                };
+                write_buffer[..rx_len].copy_from_slice(&buffer[..rx_len]);
+                self.buffer.replace(buffer);
+                if self.address.get() == self.start.get() {
+                    // The first chunk holds at least the start of the header,
+                    // which is kept back so that the TBF is not found until it
+                    // is complete. The whole free region is skipped as padding
+                    // meanwhile.
+                    let mut header = [0; PADDING_TBF_HEADER_LEN];
+                    header.copy_from_slice(&write_buffer[..PADDING_TBF_HEADER_LEN]);
+                    self.header.set(header);
+                    write_buffer[..PADDING_TBF_HEADER_LEN]
+                        .copy_from_slice(&padding_tbf_header(self.region_length.get() as u32));
+                }
+                self.state.set(State::Writing);
+                if let Err(e) = self.storage.write(write_buffer, self.address.get(), rx_len) {
+                    // The storage keeps the write buffer, but the receive
+                    // buffer is still available to report the failure and to
+                    // answer further transfers.
+                    self.abort(e);
+                }
+            }
+            State::Writing
+            | State::WritingPadding
+            | State::Committing
+            | State::Loading
+            | State::ErasingRejected => {
+                self.buffer.replace(buffer);
+            }
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> uart::TransmitClient for UartAppLoader<'a, A> {
+    fn transmitted_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        _tx_len: usize,
+        _rval: Result<(), ErrorCode>,
+    ) {
+        self.tx_buffer.replace(tx_buffer);
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> NonvolatileStorageClient for UartAppLoader<'a, A> {
+    fn read_done(&self, buffer: &'static mut [u8], _length: usize) {
+        self.write_buffer.replace(buffer);
+    }
+
+    fn write_done(&self, buffer: &'static mut [u8], length: usize) {
+        self.write_buffer.replace(buffer);
+        match self.state.get() {
+            State::Writing => {}
+            State::WritingPadding => {
+                if let Err(e) =
+                    self.write_header(self.start.get(), self.header.get(), State::Committing)
+                {
+                    self.abort(e);
+                }
+                return;
+            }
+            State::Committing => {
+                self.load();
+                return;
+            }
+            State::ErasingRejected => {
+                self.respond(self.load_result.take().unwrap_or(Err(ErrorCode::FAIL)));
+                let _ = self.receive(State::Idle(0), 1);
+                return;
+            }
+            _ => return,
+        }
+        self.address.set(self.address.get() + length);
+        self.remaining
+            .set(self.remaining.get().saturating_sub(length));
+        let last_chunk = self.remaining.get() == 0;
+        match self.receive_next_chunk() {
+            // The last chunk is answered with the result of loading the
+            // application, once its header has been written.
+            Ok(()) if last_chunk => (),
+            Ok(()) => self.respond(Ok(())),
+            Err(e) => self.abort(e),
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> DynamicProcessLoadingClient for UartAppLoader<'a, A> {
+    fn load_done(&self, result: Result<(), ProcessLoadError>) {
+        if self.state.get() != State::Loading {
+            return;
+        }
+        match result {
+            Ok(()) => {
+                self.respond(Ok(()));
+                let _ = self.receive(State::Idle(0), 1);
+            }
+            Err(e) => self.reject(Err(e.into())),
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> time::AlarmClient for UartAppLoader<'a, A> {
+    fn alarm(&self) {
+        // The host stopped sending; the pending receive completes with
+        // `CANCEL`, which abandons the transfer.
+        if matches!(self.state.get(), State::Header | State::Receiving) {
+            let _ = self.uart.receive_abort();
+        }
+    }
+}
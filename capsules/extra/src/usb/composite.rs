@@ -0,0 +1,608 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Composite USB device
+//!
+//! This capsule shares one USB controller between several USB classes, such
+//! as CDC-ACM and CTAP, which the host sees as the functions of one device.
+//!
+//! Each class runs unmodified on its own `UsbCompositeFunction`, a virtual
+//! USB controller. When the device is enabled, the composite device enables
+//! each function and reads its configuration descriptor as a host would. It
+//! then builds a configuration with the interfaces of all functions, groups
+//! the interfaces of each function with an interface association descriptor,
+//! and renumbers the interfaces and endpoints so that they do not collide.
+//! The endpoints of the functions are allocated on the controller in the order
+//! the functions use them.
+//!
+//! The composite device answers the requests for the device itself, and
+//! passes requests for an interface or an endpoint to the function owning it,
+//! with the number the function knows it by.
+//!
+//! ```text
+//!  +---------+  +---------+
+//!  | CdcAcm  |  | CtapHid |
+//!  +---------+  +---------+
+//!  | Function|  | Function|
+//!  +---------+--+---------+
+//!  |     UsbComposite     |
+//!  +----------------------+
+//!  |    UsbController     |
+//!  +----------------------+
+//! ```
+
+use core::cell::Cell;
+use core::cmp;
+
+use super::descriptors;
+use super::descriptors::Descriptor;
+use super::descriptors::DescriptorBuffer;
+use super::descriptors::DescriptorType;
+use super::descriptors::InterfaceAssociationDescriptor;
+use super::descriptors::Recipient;
+use super::descriptors::SetupData;
+use super::usbc_client_ctrl::ClientCtrl;
+
+use kernel::collections::list::{List, ListLink, ListNode};
+use kernel::hil;
+use kernel::hil::usb::TransferType;
+use kernel::utilities::cells::OptionalCell;
+use kernel::utilities::cells::VolatileCell;
+
+static LANGUAGES: &[u16; 1] = &[
+    0x0409, // English (United States)
+];
+
+/// Number of endpoints of the controller, including the control endpoint.
+const N_ENDPOINTS: usize = 8;
+
+/// Room for the configuration descriptor of a function.
+const FUNCTION_DESCRIPTORS_LEN: usize = 128;
+
+/// Class specific descriptor subtypes of CDC which refer to interfaces.
+const CDC_CALL_MANAGEMENT: u8 = 0x01;
+const CDC_UNION: u8 = 0x06;
+
+/// A USB device made of several functions.
+pub struct UsbComposite<'a, U: 'a> {
+    /// Helper USB client library for the requests for the device.
+    client_ctrl: ClientCtrl<'a, 'static, U>,
+
+    /// The functions of the device, in the order of their interfaces.
+    functions: List<'a, UsbCompositeFunction<'a, U>>,
+
+    /// The function owning each endpoint of the controller, with the number
+    /// of the endpoint for the function.
+    endpoints: [OptionalCell<(usize, usize)>; N_ENDPOINTS],
+
+    /// The function handling the current control transfer, if any.
+    ctrl_function: OptionalCell<&'a UsbCompositeFunction<'a, U>>,
+}
+
+impl<'a, U: hil::usb::UsbController<'a>> UsbComposite<'a, U> {
+    pub fn new(
+        controller: &'a U,
+        max_ctrl_packet_size: u8,
+        vendor_id: u16,
+        product_id: u16,
+        strings: &'static [&'static str; 3],
+    ) -> Self {
+        // The configuration is only known once the functions are enabled.
+        let (device_descriptor_buffer, other_descriptor_buffer) =
+            descriptors::create_descriptor_buffers(
+                descriptors::DeviceDescriptor {
+                    vendor_id,
+                    product_id,
+                    manufacturer_string: 1,
+                    product_string: 2,
+                    serial_number_string: 3,
+                    class: 0xEF,    // Class: Miscellaneous
+                    subclass: 0x02, // Common class
+                    protocol: 0x01, // Interface association descriptors
+                    max_packet_size_ep0: max_ctrl_packet_size,
+                    ..descriptors::DeviceDescriptor::default()
+                },
+                descriptors::ConfigurationDescriptor::default(),
+                &mut [],
+                &[],
+                None, // No HID descriptor
+                None, // No CDC descriptor
+            );
+
+        Self {
+            client_ctrl: ClientCtrl::new(
+                controller,
+                device_descriptor_buffer,
+                other_descriptor_buffer,
+                None, // No HID descriptor
+                None, // No report descriptor
+                LANGUAGES,
+                strings,
+            ),
+            functions: List::new(),
+            endpoints: Default::default(),
+            ctrl_function: OptionalCell::empty(),
+        }
+    }
+
+    #[inline]
+    fn controller(&self) -> &'a U {
+        self.client_ctrl.controller()
+    }
+
+    fn function(&self, id: usize) -> Option<&'a UsbCompositeFunction<'a, U>> {
+        self.functions
+            .iter()
+            .find(|function| function.id.get() == id)
+    }
+
+    /// The endpoint of the controller for `endpoint` of a function, allocated
+    /// on first use.
+    fn endpoint(&self, id: usize, endpoint: usize) -> usize {
+        if let Some(physical) = self
+            .endpoints
+            .iter()
+            .position(|owner| owner.contains(&(id, endpoint)))
+        {
+            return physical;
+        }
+
+        // The control endpoint is shared.
+        match self
+            .endpoints
+            .iter()
+            .skip(1)
+            .position(|owner| owner.is_none())
+        {
+            Some(free) => {
+                self.endpoints[free + 1].set((id, endpoint));
+                free + 1
+            }
+            None => panic!("USB composite device is out of endpoints"),
+        }
+    }
+
+    /// The function owning an endpoint of the controller, with the number of
+    /// the endpoint for the function.
+    fn endpoint_owner(&self, endpoint: usize) -> Option<(&'a UsbCompositeFunction<'a, U>, usize)> {
+        self.endpoints
+            .get(endpoint)
+            .and_then(|owner| owner.get())
+            .and_then(|(id, endpoint)| self.function(id).map(|function| (function, endpoint)))
+    }
+
+    /// Read the configuration descriptor of a function, as the host would.
+    fn read_function_descriptors(
+        &self,
+        function: &UsbCompositeFunction<'a, U>,
+        descriptors: &mut [u8],
+    ) -> usize {
+        let (Some(client), Some(ctrl_buffer)) = (function.client.get(), function.ctrl_buffer.get())
+        else {
+            return 0;
+        };
+
+        // GET_DESCRIPTOR for the configuration.
+        let request_length = (descriptors.len() as u16).to_le_bytes();
+        let setup = [
+            0x80,
+            0x06,
+            0x00,
+            DescriptorType::Configuration as u8,
+            0x00,
+            0x00,
+            request_length[0],
+            request_length[1],
+        ];
+        for (cell, byte) in ctrl_buffer.iter().zip(setup) {
+            cell.set(byte);
+        }
+
+        let mut len = 0;
+        if let hil::usb::CtrlSetupResult::Ok = client.ctrl_setup(0) {
+            while let hil::usb::CtrlInResult::Packet(packet_bytes, complete) = client.ctrl_in(0) {
+                let copied = cmp::min(packet_bytes, descriptors.len() - len);
+                for i in 0..copied {
+                    descriptors[len + i] = ctrl_buffer[i].get();
+                }
+                len += copied;
+                if complete || packet_bytes == 0 {
+                    break;
+                }
+            }
+        }
+        client.ctrl_status_complete(0);
+
+        len
+    }
+
+    /// Add the interfaces of a function to the configuration, with their
+    /// numbers starting at `first_interface`. Returns the length of the
+    /// descriptors added.
+    fn add_function(
+        &self,
+        function: &UsbCompositeFunction<'a, U>,
+        first_interface: u8,
+        buf: &[Cell<u8>],
+    ) -> usize {
+        let mut descriptors = [0; FUNCTION_DESCRIPTORS_LEN];
+        let len = self.read_function_descriptors(function, &mut descriptors);
+        let descriptors = &descriptors[..len];
+
+        // Iterate over the descriptors after the configuration descriptor.
+        let following = || {
+            let mut offset = 0;
+            core::iter::from_fn(move || {
+                offset += *descriptors.get(offset)? as usize;
+                let desc_len = *descriptors.get(offset)? as usize;
+                if desc_len < 2 {
+                    return None;
+                }
+                descriptors.get(offset..offset + desc_len)
+            })
+        };
+
+        let mut interfaces =
+            following().filter(|d| d[1] == DescriptorType::Interface as u8 && d[3] == 0);
+        let Some(class) = interfaces.next().map(|d| (d[5], d[6], d[7])) else {
+            return 0;
+        };
+        let num_interfaces = 1 + interfaces.count() as u8;
+        function.first_interface.set(first_interface);
+        function.num_interfaces.set(num_interfaces);
+
+        let mut written = 0;
+        if num_interfaces > 1 {
+            written += InterfaceAssociationDescriptor {
+                first_interface,
+                interface_count: num_interfaces,
+                function_class: class.0,
+                function_subclass: class.1,
+                function_protocol: class.2,
+                string_index: 0,
+            }
+            .write_to(&buf[written..]);
+        }
+
+        for d in following() {
+            if written + d.len() > buf.len() {
+                panic!("USB composite device descriptors do not fit");
+            }
+            let out = &buf[written..written + d.len()];
+            for (cell, byte) in out.iter().zip(d) {
+                cell.set(*byte);
+            }
+
+            if d[1] == DescriptorType::Interface as u8 {
+                out[2].set(first_interface + d[2]);
+            } else if d[1] == DescriptorType::Endpoint as u8 {
+                let endpoint = self.endpoint(function.id.get(), (d[2] & 0x0f) as usize);
+                out[2].set(d[2] & 0x80 | endpoint as u8);
+            } else if d[1] == DescriptorType::CdcInterface as u8 {
+                match d[2] {
+                    CDC_CALL_MANAGEMENT if d.len() >= 5 => out[4].set(first_interface + d[4]),
+                    CDC_UNION => {
+                        for (cell, interface) in out[3..].iter().zip(&d[3..]) {
+                            cell.set(first_interface + interface);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            written += d.len();
+        }
+
+        written
+    }
+}
+
+impl<'a, U: hil::usb::UsbController<'a>> hil::usb::Client<'a> for UsbComposite<'a, U> {
+    fn enable(&'a self) {
+        // Set up the default control endpoint
+        self.client_ctrl.enable();
+
+        // Enable the functions, and gather their interfaces into the
+        // configuration.
+        let descriptors = DescriptorBuffer::default();
+        let mut configuration = descriptors::ConfigurationDescriptor::default();
+        let mut len = configuration.size();
+        let mut num_interfaces = 0;
+        for function in self.functions.iter() {
+            function.client.map(|client| client.enable());
+            len += self.add_function(function, num_interfaces, &descriptors.buf[len..]);
+            num_interfaces += function.num_interfaces.get();
+        }
+
+        configuration.num_interfaces = num_interfaces;
+        configuration.related_descriptor_length = len - configuration.size();
+        configuration.write_to(&descriptors.buf);
+
+        self.client_ctrl
+            .set_other_descriptor_buffer(DescriptorBuffer { len, ..descriptors });
+    }
+
+    fn attach(&'a self) {
+        for function in self.functions.iter() {
+            function.client.map(|client| client.attach());
+        }
+        self.client_ctrl.attach();
+    }
+
+    fn bus_reset(&'a self) {
+        for function in self.functions.iter() {
+            function.client.map(|client| client.bus_reset());
+        }
+    }
+
+    /// Handle a Control Setup transaction.
+    ///
+    /// Requests for an interface or an endpoint go to the function owning it.
+    fn ctrl_setup(&'a self, endpoint: usize) -> hil::usb::CtrlSetupResult {
+        self.ctrl_function.clear();
+
+        let target =
+            SetupData::get(&self.client_ctrl.ctrl_buffer.buf).and_then(
+                |setup_data| match setup_data.request_type.recipient() {
+                    Recipient::Interface => {
+                        let interface = setup_data.index as u8;
+                        self.functions
+                            .iter()
+                            .find(|function| function.has_interface(interface))
+                            .map(|function| {
+                                let index = setup_data.index & 0xff00
+                                    | (interface - function.first_interface.get()) as u16;
+                                (function, index)
+                            })
+                    }
+                    Recipient::Endpoint => self
+                        .endpoint_owner((setup_data.index & 0x0f) as usize)
+                        .map(|(function, endpoint)| {
+                            (function, setup_data.index & !0x0f | endpoint as u16)
+                        }),
+                    _ => None,
+                },
+            );
+
+        match target {
+            Some((function, index)) => {
+                // Pass the request on, with the index the function knows.
+                function.ctrl_buffer.map(|buf| {
+                    for (cell, setup) in buf.iter().zip(self.client_ctrl.ctrl_buffer.buf.iter()) {
+                        cell.set(setup.get());
+                    }
+                    buf[4].set(index as u8);
+                    buf[5].set((index >> 8) as u8);
+                });
+                self.ctrl_function.set(function);
+                function
+                    .client
+                    .map_or(hil::usb::CtrlSetupResult::ErrGeneric, |client| {
+                        client.ctrl_setup(endpoint)
+                    })
+            }
+            None => self.client_ctrl.ctrl_setup(endpoint),
+        }
+    }
+
+    /// Handle a Control In transaction
+    fn ctrl_in(&'a self, endpoint: usize) -> hil::usb::CtrlInResult {
+        match self.ctrl_function.get() {
+            Some(function) => {
+                let result = function
+                    .client
+                    .map_or(hil::usb::CtrlInResult::Error, |client| {
+                        client.ctrl_in(endpoint)
+                    });
+                if let hil::usb::CtrlInResult::Packet(packet_bytes, _) = result {
+                    function.ctrl_buffer.map(|buf| {
+                        for i in 0..packet_bytes {
+                            self.client_ctrl.ctrl_buffer.buf[i].set(buf[i].get());
+                        }
+                    });
+                }
+                result
+            }
+            None => self.client_ctrl.ctrl_in(endpoint),
+        }
+    }
+
+    /// Handle a Control Out transaction
+    fn ctrl_out(&'a self, endpoint: usize, packet_bytes: u32) -> hil::usb::CtrlOutResult {
+        match self.ctrl_function.get() {
+            Some(function) => {
+                function.ctrl_buffer.map(|buf| {
+                    for i in 0..packet_bytes as usize {
+                        buf[i].set(self.client_ctrl.ctrl_buffer.buf[i].get());
+                    }
+                });
+                function
+                    .client
+                    .map_or(hil::usb::CtrlOutResult::Halted, |client| {
+                        client.ctrl_out(endpoint, packet_bytes)
+                    })
+            }
+            None => self.client_ctrl.ctrl_out(endpoint, packet_bytes),
+        }
+    }
+
+    fn ctrl_status(&'a self, endpoint: usize) {
+        match self.ctrl_function.get() {
+            Some(function) => {
+                function.client.map(|client| client.ctrl_status(endpoint));
+            }
+            None => self.client_ctrl.ctrl_status(endpoint),
+        }
+    }
+
+    /// Handle the completion of a Control transfer
+    fn ctrl_status_complete(&'a self, endpoint: usize) {
+        match self.ctrl_function.take() {
+            Some(function) => {
+                function
+                    .client
+                    .map(|client| client.ctrl_status_complete(endpoint));
+            }
+            None => self.client_ctrl.ctrl_status_complete(endpoint),
+        }
+    }
+
+    /// Handle a Bulk/Interrupt IN transaction
+    fn packet_in(&'a self, transfer_type: TransferType, endpoint: usize) -> hil::usb::InResult {
+        self.endpoint_owner(endpoint)
+            .and_then(|(function, endpoint)| {
+                function
+                    .client
+                    .map(|client| client.packet_in(transfer_type, endpoint))
+            })
+            .unwrap_or(hil::usb::InResult::Error)
+    }
+
+    /// Handle a Bulk/Interrupt OUT transaction
+    fn packet_out(
+        &'a self,
+        transfer_type: TransferType,
+        endpoint: usize,
+        packet_bytes: u32,
+    ) -> hil::usb::OutResult {
+        self.endpoint_owner(endpoint)
+            .and_then(|(function, endpoint)| {
+                function
+                    .client
+                    .map(|client| client.packet_out(transfer_type, endpoint, packet_bytes))
+            })
+            .unwrap_or(hil::usb::OutResult::Error)
+    }
+
+    fn packet_transmitted(&'a self, endpoint: usize) {
+        if let Some((function, endpoint)) = self.endpoint_owner(endpoint) {
+            function
+                .client
+                .map(|client| client.packet_transmitted(endpoint));
+        }
+    }
+}
+
+/// A function of a composite USB device, which a USB class uses as its
+/// controller.
+pub struct UsbCompositeFunction<'a, U: 'a> {
+    mux: &'a UsbComposite<'a, U>,
+    /// Identifies the function in the endpoints of the composite device.
+    id: Cell<usize>,
+    client: OptionalCell<&'a dyn hil::usb::Client<'a>>,
+    /// The buffer of the control endpoint of the class.
+    ctrl_buffer: OptionalCell<&'a [VolatileCell<u8>]>,
+    /// The interfaces of the function in the configuration.
+    first_interface: Cell<u8>,
+    num_interfaces: Cell<u8>,
+    next: ListLink<'a, UsbCompositeFunction<'a, U>>,
+}
+
+impl<'a, U: hil::usb::UsbController<'a>> UsbCompositeFunction<'a, U> {
+    pub fn new(mux: &'a UsbComposite<'a, U>) -> Self {
+        Self {
+            mux,
+            id: Cell::new(0),
+            client: OptionalCell::empty(),
+            ctrl_buffer: OptionalCell::empty(),
+            first_interface: Cell::new(0),
+            num_interfaces: Cell::new(0),
+            next: ListLink::empty(),
+        }
+    }
+
+    /// Add the function to the composite device. Functions get their
+    /// interfaces in the order they are added.
+    pub fn setup(&'a self) {
+        self.id.set(self.mux.functions.iter().count());
+        self.mux.functions.push_tail(self);
+    }
+
+    fn has_interface(&self, interface: u8) -> bool {
+        interface
+            .checked_sub(self.first_interface.get())
+            .is_some_and(|i| i < self.num_interfaces.get())
+    }
+
+    fn endpoint(&self, endpoint: usize) -> usize {
+        self.mux.endpoint(self.id.get(), endpoint)
+    }
+}
+
+impl<'a, U> ListNode<'a, UsbCompositeFunction<'a, U>> for UsbCompositeFunction<'a, U> {
+    fn next(&'a self) -> &'a ListLink<'a, UsbCompositeFunction<'a, U>> {
+        &self.next
+    }
+}
+
+/// The composite device owns the state of the device and the control
+/// endpoint, so the functions only set up their other endpoints.
+impl<'a, U: hil::usb::UsbController<'a>> hil::usb::UsbController<'a>
+    for UsbCompositeFunction<'a, U>
+{
+    fn set_client(&self, client: &'a dyn hil::usb::Client<'a>) {
+        self.client.set(client);
+    }
+
+    fn endpoint_set_ctrl_buffer(&self, buf: &'a [VolatileCell<u8>]) {
+        self.ctrl_buffer.set(buf);
+    }
+
+    fn endpoint_set_in_buffer(&self, endpoint: usize, buf: &'a [VolatileCell<u8>]) {
+        self.mux
+            .controller()
+            .endpoint_set_in_buffer(self.endpoint(endpoint), buf);
+    }
+
+    fn endpoint_set_out_buffer(&self, endpoint: usize, buf: &'a [VolatileCell<u8>]) {
+        self.mux
+            .controller()
+            .endpoint_set_out_buffer(self.endpoint(endpoint), buf);
+    }
+
+    fn enable_as_device(&self, _speed: hil::usb::DeviceSpeed) {}
+
+    fn attach(&self) {}
+
+    fn detach(&self) {}
+
+    fn set_address(&self, _addr: u16) {}
+
+    fn enable_address(&self) {}
+
+    fn endpoint_in_enable(&self, transfer_type: TransferType, endpoint: usize) {
+        if endpoint != 0 {
+            self.mux
+                .controller()
+                .endpoint_in_enable(transfer_type, self.endpoint(endpoint));
+        }
+    }
+
+    fn endpoint_out_enable(&self, transfer_type: TransferType, endpoint: usize) {
+        if endpoint != 0 {
+            self.mux
+                .controller()
+                .endpoint_out_enable(transfer_type, self.endpoint(endpoint));
+        }
+    }
+
+    fn endpoint_in_out_enable(&self, transfer_type: TransferType, endpoint: usize) {
+        if endpoint != 0 {
+            self.mux
+                .controller()
+                .endpoint_in_out_enable(transfer_type, self.endpoint(endpoint));
+        }
+    }
+
+    fn endpoint_resume_in(&self, endpoint: usize) {
+        self.mux
+            .controller()
+            .endpoint_resume_in(self.endpoint(endpoint));
+    }
+
+    fn endpoint_resume_out(&self, endpoint: usize) {
+        self.mux
+            .controller()
+            .endpoint_resume_out(self.endpoint(endpoint));
+    }
+}
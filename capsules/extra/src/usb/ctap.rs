@@ -103,6 +103,8 @@ pub struct CtapHid<'a, U: 'a> {
     recv_offset: Cell<usize>,
 
     saved_endpoint: OptionalCell<usize>,
+    /// Whether the OUT endpoint is paused, waiting for the client.
+    out_delayed: Cell<bool>,
 }
 
 impl<'a, U: hil::usb::UsbController<'a>> CtapHid<'a, U> {
@@ -177,6 +179,7 @@ impl<'a, U: hil::usb::UsbController<'a>> CtapHid<'a, U> {
             recv_len: Cell::new(0),
             recv_offset: Cell::new(0),
             saved_endpoint: OptionalCell::empty(),
+            out_delayed: Cell::new(false),
         }
     }
 
@@ -233,7 +236,7 @@ impl<'a, U: hil::usb::UsbController<'a>> hil::usb_hid::UsbHid<'a, [u8; 64]> for
                 // Reset the offset
                 self.recv_offset.set(0);
             }
-        } else {
+        } else if self.out_delayed.take() {
             // If we have nothing to process, accept more data
             self.controller().endpoint_resume_out(ENDPOINT_NUM);
         }
@@ -268,7 +271,9 @@ impl<'a, U: hil::usb::UsbController<'a>> hil::usb::Client<'a> for CtapHid<'a, U>
         self.client_ctrl.attach();
     }
 
-    fn bus_reset(&'a self) {}
+    fn bus_reset(&'a self) {
+        self.out_delayed.set(false);
+    }
 
     /// Handle a Control Setup transaction.
     fn ctrl_setup(&'a self, endpoint: usize) -> hil::usb::CtrlSetupResult {
@@ -377,12 +382,14 @@ impl<'a, U: hil::usb::UsbController<'a>> hil::usb::Client<'a> for CtapHid<'a, U>
                                 self.recv_offset.set(0);
                                 // Delay the next packet until we have finished
                                 // processing this packet
+                                self.out_delayed.set(true);
                                 hil::usb::OutResult::Delay
                             } else {
                                 // We can't receive data. Record that we have data to send later
                                 // and apply back pressure to USB
                                 self.saved_endpoint.set(endpoint);
                                 self.recv_buffer.replace(buf);
+                                self.out_delayed.set(true);
                                 hil::usb::OutResult::Delay
                             }
                         } else {
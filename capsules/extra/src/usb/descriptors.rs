@@ -160,6 +160,7 @@ pub enum DescriptorType {
     DeviceQualifier,
     OtherSpeedConfiguration,
     InterfacePower,
+    InterfaceAssociation = 0x0B,
     HID = 0x21,
     Report = 0x22,
     CdcInterface = 0x24,
@@ -175,6 +176,7 @@ fn get_descriptor_type(byte: u8) -> Option<DescriptorType> {
         6 => Some(DescriptorType::DeviceQualifier),
         7 => Some(DescriptorType::OtherSpeedConfiguration),
         8 => Some(DescriptorType::InterfacePower),
+        0x0B => Some(DescriptorType::InterfaceAssociation),
         0x21 => Some(DescriptorType::HID),
         0x22 => Some(DescriptorType::Report),
         0x24 => Some(DescriptorType::CdcInterface),
@@ -403,6 +405,15 @@ pub struct DescriptorBuffer {
     pub len: usize,
 }
 
+impl Default for DescriptorBuffer {
+    fn default() -> Self {
+        DescriptorBuffer {
+            buf: core::array::from_fn(|_| Cell::default()),
+            len: 0,
+        }
+    }
+}
+
 impl DescriptorBuffer {
     pub fn write_to(&self, buf: &[Cell<u8>]) -> usize {
         for i in 0..self.len {
@@ -658,6 +669,35 @@ impl Descriptor for InterfaceDescriptor {
     }
 }
 
+/// Groups the interfaces of a function of a composite device, such as the
+/// two interfaces of CDC-ACM.
+pub struct InterfaceAssociationDescriptor {
+    pub first_interface: u8,
+    pub interface_count: u8,
+    pub function_class: u8,
+    pub function_subclass: u8,
+    pub function_protocol: u8,
+    pub string_index: u8,
+}
+
+impl Descriptor for InterfaceAssociationDescriptor {
+    fn size(&self) -> usize {
+        8
+    }
+
+    fn write_to_unchecked(&self, buf: &[Cell<u8>]) -> usize {
+        buf[0].set(8); // Size of descriptor
+        buf[1].set(DescriptorType::InterfaceAssociation as u8);
+        buf[2].set(self.first_interface);
+        buf[3].set(self.interface_count);
+        buf[4].set(self.function_class);
+        buf[5].set(self.function_subclass);
+        buf[6].set(self.function_protocol);
+        buf[7].set(self.string_index);
+        8
+    }
+}
+
 pub struct EndpointAddress(u8);
 
 impl EndpointAddress {
@@ -3,9 +3,11 @@
 // Copyright Tock Contributors 2022.
 
 pub mod cdc;
+pub mod composite;
 pub mod ctap;
 pub mod descriptors;
 pub mod keyboard_hid;
+pub mod msc;
 pub mod usb_user;
 pub mod usbc_client;
 pub mod usbc_client_ctrl;
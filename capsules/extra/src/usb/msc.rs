@@ -0,0 +1,851 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Mass Storage Class Device for USB
+//!
+//! This capsule exposes a region of a `NonvolatileStorage` to the host as a
+//! USB drive, with the SCSI transparent command set over the Bulk-Only
+//! Transport (BOT).
+//!
+//! Based on the specs available at:
+//! - <https://www.usb.org/sites/default/files/usbmassbulk_10.pdf>
+//! - SCSI Primary Commands (SPC-2) and SCSI Block Commands (SBC)
+//!
+//! The drive has a single logical unit of `BLOCK_SIZE` byte blocks. The host
+//! formats it with the file system of its choice, so the device side usually
+//! only writes raw data or a prepared image to the region.
+//!
+//! The USB HIL cannot stall bulk endpoints, so when the host and the device
+//! disagree on the length of the data of a command, the device pads the data
+//! it sends with zeros or discards the extra data it receives, and reports
+//! the difference in the residue of the command status.
+
+use core::cell::Cell;
+use core::cmp;
+
+use super::descriptors;
+use super::descriptors::Buffer64;
+use super::descriptors::EndpointAddress;
+use super::descriptors::EndpointDescriptor;
+use super::descriptors::FeatureSelector;
+use super::descriptors::InterfaceDescriptor;
+use super::descriptors::Recipient;
+use super::descriptors::RequestType;
+use super::descriptors::StandardRequest;
+use super::descriptors::TransferDirection;
+use super::usbc_client_ctrl::ClientCtrl;
+
+use kernel::hil;
+use kernel::hil::nonvolatile_storage::{NonvolatileStorage, NonvolatileStorageClient};
+use kernel::hil::usb::TransferType;
+use kernel::utilities::cells::TakeCell;
+use kernel::utilities::cells::VolatileCell;
+
+/// Identifying number for the endpoint when transferring data from us to the
+/// host.
+const ENDPOINT_IN_NUM: usize = 1;
+/// Identifying number for the endpoint when transferring data from the host to
+/// us.
+const ENDPOINT_OUT_NUM: usize = 2;
+
+const IN_BUFFER: usize = 0;
+const OUT_BUFFER: usize = 1;
+
+static LANGUAGES: &[u16; 1] = &[
+    0x0409, // English (United States)
+];
+
+/// Size of the blocks of the drive, and of the buffer the capsule needs.
+pub const BLOCK_SIZE: usize = 512;
+
+/// Class specific requests on the control endpoint.
+const GET_MAX_LUN: u8 = 0xFE;
+const BULK_ONLY_RESET: u8 = 0xFF;
+
+/// Command Block Wrapper, sent by the host to start a command.
+const CBW_SIGNATURE: u32 = 0x43425355;
+const CBW_LEN: usize = 31;
+/// Command Status Wrapper, sent by the device to end a command.
+const CSW_SIGNATURE: u32 = 0x53425355;
+const CSW_LEN: usize = 13;
+
+/// Values of the status of a command.
+const STATUS_PASSED: u8 = 0;
+const STATUS_FAILED: u8 = 1;
+const STATUS_PHASE_ERROR: u8 = 2;
+
+/// SCSI operation codes.
+const TEST_UNIT_READY: u8 = 0x00;
+const REQUEST_SENSE: u8 = 0x03;
+const INQUIRY: u8 = 0x12;
+const MODE_SENSE_6: u8 = 0x1A;
+const START_STOP_UNIT: u8 = 0x1B;
+const PREVENT_ALLOW_MEDIUM_REMOVAL: u8 = 0x1E;
+const READ_FORMAT_CAPACITIES: u8 = 0x23;
+const READ_CAPACITY_10: u8 = 0x25;
+const READ_10: u8 = 0x28;
+const WRITE_10: u8 = 0x2A;
+const VERIFY_10: u8 = 0x2F;
+const SYNCHRONIZE_CACHE_10: u8 = 0x35;
+
+/// Sense data of the last failed command, which the host fetches with
+/// REQUEST SENSE.
+#[derive(Copy, Clone)]
+struct Sense {
+    key: u8,
+    asc: u8,
+    ascq: u8,
+}
+
+impl Sense {
+    const fn new(key: u8, asc: u8, ascq: u8) -> Self {
+        Sense { key, asc, ascq }
+    }
+}
+
+const NO_SENSE: Sense = Sense::new(0x00, 0x00, 0x00);
+const UNRECOVERED_READ_ERROR: Sense = Sense::new(0x03, 0x11, 0x00);
+const WRITE_ERROR: Sense = Sense::new(0x03, 0x0C, 0x00);
+const NOT_READY: Sense = Sense::new(0x02, 0x04, 0x00);
+const INVALID_COMMAND: Sense = Sense::new(0x05, 0x20, 0x00);
+const LBA_OUT_OF_RANGE: Sense = Sense::new(0x05, 0x21, 0x00);
+const INVALID_FIELD_IN_CDB: Sense = Sense::new(0x05, 0x24, 0x00);
+const WRITE_PROTECTED: Sense = Sense::new(0x07, 0x27, 0x00);
+
+/// States of the Bulk-Only Transport.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum State {
+    /// Waiting for the host to send a command.
+    Command,
+    /// Sending the data of the command to the host.
+    DataIn,
+    /// Receiving the data of the command from the host.
+    DataOut,
+    /// Sending the status of the command to the host.
+    Status,
+}
+
+/// States of the Control Endpoint related to the mass storage class.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum CtrlState {
+    /// No ongoing ctrl transaction.
+    Idle,
+    /// Host has sent a GET_MAX_LUN request.
+    GetMaxLun,
+}
+
+/// Implementation of the USB Mass Storage Class with the Bulk-Only Transport.
+pub struct MassStorage<'a, U: 'a> {
+    /// Helper USB client library for handling many USB operations.
+    client_ctrl: ClientCtrl<'a, 'static, U>,
+
+    /// 64 byte buffers for each endpoint.
+    buffers: [Buffer64; 2],
+
+    /// Current state of the Bulk-Only Transport.
+    state: Cell<State>,
+
+    /// Current state of the Control Endpoint.
+    ctrl_state: Cell<CtrlState>,
+
+    /// The storage backing the drive.
+    storage: &'a dyn NonvolatileStorage<'a>,
+    /// The address of the drive in the storage.
+    start: usize,
+    /// The number of blocks of the drive.
+    num_blocks: u32,
+    /// Whether the host may write to the drive.
+    read_only: bool,
+    /// Product strings reported in the INQUIRY data.
+    strings: &'static [&'static str; 3],
+
+    /// Buffer for one block of the drive, which also holds the data of the
+    /// other commands. It is empty while the storage reads or writes a block.
+    block_buffer: TakeCell<'static, [u8]>,
+    /// How many bytes of `block_buffer` hold data to send to the host.
+    data_len: Cell<usize>,
+    /// Where in `block_buffer` the data phase continues.
+    data_offset: Cell<usize>,
+    /// The next block to read or write.
+    lba: Cell<u32>,
+    /// The number of blocks left to read or write.
+    blocks: Cell<u32>,
+
+    /// The tag of the command, echoed in its status.
+    tag: Cell<u32>,
+    /// The length of the data the host expects to transfer for the command.
+    expected: Cell<u32>,
+    /// Whether the data of the command flows from the device to the host.
+    direction_in: Cell<bool>,
+    /// The number of bytes transferred so far, including padding.
+    transferred: Cell<u32>,
+    /// The number of expected bytes which are not data of the command.
+    residue: Cell<u32>,
+    /// The status of the command.
+    status: Cell<u8>,
+    /// The sense data reported for the last command.
+    sense: Cell<Sense>,
+
+    /// Whether we told the controller to hold OUT packets back.
+    out_delayed: Cell<bool>,
+}
+
+impl<'a, U: hil::usb::UsbController<'a>> MassStorage<'a, U> {
+    /// Create a drive of the `length` bytes at `start` in `storage`. The
+    /// `block_buffer` must be at least `BLOCK_SIZE` bytes long.
+    pub fn new(
+        controller: &'a U,
+        max_ctrl_packet_size: u8,
+        vendor_id: u16,
+        product_id: u16,
+        strings: &'static [&'static str; 3],
+        storage: &'a dyn NonvolatileStorage<'a>,
+        start: usize,
+        length: usize,
+        block_buffer: &'static mut [u8],
+        read_only: bool,
+    ) -> Self {
+        let interfaces: &mut [InterfaceDescriptor] = &mut [InterfaceDescriptor {
+            interface_number: 0,
+            interface_class: 0x08,    // Mass Storage
+            interface_subclass: 0x06, // SCSI transparent command set
+            interface_protocol: 0x50, // Bulk-Only Transport
+            ..InterfaceDescriptor::default()
+        }];
+
+        let endpoints: &[&[EndpointDescriptor]] = &[&[
+            EndpointDescriptor {
+                endpoint_address: EndpointAddress::new_const(
+                    ENDPOINT_IN_NUM,
+                    TransferDirection::DeviceToHost,
+                ),
+                transfer_type: TransferType::Bulk,
+                max_packet_size: 64,
+                interval: 0,
+            },
+            EndpointDescriptor {
+                endpoint_address: EndpointAddress::new_const(
+                    ENDPOINT_OUT_NUM,
+                    TransferDirection::HostToDevice,
+                ),
+                transfer_type: TransferType::Bulk,
+                max_packet_size: 64,
+                interval: 0,
+            },
+        ]];
+
+        let (device_descriptor_buffer, other_descriptor_buffer) =
+            descriptors::create_descriptor_buffers(
+                descriptors::DeviceDescriptor {
+                    vendor_id,
+                    product_id,
+                    manufacturer_string: 1,
+                    product_string: 2,
+                    serial_number_string: 3,
+                    class: 0x00, // Class: defined by the interface
+                    max_packet_size_ep0: max_ctrl_packet_size,
+                    ..descriptors::DeviceDescriptor::default()
+                },
+                descriptors::ConfigurationDescriptor::default(),
+                interfaces,
+                endpoints,
+                None, // No HID descriptor
+                None, // No CDC descriptor
+            );
+
+        Self {
+            client_ctrl: ClientCtrl::new(
+                controller,
+                device_descriptor_buffer,
+                other_descriptor_buffer,
+                None, // No HID descriptor
+                None, // No report descriptor
+                LANGUAGES,
+                strings,
+            ),
+            buffers: [Buffer64::default(), Buffer64::default()],
+            state: Cell::new(State::Command),
+            ctrl_state: Cell::new(CtrlState::Idle),
+            storage,
+            start,
+            num_blocks: (length / BLOCK_SIZE) as u32,
+            read_only,
+            strings,
+            block_buffer: TakeCell::new(block_buffer),
+            data_len: Cell::new(0),
+            data_offset: Cell::new(0),
+            lba: Cell::new(0),
+            blocks: Cell::new(0),
+            tag: Cell::new(0),
+            expected: Cell::new(0),
+            direction_in: Cell::new(false),
+            transferred: Cell::new(0),
+            residue: Cell::new(0),
+            status: Cell::new(STATUS_PASSED),
+            sense: Cell::new(NO_SENSE),
+            out_delayed: Cell::new(false),
+        }
+    }
+
+    #[inline]
+    fn controller(&self) -> &'a U {
+        self.client_ctrl.controller()
+    }
+
+    /// Let the controller pass OUT packets to us again.
+    fn resume_out(&self) {
+        if self.out_delayed.take() {
+            self.controller().endpoint_resume_out(ENDPOINT_OUT_NUM);
+        }
+    }
+
+    /// Abort the current command and wait for the next one.
+    fn reset(&self) {
+        self.state.set(State::Command);
+        self.blocks.set(0);
+        // If the storage still holds the block buffer, the next command has
+        // to wait until it returns the buffer.
+        if self.block_buffer.is_some() {
+            self.resume_out();
+        }
+    }
+
+    /// Parse a Command Block Wrapper and start its command. Invalid wrappers
+    /// are ignored.
+    fn receive_command(&self, packet: &[VolatileCell<u8>], packet_bytes: usize) {
+        let get_u32 = |i: usize| {
+            u32::from_le_bytes([
+                packet[i].get(),
+                packet[i + 1].get(),
+                packet[i + 2].get(),
+                packet[i + 3].get(),
+            ])
+        };
+
+        if packet_bytes != CBW_LEN || get_u32(0) != CBW_SIGNATURE {
+            return;
+        }
+        let mut cb = [0; 16];
+        for (i, b) in cb.iter_mut().enumerate() {
+            *b = packet[15 + i].get();
+        }
+
+        self.tag.set(get_u32(4));
+        self.expected.set(get_u32(8));
+        self.direction_in.set(packet[12].get() & 0x80 != 0);
+        self.transferred.set(0);
+        self.residue.set(self.expected.get());
+        self.status.set(STATUS_PASSED);
+        self.data_len.set(0);
+        self.data_offset.set(0);
+        self.blocks.set(0);
+
+        if self.block_buffer.is_none() {
+            self.fail(NOT_READY);
+        } else {
+            self.command(&cb);
+        }
+    }
+
+    /// Run a SCSI command.
+    fn command(&self, cb: &[u8; 16]) {
+        let get_u16 = |i: usize| u16::from_be_bytes([cb[i], cb[i + 1]]) as usize;
+        let get_u32 = |i: usize| u32::from_be_bytes([cb[i], cb[i + 1], cb[i + 2], cb[i + 3]]);
+
+        // Every command clears the sense data of the previous one, which
+        // REQUEST SENSE reports.
+        let sense = self.sense.replace(NO_SENSE);
+
+        match cb[0] {
+            TEST_UNIT_READY
+            | PREVENT_ALLOW_MEDIUM_REMOVAL
+            | START_STOP_UNIT
+            | VERIFY_10
+            | SYNCHRONIZE_CACHE_10 => self.finish(),
+            REQUEST_SENSE => {
+                self.respond(cb[4] as usize, |buf| {
+                    // Fixed format sense data for the current command.
+                    buf[..18].fill(0);
+                    buf[0] = 0x70;
+                    buf[2] = sense.key;
+                    // Additional sense length
+                    buf[7] = 10;
+                    buf[12] = sense.asc;
+                    buf[13] = sense.ascq;
+                    18
+                });
+            }
+            INQUIRY => {
+                if cb[1] & 0x01 != 0 {
+                    // Vital product data pages are not supported.
+                    return self.fail(INVALID_FIELD_IN_CDB);
+                }
+                self.respond(get_u16(3), |buf| {
+                    buf[..8].copy_from_slice(&[
+                        0x00, // Direct access block device
+                        0x80, // Removable medium
+                        0x04, // SPC-2
+                        0x02, // Response data format
+                        31,   // Additional length
+                        0x00, 0x00, 0x00,
+                    ]);
+                    copy_padded(&mut buf[8..16], self.strings[0]);
+                    copy_padded(&mut buf[16..32], self.strings[1]);
+                    copy_padded(&mut buf[32..36], "1.0");
+                    36
+                });
+            }
+            MODE_SENSE_6 => {
+                let write_protect = if self.read_only { 0x80 } else { 0x00 };
+                self.respond(cb[4] as usize, |buf| {
+                    // Mode parameter header without any page.
+                    buf[..4].copy_from_slice(&[3, 0x00, write_protect, 0]);
+                    4
+                });
+            }
+            READ_FORMAT_CAPACITIES => {
+                let num_blocks = self.num_blocks.to_be_bytes();
+                let block_size = (BLOCK_SIZE as u32).to_be_bytes();
+                self.respond(get_u16(7), |buf| {
+                    buf[..4].copy_from_slice(&[0, 0, 0, 8]);
+                    buf[4..8].copy_from_slice(&num_blocks);
+                    // Formatted media.
+                    buf[8] = 0x02;
+                    buf[9..12].copy_from_slice(&block_size[1..]);
+                    12
+                });
+            }
+            READ_CAPACITY_10 => {
+                let last_lba = self.num_blocks.saturating_sub(1).to_be_bytes();
+                let block_size = (BLOCK_SIZE as u32).to_be_bytes();
+                self.respond(usize::MAX, |buf| {
+                    buf[..4].copy_from_slice(&last_lba);
+                    buf[4..8].copy_from_slice(&block_size);
+                    8
+                });
+            }
+            READ_10 | WRITE_10 => {
+                let lba = get_u32(2);
+                let blocks = get_u16(7) as u32;
+                let write = cb[0] == WRITE_10;
+                let bytes = blocks * BLOCK_SIZE as u32;
+
+                if lba
+                    .checked_add(blocks)
+                    .map_or(true, |end| end > self.num_blocks)
+                {
+                    self.fail(LBA_OUT_OF_RANGE)
+                } else if write && self.read_only {
+                    self.fail(WRITE_PROTECTED)
+                } else if bytes > 0
+                    && (self.direction_in.get() == write || self.expected.get() < bytes)
+                {
+                    self.phase_error()
+                } else {
+                    self.residue.set(self.expected.get() - bytes);
+                    self.lba.set(lba);
+                    self.blocks.set(blocks);
+                    if blocks == 0 {
+                        self.finish()
+                    } else if write {
+                        self.state.set(State::DataOut);
+                    } else {
+                        self.state.set(State::DataIn);
+                        self.read_block();
+                    }
+                }
+            }
+            _ => self.fail(INVALID_COMMAND),
+        }
+    }
+
+    /// Send the data `fill` writes in the block buffer, at most `allocation`
+    /// bytes of it.
+    fn respond<F: FnOnce(&mut [u8]) -> usize>(&self, allocation: usize, fill: F) {
+        let len = self
+            .block_buffer
+            .map_or(0, |buf| cmp::min(fill(buf), allocation));
+
+        if len == 0 {
+            self.finish()
+        } else if !self.direction_in.get() || (self.expected.get() as usize) < len {
+            self.phase_error()
+        } else {
+            self.residue.set(self.expected.get() - len as u32);
+            self.data_len.set(len);
+            self.state.set(State::DataIn);
+            self.controller().endpoint_resume_in(ENDPOINT_IN_NUM);
+        }
+    }
+
+    /// End the command with an error.
+    fn fail(&self, sense: Sense) {
+        self.sense.set(sense);
+        self.status.set(STATUS_FAILED);
+        self.finish();
+    }
+
+    /// End a command whose data phase the host and the device disagree on.
+    fn phase_error(&self) {
+        self.residue.set(self.expected.get());
+        self.status.set(STATUS_PHASE_ERROR);
+        self.finish();
+    }
+
+    /// End the data of the command. The rest of the data the host expects is
+    /// padded or discarded before sending the status.
+    fn finish(&self) {
+        self.blocks.set(0);
+        self.data_len.set(0);
+        self.data_offset.set(0);
+
+        if self.transferred.get() < self.expected.get() {
+            if self.direction_in.get() {
+                self.state.set(State::DataIn);
+                self.controller().endpoint_resume_in(ENDPOINT_IN_NUM);
+            } else {
+                self.state.set(State::DataOut);
+                self.resume_out();
+            }
+        } else {
+            self.state.set(State::Status);
+            self.controller().endpoint_resume_in(ENDPOINT_IN_NUM);
+        }
+    }
+
+    /// End the command after a storage error.
+    fn storage_error(&self, sense: Sense) {
+        self.residue
+            .set(self.expected.get() - self.transferred.get());
+        self.fail(sense);
+    }
+
+    fn block_address(&self) -> usize {
+        self.start + self.lba.get() as usize * BLOCK_SIZE
+    }
+
+    /// Read the next block to send to the host.
+    fn read_block(&self) {
+        self.block_buffer.take().map(|buf| {
+            // The storage drops the buffer if it fails to start, after which
+            // the drive is not ready anymore.
+            if self
+                .storage
+                .read(buf, self.block_address(), BLOCK_SIZE)
+                .is_err()
+            {
+                self.storage_error(UNRECOVERED_READ_ERROR);
+            }
+        });
+    }
+
+    /// Write the block received from the host.
+    fn write_block(&self) {
+        self.block_buffer.take().map(|buf| {
+            if self
+                .storage
+                .write(buf, self.block_address(), BLOCK_SIZE)
+                .is_err()
+            {
+                self.storage_error(WRITE_ERROR);
+            }
+        });
+    }
+
+    /// Send the next packet of data, or padding once the data is sent.
+    fn send_data(&self, packet: &[VolatileCell<u8>]) -> hil::usb::InResult {
+        let remaining = (self.expected.get() - self.transferred.get()) as usize;
+        let offset = self.data_offset.get();
+        let available = self.data_len.get() - offset;
+
+        if remaining == 0 || (available == 0 && self.blocks.get() > 0) {
+            // Waiting for the storage.
+            return hil::usb::InResult::Delay;
+        }
+
+        let to_send = if available > 0 {
+            cmp::min(cmp::min(packet.len(), available), remaining)
+        } else {
+            cmp::min(packet.len(), remaining)
+        };
+        if available > 0 {
+            let sent = self.block_buffer.map(|buf| {
+                for i in 0..to_send {
+                    packet[i].set(buf[offset + i]);
+                }
+            });
+            if sent.is_none() {
+                return hil::usb::InResult::Delay;
+            }
+            self.data_offset.set(offset + to_send);
+        } else {
+            for byte in &packet[..to_send] {
+                byte.set(0);
+            }
+        }
+
+        self.transferred
+            .set(self.transferred.get() + to_send as u32);
+        hil::usb::InResult::Packet(to_send)
+    }
+
+    /// Store a packet of data from the host, or discard it once the command
+    /// does not need more data.
+    fn receive_data(&self, packet: &[VolatileCell<u8>], packet_bytes: usize) {
+        let remaining = (self.expected.get() - self.transferred.get()) as usize;
+        let received = cmp::min(packet_bytes, remaining);
+        self.transferred
+            .set(self.transferred.get() + received as u32);
+
+        if self.blocks.get() == 0 {
+            if self.transferred.get() == self.expected.get() {
+                self.finish();
+            }
+            return;
+        }
+
+        let offset = self.data_offset.get();
+        let copied = self.block_buffer.map_or(0, |buf| {
+            let copied = cmp::min(received, BLOCK_SIZE - offset);
+            for i in 0..copied {
+                buf[offset + i] = packet[i].get();
+            }
+            copied
+        });
+        self.data_offset.set(offset + copied);
+
+        if self.data_offset.get() == BLOCK_SIZE {
+            self.write_block();
+        }
+    }
+
+    /// Send the Command Status Wrapper.
+    fn send_status(&self, packet: &[VolatileCell<u8>]) -> hil::usb::InResult {
+        let mut csw = [0; CSW_LEN];
+        csw[0..4].copy_from_slice(&CSW_SIGNATURE.to_le_bytes());
+        csw[4..8].copy_from_slice(&self.tag.get().to_le_bytes());
+        csw[8..12].copy_from_slice(&self.residue.get().to_le_bytes());
+        csw[12] = self.status.get();
+
+        for (i, b) in csw.iter().enumerate() {
+            packet[i].set(*b);
+        }
+        hil::usb::InResult::Packet(CSW_LEN)
+    }
+}
+
+/// Copy `s` in `buf`, padded with spaces as SCSI strings are.
+fn copy_padded(buf: &mut [u8], s: &str) {
+    for (i, b) in buf.iter_mut().enumerate() {
+        *b = s.as_bytes().get(i).copied().unwrap_or(b' ');
+    }
+}
+
+impl<'a, U: hil::usb::UsbController<'a>> hil::usb::Client<'a> for MassStorage<'a, U> {
+    fn enable(&'a self) {
+        // Set up the default control endpoint
+        self.client_ctrl.enable();
+
+        // Setup buffers for IN and OUT data transfer.
+        self.controller()
+            .endpoint_set_in_buffer(ENDPOINT_IN_NUM, &self.buffers[IN_BUFFER].buf);
+        self.controller()
+            .endpoint_in_enable(TransferType::Bulk, ENDPOINT_IN_NUM);
+
+        self.controller()
+            .endpoint_set_out_buffer(ENDPOINT_OUT_NUM, &self.buffers[OUT_BUFFER].buf);
+        self.controller()
+            .endpoint_out_enable(TransferType::Bulk, ENDPOINT_OUT_NUM);
+    }
+
+    fn attach(&'a self) {
+        self.client_ctrl.attach();
+    }
+
+    fn bus_reset(&'a self) {
+        // The controller accepts OUT packets again after a bus reset.
+        self.out_delayed.set(false);
+        self.reset();
+    }
+
+    /// Handle a Control Setup transaction.
+    ///
+    /// Mass storage adds two class requests, and relies on the host clearing
+    /// the halt of the bulk endpoints after a reset.
+    fn ctrl_setup(&'a self, endpoint: usize) -> hil::usb::CtrlSetupResult {
+        if let Some(setup_data) = descriptors::SetupData::get(&self.client_ctrl.ctrl_buffer.buf) {
+            match setup_data.request_type.request_type() {
+                RequestType::Class => match setup_data.request_code {
+                    GET_MAX_LUN => {
+                        self.ctrl_state.set(CtrlState::GetMaxLun);
+                        return hil::usb::CtrlSetupResult::Ok;
+                    }
+                    BULK_ONLY_RESET => {
+                        self.reset();
+                        return hil::usb::CtrlSetupResult::Ok;
+                    }
+                    _ => {}
+                },
+                RequestType::Standard => {
+                    if let (
+                        Recipient::Endpoint,
+                        Some(StandardRequest::ClearFeature {
+                            feature: FeatureSelector::EndpointHalt,
+                            ..
+                        }),
+                    ) = (
+                        setup_data.request_type.recipient(),
+                        setup_data.get_standard_request(),
+                    ) {
+                        // We never halt the endpoints.
+                        return hil::usb::CtrlSetupResult::Ok;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        self.client_ctrl.ctrl_setup(endpoint)
+    }
+
+    /// Handle a Control In transaction
+    fn ctrl_in(&'a self, endpoint: usize) -> hil::usb::CtrlInResult {
+        match self.ctrl_state.get() {
+            CtrlState::GetMaxLun => {
+                // We have a single logical unit.
+                self.client_ctrl.ctrl_buffer.buf[0].set(0);
+                hil::usb::CtrlInResult::Packet(1, true)
+            }
+            CtrlState::Idle => self.client_ctrl.ctrl_in(endpoint),
+        }
+    }
+
+    /// Handle a Control Out transaction
+    fn ctrl_out(&'a self, endpoint: usize, packet_bytes: u32) -> hil::usb::CtrlOutResult {
+        self.client_ctrl.ctrl_out(endpoint, packet_bytes)
+    }
+
+    fn ctrl_status(&'a self, endpoint: usize) {
+        self.client_ctrl.ctrl_status(endpoint)
+    }
+
+    /// Handle the completion of a Control transfer
+    fn ctrl_status_complete(&'a self, endpoint: usize) {
+        self.ctrl_state.set(CtrlState::Idle);
+        self.client_ctrl.ctrl_status_complete(endpoint)
+    }
+
+    /// Handle a Bulk IN transaction.
+    ///
+    /// This sends the data of the command, then its status.
+    fn packet_in(&'a self, transfer_type: TransferType, _endpoint: usize) -> hil::usb::InResult {
+        match transfer_type {
+            TransferType::Bulk => {
+                let packet = &self.buffers[IN_BUFFER].buf;
+                match self.state.get() {
+                    State::DataIn => self.send_data(packet),
+                    State::Status => self.send_status(packet),
+                    State::Command | State::DataOut => hil::usb::InResult::Delay,
+                }
+            }
+            TransferType::Control | TransferType::Interrupt | TransferType::Isochronous => {
+                panic!("Transfer protocol not supported by mass storage");
+            }
+        }
+    }
+
+    /// Handle a Bulk OUT transaction.
+    ///
+    /// This receives commands and their data. Further packets are held back
+    /// while the storage is busy or a command sends data or status.
+    fn packet_out(
+        &'a self,
+        transfer_type: TransferType,
+        _endpoint: usize,
+        packet_bytes: u32,
+    ) -> hil::usb::OutResult {
+        match transfer_type {
+            TransferType::Bulk => {
+                let packet = &self.buffers[OUT_BUFFER].buf;
+                match self.state.get() {
+                    State::Command => self.receive_command(packet, packet_bytes as usize),
+                    State::DataOut => self.receive_data(packet, packet_bytes as usize),
+                    // The host does not send anything before the status.
+                    State::DataIn | State::Status => {}
+                }
+
+                let ready = matches!(self.state.get(), State::Command | State::DataOut)
+                    && self.block_buffer.is_some();
+                if ready {
+                    hil::usb::OutResult::Ok
+                } else {
+                    self.out_delayed.set(true);
+                    hil::usb::OutResult::Delay
+                }
+            }
+            TransferType::Control | TransferType::Interrupt | TransferType::Isochronous => {
+                panic!("Transfer protocol not supported by mass storage");
+            }
+        }
+    }
+
+    fn packet_transmitted(&'a self, _endpoint: usize) {
+        match self.state.get() {
+            State::DataIn => {
+                if self.transferred.get() == self.expected.get() {
+                    self.state.set(State::Status);
+                    self.controller().endpoint_resume_in(ENDPOINT_IN_NUM);
+                } else if self.data_offset.get() == self.data_len.get() && self.blocks.get() > 0 {
+                    self.read_block();
+                } else {
+                    self.controller().endpoint_resume_in(ENDPOINT_IN_NUM);
+                }
+            }
+            State::Status => {
+                self.state.set(State::Command);
+                self.resume_out();
+            }
+            State::Command | State::DataOut => {}
+        }
+    }
+}
+
+impl<'a, U: hil::usb::UsbController<'a>> NonvolatileStorageClient for MassStorage<'a, U> {
+    fn read_done(&self, buffer: &'static mut [u8], _length: usize) {
+        self.block_buffer.replace(buffer);
+
+        match self.state.get() {
+            State::DataIn if self.blocks.get() > 0 => {
+                self.lba.set(self.lba.get() + 1);
+                self.blocks.set(self.blocks.get() - 1);
+                self.data_len.set(BLOCK_SIZE);
+                self.data_offset.set(0);
+                self.controller().endpoint_resume_in(ENDPOINT_IN_NUM);
+            }
+            // The command was reset while reading.
+            State::Command => self.resume_out(),
+            _ => {}
+        }
+    }
+
+    fn write_done(&self, buffer: &'static mut [u8], _length: usize) {
+        self.block_buffer.replace(buffer);
+
+        match self.state.get() {
+            State::DataOut if self.blocks.get() > 0 => {
+                self.lba.set(self.lba.get() + 1);
+                self.blocks.set(self.blocks.get() - 1);
+                self.data_offset.set(0);
+                if self.blocks.get() == 0 {
+                    self.finish();
+                } else {
+                    self.resume_out();
+                }
+            }
+            // The command was reset while writing.
+            State::Command => self.resume_out(),
+            _ => {}
+        }
+    }
+}
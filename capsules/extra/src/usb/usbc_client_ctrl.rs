@@ -39,6 +39,7 @@ use core::cmp::min;
 
 use kernel::hil;
 use kernel::hil::usb::TransferType;
+use kernel::utilities::cells::MapCell;
 
 const DESCRIPTOR_BUFLEN: usize = 128;
 
@@ -66,7 +67,7 @@ pub struct ClientCtrl<'a, 'b, U: 'a> {
 
     /// Buffer containing the byte-serialized representation of the configuration
     /// descriptor and all other descriptors for this device.
-    other_descriptor_buffer: DescriptorBuffer,
+    other_descriptor_buffer: MapCell<DescriptorBuffer>,
 
     /// An optional HID descriptor for the configuration. This can be requested
     /// separately. It must also be included in `other_descriptor_buffer` if it exists.
@@ -153,7 +154,7 @@ impl<'a, 'b, U: hil::usb::UsbController<'a>> ClientCtrl<'a, 'b, U> {
             ],
             ctrl_buffer: Buffer64::default(),
             device_descriptor_buffer,
-            other_descriptor_buffer,
+            other_descriptor_buffer: MapCell::new(other_descriptor_buffer),
             hid_descriptor,
             report_descriptor,
             language,
@@ -171,6 +172,13 @@ impl<'a, 'b, U: hil::usb::UsbController<'a>> ClientCtrl<'a, 'b, U> {
         &self.descriptor_storage
     }
 
+    /// Replace the configuration descriptor and the descriptors following it,
+    /// for devices which only know them once their functions are enabled.
+    pub fn set_other_descriptor_buffer(&self, other_descriptor_buffer: DescriptorBuffer) {
+        self.other_descriptor_buffer
+            .replace(other_descriptor_buffer);
+    }
+
     pub fn enable(&'a self) {
         // Set up the default control endpoint
         self.controller
@@ -259,7 +267,9 @@ impl<'a, 'b, U: hil::usb::UsbController<'a>> ClientCtrl<'a, 'b, U> {
                     DescriptorType::Configuration => match descriptor_index {
                         0 => {
                             let buf = self.descriptor_buf();
-                            let len = self.other_descriptor_buffer.write_to(buf);
+                            let len = self
+                                .other_descriptor_buffer
+                                .map_or(0, |descriptors| descriptors.write_to(buf));
 
                             let end = min(len, requested_length as usize);
                             self.state[endpoint].set(State::CtrlIn(0, end));
@@ -22,6 +22,11 @@ use tock_tbf::types::TbfFooterV2CredentialsType;
 /// This assumes the `TbfFooterV2CredentialsType` data format only contains the
 /// signature (i.e. the data length of the credential in the TBF footer is the
 /// same as `SL`).
+///
+/// For example, apps signed with ECDSA over NIST P-256 are accepted by
+/// instantiating this checker with a SHA-256 hasher, the
+/// `capsules_extra::ecdsa_p256` verifier (`HL = 32`, `SL = 64`) and
+/// `TbfFooterV2CredentialsType::EcdsaNistP256`.
 pub struct AppCheckerSignature<
     'a,
     S: hil::public_key_crypto::signature::SignatureVerify<'static, HL, SL>,
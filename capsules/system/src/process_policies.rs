@@ -8,6 +8,7 @@
 //! managing processes. For example, these policies control decisions such as
 //! whether a specific process should be restarted.
 
+use kernel::core_dump::CoreDumpWriter;
 use kernel::process;
 use kernel::process::Process;
 use kernel::process::ProcessFaultPolicy;
@@ -45,6 +46,32 @@ impl ProcessFaultPolicy for StopWithDebugFaultPolicy {
     }
 }
 
+/// Stop the process if it faults and keep it stopped, and emit its core dump
+/// through a `CoreDumpWriter`, for example the process console. The dump
+/// holds the process identity, its stored state and its RAM, see
+/// `kernel::core_dump`.
+pub struct CoreDumpFaultPolicy<'a> {
+    writer: &'a dyn CoreDumpWriter,
+}
+
+impl<'a> CoreDumpFaultPolicy<'a> {
+    pub const fn new(writer: &'a dyn CoreDumpWriter) -> CoreDumpFaultPolicy<'a> {
+        CoreDumpFaultPolicy { writer }
+    }
+}
+
+impl ProcessFaultPolicy for CoreDumpFaultPolicy<'_> {
+    fn action(&self, process: &dyn Process) -> process::FaultAction {
+        kernel::debug!(
+            "Process {} faulted and was stopped, writing its core dump of {} bytes.",
+            process.get_process_name(),
+            kernel::core_dump::len(process)
+        );
+        self.writer.write_core_dump(process);
+        process::FaultAction::Stop
+    }
+}
+
 /// Always restart the process if it faults.
 pub struct RestartFaultPolicy {}
 
@@ -0,0 +1,159 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Alarm on the monotonic clock of the host.
+//!
+//! The counter runs at 1 MHz from the creation of the alarm and wraps around
+//! after 32 bits, about every 71 minutes. A thread waits for the alarm to
+//! expire and raises the alarm interrupt.
+
+use core::cell::Cell;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use kernel::hil::time::{self, Freq1MHz, Ticks, Ticks32};
+use kernel::utilities::cells::OptionalCell;
+use kernel::ErrorCode;
+
+use crate::interrupts;
+
+/// When the timer thread raises the interrupt next, if it does.
+type Deadline = Arc<(Mutex<Option<Instant>>, Condvar)>;
+
+pub struct Alarm<'a> {
+    epoch: Instant,
+    client: OptionalCell<&'a dyn time::AlarmClient>,
+    reference: Cell<Ticks32>,
+    dt: Cell<Ticks32>,
+    armed: Cell<bool>,
+    deadline: Deadline,
+    timer_started: Cell<bool>,
+}
+
+impl<'a> Alarm<'a> {
+    pub fn new() -> Self {
+        Self {
+            epoch: Instant::now(),
+            client: OptionalCell::empty(),
+            reference: Cell::new(Ticks32::from(0)),
+            dt: Cell::new(Ticks32::from(0)),
+            armed: Cell::new(false),
+            deadline: Arc::new((Mutex::new(None), Condvar::new())),
+            timer_started: Cell::new(false),
+        }
+    }
+
+    fn start_timer(&self) {
+        if self.timer_started.replace(true) {
+            return;
+        }
+        let deadline = self.deadline.clone();
+        thread::Builder::new()
+            .name("alarm".into())
+            .spawn(move || {
+                let (lock, changed) = &*deadline;
+                let mut deadline = lock.lock().unwrap();
+                loop {
+                    match *deadline {
+                        None => deadline = changed.wait(deadline).unwrap(),
+                        Some(at) => {
+                            let now = Instant::now();
+                            if now >= at {
+                                *deadline = None;
+                                interrupts::raise(interrupts::ALARM);
+                            } else {
+                                deadline = changed.wait_timeout(deadline, at - now).unwrap().0;
+                            }
+                        }
+                    }
+                }
+            })
+            .expect("alarm: failed to start the timer thread");
+    }
+
+    /// Tells the timer thread when the alarm expires, or that it does not.
+    fn set_deadline(&self, deadline: Option<Instant>) {
+        let (lock, changed) = &*self.deadline;
+        *lock.lock().unwrap() = deadline;
+        changed.notify_one();
+    }
+
+    /// Returns the ticks left until the alarm expires, zero if it expired.
+    fn remaining(&self) -> u32 {
+        let reference = self.reference.get();
+        let expiration = reference.wrapping_add(self.dt.get());
+        let now = time::Time::now(self);
+        if now.within_range(reference, expiration) {
+            expiration.wrapping_sub(now).into_u32()
+        } else {
+            0
+        }
+    }
+
+    pub fn handle_interrupt(&self) {
+        if !self.armed.get() {
+            return;
+        }
+        match self.remaining() {
+            0 => {
+                self.armed.set(false);
+                self.client.map(|client| client.alarm());
+            }
+            // The interrupt was raised for an earlier alarm.
+            remaining => self.set_deadline(Some(
+                Instant::now() + Duration::from_micros(remaining.into()),
+            )),
+        }
+    }
+}
+
+impl<'a> Default for Alarm<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl time::Time for Alarm<'_> {
+    type Frequency = Freq1MHz;
+    type Ticks = Ticks32;
+
+    fn now(&self) -> Ticks32 {
+        Ticks32::from(self.epoch.elapsed().as_micros() as u32)
+    }
+}
+
+impl<'a> time::Alarm<'a> for Alarm<'a> {
+    fn set_alarm_client(&self, client: &'a dyn time::AlarmClient) {
+        self.client.set(client);
+    }
+
+    fn set_alarm(&self, reference: Ticks32, dt: Ticks32) {
+        self.reference.set(reference);
+        self.dt.set(dt);
+        self.armed.set(true);
+        self.start_timer();
+        self.set_deadline(Some(
+            Instant::now() + Duration::from_micros(self.remaining().into()),
+        ));
+    }
+
+    fn get_alarm(&self) -> Ticks32 {
+        self.reference.get().wrapping_add(self.dt.get())
+    }
+
+    fn disarm(&self) -> Result<(), ErrorCode> {
+        self.armed.set(false);
+        self.set_deadline(None);
+        Ok(())
+    }
+
+    fn is_armed(&self) -> bool {
+        self.armed.get()
+    }
+
+    fn minimum_dt(&self) -> Ticks32 {
+        Ticks32::from(1)
+    }
+}
@@ -0,0 +1,463 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Applications running on host threads.
+//!
+//! An application is a function taking an [`App`], linked into the same
+//! executable as the kernel. [`Image`] builds TBF images holding such
+//! functions, which the kernel loads like applications in flash. When the
+//! kernel starts a process, its function is called on a new thread and makes
+//! system calls through the `App`. When the function returns, the process
+//! exits.
+//!
+//! System calls are passed to the kernel as `Syscall` values and return
+//! `SyscallReturn` values, so they are not limited by the width of registers.
+//! Memop calls return addresses as `u32`, which truncates them on a 64 bit
+//! host; [`App::memory`] returns the bounds of the process memory instead.
+//!
+//! Buffers shared with the kernel must be in the memory of the process, so
+//! they are allocated with [`App::alloc`]. Upcalls are functions of the
+//! application as well, and each one subscribed is given a slot in the
+//! memory of the process, which the kernel sees as the address of the upcall.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! fn blink(app: &App) {
+//!     loop {
+//!         app.command(0x2, 1, 0, 0);
+//!         app.yield_wait();
+//!     }
+//! }
+//!
+//! let apps = Image::new().add("blink", blink, 8192).into_flash();
+//! ```
+
+use core::cell::{Cell, RefCell};
+use std::panic;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Once;
+use std::thread;
+
+use kernel::process::FunctionCall;
+use kernel::syscall::{Syscall, SyscallReturn};
+
+use crate::syscall::{ProcessThread, Resume, Trap};
+
+/// Signature of an upcall: the application, the three arguments passed by
+/// the capsule and the data passed to `subscribe`.
+pub type Upcall = fn(&App, usize, usize, usize, usize);
+
+/// Marks the binary of the images built by [`Image`], before the entry
+/// point.
+const MAGIC: [u8; 8] = *b"TockHost";
+const BINARY_LEN: usize = MAGIC.len() + 8;
+
+/// Space at the start of the process memory for the flag set by
+/// `yield_no_wait`.
+const YIELD_FLAG_LEN: usize = 8;
+
+/// Maximum number of different upcall functions a process subscribes.
+const MAX_UPCALLS: usize = 32;
+
+thread_local! {
+    /// Where the thread of a process reports a fault.
+    static FAULT: RefCell<Option<Sender<Trap>>> = const { RefCell::new(None) };
+}
+
+static PANIC_HOOK: Once = Once::new();
+
+/// Blocks the calling thread forever, once the kernel does not run the
+/// process anymore.
+fn park_forever() -> ! {
+    loop {
+        thread::park();
+    }
+}
+
+/// Starts the thread of a process, whose entry point is `entry`. Returns
+/// `None` if the entry point is not in an image built by [`Image`].
+pub(crate) fn start(entry: FunctionCall) -> Option<ProcessThread> {
+    // SAFETY: The kernel only calls functions in the flash or memory of the
+    // process, and the binary of images built by `Image` holds the magic
+    // followed by the address of the application function.
+    let function = unsafe {
+        let binary = core::slice::from_raw_parts(entry.pc as *const u8, BINARY_LEN);
+        if binary[..MAGIC.len()] != MAGIC {
+            return None;
+        }
+        let address = usize::from_le_bytes(binary[MAGIC.len()..].try_into().ok()?);
+        core::mem::transmute::<usize, fn(&App)>(address)
+    };
+
+    PANIC_HOOK.call_once(|| {
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            default_hook(info);
+            // A panic on the thread of a process faults the process instead
+            // of aborting the kernel.
+            if let Some(fault) = FAULT.with(|fault| fault.borrow_mut().take()) {
+                let _ = fault.send(Trap::Fault);
+                park_forever();
+            }
+        }));
+    });
+
+    let (resume_sender, resume) = mpsc::channel();
+    let (trap, trap_receiver) = mpsc::channel();
+    let app = App {
+        resume,
+        trap,
+        app_start: entry.argument0,
+        memory_start: entry.argument1,
+        memory_len: entry.argument2,
+        app_brk: Cell::new(entry.argument3),
+        heap: Cell::new(entry.argument1),
+        upcalls: RefCell::new(Vec::new()),
+    };
+    thread::Builder::new()
+        .name(format!("process@{:#x}", entry.argument1))
+        .spawn(move || {
+            FAULT.with(|fault| fault.replace(Some(app.trap.clone())));
+            // The flag set by `yield_no_wait` is the first allocation, at
+            // the start of the process memory.
+            app.alloc(YIELD_FLAG_LEN);
+            function(&app);
+            app.exit_terminate(0);
+        })
+        .ok()?;
+
+    Some(ProcessThread {
+        resume: resume_sender,
+        trap: trap_receiver,
+    })
+}
+
+/// A buffer in the memory of the process, which can be shared with the
+/// kernel.
+///
+/// The kernel may access shared buffers while the process is in a system
+/// call, so their contents are copied in and out rather than borrowed.
+#[derive(Clone, Copy, Debug)]
+pub struct Buffer {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl Buffer {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Copies `data` to the start of the buffer. Panics if it does not fit.
+    pub fn write(&self, data: &[u8]) {
+        assert!(data.len() <= self.len, "data does not fit in the buffer");
+        // SAFETY: The buffer is in the memory of the process, which the
+        // kernel does not access while the process runs.
+        unsafe { core::ptr::copy_nonoverlapping(data.as_ptr(), self.ptr, data.len()) };
+    }
+
+    /// Returns a copy of the first `len` bytes of the buffer.
+    pub fn read(&self, len: usize) -> Vec<u8> {
+        let len = core::cmp::min(len, self.len);
+        // SAFETY: As for `write`.
+        unsafe { core::slice::from_raw_parts(self.ptr, len).to_vec() }
+    }
+}
+
+/// The interface of an application to the kernel.
+pub struct App {
+    resume: Receiver<Resume>,
+    trap: Sender<Trap>,
+    app_start: usize,
+    memory_start: usize,
+    memory_len: usize,
+    app_brk: Cell<usize>,
+    /// Start of the memory not allocated yet.
+    heap: Cell<usize>,
+    /// Upcall functions subscribed so far and the address of their slot.
+    upcalls: RefCell<Vec<(Upcall, usize)>>,
+}
+
+impl App {
+    /// Address of the application binary in flash.
+    pub fn app_start(&self) -> usize {
+        self.app_start
+    }
+
+    /// Start and length of the memory of the process.
+    pub fn memory(&self) -> (usize, usize) {
+        (self.memory_start, self.memory_len)
+    }
+
+    /// Allocates a zeroed buffer of `len` bytes in the memory of the
+    /// process, moving the application break up as needed. Panics, and so
+    /// faults the process, if the memory is exhausted.
+    pub fn alloc(&self, len: usize) -> Buffer {
+        let start = self.heap.get().next_multiple_of(8);
+        if start + len > self.app_brk.get() {
+            assert!(
+                matches!(self.memop(0, start + len), SyscallReturn::Success),
+                "process memory exhausted allocating {} bytes",
+                len
+            );
+            self.app_brk.set(start + len);
+        }
+        self.heap.set(start + len);
+        let buffer = Buffer {
+            ptr: start as *mut u8,
+            len,
+        };
+        // SAFETY: The memory belongs to the process and was not handed out.
+        unsafe { core::ptr::write_bytes(buffer.ptr, 0, len) };
+        buffer
+    }
+
+    /// Passes `syscall` to the kernel and returns its return value, or `None`
+    /// for a yield, which has none.
+    fn syscall(&self, syscall: Syscall) -> Option<SyscallReturn> {
+        if self.trap.send(Trap::Syscall(syscall)).is_err() {
+            park_forever();
+        }
+        match self.resume.recv() {
+            Ok(Resume::Return(value)) => Some(value),
+            Ok(Resume::Continue) => None,
+            Ok(Resume::Call(call)) => {
+                let upcall = self
+                    .upcalls
+                    .borrow()
+                    .iter()
+                    .find(|(_, slot)| *slot == call.pc)
+                    .map(|(upcall, _)| *upcall);
+                match upcall {
+                    Some(upcall) => upcall(
+                        self,
+                        call.argument0,
+                        call.argument1,
+                        call.argument2,
+                        call.argument3,
+                    ),
+                    None => panic!("call to unknown function {:#x}", call.pc),
+                }
+                None
+            }
+            // The kernel stopped or restarted the process.
+            Err(_) => park_forever(),
+        }
+    }
+
+    /// Calls `syscall`, which always returns a value.
+    fn syscall_value(&self, syscall: Syscall) -> SyscallReturn {
+        match self.syscall(syscall) {
+            Some(value) => value,
+            None => panic!("no return value for {:?}", syscall),
+        }
+    }
+
+    /// Returns the slot of `upcall`, giving it one if it has none yet.
+    fn upcall_slot(&self, upcall: Upcall) -> usize {
+        let slot = self
+            .upcalls
+            .borrow()
+            .iter()
+            .find(|(function, _)| *function as usize == upcall as usize)
+            .map(|(_, slot)| *slot);
+        slot.unwrap_or_else(|| {
+            assert!(
+                self.upcalls.borrow().len() < MAX_UPCALLS,
+                "too many upcall functions"
+            );
+            let slot = self.alloc(core::mem::size_of::<usize>());
+            slot.write(&(upcall as usize).to_le_bytes());
+            self.upcalls.borrow_mut().push((upcall, slot.ptr as usize));
+            slot.ptr as usize
+        })
+    }
+
+    pub fn command(
+        &self,
+        driver: usize,
+        command: usize,
+        arg0: usize,
+        arg1: usize,
+    ) -> SyscallReturn {
+        self.syscall_value(Syscall::Command {
+            driver_number: driver,
+            subdriver_number: command,
+            arg0,
+            arg1,
+        })
+    }
+
+    /// Subscribes `upcall` to upcall `subscribe` of `driver`, or unsubscribes
+    /// if it is `None`.
+    pub fn subscribe(
+        &self,
+        driver: usize,
+        subscribe: usize,
+        upcall: Option<Upcall>,
+        appdata: usize,
+    ) -> SyscallReturn {
+        let upcall_ptr = upcall.map_or(0, |upcall| self.upcall_slot(upcall));
+        self.syscall_value(Syscall::Subscribe {
+            driver_number: driver,
+            subdriver_number: subscribe,
+            upcall_ptr: upcall_ptr as *mut (),
+            appdata,
+        })
+    }
+
+    /// Shares `buffer` with `driver` for reading and writing, or stops
+    /// sharing if it is `None`.
+    pub fn allow_rw(&self, driver: usize, allow: usize, buffer: Option<&Buffer>) -> SyscallReturn {
+        self.syscall_value(Syscall::ReadWriteAllow {
+            driver_number: driver,
+            subdriver_number: allow,
+            allow_address: buffer.map_or(core::ptr::null_mut(), |buffer| buffer.ptr),
+            allow_size: buffer.map_or(0, |buffer| buffer.len),
+        })
+    }
+
+    /// Shares `buffer` with `driver` for reading, or stops sharing if it is
+    /// `None`.
+    pub fn allow_ro(&self, driver: usize, allow: usize, buffer: Option<&Buffer>) -> SyscallReturn {
+        self.syscall_value(Syscall::ReadOnlyAllow {
+            driver_number: driver,
+            subdriver_number: allow,
+            allow_address: buffer.map_or(core::ptr::null(), |buffer| buffer.ptr),
+            allow_size: buffer.map_or(0, |buffer| buffer.len),
+        })
+    }
+
+    pub fn memop(&self, operand: usize, arg0: usize) -> SyscallReturn {
+        self.syscall_value(Syscall::Memop { operand, arg0 })
+    }
+
+    /// Waits for an upcall and runs it.
+    pub fn yield_wait(&self) {
+        self.syscall(Syscall::Yield {
+            which: 1,
+            param_a: 0,
+            param_b: 0,
+        });
+    }
+
+    /// Runs a pending upcall, if there is one. Returns whether one ran.
+    pub fn yield_no_wait(&self) -> bool {
+        let flag = self.memory_start as *mut u8;
+        self.syscall(Syscall::Yield {
+            which: 0,
+            param_a: flag as usize,
+            param_b: 0,
+        });
+        // SAFETY: The flag is reserved at the start of the process memory.
+        unsafe { flag.read() != 0 }
+    }
+
+    /// Waits for upcall `subscribe` of `driver` and returns its arguments,
+    /// without running the subscribed upcall.
+    pub fn yield_wait_for(&self, driver: usize, subscribe: usize) -> (usize, usize, usize) {
+        match self.syscall(Syscall::Yield {
+            which: 2,
+            param_a: driver,
+            param_b: subscribe,
+        }) {
+            Some(SyscallReturn::YieldWaitFor(a, b, c)) => (a, b, c),
+            value => panic!("unexpected yield-wait-for return {:?}", value),
+        }
+    }
+
+    pub fn exit_terminate(&self, completion_code: usize) -> ! {
+        self.syscall(Syscall::Exit {
+            which: 0,
+            completion_code,
+        });
+        // The kernel does not resume a terminated process.
+        park_forever();
+    }
+
+    pub fn exit_restart(&self, completion_code: usize) -> ! {
+        self.syscall(Syscall::Exit {
+            which: 1,
+            completion_code,
+        });
+        park_forever();
+    }
+}
+
+/// Builds TBF images of applications, to load as the application flash.
+pub struct Image {
+    bytes: Vec<u8>,
+}
+
+impl Image {
+    pub fn new() -> Self {
+        Self { bytes: Vec::new() }
+    }
+
+    /// Adds the application `name` running `function`, for which the kernel
+    /// allocates `minimum_ram_size` bytes of memory. The memory holds the
+    /// buffers of the application and the grants of the kernel, but not its
+    /// stack, which is the stack of its thread.
+    pub fn add(mut self, name: &str, function: fn(&App), minimum_ram_size: u32) -> Self {
+        const TBF_HEADER_MAIN: u16 = 1;
+        const TBF_HEADER_PACKAGE_NAME: u16 = 3;
+        const TBF_HEADER_KERNEL_VERSION: u16 = 8;
+        const FLAG_ENABLED: u32 = 1;
+
+        let name_len = name.len().next_multiple_of(4);
+        let header_len = 16 + 4 + 12 + 4 + name_len + 4 + 4;
+        let total_len = header_len + BINARY_LEN;
+
+        let mut header = Vec::with_capacity(header_len);
+        header.extend(2u16.to_le_bytes());
+        header.extend((header_len as u16).to_le_bytes());
+        header.extend((total_len as u32).to_le_bytes());
+        header.extend(FLAG_ENABLED.to_le_bytes());
+        header.extend(0u32.to_le_bytes());
+        // The init function is at the start of the binary.
+        header.extend(TBF_HEADER_MAIN.to_le_bytes());
+        header.extend(12u16.to_le_bytes());
+        header.extend(0u32.to_le_bytes());
+        header.extend(0u32.to_le_bytes());
+        header.extend(minimum_ram_size.to_le_bytes());
+        header.extend(TBF_HEADER_PACKAGE_NAME.to_le_bytes());
+        header.extend((name.len() as u16).to_le_bytes());
+        header.extend(name.as_bytes());
+        header.resize(header_len - 8, 0);
+        // The application is built with the kernel.
+        header.extend(TBF_HEADER_KERNEL_VERSION.to_le_bytes());
+        header.extend(4u16.to_le_bytes());
+        header.extend(kernel::KERNEL_MAJOR_VERSION.to_le_bytes());
+        header.extend(kernel::KERNEL_MINOR_VERSION.to_le_bytes());
+
+        let checksum = header
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+            .fold(0, |checksum, word| checksum ^ word);
+        header[12..16].copy_from_slice(&checksum.to_le_bytes());
+
+        self.bytes.extend(header);
+        self.bytes.extend(MAGIC);
+        self.bytes.extend((function as usize).to_le_bytes());
+        self
+    }
+
+    /// Returns the images, to pass to the process loader as the application
+    /// flash.
+    pub fn into_flash(self) -> &'static [u8] {
+        self.bytes.leak()
+    }
+}
+
+impl Default for Image {
+    fn default() -> Self {
+        Self::new()
+    }
+}
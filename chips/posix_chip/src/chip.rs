@@ -0,0 +1,125 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! High-level setup and interrupt mapping for the chip.
+
+use core::fmt::Write;
+
+use kernel::debug;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::platform::chip::{Chip, InterruptService};
+
+use crate::interrupts;
+
+pub struct Posix<'a, I: InterruptService + 'a> {
+    userspace_kernel_boundary: crate::syscall::SysCall,
+    interrupt_service: &'a I,
+}
+
+pub struct PosixDefaultPeripherals<'a> {
+    pub uart: crate::uart::Uart<'a>,
+    pub alarm: crate::alarm::Alarm<'a>,
+    pub flash: crate::flash::Flash,
+    pub ethernet: crate::ethernet::Ethernet<'a>,
+}
+
+impl<'a> PosixDefaultPeripherals<'a> {
+    pub fn new() -> Self {
+        Self {
+            uart: crate::uart::Uart::new(),
+            alarm: crate::alarm::Alarm::new(),
+            flash: crate::flash::Flash::new(),
+            ethernet: crate::ethernet::Ethernet::new(),
+        }
+    }
+
+    // Necessary for setting up circular dependencies and registering deferred
+    // calls
+    pub fn init(&'static self) {
+        self.uart.register();
+        self.flash.register();
+        self.ethernet.register();
+    }
+}
+
+impl<'a> Default for PosixDefaultPeripherals<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> InterruptService for PosixDefaultPeripherals<'a> {
+    unsafe fn service_interrupt(&self, interrupt: u32) -> bool {
+        match interrupt {
+            interrupts::UART => self.uart.handle_interrupt(),
+            interrupts::ALARM => self.alarm.handle_interrupt(),
+            interrupts::ETHERNET => self.ethernet.handle_interrupt(),
+            _ => return false,
+        }
+        true
+    }
+}
+
+impl<'a, I: InterruptService + 'a> Posix<'a, I> {
+    pub fn new(interrupt_service: &'a I) -> Self {
+        Self {
+            userspace_kernel_boundary: crate::syscall::SysCall::new(),
+            interrupt_service,
+        }
+    }
+}
+
+impl<'a, I: InterruptService + 'a> Chip for Posix<'a, I> {
+    // Processes share the address space of the kernel.
+    type MPU = ();
+    type UserspaceKernelBoundary = crate::syscall::SysCall;
+
+    fn mpu(&self) -> &Self::MPU {
+        &()
+    }
+
+    fn userspace_kernel_boundary(&self) -> &crate::syscall::SysCall {
+        &self.userspace_kernel_boundary
+    }
+
+    fn service_pending_interrupts(&self) {
+        loop {
+            let pending = interrupts::take();
+            if pending == 0 {
+                break;
+            }
+            for interrupt in 0..u32::BITS {
+                if pending & (1 << interrupt) != 0
+                    && !unsafe { self.interrupt_service.service_interrupt(interrupt) }
+                {
+                    debug!("Unhandled interrupt {}", interrupt);
+                }
+            }
+        }
+    }
+
+    fn has_pending_interrupts(&self) -> bool {
+        interrupts::is_pending()
+    }
+
+    fn sleep(&self) {
+        interrupts::wait();
+    }
+
+    unsafe fn atomic<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        // Interrupts are only serviced on the kernel thread, so they cannot
+        // preempt `f`.
+        f()
+    }
+
+    unsafe fn print_state(&self, writer: &mut dyn Write) {
+        let _ = writer.write_fmt(format_args!(
+            "\r\n---| POSIX host |---\r\n Process ID: {}\r\n",
+            std::process::id()
+        ));
+    }
+}
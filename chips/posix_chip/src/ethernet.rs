@@ -0,0 +1,151 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Ethernet adapter over a TCP connection.
+//!
+//! Frames are exchanged with the peer prefixed by their length as a 32 bit
+//! big endian integer, which is the protocol of QEMU's socket network
+//! backend. Connected to the hub of `tools/ieee802154_hub.py`, the adapter
+//! carries the simulated 802.15.4 radio of
+//! `capsules_extra::ieee802154::ethernet_radio`, so that the kernel joins
+//! the same PAN as QEMU boards connected to the hub.
+//!
+//! Frames are sent synchronously and the transmission completes from a
+//! deferred call. A thread reads the frames from the connection and raises
+//! the Ethernet interrupt.
+
+use core::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::ethernet::{EthernetAdapter, EthernetAdapterClient, MAX_FRAME_LEN};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+use crate::interrupts;
+
+pub struct Ethernet<'a> {
+    client: OptionalCell<&'a dyn EthernetAdapterClient>,
+    stream: RefCell<Option<TcpStream>>,
+    receive_enabled: Cell<bool>,
+    /// Frames read from the connection and not delivered yet.
+    received: Arc<Mutex<VecDeque<Vec<u8>>>>,
+    tx_frame: TakeCell<'static, [u8]>,
+    tx_result: Cell<Result<(), ErrorCode>>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a> Ethernet<'a> {
+    pub fn new() -> Self {
+        Self {
+            client: OptionalCell::empty(),
+            stream: RefCell::new(None),
+            receive_enabled: Cell::new(false),
+            received: Arc::new(Mutex::new(VecDeque::new())),
+            tx_frame: TakeCell::empty(),
+            tx_result: Cell::new(Ok(())),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    /// Connects to the peer at `address` and starts reading frames from it.
+    pub fn connect<A: ToSocketAddrs>(&self, address: A) -> io::Result<()> {
+        let stream = TcpStream::connect(address)?;
+        stream.set_nodelay(true)?;
+        let mut reader = stream.try_clone()?;
+        let received = self.received.clone();
+        thread::Builder::new()
+            .name("ethernet".into())
+            .spawn(move || {
+                let mut len = [0; 4];
+                // Reading stops when the peer closes the connection.
+                while reader.read_exact(&mut len).is_ok() {
+                    let mut frame = vec![0; u32::from_be_bytes(len) as usize];
+                    if reader.read_exact(&mut frame).is_err() {
+                        break;
+                    }
+                    received.lock().unwrap().push_back(frame);
+                    interrupts::raise(interrupts::ETHERNET);
+                }
+            })?;
+        self.stream.replace(Some(stream));
+        Ok(())
+    }
+
+    pub fn handle_interrupt(&self) {
+        if !self.receive_enabled.get() {
+            // Frames are dropped until receiving is enabled.
+            self.received.lock().unwrap().clear();
+            return;
+        }
+        loop {
+            // The lock is not held while the client handles the frame.
+            let frame = self.received.lock().unwrap().pop_front();
+            match frame {
+                Some(frame) => self.client.map(|client| client.received_frame(&frame)),
+                None => break,
+            };
+        }
+    }
+}
+
+impl<'a> Default for Ethernet<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DeferredCallClient for Ethernet<'_> {
+    fn handle_deferred_call(&self) {
+        if let Some(frame) = self.tx_frame.take() {
+            let result = self.tx_result.get();
+            self.client
+                .map(move |client| client.transmit_done(result, frame));
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+impl<'a> EthernetAdapter<'a> for Ethernet<'a> {
+    fn set_client(&self, client: &'a dyn EthernetAdapterClient) {
+        self.client.set(client);
+    }
+
+    fn enable_receive(&self) {
+        self.receive_enabled.set(true);
+    }
+
+    fn transmit(
+        &self,
+        frame: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.tx_frame.is_some() {
+            return Err((ErrorCode::BUSY, frame));
+        }
+        if len > frame.len() || len > MAX_FRAME_LEN {
+            return Err((ErrorCode::SIZE, frame));
+        }
+        let mut stream = self.stream.borrow_mut();
+        let stream = match stream.as_mut() {
+            Some(stream) => stream,
+            None => return Err((ErrorCode::OFF, frame)),
+        };
+
+        let result = stream
+            .write_all(&(len as u32).to_be_bytes())
+            .and_then(|()| stream.write_all(&frame[..len]));
+        self.tx_result.set(result.map_err(|_| ErrorCode::FAIL));
+        self.tx_frame.replace(frame);
+        self.deferred_call.set();
+        Ok(())
+    }
+}
@@ -0,0 +1,209 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Flash kept in a file of the host.
+//!
+//! The flash is made of [`PAGE_SIZE`] byte pages, stored one after the other
+//! in the file given to [`Flash::open`], so that its contents persist across
+//! runs of the kernel. Operations are done synchronously on the file and
+//! complete from a deferred call. Like the nRF52 flash controller, writing a
+//! page replaces its contents, as if it was erased first.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! peripherals
+//!     .flash
+//!     .open(Path::new("flash.bin"), 64)
+//!     .expect("cannot open the flash file");
+//! ```
+
+use core::cell::{Cell, RefCell};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+pub const PAGE_SIZE: usize = 4096;
+
+pub struct FlashPage(pub [u8; PAGE_SIZE]);
+
+impl Default for FlashPage {
+    fn default() -> Self {
+        Self([0; PAGE_SIZE])
+    }
+}
+
+impl AsMut<[u8]> for FlashPage {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Operation {
+    Read,
+    Write,
+    Erase,
+}
+
+pub struct Flash {
+    file: RefCell<Option<File>>,
+    page_count: Cell<usize>,
+    client: OptionalCell<&'static dyn hil::flash::Client<Flash>>,
+    buffer: TakeCell<'static, FlashPage>,
+    /// Operation done and waiting for the deferred call to complete.
+    operation: OptionalCell<Operation>,
+    result: Cell<Result<(), hil::flash::Error>>,
+    deferred_call: DeferredCall,
+}
+
+impl Flash {
+    pub fn new() -> Self {
+        Self {
+            file: RefCell::new(None),
+            page_count: Cell::new(0),
+            client: OptionalCell::empty(),
+            buffer: TakeCell::empty(),
+            operation: OptionalCell::empty(),
+            result: Cell::new(Ok(())),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    /// Uses `path` as the flash, with `page_count` pages. The file is created
+    /// if needed and extended with erased pages if it is too short.
+    pub fn open(&self, path: &Path, page_count: usize) -> io::Result<()> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let len = file.metadata()?.len() as usize;
+        let size = page_count * PAGE_SIZE;
+        if len < size {
+            file.write_all_at(&vec![0xff; size - len], len as u64)?;
+        }
+        self.file.replace(Some(file));
+        self.page_count.set(page_count);
+        Ok(())
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.page_count.get()
+    }
+
+    /// Runs `f` on the file and page offset, and schedules the completion.
+    fn start(
+        &self,
+        operation: Operation,
+        page_number: usize,
+        f: impl FnOnce(&File, u64) -> io::Result<()>,
+    ) -> Result<(), ErrorCode> {
+        if self.operation.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        if page_number >= self.page_count.get() {
+            return Err(ErrorCode::INVAL);
+        }
+        let file = self.file.borrow();
+        let file = file.as_ref().ok_or(ErrorCode::OFF)?;
+
+        let result = f(file, (page_number * PAGE_SIZE) as u64);
+        self.result
+            .set(result.map_err(|_| hil::flash::Error::FlashError));
+        self.operation.set(operation);
+        self.deferred_call.set();
+        Ok(())
+    }
+}
+
+impl Default for Flash {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DeferredCallClient for Flash {
+    fn handle_deferred_call(&self) {
+        let result = self.result.get();
+        match self.operation.take() {
+            Some(Operation::Read) => {
+                if let Some(buffer) = self.buffer.take() {
+                    self.client
+                        .map(move |client| client.read_complete(buffer, result));
+                }
+            }
+            Some(Operation::Write) => {
+                if let Some(buffer) = self.buffer.take() {
+                    self.client
+                        .map(move |client| client.write_complete(buffer, result));
+                }
+            }
+            Some(Operation::Erase) => {
+                self.client.map(|client| client.erase_complete(result));
+            }
+            None => {}
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+impl<C: hil::flash::Client<Self>> hil::flash::HasClient<'static, C> for Flash {
+    fn set_client(&self, client: &'static C) {
+        self.client.set(client);
+    }
+}
+
+impl hil::flash::Flash for Flash {
+    type Page = FlashPage;
+
+    fn read_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Self::Page,
+    ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+        match self.start(Operation::Read, page_number, |file, offset| {
+            file.read_exact_at(&mut buf.0, offset)
+        }) {
+            Ok(()) => {
+                self.buffer.replace(buf);
+                Ok(())
+            }
+            Err(error) => Err((error, buf)),
+        }
+    }
+
+    fn write_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Self::Page,
+    ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+        match self.start(Operation::Write, page_number, |file, offset| {
+            file.write_all_at(&buf.0, offset)
+        }) {
+            Ok(()) => {
+                self.buffer.replace(buf);
+                Ok(())
+            }
+            Err(error) => Err((error, buf)),
+        }
+    }
+
+    fn erase_page(&self, page_number: usize) -> Result<(), ErrorCode> {
+        self.start(Operation::Erase, page_number, |file, offset| {
+            file.write_all_at(&[0xff; PAGE_SIZE], offset)
+        })
+    }
+}
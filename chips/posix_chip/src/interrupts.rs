@@ -0,0 +1,47 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Interrupt lines of the emulated peripherals.
+//!
+//! Host threads raise a line when a peripheral needs attention. The kernel
+//! thread takes the pending lines and services them, and sleeps until a line
+//! is raised.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Condvar, Mutex};
+
+pub const UART: u32 = 0;
+pub const ALARM: u32 = 1;
+pub const ETHERNET: u32 = 2;
+
+static PENDING: AtomicU32 = AtomicU32::new(0);
+/// Held while checking for pending lines before sleeping, so that a line
+/// raised at that time wakes up the kernel.
+static SLEEP: Mutex<()> = Mutex::new(());
+static RAISED: Condvar = Condvar::new();
+
+/// Marks `line` pending and wakes up the kernel if it sleeps.
+pub fn raise(line: u32) {
+    PENDING.fetch_or(1 << line, Ordering::SeqCst);
+    drop(SLEEP.lock().unwrap());
+    RAISED.notify_all();
+}
+
+/// Returns the pending lines as a bitmask and clears them.
+pub fn take() -> u32 {
+    PENDING.swap(0, Ordering::SeqCst)
+}
+
+pub fn is_pending() -> bool {
+    PENDING.load(Ordering::SeqCst) != 0
+}
+
+/// Blocks until a line is pending.
+pub fn wait() {
+    drop(
+        RAISED
+            .wait_while(SLEEP.lock().unwrap(), |()| !is_pending())
+            .unwrap(),
+    );
+}
@@ -0,0 +1,27 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Chip support for running the kernel as a process on a POSIX host.
+//!
+//! The peripherals are emulated with the host: the UART is the standard input
+//! and output of the process, the alarm follows the host monotonic clock, the
+//! flash is a file and the Ethernet adapter a TCP connection. Threads waiting
+//! on the host raise interrupt lines that the kernel services on its own
+//! thread, as it would on hardware.
+//!
+//! Applications are Rust functions linked into the same executable and run on
+//! threads of their own, see [`app`]. There is no memory protection between
+//! the kernel and the applications.
+
+#![crate_name = "posix_chip"]
+#![crate_type = "rlib"]
+
+pub mod alarm;
+pub mod app;
+pub mod chip;
+pub mod ethernet;
+pub mod flash;
+pub mod interrupts;
+pub mod syscall;
+pub mod uart;
@@ -0,0 +1,185 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Kernel-userland system call interface for processes on host threads.
+//!
+//! Each process runs on a thread of its own, which is started the first time
+//! the kernel switches to the process. The kernel and the thread hand control
+//! to each other over a pair of channels: switching to a process sends it what
+//! it resumes with, the return value of its last system call or an upcall, and
+//! blocks until the process makes its next system call. Only one of them runs
+//! at a time, as on a single core, so the kernel can access the memory of a
+//! process while it does not run.
+//!
+//! Processes are not preempted, they run until their next system call. A
+//! process looping without making system calls stalls the kernel. A panic on
+//! the thread of a process faults the process. When a process is restarted,
+//! its previous thread is left blocked and a new one is started.
+
+use core::fmt::Write;
+use std::sync::mpsc::{Receiver, Sender};
+
+use kernel::errorcode::ErrorCode;
+use kernel::process::FunctionCall;
+use kernel::syscall::{ContextSwitchReason, Syscall, SyscallReturn};
+
+use crate::app;
+
+/// What a process resumes with when the kernel switches to it.
+pub(crate) enum Resume {
+    /// The system call returned this value.
+    Return(SyscallReturn),
+    /// Call a function, then return from the system call.
+    Call(FunctionCall),
+    /// Return from a yield without having called anything.
+    Continue,
+}
+
+/// Why a process hands control back to the kernel.
+pub(crate) enum Trap {
+    Syscall(Syscall),
+    Fault,
+}
+
+// SAFETY: The pointers in system calls and their return values point into
+// process memory. The kernel and the process hand it to each other with the
+// messages, and only one of them runs at a time.
+unsafe impl Send for Resume {}
+unsafe impl Send for Trap {}
+
+/// The channels to the thread of a process.
+pub(crate) struct ProcessThread {
+    pub(crate) resume: Sender<Resume>,
+    pub(crate) trap: Receiver<Trap>,
+}
+
+#[derive(Default)]
+pub struct PosixStoredState {
+    thread: Option<ProcessThread>,
+    resume: Option<Resume>,
+    /// The last system call of the process, for printing its context.
+    last_syscall: Option<Syscall>,
+}
+
+/// Implementation of the `UserspaceKernelBoundary` for processes running on
+/// host threads.
+pub struct SysCall(());
+
+impl SysCall {
+    pub const fn new() -> Self {
+        Self(())
+    }
+}
+
+impl Default for SysCall {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl kernel::syscall::UserspaceKernelBoundary for SysCall {
+    type StoredState = PosixStoredState;
+
+    fn initial_process_app_brk_size(&self) -> usize {
+        // Processes use their own host stack.
+        0
+    }
+
+    unsafe fn initialize_process(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        state: &mut Self::StoredState,
+    ) -> Result<(), ()> {
+        // Dropping the channels leaves a previous thread of the process
+        // blocked forever.
+        *state = PosixStoredState::default();
+        Ok(())
+    }
+
+    unsafe fn set_syscall_return_value(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        state: &mut Self::StoredState,
+        return_value: SyscallReturn,
+    ) -> Result<(), ()> {
+        state.resume = Some(Resume::Return(return_value));
+        Ok(())
+    }
+
+    unsafe fn set_process_function(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        state: &mut Self::StoredState,
+        callback: FunctionCall,
+    ) -> Result<(), ()> {
+        state.resume = Some(Resume::Call(callback));
+        Ok(())
+    }
+
+    unsafe fn switch_to_process(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        state: &mut Self::StoredState,
+    ) -> (ContextSwitchReason, Option<*const u8>) {
+        let resume = state.resume.take().unwrap_or(Resume::Continue);
+        let thread = match state.thread.as_ref() {
+            Some(thread) => {
+                if thread.resume.send(resume).is_err() {
+                    return (ContextSwitchReason::Fault, None);
+                }
+                thread
+            }
+            // The first function a process calls is its entry point.
+            None => match resume {
+                Resume::Call(entry) => match app::start(entry) {
+                    Some(thread) => state.thread.insert(thread),
+                    None => return (ContextSwitchReason::Fault, None),
+                },
+                _ => return (ContextSwitchReason::Fault, None),
+            },
+        };
+
+        match thread.trap.recv() {
+            Ok(Trap::Syscall(syscall)) => {
+                state.last_syscall = Some(syscall);
+                (ContextSwitchReason::SyscallFired { syscall }, None)
+            }
+            Ok(Trap::Fault) | Err(_) => (ContextSwitchReason::Fault, None),
+        }
+    }
+
+    unsafe fn print_context(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        state: &Self::StoredState,
+        writer: &mut dyn Write,
+    ) {
+        let _ = writer.write_fmt(format_args!(
+            "\
+             \r\n Thread: {}\
+             \r\n Last system call: {:?}\
+             \r\n",
+            if state.thread.is_some() {
+                "started"
+            } else {
+                "not started"
+            },
+            state.last_syscall,
+        ));
+    }
+
+    fn store_context(
+        &self,
+        _state: &Self::StoredState,
+        _out: &mut [u8],
+    ) -> Result<usize, ErrorCode> {
+        // The registers of a process are those of its host thread.
+        Err(ErrorCode::NOSUPPORT)
+    }
+}
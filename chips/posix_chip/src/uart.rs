@@ -0,0 +1,229 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! UART on the standard input and output of the host process.
+//!
+//! Transmitted buffers are written to stdout right away and the transmission
+//! completes from a deferred call. The first receive starts a thread reading
+//! stdin, which queues the bytes it reads and raises the UART interrupt. The
+//! terminal should be in raw mode, so that the process console receives
+//! every key press, see the `run` target of the posix board Makefile.
+
+use core::cell::Cell;
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::uart;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+use crate::interrupts;
+
+pub struct Uart<'a> {
+    tx_client: OptionalCell<&'a dyn uart::TransmitClient>,
+    rx_client: OptionalCell<&'a dyn uart::ReceiveClient>,
+    tx_buffer: TakeCell<'static, [u8]>,
+    tx_len: Cell<usize>,
+    rx_buffer: TakeCell<'static, [u8]>,
+    rx_len: Cell<usize>,
+    rx_position: Cell<usize>,
+    rx_aborted: Cell<bool>,
+    /// Bytes read from stdin and not received yet.
+    received: Arc<Mutex<VecDeque<u8>>>,
+    reader_started: Cell<bool>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a> Uart<'a> {
+    pub fn new() -> Self {
+        Self {
+            tx_client: OptionalCell::empty(),
+            rx_client: OptionalCell::empty(),
+            tx_buffer: TakeCell::empty(),
+            tx_len: Cell::new(0),
+            rx_buffer: TakeCell::empty(),
+            rx_len: Cell::new(0),
+            rx_position: Cell::new(0),
+            rx_aborted: Cell::new(false),
+            received: Arc::new(Mutex::new(VecDeque::new())),
+            reader_started: Cell::new(false),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    fn start_reader(&self) {
+        if self.reader_started.replace(true) {
+            return;
+        }
+        let received = self.received.clone();
+        thread::Builder::new()
+            .name("uart-stdin".into())
+            .spawn(move || {
+                let mut buf = [0; 64];
+                // Reading stops at the end of stdin.
+                while let Ok(len @ 1..) = std::io::stdin().read(&mut buf) {
+                    received.lock().unwrap().extend(&buf[..len]);
+                    interrupts::raise(interrupts::UART);
+                }
+            })
+            .expect("uart: failed to start the stdin thread");
+    }
+
+    /// Copies the queued bytes into the receive buffer and completes the
+    /// receive once the buffer is full.
+    pub fn handle_interrupt(&self) {
+        let mut received = self.received.lock().unwrap();
+        let complete = self.rx_buffer.map_or(false, |buffer| {
+            let mut position = self.rx_position.get();
+            while position < self.rx_len.get() {
+                match received.pop_front() {
+                    Some(byte) => buffer[position] = byte,
+                    None => break,
+                }
+                position += 1;
+            }
+            self.rx_position.set(position);
+            position == self.rx_len.get()
+        });
+        drop(received);
+
+        if complete {
+            self.rx_aborted.set(false);
+            self.complete_receive(Ok(()), uart::Error::None);
+        }
+    }
+
+    fn complete_receive(&self, rcode: Result<(), ErrorCode>, error: uart::Error) {
+        if let Some(buffer) = self.rx_buffer.take() {
+            let len = self.rx_position.get();
+            self.rx_client.map(move |client| {
+                client.received_buffer(buffer, len, rcode, error);
+            });
+        }
+    }
+}
+
+impl<'a> Default for Uart<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DeferredCallClient for Uart<'_> {
+    fn handle_deferred_call(&self) {
+        if let Some(buffer) = self.tx_buffer.take() {
+            let len = self.tx_len.get();
+            self.tx_client.map(move |client| {
+                client.transmitted_buffer(buffer, len, Ok(()));
+            });
+        }
+        if self.rx_aborted.replace(false) {
+            self.complete_receive(Err(ErrorCode::CANCEL), uart::Error::Aborted);
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+impl uart::Configure for Uart<'_> {
+    fn configure(&self, _params: uart::Parameters) -> Result<(), ErrorCode> {
+        // The host terminal has no line settings to change.
+        Ok(())
+    }
+}
+
+impl<'a> uart::Transmit<'a> for Uart<'a> {
+    fn set_transmit_client(&self, client: &'a dyn uart::TransmitClient) {
+        self.tx_client.set(client);
+    }
+
+    fn transmit_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        tx_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.tx_buffer.is_some() {
+            return Err((ErrorCode::BUSY, tx_buffer));
+        }
+        if tx_len == 0 || tx_len > tx_buffer.len() {
+            return Err((ErrorCode::SIZE, tx_buffer));
+        }
+
+        let mut stdout = std::io::stdout().lock();
+        if stdout
+            .write_all(&tx_buffer[..tx_len])
+            .and_then(|()| stdout.flush())
+            .is_err()
+        {
+            return Err((ErrorCode::FAIL, tx_buffer));
+        }
+
+        self.tx_buffer.replace(tx_buffer);
+        self.tx_len.set(tx_len);
+        self.deferred_call.set();
+        Ok(())
+    }
+
+    fn transmit_word(&self, _word: u32) -> Result<(), ErrorCode> {
+        Err(ErrorCode::FAIL)
+    }
+
+    fn transmit_abort(&self) -> Result<(), ErrorCode> {
+        // Buffers are written out as soon as they are transmitted, so the
+        // pending transmission completes successfully.
+        if self.tx_buffer.is_some() {
+            Err(ErrorCode::FAIL)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<'a> uart::Receive<'a> for Uart<'a> {
+    fn set_receive_client(&self, client: &'a dyn uart::ReceiveClient) {
+        self.rx_client.set(client);
+    }
+
+    fn receive_buffer(
+        &self,
+        rx_buffer: &'static mut [u8],
+        rx_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.rx_buffer.is_some() {
+            return Err((ErrorCode::BUSY, rx_buffer));
+        }
+        if rx_len == 0 || rx_len > rx_buffer.len() {
+            return Err((ErrorCode::SIZE, rx_buffer));
+        }
+
+        self.rx_buffer.replace(rx_buffer);
+        self.rx_len.set(rx_len);
+        self.rx_position.set(0);
+        self.start_reader();
+
+        // Bytes may have arrived before this receive.
+        if !self.received.lock().unwrap().is_empty() {
+            interrupts::raise(interrupts::UART);
+        }
+        Ok(())
+    }
+
+    fn receive_word(&self) -> Result<(), ErrorCode> {
+        Err(ErrorCode::FAIL)
+    }
+
+    fn receive_abort(&self) -> Result<(), ErrorCode> {
+        if self.rx_buffer.is_none() {
+            return Ok(());
+        }
+        self.rx_aborted.set(true);
+        self.deferred_call.set();
+        Err(ErrorCode::BUSY)
+    }
+}
@@ -4,6 +4,7 @@
 
 use core::cell::Cell;
 
+use kernel::hil::ethernet::{EthernetAdapter, EthernetAdapterClient, MAX_FRAME_LEN};
 use kernel::utilities::cells::OptionalCell;
 use kernel::utilities::registers::{register_bitfields, LocalRegisterCopy};
 use kernel::ErrorCode;
@@ -48,7 +49,7 @@ pub struct VirtIONet<'a> {
     tx_header: OptionalCell<&'static mut [u8; 12]>,
     rx_header: OptionalCell<&'static mut [u8]>,
     rx_buffer: OptionalCell<&'static mut [u8]>,
-    client: OptionalCell<&'a dyn VirtIONetClient>,
+    client: OptionalCell<&'a dyn EthernetAdapterClient>,
 }
 
 impl<'a> VirtIONet<'a> {
@@ -78,15 +79,11 @@ impl<'a> VirtIONet<'a> {
         self.id.get()
     }
 
-    pub fn set_client(&self, client: &'a dyn VirtIONetClient) {
-        self.client.set(client);
-    }
-
     // This is not executed as part of the `device_initialized` hook to avoid
     // missing any packets if a client has not been registered, and because this
     // device can be used in a transmit-only fashion before invoking this
     // function.
-    pub fn enable_rx(&self) {
+    fn enable_rx(&self) {
         // To start operation, put the receive buffers into the device initially
         let rx_buffer = self.rx_buffer.take().unwrap();
         let rx_buffer_len = rx_buffer.len();
@@ -109,15 +106,6 @@ impl<'a> VirtIONet<'a> {
             .unwrap();
     }
 
-    pub fn return_rx_buffer(&self, buf: &'static mut [u8]) {
-        assert!(self.rx_buffer.is_none());
-        assert!(self.rx_header.is_some());
-        self.rx_buffer.replace(buf);
-
-        // Re-register the RX buffer with the Virtqueue:
-        self.enable_rx();
-    }
-
     pub fn send_packet(
         &self,
         packet: &'static mut [u8],
@@ -188,9 +176,13 @@ impl<'a> SplitVirtqueueClient<'static> for VirtIONet<'a> {
             self.rx_header.replace(rx_header);
 
             let rx_buffer = buffer_chain[1].take().expect("No rx content buffer").buf;
-            self.client.map(move |client| {
-                client.packet_received(self.id.get(), rx_buffer, bytes_used - 12)
-            });
+            let len = bytes_used.saturating_sub(12).min(rx_buffer.len());
+            self.client
+                .map(|client| client.received_frame(&rx_buffer[..len]));
+
+            // Re-register the RX buffer with the Virtqueue:
+            self.rx_buffer.replace(rx_buffer);
+            self.enable_rx();
         } else if queue_number == self.txqueue.queue_number().unwrap() {
             // Sent a packet
 
@@ -199,7 +191,7 @@ impl<'a> SplitVirtqueueClient<'static> for VirtIONet<'a> {
 
             let packet_buf = buffer_chain[1].take().expect("No packet buffer").buf;
             self.client
-                .map(move |client| client.packet_sent(self.id.get(), packet_buf));
+                .map(move |client| client.transmit_done(Ok(()), packet_buf));
         } else {
             panic!("Callback from unknown queue");
         }
@@ -241,7 +233,28 @@ impl<'a> VirtIODeviceDriver for VirtIONet<'a> {
     }
 }
 
-pub trait VirtIONetClient {
-    fn packet_sent(&self, id: usize, buffer: &'static mut [u8]);
-    fn packet_received(&self, id: usize, buffer: &'static mut [u8], len: usize);
+impl<'a> EthernetAdapter<'a> for VirtIONet<'a> {
+    fn set_client(&self, client: &'a dyn EthernetAdapterClient) {
+        self.client.set(client);
+    }
+
+    fn enable_receive(&self) {
+        // The receive buffer is only held by the driver while receive is
+        // disabled; afterwards it is always registered with the device.
+        if self.rx_buffer.is_some() {
+            self.enable_rx();
+        }
+    }
+
+    fn transmit(
+        &self,
+        frame: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if len > frame.len() || len > MAX_FRAME_LEN {
+            return Err((ErrorCode::SIZE, frame));
+        }
+        self.send_packet(frame, len)
+            .map_err(|(frame, ecode)| (ecode, frame))
+    }
 }
@@ -183,6 +183,19 @@ impl VirtIOMMIODevice {
         // Extract the device type
         VirtIODeviceType::from_device_id(self.regs.device_id.get())
     }
+
+    /// Read a byte from the device-specific configuration space
+    ///
+    /// The layout of this space depends on the device type, for instance a
+    /// network card exposes its MAC address in the first 6 bytes. Returns
+    /// `None` if the offset is outside of the configuration space.
+    pub fn read_device_config(&self, offset: usize) -> Option<u8> {
+        let word = self.regs.config.get(offset / 4)?;
+        // The configuration space is device memory and must not be cached
+        // or elided by the compiler.
+        let value = unsafe { core::ptr::read_volatile(word) };
+        Some(value.to_le_bytes()[offset % 4])
+    }
 }
 
 impl VirtIOTransport for VirtIOMMIODevice {
@@ -0,0 +1,244 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Process core dumps.
+//!
+//! A core dump holds the state of a process for offline debugging: its
+//! identity, its saved `UserspaceKernelBoundary` state and its RAM below the
+//! application break, which includes the stack but not the grant region. It
+//! is encoded as an ELF core file and generated on the fly from the process,
+//! so no buffer is needed to hold it: [`read`] returns any part of it. The
+//! dump is only consistent while the process does not run, for example once
+//! it faulted and was stopped.
+//!
+//! The ELF file has two program headers. A `PT_NOTE` segment holds two notes
+//! named `TOCK`:
+//!
+//! - [`NT_TOCK_PROCESS`] describes the process, with the following `u32`
+//!   fields: short ID (0 if not fixed), binary version (0 if none), restart
+//!   count, flash start, start of the flash the process can access, flash
+//!   end, RAM start, application break, grant region start, RAM end, stack
+//!   top, stack bottom and heap start (0 if unknown). They are followed by
+//!   the process name, NUL terminated and padded with NULs.
+//! - [`NT_TOCK_STORED_STATE`] holds the stored state of the process in the
+//!   architecture specific format of `UserspaceKernelBoundary::store_context`.
+//!
+//! A `PT_LOAD` segment holds the process RAM. The ELF machine is set from
+//! the architecture tag of the stored state when it is known. Host tools
+//! convert the Tock notes into the register notes debuggers expect.
+//!
+//! A [`CoreDumpWriter`], such as the process console, emits the dump of a
+//! process when it faults, if the board uses a fault policy that passes
+//! faulted processes to it.
+
+use crate::process::{Process, ShortId};
+
+/// Name of the Tock notes.
+pub const NOTE_NAME: &[u8; 5] = b"TOCK\0";
+/// Type of the note describing the process.
+pub const NT_TOCK_PROCESS: u32 = 1;
+/// Type of the note holding the stored state of the process.
+pub const NT_TOCK_STORED_STATE: u32 = 2;
+
+const ELF_HEADER_LEN: usize = 52;
+const PROGRAM_HEADER_LEN: usize = 32;
+const PROGRAM_HEADER_COUNT: usize = 2;
+/// Note header with the padded name.
+const NOTE_HEADER_LEN: usize = 12 + 8;
+const PROCESS_FIELDS_LEN: usize = 13 * 4;
+/// Longest process name kept, including the NUL terminator.
+const NAME_MAX_LEN: usize = 32;
+/// Largest stored state of the supported architectures.
+const STORED_STATE_MAX_LEN: usize = 160;
+const HEADER_MAX_LEN: usize = ELF_HEADER_LEN
+    + PROGRAM_HEADER_COUNT * PROGRAM_HEADER_LEN
+    + 2 * NOTE_HEADER_LEN
+    + PROCESS_FIELDS_LEN
+    + NAME_MAX_LEN
+    + STORED_STATE_MAX_LEN;
+
+const ET_CORE: u16 = 4;
+const EM_NONE: u16 = 0;
+const EM_ARM: u16 = 40;
+const EM_RISCV: u16 = 243;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+/// Emits the core dumps of faulted processes.
+pub trait CoreDumpWriter {
+    /// Start emitting the core dump of `process`, which faulted and is being
+    /// stopped. The dump is read with [`read`] while it is emitted, so the
+    /// process must stay stopped until then.
+    fn write_core_dump(&self, process: &dyn Process);
+}
+
+/// Returns the length of the core dump of `process`.
+pub fn len(process: &dyn Process) -> usize {
+    let mut header = [0; HEADER_MAX_LEN];
+    let addresses = process.get_addresses();
+    build_header(process, &mut header) + (addresses.sram_app_brk - addresses.sram_start)
+}
+
+/// Copies the core dump of `process`, starting at `offset`, into `buf`.
+/// Returns the number of bytes copied, which is zero past the end of the
+/// dump.
+pub fn read(process: &dyn Process, offset: usize, buf: &mut [u8]) -> usize {
+    let mut header = [0; HEADER_MAX_LEN];
+    let header_len = build_header(process, &mut header);
+    let addresses = process.get_addresses();
+    let ram_len = addresses.sram_app_brk - addresses.sram_start;
+
+    let mut copied = 0;
+    if offset < header_len {
+        copied = core::cmp::min(buf.len(), header_len - offset);
+        buf[..copied].copy_from_slice(&header[offset..offset + copied]);
+    }
+
+    let ram_offset = (offset + copied).saturating_sub(header_len);
+    if ram_offset < ram_len {
+        let len = core::cmp::min(buf.len() - copied, ram_len - ram_offset);
+        if process
+            .read_memory(
+                addresses.sram_start + ram_offset,
+                &mut buf[copied..copied + len],
+            )
+            .is_err()
+        {
+            return copied;
+        }
+        copied += len;
+    }
+    copied
+}
+
+/// Writes little endian values into a buffer.
+struct Cursor<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl Cursor<'_> {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.buf[self.pos..self.pos + bytes.len()].copy_from_slice(bytes);
+        self.pos += bytes.len();
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn align(&mut self) {
+        self.pos = self.pos.next_multiple_of(4);
+    }
+
+    fn note_header(&mut self, desc_len: usize, note_type: u32) {
+        self.u32(NOTE_NAME.len() as u32);
+        self.u32(desc_len as u32);
+        self.u32(note_type);
+        self.bytes(NOTE_NAME);
+        self.align();
+    }
+}
+
+/// Writes the ELF header, the program headers and the notes into `header`,
+/// which must be zeroed, and returns their length.
+fn build_header(process: &dyn Process, header: &mut [u8; HEADER_MAX_LEN]) -> usize {
+    let addresses = process.get_addresses();
+
+    let mut stored_state = [0; STORED_STATE_MAX_LEN];
+    let stored_state_len = process.get_stored_state(&mut stored_state).unwrap_or(0);
+    let machine = match stored_state.get(8..12) {
+        Some(b"ctxm") => EM_ARM,
+        Some(b"rv5i") => EM_RISCV,
+        _ => EM_NONE,
+    };
+
+    let name = process.get_process_name().as_bytes();
+    let name = &name[..core::cmp::min(name.len(), NAME_MAX_LEN - 1)];
+    let process_desc_len = PROCESS_FIELDS_LEN + name.len() + 1;
+
+    let notes_offset = ELF_HEADER_LEN + PROGRAM_HEADER_COUNT * PROGRAM_HEADER_LEN;
+    let notes_len = 2 * NOTE_HEADER_LEN
+        + process_desc_len.next_multiple_of(4)
+        + stored_state_len.next_multiple_of(4);
+    let ram_offset = notes_offset + notes_len;
+    let ram_len = addresses.sram_app_brk - addresses.sram_start;
+
+    let mut cursor = Cursor {
+        buf: header,
+        pos: 0,
+    };
+
+    // ELF header.
+    cursor.bytes(&[0x7f, b'E', b'L', b'F', 1, 1, 1]);
+    cursor.pos = 16;
+    cursor.u16(ET_CORE);
+    cursor.u16(machine);
+    cursor.u32(1); // Version.
+    cursor.u32(0); // Entry point.
+    cursor.u32(ELF_HEADER_LEN as u32); // Program headers offset.
+    cursor.u32(0); // Section headers offset.
+    cursor.u32(0); // Flags.
+    cursor.u16(ELF_HEADER_LEN as u16);
+    cursor.u16(PROGRAM_HEADER_LEN as u16);
+    cursor.u16(PROGRAM_HEADER_COUNT as u16);
+    cursor.u16(0); // Section header size.
+    cursor.u16(0); // Section header count.
+    cursor.u16(0); // Section name table index.
+
+    // Notes segment.
+    cursor.u32(PT_NOTE);
+    cursor.u32(notes_offset as u32);
+    cursor.u32(0);
+    cursor.u32(0);
+    cursor.u32(notes_len as u32);
+    cursor.u32(0);
+    cursor.u32(0);
+    cursor.u32(4);
+
+    // RAM segment.
+    cursor.u32(PT_LOAD);
+    cursor.u32(ram_offset as u32);
+    cursor.u32(addresses.sram_start as u32);
+    cursor.u32(addresses.sram_start as u32);
+    cursor.u32(ram_len as u32);
+    cursor.u32(ram_len as u32);
+    cursor.u32(PF_R | PF_W);
+    cursor.u32(4);
+
+    // Process note.
+    cursor.note_header(process_desc_len, NT_TOCK_PROCESS);
+    cursor.u32(match process.short_app_id() {
+        ShortId::LocallyUnique => 0,
+        ShortId::Fixed(id) => id.get(),
+    });
+    cursor.u32(process.binary_version().map_or(0, |version| version.get()));
+    cursor.u32(process.get_restart_count() as u32);
+    cursor.u32(addresses.flash_start as u32);
+    cursor.u32(addresses.flash_non_protected_start as u32);
+    cursor.u32(addresses.flash_end as u32);
+    cursor.u32(addresses.sram_start as u32);
+    cursor.u32(addresses.sram_app_brk as u32);
+    cursor.u32(addresses.sram_grant_start as u32);
+    cursor.u32(addresses.sram_end as u32);
+    cursor.u32(addresses.sram_stack_top.unwrap_or(0) as u32);
+    cursor.u32(addresses.sram_stack_bottom.unwrap_or(0) as u32);
+    cursor.u32(addresses.sram_heap_start.unwrap_or(0) as u32);
+    cursor.bytes(name);
+    cursor.pos += 1;
+    cursor.align();
+
+    // Stored state note.
+    cursor.note_header(stored_state_len, NT_TOCK_STORED_STATE);
+    cursor.bytes(&stored_state[..stored_state_len]);
+    cursor.align();
+
+    cursor.pos
+}
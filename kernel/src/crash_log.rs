@@ -0,0 +1,91 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Crash reports kept across reboots.
+//!
+//! Panic reports and process fault reports are normally only printed to the
+//! console, and are lost if nothing is attached to it. A board can register a
+//! [`CrashRecorder`] with
+//! [`debug::set_crash_recorder`](crate::debug::set_crash_recorder), which
+//! receives a copy of the panic report printed by
+//! [`debug::panic_print`](crate::debug::panic_print) and saves it, typically
+//! to nonvolatile storage. The recorder may also record other reports, such
+//! as process faults.
+//!
+//! On the next boot, the saved reports are accessed through
+//! [`CrashLogControl`], for example from the process console.
+
+use crate::ErrorCode;
+
+/// What caused a crash report.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CrashKind {
+    /// The kernel panicked.
+    KernelPanic = 0,
+    /// A process faulted and the kernel kept running.
+    ProcessFault = 1,
+}
+
+impl TryFrom<u8> for CrashKind {
+    type Error = ();
+
+    fn try_from(kind: u8) -> Result<Self, Self::Error> {
+        match kind {
+            0 => Ok(CrashKind::KernelPanic),
+            1 => Ok(CrashKind::ProcessFault),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Information about a crash report held by a [`CrashLogControl`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CrashReportInfo {
+    pub kind: CrashKind,
+    /// Length of the report text, in bytes.
+    pub len: usize,
+    /// Whether the end of the report did not fit and was dropped.
+    pub truncated: bool,
+}
+
+/// Receives crash reports and saves them.
+///
+/// The panic handler uses this interface with interrupts not being
+/// dispatched, so `save_report()` must make progress when the caller services
+/// pending interrupts and deferred calls itself, until `is_saving()` returns
+/// `false`.
+pub trait CrashRecorder {
+    /// Starts a new report of `kind`. Returns `false` if the recorder cannot
+    /// take a report now, for example because it is still saving a previous
+    /// one, in which case the report is not recorded.
+    fn begin_report(&self, kind: CrashKind) -> bool;
+
+    /// Appends `buf` to the current report. Text that does not fit in the
+    /// report is dropped.
+    fn write_report(&self, buf: &[u8]);
+
+    /// Starts saving the current report.
+    fn save_report(&self) -> Result<(), ErrorCode>;
+
+    /// Returns `true` while a report is being saved.
+    fn is_saving(&self) -> bool;
+}
+
+/// Access to saved crash reports, for consoles and other debugging tools.
+pub trait CrashLogControl {
+    /// Returns the number of reports saved, including ones from previous
+    /// boots.
+    fn report_count(&self) -> usize;
+
+    /// Returns information about the newest report, if one is available.
+    fn last_report(&self) -> Option<CrashReportInfo>;
+
+    /// Copies the text of the newest report, starting at `offset`, into
+    /// `buf`. Returns the number of bytes copied, which is zero past the end
+    /// of the report.
+    fn read_last_report(&self, offset: usize, buf: &mut [u8]) -> usize;
+
+    /// Erases all saved reports.
+    fn erase(&self) -> Result<(), ErrorCode>;
+}
@@ -60,6 +60,8 @@ use core::str;
 
 use crate::collections::queue::Queue;
 use crate::collections::ring_buffer::RingBuffer;
+use crate::crash_log::{CrashKind, CrashRecorder};
+use crate::deferred_call::DeferredCall;
 use crate::hil;
 use crate::platform::chip::Chip;
 use crate::process::Process;
@@ -107,6 +109,9 @@ pub trait IoWrite {
 /// well-defined state. Care must be taken on how one interacts with
 /// the system once this function returns.
 ///
+/// If a crash recorder was registered with [`set_crash_recorder`], the panic
+/// report is also saved to it.
+///
 /// **NOTE:** The supplied `writer` must be synchronous.
 pub unsafe fn panic_print<W: Write + IoWrite, C: Chip, PP: ProcessPrinter>(
     writer: &mut W,
@@ -117,6 +122,15 @@ pub unsafe fn panic_print<W: Write + IoWrite, C: Chip, PP: ProcessPrinter>(
     process_printer: &'static Option<&'static PP>,
 ) {
     panic_begin(nop);
+
+    // Keep a copy of the report if the board registered a crash recorder. The
+    // recorder is removed first so that a panic while recording does not
+    // record again.
+    let recorder = CRASH_RECORDER
+        .take()
+        .filter(|recorder| recorder.begin_report(CrashKind::KernelPanic));
+    let writer = &mut CrashRecorderWriter { writer, recorder };
+
     // Flush debug buffer if needed
     flush(writer);
     panic_banner(writer, panic_info);
@@ -132,6 +146,10 @@ pub unsafe fn panic_print<W: Write + IoWrite, C: Chip, PP: ProcessPrinter>(
         c.mpu().disable_app_mpu()
     });
     panic_process_info(processes, process_printer, writer);
+
+    if let Some(recorder) = writer.recorder {
+        panic_save_crash_report(recorder, chip);
+    }
 }
 
 /// Tock default panic routine.
@@ -222,6 +240,27 @@ pub unsafe fn panic_process_info<PP: ProcessPrinter, W: Write>(
     });
 }
 
+/// Saves the panic report to the crash recorder.
+///
+/// Interrupts are not dispatched during a panic, so this services pending
+/// interrupts and deferred calls itself until the recorder is done, or gives
+/// up after a bounded number of attempts.
+unsafe fn panic_save_crash_report<C: Chip>(
+    recorder: &dyn CrashRecorder,
+    chip: &'static Option<&'static C>,
+) {
+    if recorder.save_report().is_err() {
+        return;
+    }
+    for _ in 0..PANIC_SAVE_ITERATIONS {
+        if !recorder.is_saving() {
+            break;
+        }
+        chip.map(|c| c.service_pending_interrupts());
+        DeferredCall::service_next_pending();
+    }
+}
+
 /// Blinks a recognizable pattern forever.
 ///
 /// The LED will blink "sporadically" in a somewhat irregular pattern. This
@@ -257,6 +296,49 @@ pub fn panic_blink_forever<L: hil::led::Led>(leds: &mut [&L]) -> ! {
 // panic! support routines
 ///////////////////////////////////////////////////////////////////
 
+///////////////////////////////////////////////////////////////////
+// crash recorder support
+
+/// Bound on the number of times the panic handler services interrupts and
+/// deferred calls while waiting for the crash report to be saved.
+const PANIC_SAVE_ITERATIONS: usize = 1_000_000;
+
+static mut CRASH_RECORDER: Option<&'static dyn CrashRecorder> = None;
+
+/// Registers `recorder` to receive a copy of the report printed when the
+/// kernel panics.
+pub unsafe fn set_crash_recorder(recorder: &'static dyn CrashRecorder) {
+    CRASH_RECORDER = Some(recorder);
+}
+
+/// Passes everything written to the panic writer to the crash recorder as
+/// well.
+struct CrashRecorderWriter<'a, W: Write + IoWrite> {
+    writer: &'a mut W,
+    recorder: Option<&'static dyn CrashRecorder>,
+}
+
+impl<W: Write + IoWrite> Write for CrashRecorderWriter<'_, W> {
+    fn write_str(&mut self, s: &str) -> Result {
+        if let Some(recorder) = self.recorder {
+            recorder.write_report(s.as_bytes());
+        }
+        self.writer.write_str(s)
+    }
+}
+
+impl<W: Write + IoWrite> IoWrite for CrashRecorderWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> usize {
+        if let Some(recorder) = self.recorder {
+            recorder.write_report(buf);
+        }
+        self.writer.write(buf)
+    }
+}
+
+// crash recorder support
+///////////////////////////////////////////////////////////////////
+
 ///////////////////////////////////////////////////////////////////
 // debug_gpio! support
 
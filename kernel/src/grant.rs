@@ -1672,7 +1672,7 @@ pub struct Grant<T: Default, Upcalls: UpcallSize, AllowROs: AllowRoSize, AllowRW
     /// The identifier for this grant. Having an identifier allows the Process
     /// implementation to lookup the memory for this grant in the specific
     /// process.
-    grant_num: usize,
+    pub(crate) grant_num: usize,
 
     /// Used to store the Rust types for grant.
     ptr: PhantomData<(T, Upcalls, AllowROs, AllowRWs)>,
@@ -0,0 +1,62 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Interface for Ethernet adapters.
+//!
+//! An Ethernet adapter transmits and receives complete Ethernet II frames,
+//! starting with the destination MAC address and ending with the payload. The
+//! frame check sequence is neither provided on transmission nor included in
+//! received frames; it is expected to be handled by the hardware (or the
+//! hypervisor, for virtual adapters).
+//!
+//! The adapter does not filter received frames by destination address, so
+//! clients must discard frames not addressed to them.
+
+use crate::ErrorCode;
+
+/// Length of an Ethernet MAC address.
+pub const MAC_ADDR_LEN: usize = 6;
+
+/// Length of an Ethernet II header: destination and source MAC address, and
+/// EtherType.
+pub const ETHERNET_HDR_LEN: usize = 14;
+
+/// Maximum payload of a standard Ethernet frame (MTU).
+pub const ETHERNET_MTU: usize = 1500;
+
+/// Maximum length of a frame passed to or from an adapter.
+pub const MAX_FRAME_LEN: usize = ETHERNET_HDR_LEN + ETHERNET_MTU;
+
+/// Client of an Ethernet adapter, notified of completed transmissions and of
+/// received frames.
+pub trait EthernetAdapterClient {
+    /// A frame passed to `EthernetAdapter::transmit` has been sent, or the
+    /// transmission failed. Returns ownership of the frame buffer.
+    fn transmit_done(&self, result: Result<(), ErrorCode>, frame: &'static mut [u8]);
+
+    /// A frame has been received. The frame is only valid for the duration of
+    /// the call.
+    fn received_frame(&self, frame: &[u8]);
+}
+
+/// An Ethernet adapter able to send and receive raw frames.
+pub trait EthernetAdapter<'a> {
+    fn set_client(&self, client: &'a dyn EthernetAdapterClient);
+
+    /// Starts delivering received frames to the client.
+    fn enable_receive(&self);
+
+    /// Transmits the first `len` bytes of `frame`. If this returns `Ok(())`,
+    /// `transmit_done` will be called with the buffer once the transmission
+    /// finishes.
+    ///
+    /// Returns `BUSY` if the adapter cannot accept another frame at the
+    /// moment, and `SIZE` if `len` exceeds the buffer or the maximum frame
+    /// length.
+    fn transmit(
+        &self,
+        frame: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+}
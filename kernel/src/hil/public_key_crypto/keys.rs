@@ -283,3 +283,83 @@ pub trait RsaPrivKeyMut: PubPrivKeyMut + RsaKeyMut {
     /// the output of this function.
     fn take_exponent(&self) -> Option<&'static mut [u8]>;
 }
+
+/// Upcall from the `SetKeyBySlice` trait.
+pub trait SetKeyBySliceClient<const KL: usize> {
+    /// The `set_key()` command has been completed.
+    ///
+    /// `key` is the buffer passed to `set_key()`. On failure `result` is
+    /// `Err()` and the previous key, if any, is still in use. Valid
+    /// `ErrorCode`s include:
+    ///
+    /// - `INVAL`: The key is not valid for this algorithm.
+    fn set_key_done(&self, key: &'static mut [u8; KL], result: Result<(), ErrorCode>);
+}
+
+/// Select the key used by a public key operation, such as signing.
+///
+/// The implementation keeps its own copy of the key, so the buffer is handed
+/// back in `set_key_done()` and can be cleared or reused by the caller.
+///
+/// - `KL`: The length in bytes of the key.
+pub trait SetKeyBySlice<'a, const KL: usize> {
+    /// Set the client instance which will receive the `set_key_done()`
+    /// callback.
+    fn set_key_client(&self, client: &'a dyn SetKeyBySliceClient<KL>);
+
+    /// Use `key` for the following operations.
+    ///
+    /// If this returns `Ok(())`, then the `set_key_done()` callback will be
+    /// called. If this returns `Err()`, no callback will be called.
+    ///
+    /// The valid `ErrorCode`s that can occur are:
+    ///
+    /// - `BUSY`: An operation is already in progress.
+    fn set_key(&self, key: &'static mut [u8; KL])
+        -> Result<(), (ErrorCode, &'static mut [u8; KL])>;
+}
+
+/// Upcall from the `KeyPairGenerate` trait.
+pub trait KeyPairGenerateClient<const PL: usize, const SL: usize> {
+    /// The `generate()` command has been completed.
+    ///
+    /// `public_key` and `private_key` are the buffers passed to `generate()`.
+    /// On success they hold the new key pair. Valid `ErrorCode`s include:
+    ///
+    /// - `FAIL`: No randomness could be obtained.
+    fn generation_done(
+        &self,
+        result: Result<(), ErrorCode>,
+        public_key: &'static mut [u8; PL],
+        private_key: &'static mut [u8; SL],
+    );
+}
+
+/// Generate public/private key pairs into caller supplied buffers.
+///
+/// Unlike `PubPrivKeyGenerate` the implementation does not keep the keys, so
+/// it can generate any number of key pairs. The generated private key is not
+/// selected for later operations, use `SetKeyBySlice` for that.
+///
+/// - `PL`: The length in bytes of the public key.
+/// - `SL`: The length in bytes of the private key.
+pub trait KeyPairGenerate<'a, const PL: usize, const SL: usize> {
+    /// Set the client instance which will receive the `generation_done()`
+    /// callback.
+    fn set_generate_client(&self, client: &'a dyn KeyPairGenerateClient<PL, SL>);
+
+    /// Generate a new key pair.
+    ///
+    /// If this returns `Ok(())`, then the `generation_done()` callback will be
+    /// called. If this returns `Err()`, no callback will be called.
+    ///
+    /// The valid `ErrorCode`s that can occur are:
+    ///
+    /// - `BUSY`: An operation is already in progress.
+    /// - `OFF`: The source of randomness is powered down.
+    fn generate(
+        &self,
+        public_key: &'static mut [u8; PL],
+        private_key: &'static mut [u8; SL],
+    ) -> Result<(), (ErrorCode, &'static mut [u8; PL], &'static mut [u8; SL])>;
+}
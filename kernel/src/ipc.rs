@@ -6,12 +6,22 @@
 //!
 //! This is a special syscall driver that allows userspace applications to
 //! share memory.
+//!
+//! There are two ways to share memory. A client can allow a buffer to a
+//! service, which gets access to it for the duration of the upcall notifying
+//! the service. For long-lived pipelines, a client can instead share a region
+//! of its RAM with a service, which keeps access to the region until the
+//! client revokes it or terminates. Shared regions are recorded in the grant
+//! of both processes, and checked each time the service is switched to so
+//! that the service never runs with access to memory of a process that is no
+//! longer sharing it.
 
 use crate::capabilities::MemoryAllocationCapability;
 use crate::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
 use crate::kernel::Kernel;
+use crate::platform::mpu;
 use crate::process;
-use crate::process::ProcessId;
+use crate::process::{Process, ProcessId};
 use crate::processbuffer::ReadableProcessBuffer;
 use crate::syscall_driver::{CommandReturn, SyscallDriver};
 use crate::ErrorCode;
@@ -37,9 +47,35 @@ pub enum IPCUpcallType {
     Client,
 }
 
+/// Maximum number of regions a process can share with services, and
+/// maximum number of regions shared with a process.
+pub const MAX_SHARED_REGIONS: usize = 2;
+
+/// A region of a client's RAM that it shares with a service.
+#[derive(Copy, Clone, PartialEq, Eq)]
+struct SharedRegion {
+    service: ProcessId,
+    start: usize,
+    len: usize,
+}
+
+/// A region of another process's RAM mapped into this process.
+#[derive(Copy, Clone)]
+struct MappedRegion {
+    owner: ProcessId,
+    start: usize,
+    len: usize,
+    region: mpu::Region,
+}
+
 /// State that is stored in each process's grant region to support IPC.
 #[derive(Default)]
-struct IPCData;
+struct IPCData {
+    /// Regions of this process's RAM shared with services.
+    shared: [Option<SharedRegion>; MAX_SHARED_REGIONS],
+    /// Regions of clients' RAM this process can access.
+    mapped: [Option<MappedRegion>; MAX_SHARED_REGIONS],
+}
 
 /// The IPC mechanism struct.
 pub struct IPC<const NUM_PROCS: u8> {
@@ -80,32 +116,213 @@ impl<const NUM_PROCS: u8> IPC<NUM_PROCS> {
             return Err(process::Error::AlreadyInUse);
         }
 
-        self.data.enter(schedule_on, |_, schedule_on_data| {
-            self.data.enter(called_from, |_, called_from_data| {
-                // If the other app shared a buffer with us, make
-                // sure we have access to that slice and then call
-                // the upcall. If no slice was shared then just
-                // call the upcall.
-                let (len, ptr) = match called_from_data.get_readwrite_processbuffer(schedule_on_id)
+        self.data
+            .enter(schedule_on, |schedule_on_ipc, schedule_on_data| {
+                self.data.enter(called_from, |_, called_from_data| {
+                    // If the other app shared a buffer with us, make
+                    // sure we have access to that slice and then call
+                    // the upcall. If no slice was shared then just
+                    // call the upcall.
+                    let (len, ptr) = match called_from_data
+                        .get_readwrite_processbuffer(schedule_on_id)
+                    {
+                        Ok(slice) => {
+                            // Ensure receiving app has MPU access to sending app's
+                            // buffer, unless it lies in a region the sending app
+                            // already shares with it.
+                            let start = slice.ptr() as usize;
+                            let already_mapped = schedule_on_ipc.mapped.iter().flatten().any(|m| {
+                                m.owner == called_from
+                                    && start >= m.start
+                                    && start + slice.len() <= m.start + m.len
+                            });
+                            if !already_mapped {
+                                self.data
+                                    .kernel
+                                    .process_map_or(None, schedule_on, |process| {
+                                        process.add_mpu_region(
+                                            slice.ptr(),
+                                            slice.len(),
+                                            slice.len(),
+                                        )
+                                    });
+                            }
+                            (slice.len(), start)
+                        }
+                        Err(_) => (0, 0),
+                    };
+                    let to_schedule: usize = match cb_type {
+                        IPCUpcallType::Service => schedule_on_id,
+                        IPCUpcallType::Client => called_from_id,
+                    };
+                    let _ =
+                        schedule_on_data.schedule_upcall(to_schedule, (called_from_id, len, ptr));
+                })
+            })?
+    }
+
+    /// Update the regions shared with a process before it is switched to.
+    ///
+    /// A region stays mapped only as long as the process owning it is alive
+    /// and still shares it. Regions of clients that terminated or restarted
+    /// are removed from the process's MPU configuration here, so that they
+    /// are no longer accessible once the MPU is configured for the process.
+    pub(crate) fn update_shared_regions(&self, process: &dyn Process) {
+        // Only processes that used IPC can have regions mapped; avoid
+        // allocating the grant of every other process.
+        if process.grant_is_allocated(self.data.grant_num) != Some(true) {
+            return;
+        }
+        let processid = process.processid();
+
+        let _ = self.data.enter(processid, |data, _| {
+            for slot in data.mapped.iter_mut() {
+                if let Some(mapped) = *slot {
+                    let expected = SharedRegion {
+                        service: processid,
+                        start: mapped.start,
+                        len: mapped.len,
+                    };
+                    let still_shared = mapped.owner != processid
+                        && self
+                            .data
+                            .enter(mapped.owner, |owner_data, _| {
+                                owner_data.shared.contains(&Some(expected))
+                            })
+                            .unwrap_or(false);
+                    if !still_shared {
+                        let _ = process.remove_mpu_region(mapped.region);
+                        *slot = None;
+                    }
+                }
+            }
+        });
+    }
+
+    /// Share the buffer `client` allowed for `service` with it until revoked.
+    fn share_region(&self, client: ProcessId, service: ProcessId) -> Result<(), ErrorCode> {
+        let service_id = service.index().ok_or(ErrorCode::INVAL)?;
+
+        self.data
+            .enter(client, |client_data, client_kernel_data| {
+                let (start, len) = client_kernel_data
+                    .get_readwrite_processbuffer(service_id)
+                    .map(|slice| (slice.ptr() as usize, slice.len()))
+                    .map_err(ErrorCode::from)?;
+                if len == 0 {
+                    return Err(ErrorCode::INVAL);
+                }
+
+                // Forget regions shared with services that no longer exist.
+                for slot in client_data.shared.iter_mut() {
+                    if let Some(shared) = *slot {
+                        if self
+                            .data
+                            .kernel
+                            .process_map_or(true, shared.service, |_| false)
+                        {
+                            *slot = None;
+                        }
+                    }
+                }
+                if client_data
+                    .shared
+                    .iter()
+                    .flatten()
+                    .any(|shared| shared.service == service)
                 {
-                    Ok(slice) => {
-                        // Ensure receiving app has MPU access to sending app's buffer
-                        self.data
+                    return Err(ErrorCode::ALREADY);
+                }
+                let shared_slot = client_data
+                    .shared
+                    .iter_mut()
+                    .find(|slot| slot.is_none())
+                    .ok_or(ErrorCode::NOMEM)?;
+
+                self.data
+                    .enter(service, |service_data, _| {
+                        let mapped_slot = service_data
+                            .mapped
+                            .iter_mut()
+                            .find(|slot| slot.is_none())
+                            .ok_or(ErrorCode::NOMEM)?;
+
+                        // The region must cover exactly the shared buffer, so
+                        // that no other memory of the client is exposed.
+                        let region = self
+                            .data
                             .kernel
-                            .process_map_or(None, schedule_on, |process| {
-                                process.add_mpu_region(slice.ptr(), slice.len(), slice.len())
-                            });
-                        (slice.len(), slice.ptr() as usize)
+                            .process_map_or(None, service, |process| {
+                                process.add_mpu_region(start as *const u8, len, len)
+                            })
+                            .ok_or(ErrorCode::FAIL)?;
+                        if region.start_address() as usize != start || region.size() != len {
+                            let _ = self.data.kernel.process_map_or(
+                                Err(ErrorCode::FAIL),
+                                service,
+                                |process| process.remove_mpu_region(region),
+                            );
+                            return Err(ErrorCode::FAIL);
+                        }
+
+                        *mapped_slot = Some(MappedRegion {
+                            owner: client,
+                            start,
+                            len,
+                            region,
+                        });
+                        *shared_slot = Some(SharedRegion {
+                            service,
+                            start,
+                            len,
+                        });
+                        Ok(())
+                    })
+                    .map_err(ErrorCode::from)?
+            })
+            .map_err(ErrorCode::from)?
+    }
+
+    /// Revoke the region `client` shares with `service`.
+    fn revoke_region(&self, client: ProcessId, service: ProcessId) -> Result<(), ErrorCode> {
+        self.data
+            .enter(client, |client_data, _| {
+                let slot = client_data
+                    .shared
+                    .iter_mut()
+                    .find(|slot| slot.map_or(false, |shared| shared.service == service))
+                    .ok_or(ErrorCode::INVAL)?;
+                *slot = None;
+
+                // Unmap the region right away if the service is alive. If it
+                // is not, there is nothing left to unmap.
+                let _ = self.data.enter(service, |service_data, _| {
+                    for slot in service_data.mapped.iter_mut() {
+                        if let Some(mapped) = *slot {
+                            if mapped.owner == client {
+                                let _ = self.data.kernel.process_map_or(
+                                    Err(ErrorCode::FAIL),
+                                    service,
+                                    |process| process.remove_mpu_region(mapped.region),
+                                );
+                                *slot = None;
+                            }
+                        }
                     }
-                    Err(_) => (0, 0),
-                };
-                let to_schedule: usize = match cb_type {
-                    IPCUpcallType::Service => schedule_on_id,
-                    IPCUpcallType::Client => called_from_id,
-                };
-                let _ = schedule_on_data.schedule_upcall(to_schedule, (called_from_id, len, ptr));
+                });
+                Ok(())
+            })
+            .map_err(ErrorCode::from)?
+    }
+
+    /// Find the process with the given index, as used for target ids.
+    fn process_with_index(&self, target_id: usize) -> Option<ProcessId> {
+        self.data
+            .kernel
+            .process_until(|p| match p.processid().index() {
+                Some(i) if i == target_id => Some(p.processid()),
+                _ => None,
             })
-        })?
     }
 }
 
@@ -131,6 +348,12 @@ impl<const NUM_PROCS: u8> SyscallDriver for IPC<NUM_PROCS> {
     /// - `3`: Notify a client with descriptor `target_id`, typically in response to a previous
     ///        notify from the client. Returns an error if `target_id` refers to an invalid client
     ///        or the notify fails to enqueue.
+    /// - `4`: Share the buffer allowed for the service `target_id` with it until revoked. The
+    ///        service can access the buffer even after it is unallowed. The buffer must be
+    ///        suitably aligned for the MPU to cover exactly the buffer, otherwise FAIL is
+    ///        returned. Returns ALREADY if a region is already shared with the service.
+    /// - `5`: Revoke the region shared with the service `target_id`.
+    /// - `6`: Get the address and length of the region shared by the client `target_id`.
     fn command(
         &self,
         command_number: usize,
@@ -180,13 +403,7 @@ impl<const NUM_PROCS: u8> SyscallDriver for IPC<NUM_PROCS> {
             {
                 let cb_type = IPCUpcallType::Service;
 
-                let other_process =
-                    self.data
-                        .kernel
-                        .process_until(|p| match p.processid().index() {
-                            Some(i) if i == target_id => Some(p.processid()),
-                            _ => None,
-                        });
+                let other_process = self.process_with_index(target_id);
 
                 other_process.map_or(CommandReturn::failure(ErrorCode::INVAL), |otherapp| {
                     self.data.kernel.process_map_or(
@@ -213,13 +430,7 @@ impl<const NUM_PROCS: u8> SyscallDriver for IPC<NUM_PROCS> {
             {
                 let cb_type = IPCUpcallType::Client;
 
-                let other_process =
-                    self.data
-                        .kernel
-                        .process_until(|p| match p.processid().index() {
-                            Some(i) if i == target_id => Some(p.processid()),
-                            _ => None,
-                        });
+                let other_process = self.process_with_index(target_id);
 
                 other_process.map_or(CommandReturn::failure(ErrorCode::INVAL), |otherapp| {
                     self.data.kernel.process_map_or(
@@ -241,6 +452,53 @@ impl<const NUM_PROCS: u8> SyscallDriver for IPC<NUM_PROCS> {
                     )
                 })
             }
+            4 =>
+            /* Share region */
+            {
+                match self.process_with_index(target_id) {
+                    Some(service) if service != processid => {
+                        self.share_region(processid, service).into()
+                    }
+                    _ => CommandReturn::failure(ErrorCode::INVAL),
+                }
+            }
+            5 =>
+            /* Revoke region */
+            {
+                // The service may have terminated, in which case only its
+                // index identifies the region to revoke.
+                let service = self
+                    .data
+                    .enter(processid, |data, _| {
+                        data.shared
+                            .iter()
+                            .flatten()
+                            .map(|shared| shared.service)
+                            .find(|service| service.index() == Some(target_id))
+                    })
+                    .unwrap_or(None);
+                service.map_or(CommandReturn::failure(ErrorCode::INVAL), |service| {
+                    self.revoke_region(processid, service).into()
+                })
+            }
+            6 =>
+            /* Get shared region */
+            {
+                self.data
+                    .enter(processid, |data, _| {
+                        data.mapped
+                            .iter()
+                            .flatten()
+                            .find(|mapped| mapped.owner.index() == Some(target_id))
+                            .map_or(CommandReturn::failure(ErrorCode::INVAL), |mapped| {
+                                CommandReturn::success_u32_u32(
+                                    mapped.start as u32,
+                                    mapped.len as u32,
+                                )
+                            })
+                    })
+                    .unwrap_or(CommandReturn::failure(ErrorCode::NOMEM))
+            }
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
         }
     }
@@ -32,8 +32,9 @@ use crate::syscall::SyscallDriver;
 use crate::syscall::{ContextSwitchReason, SyscallReturn};
 use crate::syscall::{Syscall, YieldCall};
 use crate::syscall_driver::CommandReturn;
+use crate::syscall_trace::SyscallTracer;
 use crate::upcall::{Upcall, UpcallId};
-use crate::utilities::cells::NumericCellExt;
+use crate::utilities::cells::{NumericCellExt, OptionalCell};
 
 /// Threshold in microseconds to consider a process's timeslice to be exhausted.
 /// That is, Tock will skip re-scheduling a process if its remaining timeslice
@@ -59,6 +60,13 @@ pub struct Kernel {
     /// created and the data structures for grants have already been
     /// established.
     grants_finalized: Cell<bool>,
+
+    /// Optional tracer every handled system call is reported to.
+    syscall_tracer: OptionalCell<&'static dyn SyscallTracer>,
+
+    /// Optional loader of processes while the kernel is running, used to
+    /// unload processes.
+    process_loader: OptionalCell<&'static dyn process::DynamicProcessLoading<'static>>,
 }
 
 /// Represents the different outcomes when trying to allocate a grant region
@@ -93,9 +101,68 @@ impl Kernel {
             process_identifier_max: Cell::new(0),
             grant_counter: Cell::new(0),
             grants_finalized: Cell::new(false),
+            syscall_tracer: OptionalCell::empty(),
+            process_loader: OptionalCell::empty(),
         }
     }
 
+    /// Set the tracer that is told about every system call the kernel
+    /// handles, along with the value returned to the process.
+    pub fn set_syscall_tracer(
+        &self,
+        tracer: &'static dyn SyscallTracer,
+        _capability: &dyn capabilities::ProcessManagementCapability,
+    ) {
+        self.syscall_tracer.set(tracer);
+    }
+
+    /// Set the loader that processes are unloaded with, for boards that load
+    /// processes while the kernel is running.
+    pub fn set_dynamic_process_loader(
+        &self,
+        loader: &'static dyn process::DynamicProcessLoading<'static>,
+        _capability: &dyn capabilities::ProcessManagementCapability,
+    ) {
+        self.process_loader.set(loader);
+    }
+
+    /// Unload the process `processid`: terminate it, free its slot in the
+    /// processes array, and reclaim its RAM for the processes loaded
+    /// afterwards.
+    ///
+    /// Returns the address and size of its process binary in flash. The caller
+    /// must erase it by replacing its TBF header with the header of a padding
+    /// entry of the same size (see `process::padding_tbf_header()`), after
+    /// which the process loader writes new process binaries there.
+    ///
+    /// `processid` must not be the process whose system call is being
+    /// handled, as the kernel still uses its RAM until the system call
+    /// returns. Returns `NODEVICE` if the board did not set a process loader
+    /// with `set_dynamic_process_loader()`, and `INVAL` if the process does
+    /// not exist.
+    pub fn unload_process(
+        &self,
+        processid: ProcessId,
+        _capability: &dyn capabilities::ProcessManagementCapability,
+    ) -> Result<(usize, usize), ErrorCode> {
+        self.process_loader
+            .map_or(Err(ErrorCode::NODEVICE), |loader| {
+                loader.unload_process(processid)
+            })
+    }
+
+    /// Report a handled system call to the tracer, if there is one.
+    fn trace_syscall(
+        &self,
+        process: &dyn process::Process,
+        syscall: &Syscall,
+        result: Option<&SyscallReturn>,
+    ) {
+        self.syscall_tracer.map(|tracer| {
+            tracer.trace_syscall(process.processid(), syscall, result);
+        });
+    }
+
     /// Helper function that moves all non-generic portions of process_map_or
     /// into a non-generic function to reduce code bloat from monomorphization.
     pub(crate) fn get_process(&self, processid: ProcessId) -> Option<&dyn process::Process> {
@@ -548,6 +615,9 @@ impl Kernel {
                     resources
                         .context_switch_callback()
                         .context_switch_hook(process);
+                    if let Some(ipc) = ipc {
+                        ipc.update_shared_regions(process);
+                    }
                     process.setup_mpu();
                     chip.mpu().enable_app_mpu();
                     scheduler_timer.arm();
@@ -772,6 +842,7 @@ impl Kernel {
                 if let Err(response) = resources.syscall_filter().filter_syscall(process, &syscall)
                 {
                     process.set_syscall_return_value(SyscallReturn::Failure(response));
+                    self.trace_syscall(process, &syscall, Some(&SyscallReturn::Failure(response)));
 
                     if config::CONFIG.trace_syscalls {
                         debug!(
@@ -800,6 +871,7 @@ impl Kernel {
                         rval
                     );
                 }
+                self.trace_syscall(process, &syscall, Some(&rval));
                 process.set_syscall_return_value(rval);
             }
             Syscall::Yield {
@@ -810,6 +882,7 @@ impl Kernel {
                 if config::CONFIG.trace_syscalls {
                     debug!("[{:?}] yield. which: {}", process.processid(), which);
                 }
+                self.trace_syscall(process, &syscall, None);
                 match which.try_into() {
                     Ok(YieldCall::NoWait) => {
                         // If this is a `Yield-WaitFor` AND there are no pending
@@ -1016,6 +1089,7 @@ impl Kernel {
                             );
                         }
 
+                        self.trace_syscall(process, &syscall, Some(&rval));
                         process.set_syscall_return_value(rval);
                     }
                     Syscall::Command {
@@ -1042,6 +1116,7 @@ impl Kernel {
                                 res,
                             );
                         }
+                        self.trace_syscall(process, &syscall, Some(&res));
                         process.set_syscall_return_value(res);
                     }
                     Syscall::ReadWriteAllow {
@@ -1167,6 +1242,7 @@ impl Kernel {
                                 res
                             );
                         }
+                        self.trace_syscall(process, &syscall, Some(&res));
                         process.set_syscall_return_value(res);
                     }
                     Syscall::UserspaceReadableAllow {
@@ -1247,6 +1323,7 @@ impl Kernel {
                                 res
                             );
                         }
+                        self.trace_syscall(process, &syscall, Some(&res));
                         process.set_syscall_return_value(res);
                     }
                     Syscall::ReadOnlyAllow {
@@ -1373,6 +1450,7 @@ impl Kernel {
                             );
                         }
 
+                        self.trace_syscall(process, &syscall, Some(&res));
                         process.set_syscall_return_value(res);
                     }
                     Syscall::Yield { .. }
@@ -1387,15 +1465,19 @@ impl Kernel {
             Syscall::Exit {
                 which,
                 completion_code,
-            } => match which {
-                // The process called the `exit-terminate` system call.
-                0 => process.terminate(Some(completion_code as u32)),
-                // The process called the `exit-restart` system call.
-                1 => process.try_restart(Some(completion_code as u32)),
-                // The process called an invalid variant of the Exit
-                // system call class.
-                _ => process.set_syscall_return_value(SyscallReturn::Failure(ErrorCode::NOSUPPORT)),
-            },
+            } => {
+                self.trace_syscall(process, &syscall, None);
+                match which {
+                    // The process called the `exit-terminate` system call.
+                    0 => process.terminate(Some(completion_code as u32)),
+                    // The process called the `exit-restart` system call.
+                    1 => process.try_restart(Some(completion_code as u32)),
+                    // The process called an invalid variant of the Exit
+                    // system call class.
+                    _ => process
+                        .set_syscall_return_value(SyscallReturn::Failure(ErrorCode::NOSUPPORT)),
+                }
+            }
         }
     }
 }
@@ -107,6 +107,8 @@ pub const KERNEL_MINOR_VERSION: u16 = 1;
 pub mod capabilities;
 pub mod collections;
 pub mod component;
+pub mod core_dump;
+pub mod crash_log;
 pub mod debug;
 pub mod deferred_call;
 pub mod errorcode;
@@ -121,6 +123,7 @@ pub mod processbuffer;
 pub mod scheduler;
 pub mod storage_permissions;
 pub mod syscall;
+pub mod syscall_trace;
 pub mod upcall;
 pub mod utilities;
 
@@ -28,7 +28,11 @@ pub use crate::process_checker::{ProcessCheckerMachine, ProcessCheckerMachineCli
 pub use crate::process_loading::load_processes;
 pub use crate::process_loading::ProcessLoadError;
 pub use crate::process_loading::SequentialProcessLoaderMachine;
-pub use crate::process_loading::{ProcessLoadingAsync, ProcessLoadingAsyncClient};
+pub use crate::process_loading::{padding_tbf_header, PADDING_TBF_HEADER_LEN};
+pub use crate::process_loading::{
+    DynamicProcessLoading, DynamicProcessLoadingClient, ProcessLoadingAsync,
+    ProcessLoadingAsyncClient,
+};
 pub use crate::process_policies::{ProcessFaultPolicy, ProcessStandardStoragePermissionsPolicy};
 pub use crate::process_printer::{ProcessPrinter, ProcessPrinterContext};
 pub use crate::process_standard::ProcessStandard;
@@ -322,6 +326,11 @@ impl BinaryVersion {
     pub fn new(value: NonZeroU32) -> Self {
         Self(value)
     }
+
+    /// Returns the version number.
+    pub fn get(&self) -> u32 {
+        self.0.get()
+    }
 }
 
 /// This trait represents a generic process that the Tock scheduler can
@@ -659,6 +668,12 @@ pub trait Process {
     /// Returns `None` if the process has no storage permissions.
     fn get_storage_permissions(&self) -> storage_permissions::StoragePermissions;
 
+    /// Get the scheduling parameters the process requested in its TBF header,
+    /// as a `(period_us, budget_us)` tuple.
+    ///
+    /// Returns `None` if the process did not request periodic scheduling.
+    fn get_scheduling_parameters(&self) -> Option<(u32, u32)>;
+
     // mpu
 
     /// Configure the MPU to use the process's allocated regions.
@@ -783,6 +798,12 @@ pub trait Process {
     /// if there is a grant associated with that driver_num.
     fn lookup_grant_from_driver_num(&self, driver_num: usize) -> Result<usize, Error>;
 
+    /// Return where the grant `grant_num` is allocated in the grant region,
+    /// if the process is active and the grant has been allocated.
+    ///
+    /// Useful for debugging/inspecting the system.
+    fn grant_allocation(&self, grant_num: usize) -> Option<GrantAllocation>;
+
     // subscribe
 
     /// Verify that an upcall function pointer is within process-accessible
@@ -840,6 +861,14 @@ pub trait Process {
     /// binary representation. Returns `ErrorCode::FAIL` on an internal error.
     fn get_stored_state(&self, out: &mut [u8]) -> Result<usize, ErrorCode>;
 
+    /// Copy the process memory starting at `address` into `out`. Only memory
+    /// the process can access is readable: its RAM below the application
+    /// break and its region of flash. Grant memory cannot be read.
+    ///
+    /// Returns `ErrorCode::INVAL` if any byte of the range is outside of the
+    /// readable memory.
+    fn read_memory(&self, address: usize, out: &mut [u8]) -> Result<(), ErrorCode>;
+
     /// Print out the full state of the process: its memory map, its context,
     /// and the state of the memory protection unit (MPU).
     fn print_full_process(&self, writer: &mut dyn Write);
@@ -1152,6 +1181,19 @@ pub struct ProcessAddresses {
     pub sram_stack_bottom: Option<usize>,
 }
 
+/// Location of an allocated grant in the grant region of a process.
+#[derive(Clone, Copy, Debug)]
+pub struct GrantAllocation {
+    /// The driver number the grant belongs to.
+    pub driver_num: usize,
+    /// The address of the start of the grant.
+    pub address: usize,
+    /// The number of bytes from the start of the grant to the next allocation
+    /// above it in the grant region. Besides the grant itself, this includes
+    /// alignment padding and any custom grant allocated right after it.
+    pub size: usize,
+}
+
 /// Collection of process state related to the size in memory of various process
 /// structures.
 pub struct ProcessSizes {
@@ -14,6 +14,7 @@
 
 use core::cell::Cell;
 use core::fmt;
+use core::num::NonZeroU32;
 
 use crate::capabilities::ProcessManagementCapability;
 use crate::config;
@@ -21,14 +22,15 @@ use crate::debug;
 use crate::deferred_call::{DeferredCall, DeferredCallClient};
 use crate::kernel::Kernel;
 use crate::platform::chip::Chip;
-use crate::process::{Process, ShortId};
+use crate::process::{BinaryVersion, Process, ProcessId, ShortId};
 use crate::process_binary::{ProcessBinary, ProcessBinaryError};
 use crate::process_checker::AcceptedCredential;
 use crate::process_checker::{AppIdPolicy, ProcessCheckError, ProcessCheckerMachine};
 use crate::process_policies::ProcessFaultPolicy;
 use crate::process_policies::ProcessStandardStoragePermissionsPolicy;
 use crate::process_standard::ProcessStandard;
-use crate::utilities::cells::{MapCell, OptionalCell};
+use crate::utilities::cells::{MapCell, OptionalCell, TakeCell};
+use crate::ErrorCode;
 
 /// Errors that can occur when trying to load and create processes.
 pub enum ProcessLoadError {
@@ -64,6 +66,16 @@ pub enum ProcessLoadError {
     /// Process loading failed because checking the process failed.
     CheckError(ProcessCheckError),
 
+    /// A process with the same AppID or ShortId is already loaded.
+    AppIdConflict,
+
+    /// The process binary would replace a process with a different AppID.
+    AppIdMismatch,
+
+    /// The process binary would replace a process whose binary version is the
+    /// same or newer.
+    Downgrade,
+
     /// Process loading error due (likely) to a bug in the kernel. If you get
     /// this error please open a bug report.
     InternalError,
@@ -107,11 +119,37 @@ impl fmt::Debug for ProcessLoadError {
                 write!(f, "{:?}", check_error)
             }
 
+            ProcessLoadError::AppIdConflict => {
+                write!(f, "A process with the same AppID is already loaded")
+            }
+
+            ProcessLoadError::AppIdMismatch => {
+                write!(f, "The process to replace has a different AppID")
+            }
+
+            ProcessLoadError::Downgrade => {
+                write!(f, "The process to replace is not older")
+            }
+
             ProcessLoadError::InternalError => write!(f, "Error in kernel. Likely a bug."),
         }
     }
 }
 
+/// Convert a process loading error into the `ErrorCode` reported to whoever
+/// asked for a process to be loaded at runtime.
+impl From<ProcessLoadError> for ErrorCode {
+    fn from(err: ProcessLoadError) -> ErrorCode {
+        match err {
+            ProcessLoadError::NotEnoughMemory | ProcessLoadError::NoProcessSlot => ErrorCode::NOMEM,
+            ProcessLoadError::BinaryError(_) | ProcessLoadError::AppIdMismatch => ErrorCode::INVAL,
+            ProcessLoadError::CheckError(_) => ErrorCode::NOSUPPORT,
+            ProcessLoadError::AppIdConflict | ProcessLoadError::Downgrade => ErrorCode::ALREADY,
+            _ => ErrorCode::FAIL,
+        }
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 // SYNCHRONOUS PROCESS LOADING
 ////////////////////////////////////////////////////////////////////////////////
@@ -413,6 +451,61 @@ fn load_process<C: Chip>(
     Ok((unused_memory, process_option))
 }
 
+/// Find the TBF entry at the start of `flash`.
+///
+/// Returns the flash of the entry, the version of its TBF header and the
+/// length of its header. An entry whose header cannot be parsed but gives its
+/// length is returned with a version and header length of 0, so that it can
+/// be skipped.
+fn tbf_entry(flash: &'static [u8]) -> Result<(&'static [u8], u16, u16), ProcessBinaryError> {
+    // If this fails, not enough remaining flash to check for an app.
+    let test_header_slice = flash.get(0..8).ok_or(ProcessBinaryError::NotEnoughFlash)?;
+
+    // Pass the first eight bytes to tbfheader to parse out the length of
+    // the tbf header and app. We then use those values to see if we have
+    // enough flash remaining to parse the remainder of the header.
+    //
+    // Start by converting [u8] to [u8; 8].
+    let header = test_header_slice
+        .try_into()
+        .or(Err(ProcessBinaryError::NotEnoughFlash))?;
+
+    let (version, header_length, app_length) =
+        match tock_tbf::parse::parse_tbf_header_lengths(header) {
+            Ok((v, hl, el)) => (v, hl, el),
+            Err(tock_tbf::types::InitialTbfParseError::InvalidHeader(app_length)) => {
+                // If we could not parse the header, then we want to skip over
+                // this app and look for the next one.
+                (0, 0, app_length)
+            }
+            Err(tock_tbf::types::InitialTbfParseError::UnableToParse) => {
+                // Since Tock apps use a linked list, it is very possible the
+                // header we started to parse is intentionally invalid to signal
+                // the end of apps. This is ok and just means we have finished
+                // loading apps.
+                return Err(ProcessBinaryError::TbfHeaderNotFound);
+            }
+        };
+
+    // Now we can get a slice which only encompasses the length of flash
+    // described by this tbf header.  We will either parse this as an actual
+    // app, or skip over this region.
+    let app_flash = flash
+        .get(0..app_length as usize)
+        .ok_or(ProcessBinaryError::NotEnoughFlash)?;
+
+    Ok((app_flash, version, header_length))
+}
+
+/// Check if the TBF entry `app_flash`, with the given header version and
+/// length, is a padding entry.
+fn is_padding(app_flash: &'static [u8], version: u16, header_length: u16) -> bool {
+    app_flash
+        .get(0..header_length as usize)
+        .and_then(|header| tock_tbf::parse::parse_tbf_header(header, version).ok())
+        .map_or(false, |header| !header.is_app())
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 // ASYNCHRONOUS PROCESS LOADING
 ////////////////////////////////////////////////////////////////////////////////
@@ -450,6 +543,123 @@ pub trait ProcessLoadingAsync<'a> {
     fn start(&self);
 }
 
+/// Client for the loading of process binaries written after boot.
+pub trait DynamicProcessLoadingClient {
+    /// Loading the process binary requested with `load_new_process_binary()`
+    /// or `upgrade_process()` finished with `result`.
+    fn load_done(&self, result: Result<(), ProcessLoadError>);
+}
+
+/// Size of the TBF header of a padding entry, which only holds the base
+/// header.
+pub const PADDING_TBF_HEADER_LEN: usize = 16;
+
+/// Build the TBF header of a padding entry covering `size` bytes of app flash,
+/// including the header itself.
+///
+/// Padding entries are skipped when processes are loaded, while the entries
+/// after them are still found.
+pub fn padding_tbf_header(size: u32) -> [u8; PADDING_TBF_HEADER_LEN] {
+    let version_and_header_size = 2u32 | ((PADDING_TBF_HEADER_LEN as u32) << 16);
+    let flags = 0u32;
+    let checksum = version_and_header_size ^ size ^ flags;
+    let mut header = [0; PADDING_TBF_HEADER_LEN];
+    for (i, word) in [version_and_header_size, size, flags, checksum]
+        .iter()
+        .enumerate()
+    {
+        header[i * 4..(i + 1) * 4].copy_from_slice(&word.to_le_bytes());
+    }
+    header
+}
+
+/// Loading of process binaries written to flash while the kernel is running.
+///
+/// New process binaries are written to free regions of the app flash, so
+/// that they are also found when the board boots. A free region is a run of
+/// padding entries, possibly followed by the unused flash after the last
+/// entry, or the unused flash alone. Once a process binary has been written
+/// there, it is checked and loaded like the process binaries found at boot,
+/// and the new process is started without a reboot. The outcome is signaled
+/// to the `DynamicProcessLoadingClient`, not to the client of
+/// `ProcessLoadingAsync`.
+///
+/// Running processes can also be unloaded, or replaced by a newer version of
+/// the same application. Callers then erase the old process binary by
+/// replacing its TBF header with the header of a padding entry of the same
+/// size (see `padding_tbf_header()`), which makes its flash free again. The
+/// RAM of unloaded and replaced processes is given to the processes loaded
+/// afterwards.
+///
+/// Only one caller should write a new process binary at a time, as a free
+/// region is returned by `free_flash()` until a process binary is written to
+/// it.
+pub trait DynamicProcessLoading<'a> {
+    /// Set the client notified when loading a process binary written after
+    /// boot finishes.
+    fn set_load_client(&self, client: &'a dyn DynamicProcessLoadingClient);
+
+    /// Returns the first free region of app flash in which a new process
+    /// binary of `length` bytes can be written. Returns `SIZE` if there is
+    /// none, and `BUSY` while processes are being loaded.
+    ///
+    /// The region may be longer than `length`. The caller must then write the
+    /// TBF header of a padding entry covering the rest of the region, at
+    /// offset `length`, before writing the process binary, so that the
+    /// entries after the region are still found. The rest of the region is
+    /// always large enough to hold that header.
+    fn free_flash(&self, length: usize) -> Result<&'static [u8], ErrorCode>;
+
+    /// Check and load the process binary written at `address`, the start of
+    /// a region returned by `free_flash()`. Returns `INVAL` if `address` is
+    /// not in the app flash or is in the process binary of a running process,
+    /// and `BUSY` if processes are being loaded.
+    ///
+    /// If the process binary is rejected, the caller should replace its TBF
+    /// header with the header of a padding entry of the same size, so that
+    /// it is not found at the next boot and its flash is free again.
+    fn load_new_process_binary(&self, address: usize) -> Result<(), ErrorCode>;
+
+    /// Replace the process `processid` by the process binary written at
+    /// `address`, as with `load_new_process_binary()`.
+    ///
+    /// The new process binary must have the same AppID as the process it
+    /// replaces and a newer binary version, and must not share its AppID or
+    /// ShortId with any other process. The old process keeps running unless
+    /// the new one is loaded successfully.
+    ///
+    /// Returns the address and size of the process binary of `processid`.
+    /// Once the upgrade succeeds, the caller should erase it, so that the old
+    /// version is skipped at the next boot and its flash is free again.
+    ///
+    /// `processid` must not be the process on whose behalf this is called, as
+    /// its RAM is reused once it is replaced.
+    fn upgrade_process(
+        &self,
+        processid: ProcessId,
+        address: usize,
+    ) -> Result<(usize, usize), ErrorCode>;
+
+    /// Terminate the process `processid`, free its slot in the `PROCESSES`
+    /// array and reclaim its RAM.
+    ///
+    /// Returns the address and size of its process binary, which the caller
+    /// should erase so that it is not loaded at the next boot and its flash
+    /// is free again.
+    ///
+    /// As with `upgrade_process()`, `processid` must not be the process on
+    /// whose behalf this is called.
+    ///
+    /// Boards usually do not call this directly, but through
+    /// `Kernel::unload_process()`.
+    fn unload_process(&self, processid: ProcessId) -> Result<(usize, usize), ErrorCode>;
+}
+
+/// Number of regions of RAM freed by unloaded or replaced processes that the
+/// loader keeps track of. The RAM of a process that does not fit is not
+/// reused until the board reboots.
+const RECLAIMED_MEMORY_REGIONS: usize = 4;
+
 /// Operating mode of the loader.
 #[derive(Clone, Copy)]
 enum SequentialProcessLoaderMachineState {
@@ -457,6 +667,11 @@ enum SequentialProcessLoaderMachineState {
     DiscoverProcessBinaries,
     /// Phase of loading `ProcessBinary`s into `Process`s.
     LoadProcesses,
+    /// Checking and loading a process binary written after boot.
+    LoadNewProcessBinary,
+    /// Checking a process binary written after boot and swapping it in place
+    /// of a running process.
+    UpgradeProcess(ProcessId),
 }
 
 /// A machine for loading processes stored sequentially in a region of flash.
@@ -468,6 +683,9 @@ enum SequentialProcessLoaderMachineState {
 pub struct SequentialProcessLoaderMachine<'a, C: Chip + 'static> {
     /// Client to notify as processes are loaded and process loading finishes.
     client: OptionalCell<&'a dyn ProcessLoadingAsyncClient>,
+    /// Client to notify when loading a process binary written after boot
+    /// finishes.
+    load_client: OptionalCell<&'a dyn DynamicProcessLoadingClient>,
     /// Machine to use to check process credentials.
     checker: &'static ProcessCheckerMachine,
     /// Array of stored process references for loaded processes.
@@ -476,8 +694,16 @@ pub struct SequentialProcessLoaderMachine<'a, C: Chip + 'static> {
     proc_binaries: MapCell<&'static mut [Option<ProcessBinary>]>,
     /// Flash memory region to load processes from.
     flash: Cell<&'static [u8]>,
+    /// The whole app flash, in which process binaries are written after boot.
+    app_flash: &'static [u8],
+    /// Flash memory region starting with the process binary written after
+    /// boot that is being loaded.
+    new_binary_flash: OptionalCell<&'static [u8]>,
     /// Memory available to assign to applications.
     app_memory: Cell<&'static mut [u8]>,
+    /// RAM of unloaded or replaced processes that does not directly precede
+    /// `app_memory`. No two regions are adjacent.
+    reclaimed_memory: [TakeCell<'static, [u8]>; RECLAIMED_MEMORY_REGIONS],
     /// Mechanism for generating async callbacks.
     deferred_call: DeferredCall,
     /// Reference to the kernel object for creating Processes.
@@ -516,12 +742,16 @@ impl<'a, C: Chip> SequentialProcessLoaderMachine<'a, C> {
             deferred_call: DeferredCall::new(),
             checker,
             client: OptionalCell::empty(),
+            load_client: OptionalCell::empty(),
             procs: MapCell::new(procs),
             proc_binaries: MapCell::new(proc_binaries),
             kernel,
             chip,
             flash: Cell::new(flash),
+            app_flash: flash,
+            new_binary_flash: OptionalCell::empty(),
             app_memory: Cell::new(app_memory),
+            reclaimed_memory: core::array::from_fn(|_| TakeCell::empty()),
             policy: OptionalCell::new(policy),
             fault_policy,
             storage_policy,
@@ -604,41 +834,7 @@ impl<'a, C: Chip> SequentialProcessLoaderMachine<'a, C> {
             );
         }
 
-        // If this fails, not enough remaining flash to check for an app.
-        let test_header_slice = flash.get(0..8).ok_or(ProcessBinaryError::NotEnoughFlash)?;
-
-        // Pass the first eight bytes to tbfheader to parse out the length of
-        // the tbf header and app. We then use those values to see if we have
-        // enough flash remaining to parse the remainder of the header.
-        //
-        // Start by converting [u8] to [u8; 8].
-        let header = test_header_slice
-            .try_into()
-            .or(Err(ProcessBinaryError::NotEnoughFlash))?;
-
-        let (version, header_length, app_length) =
-            match tock_tbf::parse::parse_tbf_header_lengths(header) {
-                Ok((v, hl, el)) => (v, hl, el),
-                Err(tock_tbf::types::InitialTbfParseError::InvalidHeader(app_length)) => {
-                    // If we could not parse the header, then we want to skip over
-                    // this app and look for the next one.
-                    (0, 0, app_length)
-                }
-                Err(tock_tbf::types::InitialTbfParseError::UnableToParse) => {
-                    // Since Tock apps use a linked list, it is very possible the
-                    // header we started to parse is intentionally invalid to signal
-                    // the end of apps. This is ok and just means we have finished
-                    // loading apps.
-                    return Err(ProcessBinaryError::TbfHeaderNotFound);
-                }
-            };
-
-        // Now we can get a slice which only encompasses the length of flash
-        // described by this tbf header.  We will either parse this as an actual
-        // app, or skip over this region.
-        let app_flash = flash
-            .get(0..app_length as usize)
-            .ok_or(ProcessBinaryError::NotEnoughFlash)?;
+        let (app_flash, version, header_length) = tbf_entry(flash)?;
 
         // Advance the flash slice for process discovery beyond this last entry.
         // This will be the start of where we look for a new process since Tock
@@ -798,6 +994,268 @@ impl<'a, C: Chip> SequentialProcessLoaderMachine<'a, C> {
         Ok(())
     }
 
+    /// Create and start a process from a process binary written after boot.
+    ///
+    /// Unlike at boot, a new process binary with the same AppID or ShortId as
+    /// a running process does not replace it, regardless of the version.
+    fn load_new_process_object(
+        &self,
+        process_binary: ProcessBinary,
+    ) -> Result<(), ProcessLoadError> {
+        let blocked = self.procs.map_or(false, |procs| {
+            procs
+                .iter()
+                .flatten()
+                .any(|p| self.is_blocked_from_loading_by_process(&process_binary, *p))
+        });
+        if blocked {
+            return Err(ProcessLoadError::AppIdConflict);
+        }
+
+        let index = self
+            .find_open_process_slot()
+            .ok_or(ProcessLoadError::NoProcessSlot)?;
+        let process = self.create_process(process_binary, index)?;
+        // Storing the process in the `PROCESSES` array makes it visible to the
+        // scheduler, which starts it.
+        self.procs.map(|procs| {
+            procs[index] = Some(process);
+        });
+        Ok(())
+    }
+
+    /// Create a process from a process binary written after boot, and swap it
+    /// in place of the running process `processid`.
+    fn upgrade_process_object(
+        &self,
+        process_binary: ProcessBinary,
+        processid: ProcessId,
+    ) -> Result<(), ProcessLoadError> {
+        let (index, old) = self
+            .find_process(processid)
+            .ok_or(ProcessLoadError::InternalError)?;
+
+        // Without an AppID policy, processes cannot be told apart and any
+        // process binary may replace any process.
+        let same_app = self.policy.map_or(true, |policy| {
+            !policy.different_identifier_process(&process_binary, old)
+        });
+        if !same_app {
+            return Err(ProcessLoadError::AppIdMismatch);
+        }
+
+        let version =
+            NonZeroU32::new(process_binary.header.get_binary_version()).map(BinaryVersion::new);
+        if version <= old.binary_version() {
+            return Err(ProcessLoadError::Downgrade);
+        }
+
+        let blocked = self.procs.map_or(false, |procs| {
+            procs
+                .iter()
+                .flatten()
+                .filter(|p| p.processid() != processid)
+                .any(|p| self.is_blocked_from_loading_by_process(&process_binary, *p))
+        });
+        if blocked {
+            return Err(ProcessLoadError::AppIdConflict);
+        }
+
+        // The new process takes the slot of the old one. Until it is stored
+        // there, the old process keeps running.
+        let process = self.create_process(process_binary, index)?;
+        old.terminate(None);
+        self.procs.map(|procs| {
+            procs[index] = Some(process);
+        });
+        if config::CONFIG.debug_load_processes {
+            debug!(
+                "Loading: Replaced process {} at index {}",
+                old.get_process_name(),
+                index
+            );
+        }
+        self.reclaim_memory(old);
+        Ok(())
+    }
+
+    /// Create a process from a process binary written after boot, using the
+    /// process slot `index`. The process is not stored in the `PROCESSES`
+    /// array.
+    fn create_process(
+        &self,
+        process_binary: ProcessBinary,
+        index: usize,
+    ) -> Result<&'static dyn Process, ProcessLoadError> {
+        let short_app_id = self.policy.map_or(ShortId::LocallyUnique, |policy| {
+            policy.to_short_id(&process_binary)
+        });
+
+        // The process binary is consumed by loading it, so there is a single
+        // attempt: the process is given the largest region of RAM, which is
+        // the most likely to fit it.
+        let largest_reclaimed = self
+            .reclaimed_memory
+            .iter()
+            .filter(|region| region.map_or(0, |memory| memory.len()) > 0)
+            .max_by_key(|region| region.map_or(0, |memory| memory.len()));
+        let app_memory = self.app_memory.take();
+        let (memory, reclaimed) = match largest_reclaimed {
+            Some(region) if region.map_or(0, |memory| memory.len()) > app_memory.len() => {
+                self.app_memory.set(app_memory);
+                (region.take().unwrap_or(&mut []), Some(region))
+            }
+            _ => (app_memory, None),
+        };
+        let put_back = |new_mem: &'static mut [u8]| match reclaimed {
+            Some(region) => {
+                region.replace(new_mem);
+            }
+            None => self.app_memory.set(new_mem),
+        };
+
+        match load_process(
+            self.kernel,
+            self.chip,
+            process_binary,
+            memory,
+            short_app_id,
+            index,
+            self.fault_policy,
+            self.storage_policy,
+        ) {
+            Ok((new_mem, proc)) => {
+                put_back(new_mem);
+                // Disabled process binaries are rejected when they are
+                // discovered, so this is not expected.
+                let p = proc.ok_or(ProcessLoadError::BinaryError(
+                    ProcessBinaryError::NotEnabledProcess,
+                ))?;
+                if config::CONFIG.debug_load_processes {
+                    debug!("Loading: Loaded new process {}", p.get_process_name());
+                }
+                Ok(p)
+            }
+            Err((new_mem, err)) => {
+                put_back(new_mem);
+                Err(err)
+            }
+        }
+    }
+
+    /// Find the slot of the process `processid` in the `PROCESSES` array.
+    fn find_process(&self, processid: ProcessId) -> Option<(usize, &'static dyn Process)> {
+        self.procs.map_or(None, |procs| {
+            procs.iter().enumerate().find_map(|(i, p)| match p {
+                Some(p) if p.processid() == processid => Some((i, *p)),
+                _ => None,
+            })
+        })
+    }
+
+    /// Signal the outcome of loading a process binary written after boot.
+    fn new_process_binary_done(&self, result: Result<(), ProcessLoadError>) {
+        if config::CONFIG.debug_load_processes {
+            if let Err(e) = &result {
+                debug!("Loading: Could not load new process: {:?}", e);
+            }
+        }
+        self.new_binary_flash.clear();
+        self.state.clear();
+        self.load_client.map(|client| {
+            client.load_done(result);
+        });
+    }
+
+    /// Reclaim the RAM of the terminated process `process`, so that it is
+    /// given to the processes loaded afterwards.
+    ///
+    /// The process must not be stored in the `PROCESSES` array anymore, and
+    /// must not be in use by the kernel, as its RAM holds its grants and
+    /// process object.
+    fn reclaim_memory(&self, process: &dyn Process) {
+        let addresses = process.get_addresses();
+        let mut start = addresses.sram_start;
+        let mut end = addresses.sram_end;
+
+        // Merge the RAM with the reclaimed regions directly before and after
+        // it. As no two reclaimed regions are adjacent, there is at most one
+        // of each.
+        for region in self.reclaimed_memory.iter() {
+            if let Some(memory) = region.take() {
+                let memory_start = memory.as_ptr() as usize;
+                let memory_end = memory_start + memory.len();
+                if memory_end == start {
+                    start = memory_start;
+                } else if memory_start == end {
+                    end = memory_end;
+                } else {
+                    region.replace(memory);
+                }
+            }
+        }
+
+        let app_memory = self.app_memory.take();
+        let merged = end == app_memory.as_ptr() as usize;
+        if merged {
+            end += app_memory.len();
+        } else {
+            self.app_memory.set(app_memory);
+        }
+
+        // SAFETY: The RAM of the process was split from the memory available
+        // to applications, as were the reclaimed regions, so the merged region
+        // is not used by any other process or by the kernel. The process is
+        // terminated and no longer stored in the `PROCESSES` array, so its RAM
+        // is not used anymore either.
+        let memory = unsafe { core::slice::from_raw_parts_mut(start as *mut u8, end - start) };
+        if merged {
+            self.app_memory.set(memory);
+        } else {
+            match self.reclaimed_memory.iter().find(|region| region.is_none()) {
+                Some(region) => {
+                    region.replace(memory);
+                }
+                None => {
+                    if config::CONFIG.debug_load_processes {
+                        debug!(
+                            "Loading: No room to reclaim ram={:#010X}-{:#010X}",
+                            start,
+                            end - 1
+                        );
+                    }
+                    return;
+                }
+            }
+        }
+        if config::CONFIG.debug_load_processes {
+            debug!(
+                "Loading: Reclaimed ram={:#010X}-{:#010X}",
+                addresses.sram_start,
+                addresses.sram_end - 1
+            );
+        }
+    }
+
+    /// Check that `address` is in the app flash, outside of the process
+    /// binaries of running processes, and return the flash starting there.
+    fn new_binary_flash_at(&self, address: usize) -> Result<&'static [u8], ErrorCode> {
+        let in_use = self.procs.map_or(false, |procs| {
+            procs.iter().flatten().any(|p| {
+                let addresses = p.get_addresses();
+                (addresses.flash_start..addresses.flash_end).contains(&address)
+            })
+        });
+        if in_use {
+            return Err(ErrorCode::INVAL);
+        }
+        address
+            .checked_sub(self.app_flash.as_ptr() as usize)
+            .and_then(|offset| self.app_flash.get(offset..))
+            .filter(|flash| !flash.is_empty())
+            .ok_or(ErrorCode::INVAL)
+    }
+
     /// Check if `pb1` is blocked from running by `pb2`.
     ///
     /// `pb2` blocks `pb1` if:
@@ -884,6 +1342,116 @@ impl<'a, C: Chip> ProcessLoadingAsync<'a> for SequentialProcessLoaderMachine<'a,
     }
 }
 
+impl<'a, C: Chip> DynamicProcessLoading<'a> for SequentialProcessLoaderMachine<'a, C> {
+    fn set_load_client(&self, client: &'a dyn DynamicProcessLoadingClient) {
+        self.load_client.set(client);
+    }
+
+    fn free_flash(&self, length: usize) -> Result<&'static [u8], ErrorCode> {
+        // Until all processes found at boot have been discovered, the
+        // process binaries are not all known.
+        if self.state.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+
+        // A region fits if the process binary fills it exactly, or if the
+        // rest of the region can hold a padding entry.
+        let fits = |region_len: usize| {
+            length > 0
+                && (region_len == length
+                    || region_len >= length.saturating_add(PADDING_TBF_HEADER_LEN))
+        };
+
+        // Walk the process binaries in flash, collecting runs of padding
+        // entries, until the end of the app flash.
+        let mut region_start = 0;
+        let mut offset = 0;
+        while let Some(flash) = self.app_flash.get(offset..) {
+            let entry_len = match tbf_entry(flash) {
+                Ok((app_flash, version, header_length)) if !app_flash.is_empty() => {
+                    if !is_padding(app_flash, version, header_length) {
+                        if fits(offset - region_start) {
+                            break;
+                        }
+                        region_start = offset + app_flash.len();
+                    }
+                    app_flash.len()
+                }
+                // The unused flash after the last entry is free.
+                _ => {
+                    offset = self.app_flash.len();
+                    break;
+                }
+            };
+            offset += entry_len;
+        }
+
+        match self.app_flash.get(region_start..offset) {
+            Some(region) if fits(region.len()) => Ok(region),
+            _ => Err(ErrorCode::SIZE),
+        }
+    }
+
+    fn load_new_process_binary(&self, address: usize) -> Result<(), ErrorCode> {
+        if self.state.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.new_binary_flash
+            .set(self.new_binary_flash_at(address)?);
+        self.state
+            .set(SequentialProcessLoaderMachineState::LoadNewProcessBinary);
+        self.deferred_call.set();
+        Ok(())
+    }
+
+    fn upgrade_process(
+        &self,
+        processid: ProcessId,
+        address: usize,
+    ) -> Result<(usize, usize), ErrorCode> {
+        if self.state.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        let (_, process) = self.find_process(processid).ok_or(ErrorCode::INVAL)?;
+        self.new_binary_flash
+            .set(self.new_binary_flash_at(address)?);
+        self.state
+            .set(SequentialProcessLoaderMachineState::UpgradeProcess(
+                processid,
+            ));
+        self.deferred_call.set();
+        let addresses = process.get_addresses();
+        Ok((
+            addresses.flash_start,
+            addresses.flash_end - addresses.flash_start,
+        ))
+    }
+
+    fn unload_process(&self, processid: ProcessId) -> Result<(usize, usize), ErrorCode> {
+        if self.state.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        let (index, process) = self.find_process(processid).ok_or(ErrorCode::INVAL)?;
+        process.terminate(None);
+        self.procs.map(|procs| {
+            procs[index] = None;
+        });
+        if config::CONFIG.debug_load_processes {
+            debug!(
+                "Loading: Unloaded process {} from index {}",
+                process.get_process_name(),
+                index
+            );
+        }
+        let addresses = process.get_addresses();
+        self.reclaim_memory(process);
+        Ok((
+            addresses.flash_start,
+            addresses.flash_end - addresses.flash_start,
+        ))
+    }
+}
+
 impl<'a, C: Chip> DeferredCallClient for SequentialProcessLoaderMachine<'a, C> {
     fn handle_deferred_call(&self) {
         // We use deferred calls to start the operation in the async loop.
@@ -904,6 +1472,27 @@ impl<'a, C: Chip> DeferredCallClient for SequentialProcessLoaderMachine<'a, C> {
                     }
                 }
             }
+            Some(SequentialProcessLoaderMachineState::LoadNewProcessBinary)
+            | Some(SequentialProcessLoaderMachineState::UpgradeProcess(_)) => {
+                let process_binary = self
+                    .new_binary_flash
+                    .get()
+                    .ok_or(ProcessBinaryError::TbfHeaderNotFound)
+                    .and_then(tbf_entry)
+                    .and_then(|(app_flash, version, header_length)| {
+                        ProcessBinary::create(app_flash, header_length as usize, version, true)
+                    });
+                match process_binary {
+                    Ok(pb) => {
+                        if let Err(e) = self.checker.check(pb) {
+                            self.new_process_binary_done(Err(ProcessLoadError::CheckError(e)));
+                        }
+                    }
+                    Err(e) => {
+                        self.new_process_binary_done(Err(ProcessLoadError::BinaryError(e)));
+                    }
+                }
+            }
             None => {}
         }
     }
@@ -921,6 +1510,31 @@ impl<'a, C: Chip> crate::process_checker::ProcessCheckerMachineClient
         process_binary: ProcessBinary,
         result: Result<Option<AcceptedCredential>, crate::process_checker::ProcessCheckError>,
     ) {
+        // A process binary written after boot is loaded right away.
+        match self.state.get() {
+            Some(SequentialProcessLoaderMachineState::LoadNewProcessBinary) => {
+                let result = result
+                    .map_err(ProcessLoadError::CheckError)
+                    .and_then(|credential| {
+                        process_binary.credential.insert(credential);
+                        self.load_new_process_object(process_binary)
+                    });
+                self.new_process_binary_done(result);
+                return;
+            }
+            Some(SequentialProcessLoaderMachineState::UpgradeProcess(processid)) => {
+                let result = result
+                    .map_err(ProcessLoadError::CheckError)
+                    .and_then(|credential| {
+                        process_binary.credential.insert(credential);
+                        self.upgrade_process_object(process_binary, processid)
+                    });
+                self.new_process_binary_done(result);
+                return;
+            }
+            _ => {}
+        }
+
         // Check if this process was approved by the checker.
         match result {
             Ok(optional_credential) => {
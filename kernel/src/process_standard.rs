@@ -25,7 +25,7 @@ use crate::platform::mpu::{self, MPU};
 use crate::process::BinaryVersion;
 use crate::process::ProcessBinary;
 use crate::process::{Error, FunctionCall, FunctionCallSource, Process, Task};
-use crate::process::{FaultAction, ProcessCustomGrantIdentifier, ProcessId};
+use crate::process::{FaultAction, GrantAllocation, ProcessCustomGrantIdentifier, ProcessId};
 use crate::process::{ProcessAddresses, ProcessSizes, ShortId};
 use crate::process::{State, StoppedState};
 use crate::process_checker::AcceptedCredential;
@@ -499,6 +499,10 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
         self.storage_permissions
     }
 
+    fn get_scheduling_parameters(&self) -> Option<(u32, u32)> {
+        self.header.get_scheduling_parameters()
+    }
+
     fn number_writeable_flash_regions(&self) -> usize {
         self.header.number_writeable_flash_regions()
     }
@@ -984,6 +988,36 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
             })
     }
 
+    fn grant_allocation(&self, grant_num: usize) -> Option<GrantAllocation> {
+        // Grants of an inactive process are not valid.
+        if !self.is_running() {
+            return None;
+        }
+
+        self.grant_pointers.map_or(None, |grant_pointers| {
+            let grant_entry = grant_pointers.get(grant_num)?;
+            if grant_entry.grant_ptr.is_null() {
+                return None;
+            }
+            let address = grant_entry.grant_ptr as usize;
+
+            // Grants are allocated downwards from the process struct, which
+            // is the lowest of the structures at the top of the grant region.
+            // The grant ends where the lowest allocation above it starts.
+            let end = grant_pointers
+                .iter()
+                .map(|other| other.grant_ptr as usize)
+                .filter(|&other| other > address)
+                .fold(ptr::from_ref(self) as usize, cmp::min);
+
+            Some(GrantAllocation {
+                driver_num: grant_entry.driver_num,
+                address,
+                size: end - address,
+            })
+        })
+    }
+
     fn is_valid_upcall_function_pointer(&self, upcall_fn: NonNull<()>) -> bool {
         let ptr = upcall_fn.as_ptr() as *const u8;
         let size = mem::size_of::<*const u8>();
@@ -1293,6 +1327,27 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
             })
             .unwrap_or(Err(ErrorCode::FAIL))
     }
+
+    fn read_memory(&self, address: usize, out: &mut [u8]) -> Result<(), ErrorCode> {
+        let end = address.checked_add(out.len()).ok_or(ErrorCode::INVAL)?;
+
+        if address >= self.flash_start() as usize && end <= self.flash_end() as usize {
+            let offset = address - self.flash_start() as usize;
+            out.copy_from_slice(&self.flash[offset..offset + out.len()]);
+            Ok(())
+        } else if address >= self.mem_start() as usize && end <= self.app_memory_break() as usize {
+            // Safety: the range is within the memory allocated to this
+            // process, which is valid for as long as the process exists, and
+            // below the application break, so it does not overlap with any
+            // kernel state held in the grant region. The memory is only read.
+            unsafe {
+                core::ptr::copy_nonoverlapping(address as *const u8, out.as_mut_ptr(), out.len());
+            }
+            Ok(())
+        } else {
+            Err(ErrorCode::INVAL)
+        }
+    }
 }
 
 impl<C: 'static + Chip> ProcessStandard<'_, C> {
@@ -5,6 +5,7 @@
 //! Interface for Tock kernel schedulers.
 
 pub mod cooperative;
+pub mod edf;
 pub mod mlfq;
 pub mod priority;
 pub mod round_robin;
@@ -13,6 +14,7 @@ use crate::deferred_call::DeferredCall;
 use crate::platform::chip::Chip;
 use crate::process::ProcessId;
 use crate::process::StoppedExecutingReason;
+use crate::ErrorCode;
 
 /// Trait which any scheduler must implement.
 pub trait Scheduler<C: Chip> {
@@ -98,3 +100,21 @@ pub enum SchedulingDecision {
     /// and will instead restart the main loop and call `next()` again.
     TrySleep,
 }
+
+/// Scheduler parameters that can be changed at runtime, for example from a
+/// debugging console, to tune scheduling without rebuilding the kernel.
+///
+/// Parameters are unsigned integers identified by their name. Schedulers
+/// without runtime parameters do not need to implement this trait.
+pub trait SchedulerParameters {
+    /// Returns the name and the current value of the parameter at `index`, or
+    /// `None` past the last parameter.
+    fn parameter(&self, index: usize) -> Option<(&'static str, u32)>;
+
+    /// Sets the parameter called `name` to `value`. The new value is used
+    /// from the next scheduling decision on.
+    ///
+    /// Returns `ErrorCode::NOSUPPORT` if the scheduler has no parameter called
+    /// `name`, and `ErrorCode::INVAL` if `value` is not valid for it.
+    fn set_parameter(&self, name: &str, value: u32) -> Result<(), ErrorCode>;
+}
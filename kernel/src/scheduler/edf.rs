@@ -0,0 +1,541 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Earliest Deadline First Scheduler for Tock
+//!
+//! This scheduler lets processes declare periodic timing requirements: every
+//! `period_us` microseconds a process may use up to `budget_us` microseconds of
+//! CPU time, and the end of the period is its deadline. Processes request these
+//! parameters either with the `TbfHeaderSchedulingParameters` TLV in their TBF
+//! header, or at runtime through the [`DeadlineScheduler`] interface (which the
+//! `deadline_scheduler` capsule exposes to userspace).
+//!
+//! The scheduler follows these rules:
+//!
+//! - Rule 1: A process is only admitted as a real-time process if the total
+//!           utilization (`budget_us / period_us`) of all admitted processes
+//!           stays below the maximum utilization (by default
+//!           [`EDFSched::MAX_UTILIZATION_PPM`]). Processes that are not
+//!           admitted run as best-effort processes.
+//! - Rule 2: The period of a real-time process starts when it becomes ready,
+//!           and its budget is replenished at the start of every period.
+//! - Rule 3: Among the ready real-time processes with budget left, the one
+//!           with the earliest deadline runs, for at most its remaining
+//!           budget. It is preempted as soon as a process with an earlier
+//!           deadline becomes ready.
+//! - Rule 4: Best-effort processes, and real-time processes that used up their
+//!           budget, run in round-robin fashion only when no real-time process
+//!           with budget left is ready.
+//!
+//! A real-time process that uses up its budget while it still has work to do
+//! overruns its budget, and a real-time process that still has work to do at
+//! the end of its period misses its deadline. Both are counted, and reported
+//! on the debug output using the `ProcessPrinter` given to the scheduler. To
+//! avoid flooding the debug output, only the 1st, 2nd, 4th, 8th, etc. overrun
+//! of each kind is reported.
+//!
+//! The maximum utilization (`max_utilization_ppm`) and the timeslice of
+//! best-effort processes (`best_effort_timeslice_us`) can be changed at
+//! runtime through [`SchedulerParameters`]. Lowering the maximum utilization
+//! only affects processes admitted afterwards.
+
+use core::cell::Cell;
+use core::fmt::Write;
+
+use crate::collections::list::{List, ListLink, ListNode};
+use crate::debug;
+use crate::hil::time::{self, ConvertTicks, Ticks};
+use crate::platform::chip::Chip;
+use crate::process::ProcessId;
+use crate::process::StoppedExecutingReason;
+use crate::process::{Process, ProcessPrinter};
+use crate::scheduler::{Scheduler, SchedulerParameters, SchedulingDecision};
+use crate::utilities::binary_write::BinaryToWriteWrapper;
+use crate::utilities::cells::OptionalCell;
+use crate::ErrorCode;
+
+/// Interface to set the timing requirements of processes at runtime.
+pub trait DeadlineScheduler {
+    /// Request a budget of `budget_us` microseconds of CPU time every
+    /// `period_us` microseconds for the process `processid`. This replaces
+    /// the parameters the process had before, including those from its TBF
+    /// header.
+    ///
+    /// Returns `INVAL` if the parameters are not valid or there is no such
+    /// process, and `BUSY` if admitting the process would exceed the maximum
+    /// utilization. In the latter case the previous parameters are kept.
+    fn set_parameters(
+        &self,
+        processid: ProcessId,
+        period_us: u32,
+        budget_us: u32,
+    ) -> Result<(), ErrorCode>;
+
+    /// Run the process `processid` as a best-effort process.
+    fn clear_parameters(&self, processid: ProcessId) -> Result<(), ErrorCode>;
+
+    /// Return the number of budget overruns and deadline misses of the process
+    /// `processid`, in that order.
+    fn overrun_counts(&self, processid: ProcessId) -> Result<(u32, u32), ErrorCode>;
+}
+
+/// Per-process scheduling state
+#[derive(Default)]
+struct EdfProcState {
+    /// The process this state belongs to. It is only set while the process is
+    /// running, so that the state is reset when the process is restarted or
+    /// the slot is reused by another process.
+    processid: OptionalCell<ProcessId>,
+    /// Whether the process was admitted as a real-time process
+    admitted: Cell<bool>,
+    period_us: Cell<u32>,
+    budget_us: Cell<u32>,
+    /// Share of the CPU reserved for the process, in parts per million
+    utilization_ppm: Cell<u32>,
+    /// Whether the current period has started and not yet ended
+    active: Cell<bool>,
+    /// Deadline of the current period, on the scheduler clock
+    deadline_us: Cell<u64>,
+    /// CPU time used during the current period
+    us_used_this_period: Cell<u32>,
+    /// Whether the process had work to do and has not yielded since
+    job_pending: Cell<bool>,
+    /// Whether the process was ready when the scheduler last refreshed it
+    was_ready: Cell<bool>,
+    budget_overruns: Cell<u32>,
+    deadline_misses: Cell<u32>,
+}
+
+/// Nodes store per-process state
+pub struct EDFProcessNode<'a> {
+    proc: &'static Option<&'static dyn Process>,
+    state: EdfProcState,
+    next: ListLink<'a, EDFProcessNode<'a>>,
+}
+
+impl<'a> EDFProcessNode<'a> {
+    pub fn new(proc: &'static Option<&'static dyn Process>) -> EDFProcessNode<'a> {
+        EDFProcessNode {
+            proc,
+            state: EdfProcState::default(),
+            next: ListLink::empty(),
+        }
+    }
+}
+
+impl<'a> ListNode<'a, EDFProcessNode<'a>> for EDFProcessNode<'a> {
+    fn next(&'a self) -> &'a ListLink<'a, EDFProcessNode<'a>> {
+        &self.next
+    }
+}
+
+/// Writer forwarding the output of the `ProcessPrinter` to the debug output.
+struct DebugPrintWriter;
+
+impl Write for DebugPrintWriter {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        debug::debug_print(format_args!("{}", s));
+        Ok(())
+    }
+}
+
+pub struct EDFSched<'a, A: 'static + time::Alarm<'static>> {
+    alarm: &'static A,
+    process_printer: Option<&'static dyn ProcessPrinter>,
+    pub processes: List<'a, EDFProcessNode<'a>>,
+    /// Time elapsed since the scheduler started, in microseconds. Unlike the
+    /// alarm ticks, this never wraps around.
+    now_us: Cell<u64>,
+    /// Alarm ticks corresponding to `now_us`
+    last_ticks: Cell<A::Ticks>,
+    /// Sum of the utilization of all admitted processes, in parts per million
+    utilization_ppm: Cell<u32>,
+    max_utilization_ppm: Cell<u32>,
+    best_effort_timeslice_us: Cell<u32>,
+    running: OptionalCell<&'a EDFProcessNode<'a>>,
+    running_realtime: Cell<bool>,
+}
+
+impl<'a, A: 'static + time::Alarm<'static>> EDFSched<'a, A> {
+    /// Default maximum total utilization of the admitted processes, in parts
+    /// per million. Some CPU time is left for the kernel and best-effort
+    /// processes.
+    pub const MAX_UTILIZATION_PPM: u32 = 900_000;
+    /// Default timeslice of best-effort processes
+    pub const BEST_EFFORT_TIMESLICE_US: u32 = 10000;
+
+    pub fn new(alarm: &'static A, process_printer: Option<&'static dyn ProcessPrinter>) -> Self {
+        Self {
+            alarm,
+            process_printer,
+            processes: List::new(),
+            now_us: Cell::new(0),
+            last_ticks: Cell::new(A::Ticks::from(0)),
+            utilization_ppm: Cell::new(0),
+            max_utilization_ppm: Cell::new(Self::MAX_UTILIZATION_PPM),
+            best_effort_timeslice_us: Cell::new(Self::BEST_EFFORT_TIMESLICE_US),
+            running: OptionalCell::empty(),
+            running_realtime: Cell::new(false),
+        }
+    }
+
+    /// Advance the scheduler clock to the current time.
+    fn update_clock(&self) {
+        let elapsed = self.alarm.now().wrapping_sub(self.last_ticks.get());
+        let elapsed_us = self.alarm.ticks_to_us(elapsed);
+        // Only advance the ticks by the amount converted to microseconds, so
+        // that rounding errors do not accumulate.
+        self.last_ticks.set(
+            self.last_ticks
+                .get()
+                .wrapping_add(self.alarm.ticks_from_us(elapsed_us)),
+        );
+        self.now_us.set(self.now_us.get() + elapsed_us as u64);
+    }
+
+    /// Give up the CPU share reserved for a process.
+    fn release(&self, node: &EDFProcessNode<'a>) {
+        if node.state.admitted.get() {
+            self.utilization_ppm
+                .set(self.utilization_ppm.get() - node.state.utilization_ppm.get());
+        }
+        node.state.admitted.set(false);
+        node.state.active.set(false);
+        node.state.job_pending.set(false);
+    }
+
+    /// Reserve a CPU share for a process, keeping its previous parameters if
+    /// the process cannot be admitted.
+    fn admit(
+        &self,
+        node: &EDFProcessNode<'a>,
+        period_us: u32,
+        budget_us: u32,
+    ) -> Result<(), ErrorCode> {
+        if period_us == 0 || budget_us == 0 || budget_us > period_us {
+            return Err(ErrorCode::INVAL);
+        }
+        let utilization_ppm = (budget_us as u64 * 1_000_000).div_ceil(period_us as u64) as u32;
+        let previous_ppm = if node.state.admitted.get() {
+            node.state.utilization_ppm.get()
+        } else {
+            0
+        };
+        let total_ppm = self.utilization_ppm.get() - previous_ppm + utilization_ppm;
+        if total_ppm > self.max_utilization_ppm.get() {
+            return Err(ErrorCode::BUSY);
+        }
+
+        self.utilization_ppm.set(total_ppm);
+        node.state.admitted.set(true);
+        node.state.period_us.set(period_us);
+        node.state.budget_us.set(budget_us);
+        node.state.utilization_ppm.set(utilization_ppm);
+        node.state.active.set(false);
+        node.state.us_used_this_period.set(0);
+        Ok(())
+    }
+
+    /// Reset the state of a node if its process changed, and admit the new
+    /// process if it requested scheduling parameters in its TBF header.
+    fn sync_process(&self, node: &EDFProcessNode<'a>) {
+        let current = node.proc.filter(|proc| proc.is_running());
+        let changed = match current {
+            Some(proc) => node.state.processid.get() != Some(proc.processid()),
+            None => node.state.processid.is_some(),
+        };
+        if !changed {
+            return;
+        }
+
+        self.release(node);
+        node.state.processid.clear();
+        node.state.budget_overruns.set(0);
+        node.state.deadline_misses.set(0);
+        if let Some(proc) = current {
+            node.state.processid.set(proc.processid());
+            if let Some((period_us, budget_us)) = proc.get_scheduling_parameters() {
+                if let Err(e) = self.admit(node, period_us, budget_us) {
+                    debug!(
+                        "[EDF] {} not admitted ({:?}), running as best effort",
+                        proc.get_process_name(),
+                        e
+                    );
+                }
+            }
+        }
+    }
+
+    /// Start a new period for real-time processes whose period ended or that
+    /// became ready.
+    fn refresh(&self, node: &EDFProcessNode<'a>) {
+        self.sync_process(node);
+        node.state
+            .was_ready
+            .set(node.proc.map_or(false, |proc| proc.ready()));
+        let proc = match node.proc {
+            Some(proc) if node.state.admitted.get() => proc,
+            _ => return,
+        };
+        let now_us = self.now_us.get();
+        let state = &node.state;
+
+        if state.active.get() && now_us >= state.deadline_us.get() {
+            if state.job_pending.get() {
+                let misses = state.deadline_misses.get() + 1;
+                state.deadline_misses.set(misses);
+                self.report(*proc, "missed its deadline", misses);
+            }
+            state.active.set(false);
+            state.job_pending.set(false);
+            if proc.ready() {
+                // Periods of a process that stays ready follow each other, as
+                // long as the scheduler did not fall a full period behind.
+                let period_us = state.period_us.get() as u64;
+                let start_us = if now_us - state.deadline_us.get() < period_us {
+                    state.deadline_us.get()
+                } else {
+                    now_us
+                };
+                state.active.set(true);
+                state.deadline_us.set(start_us + period_us);
+                state.us_used_this_period.set(0);
+            }
+        } else if !state.active.get() && proc.ready() {
+            state.active.set(true);
+            state.deadline_us.set(now_us + state.period_us.get() as u64);
+            state.us_used_this_period.set(0);
+        }
+
+        if proc.ready() {
+            state.job_pending.set(true);
+        }
+    }
+
+    fn refresh_all(&self) {
+        self.update_clock();
+        for node in self.processes.iter() {
+            self.refresh(node);
+        }
+    }
+
+    /// Returns whether a real-time process became ready since the last
+    /// refresh.
+    fn realtime_became_ready(&self) -> bool {
+        self.processes.iter().any(|node| {
+            node.state.admitted.get()
+                && !node.state.was_ready.get()
+                && node.proc.map_or(false, |proc| proc.ready())
+        })
+    }
+
+    /// Returns the ready real-time process with budget left and the earliest
+    /// deadline.
+    fn earliest_deadline_node(&self) -> Option<&'a EDFProcessNode<'a>> {
+        self.processes
+            .iter()
+            .filter(|node| {
+                node.state.admitted.get()
+                    && node.state.active.get()
+                    && node.state.us_used_this_period.get() < node.state.budget_us.get()
+                    && node.proc.map_or(false, |proc| proc.ready())
+            })
+            .min_by_key(|node| node.state.deadline_us.get())
+    }
+
+    /// Returns the first ready process in round-robin order. This method moves
+    /// that node to the head of the list.
+    fn next_best_effort_node(&self) -> Option<&'a EDFProcessNode<'a>> {
+        let next = self
+            .processes
+            .iter()
+            .find(|node| node.proc.map_or(false, |proc| proc.ready()))?;
+        loop {
+            let node = self.processes.pop_head()?;
+            if core::ptr::eq(node, next) {
+                self.processes.push_head(node);
+                return Some(next);
+            }
+            self.processes.push_tail(node);
+        }
+    }
+
+    fn report(&self, proc: &dyn Process, what: &str, count: u32) {
+        if !count.is_power_of_two() {
+            return;
+        }
+        debug!(
+            "[EDF] {} {} ({} times)",
+            proc.get_process_name(),
+            what,
+            count
+        );
+        if let Some(printer) = self.process_printer {
+            printer.print_overview(
+                proc,
+                &mut BinaryToWriteWrapper::new(&mut DebugPrintWriter),
+                None,
+            );
+        }
+    }
+
+    fn find_node(&self, processid: ProcessId) -> Result<&'a EDFProcessNode<'a>, ErrorCode> {
+        let node = self
+            .processes
+            .iter()
+            .find(|node| {
+                node.proc
+                    .map_or(false, |proc| proc.processid() == processid)
+            })
+            .ok_or(ErrorCode::INVAL)?;
+        self.sync_process(node);
+        Ok(node)
+    }
+}
+
+impl<'a, A: 'static + time::Alarm<'static>> DeadlineScheduler for EDFSched<'a, A> {
+    fn set_parameters(
+        &self,
+        processid: ProcessId,
+        period_us: u32,
+        budget_us: u32,
+    ) -> Result<(), ErrorCode> {
+        let node = self.find_node(processid)?;
+        self.admit(node, period_us, budget_us)
+    }
+
+    fn clear_parameters(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        let node = self.find_node(processid)?;
+        self.release(node);
+        Ok(())
+    }
+
+    fn overrun_counts(&self, processid: ProcessId) -> Result<(u32, u32), ErrorCode> {
+        let node = self.find_node(processid)?;
+        Ok((
+            node.state.budget_overruns.get(),
+            node.state.deadline_misses.get(),
+        ))
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>> SchedulerParameters for EDFSched<'_, A> {
+    fn parameter(&self, index: usize) -> Option<(&'static str, u32)> {
+        match index {
+            0 => Some(("max_utilization_ppm", self.max_utilization_ppm.get())),
+            1 => Some((
+                "best_effort_timeslice_us",
+                self.best_effort_timeslice_us.get(),
+            )),
+            _ => None,
+        }
+    }
+
+    fn set_parameter(&self, name: &str, value: u32) -> Result<(), ErrorCode> {
+        match name {
+            "max_utilization_ppm" if value == 0 || value > 1_000_000 => Err(ErrorCode::INVAL),
+            "max_utilization_ppm" => {
+                self.max_utilization_ppm.set(value);
+                Ok(())
+            }
+            "best_effort_timeslice_us" if value == 0 => Err(ErrorCode::INVAL),
+            "best_effort_timeslice_us" => {
+                self.best_effort_timeslice_us.set(value);
+                Ok(())
+            }
+            _ => Err(ErrorCode::NOSUPPORT),
+        }
+    }
+}
+
+impl<'a, A: 'static + time::Alarm<'static>, C: Chip> Scheduler<C> for EDFSched<'a, A> {
+    fn next(&self) -> SchedulingDecision {
+        self.refresh_all();
+
+        if let Some(node) = self.earliest_deadline_node() {
+            let timeslice = node.state.budget_us.get() - node.state.us_used_this_period.get();
+            self.running.set(node);
+            self.running_realtime.set(true);
+            return SchedulingDecision::RunProcess((
+                node.proc.unwrap().processid(),
+                Some(timeslice),
+            ));
+        }
+
+        match self.next_best_effort_node() {
+            Some(node) => {
+                // Stop best-effort processes in time to replenish the budget of
+                // real-time processes at the end of their period.
+                let now_us = self.now_us.get();
+                let timeslice = self
+                    .processes
+                    .iter()
+                    .filter(|node| node.state.admitted.get() && node.state.active.get())
+                    .map(|node| node.state.deadline_us.get().saturating_sub(now_us).max(1))
+                    .fold(self.best_effort_timeslice_us.get() as u64, u64::min);
+                self.running.set(node);
+                self.running_realtime.set(false);
+                SchedulingDecision::RunProcess((
+                    node.proc.unwrap().processid(),
+                    Some(timeslice as u32),
+                ))
+            }
+            None => SchedulingDecision::TrySleep,
+        }
+    }
+
+    fn result(&self, result: StoppedExecutingReason, execution_time_us: Option<u32>) {
+        let node = match self.running.take() {
+            Some(node) => node,
+            None => return,
+        };
+        let state = &node.state;
+
+        if self.running_realtime.get() && state.admitted.get() {
+            let used = state.us_used_this_period.get() + execution_time_us.unwrap_or(0);
+            state.us_used_this_period.set(used);
+            if result == StoppedExecutingReason::TimesliceExpired && used >= state.budget_us.get() {
+                let overruns = state.budget_overruns.get() + 1;
+                state.budget_overruns.set(overruns);
+                if let Some(proc) = node.proc {
+                    self.report(*proc, "overran its budget", overruns);
+                }
+            }
+        }
+
+        match result {
+            StoppedExecutingReason::TimesliceExpired | StoppedExecutingReason::KernelPreemption => {
+            }
+            _ => state.job_pending.set(false),
+        }
+
+        if !self.running_realtime.get() {
+            // Best-effort nodes are at the head of the list while they run.
+            if let Some(head) = self.processes.pop_head() {
+                self.processes.push_tail(head);
+            }
+        }
+    }
+
+    unsafe fn continue_process(&self, _: ProcessId, chip: &C) -> bool {
+        if chip.has_pending_interrupts() || crate::deferred_call::DeferredCall::has_tasks() {
+            return false;
+        }
+        // A system call of the running process (e.g. IPC) can make a process
+        // with an earlier deadline ready. Deadlines only need to be compared
+        // again when that happens.
+        if !self.realtime_became_ready() {
+            return true;
+        }
+        self.refresh_all();
+        match (self.running.get(), self.earliest_deadline_node()) {
+            (Some(running), Some(earliest)) => {
+                self.running_realtime.get()
+                    && (core::ptr::eq(running, earliest)
+                        || running.state.deadline_us.get() <= earliest.state.deadline_us.get())
+            }
+            _ => true,
+        }
+    }
+}
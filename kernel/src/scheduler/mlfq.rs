@@ -29,7 +29,8 @@ use crate::platform::chip::Chip;
 use crate::process::Process;
 use crate::process::ProcessId;
 use crate::process::StoppedExecutingReason;
-use crate::scheduler::{Scheduler, SchedulingDecision};
+use crate::scheduler::{Scheduler, SchedulerParameters, SchedulingDecision};
+use crate::ErrorCode;
 
 #[derive(Default)]
 struct MfProcState {
@@ -67,10 +68,11 @@ pub struct MLFQSched<'a, A: 'static + time::Alarm<'static>> {
     last_reset_check: Cell<A::Ticks>,
     last_timeslice: Cell<u32>,
     last_queue_idx: Cell<usize>,
+    priority_refresh_period_ms: Cell<u32>,
 }
 
 impl<'a, A: 'static + time::Alarm<'static>> MLFQSched<'a, A> {
-    /// How often to restore all processes to max priority
+    /// How often to restore all processes to max priority, by default
     pub const PRIORITY_REFRESH_PERIOD_MS: u32 = 5000;
     pub const NUM_QUEUES: usize = 3;
 
@@ -82,6 +84,7 @@ impl<'a, A: 'static + time::Alarm<'static>> MLFQSched<'a, A> {
             last_reset_check: Cell::new(A::Ticks::from(0)),
             last_timeslice: Cell::new(0),
             last_queue_idx: Cell::new(0),
+            priority_refresh_period_ms: Cell::new(Self::PRIORITY_REFRESH_PERIOD_MS),
         }
     }
 
@@ -141,8 +144,12 @@ impl<'a, A: 'static + time::Alarm<'static>, C: Chip> Scheduler<C> for MLFQSched<
         // alarm wraps around
         if !now.within_range(last_reset_check, next_reset) {
             // Promote all processes to highest priority queue
-            self.next_reset
-                .set(now.wrapping_add(self.alarm.ticks_from_ms(Self::PRIORITY_REFRESH_PERIOD_MS)));
+            self.next_reset.set(
+                now.wrapping_add(
+                    self.alarm
+                        .ticks_from_ms(self.priority_refresh_period_ms.get()),
+                ),
+            );
             self.redeem_all_procs();
         }
         self.last_reset_check.set(now);
@@ -188,3 +195,23 @@ impl<'a, A: 'static + time::Alarm<'static>, C: Chip> Scheduler<C> for MLFQSched<
         true
     }
 }
+
+impl<A: 'static + time::Alarm<'static>> SchedulerParameters for MLFQSched<'_, A> {
+    fn parameter(&self, index: usize) -> Option<(&'static str, u32)> {
+        match index {
+            0 => Some(("refresh_ms", self.priority_refresh_period_ms.get())),
+            _ => None,
+        }
+    }
+
+    fn set_parameter(&self, name: &str, value: u32) -> Result<(), ErrorCode> {
+        match name {
+            "refresh_ms" if value == 0 => Err(ErrorCode::INVAL),
+            "refresh_ms" => {
+                self.priority_refresh_period_ms.set(value);
+                Ok(())
+            }
+            _ => Err(ErrorCode::NOSUPPORT),
+        }
+    }
+}
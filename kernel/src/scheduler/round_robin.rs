@@ -24,7 +24,8 @@ use crate::collections::list::{List, ListLink, ListNode};
 use crate::platform::chip::Chip;
 use crate::process::Process;
 use crate::process::StoppedExecutingReason;
-use crate::scheduler::{Scheduler, SchedulingDecision};
+use crate::scheduler::{Scheduler, SchedulerParameters, SchedulingDecision};
+use crate::ErrorCode;
 
 /// A node in the linked list the scheduler uses to track processes
 /// Each node holds a pointer to a slot in the processes array
@@ -51,7 +52,7 @@ impl<'a> ListNode<'a, RoundRobinProcessNode<'a>> for RoundRobinProcessNode<'a> {
 /// Round Robin Scheduler
 pub struct RoundRobinSched<'a> {
     time_remaining: Cell<u32>,
-    timeslice_length: u32,
+    timeslice_length: Cell<u32>,
     pub processes: List<'a, RoundRobinProcessNode<'a>>,
     last_rescheduled: Cell<bool>,
 }
@@ -66,7 +67,7 @@ impl<'a> RoundRobinSched<'a> {
     pub const fn new_with_time(time_us: u32) -> RoundRobinSched<'a> {
         RoundRobinSched {
             time_remaining: Cell::new(time_us),
-            timeslice_length: time_us,
+            timeslice_length: Cell::new(time_us),
             processes: List::new(),
             last_rescheduled: Cell::new(false),
         }
@@ -117,8 +118,8 @@ impl<'a, C: Chip> Scheduler<C> for RoundRobinSched<'a> {
             self.time_remaining.get()
         } else {
             // grant a fresh timeslice
-            self.time_remaining.set(self.timeslice_length);
-            self.timeslice_length
+            self.time_remaining.set(self.timeslice_length.get());
+            self.timeslice_length.get()
         };
         assert!(timeslice != 0);
 
@@ -145,3 +146,23 @@ impl<'a, C: Chip> Scheduler<C> for RoundRobinSched<'a> {
         }
     }
 }
+
+impl SchedulerParameters for RoundRobinSched<'_> {
+    fn parameter(&self, index: usize) -> Option<(&'static str, u32)> {
+        match index {
+            0 => Some(("timeslice_us", self.timeslice_length.get())),
+            _ => None,
+        }
+    }
+
+    fn set_parameter(&self, name: &str, value: u32) -> Result<(), ErrorCode> {
+        match name {
+            "timeslice_us" if value == 0 => Err(ErrorCode::INVAL),
+            "timeslice_us" => {
+                self.timeslice_length.set(value);
+                Ok(())
+            }
+            _ => Err(ErrorCode::NOSUPPORT),
+        }
+    }
+}
@@ -0,0 +1,270 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Runtime system call tracing.
+//!
+//! Unlike the `trace_syscalls` feature, which prints every system call to the
+//! debug writer and requires rebuilding the kernel, a board can register a
+//! [`SyscallTracer`] with [`Kernel::set_syscall_tracer`](crate::Kernel). The
+//! kernel reports each system call to the tracer once it has been handled,
+//! with the value returned to the process. The tracer decides what to keep,
+//! typically a [`SyscallRecord`] in a ring buffer that can be inspected
+//! later through [`SyscallTraceControl`].
+//!
+//! Records have a fixed size binary encoding, so they can be dumped as they
+//! are and decoded on the host:
+//!
+//! ```text
+//! 0..4    Timestamp, in ticks of the tracer's clock
+//! 4..8    Process identifier
+//! 8       System call class (TRD104)
+//! 9       Flags, bit 0 is set if a value was returned to the process
+//! 10..12  Reserved, zero
+//! 12..16  Driver number, or the first argument for classes without one
+//! 16..20  Subdriver number, or the second argument
+//! 20..28  Remaining two arguments
+//! 28..44  Return value, encoded in four registers as defined in TRD104
+//! ```
+//!
+//! All fields are little endian.
+//!
+//! Tracers may also count the system calls made to each driver, which
+//! [`SyscallTraceControl::driver_stats`] returns as [`DriverSyscallStats`].
+
+use crate::process::ProcessId;
+use crate::syscall::{Syscall, SyscallClass, SyscallReturn};
+
+/// Length of an encoded [`SyscallRecord`].
+pub const RECORD_LEN: usize = 44;
+
+/// Flag set in [`SyscallRecord::flags`] if the system call returned a value.
+pub const FLAG_RETURNED: u8 = 0x01;
+
+/// A traced system call.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SyscallRecord {
+    pub timestamp: u32,
+    /// Identifier of the process, as returned by [`ProcessId::id`].
+    pub process: u32,
+    pub class: u8,
+    pub flags: u8,
+    /// The four system call arguments. For classes with a driver, the first
+    /// two are the driver and subdriver numbers.
+    pub args: [u32; 4],
+    /// The return value, if [`FLAG_RETURNED`] is set.
+    pub ret: [u32; 4],
+}
+
+impl SyscallRecord {
+    /// Creates the record of `syscall` made by `processid` at `timestamp`.
+    /// Yield and exit do not return a value, and `result` is `None` for them.
+    pub fn new(
+        timestamp: u32,
+        processid: ProcessId,
+        syscall: &Syscall,
+        result: Option<&SyscallReturn>,
+    ) -> Self {
+        let (class, args) = match *syscall {
+            Syscall::Yield {
+                which,
+                param_a,
+                param_b,
+            } => (SyscallClass::Yield, [which, param_a, param_b, 0]),
+            Syscall::Subscribe {
+                driver_number,
+                subdriver_number,
+                upcall_ptr,
+                appdata,
+            } => (
+                SyscallClass::Subscribe,
+                [
+                    driver_number,
+                    subdriver_number,
+                    upcall_ptr as usize,
+                    appdata,
+                ],
+            ),
+            Syscall::Command {
+                driver_number,
+                subdriver_number,
+                arg0,
+                arg1,
+            } => (
+                SyscallClass::Command,
+                [driver_number, subdriver_number, arg0, arg1],
+            ),
+            Syscall::ReadWriteAllow {
+                driver_number,
+                subdriver_number,
+                allow_address,
+                allow_size,
+            } => (
+                SyscallClass::ReadWriteAllow,
+                [
+                    driver_number,
+                    subdriver_number,
+                    allow_address as usize,
+                    allow_size,
+                ],
+            ),
+            Syscall::UserspaceReadableAllow {
+                driver_number,
+                subdriver_number,
+                allow_address,
+                allow_size,
+            } => (
+                SyscallClass::UserspaceReadableAllow,
+                [
+                    driver_number,
+                    subdriver_number,
+                    allow_address as usize,
+                    allow_size,
+                ],
+            ),
+            Syscall::ReadOnlyAllow {
+                driver_number,
+                subdriver_number,
+                allow_address,
+                allow_size,
+            } => (
+                SyscallClass::ReadOnlyAllow,
+                [
+                    driver_number,
+                    subdriver_number,
+                    allow_address as usize,
+                    allow_size,
+                ],
+            ),
+            Syscall::Memop { operand, arg0 } => (SyscallClass::Memop, [operand, arg0, 0, 0]),
+            Syscall::Exit {
+                which,
+                completion_code,
+            } => (SyscallClass::Exit, [which, completion_code, 0, 0]),
+        };
+
+        let mut ret = [0; 4];
+        if let Some(result) = result {
+            let [a0, a1, a2, a3] = &mut ret;
+            result.encode_syscall_return(a0, a1, a2, a3);
+        }
+
+        SyscallRecord {
+            timestamp,
+            process: processid.id() as u32,
+            class: class as u8,
+            flags: if result.is_some() { FLAG_RETURNED } else { 0 },
+            args: args.map(|arg| arg as u32),
+            ret,
+        }
+    }
+
+    /// Returns the driver number, for the classes that have one.
+    pub fn driver_number(&self) -> Option<u32> {
+        match SyscallClass::try_from(self.class) {
+            Ok(SyscallClass::Subscribe)
+            | Ok(SyscallClass::Command)
+            | Ok(SyscallClass::ReadWriteAllow)
+            | Ok(SyscallClass::UserspaceReadableAllow)
+            | Ok(SyscallClass::ReadOnlyAllow) => Some(self.args[0]),
+            _ => None,
+        }
+    }
+
+    /// Encodes the record in the binary format described in the module
+    /// documentation.
+    pub fn encode(&self) -> [u8; RECORD_LEN] {
+        let mut buf = [0; RECORD_LEN];
+        buf[0..4].copy_from_slice(&self.timestamp.to_le_bytes());
+        buf[4..8].copy_from_slice(&self.process.to_le_bytes());
+        buf[8] = self.class;
+        buf[9] = self.flags;
+        for (i, word) in self.args.iter().chain(self.ret.iter()).enumerate() {
+            let start = 12 + 4 * i;
+            buf[start..start + 4].copy_from_slice(&word.to_le_bytes());
+        }
+        buf
+    }
+}
+
+/// Number of system calls made to one driver by all processes.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DriverSyscallStats {
+    pub driver_number: u32,
+    pub subscribes: u32,
+    pub commands: u32,
+    /// Allows of all three kinds.
+    pub allows: u32,
+    /// System calls to the driver that returned a failure.
+    pub failures: u32,
+}
+
+impl DriverSyscallStats {
+    /// Returns the total number of system calls made to the driver.
+    pub fn total(&self) -> u32 {
+        self.subscribes
+            .saturating_add(self.commands)
+            .saturating_add(self.allows)
+    }
+}
+
+/// Receives the system calls handled by the kernel.
+pub trait SyscallTracer {
+    /// Called after the kernel handled `syscall` from `processid`. `result`
+    /// is the value returned to the process, if any.
+    fn trace_syscall(
+        &self,
+        processid: ProcessId,
+        syscall: &Syscall,
+        result: Option<&SyscallReturn>,
+    );
+}
+
+/// Control over a tracer that keeps records, for consoles and other
+/// debugging tools.
+pub trait SyscallTraceControl {
+    /// Starts or stops recording system calls.
+    fn set_enabled(&self, enabled: bool);
+
+    fn is_enabled(&self) -> bool;
+
+    /// Only records the system calls of `process`, or of all processes if
+    /// `None`.
+    fn set_process_filter(&self, process: Option<ProcessId>);
+
+    fn process_filter(&self) -> Option<ProcessId>;
+
+    /// Only records the system calls to driver `driver_number`, or to all
+    /// drivers if `None`. System calls without a driver are not recorded
+    /// while a driver filter is set.
+    fn set_driver_filter(&self, driver_number: Option<usize>);
+
+    fn driver_filter(&self) -> Option<usize>;
+
+    /// Discards all records.
+    fn clear(&self);
+
+    /// Returns the number of records held.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of records that have been overwritten since the
+    /// last `clear()`.
+    fn overwritten(&self) -> usize;
+
+    /// Returns the record at `index`, oldest first.
+    fn record(&self, index: usize) -> Option<SyscallRecord>;
+
+    /// Returns the frequency of the timestamps, in Hz.
+    fn timestamp_frequency(&self) -> u32;
+
+    /// Returns the system call counts of the driver at `index`, in the order
+    /// the drivers were first called, or `None` past the last driver counted.
+    fn driver_stats(&self, index: usize) -> Option<DriverSyscallStats>;
+
+    /// Resets the system call counts of all drivers.
+    fn clear_driver_stats(&self);
+}
@@ -129,6 +129,8 @@ pub fn parse_tbf_header(
                 let mut storage_permissions_pointer: Option<&'static [u8]> = None;
                 let mut kernel_version: Option<types::TbfHeaderV2KernelVersion> = None;
                 let mut short_id: Option<types::TbfHeaderV2ShortId> = None;
+                let mut scheduling_parameters: Option<types::TbfHeaderV2SchedulingParameters> =
+                    None;
 
                 // Iterate the remainder of the header looking for TLV entries.
                 while remaining.len() > 0 {
@@ -273,6 +275,23 @@ pub fn parse_tbf_header(
                             }
                         }
 
+                        types::TbfHeaderTypes::TbfHeaderSchedulingParameters => {
+                            let entry_len =
+                                mem::size_of::<types::TbfHeaderV2SchedulingParameters>();
+                            if tlv_header.length as usize == entry_len {
+                                scheduling_parameters = Some(
+                                    remaining
+                                        .get(0..entry_len)
+                                        .ok_or(types::TbfParseError::NotEnoughFlash)?
+                                        .try_into()?,
+                                );
+                            } else {
+                                return Err(types::TbfParseError::BadTlvEntry(
+                                    tlv_header.tipe as usize,
+                                ));
+                            }
+                        }
+
                         _ => {}
                     }
 
@@ -297,6 +316,7 @@ pub fn parse_tbf_header(
                     storage_permissions: storage_permissions_pointer,
                     kernel_version,
                     short_id,
+                    scheduling_parameters,
                 };
 
                 Ok(types::TbfHeader::TbfHeaderV2(tbf_header))
@@ -131,6 +131,7 @@ pub enum TbfHeaderTypes {
     TbfHeaderKernelVersion = 8,
     TbfHeaderProgram = 9,
     TbfHeaderShortId = 10,
+    TbfHeaderSchedulingParameters = 11,
     TbfFooterCredentials = 128,
 
     /// Some field in the header that we do not understand. Since the TLV format
@@ -250,6 +251,18 @@ pub struct TbfHeaderV2ShortId {
     short_id: Option<core::num::NonZeroU32>,
 }
 
+/// The v2 scheduling parameters for apps.
+///
+/// Header to request periodic real-time scheduling for an app. Every
+/// `period_us` microseconds, the app asks for up to `budget_us` microseconds
+/// of CPU time, and the end of the period is its deadline. Schedulers that do
+/// not support deadlines ignore this header.
+#[derive(Clone, Copy, Debug)]
+pub struct TbfHeaderV2SchedulingParameters {
+    period_us: u32,
+    budget_us: u32,
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum TbfFooterV2CredentialsType {
     Reserved = 0,
@@ -258,6 +271,7 @@ pub enum TbfFooterV2CredentialsType {
     SHA256 = 3,
     SHA384 = 4,
     SHA512 = 5,
+    EcdsaNistP256 = 6,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -329,6 +343,7 @@ impl core::convert::TryFrom<u16> for TbfHeaderTypes {
             8 => Ok(TbfHeaderTypes::TbfHeaderKernelVersion),
             9 => Ok(TbfHeaderTypes::TbfHeaderProgram),
             10 => Ok(TbfHeaderTypes::TbfHeaderShortId),
+            11 => Ok(TbfHeaderTypes::TbfHeaderSchedulingParameters),
             128 => Ok(TbfHeaderTypes::TbfFooterCredentials),
             _ => Ok(TbfHeaderTypes::Unknown),
         }
@@ -587,6 +602,25 @@ impl core::convert::TryFrom<&[u8]> for TbfHeaderV2ShortId {
     }
 }
 
+impl core::convert::TryFrom<&[u8]> for TbfHeaderV2SchedulingParameters {
+    type Error = TbfParseError;
+
+    fn try_from(b: &[u8]) -> Result<TbfHeaderV2SchedulingParameters, Self::Error> {
+        Ok(TbfHeaderV2SchedulingParameters {
+            period_us: u32::from_le_bytes(
+                b.get(0..4)
+                    .ok_or(TbfParseError::InternalError)?
+                    .try_into()?,
+            ),
+            budget_us: u32::from_le_bytes(
+                b.get(4..8)
+                    .ok_or(TbfParseError::InternalError)?
+                    .try_into()?,
+            ),
+        })
+    }
+}
+
 impl core::convert::TryFrom<&'static [u8]> for TbfFooterV2Credentials {
     type Error = TbfParseError;
 
@@ -603,6 +637,7 @@ impl core::convert::TryFrom<&'static [u8]> for TbfFooterV2Credentials {
             3 => TbfFooterV2CredentialsType::SHA256,
             4 => TbfFooterV2CredentialsType::SHA384,
             5 => TbfFooterV2CredentialsType::SHA512,
+            6 => TbfFooterV2CredentialsType::EcdsaNistP256,
             _ => {
                 return Err(TbfParseError::BadTlvEntry(
                     TbfHeaderTypes::TbfFooterCredentials as usize,
@@ -616,6 +651,7 @@ impl core::convert::TryFrom<&'static [u8]> for TbfFooterV2Credentials {
             TbfFooterV2CredentialsType::SHA256 => 32,
             TbfFooterV2CredentialsType::SHA384 => 48,
             TbfFooterV2CredentialsType::SHA512 => 64,
+            TbfFooterV2CredentialsType::EcdsaNistP256 => 64,
         };
         let data = &b
             .get(4..(length + 4))
@@ -658,6 +694,7 @@ pub struct TbfHeaderV2 {
     pub(crate) storage_permissions: Option<&'static [u8]>,
     pub(crate) kernel_version: Option<TbfHeaderV2KernelVersion>,
     pub(crate) short_id: Option<TbfHeaderV2ShortId>,
+    pub(crate) scheduling_parameters: Option<TbfHeaderV2SchedulingParameters>,
 }
 
 /// Type that represents the fields of the Tock Binary Format header.
@@ -1019,4 +1056,15 @@ impl TbfHeader {
             _ => None,
         }
     }
+
+    /// Return the `(period_us, budget_us)` the application requested for
+    /// deadline scheduling, if it was specified in the TBF header.
+    pub fn get_scheduling_parameters(&self) -> Option<(u32, u32)> {
+        match self {
+            TbfHeader::TbfHeaderV2(hd) => hd
+                .scheduling_parameters
+                .map(|sp| (sp.period_us, sp.budget_us)),
+            _ => None,
+        }
+    }
 }
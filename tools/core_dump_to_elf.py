@@ -0,0 +1,236 @@
+#!/usr/bin/env python3
+
+# Licensed under the Apache License, Version 2.0 or the MIT License.
+# SPDX-License-Identifier: Apache-2.0 OR MIT
+# Copyright Tock Contributors 2024.
+
+"""
+Convert a process core dump printed by the process console `coredump`
+command (`kernel/src/core_dump.rs`) into an ELF core file gdb can open.
+
+Usage: core_dump_to_elf.py [-o app.core] [--app-elf app.elf] [console.log]
+
+The dump is read from the console output in the file, or from stdin. If the
+output holds several dumps, the last one is converted. The Tock notes of the
+dump are kept, and a NT_PRSTATUS note with the registers of the process is
+added. For Cortex-M, the registers saved by the hardware are read from the
+exception frame on the process stack.
+
+Passing the application ELF checks that it matches the dump and prints the
+gdb command to open the core file with the application symbols, relocated
+to where the process was loaded.
+"""
+
+import argparse
+import re
+import struct
+import sys
+
+HEADER = re.compile(r"Core dump of (\S+): (\d+) bytes")
+LINE = re.compile(r"^C ([0-9a-f]*)\s*$")
+
+EM_ARM = 40
+EM_RISCV = 243
+PT_LOAD = 1
+PT_NOTE = 4
+NT_PRSTATUS = 1
+NT_TOCK_PROCESS = 1
+NT_TOCK_STORED_STATE = 2
+
+PROCESS_FIELDS = [
+    "short_id",
+    "binary_version",
+    "restart_count",
+    "flash_start",
+    "flash_non_protected_start",
+    "flash_end",
+    "sram_start",
+    "sram_app_brk",
+    "sram_grant_start",
+    "sram_end",
+    "stack_top",
+    "stack_bottom",
+    "heap_start",
+]
+
+
+def read_dump(lines):
+    """Returns the name and bytes of the last dump in the console output."""
+    dump = None
+    for line in lines:
+        header = HEADER.search(line)
+        if header:
+            dump = (header.group(1), int(header.group(2)), bytearray())
+            continue
+        match = LINE.match(line.strip())
+        if match and dump:
+            dump[2].extend(bytes.fromhex(match.group(1)))
+    if dump is None:
+        sys.exit("No core dump found.")
+    name, length, data = dump
+    if len(data) != length:
+        sys.exit("Core dump of {} is incomplete: {} of {} bytes.".format(name, len(data), length))
+    return name, bytes(data)
+
+
+def parse_elf(data):
+    """Returns the ELF machine, the notes and the loadable segments."""
+    if data[:4] != b"\x7fELF" or data[4] != 1:
+        sys.exit("Not a 32 bit ELF file.")
+    (machine,) = struct.unpack_from("<H", data, 18)
+    (phoff,) = struct.unpack_from("<I", data, 28)
+    phentsize, phnum = struct.unpack_from("<HH", data, 42)
+
+    notes = []
+    segments = []
+    for i in range(phnum):
+        p_type, offset, vaddr, _, filesz, _, flags, _ = struct.unpack_from(
+            "<8I", data, phoff + i * phentsize
+        )
+        if p_type == PT_NOTE:
+            pos = offset
+            while pos < offset + filesz:
+                namesz, descsz, note_type = struct.unpack_from("<3I", data, pos)
+                pos += 12
+                note_name = data[pos : pos + namesz].rstrip(b"\0")
+                pos += (namesz + 3) & ~3
+                notes.append((note_name, note_type, data[pos : pos + descsz]))
+                pos += (descsz + 3) & ~3
+        elif p_type == PT_LOAD:
+            segments.append((vaddr, flags, data[offset : offset + filesz]))
+    return machine, notes, segments
+
+
+def read_word(segments, address):
+    for vaddr, _, contents in segments:
+        if vaddr <= address and address + 4 <= vaddr + len(contents):
+            return struct.unpack_from("<I", contents, address - vaddr)[0]
+    return 0
+
+
+def prstatus(machine, state, segments):
+    """Builds a NT_PRSTATUS descriptor from the stored state."""
+    words = struct.unpack("<{}I".format(len(state) // 4), state)
+    # Signal number, code and errno, current signal, pending and held
+    # signals, process identifiers and times are all left empty.
+    prefix = bytes(72)
+    if machine == EM_ARM:
+        yield_pc, psr, psp = words[3:6]
+        r4_r11 = list(words[6:14])
+        # The hardware saved r0-r3, r12, lr, pc and xpsr on the stack when
+        # the process was interrupted.
+        frame = [read_word(segments, psp + 4 * i) for i in range(8)]
+        pc = frame[6] if frame[6] else yield_pc
+        xpsr = frame[7] if frame[7] else psr
+        sp = psp + 0x20 + (4 if xpsr & (1 << 9) else 0)
+        regs = frame[0:4] + r4_r11 + [frame[4], sp, frame[5], pc, xpsr, 0]
+        return prefix + struct.pack("<18I", *regs) + bytes(4)
+    if machine == EM_RISCV:
+        pc = words[3]
+        x1_x31 = list(words[6:37])
+        return prefix + struct.pack("<32I", pc, *x1_x31) + bytes(4)
+    sys.exit("Unknown architecture, machine {}.".format(machine))
+
+
+def note(name, note_type, desc):
+    name = name + b"\0"
+    data = struct.pack("<3I", len(name), len(desc), note_type)
+    data += name + bytes(-len(name) % 4)
+    data += desc + bytes(-len(desc) % 4)
+    return data
+
+
+def write_core(machine, notes, segments):
+    notes_data = b"".join(note(name, note_type, desc) for name, note_type, desc in notes)
+    phnum = 1 + len(segments)
+    offset = 52 + 32 * phnum
+    # EABI version 5 for ARM, soft float for RISC-V.
+    flags = 0x05000000 if machine == EM_ARM else 0
+
+    header = b"\x7fELF" + bytes([1, 1, 1]) + bytes(9)
+    header += struct.pack("<HHIIIIIHHHHHH", 4, machine, 1, 0, 52, 0, flags, 52, 32, phnum, 0, 0, 0)
+
+    phdrs = struct.pack("<8I", PT_NOTE, offset, 0, 0, len(notes_data), 0, 0, 4)
+    offset += len(notes_data)
+    contents = b""
+    for vaddr, p_flags, data in segments:
+        phdrs += struct.pack("<8I", PT_LOAD, offset, vaddr, vaddr, len(data), len(data), p_flags, 4)
+        offset += len(data)
+        contents += data
+    return header + phdrs + notes_data + contents
+
+
+def app_elf_text_address(path, machine):
+    """Returns the address of the first executable segment of the app ELF."""
+    with open(path, "rb") as f:
+        data = f.read()
+    app_machine, _, _ = parse_elf(data)
+    if app_machine != machine:
+        sys.exit("{} is for machine {}, the dump for {}.".format(path, app_machine, machine))
+    (phoff,) = struct.unpack_from("<I", data, 28)
+    phentsize, phnum = struct.unpack_from("<HH", data, 42)
+    for i in range(phnum):
+        p_type, _, vaddr, _, _, _, flags, _ = struct.unpack_from(
+            "<8I", data, phoff + i * phentsize
+        )
+        if p_type == PT_LOAD and flags & 1:
+            return vaddr
+    return None
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__)
+    parser.add_argument(
+        "log", nargs="?", help="console output containing the dump (default: stdin)"
+    )
+    parser.add_argument("-o", "--output", help="core file to write (default: <process>.core)")
+    parser.add_argument("--app-elf", help="ELF of the application that was dumped")
+    args = parser.parse_args()
+
+    if args.log:
+        with open(args.log, errors="replace") as f:
+            name, data = read_dump(f)
+    else:
+        name, data = read_dump(sys.stdin)
+
+    machine, notes, segments = parse_elf(data)
+    process = None
+    state = None
+    for note_name, note_type, desc in notes:
+        if note_name == b"TOCK" and note_type == NT_TOCK_PROCESS:
+            process = dict(zip(PROCESS_FIELDS, struct.unpack_from("<13I", desc)))
+        elif note_name == b"TOCK" and note_type == NT_TOCK_STORED_STATE:
+            state = desc
+    if process is None or not state:
+        sys.exit("The dump has no process description or stored state.")
+
+    notes.insert(0, (b"CORE", NT_PRSTATUS, prstatus(machine, state, segments)))
+    output = args.output or "{}.core".format(name)
+    with open(output, "wb") as f:
+        f.write(write_core(machine, notes, segments))
+
+    print("Process {}, restarted {} times".format(name, process["restart_count"]))
+    print(
+        "  flash {:#010x}-{:#010x}, RAM {:#010x}-{:#010x}, break {:#010x}".format(
+            process["flash_start"],
+            process["flash_end"],
+            process["sram_start"],
+            process["sram_end"],
+            process["sram_app_brk"],
+        )
+    )
+    print("Wrote {}".format(output))
+
+    if args.app_elf:
+        text = app_elf_text_address(args.app_elf, machine)
+        if text is not None:
+            offset = (process["flash_non_protected_start"] - text) & 0xFFFFFFFF
+            print(
+                'gdb-multiarch -ex "add-symbol-file {} -o {:#x}" -ex "core-file {}"'.format(
+                    args.app_elf, offset, output
+                )
+            )
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,140 @@
+#!/usr/bin/env python3
+
+# Licensed under the Apache License, Version 2.0 or the MIT License.
+# SPDX-License-Identifier: Apache-2.0 OR MIT
+# Copyright Tock Contributors 2024.
+
+"""
+Connect QEMU instances running the qemu_rv32_virt board with `RADIO=HUB`, and
+kernels running on the posix board with `--radio-hub`, into one simulated
+802.15.4 PAN.
+
+Each QEMU instance connects to the hub with a `-netdev socket,connect=...`
+network card, and each posix kernel with a TCP connection, over which its
+simulated radio (`capsules/extra/src/ieee802154/ethernet_radio.rs`) broadcasts
+802.15.4 frames wrapped in Ethernet frames. The hub forwards every frame to all
+other instances, optionally dropping some of them to simulate losses, and can
+record the 802.15.4 frames in a pcap file for Wireshark.
+
+Usage: ieee802154_hub.py [--port 15154] [--loss 0.1] [--pcap pan.pcap] [-v]
+"""
+
+import argparse
+import asyncio
+import random
+import struct
+import sys
+import time
+
+# EtherType and header of the Ethernet frames carrying 802.15.4 frames.
+ETHERTYPE = 0x88B5
+ETHERNET_HDR_LEN = 14
+SIM_HDR_LEN = 2
+
+# Link type of 802.15.4 frames without FCS.
+LINKTYPE_IEEE802_15_4_NOFCS = 230
+
+
+class Pcap:
+    def __init__(self, path):
+        self.file = open(path, "wb")
+        self.file.write(
+            struct.pack("<IHHiIII", 0xA1B2C3D4, 2, 4, 0, 0, 65535, LINKTYPE_IEEE802_15_4_NOFCS)
+        )
+
+    def write(self, frame):
+        now = time.time()
+        self.file.write(
+            struct.pack("<IIII", int(now), int(now % 1 * 1e6), len(frame), len(frame))
+        )
+        self.file.write(frame)
+        self.file.flush()
+
+
+def radio_frame(frame):
+    """Returns the channel and 802.15.4 frame carried by an Ethernet frame, or
+    None if it does not carry one."""
+    if len(frame) < ETHERNET_HDR_LEN + SIM_HDR_LEN:
+        return None
+    if struct.unpack(">H", frame[12:14])[0] != ETHERTYPE:
+        return None
+    channel, length = frame[ETHERNET_HDR_LEN], frame[ETHERNET_HDR_LEN + 1]
+    start = ETHERNET_HDR_LEN + SIM_HDR_LEN
+    return channel, frame[start : start + length]
+
+
+class Hub:
+    def __init__(self, loss, pcap, verbose):
+        self.nodes = {}
+        self.loss = loss
+        self.pcap = pcap
+        self.verbose = verbose
+
+    def log(self, message):
+        if self.verbose:
+            print(message, flush=True)
+
+    def forward(self, sender, frame):
+        carried = radio_frame(frame)
+        if carried is not None:
+            channel, psdu = carried
+            self.log(
+                "{} -> ch {} {} bytes: {}".format(
+                    self.nodes[sender], channel, len(psdu), psdu.hex()
+                )
+            )
+            if self.pcap is not None:
+                self.pcap.write(psdu)
+
+        # QEMU frames each packet on the stream with its length.
+        packet = struct.pack(">I", len(frame)) + frame
+        for writer in self.nodes:
+            if writer is sender:
+                continue
+            if random.random() < self.loss:
+                self.log("  dropped for {}".format(self.nodes[writer]))
+                continue
+            writer.write(packet)
+
+    async def serve(self, reader, writer):
+        peer = "{}:{}".format(*writer.get_extra_info("peername")[:2])
+        self.nodes[writer] = peer
+        print("{} connected, {} nodes".format(peer, len(self.nodes)), flush=True)
+        try:
+            while True:
+                length = struct.unpack(">I", await reader.readexactly(4))[0]
+                self.forward(writer, await reader.readexactly(length))
+        except (asyncio.IncompleteReadError, ConnectionError):
+            pass
+        finally:
+            del self.nodes[writer]
+            writer.close()
+            print("{} disconnected, {} nodes".format(peer, len(self.nodes)), flush=True)
+
+
+async def main():
+    parser = argparse.ArgumentParser(description=__doc__.split("\n\n")[0])
+    parser.add_argument("--host", default="127.0.0.1", help="address to listen on")
+    parser.add_argument("--port", type=int, default=15154, help="port to listen on")
+    parser.add_argument(
+        "--loss", type=float, default=0.0, help="probability to drop a frame for each receiver"
+    )
+    parser.add_argument("--pcap", help="record the 802.15.4 frames in this file")
+    parser.add_argument("-v", "--verbose", action="store_true", help="print every frame")
+    args = parser.parse_args()
+
+    if not 0.0 <= args.loss <= 1.0:
+        sys.exit("--loss must be between 0 and 1")
+
+    hub = Hub(args.loss, Pcap(args.pcap) if args.pcap else None, args.verbose)
+    server = await asyncio.start_server(hub.serve, args.host, args.port)
+    print("Listening on {}:{}".format(args.host, args.port), flush=True)
+    async with server:
+        await server.serve_forever()
+
+
+if __name__ == "__main__":
+    try:
+        asyncio.run(main())
+    except KeyboardInterrupt:
+        pass
@@ -0,0 +1,162 @@
+#!/usr/bin/env python3
+
+# Licensed under the Apache License, Version 2.0 or the MIT License.
+# SPDX-License-Identifier: Apache-2.0 OR MIT
+# Copyright Tock Contributors 2024.
+
+"""
+Decode a system call trace dumped by the process console `trace dump`
+command (`capsules/core/src/syscall_trace.rs`).
+
+Usage: syscall_trace_decode.py [console.log]
+
+Reads the console output from the file, or from stdin, and prints one line
+per system call. Other console output around the dump is ignored.
+"""
+
+import argparse
+import re
+import struct
+import sys
+
+RECORD_LEN = 44
+FLAG_RETURNED = 0x01
+
+HEADER = re.compile(r"Syscall trace: (\d+) records \((\d+) overwritten\), (\d+) Hz")
+RECORD = re.compile(r"^T ([0-9a-f]{%d})\s*$" % (2 * RECORD_LEN))
+
+CLASSES = {
+    0: "yield",
+    1: "subscribe",
+    2: "command",
+    3: "allow-rw",
+    4: "allow-ro",
+    5: "memop",
+    6: "exit",
+    7: "allow-userspace-r",
+}
+
+YIELDS = {0: "no-wait", 1: "wait", 2: "wait-for"}
+
+EXITS = {0: "terminate", 1: "restart"}
+
+RETURN_VARIANTS = {
+    0: "Failure",
+    1: "FailureU32",
+    2: "FailureU32U32",
+    3: "FailureU64",
+    128: "Success",
+    129: "SuccessU32",
+    130: "SuccessU32U32",
+    131: "SuccessU64",
+    132: "SuccessU32U32U32",
+    133: "SuccessU32U64",
+}
+
+# Number of values following the variant of each return variant.
+RETURN_VALUES = {0: 0, 1: 1, 2: 2, 3: 2, 128: 0, 129: 1, 130: 2, 131: 2, 132: 3, 133: 3}
+
+ERROR_CODES = {
+    1: "FAIL",
+    2: "BUSY",
+    3: "ALREADY",
+    4: "OFF",
+    5: "RESERVE",
+    6: "INVAL",
+    7: "SIZE",
+    8: "CANCEL",
+    9: "NOMEM",
+    10: "NOSUPPORT",
+    11: "NODEVICE",
+    12: "UNINSTALLED",
+    13: "NOACK",
+}
+
+
+def format_call(class_id, args):
+    name = CLASSES.get(class_id, "class{}".format(class_id))
+    if class_id == 0:
+        which = YIELDS.get(args[0], str(args[0]))
+        if args[0] == 2:
+            return "yield({}, {:#x}, {})".format(which, args[1], args[2])
+        return "yield({})".format(which)
+    if class_id == 1:
+        return "subscribe({:#x}, {}, @{:#x}, {:#x})".format(*args)
+    if class_id == 2:
+        return "command({:#x}, {}, {:#x}, {:#x})".format(*args)
+    if class_id in (3, 4, 7):
+        return "{}({:#x}, {}, @{:#x}, {})".format(name, *args)
+    if class_id == 5:
+        return "memop({}, {:#x})".format(args[0], args[1])
+    if class_id == 6:
+        return "exit({}, {})".format(EXITS.get(args[0], str(args[0])), args[1])
+    return "{}({})".format(name, ", ".join("{:#x}".format(a) for a in args))
+
+
+def format_return(ret):
+    variant = ret[0]
+    name = RETURN_VARIANTS.get(variant, "variant{}".format(variant))
+    values = list(ret[1 : 1 + RETURN_VALUES.get(variant, 3)])
+    if variant < 128 and values:
+        error = ERROR_CODES.get(values[0], str(values[0]))
+        values = [error] + ["{:#x}".format(v) for v in values[1:]]
+    else:
+        values = ["{:#x}".format(v) for v in values]
+    if values:
+        return "{}({})".format(name, ", ".join(values))
+    return name
+
+
+def decode(lines):
+    frequency = None
+    first = None
+    for line in lines:
+        header = HEADER.search(line)
+        if header:
+            count, overwritten, frequency = (int(g) for g in header.groups())
+            first = None
+            print(
+                "{} records, {} overwritten before the oldest one".format(
+                    count, overwritten
+                )
+            )
+            continue
+
+        record = RECORD.match(line.strip())
+        if not record:
+            continue
+        fields = struct.unpack("<IIBBxx8I", bytes.fromhex(record.group(1)))
+        timestamp, process, class_id, flags = fields[:4]
+        args, ret = fields[4:8], fields[8:12]
+
+        # Timestamps are relative to the oldest record and wrap with the clock.
+        if first is None:
+            first = timestamp
+        ticks = (timestamp - first) & 0xFFFFFFFF
+        if frequency:
+            when = "{:12.3f} ms".format(ticks * 1000.0 / frequency)
+        else:
+            when = "{:12d} ticks".format(ticks)
+
+        call = format_call(class_id, args)
+        if flags & FLAG_RETURNED:
+            call += " = " + format_return(ret)
+        print("{}  [{}] {}".format(when, process, call))
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__)
+    parser.add_argument(
+        "log", nargs="?", help="console output containing the dump (default: stdin)"
+    )
+    args = parser.parse_args()
+
+    if args.log:
+        with open(args.log, errors="replace") as f:
+            decode(f)
+    else:
+        decode(sys.stdin)
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,83 @@
+#!/usr/bin/env python3
+
+# Licensed under the Apache License, Version 2.0 or the MIT License.
+# SPDX-License-Identifier: Apache-2.0 OR MIT
+# Copyright Tock Contributors 2024.
+
+"""
+Send a TBF to a board running the UART app loader capsule
+(`capsules/extra/src/uart_app_loader.rs`), which loads it without a reboot.
+
+Usage: uart_app_loader.py /dev/ttyACM1 app.tbf [--baud 115200] [--chunk 512]
+
+Requires pyserial.
+"""
+
+import argparse
+import struct
+import sys
+
+import serial
+
+MAGIC = b"TBFL"
+
+# Names of the kernel `ErrorCode` values reported by the loader.
+ERROR_CODES = {
+    1: "FAIL",
+    2: "BUSY",
+    3: "ALREADY",
+    4: "OFF",
+    5: "RESERVE",
+    6: "INVAL",
+    7: "SIZE",
+    8: "CANCEL",
+    9: "NOMEM",
+    10: "NOSUPPORT",
+    11: "NODEVICE",
+    12: "UNINSTALLED",
+    13: "NOACK",
+}
+
+
+def check_status(port, step):
+    status = port.read(1)
+    if len(status) == 0:
+        sys.exit("{}: no response from the board".format(step))
+    if status[0] != 0:
+        code = ERROR_CODES.get(status[0], str(status[0]))
+        sys.exit("{}: failed with {}".format(step, code))
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__)
+    parser.add_argument("port", help="serial port of the loader UART")
+    parser.add_argument("tbf", help="TBF to load")
+    parser.add_argument("--baud", type=int, default=115200)
+    parser.add_argument(
+        "--chunk",
+        type=int,
+        default=512,
+        help="chunk size, which must match the loader's buffer",
+    )
+    args = parser.parse_args()
+
+    with open(args.tbf, "rb") as f:
+        tbf = f.read()
+
+    # Loading the application checks its credentials, which may take a while.
+    with serial.Serial(args.port, args.baud, timeout=10) as port:
+        port.write(MAGIC + struct.pack("<I", len(tbf)))
+        check_status(port, "header")
+
+        for offset in range(0, len(tbf), args.chunk):
+            port.write(tbf[offset : offset + args.chunk])
+            step = "chunk at offset {}".format(offset)
+            if offset + args.chunk >= len(tbf):
+                step = "loading"
+            check_status(port, step)
+
+    print("Loaded {} ({} bytes)".format(args.tbf, len(tbf)))
+
+
+if __name__ == "__main__":
+    main()